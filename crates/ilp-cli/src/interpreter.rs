@@ -29,6 +29,8 @@ pub enum Error {
     WebsocketErr(#[from] tungstenite::error::Error),
     #[error("HTTP error: {0}")]
     HttpErr(#[from] http::Error),
+    #[error("Error reading file: {0}")]
+    FileErr(#[from] std::io::Error),
 }
 
 pub fn run(matches: &ArgMatches) -> Result<Response, Error> {
@@ -52,6 +54,8 @@ pub fn run(matches: &ArgMatches) -> Result<Response, Error> {
             ("update-settings", Some(submatches)) => client.put_account_settings(submatches),
             _ => Err(Error::UsageErr("ilp-cli help accounts")),
         },
+        ("export", Some(export_matches)) => client.get_export(export_matches),
+        ("import", Some(import_matches)) => client.post_import(import_matches),
         ("pay", Some(pay_matches)) => client.post_account_payments(pay_matches),
         ("rates", Some(rates_matches)) => match rates_matches.subcommand() {
             ("list", Some(submatches)) => client.get_rates(submatches),
@@ -273,6 +277,29 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    // GET /export
+    fn get_export(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/export", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // POST /import
+    fn post_import(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        let export = std::fs::read_to_string(args["file"])?;
+        self.client
+            .post(&format!("{}/import", self.url))
+            .bearer_auth(auth)
+            .header("Content-Type", "application/json")
+            .body(export)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
     // GET /
     fn get_root(&self, _matches: &ArgMatches) -> Result<Response, Error> {
         self.client
@@ -133,6 +133,20 @@ mod interface_tests {
         ]);
     }
 
+    #[test]
+    fn export() {
+        should_parse(&[
+            "ilp-cli export --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn import() {
+        should_parse(&[
+            "ilp-cli import --auth foo export.json", // minimal
+        ]);
+    }
+
     #[test]
     fn pay() {
         should_parse(&[
@@ -219,7 +233,9 @@ mod interface_tests {
                     Ok(_)
                     | Err(Error::SendErr(_))
                     | Err(Error::WebsocketErr(_))
-                    | Err(Error::TestnetErr(_)) => (),
+                    | Err(Error::TestnetErr(_))
+                    // The files passed to the examples do not exist
+                    | Err(Error::FileErr(_)) => (),
                     Err(e) => panic!("Unexpected interpreter failure: {}", e),
                 },
             }
@@ -12,6 +12,8 @@ pub fn build<'a, 'b>() -> App<'a, 'b> {
             accounts_update(),
             accounts_update_settings(),
         ]),
+        export(),
+        import(),
         pay(),
         rates().subcommands(vec![rates_list(), rates_set_all()]),
         routes().subcommands(vec![routes_list(), routes_set(), routes_set_all()]),
@@ -103,6 +105,9 @@ fn accounts_create<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("max_packet_amount")
                 .long("max-packet-amount")
                 .takes_value(true),
+            Arg::with_name("min_packet_amount")
+                .long("min-packet-amount")
+                .takes_value(true),
             Arg::with_name("min_balance")
                 .long("min-balance")
                 .takes_value(true),
@@ -142,9 +147,44 @@ fn accounts_create<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("packets_per_minute_limit")
                 .long("packets-per-minute-limit")
                 .takes_value(true),
+            Arg::with_name("spread").long("spread").takes_value(true),
+            Arg::with_name("rounding")
+                .long("rounding")
+                .takes_value(true)
+                .possible_values(&["floor", "ceil", "round_half_up", "reject", "carry"]),
+            Arg::with_name("routing_weight")
+                .long("routing-weight")
+                .takes_value(true),
+            Arg::with_name("route_priority")
+                .long("route-priority")
+                .takes_value(true),
+            Arg::with_name("volume_alert_threshold")
+                .long("volume-alert-threshold")
+                .takes_value(true),
+            Arg::with_name("spsp_display_symbol")
+                .long("spsp-display-symbol")
+                .takes_value(true),
+            Arg::with_name("spsp_display_decimals")
+                .long("spsp-display-decimals")
+                .takes_value(true),
+            Arg::with_name("allowed_source_prefix")
+                .long("allowed-source-prefix")
+                .takes_value(true),
+            Arg::with_name("max_concurrent_outgoing_requests")
+                .long("max-concurrent-outgoing-requests")
+                .takes_value(true),
+            Arg::with_name("shadow_account")
+                .long("shadow-account")
+                .takes_value(true),
             Arg::with_name("settlement_engine_url")
                 .long("settlement-engine-url")
                 .takes_value(true),
+            Arg::with_name("settlement_asset_code")
+                .long("settlement-asset-code")
+                .takes_value(true),
+            Arg::with_name("settlement_asset_scale")
+                .long("settlement-asset-scale")
+                .takes_value(true),
         ])
 }
 
@@ -174,6 +214,9 @@ fn accounts_update<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("max_packet_amount")
                 .long("max-packet-amount")
                 .takes_value(true),
+            Arg::with_name("min_packet_amount")
+                .long("min-packet-amount")
+                .takes_value(true),
             Arg::with_name("min_balance")
                 .long("min-balance")
                 .takes_value(true),
@@ -213,9 +256,44 @@ fn accounts_update<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("packets_per_minute_limit")
                 .long("packets-per-minute-limit")
                 .takes_value(true),
+            Arg::with_name("spread").long("spread").takes_value(true),
+            Arg::with_name("rounding")
+                .long("rounding")
+                .takes_value(true)
+                .possible_values(&["floor", "ceil", "round_half_up", "reject", "carry"]),
+            Arg::with_name("routing_weight")
+                .long("routing-weight")
+                .takes_value(true),
+            Arg::with_name("route_priority")
+                .long("route-priority")
+                .takes_value(true),
+            Arg::with_name("volume_alert_threshold")
+                .long("volume-alert-threshold")
+                .takes_value(true),
+            Arg::with_name("spsp_display_symbol")
+                .long("spsp-display-symbol")
+                .takes_value(true),
+            Arg::with_name("spsp_display_decimals")
+                .long("spsp-display-decimals")
+                .takes_value(true),
+            Arg::with_name("allowed_source_prefix")
+                .long("allowed-source-prefix")
+                .takes_value(true),
+            Arg::with_name("max_concurrent_outgoing_requests")
+                .long("max-concurrent-outgoing-requests")
+                .takes_value(true),
+            Arg::with_name("shadow_account")
+                .long("shadow-account")
+                .takes_value(true),
             Arg::with_name("settlement_engine_url")
                 .long("settlement-engine-url")
                 .takes_value(true),
+            Arg::with_name("settlement_asset_code")
+                .long("settlement-asset-code")
+                .takes_value(true),
+            Arg::with_name("settlement_asset_scale")
+                .long("settlement-asset-scale")
+                .takes_value(true),
         ])
 }
 
@@ -395,6 +473,21 @@ fn settlement_engines_set_all<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
+fn export<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("export")
+        .about("Export all accounts, balances and static routes of the node as JSON")
+}
+
+fn import<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("import")
+        .about("Import the accounts, balances and static routes exported by another node")
+        .args(&[Arg::with_name("file")
+            .index(1)
+            .takes_value(true)
+            .required(true)
+            .help("The JSON file written by the export command")])
+}
+
 fn logs<'a, 'b>() -> App<'a, 'b> {
     AuthorizedSubCommand::with_name("logs")
         .about("Modify the logging level of the server")
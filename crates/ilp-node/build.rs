@@ -0,0 +1,71 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bakes the git commit and the time of the build into the binary, for `ilp-node --version`
+fn main() {
+    let git_sha = Command::new("git")
+        .args(&["rev-parse", "--short=10", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|sha| sha.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=ILP_NODE_GIT_SHA={}", git_sha);
+
+    // Reproducible builds set the timestamp themselves
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    let timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("System time is before the UNIX epoch")
+                .as_secs()
+        });
+    println!(
+        "cargo:rustc-env=ILP_NODE_BUILD_TIMESTAMP={}",
+        format_utc(timestamp)
+    );
+
+    // Run again when the checked out commit changes (outside of a checkout, e.g. when the
+    // crate was published, cargo runs it again whenever a file of the crate changes)
+    if Path::new("../../.git/HEAD").exists() {
+        println!("cargo:rerun-if-changed=../../.git/HEAD");
+        println!("cargo:rerun-if-changed=../../.git/refs/heads");
+    }
+}
+
+/// Formats seconds since the UNIX epoch as an RFC 3339 timestamp in UTC
+fn format_utc(timestamp: u64) -> String {
+    let days = timestamp / 86_400;
+    let seconds = timestamp % 86_400;
+    // Converts the days since the epoch to a date of the proleptic Gregorian calendar, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,102 @@
+use interledger::store::events::{BalanceEventSink, OverflowPolicy};
+use serde::{de::Error as DeserializeError, Deserialize, Deserializer};
+use tracing::error;
+
+/// Configuration for publishing a record of every change to an account's
+/// balance (with the old and new balance and the reason for the change)
+/// to a [Redis Stream](https://redis.io/topics/streams-intro)
+#[derive(Deserialize, Clone, Debug)]
+pub struct BalanceEventsConfig {
+    /// URL of the Redis server the events are published to. This can
+    /// be the same server as the node's `database_url`.
+    pub redis_url: String,
+    /// Key of the stream the events are added to. Defaults to `balance_events`.
+    #[serde(default = "BalanceEventsConfig::default_stream")]
+    pub stream: String,
+    /// The number of events which are buffered while waiting to be published. Must be at
+    /// least 1. Defaults to 10000.
+    #[serde(
+        default = "BalanceEventsConfig::default_buffer_size",
+        deserialize_with = "deserialize_buffer_size"
+    )]
+    pub buffer_size: usize,
+    /// What to do with new events when the buffer is full: `drop` them (the default)
+    /// or `block` packet processing until there is room in the buffer.
+    #[serde(default)]
+    pub when_full: OverflowPolicy,
+}
+
+fn deserialize_buffer_size<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let buffer_size = usize::deserialize(deserializer)?;
+    if buffer_size > 0 {
+        Ok(buffer_size)
+    } else {
+        Err(DeserializeError::custom(
+            "Invalid balance event buffer size (must be at least 1): 0",
+        ))
+    }
+}
+
+impl BalanceEventsConfig {
+    fn default_stream() -> String {
+        "balance_events".to_string()
+    }
+
+    fn default_buffer_size() -> usize {
+        10_000
+    }
+
+    /// Spawns a task which publishes the events to the stream and returns the
+    /// sink the store should send the events to
+    #[cfg(feature = "redis")]
+    pub fn spawn_publisher(self) -> Result<BalanceEventSink, ()> {
+        use interledger::store::events::publish_to_redis_stream;
+        use redis_crate::IntoConnectionInfo;
+
+        let redis_info = self.redis_url.as_str().into_connection_info().map_err(
+            |err| error!(target: "interledger-node", "Invalid balance events Redis URL: {:?}", err),
+        )?;
+        let (sink, events) = BalanceEventSink::new(self.buffer_size, self.when_full);
+        tokio::spawn(publish_to_redis_stream(events, redis_info, self.stream));
+        Ok(sink)
+    }
+
+    #[cfg(not(feature = "redis"))]
+    pub fn spawn_publisher(self) -> Result<BalanceEventSink, ()> {
+        error!(target: "interledger-node", "Publishing balance events requires the node to be built with the redis feature");
+        Err(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_defaults() {
+        let config: BalanceEventsConfig =
+            serde_json::from_str(r#"{"redis_url": "redis://127.0.0.1:6379"}"#).unwrap();
+        assert_eq!(config.stream, "balance_events");
+        assert_eq!(config.buffer_size, 10_000);
+        assert_eq!(config.when_full, OverflowPolicy::Drop);
+
+        let config: BalanceEventsConfig = serde_json::from_str(
+            r#"{"redis_url": "redis://127.0.0.1:6379", "stream": "audit", "buffer_size": 10, "when_full": "block"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.stream, "audit");
+        assert_eq!(config.buffer_size, 10);
+        assert_eq!(config.when_full, OverflowPolicy::Block);
+    }
+
+    #[test]
+    fn rejects_buffer_size_of_zero() {
+        let result: Result<BalanceEventsConfig, _> = serde_json::from_str(
+            r#"{"redis_url": "redis://127.0.0.1:6379", "buffer_size": 0}"#,
+        );
+        assert!(result.is_err());
+    }
+}
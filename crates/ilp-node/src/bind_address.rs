@@ -0,0 +1,298 @@
+use futures::{future::Either, Future, Stream, StreamExt, TryStreamExt};
+use interledger::http::IdleTimeout;
+use net2::TcpBuilder;
+use serde::{de::Error as DeserializeError, Deserialize, Deserializer};
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    net::SocketAddr,
+    os::unix::fs::FileTypeExt,
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+use tokio::net::{TcpListener, TcpStream, UnixListener};
+use tracing::{debug, error};
+use warp::{filters::BoxedFilter, Reply};
+
+/// Number of connections queued until they are accepted, which is what the node's
+/// listeners used before the backlog was configurable
+pub const DEFAULT_LISTEN_BACKLOG: u32 = 1024;
+
+/// Options for the TCP sockets of the node's HTTP servers (which also accept BTP connections)
+#[derive(Clone, Debug, PartialEq)]
+pub struct TcpOptions {
+    /// Maximum number of connections queued until they are accepted
+    pub listen_backlog: u32,
+    /// How long a connection is idle before TCP keepalive probes are sent.
+    /// None disables keepalive
+    pub keepalive: Option<Duration>,
+    /// How long a connection may be idle (neither reading nor writing anything) before
+    /// the node closes it. None keeps idle connections open
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for TcpOptions {
+    fn default() -> Self {
+        TcpOptions {
+            listen_backlog: DEFAULT_LISTEN_BACKLOG,
+            keepalive: None,
+            idle_timeout: None,
+        }
+    }
+}
+
+impl TcpOptions {
+    /// Binds a listener with the configured backlog to the address
+    pub fn bind(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let builder = match addr {
+            SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+            SocketAddr::V6(_) => TcpBuilder::new_v6()?,
+        };
+        builder.reuse_address(true)?;
+        let listener = builder.bind(addr)?.listen(self.listen_backlog as i32)?;
+        listener.set_nonblocking(true)?;
+        TcpListener::from_std(listener)
+    }
+
+    /// Returns the connections the listener accepts, with the configured keepalive.
+    /// Errors accepting a connection are logged and skipped, so that the stream only
+    /// ends with the listener.
+    pub fn incoming(
+        &self,
+        listener: TcpListener,
+    ) -> impl Stream<Item = io::Result<TcpStream>> + Unpin {
+        let keepalive = self.keepalive;
+        futures::stream::unfold(listener, |mut listener| async move {
+            let stream = listener.accept().await.map(|(stream, _)| stream);
+            Some((stream, listener))
+        })
+        .filter_map(move |stream| {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    error!(target: "interledger-node", "Error accepting connection: {}", err);
+                    return futures::future::ready(None);
+                }
+            };
+            if let Err(err) = stream.set_keepalive(keepalive) {
+                debug!(target: "interledger-node", "Error setting TCP keepalive: {}", err);
+            }
+            futures::future::ready(Some(Ok(stream)))
+        })
+        .boxed()
+    }
+}
+
+/// Address one of the node's HTTP servers listens on: either an IP address and port
+/// (`127.0.0.1:7770`) or the path of a unix domain socket (`unix:/run/ilp-node/api.sock`)
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BindAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl BindAddress {
+    /// Returns the IP address and port, unless this is a unix domain socket
+    pub fn tcp(&self) -> Option<SocketAddr> {
+        match self {
+            BindAddress::Tcp(addr) => Some(*addr),
+            BindAddress::Unix(_) => None,
+        }
+    }
+
+    /// Returns whether the node's servers could not listen on both addresses at once,
+    /// either because they are the same or because they use the same port and one of
+    /// them listens on all interfaces (e.g. `0.0.0.0:7770` and `127.0.0.1:7770`).
+    /// Port 0 picks a free port, so it never overlaps.
+    pub fn overlaps(&self, other: &BindAddress) -> bool {
+        match (self, other) {
+            (BindAddress::Tcp(a), BindAddress::Tcp(b)) => {
+                a.port() != 0
+                    && a.port() == b.port()
+                    && (a.ip() == b.ip() || a.ip().is_unspecified() || b.ip().is_unspecified())
+            }
+            (BindAddress::Unix(a), BindAddress::Unix(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Binds to the address. The options only apply if this is an IP address and port.
+    ///
+    /// A unix domain socket left behind by a previous run of the node is replaced. Access to
+    /// the socket can be restricted with the permissions of the directory it is created in.
+    pub fn bind(&self, options: &TcpOptions) -> Result<Listener, ()> {
+        match self {
+            BindAddress::Tcp(addr) => options.bind(*addr).map(Listener::Tcp).map_err(
+                |err| error!(target: "interledger-node", "Error binding to {}: {}", addr, err),
+            ),
+            BindAddress::Unix(path) => {
+                if let Ok(metadata) = fs::metadata(path) {
+                    if metadata.file_type().is_socket() {
+                        fs::remove_file(path).map_err(|err| {
+                            error!(target: "interledger-node",
+                                "Error removing old unix socket {}: {}", path.display(), err
+                            )
+                        })?;
+                    }
+                }
+                UnixListener::bind(path).map(Listener::Unix).map_err(|err| {
+                    error!(target: "interledger-node",
+                        "Error binding to unix socket {}: {}", path.display(), err
+                    )
+                })
+            }
+        }
+    }
+}
+
+/// A listener bound to a [`BindAddress`](./enum.BindAddress.html), which is served later
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Returns a future serving the filter over plain HTTP on the listener. The options,
+    /// except for the idle timeout, only apply to TCP listeners.
+    pub fn serve<T>(
+        self,
+        filter: BoxedFilter<(T,)>,
+        options: &TcpOptions,
+    ) -> impl Future<Output = ()>
+    where
+        T: Reply + Send + 'static,
+    {
+        let idle_timeout = options.idle_timeout;
+        match self {
+            Listener::Tcp(listener) => {
+                let incoming = options
+                    .incoming(listener)
+                    .map_ok(move |stream| IdleTimeout::new(stream, idle_timeout));
+                Either::Left(warp::serve(filter).run_incoming(incoming))
+            }
+            Listener::Unix(listener) => {
+                let incoming = futures::stream::unfold(listener, |mut listener| async move {
+                    let stream = listener.accept().await.map(|(stream, _)| stream);
+                    Some((stream, listener))
+                })
+                .map_ok(move |stream| IdleTimeout::new(stream, idle_timeout));
+                Either::Right(warp::serve(filter).run_incoming(Box::pin(incoming)))
+            }
+        }
+    }
+}
+
+impl From<SocketAddr> for BindAddress {
+    fn from(addr: SocketAddr) -> Self {
+        BindAddress::Tcp(addr)
+    }
+}
+
+impl FromStr for BindAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        if let (Some("unix"), Some(path)) = (parts.next(), parts.next()) {
+            if path.is_empty() {
+                return Err("The path of the unix socket is missing".to_string());
+            }
+            Ok(BindAddress::Unix(PathBuf::from(path)))
+        } else {
+            SocketAddr::from_str(s)
+                .map(BindAddress::Tcp)
+                .map_err(|err| format!("Invalid bind address {}: {}", s, err))
+        }
+    }
+}
+
+impl Display for BindAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindAddress::Tcp(addr) => write!(f, "{}", addr),
+            BindAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BindAddress {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        BindAddress::from_str(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_socket_addresses_and_unix_sockets() {
+        assert_eq!(
+            BindAddress::from_str("127.0.0.1:7770").unwrap(),
+            BindAddress::Tcp(SocketAddr::from(([127, 0, 0, 1], 7770)))
+        );
+        assert_eq!(
+            BindAddress::from_str("unix:/run/ilp-node/api.sock").unwrap(),
+            BindAddress::Unix(PathBuf::from("/run/ilp-node/api.sock"))
+        );
+        assert_eq!(
+            BindAddress::from_str("unix:/run/ilp-node/api.sock")
+                .unwrap()
+                .to_string(),
+            "unix:/run/ilp-node/api.sock"
+        );
+        assert!(BindAddress::from_str("unix:").is_err());
+        assert!(BindAddress::from_str("localhost").is_err());
+    }
+
+    #[test]
+    fn detects_overlapping_addresses() {
+        let overlaps = |a: &str, b: &str| {
+            BindAddress::from_str(a)
+                .unwrap()
+                .overlaps(&BindAddress::from_str(b).unwrap())
+        };
+        assert!(overlaps("127.0.0.1:7770", "127.0.0.1:7770"));
+        assert!(overlaps("0.0.0.0:7770", "127.0.0.1:7770"));
+        assert!(overlaps("127.0.0.1:7770", "[::]:7770"));
+        assert!(overlaps("unix:/run/api.sock", "unix:/run/api.sock"));
+        assert!(!overlaps("127.0.0.1:7770", "127.0.0.2:7770"));
+        assert!(!overlaps("0.0.0.0:7770", "0.0.0.0:7771"));
+        assert!(!overlaps("0.0.0.0:0", "0.0.0.0:0"));
+        assert!(!overlaps("unix:/run/api.sock", "unix:/run/other.sock"));
+    }
+
+    #[tokio::test]
+    async fn applies_tcp_options_to_accepted_connections() {
+        let options = TcpOptions {
+            listen_backlog: 16,
+            keepalive: Some(Duration::from_secs(30)),
+            idle_timeout: None,
+        };
+        let listener = options.bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut incoming = options.incoming(listener);
+
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let stream = incoming.next().await.unwrap().unwrap();
+        assert_eq!(stream.keepalive().unwrap(), Some(Duration::from_secs(30)));
+    }
+
+    #[tokio::test]
+    async fn disables_keepalive_by_default() {
+        let options = TcpOptions::default();
+        assert_eq!(options.listen_backlog, DEFAULT_LISTEN_BACKLOG);
+        let listener = options.bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut incoming = options.incoming(listener);
+
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let stream = incoming.next().await.unwrap().unwrap();
+        assert_eq!(stream.keepalive().unwrap(), None);
+    }
+}
@@ -0,0 +1,244 @@
+use serde::{de::Error as DeserializeError, Deserialize, Deserializer};
+use std::time::Duration;
+use url::Url;
+use warp::{
+    filters::BoxedFilter,
+    http::{header::HeaderName, Method},
+    Filter, Rejection, Reply,
+};
+
+/// Cross-Origin Resource Sharing policy of the node's HTTP API, which lets browser-based
+/// tools such as dashboards call it from other origins. Preflight requests are answered by
+/// the node, and requests from origins which are not allowed are rejected with 403 Forbidden.
+#[derive(Deserialize, Clone, Debug)]
+pub struct CorsConfig {
+    /// Origins (e.g. `https://dashboard.example.com`) which may call the API,
+    /// or `*` to allow every origin
+    #[serde(deserialize_with = "deserialize_origins")]
+    pub allowed_origins: Vec<String>,
+    /// Methods which may be used. Defaults to `GET`, `POST`, `PUT` and `DELETE`.
+    #[serde(
+        default = "CorsConfig::default_allowed_methods",
+        deserialize_with = "deserialize_methods"
+    )]
+    pub allowed_methods: Vec<Method>,
+    /// Headers which may be sent. Defaults to `authorization` and `content-type`.
+    #[serde(
+        default = "CorsConfig::default_allowed_headers",
+        deserialize_with = "deserialize_headers"
+    )]
+    pub allowed_headers: Vec<HeaderName>,
+    /// How long, in seconds, browsers may cache the responses to preflight requests
+    #[serde(default)]
+    pub max_age: Option<u64>,
+}
+
+impl CorsConfig {
+    fn default_allowed_methods() -> Vec<Method> {
+        vec![Method::GET, Method::POST, Method::PUT, Method::DELETE]
+    }
+
+    fn default_allowed_headers() -> Vec<HeaderName> {
+        vec![
+            HeaderName::from_static("authorization"),
+            HeaderName::from_static("content-type"),
+        ]
+    }
+
+    fn cors(&self) -> warp::filters::cors::Cors {
+        let mut cors = warp::cors()
+            .allow_methods(self.allowed_methods.clone())
+            .allow_headers(self.allowed_headers.clone());
+        cors = if self.allowed_origins.iter().any(|origin| origin == "*") {
+            cors.allow_any_origin()
+        } else {
+            cors.allow_origins(self.allowed_origins.iter().map(String::as_str))
+        };
+        if let Some(max_age) = self.max_age {
+            cors = cors.max_age(Duration::from_secs(max_age));
+        }
+        cors.build()
+    }
+}
+
+/// Applies the CORS policy to the filter, if one is configured. Without one, requests
+/// are handled the same whatever their origin (so browsers only let pages of the same
+/// origin read the responses).
+pub fn with_cors<F, T>(filter: F, cors: Option<&CorsConfig>) -> BoxedFilter<(Box<dyn Reply>,)>
+where
+    F: Filter<Extract = (T,), Error = Rejection> + Clone + Send + Sync + 'static,
+    T: Reply + 'static,
+{
+    match cors {
+        Some(cors) => filter
+            .with(cors.cors())
+            .map(|reply| Box::new(reply) as Box<dyn Reply>)
+            .boxed(),
+        None => filter
+            .map(|reply| Box::new(reply) as Box<dyn Reply>)
+            .boxed(),
+    }
+}
+
+fn deserialize_origins<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|origin| {
+            if origin == "*" {
+                return Ok(origin);
+            }
+            let url = Url::parse(&origin).map_err(|err| {
+                DeserializeError::custom(format!("Invalid CORS origin {}: {:?}", origin, err))
+            })?;
+            let is_origin = (url.scheme() == "http" || url.scheme() == "https")
+                && url.has_host()
+                && url.path() == "/"
+                && url.query().is_none()
+                && url.fragment().is_none();
+            if is_origin {
+                Ok(url.origin().ascii_serialization())
+            } else {
+                Err(DeserializeError::custom(format!(
+                    "Invalid CORS origin {} (must be a scheme and a host, e.g. https://example.com)",
+                    origin
+                )))
+            }
+        })
+        .collect()
+}
+
+fn deserialize_methods<'de, D>(deserializer: D) -> Result<Vec<Method>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|method| {
+            Method::from_bytes(method.to_uppercase().as_bytes())
+                .map_err(|_| DeserializeError::custom(format!("Invalid CORS method: {}", method)))
+        })
+        .collect()
+}
+
+fn deserialize_headers<'de, D>(deserializer: D) -> Result<Vec<HeaderName>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|header| {
+            HeaderName::from_bytes(header.as_bytes())
+                .map_err(|_| DeserializeError::custom(format!("Invalid CORS header: {}", header)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_api() -> BoxedFilter<(Box<dyn Reply>,)> {
+        let config: CorsConfig = serde_json::from_str(
+            r#"{"allowed_origins": ["https://dashboard.example.com/"], "max_age": 600}"#,
+        )
+        .unwrap();
+        let api = warp::path("accounts").map(|| "accounts");
+        with_cors(api, Some(&config))
+    }
+
+    #[tokio::test]
+    async fn answers_preflight_requests_from_allowed_origins() {
+        let resp = warp::test::request()
+            .method("OPTIONS")
+            .path("/accounts")
+            .header("origin", "https://dashboard.example.com")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "authorization")
+            .reply(&test_api())
+            .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let headers = resp.headers();
+        assert_eq!(
+            headers["access-control-allow-origin"],
+            "https://dashboard.example.com"
+        );
+        assert!(headers["access-control-allow-methods"]
+            .to_str()
+            .unwrap()
+            .contains("POST"));
+        assert!(headers["access-control-allow-headers"]
+            .to_str()
+            .unwrap()
+            .contains("authorization"));
+        assert_eq!(headers["access-control-max-age"], "600");
+    }
+
+    #[tokio::test]
+    async fn adds_headers_for_allowed_origins() {
+        let resp = warp::test::request()
+            .path("/accounts")
+            .header("origin", "https://dashboard.example.com")
+            .reply(&test_api())
+            .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body(), "accounts");
+        assert_eq!(
+            resp.headers()["access-control-allow-origin"],
+            "https://dashboard.example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn blocks_other_origins() {
+        let resp = warp::test::request()
+            .method("OPTIONS")
+            .path("/accounts")
+            .header("origin", "https://evil.example.com")
+            .header("access-control-request-method", "GET")
+            .reply(&test_api())
+            .await;
+        assert_eq!(resp.status().as_u16(), 403);
+
+        let resp = warp::test::request()
+            .path("/accounts")
+            .header("origin", "https://evil.example.com")
+            .reply(&test_api())
+            .await;
+        assert_eq!(resp.status().as_u16(), 403);
+        assert!(resp.headers().get("access-control-allow-origin").is_none());
+
+        // Requests which are not made by browsers are not affected
+        let resp = warp::test::request()
+            .path("/accounts")
+            .reply(&test_api())
+            .await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn does_not_add_headers_without_policy() {
+        let api = with_cors(warp::path("accounts").map(|| "accounts"), None);
+        let resp = warp::test::request()
+            .path("/accounts")
+            .header("origin", "https://dashboard.example.com")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert!(resp.headers().get("access-control-allow-origin").is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_policies() {
+        for config in &[
+            r#"{"allowed_origins": ["dashboard.example.com"]}"#,
+            r#"{"allowed_origins": ["https://example.com/dashboard"]}"#,
+            r#"{"allowed_origins": ["*"], "allowed_methods": ["GET POST"]}"#,
+            r#"{"allowed_origins": ["*"], "allowed_headers": ["bad header"]}"#,
+        ] {
+            assert!(serde_json::from_str::<CorsConfig>(config).is_err());
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use crate::bind_address::TcpOptions;
+use futures::Future;
+use interledger::http::{
+    parse_ca_certificates, restrict_tls_server_config, serve_tls_incoming, tls_server_config,
+    TlsVersion,
+};
+use reqwest::Certificate;
+use serde::Deserialize;
+use std::{fs, path::PathBuf};
+use tokio::net::TcpListener;
+use tracing::error;
+use warp::{filters::BoxedFilter, Reply};
+
+/// Configuration for serving ILP over HTTP (and everything else served on the
+/// `http_bind_address`) over HTTPS.
+#[derive(Deserialize, Clone, Debug)]
+pub struct HttpTlsConfig {
+    /// Path of the PEM-encoded certificate chain of the node
+    pub certificate: PathBuf,
+    /// Path of the PEM-encoded (PKCS8 or RSA) private key of the certificate
+    pub private_key: PathBuf,
+    /// Path of the PEM-encoded certificates of the authorities which issue the peers'
+    /// client certificates. If this is set, peers may authenticate with one of the
+    /// client certificates listed in their account's `ilp_over_http_client_certificates`
+    /// instead of a bearer token.
+    #[serde(default)]
+    pub client_ca: Option<PathBuf>,
+    /// The oldest TLS version connections may negotiate, `1.2` (the default) or `1.3`
+    #[serde(default)]
+    pub min_version: TlsVersion,
+    /// Names of the cipher suites connections may negotiate, e.g. `TLS13_AES_256_GCM_SHA384`.
+    /// Defaults to all cipher suites supported by the node.
+    #[serde(default)]
+    pub cipher_suites: Vec<String>,
+}
+
+fn read_pem(path: &PathBuf) -> Result<Vec<u8>, ()> {
+    fs::read(path).map_err(
+        |err| error!(target: "interledger-node", "Error reading {}: {}", path.display(), err),
+    )
+}
+
+impl HttpTlsConfig {
+    /// Loads the certificates and returns a future serving the filter over HTTPS on the listener
+    pub fn serve<T>(
+        &self,
+        filter: BoxedFilter<(T,)>,
+        listener: TcpListener,
+        options: &TcpOptions,
+    ) -> Result<impl Future<Output = ()>, ()>
+    where
+        T: Reply + Send + 'static,
+    {
+        let certificate = read_pem(&self.certificate)?;
+        let private_key = read_pem(&self.private_key)?;
+        let client_ca = match self.client_ca {
+            Some(ref path) => Some(read_pem(path)?),
+            None => None,
+        };
+        let config = tls_server_config(&certificate, &private_key, client_ca.as_deref())
+            .and_then(|mut config| {
+                restrict_tls_server_config(&mut config, self.min_version, &self.cipher_suites)?;
+                Ok(config)
+            })
+            .map_err(
+                |err| error!(target: "interledger-node", "Invalid HTTP TLS configuration: {}", err),
+            )?;
+        Ok(serve_tls_incoming(
+            filter,
+            options.incoming(listener),
+            config,
+            options.idle_timeout,
+        ))
+    }
+}
+
+/// Loads the certificate authorities of the node's `ca_bundle`, which is either
+/// the PEM-encoded certificates themselves or the path of a file containing them
+pub fn load_ca_bundle(bundle: &str) -> Result<Vec<Certificate>, ()> {
+    let pem = if bundle.contains("-----BEGIN") {
+        bundle.as_bytes().to_vec()
+    } else {
+        read_pem(&PathBuf::from(bundle))?
+    };
+    parse_ca_certificates(&pem)
+        .map_err(|err| error!(target: "interledger-node", "Invalid CA bundle: {}", err))
+}
@@ -65,6 +65,7 @@ type BoxedIlpFuture = Box<dyn Future<Output = IlpResult> + Send + 'static>;
 /// This is an experimental feature that may be removed in the future.
 pub fn create_google_pubsub_wrapper<A: Account + 'static>(
     config: Option<PubsubConfig>,
+    client: Client,
 ) -> impl Fn(OutgoingRequest<A>, Box<dyn OutgoingService<A> + Send>) -> Pin<BoxedIlpFuture> + Clone
 {
     // If Google credentials were passed in, create an HTTP client and
@@ -79,7 +80,6 @@ pub fn create_google_pubsub_wrapper<A: Account + 'static>(
         let token_fetcher = Arc::new(Mutex::new(access.build()));
 
         // TODO make sure the client uses HTTP/2
-        let client = Client::new();
         let api_endpoint = Arc::new(format!(
             "https://pubsub.googleapis.com/v1/projects/{}/topics/{}:publish",
             config.project_id, config.topic
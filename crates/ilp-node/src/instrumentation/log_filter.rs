@@ -0,0 +1,337 @@
+use interledger::errors::ApiError;
+use once_cell::sync::OnceCell;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{debug, error};
+use tracing_subscriber::{
+    filter::EnvFilter,
+    fmt::{time::ChronoUtc, Subscriber},
+};
+
+/// The filter of the process' global tracing subscriber
+static GLOBAL_FILTER: OnceCell<LogFilter> = OnceCell::new();
+
+type Reload = dyn Fn(EnvFilter) -> Result<(), String> + Send + Sync;
+
+/// Filter of the node's log lines which can be changed at runtime, either by replacing it
+/// or by adding directives for single modules on top of it (optionally only for a while)
+#[derive(Clone)]
+pub struct LogFilter {
+    state: Arc<Mutex<FilterState>>,
+    reload: Arc<Reload>,
+}
+
+struct FilterState {
+    /// The directives which are used unless they are overridden
+    base: String,
+    /// The added directives by the target they apply to, along with
+    /// the id of the change that added them
+    overrides: BTreeMap<String, (String, u64)>,
+    next_id: u64,
+}
+
+impl LogFilter {
+    /// Installs the process' global tracing subscriber, with the filter of the `RUST_LOG`
+    /// environment variable, and returns its filter. The subscriber is installed only once,
+    /// so the nodes of all tenants share it.
+    pub fn init() -> LogFilter {
+        GLOBAL_FILTER
+            .get_or_init(|| {
+                let base = std::env::var("RUST_LOG").unwrap_or_default();
+                let builder = Subscriber::builder()
+                    .with_timer(ChronoUtc::rfc3339())
+                    .with_env_filter(EnvFilter::from_default_env())
+                    .with_filter_reloading();
+                let handle = builder.reload_handle();
+                builder.try_init().unwrap_or(());
+                LogFilter::new(&base, move |filter| {
+                    handle.reload(filter).map_err(|err| err.to_string())
+                })
+            })
+            .clone()
+    }
+
+    /// Creates a filter with the given directives (in the `RUST_LOG` format), which applies
+    /// every change with the given function
+    pub fn new<F>(base: &str, reload: F) -> Self
+    where
+        F: Fn(EnvFilter) -> Result<(), String> + Send + Sync + 'static,
+    {
+        LogFilter {
+            state: Arc::new(Mutex::new(FilterState {
+                base: base.to_string(),
+                overrides: BTreeMap::new(),
+                next_id: 0,
+            })),
+            reload: Arc::new(reload),
+        }
+    }
+
+    /// Returns the directives the log lines are currently filtered with
+    pub fn current(&self) -> String {
+        self.state.lock().unwrap().directives()
+    }
+
+    /// Replaces the filter (including all of the added directives) with the given directives
+    pub fn set(&self, directives: &str) -> Result<String, ApiError> {
+        let filter = parse_filter(directives)?;
+        let mut state = self.state.lock().unwrap();
+        (self.reload)(filter).map_err(could_not_apply)?;
+        state.base = directives.to_string();
+        state.overrides.clear();
+        debug!(target: "interledger-node", "Logging level adjusted to {}", directives);
+        Ok(state.directives())
+    }
+
+    /// Adds the given directives on top of the filter, replacing the directives for the
+    /// same targets. If a duration is given, the directives are removed again once it has passed.
+    pub fn add(&self, directives: &str, duration: Option<Duration>) -> Result<String, ApiError> {
+        // Check that the directives are valid on their own before combining them
+        parse_filter(directives)?;
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        let mut overrides = state.overrides.clone();
+        for directive in split_directives(directives) {
+            overrides.insert(directive_target(directive), (directive.to_string(), id));
+        }
+        let combined = combine(&state.base, &overrides);
+        (self.reload)(parse_filter(&combined)?).map_err(could_not_apply)?;
+        state.overrides = overrides;
+        state.next_id += 1;
+        debug!(target: "interledger-node", "Logging level adjusted to {}", combined);
+
+        if let Some(duration) = duration {
+            let filter = self.clone();
+            tokio::spawn(async move {
+                tokio::time::delay_for(duration).await;
+                filter.expire(id);
+            });
+        }
+        Ok(combined)
+    }
+
+    /// Removes the directives added by the given change, unless they were replaced since
+    fn expire(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        let mut overrides = state.overrides.clone();
+        overrides.retain(|_, (_, added_by)| *added_by != id);
+        if overrides.len() == state.overrides.len() {
+            return;
+        }
+        let combined = combine(&state.base, &overrides);
+        let applied = parse_filter(&combined)
+            .map_err(|_| "invalid directives".to_string())
+            .and_then(|filter| (self.reload)(filter));
+        match applied {
+            Ok(()) => {
+                state.overrides = overrides;
+                debug!(target: "interledger-node", "Logging level reverted to {}", combined);
+            }
+            Err(err) => {
+                error!(target: "interledger-node", "Could not revert logging level: {}", err)
+            }
+        }
+    }
+}
+
+impl FilterState {
+    fn directives(&self) -> String {
+        combine(&self.base, &self.overrides)
+    }
+}
+
+/// Returns the base directives, without the ones overridden, followed by the overrides
+fn combine(base: &str, overrides: &BTreeMap<String, (String, u64)>) -> String {
+    // Logging errors is the default if no directives are given at all
+    let base = if base.trim().is_empty() {
+        "error"
+    } else {
+        base
+    };
+    split_directives(base)
+        .into_iter()
+        .filter(|directive| !overrides.contains_key(&directive_target(directive)))
+        .chain(overrides.values().map(|(directive, _)| directive.as_str()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Splits a `RUST_LOG` filter into its directives. Commas inside of the span and field
+/// filters of a directive do not separate directives.
+fn split_directives(directives: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in directives.char_indices() {
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                split.push(&directives[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&directives[start..]);
+    split
+        .into_iter()
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .collect()
+}
+
+/// Returns the part of the directive which determines what it applies to
+/// (an empty string for the global level)
+fn directive_target(directive: &str) -> String {
+    let target = match directive.rfind('=') {
+        // The last `=` may be part of a field filter if there is no level
+        Some(i) if !directive[i..].contains(|c: char| c == ']' || c == '}') => &directive[..i],
+        _ => directive,
+    };
+    let is_level = ["trace", "debug", "info", "warn", "error", "off"]
+        .iter()
+        .any(|level| target.eq_ignore_ascii_case(level));
+    if is_level {
+        String::new()
+    } else {
+        target.to_string()
+    }
+}
+
+fn parse_filter(directives: &str) -> Result<EnvFilter, ApiError> {
+    directives
+        .parse::<EnvFilter>()
+        .map_err(|_| ApiError::bad_request().detail("could not parse body as log level"))
+}
+
+fn could_not_apply(err: String) -> ApiError {
+    ApiError::internal_server_error().detail(format!("could not apply new log level {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use tracing::{debug, info};
+
+    #[test]
+    fn adds_directives_for_a_module() {
+        let (filter, logs, subscriber) = test_setup("warn");
+        tracing::subscriber::with_default(subscriber, || {
+            info!(target: "interledger_ccp", "route update 1");
+            assert!(logs.lines().is_empty());
+
+            let current = filter.add("interledger_ccp=debug", None).unwrap();
+            assert_eq!(current, "warn,interledger_ccp=debug");
+            debug!(target: "interledger_ccp", "route update 2");
+            debug!(target: "interledger_btp", "btp message");
+            let lines = logs.lines();
+            assert_eq!(lines.len(), 1);
+            assert!(lines[0].contains("route update 2"));
+
+            // the module's directive is replaced while the rest is kept
+            assert_eq!(
+                filter.add("interledger_ccp=info", None).unwrap(),
+                "warn,interledger_ccp=info"
+            );
+            debug!(target: "interledger_ccp", "route update 3");
+            assert_eq!(logs.lines().len(), 1);
+        });
+    }
+
+    #[test]
+    fn replaces_the_filter() {
+        let (filter, logs, subscriber) = test_setup("interledger_ccp=debug");
+        tracing::subscriber::with_default(subscriber, || {
+            filter.add("interledger_btp=debug", None).unwrap();
+            assert_eq!(filter.set("info").unwrap(), "info");
+            debug!(target: "interledger_ccp", "route update");
+            debug!(target: "interledger_btp", "btp message");
+            info!(target: "interledger_btp", "btp connection");
+            let lines = logs.lines();
+            assert_eq!(lines.len(), 1);
+            assert!(lines[0].contains("btp connection"));
+
+            assert!(filter.set("not a valid=filter").is_err());
+            assert_eq!(filter.current(), "info");
+        });
+    }
+
+    #[tokio::test]
+    async fn removes_temporary_directives() {
+        let (filter, _logs, _subscriber) = test_setup("");
+        assert_eq!(filter.current(), "error");
+        filter
+            .add("interledger_ccp=debug", Some(Duration::from_millis(50)))
+            .unwrap();
+        filter.add("interledger_btp=debug", None).unwrap();
+        assert_eq!(
+            filter.current(),
+            "error,interledger_btp=debug,interledger_ccp=debug"
+        );
+
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        assert_eq!(filter.current(), "error,interledger_btp=debug");
+    }
+
+    #[test]
+    fn overrides_directives_of_the_same_target() {
+        let mut overrides = BTreeMap::new();
+        overrides.insert(directive_target("debug"), ("debug".to_string(), 0));
+        overrides.insert(
+            directive_target("interledger[incoming{from.id=1}]"),
+            ("interledger[incoming{from.id=1}]".to_string(), 1),
+        );
+        assert_eq!(
+            combine(
+                "warn,interledger[incoming{from.id=1}]=info,interledger=info",
+                &overrides
+            ),
+            "interledger=info,debug,interledger[incoming{from.id=1}]"
+        );
+    }
+
+    /// Returns a subscriber which writes its log lines to the returned logs,
+    /// and a filter which changes the subscriber's filter
+    fn test_setup(base: &str) -> (LogFilter, TestLogs, impl tracing::Subscriber + Send + Sync) {
+        let logs = TestLogs::default();
+        let writer = logs.clone();
+        let builder = Subscriber::builder()
+            .with_writer(move || writer.clone())
+            .with_env_filter(EnvFilter::new(base))
+            .with_filter_reloading();
+        let handle = builder.reload_handle();
+        let filter = LogFilter::new(base, move |filter| {
+            handle.reload(filter).map_err(|err| err.to_string())
+        });
+        (filter, logs, builder.finish())
+    }
+
+    /// Collects the log lines written to it
+    #[derive(Clone, Default)]
+    struct TestLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl TestLogs {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    impl io::Write for TestLogs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -7,6 +7,11 @@ use interledger::{
 use metrics::{self, labels, recorder, Key};
 use std::time::Instant;
 
+tokio::task_local! {
+    /// When the node received the Prepare packet which is currently being forwarded
+    static PREPARE_RECEIVED_AT: Instant;
+}
+
 pub async fn incoming_metrics<A: Account + CcpRoutingAccount>(
     request: IncomingRequest<A>,
     mut next: Box<dyn IncomingService<A> + Send>,
@@ -21,7 +26,9 @@ pub async fn incoming_metrics<A: Account + CcpRoutingAccount>(
     );
     let start_time = Instant::now();
 
-    let result = next.handle_request(request).await;
+    let result = PREPARE_RECEIVED_AT
+        .scope(start_time, next.handle_request(request))
+        .await;
     if result.is_ok() {
         recorder().increment_counter(
             Key::from_name_and_labels("requests.incoming.fulfill", labels.clone()),
@@ -41,6 +48,11 @@ pub async fn incoming_metrics<A: Account + CcpRoutingAccount>(
     result
 }
 
+/// Besides the metrics of the outgoing requests, this records the `requests.outgoing.latency`
+/// histogram labeled with the username of the peer the packet was sent to. It measures the
+/// time from when the node received the Prepare until the peer responded, so it includes the
+/// time the node spent processing the packet (unlike `requests.outgoing.duration`).
+/// Packets the node sends itself (like route broadcasts) are measured from when they were sent.
 pub async fn outgoing_metrics<A: Account + CcpRoutingAccount>(
     request: OutgoingRequest<A>,
     mut next: Box<dyn OutgoingService<A> + Send>,
@@ -60,7 +72,16 @@ pub async fn outgoing_metrics<A: Account + CcpRoutingAccount>(
     );
     let start_time = Instant::now();
 
+    let latency_labels = labels!("to_username" => request.to.username().to_string());
     let result = next.send_request(request).await;
+    let received_at = PREPARE_RECEIVED_AT
+        .try_with(|received_at| *received_at)
+        .unwrap_or(start_time);
+    recorder().record_histogram(
+        Key::from_name_and_labels("requests.outgoing.latency", latency_labels),
+        (Instant::now() - received_at).as_nanos() as u64,
+    );
+
     if result.is_ok() {
         recorder().increment_counter(
             Key::from_name_and_labels("requests.outgoing.fulfill", labels.clone()),
@@ -80,3 +101,142 @@ pub async fn outgoing_metrics<A: Account + CcpRoutingAccount>(
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use interledger::{
+        ccp::RoutingRelation,
+        packet::{Address, FulfillBuilder, PrepareBuilder},
+        service::Username,
+    };
+    use once_cell::sync::Lazy;
+    use std::{
+        collections::HashMap,
+        str::FromStr,
+        time::{Duration, SystemTime},
+    };
+    use tokio::time::delay_for;
+    use uuid::Uuid;
+
+    static ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.node").unwrap());
+
+    #[derive(Clone, Debug)]
+    struct TestAccount(Username);
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+        fn username(&self) -> &Username {
+            &self.0
+        }
+        fn ilp_address(&self) -> &Address {
+            &ADDRESS
+        }
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+    }
+
+    impl CcpRoutingAccount for TestAccount {
+        fn routing_relation(&self) -> RoutingRelation {
+            RoutingRelation::Peer
+        }
+    }
+
+    /// Peer which takes the given time to fulfill every packet
+    #[derive(Clone)]
+    struct MockPeer(Duration);
+
+    #[async_trait]
+    impl OutgoingService<TestAccount> for MockPeer {
+        async fn send_request(&mut self, _request: OutgoingRequest<TestAccount>) -> IlpResult {
+            delay_for(self.0).await;
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        }
+    }
+
+    /// Forwards every packet to the given peer through the outgoing metrics
+    #[derive(Clone)]
+    struct Forwarder {
+        to: TestAccount,
+        peer: MockPeer,
+    }
+
+    #[async_trait]
+    impl IncomingService<TestAccount> for Forwarder {
+        async fn handle_request(&mut self, request: IncomingRequest<TestAccount>) -> IlpResult {
+            // the node takes a while to process the packet before forwarding it
+            delay_for(Duration::from_millis(20)).await;
+            outgoing_metrics(
+                OutgoingRequest {
+                    from: request.from,
+                    to: self.to.clone(),
+                    original_amount: request.prepare.amount(),
+                    prepare: request.prepare,
+                },
+                Box::new(self.peer.clone()),
+            )
+            .await
+        }
+    }
+
+    #[tokio::test]
+    async fn records_latency_by_peer() {
+        let receiver = metrics_runtime::Receiver::builder().build().unwrap();
+        let controller = receiver.controller();
+        receiver.install();
+
+        let alice = TestAccount(Username::from_str("alice").unwrap());
+        let peers = vec![
+            ("fast", Duration::from_millis(0)),
+            ("slow", Duration::from_millis(200)),
+        ];
+        for (username, delay) in peers {
+            let forwarder = Forwarder {
+                to: TestAccount(Username::from_str(username).unwrap()),
+                peer: MockPeer(delay),
+            };
+            let prepare = PrepareBuilder {
+                destination: ADDRESS.clone(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build();
+            let request = IncomingRequest {
+                from: alice.clone(),
+                prepare,
+            };
+            incoming_metrics(request, Box::new(forwarder))
+                .await
+                .unwrap();
+        }
+
+        let mut latencies = HashMap::new();
+        for (key, measurement) in controller.snapshot().into_measurements() {
+            if key.name() != "requests.outgoing.latency" {
+                continue;
+            }
+            if let metrics_runtime::Measurement::Histogram(values) = measurement {
+                let peer = key.labels().next().unwrap().value().to_string();
+                latencies.insert(peer, values.decompress()[0]);
+            }
+        }
+        let millis = |peer: &str| Duration::from_nanos(latencies[peer]).as_millis();
+        // Both include the time spent processing the packet before forwarding it
+        assert!(millis("fast") >= 20);
+        assert!(millis("fast") < 200);
+        assert!(millis("slow") >= 220);
+    }
+}
@@ -1,4 +1,6 @@
 #[cfg(feature = "monitoring")]
+pub mod log_filter;
+#[cfg(feature = "monitoring")]
 pub mod metrics;
 #[cfg(feature = "monitoring")]
 pub mod trace;
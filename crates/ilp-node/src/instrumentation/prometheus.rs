@@ -1,19 +1,36 @@
+use crate::http_tls::load_ca_bundle;
+use crate::node::DEFAULT_ILP_ADDRESS;
 use crate::InterledgerNode;
 use metrics_core::{Builder, Drain, Observe};
-use metrics_runtime;
+use metrics_runtime::{self, observers::PrometheusBuilder, Controller};
+use reqwest::Client;
 use serde::Deserialize;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
-use tracing::{error, info};
+use std::{collections::HashSet, net::SocketAddr, sync::Arc, time::Duration};
+use tracing::{debug, error, info, warn};
 use warp::{
     http::{Response, StatusCode},
     Filter,
 };
 
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
 /// Configuration for [Prometheus](https://prometheus.io) metrics collection.
 #[derive(Deserialize, Clone)]
 pub struct PrometheusConfig {
     /// IP address and port to host the Prometheus endpoint on.
-    pub bind_address: SocketAddr,
+    /// If this is not set, the metrics are only pushed to the `push_gateway_url`.
+    #[serde(default)]
+    pub bind_address: Option<SocketAddr>,
+    /// URL of a [Pushgateway](https://github.com/prometheus/pushgateway) the metrics are
+    /// pushed to, for nodes Prometheus cannot scrape. The metrics are grouped by the
+    /// node's ILP address.
+    #[serde(default)]
+    pub push_gateway_url: Option<String>,
+    /// Interval, in milliseconds, on which the metrics are pushed to the
+    /// `push_gateway_url`. Defaults to 15000ms (15 seconds).
+    #[serde(default = "PrometheusConfig::default_push_interval")]
+    pub push_interval: u64,
     /// Amount of time, in milliseconds, that the node will collect data points for the
     /// Prometheus histograms. Defaults to 300000ms (5 minutes).
     #[serde(default = "PrometheusConfig::default_histogram_window")]
@@ -23,6 +40,45 @@ pub struct PrometheusConfig {
     /// 1 second of histogram data points every second. Defaults to 10000ms (10 seconds).
     #[serde(default = "PrometheusConfig::default_histogram_granularity")]
     pub histogram_granularity: u64,
+    /// Format the metrics are served in on the `bind_address`. Defaults to the Prometheus
+    /// text format. The metrics are always pushed in the Prometheus text format, since
+    /// that is the only text format the Pushgateway accepts.
+    #[serde(default)]
+    pub format: MetricsFormat,
+}
+
+/// Exposition format of the metrics endpoint
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum MetricsFormat {
+    /// The [Prometheus text format](https://prometheus.io/docs/instrumenting/exposition_formats/)
+    #[serde(alias = "prometheus")]
+    Prometheus,
+    /// The [OpenMetrics](https://openmetrics.io) text format
+    #[serde(alias = "openmetrics")]
+    OpenMetrics,
+}
+
+impl Default for MetricsFormat {
+    fn default() -> Self {
+        MetricsFormat::Prometheus
+    }
+}
+
+impl MetricsFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            MetricsFormat::Prometheus => PROMETHEUS_CONTENT_TYPE,
+            MetricsFormat::OpenMetrics => OPENMETRICS_CONTENT_TYPE,
+        }
+    }
+
+    fn render(self, controller: &Controller, observer: &PrometheusBuilder) -> String {
+        let metrics = render_metrics(controller, observer);
+        match self {
+            MetricsFormat::Prometheus => metrics,
+            MetricsFormat::OpenMetrics => to_openmetrics(&metrics),
+        }
+    }
 }
 
 impl PrometheusConfig {
@@ -33,13 +89,19 @@ impl PrometheusConfig {
     fn default_histogram_granularity() -> u64 {
         10_000
     }
+
+    fn default_push_interval() -> u64 {
+        15_000
+    }
 }
 
-/// Starts a Prometheus metrics server that will listen on the configured address.
+/// Starts a Prometheus metrics server that will listen on the configured address,
+/// and pushes the metrics to the configured Pushgateway.
 ///
 /// # Errors
 /// This will fail if another Prometheus server is already running in this
-/// process or on the configured port.
+/// process or on the configured port, or if neither an address nor a Pushgateway
+/// is configured.
 #[allow(clippy::cognitive_complexity)]
 pub async fn serve_prometheus(node: InterledgerNode) -> Result<(), ()> {
     let prometheus = if let Some(ref prometheus) = node.prometheus {
@@ -48,6 +110,10 @@ pub async fn serve_prometheus(node: InterledgerNode) -> Result<(), ()> {
         error!(target: "interledger-node", "No prometheus configuration provided");
         return Err(());
     };
+    if prometheus.bind_address.is_none() && prometheus.push_gateway_url.is_none() {
+        error!(target: "interledger-node", "The prometheus configuration needs a bind_address or a push_gateway_url");
+        return Err(());
+    }
 
     // Set up the metrics collector
     let receiver = metrics_runtime::Builder::default()
@@ -62,24 +128,53 @@ pub async fn serve_prometheus(node: InterledgerNode) -> Result<(), ()> {
     // Try installing the global recorder
     match metrics::set_boxed_recorder(Box::new(receiver)) {
         Ok(_) => {
-            let observer = Arc::new(metrics_runtime::observers::PrometheusBuilder::default());
-
-            let filter = warp::get().and(warp::path::end()).map(move || {
-                let mut observer = observer.build();
-                controller.observe(&mut observer);
-                let prometheus_response = observer.drain();
-                Response::builder()
-                    .status(StatusCode::OK)
-                    .header("Content-Type", "text/plain; version=0.0.4")
-                    .body(prometheus_response)
-            });
+            let observer = Arc::new(PrometheusBuilder::default());
 
-            info!(target: "interledger-node",
-                "Prometheus metrics server listening on: {}",
-                prometheus.bind_address
-            );
+            if let Some(ref push_gateway_url) = prometheus.push_gateway_url {
+                let root_certificates = match node.ca_bundle {
+                    Some(ref bundle) => load_ca_bundle(bundle)?,
+                    None => Vec::new(),
+                };
+                let client = root_certificates
+                    .into_iter()
+                    .fold(Client::builder(), reqwest::ClientBuilder::add_root_certificate)
+                    .build()
+                    .map_err(|err| error!(target: "interledger-node", "Error creating HTTP client: {}", err))?;
+                let ilp_address = node
+                    .ilp_address
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_ILP_ADDRESS.clone());
+                let url = push_url(push_gateway_url, &ilp_address.to_string());
+                info!(target: "interledger-node",
+                    "Pushing Prometheus metrics to: {}",
+                    url
+                );
+                let controller = controller.clone();
+                let observer = observer.clone();
+                tokio::spawn(push_metrics(
+                    client,
+                    url,
+                    Duration::from_millis(prometheus.push_interval),
+                    move || render_metrics(&controller, &observer),
+                ));
+            }
 
-            tokio::spawn(warp::serve(filter).bind(prometheus.bind_address));
+            if let Some(bind_address) = prometheus.bind_address {
+                let format = prometheus.format;
+                let filter = warp::get().and(warp::path::end()).map(move || {
+                    Response::builder()
+                        .status(StatusCode::OK)
+                        .header("Content-Type", format.content_type())
+                        .body(format.render(&controller, &observer))
+                });
+
+                info!(target: "interledger-node",
+                    "Prometheus metrics server listening on: {}",
+                    bind_address
+                );
+
+                tokio::spawn(warp::serve(filter).bind(bind_address));
+            }
             Ok(())
         }
         Err(e) => {
@@ -88,3 +183,222 @@ pub async fn serve_prometheus(node: InterledgerNode) -> Result<(), ()> {
         }
     }
 }
+
+/// Returns the metrics in the Prometheus text format
+fn render_metrics(controller: &Controller, observer: &PrometheusBuilder) -> String {
+    let mut observer = observer.build();
+    controller.observe(&mut observer);
+    observer.drain()
+}
+
+/// Converts metrics in the Prometheus text format to the OpenMetrics text format.
+/// The samples of counters get the `_total` suffix OpenMetrics requires, comments other
+/// than the metadata and empty lines (neither of which OpenMetrics allows) are dropped,
+/// and the exposition is terminated with `# EOF`.
+fn to_openmetrics(metrics: &str) -> String {
+    let mut counters = HashSet::new();
+    let mut output = String::with_capacity(metrics.len() + 6);
+    for line in metrics
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        if line.starts_with('#') {
+            let mut parts = line.splitn(4, ' ');
+            let (kind, name, rest) = match (parts.next(), parts.next(), parts.next()) {
+                (Some("#"), Some(kind), Some(name)) => (kind, name, parts.next()),
+                _ => continue,
+            };
+            let name = match (kind, rest) {
+                ("TYPE", Some("counter")) => {
+                    // The name of a counter's family must not include the suffix
+                    let family = name.trim_end_matches("_total");
+                    counters.insert(family.to_string());
+                    family
+                }
+                ("TYPE", Some(_)) | ("HELP", _) | ("UNIT", _) => name,
+                _ => continue,
+            };
+            output.push_str("# ");
+            output.push_str(kind);
+            output.push(' ');
+            output.push_str(name);
+            if let Some(rest) = rest {
+                output.push(' ');
+                output.push_str(rest);
+            }
+        } else {
+            let name_end = line.find(|c| c == '{' || c == ' ').unwrap_or(line.len());
+            let (name, rest) = line.split_at(name_end);
+            output.push_str(name);
+            if counters.contains(name) {
+                output.push_str("_total");
+            }
+            output.push_str(rest);
+        }
+        output.push('\n');
+    }
+    output.push_str("# EOF\n");
+    output
+}
+
+/// Returns the URL of the Pushgateway group of the node with the given ILP address
+fn push_url(push_gateway_url: &str, ilp_address: &str) -> String {
+    // ILP addresses only contain characters which are safe in URL paths
+    format!(
+        "{}/metrics/job/ilp_node/ilp_address/{}",
+        push_gateway_url.trim_end_matches('/'),
+        ilp_address
+    )
+}
+
+/// POSTs the rendered metrics to the given Pushgateway URL on every interval.
+/// Failed pushes are only logged, since the next push replaces the metrics anyway.
+async fn push_metrics<F>(client: Client, url: String, interval: Duration, render: F)
+where
+    F: Fn() -> String,
+{
+    loop {
+        tokio::time::delay_for(interval).await;
+        let result = client
+            .post(url.as_str())
+            .header("Content-Type", PROMETHEUS_CONTENT_TYPE)
+            .body(render())
+            .send()
+            .await;
+        match result {
+            Ok(res) if res.status().is_success() => {
+                debug!(target: "interledger-node", "Pushed Prometheus metrics to {}", url)
+            }
+            Ok(res) => warn!(target: "interledger-node",
+                "Prometheus Pushgateway {} responded with status {}",
+                url,
+                res.status()
+            ),
+            Err(err) => warn!(target: "interledger-node",
+                "Error pushing Prometheus metrics to {}: {:?}",
+                url,
+                err
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn pushes_metrics_on_the_interval() {
+        let receiver = metrics_runtime::Builder::default().build().unwrap();
+        let mut sink = receiver.sink();
+        sink.increment_counter("requests_incoming_prepare", 3);
+        let controller = receiver.controller();
+        let observer = PrometheusBuilder::default();
+
+        // Records the path and body of every push
+        let pushes = Arc::new(Mutex::new(Vec::new()));
+        let pushes_clone = pushes.clone();
+        let gateway = warp::post()
+            .and(warp::path::full())
+            .and(warp::body::bytes())
+            .map(move |path: warp::path::FullPath, body: bytes05::Bytes| {
+                let body = String::from_utf8(body.to_vec()).unwrap();
+                pushes_clone
+                    .lock()
+                    .unwrap()
+                    .push((path.as_str().to_string(), body));
+                ""
+            });
+        let (addr, server) = warp::serve(gateway).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let url = push_url(&format!("http://{}/", addr), "example.node");
+        tokio::spawn(push_metrics(
+            Client::new(),
+            url,
+            Duration::from_millis(50),
+            move || render_metrics(&controller, &observer),
+        ));
+
+        tokio::time::delay_for(Duration::from_millis(20)).await;
+        assert!(pushes.lock().unwrap().is_empty());
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+        let pushes = pushes.lock().unwrap();
+        assert!(pushes.len() >= 2);
+        for (path, body) in pushes.iter() {
+            assert_eq!(path, "/metrics/job/ilp_node/ilp_address/example.node");
+            assert!(body.contains("requests_incoming_prepare 3"));
+        }
+    }
+
+    #[test]
+    fn renders_openmetrics() {
+        let receiver = metrics_runtime::Builder::default().build().unwrap();
+        let mut sink = receiver.sink();
+        sink.increment_counter("requests_incoming_prepare", 3);
+        sink.increment_counter("requests_incoming_fulfill_total", 2);
+        sink.update_gauge("store_connected", 1);
+        sink.record_value("requests_incoming_prepare_duration", 42);
+        let controller = receiver.controller();
+        let observer = PrometheusBuilder::default();
+
+        let metrics = MetricsFormat::OpenMetrics.render(&controller, &observer);
+        assert!(metrics.ends_with("\n# EOF\n"));
+        assert_eq!(metrics.matches("# EOF").count(), 1);
+
+        // Every line is either metadata of a family or a sample of the last family
+        let mut family: Option<(String, String)> = None;
+        let mut samples = Vec::new();
+        for line in metrics.lines().take_while(|line| *line != "# EOF") {
+            let parts: Vec<&str> = line.split(' ').collect();
+            if parts[0] == "#" {
+                assert!(parts.len() >= 4, "Invalid metadata: {}", line);
+                assert!(["TYPE", "HELP", "UNIT"].contains(&parts[1]));
+                if parts[1] == "TYPE" {
+                    assert!(!parts[2].ends_with("_total"));
+                    family = Some((parts[2].to_string(), parts[3].to_string()));
+                }
+                continue;
+            }
+            let (name, kind) = family.clone().expect("Sample before its family");
+            let sample_name = parts[0].split('{').next().unwrap();
+            let suffix = &sample_name[name.len()..];
+            assert!(sample_name.starts_with(&name), "Invalid sample: {}", line);
+            match kind.as_str() {
+                "counter" => assert_eq!(suffix, "_total"),
+                "gauge" => assert_eq!(suffix, ""),
+                "summary" => assert!(["", "_sum", "_count"].contains(&suffix)),
+                "histogram" => assert!(["_bucket", "_sum", "_count"].contains(&suffix)),
+                _ => panic!("Unexpected metric type: {}", kind),
+            }
+            assert!(parts[1].parse::<f64>().is_ok(), "Invalid value: {}", line);
+            samples.push(parts[..2].join(" "));
+        }
+        assert!(samples.contains(&"requests_incoming_prepare_total 3".to_string()));
+        assert!(samples.contains(&"requests_incoming_fulfill_total 2".to_string()));
+        assert!(samples.contains(&"store_connected 1".to_string()));
+        assert!(samples
+            .iter()
+            .any(|sample| sample.starts_with("requests_incoming_prepare_duration_count")));
+    }
+
+    #[test]
+    fn converts_prometheus_text_to_openmetrics() {
+        let metrics = "# metrics snapshot (ts=1588000000) (prometheus exposition format)\n\
+                       # TYPE requests_incoming_prepare counter\n\
+                       requests_incoming_prepare{from=\"alice\"} 3\n\
+                       \n\
+                       # TYPE store_connected gauge\n\
+                       store_connected 1\n";
+        assert_eq!(
+            to_openmetrics(metrics),
+            "# TYPE requests_incoming_prepare counter\n\
+             requests_incoming_prepare_total{from=\"alice\"} 3\n\
+             # TYPE store_connected gauge\n\
+             store_connected 1\n\
+             # EOF\n"
+        );
+    }
+}
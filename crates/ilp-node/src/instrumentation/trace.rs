@@ -13,13 +13,14 @@ use uuid::Uuid;
 /// Add tracing context for the incoming request.
 /// This adds minimal information for the ERROR log
 /// level and more information for the DEBUG level.
+/// The request ID is not added here but by the span of the
+/// BTP, HTTP or API ingress the packet was received from.
 pub async fn trace_incoming<A: Account>(
     request: IncomingRequest<A>,
     mut next: Box<dyn IncomingService<A> + Send>,
 ) -> IlpResult {
     let request_span = error_span!(target: "interledger-node",
         "incoming",
-        request.id = %Uuid::new_v4(),
         prepare.destination = %request.prepare.destination(),
         prepare.amount = request.prepare.amount(),
         from.id = %request.from.id()
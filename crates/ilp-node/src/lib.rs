@@ -1,8 +1,22 @@
-#![type_length_limit = "10000000"]
+#![type_length_limit = "20000000"]
+mod balance_events;
+mod bind_address;
+mod cors;
+mod http_tls;
 mod instrumentation;
+mod maintenance;
 mod node;
+mod volume_alerts;
+mod webhooks;
 
 #[cfg(feature = "redis")]
 mod redis_store;
 
+pub use balance_events::BalanceEventsConfig;
+pub use bind_address::BindAddress;
+pub use cors::CorsConfig;
+pub use http_tls::HttpTlsConfig;
+pub use maintenance::MaintenanceConfig;
 pub use node::*;
+pub use volume_alerts::VolumeAlertsConfig;
+pub use webhooks::{IncomingPaymentWebhook, WebhookConfig, SIGNATURE_HEADER};
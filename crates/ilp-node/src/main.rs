@@ -1,6 +1,14 @@
-#![type_length_limit = "10000000"]
+#![type_length_limit = "20000000"]
+mod balance_events;
+mod bind_address;
+mod cors;
+mod http_tls;
 mod instrumentation;
+mod maintenance;
 pub mod node;
+mod version;
+mod volume_alerts;
+mod webhooks;
 
 #[cfg(feature = "redis")]
 mod redis_store;
@@ -11,10 +19,12 @@ use config::{ConfigError, FileFormat, Value};
 use libc::{c_int, isatty};
 use node::InterledgerNode;
 use std::{
+    collections::HashMap,
     ffi::{OsStr, OsString},
     io::Read,
     vec::Vec,
 };
+use url::Url;
 
 #[tokio::main]
 async fn main() {
@@ -33,9 +43,12 @@ async fn main() {
     //     - `http_bind_address`
     // - Addresses to which other services are bound
     //     - `xxx_bind_address`
+    let long_version = version::long_version();
     let mut app = App::new("ilp-node")
         .about("Run an Interledger.rs node (sender, connector, receiver bundle)")
         .version(crate_version!())
+        // Printed by --version (-V only prints the crate version)
+        .long_version(long_version.as_str())
         // TODO remove this line once this issue is solved:
     // https://github.com/clap-rs/clap/issues/1536
     .after_help("")
@@ -44,12 +57,16 @@ async fn main() {
         Arg::with_name("config")
             .takes_value(true)
             .index(1)
-            .help("Name of config file (in JSON, YAML, or TOML format)"),
+            .help("Name of config file (in JSON, YAML, or TOML format), or an http:// or https:// URL the config is fetched from"),
+        Arg::with_name("config_auth_token")
+            .long("config_auth_token")
+            .takes_value(true)
+            .help("Bearer token sent with the request fetching the config, if the config is read from a URL"),
         // Non-positional arguments
         Arg::with_name("ilp_address")
             .long("ilp_address")
             .takes_value(true)
-            .help("ILP Address of this account"),
+            .help("ILP Address of this account. If not set, the address is requested from the parent account (if any) via IL-DCP on startup"),
         Arg::with_name("secret_seed")
             .long("secret_seed")
             .takes_value(true)
@@ -60,17 +77,48 @@ async fn main() {
             .takes_value(true)
             .required(true)
             .help("HTTP Authorization token for the node admin (sent as a Bearer token)"),
+        Arg::with_name("read_only_admin_auth_token")
+            .long("read_only_admin_auth_token")
+            .takes_value(true)
+            .help("HTTP Authorization token which is accepted instead of the admin_auth_token for GET requests (except for GET /export), but is forbidden from making changes. Useful for dashboards."),
         Arg::with_name("database_url")
             .long("database_url")
             // temporary alias for backwards compatibility
             .alias("redis_url")
             .takes_value(true)
             .default_value("redis://127.0.0.1:6379")
-            .help("Redis URI (for example, \"redis://127.0.0.1:6379\" or \"unix:/tmp/redis.sock\")"),
+            .help("Data store URI (for example, \"redis://127.0.0.1:6379\", \"unix:/tmp/redis.sock\" or \"memory://\")"),
         Arg::with_name("http_bind_address")
             .long("http_bind_address")
             .takes_value(true)
-            .help("IP address and port to listen for HTTP connections. This is used for both the API and ILP over HTTP packets. ILP over HTTP is a means to transfer ILP packets instead of BTP connections"),
+            .help("IP address and port (or unix:/path/to/socket) to listen for HTTP connections. This is used for both the API and ILP over HTTP packets (unless admin_bind_address is set). ILP over HTTP is a means to transfer ILP packets instead of BTP connections"),
+        Arg::with_name("admin_bind_address")
+            .long("admin_bind_address")
+            .takes_value(true)
+            .help("IP address and port (or unix:/path/to/socket) to serve the API on. If this is set, the http_bind_address only accepts ILP over HTTP packets, BTP connections and the SPSP endpoints, so the API can be bound to a private interface or a unix domain socket."),
+        Arg::with_name("http_tls.certificate")
+            .long("http_tls.certificate")
+            .takes_value(true)
+            .help("Path of the PEM-encoded certificate chain to serve the http_bind_address over HTTPS with. \
+                If this is not set, the http_bind_address is served over plain HTTP."),
+        Arg::with_name("http_tls.private_key")
+            .long("http_tls.private_key")
+            .takes_value(true)
+            .help("Path of the PEM-encoded private key of the HTTPS certificate."),
+        Arg::with_name("http_tls.client_ca")
+            .long("http_tls.client_ca")
+            .takes_value(true)
+            .help("Path of the PEM-encoded certificates of the authorities issuing the peers' client certificates. \
+                If this is set, peers may authenticate ILP over HTTP requests with a client certificate \
+                listed in their account's ilp_over_http_client_certificates instead of a bearer token."),
+        Arg::with_name("http_listen_backlog")
+            .long("http_listen_backlog")
+            .takes_value(true)
+            .help("Maximum number of connections queued on the http_bind_address and admin_bind_address until they are accepted. Defaults to 1024."),
+        Arg::with_name("http_tcp_keepalive")
+            .long("http_tcp_keepalive")
+            .takes_value(true)
+            .help("Time, in milliseconds, the connections to the http_bind_address and admin_bind_address are idle before TCP keepalive probes are sent. Defaults to no keepalive."),
         Arg::with_name("settlement_api_bind_address")
             .long("settlement_api_bind_address")
             .takes_value(true)
@@ -79,19 +127,127 @@ async fn main() {
             .long("default_spsp_account")
             .takes_value(true)
             .help("When SPSP payments are sent to the root domain, the payment pointer is resolved to <domain>/.well-known/pay. This value determines which account those payments will be sent to."),
+        Arg::with_name("default_route")
+            .long("default_route")
+            .takes_value(true)
+            .help("Username of the account (usually the node's parent) that packets are forwarded to if there is no more specific route for their destination, instead of rejecting them as unreachable."),
         Arg::with_name("route_broadcast_interval")
             .long("route_broadcast_interval")
             .takes_value(true)
             .help("Interval, defined in milliseconds, on which the node will broadcast routing information to other nodes using CCP. Defaults to 30000ms (30 seconds)."),
+        Arg::with_name("route_broadcast_delay")
+            .long("route_broadcast_delay")
+            .takes_value(true)
+            .help("Time, defined in milliseconds, the node waits after startup before it broadcasts routes for the first time, so that the routing table is complete by then. Defaults to 0 (broadcasting right away)."),
+        Arg::with_name("route_broadcast_jitter")
+            .long("route_broadcast_jitter")
+            .takes_value(true)
+            .help("Maximum percentage by which each route broadcast interval is randomly lengthened or shortened, so that nodes do not all broadcast at the same time. Defaults to 0 (no jitter)."),
+        Arg::with_name("route_broadcast_concurrency")
+            .long("route_broadcast_concurrency")
+            .takes_value(true)
+            .help("Maximum number of peers each route broadcast is sent to at the same time, so that slow peers do not delay the updates to the others. Defaults to 50."),
+        Arg::with_name("max_routes_per_peer")
+            .long("max_routes_per_peer")
+            .takes_value(true)
+            .help("Maximum number of routes the node learns from each peer via CCP, so that a peer advertising too many prefixes can not exhaust its memory. Defaults to no limit."),
+        Arg::with_name("route_limit_policy")
+            .long("route_limit_policy")
+            .takes_value(true)
+            .possible_values(&["refuse", "evict_oldest"])
+            .help("What happens to the new prefixes a peer advertises once max_routes_per_peer is reached: refuse (the default) ignores and logs them, evict_oldest replaces the routes which were learned from that peer first."),
+        Arg::with_name("http2_prior_knowledge")
+            .long("http2_prior_knowledge")
+            .takes_value(true)
+            .help("Send ILP-over-HTTP packets to peers with cleartext http:// URLs over HTTP/2 without negotiating it first. All of those peers must support HTTP/2. Defaults to false (HTTP/1.1)."),
+        Arg::with_name("http_client_rustls")
+            .long("http_client_rustls")
+            .takes_value(true)
+            .help("Use rustls instead of the native TLS implementation for ILP-over-HTTP peers with https:// URLs, which negotiates HTTP/2 with the peers supporting it. Defaults to false (native TLS, HTTP/1.1)."),
+        Arg::with_name("btp_max_reconnect_delay")
+            .long("btp_max_reconnect_delay")
+            .takes_value(true)
+            .help("Longest delay, defined in milliseconds, between the attempts to reconnect to a peer whose BTP connection could not be established or went away. The delay starts at one second and doubles after every failed attempt. Defaults to 60000ms (1 minute)."),
+        Arg::with_name("balance_snapshot_max_pause")
+            .long("balance_snapshot_max_pause")
+            .takes_value(true)
+            .help("Longest time, defined in milliseconds, for which POST /balances/snapshot pauses the balance changes of packets. The snapshot fails if the balance changes which are being applied do not finish in that time. Defaults to 500ms."),
+        Arg::with_name("stream_packet_expiry")
+            .long("stream_packet_expiry")
+            .takes_value(true)
+            .help("Time, defined in milliseconds, before the packets of the STREAM payments sent by the node's accounts expire. Defaults to 30000ms (30 seconds)."),
+        Arg::with_name("min_asset_scale")
+            .long("min_asset_scale")
+            .takes_value(true)
+            .help("Smallest asset scale of the accounts created or replaced via the API. Defaults to 0."),
+        Arg::with_name("max_asset_scale")
+            .long("max_asset_scale")
+            .takes_value(true)
+            .help("Largest asset scale of the accounts created or replaced via the API. Defaults to 18."),
+        Arg::with_name("max_in_flight_packets")
+            .long("max_in_flight_packets")
+            .takes_value(true)
+            .help("Maximum number of incoming packets the node forwards at the same time. Packets over the limit are rejected with T03 (Connector Busy). Defaults to no limit."),
+        Arg::with_name("max_in_flight_packets_per_account")
+            .long("max_in_flight_packets_per_account")
+            .takes_value(true)
+            .help("Maximum number of incoming packets from a single account the node forwards at the same time. Packets over the limit are rejected with T03 (Connector Busy). Defaults to no limit."),
+        Arg::with_name("max_queued_outgoing_requests_per_account")
+            .long("max_queued_outgoing_requests_per_account")
+            .takes_value(true)
+            .help("Maximum number of outgoing packets to a single account which wait for the account's max_concurrent_outgoing_requests to allow them to be sent. Packets over the limit are rejected with T03 (Connector Busy). Defaults to 100."),
+        Arg::with_name("max_stream_connections_per_account")
+            .long("max_stream_connections_per_account")
+            .takes_value(true)
+            .help("Maximum number of incoming STREAM connections each account receives at the same time. Packets of further connections are rejected with a STREAM ConnectionClose frame. Defaults to no limit."),
+        Arg::with_name("max_streams_per_connection")
+            .long("max_streams_per_connection")
+            .takes_value(true)
+            .help("Maximum number of streams each incoming STREAM connection has open at the same time. Money sent on further streams is rejected with a STREAM StreamClose frame. Defaults to no limit."),
+        Arg::with_name("max_response_data_len")
+            .long("max_response_data_len")
+            .takes_value(true)
+            .help("Maximum length, in bytes, of the data of the Fulfills and Rejects returned by peers. Responses with more data are replaced with an F09 (Invalid Peer Response) reject. Defaults to 32767, the maximum allowed by the protocol."),
+        Arg::with_name("min_hold_time")
+            .long("min_hold_time")
+            .takes_value(true)
+            .help("Time, defined in milliseconds, the node reserves for itself to pass fulfillments back to the previous hop. The expiry of every forwarded Prepare is reduced by this amount on top of the accounts' round trip times, and Prepares with less time left are rejected with R00 (Transfer Timed Out). Defaults to 0."),
+        Arg::with_name("replay_protection_window")
+            .long("replay_protection_window")
+            .takes_value(true)
+            .help("Time, defined in milliseconds, for which the execution conditions of the fulfilled packets are remembered. Incoming Prepares with a condition which was fulfilled within this window are rejected with F00 (Bad Request). It should be at least the longest packet expiry (30000ms). Defaults to no replay protection."),
+        Arg::with_name("http_auth_cache_ttl")
+            .long("http_auth_cache_ttl")
+            .takes_value(true)
+            .help("Time, defined in milliseconds, for which the accounts which authenticated with an ILP over HTTP bearer token are cached, so that not every packet is checked against the database. Only used with Redis. Defaults to no caching."),
+        Arg::with_name("database_retry_timeout")
+            .long("database_retry_timeout")
+            .takes_value(true)
+            .help("Time, defined in milliseconds, for which the database commands wait while the node reconnects after the connection dropped, e.g. while Redis fails over to a replica. Commands which were already sent when the connection dropped are only sent again if they only read data. Only used with Redis. Defaults to 1000ms (1 second)."),
+        Arg::with_name("ca_bundle")
+            .long("ca_bundle")
+            .takes_value(true)
+            .help("Additional certificate authorities trusted by all of the node's outgoing HTTPS requests, for example those of a proxy. Either the PEM-encoded certificates or the path of a file containing them. The system's root certificates remain trusted."),
         Arg::with_name("exchange_rate.provider")
             .long("exchange_rate.provider")
             .takes_value(true)
             .help("Exchange rate API to poll for exchange rates. If this is not set, the node will not poll for rates and will instead use the rates set via the HTTP API. \
-                Note that CryptoCompare can also be used when the node is configured via a config file or stdin, because an API key must be provided to use that service."),
+                Note that CryptoCompare and SignedFeed can also be used when the node is configured via a config file or stdin, because an API key or a public key must be provided to use them."),
         Arg::with_name("exchange_rate.poll_interval")
             .long("exchange_rate.poll_interval")
             .default_value("60000")
             .help("Interval, defined in milliseconds, on which the node will poll the exchange_rate.provider (if specified) for exchange rates."),
+        Arg::with_name("exchange_rate.max_poll_backoff")
+            .long("exchange_rate.max_poll_backoff")
+            .default_value("600000")
+            .help("The longest delay, defined in milliseconds, between polls while the exchange_rate.provider keeps failing. \
+                The poll interval is doubled after every consecutive failed poll up to this delay, and returns to normal \
+                after a successful poll."),
+        Arg::with_name("exchange_rate.poll_jitter")
+            .long("exchange_rate.poll_jitter")
+            .default_value("0.1")
+            .help("Fraction of the poll interval by which every poll is randomly advanced or postponed, \
+                so that many nodes do not poll the exchange_rate.provider at the same time."),
         Arg::with_name("exchange_rate.spread")
             .long("exchange_rate.spread")
             .default_value("0")
@@ -101,10 +257,35 @@ async fn main() {
                 For example, take an incoming packet with an amount of 100. If the \
                 exchange rate is 1:0.5 and the spread is 0.01, the amount on the \
                     outgoing packet would be 198 (instead of 200 without the spread)."),
+        Arg::with_name("exchange_rate.rounding")
+            .long("exchange_rate.rounding")
+            .default_value("floor")
+            .possible_values(&["floor", "ceil", "round_half_up", "reject", "carry"])
+            .help("How outgoing amounts which are not integers after converting them with the exchange rate \
+                (or between asset scales) are rounded. Connectors may use ceil so they never forward \
+                less than the converted amount, reject to reject such packets, or carry to add the \
+                fraction which was rounded off to the next packets between the same accounts. \
+                Accounts configured with their own rounding use it instead."),
+        Arg::with_name("exchange_rate.flat_fee")
+            .long("exchange_rate.flat_fee")
+            .default_value("0")
+            .help("Fee deducted from the outgoing amount of every packet after the spread was applied, \
+                in the smallest unit of the outgoing account's asset. Packets whose outgoing amount \
+                is smaller than the fee are rejected with R01 (Insufficient Source Amount)."),
         Arg::with_name("prometheus.bind_address")
             .long("prometheus.bind_address")
             .takes_value(true)
             .help("IP address and port to host the Prometheus endpoint on."),
+        Arg::with_name("prometheus.push_gateway_url")
+            .long("prometheus.push_gateway_url")
+            .takes_value(true)
+            .help("URL of a Prometheus Pushgateway the metrics are pushed to, grouped by the \
+                node's ILP address. Can be used instead of or in addition to the bind_address."),
+        Arg::with_name("prometheus.push_interval")
+            .long("prometheus.push_interval")
+            .takes_value(true)
+            .help("Interval, in milliseconds, on which the metrics are pushed to the \
+                Pushgateway. Defaults to 15000ms (15 seconds)."),
         Arg::with_name("prometheus.histogram_window")
             .long("prometheus.histogram_window")
             .takes_value(true)
@@ -117,10 +298,29 @@ async fn main() {
                 old data. For example, a value of 1000ms (1 second) would mean that the \
                 node forgets the oldest 1 second of histogram data points every second. \
                 Defaults to 10000ms (10 seconds)."),
+        Arg::with_name("balance_events.redis_url")
+            .long("balance_events.redis_url")
+            .takes_value(true)
+            .help("URL of the Redis server a record of every balance change is published to. \
+                If this is not set, no balance events are published."),
+        Arg::with_name("balance_events.stream")
+            .long("balance_events.stream")
+            .takes_value(true)
+            .help("Key of the Redis Stream the balance events are added to. Defaults to balance_events."),
+        Arg::with_name("balance_events.buffer_size")
+            .long("balance_events.buffer_size")
+            .takes_value(true)
+            .help("The number of balance events which are buffered while waiting to be published. Defaults to 10000."),
+        Arg::with_name("balance_events.when_full")
+            .long("balance_events.when_full")
+            .takes_value(true)
+            .possible_values(&["drop", "block"])
+            .help("Whether new balance events are dropped or packet processing waits \
+                when the buffer is full. Defaults to drop."),
         ]);
 
     let mut config = get_env_config("ilp");
-    if let Ok((path, config_file)) = precheck_arguments(app.clone()) {
+    if let Ok((path, config_file, config_auth_token)) = precheck_arguments(app.clone()) {
         if !is_fd_tty(0) {
             if let Err(error) = merge_std_in(&mut config) {
                 output_config_error(error, None);
@@ -128,7 +328,14 @@ async fn main() {
             };
         }
         if let Some(ref config_path) = config_file {
-            if let Err(error) = merge_config_file(config_path, &mut config) {
+            let merged = if is_config_url(config_path) {
+                let auth_token =
+                    config_auth_token.or_else(|| config.get_str("config_auth_token").ok());
+                merge_config_url(config_path, auth_token.as_deref(), &mut config).await
+            } else {
+                merge_config_file(config_path, &mut config)
+            };
+            if let Err(error) = merged {
                 output_config_error(error, Some(config_path));
                 return;
             };
@@ -163,8 +370,9 @@ fn output_config_error(error: ConfigError, config_path: Option<&str>) {
     }
 }
 
-// returns (subcommand paths, config path)
-fn precheck_arguments(mut app: App) -> Result<(Vec<String>, Option<String>), ()> {
+// returns (subcommand paths, config path, config auth token)
+#[allow(clippy::type_complexity)]
+fn precheck_arguments(mut app: App) -> Result<(Vec<String>, Option<String>, Option<String>), ()> {
     // not to cause `required fields error`.
     reset_required(&mut app);
     let matches = app.get_matches_safe();
@@ -179,15 +387,68 @@ fn precheck_arguments(mut app: App) -> Result<(Vec<String>, Option<String>), ()>
     if let Some(config_path_arg) = subcommand.value_of("config") {
         config_path = Some(config_path_arg.to_string());
     };
-    Ok((path, config_path))
+    let config_auth_token = subcommand.value_of("config_auth_token").map(String::from);
+    Ok((path, config_path, config_auth_token))
 }
 
 fn merge_config_file(config_path: &str, config: &mut Config) -> Result<(), ConfigError> {
     let file_config = config::File::with_name(config_path);
     let file_config = file_config.collect()?;
+    merge_config_values(file_config, config)
+}
+
+fn is_config_url(config_path: &str) -> bool {
+    config_path.starts_with("http://") || config_path.starts_with("https://")
+}
+
+/// Fetches the config served at the URL once and merges it like the one of a config file.
+/// Its format is determined by the extension of the URL's path, or else by trying JSON,
+/// YAML and TOML in turn (like for STDIN).
+async fn merge_config_url(
+    config_url: &str,
+    auth_token: Option<&str>,
+    config: &mut Config,
+) -> Result<(), ConfigError> {
+    let url = Url::parse(config_url).map_err(|err| {
+        ConfigError::Message(format!("Invalid config URL {}: {}", config_url, err))
+    })?;
+    let fetch_error = |err: reqwest::Error| {
+        ConfigError::Message(format!("Could not fetch config from {}: {}", url, err))
+    };
+    let mut request = reqwest::Client::new().get(url.clone());
+    if let Some(token) = auth_token {
+        request = request.bearer_auth(token);
+    }
+    let response = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(fetch_error)?;
+    let content = response.text().await.map_err(fetch_error)?;
+
+    let extension = std::path::Path::new(url.path())
+        .extension()
+        .and_then(OsStr::to_str);
+    let url_config = match extension.unwrap_or_default() {
+        "json" => FileFormat::Json.parse(None, &content),
+        "yaml" | "yml" => FileFormat::Yaml.parse(None, &content),
+        "toml" => FileFormat::Toml.parse(None, &content),
+        _ => FileFormat::Json
+            .parse(None, &content)
+            .or_else(|_| FileFormat::Yaml.parse(None, &content))
+            .or_else(|_| FileFormat::Toml.parse(None, &content)),
+    }
+    .map_err(|err| ConfigError::Message(format!("Could not parse config from {}: {}", url, err)))?;
+    merge_config_values(url_config, config)
+}
+
+fn merge_config_values(
+    values: HashMap<String, Value>,
+    config: &mut Config,
+) -> Result<(), ConfigError> {
     // if the key is not defined in the given config already, set it to the config
     // because the original values override the ones from the config file
-    for (k, v) in file_config {
+    for (k, v) in values {
         if config.get_str(&k).is_err() {
             config.set(&k, v)?;
         }
@@ -306,3 +567,82 @@ fn is_fd_tty(file_descriptor: c_int) -> bool {
     }
     result == 1
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::mock;
+
+    const CONFIG: &str = "ilp_address: example.node\nadmin_auth_token: from-the-config\nexchange_rate:\n  spread: 0.01\n";
+
+    fn config_with_admin_auth_token() -> Config {
+        let mut config = Config::new();
+        config.set("admin_auth_token", "from-the-env").unwrap();
+        config
+    }
+
+    fn assert_merged(config: &Config) {
+        assert_eq!(config.get_str("ilp_address").unwrap(), "example.node");
+        assert_eq!(config.get_float("exchange_rate.spread").unwrap(), 0.01);
+        // the values which were already set are not overridden
+        assert_eq!(config.get_str("admin_auth_token").unwrap(), "from-the-env");
+    }
+
+    #[tokio::test]
+    async fn merges_config_from_url_like_a_file() {
+        let path =
+            std::env::temp_dir().join(format!("ilp-node-config-{}.yml", rand::random::<u64>()));
+        std::fs::write(&path, CONFIG).unwrap();
+        let mut file_config = config_with_admin_auth_token();
+        merge_config_file(path.to_str().unwrap(), &mut file_config).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_merged(&file_config);
+
+        let served = mock("GET", "/config.yml")
+            .match_header("authorization", "Bearer config-secret")
+            .with_body(CONFIG)
+            .create();
+        let mut url_config = config_with_admin_auth_token();
+        merge_config_url(
+            &format!("{}/config.yml", mockito::server_url()),
+            Some("config-secret"),
+            &mut url_config,
+        )
+        .await
+        .unwrap();
+        served.assert();
+        assert_merged(&url_config);
+    }
+
+    #[tokio::test]
+    async fn detects_the_format_of_config_urls_without_extension() {
+        let _served = mock("GET", "/config")
+            .with_body(r#"{"ilp_address": "example.node", "exchange_rate": {"spread": 0.01}}"#)
+            .create();
+        let mut config = config_with_admin_auth_token();
+        merge_config_url(
+            &format!("{}/config", mockito::server_url()),
+            None,
+            &mut config,
+        )
+        .await
+        .unwrap();
+        assert_merged(&config);
+    }
+
+    #[tokio::test]
+    async fn errors_if_config_url_cannot_be_fetched() {
+        let _unauthorized = mock("GET", "/private-config.json")
+            .with_status(401)
+            .create();
+        let mut config = Config::new();
+        let err = merge_config_url(
+            &format!("{}/private-config.json", mockito::server_url()),
+            Some("wrong-token"),
+            &mut config,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().starts_with("Could not fetch config from"));
+    }
+}
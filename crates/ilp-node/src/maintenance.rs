@@ -0,0 +1,85 @@
+use interledger::{packet::ErrorCode, service_util::MaintenanceMode};
+use serde::{de::Error as DeserializeError, Deserialize, Deserializer};
+
+/// Configuration of the maintenance mode, in which the node rejects all new packets
+/// (except for those of the peer protocols, e.g. CCP and settlement messages).
+/// It can be toggled at runtime via `PUT /maintenance`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct MaintenanceConfig {
+    /// Whether the node starts in maintenance mode. Defaults to false.
+    #[serde(default)]
+    pub enabled: bool,
+    /// The code of the rejects sent while in maintenance mode. Defaults to `T00`.
+    #[serde(
+        default = "MaintenanceConfig::default_error_code",
+        deserialize_with = "deserialize_error_code"
+    )]
+    pub error_code: ErrorCode,
+    /// The message of the rejects sent while in maintenance mode
+    #[serde(default = "MaintenanceConfig::default_message")]
+    pub message: String,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        MaintenanceConfig {
+            enabled: false,
+            error_code: Self::default_error_code(),
+            message: Self::default_message(),
+        }
+    }
+}
+
+impl MaintenanceConfig {
+    fn default_error_code() -> ErrorCode {
+        ErrorCode::T00_INTERNAL_ERROR
+    }
+
+    fn default_message() -> String {
+        "The node is down for maintenance".to_string()
+    }
+
+    /// Returns the switch shared by the node's services and its API
+    pub fn maintenance_mode(&self) -> MaintenanceMode {
+        MaintenanceMode::new(self.enabled, self.error_code, self.message.clone())
+    }
+}
+
+/// Deserializes an ILP error code such as `T00`
+fn deserialize_error_code<'de, D>(deserializer: D) -> Result<ErrorCode, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let code = String::deserialize(deserializer)?;
+    let bytes = code.as_bytes();
+    let is_valid = bytes.len() == 3
+        && (bytes[0] == b'F' || bytes[0] == b'T' || bytes[0] == b'R')
+        && bytes[1..].iter().all(u8::is_ascii_digit);
+    if is_valid {
+        Ok(ErrorCode::new([bytes[0], bytes[1], bytes[2]]))
+    } else {
+        Err(DeserializeError::custom(format!(
+            "Invalid ILP error code {} (must be e.g. T00 or F99)",
+            code
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserializes_error_codes() {
+        let config: MaintenanceConfig =
+            serde_json::from_str(r#"{"enabled": true, "error_code": "T03"}"#).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.error_code, ErrorCode::T03_CONNECTOR_BUSY);
+        assert_eq!(config.message, "The node is down for maintenance");
+
+        for code in &["T0", "T000", "X00", "T0A"] {
+            let config = format!(r#"{{"error_code": "{}"}}"#, code);
+            assert!(serde_json::from_str::<MaintenanceConfig>(&config).is_err());
+        }
+    }
+}
@@ -1,19 +1,24 @@
 use cfg_if::cfg_if;
 
+use crate::balance_events::BalanceEventsConfig;
+use crate::bind_address::{BindAddress, Listener, TcpOptions, DEFAULT_LISTEN_BACKLOG};
+use crate::cors::{with_cors, CorsConfig};
+use crate::http_tls::{load_ca_bundle, HttpTlsConfig};
+use crate::maintenance::MaintenanceConfig;
+use crate::volume_alerts::VolumeAlertsConfig;
+use crate::webhooks::{WebhookConfig, WebhookNotifier};
+
 #[cfg(feature = "google-pubsub")]
 use crate::instrumentation::google_pubsub::{create_google_pubsub_wrapper, PubsubConfig};
 
 cfg_if! {
     if #[cfg(feature = "monitoring")] {
-        use tracing_subscriber::{
-            filter::EnvFilter,
-            fmt::{time::ChronoUtc, Subscriber},
-        };
         use interledger::errors::ApiError;
         use secrecy::{ExposeSecret, SecretString};
         use tracing_futures::Instrument;
-        use tracing::debug_span;
+        use tracing::{debug_span, error_span};
         use crate::instrumentation::{
+            log_filter::LogFilter,
             metrics::{incoming_metrics, outgoing_metrics},
             prometheus::{serve_prometheus, PrometheusConfig},
             trace::{trace_forwarding, trace_incoming, trace_outgoing},
@@ -23,47 +28,61 @@ cfg_if! {
     }
 }
 
-#[cfg(any(feature = "monitoring", feature = "google-pubsub"))]
-use interledger::service::OutgoingService;
-
 use bytes::Bytes;
 use futures::TryFutureExt;
 use hex::FromHex;
 use interledger::{
-    api::{NodeApi, NodeStore},
-    btp::{btp_service_as_filter, connect_client, BtpOutgoingService, BtpStore},
-    ccp::{CcpRouteManagerBuilder, CcpRoutingAccount, CcpRoutingStore, RoutingRelation},
+    api::{AccountDetails, NodeApi, NodeStore, DEFAULT_MAX_ASSET_SCALE},
+    btp::{btp_service_as_filter, connect_client_with_reconnect, BtpOutgoingService, BtpStore},
+    ccp::{
+        CcpRouteManagerBuilder, CcpRoutingAccount, CcpRoutingStore, RouteLimitPolicy,
+        RoutingRelation,
+    },
     errors::*,
     http::{HttpClientService, HttpServer as IlpOverHttpServer, HttpStore},
-    ildcp::IldcpService,
+    ildcp::{IldcpRequest, IldcpResponse, IldcpService},
     packet::Address,
-    packet::{ErrorCode, RejectBuilder},
+    packet::{set_debug_data, ErrorCode, RejectBuilder},
     rates::{ExchangeRateFetcher, ExchangeRateStore},
     router::{Router, RouterStore},
     service::{
         outgoing_service_fn, Account as AccountTrait, AccountStore, AddressStore, OutgoingRequest,
-        Username,
+        OutgoingService, Username,
     },
     service_util::{
-        BalanceStore, EchoService, ExchangeRateService, ExpiryShortenerService,
-        MaxPacketAmountService, RateLimitService, RateLimitStore, ValidatorService,
+        AddressValidation, BalancePause, BalanceStore, ConcurrencyLimitService,
+        DestinationAllowlistService, EchoService, ExchangeRateService, ExpiryShortenerService,
+        FulfilledConditionStore, IncomingSettlementConverter, MaintenanceModeService,
+        MaxPacketAmountService, OutgoingConcurrencyLimitService, OwnAddressPolicy,
+        OwnAddressService, PacketEventsService, PeerActivity, PeerActivityService,
+        RateLimitService, RateLimitStore, ReplayProtectionService, RoundingPolicy, ShadowService,
+        TrafficCountersService, TrafficStore, ValidatorService, VolumeAlertService, VolumeStore,
     },
     settlement::{
         api::{create_settlements_filter, SettlementMessageService},
         core::{
             idempotency::IdempotentStore,
-            types::{LeftoversStore, SettlementStore},
+            types::{LeftoversStore, SettlementAccount, SettlementStore},
+            HealthCheckPolicy, RetryPolicy, SettlementClient,
         },
     },
-    store::account::Account,
+    store::{account::Account, memory::MemoryStore},
     stream::{StreamNotificationsStore, StreamReceiverService},
 };
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
 use serde::{de::Error as DeserializeError, Deserialize, Deserializer};
-use std::{convert::TryFrom, net::SocketAddr, str, str::FromStr, time::Duration};
-use tokio::spawn;
-use tracing::{debug, error, info};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    net::SocketAddr,
+    str,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{net::TcpListener, spawn, sync::broadcast};
+use tracing::{debug, error, info, warn};
 use url::Url;
 use uuid::Uuid;
 use warp::{self, Filter};
@@ -74,15 +93,29 @@ use crate::redis_store::*;
 use interledger::service_util::BalanceService;
 
 #[doc(hidden)]
-pub use interledger::rates::ExchangeRateProvider;
+pub use interledger::rates::{ExchangeRateProvider, RateProvider};
 
-static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("local.host").unwrap());
+/// Number of packet events buffered for each subscriber before the oldest ones are dropped
+const PACKET_EVENTS_CAPACITY: usize = 1024;
+/// Longest delay, defined in milliseconds, between the attempts to reconnect to a BTP peer
+const DEFAULT_BTP_MAX_RECONNECT_DELAY: u64 = 60_000;
+
+pub(crate) static DEFAULT_ILP_ADDRESS: Lazy<Address> =
+    Lazy::new(|| Address::from_str("local.host").unwrap());
+
+/// Query parameters of the requests which add directives to the tracing filter
+#[cfg(feature = "monitoring")]
+#[derive(Deserialize)]
+struct TracingLevelQuery {
+    /// Milliseconds after which the directives are removed again
+    duration: Option<u64>,
+}
 
 fn default_settlement_api_bind_address() -> SocketAddr {
     SocketAddr::from(([127, 0, 0, 1], 7771))
 }
-fn default_http_bind_address() -> SocketAddr {
-    SocketAddr::from(([127, 0, 0, 1], 7770))
+fn default_http_bind_address() -> BindAddress {
+    BindAddress::Tcp(SocketAddr::from(([127, 0, 0, 1], 7770)))
 }
 // We allow unreachable code on the below function because there must always be exactly one default
 // regardless of how many data sources the crate is compiled to support,
@@ -92,7 +125,7 @@ fn default_http_bind_address() -> SocketAddr {
 fn default_database_url() -> String {
     #[cfg(feature = "redis")]
     return default_redis_url();
-    panic!("no backing store configured")
+    String::from("memory://")
 }
 
 fn deserialize_optional_address<'de, D>(deserializer: D) -> Result<Option<Address>, D::Error>
@@ -120,6 +153,46 @@ where
     })
 }
 
+/// The listeners of a node, which are bound before any of the nodes start serving
+pub(crate) struct NodeListeners {
+    http: Listener,
+    admin: Option<Listener>,
+    settlement_api: TcpListener,
+}
+
+/// Adds the addresses to the ones already listened on, unless they overlap
+fn check_bind_addresses(
+    bind_addresses: &mut Vec<BindAddress>,
+    addresses: Vec<BindAddress>,
+) -> Result<(), ()> {
+    for address in addresses {
+        if let Some(other) = bind_addresses.iter().find(|other| other.overlaps(&address)) {
+            error!(target: "interledger-node",
+                "The address {} overlaps with {}, which the node or one of its tenants already listens on",
+                address, other
+            );
+            return Err(());
+        }
+        bind_addresses.push(address);
+    }
+    Ok(())
+}
+
+fn deserialize_spread<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let spread = f64::deserialize(deserializer)?;
+    if (0.0..1.0).contains(&spread) {
+        Ok(spread)
+    } else {
+        Err(DeserializeError::custom(format!(
+            "Invalid spread (must be at least 0 and less than 1): {}",
+            spread
+        )))
+    }
+}
+
 fn deserialize_optional_username<'de, D>(deserializer: D) -> Result<Option<Username>, D::Error>
 where
     D: Deserializer<'de>,
@@ -134,7 +207,7 @@ where
 }
 
 /// Configuration for calculating exchange rates between various pairs.
-#[derive(Deserialize, Clone, Default)]
+#[derive(Deserialize, Clone)]
 pub struct ExchangeRateConfig {
     /// Interval, defined in milliseconds, on which the node will poll the exchange rate provider.
     /// Defaults to 60000ms (60 seconds).
@@ -144,6 +217,16 @@ pub struct ExchangeRateConfig {
     /// that the connector will tolerate before invalidating the exchange rate cache.
     #[serde(default = "ExchangeRateConfig::default_poll_failure_tolerance")]
     pub poll_failure_tolerance: u32,
+    /// The longest delay, defined in milliseconds, between polls while the exchange rate
+    /// provider keeps failing (e.g. because it rate-limits the node). The poll interval is
+    /// doubled after every consecutive failed poll up to this delay, and returns to normal
+    /// after a successful poll. Defaults to 600000ms (10 minutes).
+    #[serde(default = "ExchangeRateConfig::default_max_poll_backoff")]
+    pub max_poll_backoff: u64,
+    /// Fraction of the poll interval by which every poll is randomly advanced or postponed,
+    /// so that a fleet of nodes does not poll the provider at the same time. Defaults to 0.1.
+    #[serde(default = "ExchangeRateConfig::default_poll_jitter")]
+    pub poll_jitter: f64,
     /// API to poll for exchange rates. Currently the supported options are:
     /// - [CoinCap](https://docs.coincap.io)
     /// - [CryptoCompare](https://cryptocompare.com) (note this requires an API key)
@@ -151,14 +234,63 @@ pub struct ExchangeRateConfig {
     /// instead use the rates configured via the HTTP API.
     #[serde(default)]
     pub provider: Option<ExchangeRateProvider>,
+    /// APIs which are polled, in order, while the `provider` fails. The rates are taken
+    /// from the first one which returns them, so the node switches back to the `provider`
+    /// as soon as it recovers. Which one the current rates came from is logged and
+    /// returned by `GET /rates/source`.
+    #[serde(default)]
+    pub fallback_providers: Vec<ExchangeRateProvider>,
+    /// Codes under which the providers publish the rates of assets whose account asset codes
+    /// differ from them, as a map of asset codes to the providers' codes (e.g. `XBT = "BTC"`).
+    /// Asset codes without an alias are looked up as they are. This does not apply to the
+    /// rates set via the HTTP API.
+    #[serde(default)]
+    pub asset_code_aliases: HashMap<String, String>,
+    /// Custom source of exchange rates, which can only be set when the node is
+    /// constructed programmatically (see [`InterledgerNode::rate_provider`](./struct.InterledgerNode.html#method.rate_provider)).
+    /// If set, it takes precedence over the `provider`.
+    #[serde(skip)]
+    pub custom_provider: Option<Arc<dyn RateProvider + Send + Sync>>,
     /// Spread, as a fraction, to add on top of the exchange rate.
     /// This amount is kept as the node operator's profit, or may cover
     /// fluctuations in exchange rates.
     /// For example, take an incoming packet with an amount of 100. If the
     /// exchange rate is 1:2 and the spread is 0.01, the amount on the
     /// outgoing packet would be 198 (instead of 200 without the spread).
-    #[serde(default)]
+    /// Accounts configured with their own `spread` use it instead of this one
+    /// for the packets they send. Must be at least 0 and less than 1.
+    #[serde(default, deserialize_with = "deserialize_spread")]
     pub spread: f64,
+    /// How outgoing amounts which are not integers after the conversion are rounded:
+    /// `floor` (the default), `ceil`, `round_half_up`, `reject` or `carry`.
+    /// Connectors may use `ceil` so they never forward less than the converted amount.
+    /// Accounts configured with their own `rounding` use it instead of this one
+    /// for the packets they send.
+    #[serde(default)]
+    pub rounding: RoundingPolicy,
+    /// Fee deducted from the outgoing amount of every packet after the spread was applied,
+    /// in the smallest unit of the outgoing account's asset. Packets whose outgoing amount
+    /// is smaller than the fee are rejected. Defaults to 0.
+    #[serde(default)]
+    pub flat_fee: u64,
+}
+
+impl Default for ExchangeRateConfig {
+    fn default() -> Self {
+        ExchangeRateConfig {
+            poll_interval: ExchangeRateConfig::default_poll_interval(),
+            poll_failure_tolerance: ExchangeRateConfig::default_poll_failure_tolerance(),
+            max_poll_backoff: ExchangeRateConfig::default_max_poll_backoff(),
+            poll_jitter: ExchangeRateConfig::default_poll_jitter(),
+            provider: None,
+            fallback_providers: Vec::new(),
+            asset_code_aliases: HashMap::new(),
+            custom_provider: None,
+            spread: 0.0,
+            rounding: RoundingPolicy::default(),
+            flat_fee: 0,
+        }
+    }
 }
 
 impl ExchangeRateConfig {
@@ -168,6 +300,53 @@ impl ExchangeRateConfig {
     fn default_poll_failure_tolerance() -> u32 {
         5
     }
+    fn default_max_poll_backoff() -> u64 {
+        600_000
+    }
+    fn default_poll_jitter() -> f64 {
+        0.1
+    }
+}
+
+/// A connector which is served by the same process as the node, but is otherwise separate
+/// from it. Packets and API requests reach a tenant through its own listening addresses,
+/// and it keeps its accounts in its own store, so it shares no accounts with the node or
+/// the other tenants. All of the settings not listed here are the same as the node's.
+#[derive(Deserialize, Clone)]
+pub struct TenantConfig {
+    /// ILP address of the tenant. If this is not set and the tenant has a parent account,
+    /// the tenant gets its address from the parent via IL-DCP on startup.
+    #[serde(deserialize_with = "deserialize_optional_address")]
+    #[serde(default)]
+    pub ilp_address: Option<Address>,
+    /// HTTP Authorization token for the tenant's admin (sent as a Bearer token)
+    pub admin_auth_token: String,
+    /// HTTP Authorization token which only authorizes the `GET` requests of the tenant's admin
+    #[serde(default)]
+    pub read_only_admin_auth_token: Option<String>,
+    /// Data store URI of the tenant. It must not be shared with the node or another tenant
+    /// (e.g. use a different Redis database), unless it is "memory://"
+    pub database_url: String,
+    /// IP address and port (or unix domain socket) to listen for the tenant's HTTP connections
+    pub http_bind_address: BindAddress,
+    /// IP address and port (or unix domain socket) to serve the tenant's API on instead of
+    /// its `http_bind_address`
+    #[serde(default)]
+    pub admin_bind_address: Option<BindAddress>,
+    /// IP address and port to listen for the tenant's Settlement Engine API
+    pub settlement_api_bind_address: SocketAddr,
+    /// Account which the SPSP payments sent to the tenant's root domain are sent to
+    #[serde(default, deserialize_with = "deserialize_optional_username")]
+    pub default_spsp_account: Option<Username>,
+    /// Account which the tenant forwards packets to if there is no more specific route
+    #[serde(default, deserialize_with = "deserialize_optional_username")]
+    pub default_route: Option<Username>,
+    /// Accounts which are created or updated in the tenant's store on startup
+    #[serde(default)]
+    pub accounts: Vec<AccountDetails>,
+    /// Delete the accounts of the tenant's store which are not in its `accounts` on startup
+    #[serde(default)]
+    pub delete_unconfigured_accounts: bool,
 }
 
 /// An all-in-one Interledger node that includes sender and receiver functionality,
@@ -176,7 +355,9 @@ impl ExchangeRateConfig {
 /// Cargo.toml to see a list of all supported stores.
 #[derive(Deserialize, Clone)]
 pub struct InterledgerNode {
-    /// ILP address of the node
+    /// ILP address of the node. If this is not set and the node has a parent account,
+    /// the node gets its address from the parent via IL-DCP on startup. Startup is
+    /// aborted if the parent cannot be reached.
     #[serde(deserialize_with = "deserialize_optional_address")]
     #[serde(default)]
     pub ilp_address: Option<Address>,
@@ -185,17 +366,55 @@ pub struct InterledgerNode {
     pub secret_seed: [u8; 32],
     /// HTTP Authorization token for the node admin (sent as a Bearer token)
     pub admin_auth_token: String,
-    /// Data store URI (for example, "redis://127.0.0.1:6379" or "redis+unix:/tmp/redis.sock")
+    /// HTTP Authorization token for tools which only read from the API, such as dashboards.
+    /// It is accepted instead of the `admin_auth_token` for `GET` requests (except for the
+    /// export of the node, which contains secrets), but not for requests which make changes.
+    #[serde(default)]
+    pub read_only_admin_auth_token: Option<String>,
+    /// Data store URI (for example, "redis://127.0.0.1:6379", "redis+unix:/tmp/redis.sock",
+    /// or "memory://" for a store which is not persisted)
     #[serde(
         default = "default_database_url",
         // temporary alias for backwards compatibility
         alias = "redis_url"
     )]
     pub database_url: String,
-    /// IP address and port to listen for HTTP connections
-    /// This is used for both the API and ILP over HTTP packets
+    /// IP address and port to listen for HTTP connections, or the path of a unix domain
+    /// socket in the form `unix:/path/to/socket`.
+    /// This is used for both the API and ILP over HTTP packets, unless
+    /// the API is bound to the `admin_bind_address`
     #[serde(default = "default_http_bind_address")]
-    pub http_bind_address: SocketAddr,
+    pub http_bind_address: BindAddress,
+    /// IP address and port (or unix domain socket) to serve the API on instead of the
+    /// `http_bind_address`, which then only accepts ILP over HTTP packets, BTP connections and
+    /// the public SPSP endpoints. This allows exposing the packet endpoints to peers without exposing the API, or only
+    /// to the local users allowed to access the socket.
+    #[serde(default)]
+    pub admin_bind_address: Option<BindAddress>,
+    /// Serve the `http_bind_address` over HTTPS, optionally letting peers
+    /// authenticate with TLS client certificates
+    #[serde(default)]
+    pub http_tls: Option<HttpTlsConfig>,
+    /// Cross-Origin Resource Sharing policy of the API and the ILP over HTTP endpoints, which
+    /// lets browser-based tools call them from other origins. If this is not set, no CORS
+    /// headers are sent, so browsers only let pages of the node's own origin use the responses.
+    #[serde(default)]
+    pub cors: Option<CorsConfig>,
+    /// Maximum number of connections queued on the `http_bind_address` and the
+    /// `admin_bind_address` until the node accepts them. Raising it (and the operating
+    /// system's limit, e.g. `net.core.somaxconn`) avoids dropping connections under
+    /// bursts of new ILP over HTTP and BTP connections. Defaults to 1024.
+    pub http_listen_backlog: Option<u32>,
+    /// Time, defined in milliseconds, the connections to the `http_bind_address` and the
+    /// `admin_bind_address` are idle before TCP keepalive probes are sent, so that
+    /// connections to peers which disappeared are closed. Keepalive has a granularity of
+    /// seconds. Defaults to no keepalive.
+    pub http_tcp_keepalive: Option<u64>,
+    /// Time, defined in milliseconds, the connections to the `http_bind_address` and the
+    /// `admin_bind_address` may be idle before the node closes them, e.g. the keep-alive
+    /// connections peers hold open between bursts of ILP over HTTP packets. Defaults to
+    /// keeping idle connections open.
+    pub http_idle_timeout: Option<u64>,
     /// IP address and port to listen for the Settlement Engine API
     #[serde(default = "default_settlement_api_bind_address")]
     pub settlement_api_bind_address: SocketAddr,
@@ -204,9 +423,121 @@ pub struct InterledgerNode {
     /// will be sent to.
     #[serde(default, deserialize_with = "deserialize_optional_username")]
     pub default_spsp_account: Option<Username>,
+    /// Account (usually the node's parent) which packets are forwarded to if there is
+    /// no more specific route for their destination. Startup is aborted if it does not exist.
+    #[serde(default, deserialize_with = "deserialize_optional_username")]
+    pub default_route: Option<Username>,
     /// Interval, defined in milliseconds, on which the node will broadcast routing
     /// information to other nodes using CCP. Defaults to 30000ms (30 seconds).
     pub route_broadcast_interval: Option<u64>,
+    /// Time, defined in milliseconds, the node waits after startup before it broadcasts
+    /// routes for the first time, so that the routing table is complete by then.
+    /// Defaults to 0 (broadcasting right away).
+    pub route_broadcast_delay: Option<u64>,
+    /// Maximum percentage by which each route broadcast interval is randomly
+    /// lengthened or shortened, so that nodes do not all broadcast at the same time.
+    /// Defaults to 0 (no jitter).
+    pub route_broadcast_jitter: Option<u8>,
+    /// Maximum number of peers each route broadcast is sent to at the same time,
+    /// so that slow peers do not delay the updates to the others. Defaults to 50.
+    pub route_broadcast_concurrency: Option<usize>,
+    /// Maximum number of routes the node learns from each peer via CCP, so that a peer
+    /// advertising too many prefixes can not exhaust its memory. Defaults to no limit.
+    pub max_routes_per_peer: Option<usize>,
+    /// What happens to the new prefixes a peer advertises once `max_routes_per_peer`
+    /// is reached: `refuse` (the default) ignores and logs them, `evict_oldest`
+    /// replaces the routes which were learned from that peer first.
+    #[serde(default)]
+    pub route_limit_policy: RouteLimitPolicy,
+    /// How long, in milliseconds, a route learned from a peer may go without the peer
+    /// refreshing it (with an update or heartbeat) before it is counted in the
+    /// `routes.stale` metric. Defaults to 60000ms (two default broadcast intervals).
+    pub stale_route_threshold: Option<u64>,
+    /// What happens to the packets addressed to the node's own ILP address: `protocols`
+    /// (the default) hands them to the echo protocol and rejects the others, `reject`
+    /// rejects all of them and `{ account: <username> }` forwards them to that local account.
+    #[serde(default)]
+    pub own_address_policy: OwnAddressPolicy,
+    /// How the destinations of incoming packets are checked against the ILP address
+    /// requirements: `strict` (the default) rejects the packets with malformed destinations
+    /// with `F01_INVALID_PACKET`, `lenient` forwards them.
+    #[serde(default)]
+    pub address_validation: AddressValidation,
+    /// Send ILP-over-HTTP packets to peers with cleartext `http://` URLs over HTTP/2
+    /// without negotiating it first, so that concurrent packets share one connection.
+    /// All of those peers must support HTTP/2. Defaults to false (HTTP/1.1).
+    #[serde(default)]
+    pub http2_prior_knowledge: bool,
+    /// Use rustls instead of the platform's native TLS implementation (e.g. OpenSSL) for
+    /// ILP-over-HTTP peers with `https://` URLs, which negotiates HTTP/2 with the peers
+    /// supporting it. Defaults to false (native TLS, HTTP/1.1).
+    #[serde(default)]
+    pub http_client_rustls: bool,
+    /// Longest delay, defined in milliseconds, between the attempts to reconnect to a peer
+    /// or parent whose BTP connection could not be established or went away. The delay
+    /// starts at one second and doubles after every failed attempt. Defaults to 60000ms (1 minute).
+    pub btp_max_reconnect_delay: Option<u64>,
+    /// Longest time, defined in milliseconds, for which `POST /balances/snapshot` pauses
+    /// the balance changes of packets. The snapshot fails if the balance changes which are
+    /// being applied do not finish in that time. Defaults to 500ms.
+    pub balance_snapshot_max_pause: Option<u64>,
+    /// Time, defined in milliseconds, before the packets of the STREAM payments sent by
+    /// the node's accounts expire. Payments over paths with a high latency may need a
+    /// longer expiry. Defaults to 30000ms (30 seconds).
+    pub stream_packet_expiry: Option<u64>,
+    /// Smallest asset scale of the accounts created or replaced via the API. Defaults to 0.
+    pub min_asset_scale: Option<u8>,
+    /// Largest asset scale of the accounts created or replaced via the API, which prevents
+    /// accounts whose amounts overflow when they are converted. Defaults to 18.
+    pub max_asset_scale: Option<u8>,
+    /// Maximum number of incoming packets the node forwards at the same time.
+    /// Packets over the limit are rejected with `T03_CONNECTOR_BUSY`. Defaults to no limit.
+    pub max_in_flight_packets: Option<usize>,
+    /// Maximum number of incoming packets from a single account the node forwards at the
+    /// same time. Packets over the limit are rejected with `T03_CONNECTOR_BUSY`. Defaults to no limit.
+    pub max_in_flight_packets_per_account: Option<usize>,
+    /// Maximum number of outgoing packets to a single account which wait for the account's
+    /// `max_concurrent_outgoing_requests` to allow them to be sent. Packets over the limit
+    /// are rejected with `T03_CONNECTOR_BUSY`. Defaults to 100.
+    pub max_queued_outgoing_requests_per_account: Option<usize>,
+    /// Maximum number of incoming STREAM connections each account receives at the same time.
+    /// Packets of further connections are rejected with a STREAM `ConnectionClose` frame.
+    /// Defaults to no limit.
+    pub max_stream_connections_per_account: Option<usize>,
+    /// Maximum number of streams each incoming STREAM connection has open at the same time.
+    /// Money sent on further streams is rejected with a STREAM `StreamClose` frame.
+    /// Defaults to no limit.
+    pub max_streams_per_connection: Option<usize>,
+    /// Maximum length, in bytes, of the data of the Fulfills and Rejects returned by peers.
+    /// Responses with more data are replaced with an `F09_INVALID_PEER_RESPONSE` reject.
+    /// Defaults to 32767, the maximum allowed by the protocol.
+    pub max_response_data_len: Option<usize>,
+    /// Time, defined in milliseconds, the node reserves for itself to pass fulfillments back
+    /// to the previous hop. The expiry of every forwarded Prepare is reduced by this amount on
+    /// top of the accounts' round trip times, and Prepares with less time left are rejected
+    /// with `R00_TRANSFER_TIMED_OUT`. Defaults to 0.
+    pub min_hold_time: Option<u32>,
+    /// Time, defined in milliseconds, for which the execution conditions of the fulfilled
+    /// packets are remembered. Incoming Prepares with a condition which was fulfilled within
+    /// this window are rejected with `F00_BAD_REQUEST`. It should be at least the longest
+    /// packet expiry (30000ms). Defaults to no replay protection.
+    pub replay_protection_window: Option<u64>,
+    /// Time, defined in milliseconds, for which the accounts which authenticated with an
+    /// ILP over HTTP bearer token are cached, so that not every packet is checked against
+    /// the database. Only used with Redis. Defaults to no caching.
+    pub http_auth_cache_ttl: Option<u64>,
+    /// Time, defined in milliseconds, for which the database commands wait while the node
+    /// reconnects after the connection dropped, e.g. while Redis fails over to a replica.
+    /// Commands which were already sent when the connection dropped are only sent again if
+    /// they only read data. Only used with Redis. Defaults to 1000ms (1 second).
+    pub database_retry_timeout: Option<u64>,
+    /// Additional certificate authorities trusted by all of the node's outgoing HTTPS requests
+    /// (to peers, settlement engines, exchange rate providers, SPSP receivers, webhooks and
+    /// the Prometheus Pushgateway),
+    /// for example those of a proxy. Either the PEM-encoded certificates or the path of a file
+    /// containing them. The system's root certificates remain trusted.
+    #[serde(default)]
+    pub ca_bundle: Option<String>,
     #[serde(default)]
     /// Configuration for calculating exchange rates between various pairs.
     pub exchange_rate: ExchangeRateConfig,
@@ -218,19 +549,162 @@ pub struct InterledgerNode {
     pub prometheus: Option<PrometheusConfig>,
     #[cfg(feature = "google-pubsub")]
     pub google_pubsub: Option<PubsubConfig>,
+    /// Policy for retrying requests to the settlement engines which failed
+    /// because the engine was unavailable or timed out.
+    /// Defaults to 10 retries with a base backoff of 1000ms which doubles up to 5000ms,
+    /// and 500ms of jitter, so a request is given up on after less than 50 seconds.
+    #[serde(default)]
+    pub settlement_retry_policy: RetryPolicy,
+    /// Periodically probes the settlement engines of the accounts. Accounts whose engine
+    /// failed the configured number of consecutive probes are not settled with (their balance
+    /// accumulates instead) until the engine responds again.
+    /// If this configuration is not provided, the engines are not probed.
+    #[serde(default)]
+    pub settlement_health_check: Option<HealthCheckPolicy>,
+    /// Webhook which is POSTed a signed notification with the total amount one of the
+    /// node's accounts received on a STREAM connection, once the connection is idle.
+    /// If this configuration is not provided, no webhooks are sent.
+    #[serde(default)]
+    pub webhook: Option<WebhookConfig>,
+    /// How the amounts sent to the accounts with a `volume_alert_threshold` are counted
+    /// and what happens when a threshold is crossed.
+    /// Defaults to windows of an hour, with the alerts only being logged.
+    #[serde(default)]
+    pub volume_alerts: VolumeAlertsConfig,
+    /// Whether the node starts in maintenance mode, rejecting all new packets except for
+    /// those of the peer protocols, and which reject it sends. The mode can be toggled via
+    /// the API. Defaults to not starting in maintenance mode.
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    /// Whether the `data` of ILP packets is included (hex-encoded) when packets are logged.
+    /// Defaults to only logging its length, so that the payloads the node forwards never
+    /// end up in its logs. This applies to the whole process, including its tenants.
+    #[serde(default)]
+    pub log_packet_data: bool,
+    /// Publishes a record of every change to an account's balance to a Redis Stream,
+    /// e.g. as an audit trail for accounting.
+    /// If this configuration is not provided, no balance events are published.
+    #[serde(default)]
+    pub balance_events: Option<BalanceEventsConfig>,
+    /// Additional connectors served by the same process, each with its own ILP address,
+    /// admin token, listening addresses and store.
+    /// Startup is aborted if any of them share a listening address or a store.
+    #[serde(default)]
+    pub tenants: Vec<TenantConfig>,
+    /// Accounts which are created on startup if no account with their username exists,
+    /// or otherwise updated to match their details
+    #[serde(default)]
+    pub accounts: Vec<AccountDetails>,
+    /// Delete the accounts which are not in the configured `accounts` on startup, so that
+    /// the store has exactly the configured accounts. Defaults to leaving them alone.
+    #[serde(default)]
+    pub delete_unconfigured_accounts: bool,
 }
 
 impl InterledgerNode {
-    /// Returns a future that runs the Interledger.rs Node.
+    /// Returns a node with the given root secret and admin token, and the defaults of all
+    /// other settings (the same as a configuration which only sets those two), for embedding
+    /// the node in another program without a configuration file or command line arguments.
+    /// The other settings can be changed before the node is served, for example:
+    ///
+    /// ```no_run
+    /// # use ilp_node::InterledgerNode;
+    /// # async fn run() {
+    /// let node = InterledgerNode {
+    ///     database_url: "memory://".to_string(),
+    ///     route_broadcast_interval: Some(10_000),
+    ///     ..InterledgerNode::new([1; 32], "admin".to_string())
+    /// };
+    /// node.serve().await.unwrap();
+    /// # }
+    /// ```
+    pub fn new(secret_seed: [u8; 32], admin_auth_token: String) -> Self {
+        InterledgerNode {
+            ilp_address: None,
+            secret_seed,
+            admin_auth_token,
+            read_only_admin_auth_token: None,
+            database_url: default_database_url(),
+            http_bind_address: default_http_bind_address(),
+            admin_bind_address: None,
+            http_tls: None,
+            cors: None,
+            http_listen_backlog: None,
+            http_tcp_keepalive: None,
+            http_idle_timeout: None,
+            settlement_api_bind_address: default_settlement_api_bind_address(),
+            default_spsp_account: None,
+            default_route: None,
+            route_broadcast_interval: None,
+            route_broadcast_delay: None,
+            route_broadcast_jitter: None,
+            route_broadcast_concurrency: None,
+            max_routes_per_peer: None,
+            route_limit_policy: RouteLimitPolicy::default(),
+            stale_route_threshold: None,
+            own_address_policy: OwnAddressPolicy::default(),
+            address_validation: AddressValidation::default(),
+            http2_prior_knowledge: false,
+            http_client_rustls: false,
+            btp_max_reconnect_delay: None,
+            balance_snapshot_max_pause: None,
+            stream_packet_expiry: None,
+            min_asset_scale: None,
+            max_asset_scale: None,
+            max_in_flight_packets: None,
+            max_in_flight_packets_per_account: None,
+            max_queued_outgoing_requests_per_account: None,
+            max_stream_connections_per_account: None,
+            max_streams_per_connection: None,
+            max_response_data_len: None,
+            min_hold_time: None,
+            replay_protection_window: None,
+            http_auth_cache_ttl: None,
+            database_retry_timeout: None,
+            ca_bundle: None,
+            exchange_rate: ExchangeRateConfig::default(),
+            #[cfg(feature = "monitoring")]
+            prometheus: None,
+            #[cfg(feature = "google-pubsub")]
+            google_pubsub: None,
+            settlement_retry_policy: RetryPolicy::default(),
+            settlement_health_check: None,
+            webhook: None,
+            volume_alerts: VolumeAlertsConfig::default(),
+            maintenance: MaintenanceConfig::default(),
+            log_packet_data: false,
+            balance_events: None,
+            tenants: Vec::new(),
+            accounts: Vec::new(),
+            delete_unconfigured_accounts: false,
+        }
+    }
+
+    /// Polls the given provider for exchange rates instead of one of the built-in
+    /// [`ExchangeRateProvider`](./enum.ExchangeRateProvider.html)s
+    pub fn rate_provider(&mut self, provider: Box<dyn RateProvider + Send + Sync>) -> &mut Self {
+        self.exchange_rate.custom_provider = Some(Arc::from(provider));
+        self
+    }
+
+    /// Returns a future that runs the Interledger.rs Node and its tenants.
     ///
     /// If the Prometheus configuration was provided, it will
     /// also run the Prometheus metrics server on the given address.
     // TODO when a BTP connection is made, insert a outgoing HTTP entry into the Store to tell other
     // connector instances to forward packets for that account to us
     pub async fn serve(self) -> Result<(), ()> {
+        let tenants = self.tenant_nodes()?;
+        // Bind every listener first, so that either all of the nodes are served or none
+        let listeners = self.bind_listeners()?;
+        let tenant_listeners = tenants
+            .iter()
+            .map(InterledgerNode::bind_listeners)
+            .collect::<Result<Vec<_>, _>>()?;
+        set_debug_data(self.log_packet_data);
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
-                let f = futures::future::join(serve_prometheus(self.clone()), self.serve_node()).then(
+                let f = futures::future::join(serve_prometheus(self.clone()), self.serve_node(listeners)).then(
                     |r| async move {
                         if r.0.is_ok() || r.1.is_ok() {
                             Ok(())
@@ -240,14 +714,123 @@ impl InterledgerNode {
                     },
                 );
             } else {
-                let f = self.serve_node();
+                let f = self.serve_node(listeners);
             }
         }
 
-        f.await
+        f.await?;
+        for (tenant, listeners) in tenants.into_iter().zip(tenant_listeners) {
+            let ilp_address = tenant
+                .ilp_address
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            tenant.serve_node(listeners).await?;
+            info!(target: "interledger-node", "Serving tenant {}", ilp_address);
+        }
+        Ok(())
+    }
+
+    /// Returns the configuration of each tenant as a node of its own, which has the
+    /// tenant's settings and shares the rest with this node
+    fn tenant_nodes(&self) -> Result<Vec<InterledgerNode>, ()> {
+        let mut bind_addresses = Vec::new();
+        let mut addresses = vec![
+            self.http_bind_address.clone(),
+            self.settlement_api_bind_address.into(),
+        ];
+        addresses.extend(self.admin_bind_address.clone());
+        #[cfg(feature = "monitoring")]
+        addresses.extend(
+            self.prometheus
+                .as_ref()
+                .and_then(|prometheus| prometheus.bind_address)
+                .map(BindAddress::from),
+        );
+        check_bind_addresses(&mut bind_addresses, addresses)?;
+        let mut database_urls = vec![self.database_url.as_str()];
+
+        let mut tenants = Vec::with_capacity(self.tenants.len());
+        for tenant in &self.tenants {
+            let mut addresses = vec![
+                tenant.http_bind_address.clone(),
+                tenant.settlement_api_bind_address.into(),
+            ];
+            addresses.extend(tenant.admin_bind_address.clone());
+            check_bind_addresses(&mut bind_addresses, addresses)?;
+            // Each in-memory store is separate, while any other URL points to the same store
+            if !tenant.database_url.starts_with("memory:") {
+                if database_urls.contains(&tenant.database_url.as_str()) {
+                    error!(target: "interledger-node",
+                        "The store {} is used by more than one tenant", tenant.database_url
+                    );
+                    return Err(());
+                }
+                database_urls.push(&tenant.database_url);
+            }
+
+            let mut node = self.clone();
+            node.ilp_address = tenant.ilp_address.clone();
+            node.admin_auth_token = tenant.admin_auth_token.clone();
+            node.read_only_admin_auth_token = tenant.read_only_admin_auth_token.clone();
+            node.database_url = tenant.database_url.clone();
+            node.http_bind_address = tenant.http_bind_address.clone();
+            node.admin_bind_address = tenant.admin_bind_address.clone();
+            node.settlement_api_bind_address = tenant.settlement_api_bind_address;
+            node.default_spsp_account = tenant.default_spsp_account.clone();
+            node.default_route = tenant.default_route.clone();
+            node.accounts = tenant.accounts.clone();
+            node.delete_unconfigured_accounts = tenant.delete_unconfigured_accounts;
+            node.tenants = Vec::new();
+            // Metrics are only served once, for the whole process
+            #[cfg(feature = "monitoring")]
+            {
+                node.prometheus = None;
+            }
+            tenants.push(node);
+        }
+        Ok(tenants)
+    }
+
+    fn tcp_options(&self) -> TcpOptions {
+        TcpOptions {
+            listen_backlog: self.http_listen_backlog.unwrap_or(DEFAULT_LISTEN_BACKLOG),
+            keepalive: self.http_tcp_keepalive.map(Duration::from_millis),
+            idle_timeout: self.http_idle_timeout.map(Duration::from_millis),
+        }
+    }
+
+    /// Binds to the node's HTTP, admin and settlement API addresses
+    fn bind_listeners(&self) -> Result<NodeListeners, ()> {
+        if self.http_tls.is_some() && self.http_bind_address.tcp().is_none() {
+            error!(target: "interledger-node",
+                "HTTPS cannot be served on the unix socket {}", self.http_bind_address
+            );
+            return Err(());
+        }
+        let tcp_options = self.tcp_options();
+        let http = self.http_bind_address.bind(&tcp_options)?;
+        let admin = match self.admin_bind_address {
+            Some(ref address) => Some(address.bind(&tcp_options)?),
+            None => None,
+        };
+        let settlement_api_bind_address = self.settlement_api_bind_address;
+        let settlement_api = TcpOptions::default()
+            .bind(settlement_api_bind_address)
+            .map_err(|err| {
+                error!(target: "interledger-node",
+                    "Error binding to {}: {}", settlement_api_bind_address, err
+                )
+            })?;
+        Ok(NodeListeners {
+            http,
+            admin,
+            settlement_api,
+        })
     }
 
-    async fn serve_node(self) -> Result<(), ()> {
+    #[allow(clippy::cognitive_complexity)]
+    async fn serve_node(self, listeners: NodeListeners) -> Result<(), ()> {
         let ilp_address = if let Some(address) = &self.ilp_address {
             address.clone()
         } else {
@@ -266,9 +849,24 @@ impl InterledgerNode {
             }
         };
 
+        let balance_events = match self.balance_events.clone() {
+            Some(config) => Some(config.spawn_publisher()?),
+            None => None,
+        };
+
         match database_url.scheme() {
             #[cfg(feature = "redis")]
-            "redis" | "redis+unix" => serve_redis_node(self, ilp_address).await,
+            "redis" | "redis+unix" => {
+                serve_redis_node(self, ilp_address, balance_events, listeners).await
+            }
+            "memory" => {
+                warn!("Using an in-memory store, all data will be lost when the node stops");
+                let mut store = MemoryStore::new(ilp_address.clone());
+                if let Some(sink) = balance_events {
+                    store.balance_events(sink);
+                }
+                self.chain_services(store, ilp_address, listeners).await
+            }
             other => {
                 error!("unsupported data source scheme: {}", other);
                 Err(())
@@ -277,7 +875,12 @@ impl InterledgerNode {
     }
 
     #[allow(clippy::cognitive_complexity)]
-    pub(crate) async fn chain_services<S>(self, store: S, ilp_address: Address) -> Result<(), ()>
+    pub(crate) async fn chain_services<S>(
+        self,
+        store: S,
+        ilp_address: Address,
+        listeners: NodeListeners,
+    ) -> Result<(), ()>
     where
         S: NodeStore<Account = Account>
             + AddressStore
@@ -292,6 +895,9 @@ impl InterledgerNode {
             + RouterStore<Account = Account>
             + CcpRoutingStore<Account = Account>
             + RateLimitStore<Account = Account>
+            + VolumeStore
+            + TrafficStore
+            + FulfilledConditionStore
             + LeftoversStore<AccountId = Uuid, AssetType = BigUint>
             + IdempotentStore
             + AccountStore<Account = Account>
@@ -300,25 +906,120 @@ impl InterledgerNode {
             + Sync
             + 'static,
     {
+        // If no address was configured, the store may still know the one
+        // assigned to us by our parent the last time the node was running
+        let address_configured = self.ilp_address.is_some();
+        let ilp_address = if address_configured {
+            ilp_address
+        } else {
+            store.get_ilp_address()
+        };
         debug!(target: "interledger-node",
             "Starting Interledger node with ILP address: {}",
             ilp_address
         );
 
         let secret_seed = Bytes::from(&self.secret_seed[..]);
-        let http_bind_address = self.http_bind_address;
+        let http_bind_address = self.http_bind_address.clone();
+        let admin_bind_address = self.admin_bind_address.clone();
+        let http_tls = self.http_tls.clone();
+        let tcp_options = self.tcp_options();
         let settlement_api_bind_address = self.settlement_api_bind_address;
         let ilp_address_clone = ilp_address.clone();
         let ilp_address_clone2 = ilp_address.clone();
         let admin_auth_token = self.admin_auth_token.clone();
         let default_spsp_account = self.default_spsp_account.clone();
         let route_broadcast_interval = self.route_broadcast_interval;
-        let exchange_rate_provider = self.exchange_rate.provider.clone();
+        let route_broadcast_delay = self.route_broadcast_delay;
+        let route_broadcast_jitter = self.route_broadcast_jitter;
+        let route_broadcast_concurrency = self.route_broadcast_concurrency;
+        let max_routes_per_peer = self.max_routes_per_peer;
+        let route_limit_policy = self.route_limit_policy;
+        let stale_route_threshold = self.stale_route_threshold;
+        let own_address_policy = self.own_address_policy.clone();
+        let address_validation = self.address_validation;
+        let http2_prior_knowledge = self.http2_prior_knowledge;
+        let http_client_rustls = self.http_client_rustls;
+        let max_in_flight_packets = self.max_in_flight_packets;
+        let max_in_flight_packets_per_account = self.max_in_flight_packets_per_account;
+        let max_queued_outgoing_requests_per_account =
+            self.max_queued_outgoing_requests_per_account;
+        let btp_max_reconnect_delay = self
+            .btp_max_reconnect_delay
+            .unwrap_or(DEFAULT_BTP_MAX_RECONNECT_DELAY);
+        let balance_pause = match self.balance_snapshot_max_pause {
+            Some(max_pause) => BalancePause::new(Duration::from_millis(max_pause)),
+            None => BalancePause::default(),
+        };
+        let max_stream_connections_per_account = self.max_stream_connections_per_account;
+        let max_streams_per_connection = self.max_streams_per_connection;
+        let max_response_data_len = self.max_response_data_len;
+        let min_hold_time = self.min_hold_time;
+        let replay_protection_window = self.replay_protection_window;
+        let root_certificates = match self.ca_bundle {
+            Some(ref bundle) => load_ca_bundle(bundle)?,
+            None => Vec::new(),
+        };
+        let http_client = root_certificates
+            .iter()
+            .cloned()
+            .fold(
+                reqwest::Client::builder(),
+                reqwest::ClientBuilder::add_root_certificate,
+            )
+            .build()
+            .map_err(
+                |err| error!(target: "interledger-node", "Error creating HTTP client: {}", err),
+            )?;
+        let exchange_rate_provider = match self.exchange_rate.custom_provider.clone() {
+            Some(provider) => Some(provider),
+            None => match self.exchange_rate.provider.clone() {
+                Some(provider) => Some(provider.into_rate_provider(http_client.clone()).map_err(
+                    |_| error!(target: "interledger-node", "Error creating exchange rate provider"),
+                )?),
+                None => None,
+            },
+        };
+        let exchange_rate_fallback_providers = self
+            .exchange_rate
+            .fallback_providers
+            .iter()
+            .cloned()
+            .map(|provider| provider.into_rate_provider(http_client.clone()))
+            .collect::<Result<Vec<_>, ()>>()
+            .map_err(|_| {
+                error!(target: "interledger-node", "Error creating fallback exchange rate provider")
+            })?;
         let exchange_rate_poll_interval = self.exchange_rate.poll_interval;
         let exchange_rate_poll_failure_tolerance = self.exchange_rate.poll_failure_tolerance;
+        let exchange_rate_max_poll_backoff = self.exchange_rate.max_poll_backoff;
+        let exchange_rate_poll_jitter = self.exchange_rate.poll_jitter;
+        let exchange_rate_asset_code_aliases = self.exchange_rate.asset_code_aliases.clone();
         let exchange_rate_spread = self.exchange_rate.spread;
+        let exchange_rate_rounding = self.exchange_rate.rounding;
+        let exchange_rate_flat_fee = self.exchange_rate.flat_fee;
         #[cfg(feature = "google-pubsub")]
         let google_pubsub = self.google_pubsub.clone();
+        let webhook = self.webhook.clone();
+        let volume_alerts = self.volume_alerts.clone();
+        let mut settlement_client =
+            SettlementClient::with_retry_policy(self.settlement_retry_policy);
+        settlement_client.root_certificates(root_certificates.clone());
+        if let Some(policy) = self.settlement_health_check {
+            settlement_client.health_failure_threshold(policy.failure_threshold);
+            spawn_settlement_health_checks(
+                store.clone(),
+                settlement_client.clone(),
+                Duration::from_millis(policy.interval),
+            );
+        }
+
+        // The configured accounts must exist before the node connects to its peers and parent
+        let created_accounts =
+            reconcile_accounts(&store, &self.accounts, self.delete_unconfigured_accounts).await?;
+        for account in created_accounts {
+            register_with_settlement_engine(&store, &settlement_client, &account).await;
+        }
 
         let btp_accounts = store
             .get_btp_outgoing_accounts()
@@ -349,16 +1050,14 @@ impl InterledgerNode {
         });
 
         // Connect to all of the accounts that have outgoing ilp_over_btp_urls configured
-        // but don't fail if we are unable to connect
-        // TODO try reconnecting to those accounts later
-        let btp_client_service = connect_client(
+        // but don't fail if we are unable to connect, those are retried in the background
+        let btp_client_service = connect_client_with_reconnect(
             ilp_address_clone2.clone(),
             btp_accounts,
-            false,
+            Duration::from_millis(btp_max_reconnect_delay),
             outgoing_service,
         )
-        .map_err(|err| error!("{}", err))
-        .await?;
+        .await;
         let btp_server_service =
             BtpOutgoingService::new(ilp_address_clone2, btp_client_service.clone());
         let btp_server_service_clone = btp_server_service.clone();
@@ -367,24 +1066,118 @@ impl InterledgerNode {
         // The BTP service is both an Incoming and Outgoing one so we pass it first as the Outgoing
         // service to others like the router and then call handle_incoming on it to set up the incoming handler
         let outgoing_service = btp_server_service.clone();
-        let outgoing_service = HttpClientService::new(store.clone(), outgoing_service);
+        let outgoing_service = {
+            let mut http_client_service = HttpClientService::new(store.clone(), outgoing_service);
+            http_client_service
+                .http2_prior_knowledge(http2_prior_knowledge)
+                .rustls(http_client_rustls)
+                .root_certificates(root_certificates);
+            http_client_service
+        };
+        // Queue the packets to peers which can only handle a limited number of requests at once
+        let outgoing_service = {
+            let mut outgoing_concurrency_limit_service =
+                OutgoingConcurrencyLimitService::new(store.clone(), outgoing_service);
+            if let Some(limit) = max_queued_outgoing_requests_per_account {
+                outgoing_concurrency_limit_service.max_queued_per_account(limit);
+            }
+            outgoing_concurrency_limit_service
+        };
+        // Record the outcome of the packets sent to each peer for the account status API
+        let peer_activity = PeerActivity::new();
+        let outgoing_service = PeerActivityService::new(peer_activity.clone(), outgoing_service);
+
+        // Get the address assigned to us by our parent before setting up the
+        // services which depend on it
+        let ilp_address = if address_configured {
+            ilp_address
+        } else {
+            let parent = store
+                .get_all_accounts()
+                .map_err(|_| error!(target: "interledger-node", "Error getting accounts"))
+                .await?
+                .into_iter()
+                .find(|account| account.routing_relation() == RoutingRelation::Parent);
+            if let Some(parent) = parent {
+                let username = parent.username().clone();
+                adopt_address_from_parent(&store, outgoing_service.clone(), parent)
+                    .map_err(|_| {
+                        error!(target: "interledger-node",
+                            "No ilp_address was configured and the node was unable to get one from its parent account {} via IL-DCP",
+                            username
+                        )
+                    })
+                    .await?
+            } else {
+                ilp_address
+            }
+        };
+
+        if let Some(ref username) = self.default_route {
+            set_default_route(&store, username).await?;
+        }
 
         #[cfg(feature = "monitoring")]
         let outgoing_service = outgoing_service.wrap(outgoing_metrics);
 
         // Note: the expiry shortener must come after the Validator so that the expiry duration
         // is shortened before we check whether there is enough time left
-        let outgoing_service = ValidatorService::outgoing(store.clone(), outgoing_service);
-        let outgoing_service = ExpiryShortenerService::new(outgoing_service);
-        let outgoing_service =
-            StreamReceiverService::new(secret_seed.clone(), store.clone(), outgoing_service);
+        let mut outgoing_service = ValidatorService::outgoing(store.clone(), outgoing_service);
+        if let Some(limit) = max_response_data_len {
+            outgoing_service.max_response_data_len(limit);
+        }
+        let mut outgoing_service = ExpiryShortenerService::new(store.clone(), outgoing_service);
+        if let Some(min_hold_time) = min_hold_time {
+            outgoing_service.min_hold_time(min_hold_time);
+        }
+        let mut outgoing_service = StreamReceiverService::new(
+            secret_seed.clone(),
+            WebhookNotifier::new(store.clone(), webhook, http_client.clone()),
+            outgoing_service,
+        );
+        if let Some(limit) = max_stream_connections_per_account {
+            outgoing_service.max_connections_per_account(limit);
+        }
+        if let Some(limit) = max_streams_per_connection {
+            outgoing_service.max_streams_per_connection(limit);
+        }
+        // Copies of the packets sent to accounts with a shadow account must not change the
+        // balances, so this must be wrapped by the BalanceService
+        let outgoing_service = ShadowService::new(store.clone(), outgoing_service);
         #[cfg(feature = "balance-tracking")]
-        let outgoing_service = BalanceService::new(store.clone(), outgoing_service);
-        let outgoing_service =
-            ExchangeRateService::new(exchange_rate_spread, store.clone(), outgoing_service);
+        let outgoing_service = {
+            let mut balance_service = BalanceService::new(store.clone(), outgoing_service);
+            balance_service.settlement_client(settlement_client.clone());
+            balance_service.pause(balance_pause.clone());
+            balance_service
+        };
+        // The volumes are counted in the asset of the account the packets are sent to,
+        // so this must be wrapped by the ExchangeRateService, which converts the amounts
+        let outgoing_service = {
+            let mut volume_alert_service =
+                VolumeAlertService::new(store.clone(), volume_alerts.window(), outgoing_service);
+            volume_alert_service.reject_over_threshold(volume_alerts.reject);
+            if let Some(callback) = volume_alerts.alert_callback(http_client.clone()) {
+                volume_alert_service.on_alert(callback);
+            }
+            volume_alert_service
+        };
+        // Like the volumes, the outgoing traffic is counted in the asset of the account
+        let outgoing_service = TrafficCountersService::new(store.clone(), outgoing_service);
+        let outgoing_service = {
+            let mut exchange_rate_service =
+                ExchangeRateService::new(exchange_rate_spread, store.clone(), outgoing_service);
+            exchange_rate_service
+                .rounding(exchange_rate_rounding)
+                .flat_fee(exchange_rate_flat_fee);
+            exchange_rate_service
+        };
 
         #[cfg(feature = "google-pubsub")]
-        let outgoing_service = outgoing_service.wrap(create_google_pubsub_wrapper(google_pubsub));
+        let outgoing_service = outgoing_service.wrap(create_google_pubsub_wrapper(
+            google_pubsub,
+            http_client.clone(),
+        ));
 
         // Add tracing to add the outgoing request details to the incoming span
         cfg_if! {
@@ -397,8 +1190,15 @@ impl InterledgerNode {
             }
         }
 
+        // Publish the outcome of every routed packet to the packet events API
+        let (packet_events, _) = broadcast::channel(PACKET_EVENTS_CAPACITY);
+        let outgoing_service_fwd =
+            PacketEventsService::new(packet_events.clone(), outgoing_service_fwd);
+
         // Set up the Router and Routing Manager
         let incoming_service = Router::new(store.clone(), outgoing_service_fwd);
+        // Constrained accounts may only pay to their allowed destinations, whatever the routes are
+        let incoming_service = DestinationAllowlistService::new(store.clone(), incoming_service);
 
         // Add tracing to track the outgoing request details
         #[cfg(feature = "monitoring")]
@@ -414,14 +1214,68 @@ impl InterledgerNode {
         if let Some(ms) = route_broadcast_interval {
             ccp_builder.broadcast_interval(ms);
         }
+        if let Some(ms) = route_broadcast_delay {
+            ccp_builder.broadcast_delay(ms);
+        }
+        if let Some(percent) = route_broadcast_jitter {
+            ccp_builder.broadcast_jitter(percent);
+        }
+        if let Some(limit) = route_broadcast_concurrency {
+            ccp_builder.broadcast_concurrency(limit);
+        }
+        if let Some(max_routes) = max_routes_per_peer {
+            ccp_builder.max_routes_per_peer(max_routes, route_limit_policy);
+        }
+        if let Some(ms) = stale_route_threshold {
+            ccp_builder.stale_route_threshold(ms);
+        }
 
         let incoming_service = ccp_builder.to_service();
         let incoming_service = EchoService::new(store.clone(), incoming_service);
-        let incoming_service = SettlementMessageService::new(incoming_service);
+        let incoming_service = OwnAddressService::new(
+            store.clone(),
+            own_address_policy,
+            incoming_service,
+            outgoing_service.clone(),
+        );
+        let incoming_service = {
+            let mut settlement_message_service = SettlementMessageService::new(incoming_service);
+            settlement_message_service.settlement_client(settlement_client.clone());
+            settlement_message_service
+        };
         let incoming_service = IldcpService::new(incoming_service);
         let incoming_service = MaxPacketAmountService::new(store.clone(), incoming_service);
-        let incoming_service = ValidatorService::incoming(store.clone(), incoming_service);
+        let incoming_service = {
+            let mut replay_protection_service =
+                ReplayProtectionService::new(store.clone(), incoming_service);
+            if let Some(ms) = replay_protection_window {
+                replay_protection_service.window(Duration::from_millis(ms));
+            }
+            replay_protection_service
+        };
+        let incoming_service = {
+            let mut validator_service = ValidatorService::incoming(store.clone(), incoming_service);
+            validator_service.address_validation(address_validation);
+            validator_service
+        };
         let incoming_service = RateLimitService::new(store.clone(), incoming_service);
+        let incoming_service = {
+            let mut concurrency_limit_service =
+                ConcurrencyLimitService::new(store.clone(), incoming_service);
+            if let Some(limit) = max_in_flight_packets {
+                concurrency_limit_service.max_in_flight(limit);
+            }
+            if let Some(limit) = max_in_flight_packets_per_account {
+                concurrency_limit_service.max_in_flight_per_account(limit);
+            }
+            concurrency_limit_service
+        };
+        // Packets which are already in flight are not affected when maintenance mode is enabled
+        let maintenance_mode = self.maintenance.maintenance_mode();
+        let incoming_service =
+            MaintenanceModeService::new(store.clone(), maintenance_mode.clone(), incoming_service);
+        let incoming_service = PeerActivityService::new(peer_activity.clone(), incoming_service);
+        let incoming_service = TrafficCountersService::new(store.clone(), incoming_service);
 
         // Add tracing to track the incoming request details
         #[cfg(feature = "monitoring")]
@@ -430,13 +1284,15 @@ impl InterledgerNode {
             .in_current_span()
             .wrap(incoming_metrics);
 
-        // Handle incoming packets sent via BTP
+        // Handle incoming packets sent via BTP. Every packet gets its own request ID
+        // so the logs of all services handling it can be correlated (the ILP-over-HTTP
+        // server does the same for the packets received over HTTP)
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
                 let incoming_service_btp = incoming_service
                     .clone()
                     .wrap(|request, mut next| async move {
-                        let btp = debug_span!(target: "interledger-node", "btp");
+                        let btp = error_span!(target: "interledger-node", "btp", request.id = %Uuid::new_v4());
                         let _btp_scope = btp.enter();
                         next.handle_request(request).in_current_span().await
                     })
@@ -459,7 +1315,7 @@ impl InterledgerNode {
                 let incoming_service_api = incoming_service
                     .clone()
                     .wrap(|request, mut next| async move {
-                        let api = debug_span!(target: "interledger-node", "api");
+                        let api = error_span!(target: "interledger-node", "api", request.id = %Uuid::new_v4());
                         let _api_scope = api.enter();
                         next.handle_request(request).in_current_span().await
                     })
@@ -481,7 +1337,25 @@ impl InterledgerNode {
         if let Some(username) = default_spsp_account {
             api.default_spsp_account(username);
         }
+        if let Some(token) = self.read_only_admin_auth_token.clone() {
+            api.read_only_admin_api_token(token);
+        }
         api.node_version(env!("CARGO_PKG_VERSION").to_string());
+        api.features(node_features());
+        api.packet_events(packet_events);
+        api.btp_connections(btp_server_service_clone.connections());
+        api.peer_activity(peer_activity);
+        api.maintenance_mode(maintenance_mode);
+        api.balance_pause(balance_pause);
+        api.settlement_client(settlement_client);
+        api.spsp_client(http_client);
+        if let Some(expiry) = self.stream_packet_expiry {
+            api.packet_expiry(Duration::from_millis(expiry));
+        }
+        api.asset_scale_range(
+            self.min_asset_scale.unwrap_or(0),
+            self.max_asset_scale.unwrap_or(DEFAULT_MAX_ASSET_SCALE),
+        );
 
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
@@ -498,89 +1372,160 @@ impl InterledgerNode {
             }
         }
 
-        // add an API of ILP over HTTP and add rejection handler
-        let api = api
-            .into_warp_filter()
-            .or(IlpOverHttpServer::new(incoming_service_http, store.clone()).as_filter())
+        let spsp_api = api.spsp_filter();
+        let api = api.into_admin_filter();
+        // ILP over HTTP, BTP and SPSP endpoints
+        let packets_api = IlpOverHttpServer::new(incoming_service_http, store.clone())
+            .as_filter()
             .or(btp_service_as_filter(
                 btp_server_service_clone,
                 store.clone(),
-            ));
+            ))
+            .or(spsp_api);
 
         // If monitoring is enabled, run a tracing subscriber
         // and expose a new endpoint at /tracing-level which allows
         // changing the tracing level by administrators
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
-                let builder = Subscriber::builder()
-                    .with_timer(ChronoUtc::rfc3339())
-                    .with_env_filter(EnvFilter::from_default_env())
-                    .with_filter_reloading();
-                let handle = builder.reload_handle();
-                builder.try_init().unwrap_or(());
-
-                let admin_auth_token = self.admin_auth_token.clone();
+                let log_filter = LogFilter::init();
+
+                let admin_auth_header = format!("Bearer {}", self.admin_auth_token);
+                let admin_only = warp::header::<SecretString>("authorization")
+                    .and_then(move |auth_header: SecretString| {
+                        let authorized = auth_header.expose_secret() == &admin_auth_header;
+                        async move {
+                            if authorized {
+                                Ok(())
+                            } else {
+                                Err(warp::Rejection::from(
+                                    ApiError::unauthorized().detail("invalid admin auth token"),
+                                ))
+                            }
+                        }
+                    })
+                    .untuple_one();
+                let utf8_body = warp::body::bytes().and_then(|body: bytes05::Bytes| async move {
+                    String::from_utf8(body.to_vec()).map_err(|_| {
+                        warp::Rejection::from(
+                            ApiError::bad_request().detail("invalid utf-8 body provided"),
+                        )
+                    })
+                });
+                let tracing_level = warp::path("tracing-level")
+                    .and(warp::path::end())
+                    .and(admin_only);
+
                 let api = {
+                    let log_filter_clone = log_filter.clone();
+                    let get_tracing = warp::get()
+                        .and(tracing_level.clone())
+                        .map(move || log_filter_clone.current());
+                    let log_filter_clone = log_filter.clone();
                     let adjust_tracing = warp::put()
-                        .and(warp::path("tracing-level"))
-                        .and(warp::path::end())
-                        .and(warp::header::<SecretString>("authorization"))
-                        .and(warp::body::bytes())
-                        .and_then(
-                            move |auth_header: SecretString, new_level: bytes05::Bytes| {
-                                let handle = handle.clone();
-                                let admin_auth_header = format!("Bearer {}", admin_auth_token);
-                                async move {
-                                    if auth_header.expose_secret().as_str() != admin_auth_header {
-                                        return Err(ApiError::unauthorized()
-                                            .detail("invalid admin auth token")
-                                            .into());
-                                    }
-                                    let new_level = std::str::from_utf8(new_level.as_ref()).map_err(|_| {
-                                        ApiError::bad_request().detail("invalid utf-8 body provided")
-                                    })?;
-                                    let new_tracing_level = new_level
-                                        .parse::<tracing_subscriber::filter::EnvFilter>()
-                                        .map_err(|_| {
-                                            ApiError::bad_request().detail("could not parse body as log level")
-                                        })?;
-                                    handle.reload(new_tracing_level).map_err(|err| {
-                                        ApiError::internal_server_error()
-                                            .detail(format!("could not apply new log level {}", err))
-                                    })?;
-                                    debug!(target: "interledger-node", "Logging level adjusted to {}", new_level);
-                                    Ok::<String, warp::Rejection>(format!(
-                                        "Logging level changed to: {}",
-                                        new_level
-                                    ))
-                                }
-                            },
-                        );
-                    api.or(adjust_tracing)
+                        .and(tracing_level.clone())
+                        .and(utf8_body.clone())
+                        .and_then(move |new_level: String| {
+                            let result = log_filter_clone.set(&new_level);
+                            async move {
+                                let new_level = result?;
+                                Ok::<String, warp::Rejection>(format!(
+                                    "Logging level changed to: {}",
+                                    new_level
+                                ))
+                            }
+                        });
+                    let add_tracing = warp::post()
+                        .and(tracing_level)
+                        .and(warp::query::<TracingLevelQuery>())
+                        .and(utf8_body)
+                        .and_then(move |query: TracingLevelQuery, directives: String| {
+                            let result = log_filter
+                                .add(&directives, query.duration.map(Duration::from_millis));
+                            async move {
+                                let new_level = result?;
+                                Ok::<String, warp::Rejection>(format!(
+                                    "Logging level changed to: {}",
+                                    new_level
+                                ))
+                            }
+                        });
+                    api.or(get_tracing).or(adjust_tracing).or(add_tracing)
                 };
             }
         }
 
-        let api = api
-            .recover(default_rejection_handler)
-            .with(warp::log("interledger-api"))
-            .boxed();
+        let NodeListeners {
+            http: http_listener,
+            admin: admin_listener,
+            settlement_api: settlement_api_listener,
+        } = listeners;
+        if let (Some(admin_bind_address), Some(admin_listener)) =
+            (admin_bind_address, admin_listener)
+        {
+            let api = with_cors(
+                api.recover(default_rejection_handler)
+                    .with(warp::log("interledger-api")),
+                self.cors.as_ref(),
+            );
+            let packets_api = with_cors(
+                packets_api
+                    .recover(default_rejection_handler)
+                    .with(warp::log("interledger-api")),
+                self.cors.as_ref(),
+            );
+
+            info!(target: "interledger-node", "Interledger.rs node HTTP API listening on: {}", admin_bind_address);
+            spawn(admin_listener.serve(api, &tcp_options));
+            info!(target: "interledger-node", "ILP over HTTP, BTP and SPSP listening on: {}", http_bind_address);
+            match (http_tls, http_listener) {
+                (Some(http_tls), Listener::Tcp(listener)) => {
+                    spawn(http_tls.serve(packets_api, listener, &tcp_options)?)
+                }
+                (_, listener) => spawn(listener.serve(packets_api, &tcp_options)),
+            };
+        } else {
+            // serve the API, ILP over HTTP and BTP on the same address
+            let api = with_cors(
+                api.or(packets_api)
+                    .recover(default_rejection_handler)
+                    .with(warp::log("interledger-api")),
+                self.cors.as_ref(),
+            );
 
-        info!(target: "interledger-node", "Interledger.rs node HTTP API listening on: {}", http_bind_address);
-        spawn(warp::serve(api).bind(http_bind_address));
+            info!(target: "interledger-node", "Interledger.rs node HTTP API listening on: {}", http_bind_address);
+            match (http_tls, http_listener) {
+                (Some(http_tls), Listener::Tcp(listener)) => {
+                    spawn(http_tls.serve(api, listener, &tcp_options)?)
+                }
+                (_, listener) => spawn(listener.serve(api, &tcp_options)),
+            };
+        }
 
         // Settlement API
-        let settlement_api = create_settlements_filter(store.clone(), outgoing_service.clone());
+        // Incoming settlements are converted from the accounts' settlement assets before they are credited
+        let settlement_api = create_settlements_filter(
+            IncomingSettlementConverter::new(store.clone()),
+            outgoing_service.clone(),
+        );
         info!(target: "interledger-node", "Settlement API listening on: {}", settlement_api_bind_address);
-        spawn(warp::serve(settlement_api).bind(settlement_api_bind_address));
+        spawn(
+            warp::serve(settlement_api)
+                .run_incoming(TcpOptions::default().incoming(settlement_api_listener)),
+        );
 
         // Exchange Rate Polling
         if let Some(provider) = exchange_rate_provider {
-            let exchange_rate_fetcher = ExchangeRateFetcher::new(
+            let mut exchange_rate_fetcher = ExchangeRateFetcher::new(
                 provider,
                 exchange_rate_poll_failure_tolerance,
                 store.clone(),
             );
+            exchange_rate_fetcher
+                .fallback_providers(exchange_rate_fallback_providers)
+                .max_backoff(Duration::from_millis(exchange_rate_max_poll_backoff))
+                .jitter(exchange_rate_poll_jitter)
+                .asset_code_aliases(exchange_rate_asset_code_aliases);
             exchange_rate_fetcher
                 .spawn_interval(Duration::from_millis(exchange_rate_poll_interval));
         } else {
@@ -590,3 +1535,598 @@ impl InterledgerNode {
         Ok(())
     }
 }
+
+/// Sends an IL-DCP request to the parent and sets the ILP address
+/// it assigned to us as the node's address in the store
+async fn adopt_address_from_parent<S, O, A>(
+    store: &S,
+    mut service: O,
+    parent: A,
+) -> Result<Address, ()>
+where
+    S: AddressStore,
+    O: OutgoingService<A>,
+    A: AccountTrait + Clone,
+{
+    debug!(target: "interledger-node",
+        "Getting ILP address from parent account: {} (id: {})",
+        parent.username(),
+        parent.id()
+    );
+    let prepare = IldcpRequest {}.to_prepare();
+    let fulfill = service
+        .send_request(OutgoingRequest {
+            from: parent.clone(),
+            to: parent,
+            original_amount: prepare.amount(),
+            prepare,
+        })
+        .map_err(|err| error!(target: "interledger-node", "Error getting ILDCP info: {:?}", err))
+        .await?;
+    let info = IldcpResponse::try_from(fulfill.into_data().freeze()).map_err(|err| {
+        error!(target: "interledger-node",
+            "Unable to parse ILDCP response from fulfill packet: {:?}",
+            err
+        )
+    })?;
+
+    let ilp_address = info.ilp_address();
+    store
+        .set_ilp_address(ilp_address.clone())
+        .map_err(|err| error!(target: "interledger-node", "Error saving ILP address: {:?}", err))
+        .await?;
+    info!(target: "interledger-node", "Got ILP address from parent: {}", ilp_address);
+    Ok(ilp_address)
+}
+
+/// Routes the packets the node has no more specific route for to the given account
+async fn set_default_route<S>(store: &S, username: &Username) -> Result<(), ()>
+where
+    S: NodeStore + AccountStore,
+{
+    let account_id = store
+        .get_account_id_from_username(username)
+        .map_err(|err| {
+            error!(target: "interledger-node",
+                "Error loading the default route account {}: {:?}",
+                username, err
+            )
+        })
+        .await?;
+    store
+        .set_default_route(account_id)
+        .map_err(|err| error!(target: "interledger-node", "Error setting default route: {:?}", err))
+        .await?;
+    info!(target: "interledger-node", "Set default route to account: {}", username);
+    Ok(())
+}
+
+/// Creates the configured accounts which do not exist yet and updates the others to match
+/// their configuration, identifying them by their usernames. Returns the created accounts
+async fn reconcile_accounts<S>(
+    store: &S,
+    accounts: &[AccountDetails],
+    delete_unconfigured_accounts: bool,
+) -> Result<Vec<Account>, ()>
+where
+    S: NodeStore<Account = Account> + AccountStore<Account = Account>,
+{
+    let mut created_accounts = Vec::new();
+    for details in accounts {
+        let username = &details.username;
+        match store.get_account_id_from_username(username).await {
+            Ok(id) => {
+                store
+                    .update_account(id, details.clone())
+                    .map_err(|err| {
+                        error!(target: "interledger-node",
+                            "Error updating configured account {}: {:?}",
+                            username, err
+                        )
+                    })
+                    .await?;
+                debug!(target: "interledger-node", "Updated configured account: {}", username);
+            }
+            Err(AccountStoreError::AccountNotFound(_)) => {
+                let account = store
+                    .insert_account(details.clone())
+                    .map_err(|err| {
+                        error!(target: "interledger-node",
+                            "Error creating configured account {}: {:?}",
+                            username, err
+                        )
+                    })
+                    .await?;
+                info!(target: "interledger-node", "Created configured account: {}", username);
+                created_accounts.push(account);
+            }
+            Err(err) => {
+                error!(target: "interledger-node",
+                    "Error loading configured account {}: {:?}", username, err
+                );
+                return Err(());
+            }
+        }
+    }
+
+    if delete_unconfigured_accounts {
+        let unconfigured = store
+            .get_all_accounts()
+            .map_err(|err| error!(target: "interledger-node", "Error getting accounts: {:?}", err))
+            .await?
+            .into_iter()
+            .filter(|account| {
+                !accounts
+                    .iter()
+                    .any(|details| &details.username == account.username())
+            });
+        for account in unconfigured {
+            store
+                .delete_account(account.id())
+                .map_err(|err| {
+                    error!(target: "interledger-node",
+                        "Error deleting unconfigured account {}: {:?}",
+                        account.username(), err
+                    )
+                })
+                .await?;
+            info!(target: "interledger-node", "Deleted unconfigured account: {}", account.username());
+        }
+    }
+    Ok(created_accounts)
+}
+
+/// Creates the account on its settlement engine (or the one of its asset), like the API does
+/// for the accounts created through it. Errors are only logged
+async fn register_with_settlement_engine<S>(
+    store: &S,
+    settlement_client: &SettlementClient,
+    account: &Account,
+) where
+    S: NodeStore<Account = Account>,
+{
+    let engine_url = match account.settlement_engine_details() {
+        Some(details) => Some(details.url),
+        None => store
+            .get_asset_settlement_engine(account.asset_code())
+            .await
+            .unwrap_or(None),
+    };
+    if let Some(engine_url) = engine_url {
+        match settlement_client
+            .create_engine_account(account.id(), engine_url)
+            .await
+        {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => warn!(target: "interledger-node",
+                "Error creating account {} on its settlement engine, which responded with HTTP code: {}",
+                account.username(), response.status()
+            ),
+            Err(err) => warn!(target: "interledger-node",
+                "Error creating account {} on its settlement engine: {}",
+                account.username(), err
+            ),
+        }
+    }
+}
+
+/// Returns the optional features the node was built with
+fn node_features() -> Vec<String> {
+    let features = [
+        ("balance-tracking", cfg!(feature = "balance-tracking")),
+        ("google-pubsub", cfg!(feature = "google-pubsub")),
+        ("monitoring", cfg!(feature = "monitoring")),
+        ("redis", cfg!(feature = "redis")),
+    ];
+    features
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(feature, _)| feature.to_string())
+        .collect()
+}
+
+/// Probes the settlement engines of all accounts on the given interval
+fn spawn_settlement_health_checks<S>(
+    store: S,
+    settlement_client: SettlementClient,
+    interval: Duration,
+) where
+    S: NodeStore<Account = Account> + Clone + Send + Sync + 'static,
+{
+    debug!(target: "interledger-node", "Probing the settlement engines every {:?}", interval);
+    spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match store.get_all_accounts().await {
+                Ok(accounts) => {
+                    let engine_urls: HashSet<Url> = accounts
+                        .iter()
+                        .filter_map(|account| account.settlement_engine_details())
+                        .map(|details| details.url)
+                        .collect();
+                    settlement_client.probe_engines(engine_urls).await;
+                }
+                Err(err) => {
+                    warn!(target: "interledger-node", "Error getting the accounts whose settlement engines are probed: {}", err)
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger::{
+        ildcp::IldcpResponseBuilder,
+        packet::{Fulfill, FulfillBuilder, PrepareBuilder, Reject},
+        service::{IncomingRequest, IncomingService},
+        service_util::MaxPacketAmountAccount,
+    };
+    use serde_json::json;
+    use std::sync::RwLock;
+    use std::time::SystemTime;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+
+    #[tokio::test]
+    async fn adopts_address_assigned_by_parent() {
+        let store = TestStore::default();
+        let parent = outgoing_service_fn(|request: OutgoingRequest<TestAccount>| {
+            assert_eq!(request.prepare.destination().to_string(), "peer.config");
+            Ok(Fulfill::from(
+                IldcpResponseBuilder {
+                    ilp_address: &Address::from_str("example.parent.child").unwrap(),
+                    asset_code: "XYZ",
+                    asset_scale: 9,
+                }
+                .build(),
+            ))
+        });
+
+        let address = adopt_address_from_parent(&store, parent, TestAccount)
+            .await
+            .unwrap();
+        assert_eq!(address.to_string(), "example.parent.child");
+        assert_eq!(store.get_ilp_address(), address);
+    }
+
+    #[tokio::test]
+    async fn fails_if_parent_rejects() {
+        let store = TestStore::default();
+        let parent = outgoing_service_fn(|_| -> Result<_, Reject> {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: &[],
+                triggered_by: None,
+                data: &[],
+            }
+            .build())
+        });
+
+        assert!(adopt_address_from_parent(&store, parent, TestAccount)
+            .await
+            .is_err());
+        assert_eq!(store.get_ilp_address(), *DEFAULT_ILP_ADDRESS);
+    }
+
+    #[test]
+    fn configures_tenants_as_separate_nodes() {
+        let config = |tenant_http_address: &str, tenant_database_url: &str| {
+            serde_json::from_value::<InterledgerNode>(json!({
+                "ilp_address": "example.node",
+                "secret_seed": "0".repeat(64),
+                "admin_auth_token": "admin",
+                "database_url": "redis://127.0.0.1:6379/0",
+                "http_bind_address": "127.0.0.1:7770",
+                "settlement_api_bind_address": "127.0.0.1:7771",
+                "route_broadcast_interval": 1000,
+                "tenants": [{
+                    "ilp_address": "example.tenant",
+                    "admin_auth_token": "tenant_admin",
+                    "database_url": tenant_database_url,
+                    "http_bind_address": tenant_http_address,
+                    "settlement_api_bind_address": "127.0.0.1:8771",
+                    "default_route": "parent",
+                }],
+            }))
+            .unwrap()
+        };
+
+        let tenants = config("127.0.0.1:8770", "redis://127.0.0.1:6379/1")
+            .tenant_nodes()
+            .unwrap();
+        assert_eq!(tenants.len(), 1);
+        let tenant = &tenants[0];
+        assert_eq!(
+            tenant.ilp_address,
+            Some(Address::from_str("example.tenant").unwrap())
+        );
+        assert_eq!(tenant.admin_auth_token, "tenant_admin");
+        assert_eq!(tenant.database_url, "redis://127.0.0.1:6379/1");
+        assert_eq!(
+            tenant.http_bind_address,
+            BindAddress::from_str("127.0.0.1:8770").unwrap()
+        );
+        assert_eq!(tenant.settlement_api_bind_address.port(), 8771);
+        assert_eq!(
+            tenant.default_route,
+            Some(Username::from_str("parent").unwrap())
+        );
+        // the other settings are the node's
+        assert_eq!(tenant.route_broadcast_interval, Some(1000));
+        assert!(tenant.tenants.is_empty());
+
+        // tenants may not share the node's listening addresses or store
+        assert!(config("127.0.0.1:7770", "redis://127.0.0.1:6379/1")
+            .tenant_nodes()
+            .is_err());
+        // including the same port on all interfaces
+        assert!(config("0.0.0.0:7770", "redis://127.0.0.1:6379/1")
+            .tenant_nodes()
+            .is_err());
+        assert!(config("127.0.0.1:8770", "redis://127.0.0.1:6379/0")
+            .tenant_nodes()
+            .is_err());
+        // except for in-memory stores, which are always separate
+        assert!(config("127.0.0.1:8770", "memory://").tenant_nodes().is_ok());
+    }
+
+    #[test]
+    fn rejects_spreads_outside_of_the_unit_interval() {
+        let config = |spread: f64| {
+            serde_json::from_value::<InterledgerNode>(json!({
+                "ilp_address": "example.node",
+                "secret_seed": "0".repeat(64),
+                "admin_auth_token": "admin",
+                "exchange_rate": { "spread": spread },
+            }))
+        };
+
+        assert_eq!(config(0.0).unwrap().exchange_rate.spread, 0.0);
+        assert_eq!(config(0.01).unwrap().exchange_rate.spread, 0.01);
+        assert!(config(1.0).is_err());
+        assert!(config(-0.01).is_err());
+    }
+
+    #[test]
+    fn constructs_node_with_default_settings() {
+        let node = InterledgerNode::new([1; 32], "admin".to_string());
+        let configured: InterledgerNode = serde_json::from_value(json!({
+            "secret_seed": "01".repeat(32),
+            "admin_auth_token": "admin",
+        }))
+        .unwrap();
+
+        assert_eq!(node.secret_seed, configured.secret_seed);
+        assert_eq!(node.admin_auth_token, configured.admin_auth_token);
+        assert_eq!(node.ilp_address, configured.ilp_address);
+        assert_eq!(node.database_url, configured.database_url);
+        assert_eq!(node.http_bind_address, configured.http_bind_address);
+        assert_eq!(
+            node.settlement_api_bind_address,
+            configured.settlement_api_bind_address
+        );
+        assert_eq!(node.own_address_policy, configured.own_address_policy);
+        assert_eq!(node.address_validation, configured.address_validation);
+        assert_eq!(
+            node.exchange_rate.poll_interval,
+            configured.exchange_rate.poll_interval
+        );
+        assert_eq!(
+            node.exchange_rate.max_poll_backoff,
+            configured.exchange_rate.max_poll_backoff
+        );
+        assert_eq!(node.exchange_rate.poll_interval, 60_000);
+        assert!(node.tenant_nodes().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn routes_unknown_destinations_to_default_route() {
+        let store = MemoryStore::new(Address::from_str("example.node").unwrap());
+        let account = |username: &str, address: &str, relation: &str| -> AccountDetails {
+            serde_json::from_value(json!({
+                "username": username,
+                "ilp_address": address,
+                "asset_code": "XYZ",
+                "asset_scale": 9,
+                "routing_relation": relation,
+            }))
+            .unwrap()
+        };
+        let peer = store
+            .insert_account(account("peer", "example.peer", "Peer"))
+            .await
+            .unwrap();
+        store
+            .insert_account(account("parent", "example.parent", "Parent"))
+            .await
+            .unwrap();
+        set_default_route(&store, &Username::from_str("parent").unwrap())
+            .await
+            .unwrap();
+
+        // Replies with the username of the account the packet was routed to
+        let router = Router::new(
+            store.clone(),
+            outgoing_service_fn(|request: OutgoingRequest<Account>| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: request.to.username().as_ref().as_bytes(),
+                }
+                .build())
+            }),
+        );
+        let route = |destination: &str| {
+            let mut router = router.clone();
+            let request = IncomingRequest {
+                from: peer.clone(),
+                prepare: PrepareBuilder {
+                    destination: Address::from_str(destination).unwrap(),
+                    amount: 100,
+                    expires_at: SystemTime::now() + Duration::from_secs(30),
+                    execution_condition: &[0; 32],
+                    data: &[],
+                }
+                .build(),
+            };
+            async move { router.handle_request(request).await }
+        };
+        assert_eq!(route("example.peer.bob").await.unwrap().data(), b"peer");
+        assert_eq!(route("example.other.bob").await.unwrap().data(), b"parent");
+
+        assert!(
+            set_default_route(&store, &Username::from_str("nobody").unwrap())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn loads_configured_accounts_through_the_config_crate() {
+        let yaml = format!(
+            r#"
+secret_seed: "{}"
+admin_auth_token: admin
+accounts:
+  - username: alice
+    ilp_address: example.alice
+    asset_code: XYZ
+    asset_scale: 9
+    max_packet_amount: 100
+"#,
+            "0".repeat(64)
+        );
+        let mut config = config::Config::new();
+        config
+            .merge(config::File::from_str(&yaml, config::FileFormat::Yaml))
+            .unwrap();
+        let node: InterledgerNode = config.try_into().unwrap();
+        assert_eq!(node.accounts.len(), 1);
+        let alice = &node.accounts[0];
+        assert_eq!(alice.username, Username::from_str("alice").unwrap());
+        assert_eq!(
+            alice.ilp_address,
+            Some(Address::from_str("example.alice").unwrap())
+        );
+        assert_eq!(alice.max_packet_amount, 100);
+    }
+
+    #[tokio::test]
+    async fn reconciles_configured_accounts() {
+        let store = MemoryStore::new(Address::from_str("example.node").unwrap());
+        let configured = |accounts: serde_json::Value, delete_unconfigured_accounts: bool| {
+            let node = serde_json::from_value::<InterledgerNode>(json!({
+                "secret_seed": "0".repeat(64),
+                "admin_auth_token": "admin",
+                "accounts": accounts,
+                "delete_unconfigured_accounts": delete_unconfigured_accounts,
+            }))
+            .unwrap();
+            let store = store.clone();
+            async move {
+                reconcile_accounts(&store, &node.accounts, node.delete_unconfigured_accounts).await
+            }
+        };
+        let account = |username: &str, max_packet_amount: u64| {
+            json!({
+                "username": username,
+                "asset_code": "XYZ",
+                "asset_scale": 9,
+                "max_packet_amount": max_packet_amount,
+            })
+        };
+        let get_account = |username: &str| {
+            let store = store.clone();
+            let username = Username::from_str(username).unwrap();
+            async move {
+                let id = store.get_account_id_from_username(&username).await?;
+                Ok::<_, AccountStoreError>(store.get_accounts(vec![id]).await?.pop().unwrap())
+            }
+        };
+        store
+            .insert_account(serde_json::from_value(account("charlie", 100)).unwrap())
+            .await
+            .unwrap();
+
+        let created = configured(json!([account("alice", 100), account("bob", 100)]), false)
+            .await
+            .unwrap();
+        assert_eq!(created.len(), 2);
+        let alice = get_account("alice").await.unwrap();
+        assert_eq!(alice.max_packet_amount(), 100);
+        assert_eq!(alice.ilp_address().to_string(), "example.node.alice");
+
+        // the existing accounts are updated and the unconfigured ones left alone
+        let created = configured(json!([account("alice", 500), account("bob", 100)]), false)
+            .await
+            .unwrap();
+        assert!(created.is_empty());
+        let updated_alice = get_account("alice").await.unwrap();
+        assert_eq!(updated_alice.id(), alice.id());
+        assert_eq!(updated_alice.max_packet_amount(), 500);
+        assert!(get_account("charlie").await.is_ok());
+
+        configured(json!([account("alice", 500)]), true)
+            .await
+            .unwrap();
+        assert!(get_account("alice").await.is_ok());
+        assert!(get_account("bob").await.is_err());
+        assert!(get_account("charlie").await.is_err());
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestAccount;
+
+    impl AccountTrait for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::default()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &DEFAULT_ILP_ADDRESS
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore {
+        address: Arc<RwLock<Address>>,
+    }
+
+    impl Default for TestStore {
+        fn default() -> Self {
+            TestStore {
+                address: Arc::new(RwLock::new(DEFAULT_ILP_ADDRESS.clone())),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+            *self.address.write().unwrap() = ilp_address;
+            Ok(())
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            self.address.read().unwrap().clone()
+        }
+    }
+}
@@ -1,15 +1,19 @@
 #![cfg(feature = "redis")]
 
-use crate::node::InterledgerNode;
+use crate::node::{InterledgerNode, NodeListeners};
 use futures::TryFutureExt;
 pub use interledger::{
     api::{AccountDetails, NodeStore},
     packet::Address,
     service::Account,
-    store::redis::RedisStoreBuilder,
+    store::{
+        events::BalanceEventSink,
+        redis::{ConnectionStatus, RedisStoreBuilder},
+    },
 };
 pub use redis_crate::{ConnectionInfo, IntoConnectionInfo};
 use ring::hmac;
+use std::time::Duration;
 use tracing::error;
 
 static REDIS_SECRET_GENERATION_STRING: &str = "ilp_redis_secret";
@@ -21,16 +25,44 @@ pub fn default_redis_url() -> String {
 // This function could theoretically be defined as an inherent method on InterledgerNode itself.
 // However, we define it in this module in order to consolidate conditionally-compiled code
 // into as few discrete units as possible.
-pub async fn serve_redis_node(node: InterledgerNode, ilp_address: Address) -> Result<(), ()> {
+pub async fn serve_redis_node(
+    node: InterledgerNode,
+    ilp_address: Address,
+    balance_events: Option<BalanceEventSink>,
+    listeners: NodeListeners,
+) -> Result<(), ()> {
     let redis_connection_info = node.database_url.clone().into_connection_info().unwrap();
     let redis_addr = redis_connection_info.addr.clone();
     let redis_secret = generate_redis_secret(&node.secret_seed);
-    let store = RedisStoreBuilder::new(redis_connection_info, redis_secret)
-        .node_ilp_address(ilp_address.clone())
+    let mut builder = RedisStoreBuilder::new(redis_connection_info, redis_secret);
+    builder.node_ilp_address(ilp_address.clone());
+    if let Some(sink) = balance_events {
+        builder.balance_events(sink);
+    }
+    if let Some(ttl) = node.http_auth_cache_ttl {
+        builder.http_auth_cache_ttl(Duration::from_millis(ttl));
+    }
+    if let Some(timeout) = node.database_retry_timeout {
+        builder.retry_timeout(Duration::from_millis(timeout));
+    }
+    let store = builder
         .connect()
         .map_err(move |err| error!(target: "interledger-node", "Error connecting to Redis: {:?} {:?}", redis_addr, err))
         .await?;
-    node.chain_services(store, ilp_address).await
+    #[cfg(feature = "monitoring")]
+    tokio::spawn(record_connection_status(store.connection_status()));
+    node.chain_services(store, ilp_address, listeners).await
+}
+
+/// Records whether the store is connected to Redis in the `store.connected` gauge
+/// (1 if it is, 0 while it is reconnecting)
+#[cfg(feature = "monitoring")]
+async fn record_connection_status(status: ConnectionStatus) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        metrics::gauge!("store.connected", status.is_connected() as i64);
+    }
 }
 
 pub fn generate_redis_secret(secret_seed: &[u8; 32]) -> [u8; 32] {
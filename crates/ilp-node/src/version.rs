@@ -0,0 +1,57 @@
+use clap::crate_version;
+
+/// The git commit the node was built from (`unknown` if it was not built from a checkout)
+pub const GIT_SHA: &str = env!("ILP_NODE_GIT_SHA");
+/// When the node was built, as an RFC 3339 timestamp in UTC
+pub const BUILD_TIMESTAMP: &str = env!("ILP_NODE_BUILD_TIMESTAMP");
+
+/// The optional features the node was built with
+pub fn enabled_features() -> Vec<&'static str> {
+    let features = [
+        ("balance-tracking", cfg!(feature = "balance-tracking")),
+        ("redis", cfg!(feature = "redis")),
+        ("google-pubsub", cfg!(feature = "google-pubsub")),
+        ("monitoring", cfg!(feature = "monitoring")),
+    ];
+    features
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+/// The version printed by `ilp-node --version`, with the build metadata needed for support
+pub fn long_version() -> String {
+    let features = enabled_features();
+    format!(
+        "{}\ncommit: {}\nbuilt: {}\nfeatures: {}",
+        crate_version!(),
+        GIT_SHA,
+        BUILD_TIMESTAMP,
+        if features.is_empty() {
+            "none".to_string()
+        } else {
+            features.join(", ")
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_version_contains_build_metadata() {
+        let version = long_version();
+        let lines: Vec<&str> = version.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], env!("CARGO_PKG_VERSION"));
+        assert!(lines[1].starts_with("commit: "));
+        assert!(lines[1].len() > "commit: ".len());
+        assert!(lines[2].starts_with("built: "));
+        assert!(lines[2].ends_with('Z'));
+        assert!(lines[3].starts_with("features: "));
+        #[cfg(feature = "redis")]
+        assert!(lines[3].contains("redis"));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::webhooks::{WebhookClient, WebhookConfig};
+use interledger::service_util::VolumeAlert;
+use reqwest::Client;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::spawn;
+
+/// Configuration for alerting when the amount sent to an account within a window
+/// crosses the account's `volume_alert_threshold`
+#[derive(Deserialize, Clone, Debug)]
+pub struct VolumeAlertsConfig {
+    /// The length of the windows the volumes are counted over, in seconds.
+    /// The volumes are reset at the start of every window. Defaults to 3600 (one hour).
+    #[serde(default = "VolumeAlertsConfig::default_window")]
+    pub window: u64,
+    /// Whether packets to an account are rejected with `T04_INSUFFICIENT_LIQUIDITY`
+    /// once its threshold was crossed, until the window rolls. Defaults to false.
+    #[serde(default)]
+    pub reject: bool,
+    /// Webhook which is POSTed a signed alert every time an account's threshold is crossed.
+    /// If this configuration is not provided, the alerts are only logged.
+    #[serde(default)]
+    pub webhook: Option<WebhookConfig>,
+}
+
+impl Default for VolumeAlertsConfig {
+    fn default() -> Self {
+        VolumeAlertsConfig {
+            window: Self::default_window(),
+            reject: false,
+            webhook: None,
+        }
+    }
+}
+
+impl VolumeAlertsConfig {
+    fn default_window() -> u64 {
+        3600
+    }
+
+    /// Returns the length of the windows
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window)
+    }
+
+    /// Returns the function which delivers the alerts to the configured webhook in the background
+    pub(crate) fn alert_callback(
+        &self,
+        client: Client,
+    ) -> Option<impl Fn(VolumeAlert) + Send + Sync + 'static> {
+        let webhook = WebhookClient::new(self.webhook.clone()?, client);
+        Some(move |alert: VolumeAlert| {
+            let webhook = webhook.clone();
+            spawn(async move {
+                let description = format!("volume alert for account {}", alert.account_id);
+                webhook.deliver(&description, &alert).await
+            });
+        })
+    }
+}
@@ -0,0 +1,546 @@
+use futures::channel::mpsc::UnboundedSender;
+use interledger::{
+    errors::AccountStoreError,
+    packet::Address,
+    service::{Account, AccountStore, Username},
+    stream::{PaymentNotification, StreamNotificationsStore},
+};
+use reqwest::Client;
+use ring::hmac;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    spawn,
+    sync::{
+        mpsc::{self, error::TrySendError, Receiver, Sender},
+        Semaphore,
+    },
+    time::{delay_for, timeout},
+};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+/// Name of the header which carries the hex-encoded HMAC-SHA256 of the request body
+pub static SIGNATURE_HEADER: &str = "Interledger-Signature";
+
+/// How long a STREAM connection has to be idle before the webhook for the money
+/// received on it is sent
+const PAYMENT_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longest time the money received on a STREAM connection is added up before
+/// a webhook is sent, even if more keeps arriving on it
+const MAX_PAYMENT_AGGREGATION: Duration = Duration::from_secs(30);
+/// Number of payment notifications buffered while waiting to be added up. Further
+/// notifications are dropped (and logged) until there is room in the buffer again
+const NOTIFICATION_BUFFER_SIZE: usize = 10_000;
+/// Maximum number of webhooks which are delivered (including retries) at the same time
+const MAX_CONCURRENT_DELIVERIES: usize = 16;
+
+/// Configuration for the webhook which is notified about incoming payments
+#[derive(Deserialize, Clone, Debug)]
+pub struct WebhookConfig {
+    /// URL the payment notifications are POSTed to
+    pub url: String,
+    /// Secret used to sign the payload of every notification. Receivers should
+    /// compute the HMAC-SHA256 of the request body with this secret and compare
+    /// it with the value of the `Interledger-Signature` header.
+    pub secret: SecretString,
+    /// The number of times delivery is retried (with exponential backoff)
+    /// if the webhook URL cannot be reached or returns an error. Defaults to 4.
+    #[serde(default = "WebhookConfig::default_max_retries")]
+    pub max_retries: u32,
+}
+
+impl WebhookConfig {
+    fn default_max_retries() -> u32 {
+        4
+    }
+}
+
+/// The JSON payload POSTed to the webhook when one of the node's accounts
+/// received money over a STREAM connection
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IncomingPaymentWebhook {
+    /// The id of the account that received the payment
+    pub account_id: Uuid,
+    /// The username of the account that received the payment
+    pub username: Username,
+    /// The ILP Address of the STREAM connection the payment was sent to
+    pub destination: Address,
+    /// The total amount of the fulfilled packets, denominated in the account's asset
+    pub amount: u64,
+    /// The number of fulfilled packets the amount was received in
+    pub packets: u64,
+    pub asset_code: String,
+    pub asset_scale: u8,
+    /// The time the last packet was received in RFC3339 format
+    pub timestamp: String,
+}
+
+#[derive(Clone)]
+pub(crate) struct WebhookClient {
+    client: Client,
+    url: Arc<String>,
+    key: Arc<hmac::Key>,
+    max_retries: u32,
+    initial_backoff: Duration,
+}
+
+impl WebhookClient {
+    pub(crate) fn new(config: WebhookConfig, client: Client) -> Self {
+        WebhookClient {
+            client,
+            url: Arc::new(config.url),
+            key: Arc::new(hmac::Key::new(
+                hmac::HMAC_SHA256,
+                config.secret.expose_secret().as_bytes(),
+            )),
+            max_retries: config.max_retries,
+            initial_backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// POSTs the webhook, retrying with exponential backoff until it was
+    /// either delivered or the retries were exhausted. The description is only used for logging
+    #[allow(clippy::cognitive_complexity)]
+    pub(crate) async fn deliver<T: Serialize>(
+        &self,
+        description: &str,
+        webhook: &T,
+    ) -> Result<(), ()> {
+        let body = serde_json::to_vec(webhook).map_err(
+            |err| error!(target: "interledger-node", "Error serializing webhook: {:?}", err),
+        )?;
+        let signature = hex::encode(hmac::sign(&self.key, &body).as_ref());
+
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .client
+                .post(self.url.as_str())
+                .header("Content-Type", "application/json")
+                .header(SIGNATURE_HEADER, signature.as_str())
+                .body(body.clone())
+                .send()
+                .await;
+            match result {
+                Ok(res) if res.status().is_success() => {
+                    debug!(target: "interledger-node", "Delivered {}", description);
+                    return Ok(());
+                }
+                Ok(res) => warn!(target: "interledger-node",
+                    "Webhook URL {} responded with status {} (attempt {})",
+                    self.url,
+                    res.status(),
+                    attempt + 1
+                ),
+                Err(err) => warn!(target: "interledger-node",
+                    "Error sending webhook to {}: {:?} (attempt {})",
+                    self.url,
+                    err,
+                    attempt + 1
+                ),
+            }
+
+            if attempt >= self.max_retries {
+                error!(target: "interledger-node",
+                    "Giving up delivering {} after {} attempts",
+                    description,
+                    attempt + 1
+                );
+                return Err(());
+            }
+            delay_for(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+/// # Webhook Notifier
+///
+/// Wraps the store given to the [`StreamReceiverService`](../interledger_stream/struct.StreamReceiverService.html)
+/// so that, on top of the usual payment notifications, the configured webhook is called
+/// when one of the node's accounts received money over a STREAM connection.
+///
+/// The fulfilled packets of each connection are added up and a single webhook is sent once
+/// no more packets arrived on the connection for a second (or every 30 seconds while more
+/// keep arriving). Webhooks are delivered in the background, at most 16 at a time, so they
+/// never delay packet processing; if they cannot be delivered fast enough, the notifications
+/// are dropped and logged.
+/// If no webhook is configured, this simply forwards everything to the wrapped store.
+#[derive(Clone)]
+pub struct WebhookNotifier<S> {
+    store: S,
+    packets: Option<Sender<ReceivedPacket>>,
+}
+
+impl<S, A> WebhookNotifier<S>
+where
+    S: AccountStore<Account = A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    /// Wraps the store, sending the webhooks (if configured) with the given HTTP client
+    pub fn new(store: S, config: Option<WebhookConfig>, client: Client) -> Self {
+        let webhook = config.map(|config| WebhookClient::new(config, client));
+        Self::with_idle_timeout(store, webhook, PAYMENT_IDLE_TIMEOUT)
+    }
+
+    fn with_idle_timeout(store: S, webhook: Option<WebhookClient>, idle_timeout: Duration) -> Self {
+        let packets = webhook.map(|webhook| {
+            let (sender, receiver) = mpsc::channel(NOTIFICATION_BUFFER_SIZE);
+            spawn(send_payment_webhooks(
+                store.clone(),
+                webhook,
+                receiver,
+                idle_timeout,
+            ));
+            sender
+        });
+        WebhookNotifier { store, packets }
+    }
+}
+
+impl<S, A> StreamNotificationsStore for WebhookNotifier<S>
+where
+    S: StreamNotificationsStore<Account = A>
+        + AccountStore<Account = A>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    type Account = A;
+
+    fn add_payment_notification_subscription(
+        &self,
+        account_id: Uuid,
+        sender: UnboundedSender<PaymentNotification>,
+    ) {
+        self.store
+            .add_payment_notification_subscription(account_id, sender)
+    }
+
+    fn publish_payment_notification(&self, payment: PaymentNotification) {
+        if let Some(mut packets) = self.packets.clone() {
+            let packet = ReceivedPacket {
+                username: payment.to_username.clone(),
+                destination: payment.destination.clone(),
+                amount: payment.amount,
+                timestamp: payment.timestamp.clone(),
+            };
+            if let Err(TrySendError::Full(packet)) = packets.try_send(packet) {
+                warn!(target: "interledger-node",
+                    "Too many incoming payment webhooks are pending, dropping the notification of {} received by {}",
+                    packet.amount, packet.username
+                );
+            }
+        }
+        self.store.publish_payment_notification(payment)
+    }
+}
+
+/// A fulfilled packet which the webhooks are sent for
+struct ReceivedPacket {
+    username: Username,
+    destination: Address,
+    amount: u64,
+    timestamp: String,
+}
+
+/// The money received on a STREAM connection since the last webhook was sent for it
+struct PendingPayment {
+    username: Username,
+    amount: u64,
+    packets: u64,
+    timestamp: String,
+    first_received: Instant,
+    last_received: Instant,
+}
+
+/// Adds up the packets received on each STREAM connection and sends a webhook for each of them
+/// once the connection was idle for the given time, until the notifier is dropped
+async fn send_payment_webhooks<S, A>(
+    store: S,
+    webhook: WebhookClient,
+    mut packets: Receiver<ReceivedPacket>,
+    idle_timeout: Duration,
+) where
+    S: AccountStore<Account = A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    let deliveries = Arc::new(Semaphore::new(MAX_CONCURRENT_DELIVERIES));
+    let mut pending: HashMap<Address, PendingPayment> = HashMap::new();
+    let mut next_check = Instant::now() + idle_timeout;
+    loop {
+        let closed = match timeout(idle_timeout, packets.recv()).await {
+            Ok(Some(packet)) => {
+                let now = Instant::now();
+                let username = packet.username;
+                let entry = pending
+                    .entry(packet.destination)
+                    .or_insert_with(|| PendingPayment {
+                        username,
+                        amount: 0,
+                        packets: 0,
+                        timestamp: String::new(),
+                        first_received: now,
+                        last_received: now,
+                    });
+                entry.amount = entry.amount.saturating_add(packet.amount);
+                entry.packets += 1;
+                entry.timestamp = packet.timestamp;
+                entry.last_received = now;
+                false
+            }
+            Ok(None) => true,
+            Err(_) => false,
+        };
+        if !closed && Instant::now() < next_check {
+            continue;
+        }
+        next_check = Instant::now() + idle_timeout;
+
+        let finished: Vec<_> = pending
+            .iter()
+            .filter(|(_, payment)| {
+                closed
+                    || payment.last_received.elapsed() >= idle_timeout
+                    || payment.first_received.elapsed() >= MAX_PAYMENT_AGGREGATION
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in finished {
+            let payment = pending.remove(&key).unwrap();
+            // Wait for a delivery to finish if too many are in progress. In the meantime
+            // new notifications are buffered (or dropped once the buffer is full)
+            deliveries.acquire().await.forget();
+            spawn(deliver_payment_webhook(
+                store.clone(),
+                webhook.clone(),
+                deliveries.clone(),
+                key,
+                payment,
+            ));
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+async fn deliver_payment_webhook<S, A>(
+    store: S,
+    webhook: WebhookClient,
+    deliveries: Arc<Semaphore>,
+    destination: Address,
+    payment: PendingPayment,
+) where
+    S: AccountStore<Account = A>,
+    A: Account,
+{
+    let username = payment.username;
+    match get_account(&store, &username).await {
+        Ok(account) => {
+            let description = format!("incoming payment webhook for account {}", account.id());
+            let _ = webhook
+                .deliver(
+                    &description,
+                    &IncomingPaymentWebhook {
+                        account_id: account.id(),
+                        username,
+                        destination,
+                        amount: payment.amount,
+                        packets: payment.packets,
+                        asset_code: account.asset_code().to_string(),
+                        asset_scale: account.asset_scale(),
+                        timestamp: payment.timestamp,
+                    },
+                )
+                .await;
+        }
+        Err(err) => error!(target: "interledger-node",
+            "Error loading account {} for incoming payment webhook: {:?}",
+            username, err
+        ),
+    }
+    deliveries.add_permits(1);
+}
+
+async fn get_account<S, A>(store: &S, username: &Username) -> Result<A, AccountStoreError>
+where
+    S: AccountStore<Account = A>,
+    A: Account,
+{
+    let id = store.get_account_id_from_username(username).await?;
+    let mut accounts = store.get_accounts(vec![id]).await?;
+    accounts
+        .pop()
+        .ok_or_else(|| AccountStoreError::AccountNotFound(id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use mockito::{mock, Matcher};
+    use once_cell::sync::Lazy;
+    use std::{str::FromStr, sync::Mutex};
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static ALICE_ID: Lazy<Uuid> = Lazy::new(Uuid::new_v4);
+    static ALICE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.node.alice").unwrap());
+
+    #[tokio::test]
+    async fn fulfilled_packets_send_one_signed_webhook_per_connection() {
+        let expected = IncomingPaymentWebhook {
+            account_id: *ALICE_ID,
+            username: ALICE.clone(),
+            destination: Address::from_str("example.node.alice.1234").unwrap(),
+            amount: 300,
+            packets: 2,
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            timestamp: "2020-02-20T12:00:00+00:00".to_string(),
+        };
+        let body = serde_json::to_vec(&expected).unwrap();
+        let signature =
+            hex::encode(hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, b"secret"), &body).as_ref());
+        let m = mock("POST", "/webhook")
+            .match_header(SIGNATURE_HEADER, signature.as_str())
+            .match_body(Matcher::Json(serde_json::to_value(&expected).unwrap()))
+            .with_status(200)
+            .expect(1)
+            .create();
+
+        let store = TestStore::default();
+        let notifier = WebhookNotifier::with_idle_timeout(
+            store.clone(),
+            Some(WebhookClient::new(test_config(2), Client::new())),
+            Duration::from_millis(100),
+        );
+        for (amount, timestamp) in &[
+            (100, "2020-02-20T11:59:59+00:00"),
+            (200, "2020-02-20T12:00:00+00:00"),
+        ] {
+            notifier.publish_payment_notification(PaymentNotification {
+                to_username: ALICE.clone(),
+                from_username: Username::from_str("bob").unwrap(),
+                destination: expected.destination.clone(),
+                amount: *amount,
+                timestamp: timestamp.to_string(),
+            });
+        }
+
+        // The notifications are still published to the wrapped store right away
+        assert_eq!(*store.published.lock().unwrap(), 2);
+        delay_for(Duration::from_millis(500)).await;
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn retries_failed_deliveries() {
+        let m = mock("POST", "/webhook").with_status(500).expect(3).create();
+
+        let mut client = WebhookClient::new(test_config(2), Client::new());
+        client.initial_backoff = Duration::from_millis(10);
+        let result = client
+            .deliver(
+                "incoming payment webhook",
+                &IncomingPaymentWebhook {
+                    account_id: *ALICE_ID,
+                    username: ALICE.clone(),
+                    destination: ALICE_ADDRESS.clone(),
+                    amount: 100,
+                    packets: 1,
+                    asset_code: "XYZ".to_string(),
+                    asset_scale: 9,
+                    timestamp: "2020-02-20T12:00:00+00:00".to_string(),
+                },
+            )
+            .await;
+
+        assert!(result.is_err());
+        m.assert();
+    }
+
+    fn test_config(max_retries: u32) -> WebhookConfig {
+        WebhookConfig {
+            url: format!("{}/webhook", mockito::server_url()),
+            secret: SecretString::new("secret".to_string()),
+            max_retries,
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            *ALICE_ID
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &ALICE_ADDRESS
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct TestStore {
+        published: Arc<Mutex<usize>>,
+    }
+
+    impl StreamNotificationsStore for TestStore {
+        type Account = TestAccount;
+
+        fn add_payment_notification_subscription(
+            &self,
+            _account_id: Uuid,
+            _sender: UnboundedSender<PaymentNotification>,
+        ) {
+            unimplemented!()
+        }
+
+        fn publish_payment_notification(&self, _payment: PaymentNotification) {
+            *self.published.lock().unwrap() += 1;
+        }
+    }
+
+    #[async_trait]
+    impl AccountStore for TestStore {
+        type Account = TestAccount;
+
+        async fn get_accounts(
+            &self,
+            _account_ids: Vec<Uuid>,
+        ) -> Result<Vec<TestAccount>, AccountStoreError> {
+            Ok(vec![TestAccount])
+        }
+
+        async fn get_account_id_from_username(
+            &self,
+            _username: &Username,
+        ) -> Result<Uuid, AccountStoreError> {
+            Ok(*ALICE_ID)
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use crate::redis_helpers::*;
+use crate::test_helpers::*;
+use ilp_node::InterledgerNode;
+use reqwest::{Client, StatusCode};
+use serde_json::json;
+use std::{env, path::Path};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+/// Whether the response shows that the address does not serve the endpoint at all
+fn is_not_routed(status: StatusCode) -> bool {
+    status == StatusCode::NOT_FOUND || status == StatusCode::METHOD_NOT_ALLOWED
+}
+
+#[tokio::test]
+async fn serves_admin_api_on_separate_address() {
+    let http_port = get_open_port(None);
+    let admin_port = get_open_port(None);
+    let settlement_port = get_open_port(None);
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.node",
+        "admin_auth_token": "admin",
+        "database_url": "memory://",
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "admin_bind_address": format!("127.0.0.1:{}", admin_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", settlement_port),
+        "secret_seed": random_secret(),
+    }))
+    .unwrap();
+    node.serve().await.unwrap();
+
+    // the admin API is only reachable on the admin address
+    let alice = json!({
+        "username": "alice",
+        "asset_code": "XYZ",
+        "asset_scale": 9,
+        "ilp_over_http_incoming_token" : "token",
+    });
+    assert!(create_account_on_node(http_port, alice.clone(), "admin")
+        .await
+        .is_err());
+    create_account_on_node(admin_port, alice, "admin")
+        .await
+        .unwrap();
+
+    let client = Client::new();
+    let get_accounts = |port: u16| {
+        client
+            .get(&format!("http://localhost:{}/accounts", port))
+            .header("Authorization", "Bearer admin")
+            .send()
+    };
+    assert_eq!(
+        get_accounts(admin_port).await.unwrap().status(),
+        StatusCode::OK
+    );
+    assert!(is_not_routed(
+        get_accounts(http_port).await.unwrap().status()
+    ));
+
+    // while ILP over HTTP packets are only accepted on the HTTP address
+    let send_packet = |port: u16| {
+        client
+            .post(&format!("http://localhost:{}/accounts/alice/ilp", port))
+            .header("Authorization", "Bearer token")
+            .body(vec![0u8; 10])
+            .send()
+    };
+    assert!(!is_not_routed(
+        send_packet(http_port).await.unwrap().status()
+    ));
+    assert!(is_not_routed(
+        send_packet(admin_port).await.unwrap().status()
+    ));
+
+    // and the public SPSP endpoints are served with the packet endpoints
+    let get_spsp = |port: u16| {
+        client
+            .get(&format!("http://localhost:{}/accounts/alice/spsp", port))
+            .send()
+    };
+    assert_eq!(get_spsp(http_port).await.unwrap().status(), StatusCode::OK);
+    assert!(is_not_routed(get_spsp(admin_port).await.unwrap().status()));
+}
+
+/// Sends a plain HTTP/1.1 request over the unix socket and returns the raw response
+async fn request_over_unix_socket(path: &Path, request: &str) -> String {
+    let mut stream = UnixStream::connect(path).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn serves_admin_api_on_unix_socket() {
+    let http_port = get_open_port(None);
+    let settlement_port = get_open_port(None);
+    let socket = env::temp_dir().join(format!("ilp-node-admin-{}.sock", http_port));
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.node",
+        "admin_auth_token": "admin",
+        "database_url": "memory://",
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "admin_bind_address": format!("unix:{}", socket.display()),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", settlement_port),
+        "secret_seed": random_secret(),
+    }))
+    .unwrap();
+    node.serve().await.unwrap();
+
+    let body = json!({
+        "username": "alice",
+        "asset_code": "XYZ",
+        "asset_scale": 9,
+    })
+    .to_string();
+    let response = request_over_unix_socket(
+        &socket,
+        &format!(
+            "POST /accounts HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer admin\r\n\
+            Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        ),
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    let response = request_over_unix_socket(
+        &socket,
+        "GET /accounts HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer admin\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("\"username\":\"alice\""));
+
+    // the API is not served on the TCP address
+    let status = Client::new()
+        .get(&format!("http://localhost:{}/accounts", http_port))
+        .header("Authorization", "Bearer admin")
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert!(is_not_routed(status));
+}
+
+#[tokio::test]
+async fn accepts_decimal_amounts() {
+    let http_port = get_open_port(None);
+    let settlement_port = get_open_port(None);
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.node",
+        "admin_auth_token": "admin",
+        "database_url": "memory://",
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", settlement_port),
+        "secret_seed": random_secret(),
+    }))
+    .unwrap();
+    node.serve().await.unwrap();
+
+    let client = Client::new();
+    let response = client
+        .post(&format!("http://localhost:{}/accounts", http_port))
+        .header("Authorization", "Bearer admin")
+        .json(&json!({
+            "username": "alice",
+            "asset_code": "USD",
+            "asset_scale": 2,
+            "ilp_over_http_incoming_token": "token",
+            "max_packet_amount": 1000,
+            "min_balance": "-10.00",
+            "settle_threshold": "1.5",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let account: serde_json::Value = response.json().await.unwrap();
+    // Amounts are stored in the base unit, and returned in both forms
+    assert_eq!(account["max_packet_amount"], 1000);
+    assert_eq!(account["max_packet_amount_decimal"], "10.00");
+    assert_eq!(account["min_balance"], -1000);
+    assert_eq!(account["min_balance_decimal"], "-10.00");
+    assert_eq!(account["settle_threshold"], 150);
+    assert_eq!(account["settle_threshold_decimal"], "1.50");
+
+    let put_settings = |settings: serde_json::Value| {
+        client
+            .put(&format!(
+                "http://localhost:{}/accounts/alice/settings",
+                http_port
+            ))
+            .header("Authorization", "Bearer token")
+            .json(&settings)
+            .send()
+    };
+    let response = put_settings(json!({ "settle_to": "0.25" })).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let account: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(account["settle_to"], 25);
+    assert_eq!(account["settle_to_decimal"], "0.25");
+
+    let response = put_settings(json!({ "settle_to": "0.255" })).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn reports_the_source_of_routes() {
+    // Node A peers with node B, which advertises its route to dave via CCP
+    let node_a_http = get_open_port(None);
+    let node_b_http = get_open_port(None);
+    let node = |ilp_address: &str, http_port: u16| -> InterledgerNode {
+        serde_json::from_value(json!({
+            "ilp_address": ilp_address,
+            "admin_auth_token": "admin",
+            "database_url": "memory://",
+            "http_bind_address": format!("127.0.0.1:{}", http_port),
+            "settlement_api_bind_address": format!("127.0.0.1:{}", get_open_port(None)),
+            "secret_seed": random_secret(),
+            "route_broadcast_interval": 200,
+        }))
+        .unwrap()
+    };
+    node("example.a", node_a_http).serve().await.unwrap();
+    node("example.b", node_b_http).serve().await.unwrap();
+
+    let peer = |username: &str, address: &str, port: u16, peer_username: &str| {
+        json!({
+            "username": username,
+            "ilp_address": address,
+            "asset_code": "XYZ",
+            "asset_scale": 9,
+            "ilp_over_http_url": format!("http://localhost:{}/accounts/{}/ilp", port, peer_username),
+            "ilp_over_http_incoming_token": "token",
+            "ilp_over_http_outgoing_token": "token",
+            "routing_relation": "Peer",
+        })
+    };
+    create_account_on_node(
+        node_a_http,
+        peer("b", "example.b", node_b_http, "a"),
+        "admin",
+    )
+    .await
+    .unwrap();
+    create_account_on_node(
+        node_a_http,
+        json!({
+            "username": "carol",
+            "ilp_address": "example.a.carol",
+            "asset_code": "XYZ",
+            "asset_scale": 9,
+        }),
+        "admin",
+    )
+    .await
+    .unwrap();
+    create_account_on_node(
+        node_b_http,
+        peer("a", "example.a", node_a_http, "b"),
+        "admin",
+    )
+    .await
+    .unwrap();
+    create_account_on_node(
+        node_b_http,
+        json!({
+            "username": "dave",
+            "ilp_address": "example.dave",
+            "asset_code": "XYZ",
+            "asset_scale": 9,
+        }),
+        "admin",
+    )
+    .await
+    .unwrap();
+
+    let client = Client::new();
+    let response = client
+        .put(&format!(
+            "http://localhost:{}/routes/static/example.static",
+            node_a_http
+        ))
+        .header("Authorization", "Bearer admin")
+        .body("carol")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Wait for the route broadcasts
+    delay(1000).await;
+
+    let get_route_table = |query: &str| {
+        client
+            .get(&format!(
+                "http://localhost:{}/routes/table{}",
+                node_a_http, query
+            ))
+            .header("Authorization", "Bearer admin")
+            .send()
+    };
+    let response = get_route_table("").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let routes: Vec<serde_json::Value> = response.json().await.unwrap();
+    let route = |prefix: &str| {
+        routes
+            .iter()
+            .find(|route| route["prefix"] == prefix)
+            .cloned()
+            .unwrap_or_else(|| panic!("No route for {} in {:?}", prefix, routes))
+    };
+    assert_eq!(route("example.a.carol")["next_hop"], "carol");
+    assert_eq!(route("example.a.carol")["source"], "local");
+    assert_eq!(route("example.b")["next_hop"], "b");
+    assert_eq!(route("example.b")["source"], "local");
+    assert_eq!(route("example.static")["next_hop"], "carol");
+    assert_eq!(route("example.static")["source"], "configured");
+    assert_eq!(route("example.dave")["next_hop"], "b");
+    assert_eq!(route("example.dave")["source"], "ccp");
+
+    let routes: Vec<serde_json::Value> = get_route_table("?prefix=example.d")
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(routes.len(), 1);
+    assert_eq!(routes[0]["prefix"], "example.dave");
+}
+
+#[tokio::test]
+async fn returns_display_metadata_in_spsp_responses() {
+    let http_port = get_open_port(None);
+    let settlement_port = get_open_port(None);
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.node",
+        "admin_auth_token": "admin",
+        "database_url": "memory://",
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", settlement_port),
+        "secret_seed": random_secret(),
+    }))
+    .unwrap();
+    node.serve().await.unwrap();
+
+    let account = |username: &str| {
+        json!({
+            "username": username,
+            "asset_code": "USD",
+            "asset_scale": 9,
+        })
+    };
+    let mut alice = account("alice");
+    alice["spsp_display_symbol"] = json!("$");
+    alice["spsp_display_decimals"] = json!(2);
+    create_account_on_node(http_port, alice, "admin")
+        .await
+        .unwrap();
+    create_account_on_node(http_port, account("bob"), "admin")
+        .await
+        .unwrap();
+
+    let get_spsp = |username: &str| {
+        Client::new()
+            .get(&format!(
+                "http://localhost:{}/accounts/{}/spsp",
+                http_port, username
+            ))
+            .send()
+    };
+    let response: serde_json::Value = get_spsp("alice").await.unwrap().json().await.unwrap();
+    assert_eq!(response["display"], json!({ "symbol": "$", "decimals": 2 }));
+    assert!(response["destination_account"]
+        .as_str()
+        .unwrap()
+        .starts_with("example.node.alice."));
+
+    let response: serde_json::Value = get_spsp("bob").await.unwrap().json().await.unwrap();
+    assert!(response.get("display").is_none());
+    assert!(response.get("shared_secret").is_some());
+}
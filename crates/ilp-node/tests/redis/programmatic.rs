@@ -0,0 +1,68 @@
+use crate::redis_helpers::*;
+use crate::test_helpers::*;
+use ilp_node::InterledgerNode;
+use interledger::packet::Address;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+fn random_seed() -> [u8; 32] {
+    let mut seed = [0; 32];
+    SystemRandom::new().fill(&mut seed).unwrap();
+    seed
+}
+
+#[tokio::test]
+async fn serves_node_constructed_without_configuration() {
+    let http_port = get_open_port(None);
+    let settlement_port = get_open_port(None);
+    let node = InterledgerNode {
+        ilp_address: Some(Address::from_str("example.node").unwrap()),
+        database_url: "memory://".to_string(),
+        http_bind_address: SocketAddr::from(([127, 0, 0, 1], http_port)).into(),
+        settlement_api_bind_address: SocketAddr::from(([127, 0, 0, 1], settlement_port)),
+        route_broadcast_interval: Some(200),
+        ..InterledgerNode::new(random_seed(), "admin".to_string())
+    };
+    node.serve().await.unwrap();
+
+    for username in &["alice", "bob"] {
+        create_account_on_node(
+            http_port,
+            json!({
+                "username": username,
+                "asset_code": "XYZ",
+                "asset_scale": 9,
+                "ilp_over_http_incoming_token": "token",
+            }),
+            "admin",
+        )
+        .await
+        .unwrap();
+    }
+    let accounts = get_all_accounts(http_port, "admin").await.unwrap();
+    assert_eq!(accounts.len(), 2);
+
+    let receipt = send_money_to_username(http_port, http_port, 1000, "bob", "alice", "token")
+        .await
+        .unwrap();
+    assert_eq!(receipt.delivered_amount, 1000);
+}
+
+#[tokio::test]
+async fn rejects_requests_without_configured_admin_token() {
+    let http_port = get_open_port(None);
+    let settlement_port = get_open_port(None);
+    let mut node = InterledgerNode::new(random_seed(), "admin".to_string());
+    node.database_url = "memory://".to_string();
+    node.http_bind_address = SocketAddr::from(([127, 0, 0, 1], http_port)).into();
+    node.settlement_api_bind_address = SocketAddr::from(([127, 0, 0, 1], settlement_port));
+    node.serve().await.unwrap();
+
+    assert!(get_all_accounts(http_port, "other").await.is_err());
+    assert!(get_all_accounts(http_port, "admin")
+        .await
+        .unwrap()
+        .is_empty());
+}
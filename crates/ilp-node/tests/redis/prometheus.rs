@@ -136,6 +136,7 @@ async fn prometheus() {
     assert!(ret.contains("requests_outgoing_prepare"));
     assert!(ret.contains("requests_outgoing_reject"));
     assert!(ret.contains("requests_outgoing_duration"));
+    assert!(ret.contains("requests_outgoing_latency{to_username=\"node_b\""));
 
     send_money_to_username(
         node_b_http,
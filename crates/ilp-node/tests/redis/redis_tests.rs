@@ -1,6 +1,9 @@
-#![type_length_limit = "10000000"]
+#![type_length_limit = "20000000"]
+mod admin_api;
 mod btp;
 mod exchange_rates;
+mod programmatic;
+mod tenants;
 mod three_nodes;
 
 // Only run prometheus tests if the monitoring feature is turned on
@@ -0,0 +1,113 @@
+use crate::redis_helpers::*;
+use crate::test_helpers::*;
+use bytes::BytesMut;
+use ilp_node::InterledgerNode;
+use interledger::{
+    ildcp::{IldcpRequest, IldcpResponse},
+    packet::Packet,
+};
+use reqwest::{Client, StatusCode};
+use serde_json::json;
+use std::convert::TryFrom;
+
+#[tokio::test]
+async fn isolates_tenants() {
+    let node_port = get_open_port(None);
+    let tenant_port = get_open_port(None);
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.node",
+        "admin_auth_token": "admin",
+        "database_url": "memory://",
+        "http_bind_address": format!("127.0.0.1:{}", node_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", get_open_port(None)),
+        "secret_seed": random_secret(),
+        "tenants": [{
+            "ilp_address": "example.tenant",
+            "admin_auth_token": "tenant_admin",
+            "database_url": "memory://",
+            "http_bind_address": format!("127.0.0.1:{}", tenant_port),
+            "settlement_api_bind_address": format!("127.0.0.1:{}", get_open_port(None)),
+        }],
+    }))
+    .unwrap();
+    node.serve().await.unwrap();
+
+    let child = |token: &str| {
+        json!({
+            "username": "alice",
+            "asset_code": "XYZ",
+            "asset_scale": 9,
+            "ilp_over_http_incoming_token": token,
+            "routing_relation": "Child",
+        })
+    };
+    // each tenant is administered with its own token
+    assert!(
+        create_account_on_node(tenant_port, child("tenant_token"), "admin")
+            .await
+            .is_err()
+    );
+    create_account_on_node(node_port, child("node_token"), "admin")
+        .await
+        .unwrap();
+    create_account_on_node(tenant_port, child("tenant_token"), "tenant_admin")
+        .await
+        .unwrap();
+    let bob = json!({
+        "username": "bob",
+        "asset_code": "XYZ",
+        "asset_scale": 9,
+    });
+    create_account_on_node(node_port, bob, "admin")
+        .await
+        .unwrap();
+    assert_eq!(get_all_accounts(node_port, "admin").await.unwrap().len(), 2);
+    assert_eq!(
+        get_all_accounts(tenant_port, "tenant_admin")
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // Asks the node on the given port for the address of its child account
+    let client = Client::new();
+    let get_child_address = |port: u16, token: &str| {
+        let prepare: bytes05::BytesMut = IldcpRequest::new().to_prepare().into();
+        client
+            .post(&format!("http://localhost:{}/accounts/alice/ilp", port))
+            .header("Authorization", format!("Bearer {}", token))
+            .body(prepare.to_vec())
+            .send()
+    };
+    let parse_address =
+        |body: bytes05::Bytes| match Packet::try_from(bytes05::BytesMut::from(body.as_ref()))
+            .unwrap()
+        {
+            Packet::Fulfill(fulfill) => {
+                IldcpResponse::try_from(BytesMut::from(fulfill.data()).freeze())
+                    .unwrap()
+                    .ilp_address()
+                    .to_string()
+            }
+            _ => panic!("Expected the IL-DCP request to be fulfilled"),
+        };
+
+    // packets are handled by the pipeline of the tenant they were sent to
+    let response = get_child_address(node_port, "node_token").await.unwrap();
+    assert_eq!(
+        parse_address(response.bytes().await.unwrap()),
+        "example.node.alice"
+    );
+    let response = get_child_address(tenant_port, "tenant_token")
+        .await
+        .unwrap();
+    assert_eq!(
+        parse_address(response.bytes().await.unwrap()),
+        "example.tenant.alice"
+    );
+
+    // and are not authenticated with the accounts of other tenants
+    let response = get_child_address(tenant_port, "node_token").await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 use bytes::Bytes;
-use interledger_btp::{BtpAccount, BtpOutgoingService};
+use interledger_btp::{BtpAccount, BtpConnections, BtpOutgoingService};
 use interledger_ccp::CcpRoutingAccount;
 use interledger_errors::NodeStoreError;
 use interledger_http::{HttpAccount, HttpStore};
@@ -10,18 +10,34 @@ use interledger_router::RouterStore;
 use interledger_service::{
     Account, AccountStore, AddressStore, IncomingService, OutgoingService, Username,
 };
-use interledger_service_util::BalanceStore;
-use interledger_settlement::core::types::{SettlementAccount, SettlementStore};
-use interledger_stream::StreamNotificationsStore;
+use interledger_service_util::{
+    BalancePause, BalanceStore, MaintenanceMode, PacketEvent, PeerActivity, RoundingPolicy,
+    TrafficStore,
+};
+use interledger_settlement::core::{
+    idempotency::IdempotentStore,
+    types::{SettlementAccount, SettlementStore},
+    SettlementClient,
+};
+use interledger_spsp::SpspDisplayAccount;
+use interledger_stream::{StreamNotificationsStore, DEFAULT_PACKET_EXPIRY};
 use secrecy::SecretString;
 use serde::{de, Deserialize, Serialize};
-use std::{boxed::*, collections::HashMap, fmt::Display, net::SocketAddr, str::FromStr};
+use std::{
+    boxed::*, collections::HashMap, fmt::Display, net::SocketAddr, ops::RangeInclusive,
+    str::FromStr, time::Duration,
+};
+use tokio::sync::broadcast;
 use url::Url;
 use uuid::Uuid;
 use warp::{self, Filter};
 
 mod routes;
 
+/// The largest asset scale of the accounts created via the API, unless configured otherwise.
+/// Amounts with larger scales easily overflow when they are converted to other assets.
+pub const DEFAULT_MAX_ASSET_SCALE: u8 = 18;
+
 // This enum and the following functions are used to allow clients to send either
 // numbers or strings and have them be properly deserialized into the appropriate
 // integer type.
@@ -50,11 +66,27 @@ where
     T: FromStr + Deserialize<'de>,
     <T as FromStr>::Err: Display,
 {
-    match NumOrStr::deserialize(deserializer)? {
-        NumOrStr::Num(n) => Ok(Some(n)),
-        NumOrStr::Str(s) => T::from_str(&s)
+    // `null` is accepted as well, which is how unset values are serialized (e.g. in exports)
+    match Option::<NumOrStr<T>>::deserialize(deserializer)? {
+        Some(NumOrStr::Num(n)) => Ok(Some(n)),
+        Some(NumOrStr::Str(s)) => T::from_str(&s)
             .map_err(de::Error::custom)
             .and_then(|n| Ok(Some(n))),
+        None => Ok(None),
+    }
+}
+
+/// Deserializes an optional spread, which must be at least 0 and less than 1
+pub fn optional_spread<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    match optional_number_or_string::<D, f64>(deserializer)? {
+        Some(spread) if !(0.0..1.0).contains(&spread) => Err(de::Error::custom(format!(
+            "spread must be at least 0 and less than 1: {}",
+            spread
+        ))),
+        spread => Ok(spread),
     }
 }
 
@@ -84,6 +116,20 @@ pub trait NodeStore: Clone + Send + Sync + 'static {
         account: AccountDetails,
     ) -> Result<Self::Account, NodeStoreError>;
 
+    /// Inserts the accounts to the store and returns the result of each insertion, in the
+    /// same order. The accounts which can not be inserted do not prevent the others from
+    /// being inserted. Stores should override this to insert them in fewer round trips.
+    async fn insert_accounts(
+        &self,
+        accounts: Vec<AccountDetails>,
+    ) -> Result<Vec<Result<Self::Account, NodeStoreError>>, NodeStoreError> {
+        let mut results = Vec::with_capacity(accounts.len());
+        for account in accounts {
+            results.push(self.insert_account(account).await);
+        }
+        Ok(results)
+    }
+
     /// Deletes the account corresponding to the provided id and returns it
     async fn delete_account(&self, id: Uuid) -> Result<Self::Account, NodeStoreError>;
 
@@ -122,10 +168,22 @@ pub trait NodeStore: Clone + Send + Sync + 'static {
         account_id: Uuid,
     ) -> Result<(), NodeStoreError>;
 
+    /// Gets the static routes (prefix -> account id), which take precedence
+    /// over the routes learned via CCP
+    async fn get_static_routes(&self) -> Result<HashMap<String, Uuid>, NodeStoreError>;
+
     /// Sets the default route ("") to be the provided account id
     /// (acts as a catch-all route if all other routes don't match)
     async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError>;
 
+    /// Starts draining the account corresponding to the provided id (or stops draining it),
+    /// so that no new packets are routed through it while the ones in flight complete
+    async fn set_account_draining(&self, id: Uuid, draining: bool) -> Result<(), NodeStoreError>;
+
+    /// Enables or disables the account corresponding to the provided id. Disabled accounts
+    /// cannot authenticate, packets from them are rejected and none are routed through them.
+    async fn set_account_enabled(&self, id: Uuid, enabled: bool) -> Result<(), NodeStoreError>;
+
     /// Sets the default settlement engines to be used for the provided asset codes
     async fn set_settlement_engines(
         &self,
@@ -139,6 +197,20 @@ pub trait NodeStore: Clone + Send + Sync + 'static {
         &self,
         asset_code: &str,
     ) -> Result<Option<Url>, NodeStoreError>;
+
+    /// Checks that the database backing the store can be reached, for the
+    /// readiness check of the API. Stores without a database are always healthy.
+    async fn check_health(&self) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    /// Exports all accounts with their balances, the static routes and the default route,
+    /// so that they can be imported into another store with `import_node`
+    async fn export_node(&self) -> Result<NodeExport, NodeStoreError>;
+
+    /// Recreates the exported accounts (with their ids and balances) and routes, and
+    /// returns the accounts. Nothing is imported if any of the accounts already exist
+    async fn import_node(&self, export: NodeExport) -> Result<Vec<Self::Account>, NodeStoreError>;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -214,6 +286,10 @@ pub struct AccountDetails {
     #[serde(default = "u64::max_value", deserialize_with = "number_or_string")]
     /// The max amount per packet which can be routed for this account
     pub max_packet_amount: u64,
+    /// The smallest amount of the packets this account may send. Packets below it are
+    /// rejected with `F00: Bad Request`. Defaults to no minimum
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub min_packet_amount: Option<u64>,
     /// The minimum balance this account can have (consider this as a credit/trust limit)
     #[serde(default, deserialize_with = "optional_number_or_string")]
     pub min_balance: Option<i64>,
@@ -229,6 +305,17 @@ pub struct AccountDetails {
     /// This must match the ILP over HTTP incoming token on the peer's node if sending
     /// packets to that peer
     pub ilp_over_http_outgoing_token: Option<SecretString>,
+    /// The SHA-256 fingerprints (hex-encoded) of the TLS client certificates the peer
+    /// may authenticate ILP over HTTP requests with instead of the incoming token.
+    /// If any are configured, requests without one of these certificates are rejected
+    #[serde(default)]
+    pub ilp_over_http_client_certificates: Vec<String>,
+    /// The name of the header in which ILP over HTTP requests to this account carry the
+    /// hex-encoded HMAC-SHA256 of the packet, if the peer requires them to be signed
+    pub ilp_over_http_signature_header: Option<String>,
+    /// The secret shared with the peer that ILP over HTTP requests are signed with.
+    /// Must be set together with `ilp_over_http_signature_header`
+    pub ilp_over_http_signature_secret: Option<SecretString>,
     /// The account's ILP over BTP URL (this is where packets are sent over WebSockets from your node)
     pub ilp_over_btp_url: Option<String>,
     /// The account's outgoing ILP over BTP token.
@@ -257,10 +344,113 @@ pub struct AccountDetails {
     /// The limit of packets the account can send per minute
     #[serde(default, deserialize_with = "optional_number_or_string")]
     pub packets_per_minute_limit: Option<u32>,
+    /// The spread charged on packets received from this account, e.g. 0.01 for 1%.
+    /// Must be at least 0 and less than 1. If not set, the node's global `exchange_rate.spread` is used
+    #[serde(default, deserialize_with = "optional_spread")]
+    pub spread: Option<f64>,
+    /// How the amounts of packets received from this account are rounded if they cannot be
+    /// converted exactly: `floor`, `ceil`, `round_half_up`, `reject` or `carry`.
+    /// If not set, the node's global `exchange_rate.rounding` is used
+    #[serde(default)]
+    pub rounding: Option<RoundingPolicy>,
+    /// The share of the payments sent through this account when several accounts
+    /// advertise equally good routes to a prefix. Defaults to 1
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub routing_weight: Option<u32>,
+    /// The priority of the routes advertised by this account. If several accounts advertise
+    /// a route to the same prefix, the one with the highest priority is used. Defaults to 0
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub route_priority: Option<u32>,
+    /// The amount which may be sent to this account within each volume alert window
+    /// before an alert is raised. Defaults to no threshold
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub volume_alert_threshold: Option<u64>,
+    /// The currency symbol, e.g. `$`, returned to SPSP clients to display the amounts
+    /// received by this account. Not returned if not set
+    #[serde(default)]
+    pub spsp_display_symbol: Option<String>,
+    /// The number of decimals returned to SPSP clients to display the amounts received
+    /// by this account. Not returned if not set
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub spsp_display_decimals: Option<u8>,
+    /// The prefix which the source addresses claimed in STREAM packets received from this
+    /// account must be within, e.g. the peer's subtree. Defaults to allowing any address
+    #[serde(default)]
+    pub allowed_source_prefix: Option<Address>,
+    /// The prefixes which the destinations of the packets sent by this account must be
+    /// within, e.g. the merchants a prepaid card may pay. Defaults to allowing any destination
+    #[serde(default)]
+    pub allowed_destination_prefixes: Vec<Address>,
+    /// The maximum number of requests sent to this account which are in flight at the same
+    /// time (at least 1). Further requests are queued until earlier ones complete. Defaults to no limit
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub max_concurrent_outgoing_requests: Option<u32>,
+    /// The username of an account which is sent a copy of every packet sent to this one,
+    /// e.g. a new peer whose behavior is validated before traffic is cut over to it. Its
+    /// responses are only logged and never affect the packets. Defaults to none
+    #[serde(default)]
+    pub shadow_account: Option<Username>,
     /// The account's settlement engine URL. If a global engine url is configured
     /// for the account's asset code,  that will be used instead (even if the account is
     /// configured with a specific one)
     pub settlement_engine_url: Option<String>,
+    /// The asset the account settles in, if it is not the account's own asset (e.g. a
+    /// stablecoin for an account denominated in fiat). Outgoing settlements are converted
+    /// into it, and incoming ones from it, at the current exchange rate, while the balance
+    /// stays in the account's asset
+    #[serde(default)]
+    pub settlement_asset_code: Option<String>,
+    /// The asset scale of the settlement asset. Defaults to the account's asset scale
+    #[serde(default)]
+    pub settlement_asset_scale: Option<u8>,
+}
+
+/// The secrets of an exported account, hex-encoded in the form the store keeps them in.
+/// The incoming ILP over BTP token is hashed and the Redis store encrypts the other secrets
+/// with a key derived from the node's secret seed, so they can only be used by nodes with
+/// the same seed. They are imported as they are.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportedSecrets {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ilp_over_http_incoming_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ilp_over_http_outgoing_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ilp_over_http_signature_secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ilp_over_btp_incoming_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ilp_over_btp_outgoing_token: Option<String>,
+}
+
+/// An account in a [`NodeExport`](./struct.NodeExport.html)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedAccount {
+    /// The account's id, which is kept when it is imported
+    pub id: Uuid,
+    /// The account's details. Its tokens are left out and exported in `secrets` instead
+    pub details: AccountDetails,
+    #[serde(default)]
+    pub secrets: ExportedSecrets,
+    /// The account's balance, without the prepaid amount
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub balance: i64,
+    /// The amount the account prepaid
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub prepaid_amount: i64,
+}
+
+/// Portable snapshot of a node's accounts, balances and routes, which can be imported
+/// into a fresh store to migrate the node to another database
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NodeExport {
+    pub accounts: Vec<ExportedAccount>,
+    /// Map of ILP Address prefix -> account id. The routes learned via CCP are not
+    /// exported, the peers advertise them again
+    #[serde(default)]
+    pub static_routes: HashMap<String, Uuid>,
+    #[serde(default)]
+    pub default_route: Option<Uuid>,
 }
 
 pub struct NodeApi<S, I, O, B, A: Account> {
@@ -268,6 +458,8 @@ pub struct NodeApi<S, I, O, B, A: Account> {
     /// The admin's API token, used to make admin-only changes
     // TODO: Make this a SecretString
     admin_api_token: String,
+    /// API token which is only accepted for the admin-only requests that do not make changes
+    read_only_admin_api_token: Option<String>,
     default_spsp_account: Option<Username>,
     incoming_handler: I,
     // The outgoing service is included so that the API can send outgoing
@@ -279,6 +471,16 @@ pub struct NodeApi<S, I, O, B, A: Account> {
     /// Server secret used to instantiate SPSP/Stream connections
     server_secret: Bytes,
     node_version: Option<String>,
+    features: Vec<String>,
+    packet_events: Option<broadcast::Sender<PacketEvent>>,
+    btp_connections: Vec<BtpConnections>,
+    peer_activity: PeerActivity,
+    maintenance_mode: MaintenanceMode,
+    balance_pause: BalancePause,
+    packet_expiry: Duration,
+    asset_scales: RangeInclusive<u8>,
+    settlement_client: SettlementClient,
+    spsp_client: reqwest::Client,
 }
 
 impl<S, I, O, B, A> NodeApi<S, I, O, B, A>
@@ -289,9 +491,11 @@ where
         + HttpStore<Account = A>
         + BalanceStore
         + SettlementStore<Account = A>
+        + IdempotentStore
         + StreamNotificationsStore<Account = A>
         + RouterStore
-        + ExchangeRateStore,
+        + ExchangeRateStore
+        + TrafficStore,
     I: IncomingService<A> + Clone + Send + Sync + 'static,
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     B: OutgoingService<A> + Clone + Send + Sync + 'static,
@@ -300,6 +504,7 @@ where
         + Account
         + HttpAccount
         + SettlementAccount
+        + SpspDisplayAccount
         + Serialize
         + Send
         + Sync
@@ -316,15 +521,34 @@ where
         NodeApi {
             store,
             admin_api_token,
+            read_only_admin_api_token: None,
             default_spsp_account: None,
             incoming_handler,
             outgoing_handler,
             btp,
             server_secret,
             node_version: None,
+            features: Vec::new(),
+            packet_events: None,
+            btp_connections: Vec::new(),
+            peer_activity: PeerActivity::new(),
+            maintenance_mode: MaintenanceMode::default(),
+            balance_pause: BalancePause::default(),
+            packet_expiry: DEFAULT_PACKET_EXPIRY,
+            asset_scales: 0..=DEFAULT_MAX_ASSET_SCALE,
+            settlement_client: SettlementClient::default(),
+            spsp_client: reqwest::Client::new(),
         }
     }
 
+    /// Sets a second admin token, which is accepted for `GET` requests to the admin-only
+    /// endpoints (except for the export of the node), but is forbidden from making changes.
+    /// This allows tools like dashboards to read balances and routes without the admin token.
+    pub fn read_only_admin_api_token(&mut self, token: String) -> &mut Self {
+        self.read_only_admin_api_token = Some(token);
+        self
+    }
+
     /// Sets the default SPSP account. When SPSP payments are sent to the root domain,
     /// the payment pointer is resolved to <domain>/.well-known/pay. This value determines
     /// which account those payments will be sent to.
@@ -339,20 +563,125 @@ where
         self
     }
 
-    /// Returns a Warp Filter which exposes the accounts and admin APIs
+    /// Sets the optional features the node was built with, which are listed in its public info
+    pub fn features(&mut self, features: Vec<String>) -> &mut Self {
+        self.features = features;
+        self
+    }
+
+    /// Streams the packet events published on the given channel (for example by a
+    /// [`PacketEventsService`](../interledger_service_util/struct.PacketEventsService.html))
+    /// to admins connected to the `/packets/events` WebSocket
+    pub fn packet_events(&mut self, sender: broadcast::Sender<PacketEvent>) -> &mut Self {
+        self.packet_events = Some(sender);
+        self
+    }
+
+    /// Also reports the accounts connected to the given BTP service (for example the one
+    /// accepting incoming BTP connections) as connected in the account status
+    pub fn btp_connections(&mut self, connections: BtpConnections) -> &mut Self {
+        self.btp_connections.push(connections);
+        self
+    }
+
+    /// Sets the registry from which the recent activity of every account is read
+    /// for the account status (for example one filled by a
+    /// [`PeerActivityService`](../interledger_service_util/struct.PeerActivityService.html))
+    pub fn peer_activity(&mut self, activity: PeerActivity) -> &mut Self {
+        self.peer_activity = activity;
+        self
+    }
+
+    /// Sets the maintenance mode which is toggled via `PUT /maintenance`. It must be the one
+    /// given to the node's
+    /// [`MaintenanceModeService`](../interledger_service_util/struct.MaintenanceModeService.html),
+    /// otherwise toggling it has no effect.
+    pub fn maintenance_mode(&mut self, maintenance_mode: MaintenanceMode) -> &mut Self {
+        self.maintenance_mode = maintenance_mode;
+        self
+    }
+
+    /// Sets the pause with which `POST /balances/snapshot` quiesces the balance changes of
+    /// packets. It must be the one given to the node's
+    /// [`BalanceService`](../interledger_service_util/struct.BalanceService.html),
+    /// otherwise the snapshots are taken without pausing any packets.
+    pub fn balance_pause(&mut self, pause: BalancePause) -> &mut Self {
+        self.balance_pause = pause;
+        self
+    }
+
+    /// Sets the time before the Prepares of the payments sent via the API expire,
+    /// unless the payment request sets its own `packet_expiry`.
+    /// Defaults to [`DEFAULT_PACKET_EXPIRY`](../interledger_stream/constant.DEFAULT_PACKET_EXPIRY.html).
+    pub fn packet_expiry(&mut self, expiry: Duration) -> &mut Self {
+        self.packet_expiry = expiry;
+        self
+    }
+
+    /// Sets the smallest and largest asset scale of the accounts which can be created or
+    /// replaced via the API. Defaults to 0 and
+    /// [`DEFAULT_MAX_ASSET_SCALE`](./constant.DEFAULT_MAX_ASSET_SCALE.html).
+    pub fn asset_scale_range(&mut self, min: u8, max: u8) -> &mut Self {
+        self.asset_scales = min..=max;
+        self
+    }
+
+    /// Sets the client used to create the accounts on the settlement engines
+    pub fn settlement_client(&mut self, client: SettlementClient) -> &mut Self {
+        self.settlement_client = client;
+        self
+    }
+
+    /// Sets the HTTP client used to query the receivers of SPSP payments
+    pub fn spsp_client(&mut self, client: reqwest::Client) -> &mut Self {
+        self.spsp_client = client;
+        self
+    }
+
+    /// Returns a Warp Filter which exposes the SPSP endpoints and the accounts and admin APIs
     pub fn into_warp_filter(self) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
+        self.spsp_filter().or(self.into_admin_filter()).boxed()
+    }
+
+    /// Returns a Warp Filter which only exposes the public SPSP endpoints
+    /// (`/accounts/:username/spsp` and `/.well-known/pay`)
+    pub fn spsp_filter(&self) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
+        routes::spsp_api(
+            self.server_secret.clone(),
+            self.default_spsp_account.clone(),
+            self.store.clone(),
+        )
+        .boxed()
+    }
+
+    /// Returns a Warp Filter which exposes the accounts and admin APIs without the SPSP
+    /// endpoints, for serving them on separate addresses
+    pub fn into_admin_filter(self) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
         routes::accounts_api(
             self.server_secret,
             self.admin_api_token.clone(),
-            self.default_spsp_account,
+            self.read_only_admin_api_token.clone(),
             self.incoming_handler,
             self.outgoing_handler,
             self.btp,
+            self.btp_connections,
+            self.peer_activity,
+            self.balance_pause,
+            self.packet_expiry,
+            self.asset_scales,
+            self.settlement_client.clone(),
+            self.spsp_client,
             self.store.clone(),
         )
         .or(routes::node_settings_api(
             self.admin_api_token,
+            self.read_only_admin_api_token,
             self.node_version,
+            self.default_spsp_account,
+            self.features,
+            self.packet_events,
+            self.maintenance_mode,
+            self.settlement_client,
             self.store,
         ))
         .boxed()
@@ -389,6 +718,29 @@ mod tests {
         assert!(serde_json::from_str::<One>("{\"val\":\"-1\"}").is_err());
     }
 
+    #[test]
+    fn optional_spread_deserialization() {
+        #[derive(PartialEq, Deserialize, Debug)]
+        struct Spread {
+            #[serde(default, deserialize_with = "optional_spread")]
+            val: Option<f64>,
+        }
+        assert_eq!(
+            serde_json::from_str::<Spread>("{\"val\":0.01}").unwrap(),
+            Spread { val: Some(0.01) }
+        );
+        assert_eq!(
+            serde_json::from_str::<Spread>("{\"val\":\"0\"}").unwrap(),
+            Spread { val: Some(0.0) }
+        );
+        assert_eq!(
+            serde_json::from_str::<Spread>("{}").unwrap(),
+            Spread { val: None }
+        );
+        assert!(serde_json::from_str::<Spread>("{\"val\":1}").is_err());
+        assert!(serde_json::from_str::<Spread>("{\"val\":\"-0.5\"}").is_err());
+    }
+
     #[test]
     fn optional_number_or_string_deserialization() {
         #[derive(PartialEq, Deserialize, Debug)]
@@ -423,6 +775,10 @@ mod tests {
             serde_json::from_str::<Two>("{}").unwrap(),
             Two { val: None }
         );
+        assert_eq!(
+            serde_json::from_str::<Two>("{\"val\":null}").unwrap(),
+            Two { val: None }
+        );
     }
 
     #[test]
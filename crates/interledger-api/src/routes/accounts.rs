@@ -1,30 +1,59 @@
-use crate::{number_or_string, AccountDetails, AccountSettings, NodeStore};
+use super::admin_auth::AdminAuth;
+use super::amounts::{
+    account_details_from_json, account_to_json, add_decimal_amounts, from_json,
+    has_decimal_amounts, scale_decimal_amounts, DESTINATION_AMOUNT_FIELDS, PAYMENT_AMOUNT_FIELDS,
+    SETTINGS_AMOUNT_FIELDS, SOURCE_AMOUNT_FIELDS,
+};
+use crate::{
+    number_or_string, optional_number_or_string, AccountDetails, AccountSettings, NodeExport,
+    NodeStore,
+};
 use bytes::Bytes;
 use futures::{Future, FutureExt, StreamExt, TryFutureExt};
-use interledger_btp::{connect_to_service_account, BtpAccount, BtpOutgoingService};
+use interledger_btp::{connect_to_service_account, BtpAccount, BtpConnections, BtpOutgoingService};
 use interledger_ccp::{CcpRoutingAccount, Mode, RouteControlRequest, RoutingRelation};
 use interledger_errors::*;
 use interledger_http::{deserialize_json, HttpAccount, HttpStore};
 use interledger_ildcp::IldcpRequest;
 use interledger_ildcp::IldcpResponse;
+use interledger_packet::{Address, Prepare, PrepareBuilder};
 use interledger_rates::ExchangeRateStore;
 use interledger_router::RouterStore;
 use interledger_service::{
-    Account, AccountStore, AddressStore, IncomingService, OutgoingRequest, OutgoingService,
-    Username,
+    Account, AccountStore, AddressStore, IlpResult, IncomingRequest, IncomingService,
+    OutgoingRequest, OutgoingService, Username,
+};
+use interledger_service_util::{
+    convert_settlement, BalancePause, BalanceStore, PeerActivity, TrafficStore,
+};
+use interledger_settlement::core::{
+    get_hash_of,
+    idempotency::{make_idempotent_call, IdempotentStore},
+    types::{ApiResponse, ApiResult, SettlementAccount},
+    SettlementClient,
 };
-use interledger_service_util::BalanceStore;
-use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
-use interledger_spsp::{pay, SpspResponder};
-use interledger_stream::{PaymentNotification, StreamNotificationsStore};
-use log::{debug, error, trace};
+use interledger_spsp::{pay, quote, Error as SpspError, SpspDisplayAccount};
+use interledger_stream::{
+    send_money, ConnectionGenerator, Error as StreamError, PaymentNotification, RetryPolicy,
+    StreamNotificationsStore,
+};
+use log::{debug, error, trace, warn};
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 use uuid::Uuid;
-use warp::{self, reply::Json, Filter, Rejection};
+use warp::{
+    self,
+    http::{Method, Response, StatusCode},
+    reply::Json,
+    Filter, Rejection,
+};
 
 pub const BEARER_TOKEN_START: usize = 7;
 
@@ -42,15 +71,148 @@ struct SpspPayRequest {
         default = "get_default_max_slippage"
     )]
     slippage: f64,
+    /// Time in milliseconds before each Prepare expires, overriding the node's default
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    packet_expiry: Option<u64>,
+    /// Time in milliseconds after which the payment stops and returns what was delivered so far
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    payment_timeout: Option<u64>,
+}
+
+impl SpspPayRequest {
+    fn packet_expiry(&self, default: Duration) -> Duration {
+        self.packet_expiry
+            .map(Duration::from_millis)
+            .unwrap_or(default)
+    }
+
+    fn payment_timeout(&self) -> Option<Duration> {
+        self.payment_timeout.map(Duration::from_millis)
+    }
+}
+
+/// A small payment the admin sends from an account to check that the node can pay
+#[derive(Deserialize, Debug)]
+struct TestPaymentRequest {
+    /// Payment pointer or SPSP URL of the receiver. If this is not set, the payment
+    /// loops back to the account through the node's own STREAM receiver
+    #[serde(default)]
+    receiver: Option<String>,
+    #[serde(
+        deserialize_with = "number_or_string",
+        default = "get_default_test_payment_amount"
+    )]
+    source_amount: u64,
+    #[serde(
+        deserialize_with = "number_or_string",
+        default = "get_default_max_slippage"
+    )]
+    slippage: f64,
+}
+
+const fn get_default_test_payment_amount() -> u64 {
+    1
+}
+
+/// Outcome of a test payment
+#[derive(Serialize, Debug)]
+struct TestPaymentResponse {
+    success: bool,
+    /// The payment pointer, or the ILP address the payment looped back to
+    receiver: String,
+    source_amount: u64,
+    /// Amount the receiver got, in its units. This is 0 if the payment failed
+    delivered_amount: u64,
+    error: Option<String>,
+}
+
+/// A Prepare packet to send from an account, bypassing STREAM
+#[derive(Deserialize, Debug)]
+struct PrepareRequest {
+    destination: String,
+    #[serde(deserialize_with = "number_or_string")]
+    amount: u64,
+    /// The hex-encoded SHA-256 hash of the fulfillment
+    execution_condition: String,
+    /// Time in milliseconds before the Prepare expires, overriding the node's default
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    expiry: Option<u64>,
+    /// The base64-encoded data of the Prepare
+    #[serde(default)]
+    data: String,
+}
+
+impl PrepareRequest {
+    fn into_prepare(self, default_expiry: Duration) -> Result<Prepare, ApiError> {
+        let destination = Address::from_str(&self.destination).map_err(|err| {
+            ApiError::bad_request().detail(format!("Invalid destination: {}", err))
+        })?;
+        let mut execution_condition = [0; 32];
+        hex::decode_to_slice(&self.execution_condition, &mut execution_condition).map_err(
+            |_| ApiError::bad_request().detail("execution_condition must be 32 hex-encoded bytes"),
+        )?;
+        let data = base64::decode(&self.data)
+            .map_err(|_| ApiError::bad_request().detail("data must be base64-encoded"))?;
+        let expiry = self
+            .expiry
+            .map(Duration::from_millis)
+            .unwrap_or(default_expiry);
+        Ok(PrepareBuilder {
+            destination,
+            amount: self.amount,
+            expires_at: SystemTime::now() + expiry,
+            execution_condition: &execution_condition,
+            data: &data,
+        }
+        .build())
+    }
+}
+
+/// Returns the JSON representation of the Fulfill or Reject a Prepare was answered with
+fn ilp_result_to_json(result: &IlpResult) -> Value {
+    match result {
+        Ok(fulfill) => json!({
+            "type": "fulfill",
+            "fulfillment": hex::encode(fulfill.fulfillment()),
+            "data": base64::encode(fulfill.data()),
+        }),
+        Err(reject) => json!({
+            "type": "reject",
+            "code": reject.code().to_string(),
+            "message": String::from_utf8_lossy(reject.message()),
+            "triggered_by": reject.triggered_by().map(|address| address.to_string()),
+            "data": base64::encode(reject.data()),
+        }),
+    }
 }
 
+#[derive(Deserialize, Debug)]
+struct EnabledUpdate {
+    enabled: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct BtpTokenUpdate {
+    token: SecretString,
+    #[serde(default)]
+    force_reconnect: bool,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn accounts_api<I, O, S, A, B>(
     server_secret: Bytes,
     admin_api_token: String,
-    default_spsp_account: Option<Username>,
+    read_only_admin_api_token: Option<String>,
     incoming_handler: I,
     outgoing_handler: O,
     btp: BtpOutgoingService<B, A>,
+    mut btp_connections: Vec<BtpConnections>,
+    peer_activity: PeerActivity,
+    balance_pause: BalancePause,
+    packet_expiry: Duration,
+    asset_scales: RangeInclusive<u8>,
+    settlement_client: SettlementClient,
+    spsp_client: reqwest::Client,
     store: S,
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
 where
@@ -62,14 +224,17 @@ where
         + AddressStore
         + HttpStore<Account = A>
         + BalanceStore
+        + IdempotentStore
         + StreamNotificationsStore<Account = A>
         + ExchangeRateStore
-        + RouterStore,
+        + RouterStore
+        + TrafficStore,
     A: BtpAccount
         + CcpRoutingAccount
         + SettlementAccount
         + Account
         + HttpAccount
+        + SpspDisplayAccount
         + Serialize
         + Send
         + Sync
@@ -80,24 +245,10 @@ where
     let with_incoming_handler = warp::any().map(move || incoming_handler.clone()).boxed();
 
     // Helper filters
-    let admin_auth_header = format!("Bearer {}", admin_api_token);
-    let admin_auth_header_clone = admin_auth_header.clone();
-    let with_admin_auth_header = warp::any().map(move || admin_auth_header.clone()).boxed();
-    let admin_only = warp::header::<SecretString>("authorization")
-        .and_then(move |authorization: SecretString| {
-            let admin_auth_header = admin_auth_header_clone.clone();
-            async move {
-                if authorization.expose_secret() == &admin_auth_header {
-                    Ok::<(), Rejection>(())
-                } else {
-                    Err(Rejection::from(ApiError::unauthorized()))
-                }
-            }
-        })
-        // This call makes it so we do not pass on a () value on
-        // success to the next filter, it just gets rid of it
-        .untuple_one()
-        .boxed();
+    let admin_auth = AdminAuth::new(&admin_api_token, read_only_admin_api_token.as_deref());
+    let admin_only = admin_auth.admin_only();
+    let full_admin_only = admin_auth.full_admin_only();
+    let with_admin_auth = warp::any().map(move || admin_auth.clone()).boxed();
 
     // Converts an account username to an account id or errors out
     let account_username_to_id = warp::path::param::<Username>()
@@ -134,17 +285,19 @@ where
 
     // Checks if the account is an admin or if they have provided a valid password
     let admin_or_authorized_user_only = warp::path::param::<Username>()
+        .and(warp::method())
         .and(warp::header::<SecretString>("authorization"))
         .and(with_store.clone())
-        .and(with_admin_auth_header)
+        .and(with_admin_auth)
         .and_then(
             move |path_username: Username,
+                  method: Method,
                   auth_string: SecretString,
                   store: S,
-                  admin_auth_header: String| {
+                  admin_auth: AdminAuth| {
                 async move {
                     // If it's an admin, there's no need for more checks
-                    if auth_string.expose_secret() == &admin_auth_header {
+                    if admin_auth.is_admin(&method, &auth_string)? {
                         let account_id = store.get_account_id_from_username(&path_username).await?;
                         return Ok(account_id);
                     }
@@ -170,25 +323,92 @@ where
     // POST /accounts
     let btp_clone = btp.clone();
     let outgoing_handler_clone = outgoing_handler.clone();
+    let settlement_client_clone = settlement_client.clone();
+    let asset_scales_clone = asset_scales.clone();
     let post_accounts = warp::post()
         .and(warp::path("accounts"))
         .and(warp::path::end())
         .and(admin_only.clone())
         .and(deserialize_json()) // Why does warp::body::json not work?
         .and(with_store.clone())
-        .and_then(move |account_details: AccountDetails, store: S| {
+        .and_then(move |body: Value, store: S| {
             let store_clone = store.clone();
             let handler = outgoing_handler_clone.clone();
             let btp = btp_clone.clone();
+            let settlement_client = settlement_client_clone.clone();
+            let asset_scales = asset_scales_clone.clone();
             async move {
-                let account = store.insert_account(account_details.clone()).await?;
+                let account_details = account_details_from_json(body)?;
+                check_asset_scale(&account_details, &asset_scales)?;
+                let account = store.insert_account(account_details).await?;
 
-                connect_to_external_services(handler, account.clone(), store_clone, btp).await?;
-                Ok::<Json, Rejection>(warp::reply::json(&account))
+                connect_to_external_services(
+                    handler,
+                    account.clone(),
+                    store_clone,
+                    btp,
+                    settlement_client,
+                )
+                .await?;
+                Ok::<Json, Rejection>(warp::reply::json(&account_to_json(
+                    &account,
+                    account.asset_scale(),
+                )))
             }
         })
         .boxed();
 
+    // POST /accounts/batch (optional idempotency-key header)
+    // Body: An array of the accounts to create
+    // Response: The created account or the error of each of them, in the same order
+    let btp_clone = btp.clone();
+    let outgoing_handler_clone = outgoing_handler.clone();
+    let settlement_client_clone = settlement_client.clone();
+    let asset_scales_clone = asset_scales.clone();
+    let post_accounts_batch = warp::post()
+        .and(warp::path("accounts"))
+        .and(warp::path("batch"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            move |idempotency_key: Option<String>, body: Vec<Value>, store: S| {
+                let handler = outgoing_handler_clone.clone();
+                let btp = btp_clone.clone();
+                let settlement_client = settlement_client_clone.clone();
+                let asset_scales = asset_scales_clone.clone();
+                async move {
+                    let input_hash = get_hash_of(Value::Array(body.clone()).to_string().as_ref());
+                    let (status_code, data) = make_idempotent_call(
+                        store.clone(),
+                        insert_accounts_batch(
+                            body,
+                            handler,
+                            store,
+                            btp,
+                            settlement_client,
+                            asset_scales,
+                        ),
+                        input_hash,
+                        idempotency_key,
+                        StatusCode::OK,
+                        Bytes::new(),
+                    )
+                    .await?;
+                    Ok::<_, Rejection>(
+                        Response::builder()
+                            .status(status_code)
+                            .header("Content-Type", "application/json")
+                            .body(data)
+                            .unwrap(),
+                    )
+                }
+            },
+        )
+        .boxed();
+
     // GET /accounts
     let get_accounts = warp::get()
         .and(warp::path("accounts"))
@@ -196,14 +416,69 @@ where
         .and(admin_only.clone())
         .and(with_store.clone())
         .and_then(|store: S| async move {
-            let accounts = store.get_all_accounts().await?;
+            let accounts: Vec<Value> = store
+                .get_all_accounts()
+                .await?
+                .iter()
+                .map(|account| account_to_json(account, account.asset_scale()))
+                .collect();
             Ok::<Json, Rejection>(warp::reply::json(&accounts))
         })
         .boxed();
 
+    // GET /export
+    // Response: All accounts with their balances and the static routes, for backups and migrations
+    let get_export = warp::get()
+        .and(warp::path("export"))
+        .and(warp::path::end())
+        .and(full_admin_only)
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let export = store.export_node().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&export))
+        })
+        .boxed();
+
+    // POST /import
+    // Body: An export of another node, whose accounts must not exist on this one yet
+    let btp_clone = btp.clone();
+    let post_import = warp::post()
+        .and(warp::path("import"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(move |export: NodeExport, store: S| {
+            let btp = btp_clone.clone();
+            async move {
+                let accounts = store.import_node(export).await?;
+                for account in accounts.iter() {
+                    if account.get_ilp_over_btp_url().is_some() {
+                        // The import succeeded even if the peer is not reachable right now
+                        if let Err(err) =
+                            connect_to_service_account(account.clone(), false, btp.clone()).await
+                        {
+                            error!(
+                                "Error connecting to imported account {}: {}",
+                                account.id(),
+                                err
+                            );
+                        }
+                    }
+                }
+                let accounts: Vec<Value> = accounts
+                    .iter()
+                    .map(|account| account_to_json(account, account.asset_scale()))
+                    .collect();
+                Ok::<Json, Rejection>(warp::reply::json(&accounts))
+            }
+        })
+        .boxed();
+
     // PUT /accounts/:username
     let btp_clone = btp.clone();
     let outgoing_handler_clone = outgoing_handler.clone();
+    let settlement_client_clone = settlement_client.clone();
     let put_account = warp::put()
         .and(warp::path("accounts"))
         .and(account_username_to_id.clone())
@@ -211,21 +486,35 @@ where
         .and(admin_only.clone())
         .and(deserialize_json()) // warp::body::json() is not able to decode this!
         .and(with_store.clone())
-        .and_then(move |id: Uuid, account_details: AccountDetails, store: S| {
+        .and_then(move |id: Uuid, body: Value, store: S| {
             let outgoing_handler = outgoing_handler_clone.clone();
             let btp = btp_clone.clone();
-            if account_details.ilp_over_btp_incoming_token.is_some() {
-                // if the BTP token was provided, assume that it's different
-                // from the existing one and drop the connection
-                // the saved websocket connection
-                // a new one will be initialized in the `connect_to_external_services` call
-                btp.close_connection(&id);
-            }
+            let settlement_client = settlement_client_clone.clone();
+            let asset_scales = asset_scales.clone();
             async move {
+                let account_details = account_details_from_json(body)?;
+                check_asset_scale(&account_details, &asset_scales)?;
+                if account_details.ilp_over_btp_incoming_token.is_some() {
+                    // if the BTP token was provided, assume that it's different
+                    // from the existing one and drop the connection
+                    // the saved websocket connection
+                    // a new one will be initialized in the `connect_to_external_services` call
+                    btp.close_connection(&id);
+                }
                 let account = store.update_account(id, account_details).await?;
-                connect_to_external_services(outgoing_handler, account.clone(), store, btp).await?;
+                connect_to_external_services(
+                    outgoing_handler,
+                    account.clone(),
+                    store,
+                    btp,
+                    settlement_client,
+                )
+                .await?;
 
-                Ok::<Json, Rejection>(warp::reply::json(&account))
+                Ok::<Json, Rejection>(warp::reply::json(&account_to_json(
+                    &account,
+                    account.asset_scale(),
+                )))
             }
         })
         .boxed();
@@ -240,7 +529,49 @@ where
         .and_then(|id: Uuid, store: S| async move {
             let accounts = store.get_accounts(vec![id]).await?;
 
-            Ok::<Json, Rejection>(warp::reply::json(&accounts[0]))
+            Ok::<Json, Rejection>(warp::reply::json(&account_to_json(
+                &accounts[0],
+                accounts[0].asset_scale(),
+            )))
+        })
+        .boxed();
+
+    // POST /balances/snapshot
+    // Response: The balances of all accounts at the same point in time, which are read
+    // while the balance changes of packets are briefly paused
+    let post_balances_snapshot = warp::post()
+        .and(warp::path("balances"))
+        .and(warp::path("snapshot"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(move |store: S| {
+            let balance_pause = balance_pause.clone();
+            async move {
+                let accounts = store.get_all_accounts().await?;
+                let ids = accounts.iter().map(|account| account.id()).collect();
+                let balances = match balance_pause.snapshot(store.get_balances(ids)).await {
+                    Some(balances) => balances?,
+                    None => {
+                        return Err(Rejection::from(ApiError::service_unavailable().detail(
+                            "the balance changes in flight did not finish in time to take a snapshot",
+                        )))
+                    }
+                };
+                let balances: Vec<Value> = accounts
+                    .iter()
+                    .zip(balances)
+                    .map(|(account, balance)| {
+                        json!({
+                            "username": account.username(),
+                            // normalized to the base unit like the balance of a single account
+                            "balance": balance as f64 / 10f64.powi(account.asset_scale().into()),
+                            "asset_code": account.asset_code(),
+                        })
+                    })
+                    .collect();
+                Ok::<Json, Rejection>(warp::reply::json(&balances))
+            }
         })
         .boxed();
 
@@ -259,25 +590,244 @@ where
                 let account = accounts.pop().unwrap();
 
                 let balance = store.get_balance(account.id()).await?;
+                let last_settlement = store.get_last_settlement(account.id()).await?;
+                let conversion = store.get_last_settlement_conversion(account.id()).await?;
 
                 let asset_scale = account.asset_scale();
                 let asset_code = account.asset_code().to_owned();
+                // normalize to the base unit
+                let normalize = |amount: i64| amount as f64 / 10f64.powi(asset_scale.into());
                 Ok::<Json, Rejection>(warp::reply::json(&json!({
-                    // normalize to the base unit
-                    "balance": balance as f64 / 10_u64.pow(asset_scale.into()) as f64,
+                    "balance": normalize(balance),
                     "asset_code": asset_code,
+                    "last_settlement_amount": last_settlement
+                        .as_ref()
+                        .map(|settlement| normalize(settlement.amount)),
+                    "last_settlement_at": last_settlement.map(|settlement| settlement.timestamp),
+                    "last_settlement_conversion": conversion.map(|conversion| json!({
+                        "direction": conversion.direction,
+                        "amount": normalize(conversion.amount as i64),
+                        // the settlement asset's scale may be larger than any u64 power of 10
+                        "settlement_amount": conversion.settlement_amount as f64
+                            / 10f64.powi(conversion.settlement_asset_scale.into()),
+                        "settlement_asset_code": conversion.settlement_asset_code,
+                        "rate": conversion.rate,
+                        "rate_provenance": conversion.rate_provenance,
+                        "converted_at": conversion.timestamp,
+                    })),
                 })))
             }
         })
         .boxed();
 
+    // GET /accounts/:username/status
+    btp_connections.push(btp.connections());
+    let btp_sessions = btp_connections.clone();
+    let get_account_status = warp::get()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("status"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .map(move |id: Uuid, store: S| {
+            let btp_connected = btp_connections
+                .iter()
+                .any(|connections| connections.is_connected(&id));
+            let activity = peer_activity.get(&id);
+            warp::reply::json(&json!({
+                "btp_connected": btp_connected,
+                "draining": store.draining_accounts().contains(&id),
+                "enabled": !store.disabled_accounts().contains(&id),
+                "last_activity": activity.last_activity,
+                "recent_fulfilled": activity.recent_fulfilled,
+                "recent_rejected": activity.recent_rejected,
+            }))
+        })
+        .boxed();
+
+    // GET /btp/sessions
+    let btp_sessions_clone = btp_sessions.clone();
+    let get_btp_sessions = warp::get()
+        .and(warp::path("btp"))
+        .and(warp::path("sessions"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(move |store: S| {
+            let sessions: Vec<_> = btp_sessions_clone
+                .iter()
+                .flat_map(|connections| connections.sessions())
+                .collect();
+            async move {
+                let usernames: HashMap<Uuid, Username> = store
+                    .get_all_accounts()
+                    .await?
+                    .into_iter()
+                    .map(|account| (account.id(), account.username().clone()))
+                    .collect();
+                let sessions: Vec<_> = sessions
+                    .into_iter()
+                    .map(|session| {
+                        json!({
+                            "id": session.id,
+                            "account_id": session.account_id,
+                            "username": usernames.get(&session.account_id),
+                            "remote_addr": session.remote_addr,
+                            "connected_since": session.connected_since.to_rfc3339(),
+                        })
+                    })
+                    .collect();
+                Ok::<Json, Rejection>(warp::reply::json(&sessions))
+            }
+        })
+        .boxed();
+
+    // DELETE /btp/sessions/:id
+    let delete_btp_session = warp::delete()
+        .and(warp::path("btp"))
+        .and(warp::path("sessions"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and_then(move |session_id: Uuid| {
+            // Each session belongs to one of the services, so at most one of them terminates it
+            let terminated = btp_sessions
+                .iter()
+                .any(|connections| connections.terminate(&session_id));
+            async move {
+                if terminated {
+                    Ok(StatusCode::NO_CONTENT)
+                } else {
+                    Err(Rejection::from(
+                        ApiError::not_found().detail("no BTP session with this id is open"),
+                    ))
+                }
+            }
+        })
+        .boxed();
+
+    // GET /accounts/:username/traffic
+    let get_account_traffic = warp::get()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("traffic"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, store: S| async move {
+            let mut accounts = store.get_accounts(vec![id]).await?;
+            let account = accounts.pop().unwrap();
+            let traffic = store.get_traffic(id).await.map_err(|_| {
+                Rejection::from(
+                    ApiError::internal_server_error().detail("Unable to load the traffic counters"),
+                )
+            })?;
+            // the amounts are not normalized, like the ones of the account details
+            Ok::<Json, Rejection>(warp::reply::json(&json!({
+                "packets_in": traffic.packets_in,
+                "packets_out": traffic.packets_out,
+                "bytes_in": traffic.bytes_in,
+                "bytes_out": traffic.bytes_out,
+                "amount_in": traffic.amount_in,
+                "amount_out": traffic.amount_out,
+                "asset_code": account.asset_code(),
+                "asset_scale": account.asset_scale(),
+            })))
+        })
+        .boxed();
+
+    // PUT /accounts/:username/draining
+    let put_account_draining = warp::put()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("draining"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, store: S| async move {
+            store.set_account_draining(id, true).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&json!({ "draining": true })))
+        })
+        .boxed();
+
+    // DELETE /accounts/:username/draining
+    let delete_account_draining = warp::delete()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("draining"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, store: S| async move {
+            store.set_account_draining(id, false).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&json!({ "draining": false })))
+        })
+        .boxed();
+
+    // PUT /accounts/:username/enabled
+    let btp_clone = btp.clone();
+    let put_account_enabled = warp::put()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("enabled"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(move |id: Uuid, update: EnabledUpdate, store: S| {
+            let btp = btp_clone.clone();
+            async move {
+                store.set_account_enabled(id, update.enabled).await?;
+                // Disabled accounts cannot authenticate, but would keep their BTP connection
+                if !update.enabled {
+                    btp.close_connection(&id);
+                }
+                Ok::<Json, Rejection>(warp::reply::json(&json!({ "enabled": update.enabled })))
+            }
+        })
+        .boxed();
+
+    // PUT /accounts/:username/btp/token
+    let btp_clone = btp.clone();
+    let put_btp_token = warp::put()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("btp"))
+        .and(warp::path("token"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(move |id: Uuid, update: BtpTokenUpdate, store: S| {
+            let btp = btp_clone.clone();
+            async move {
+                // Only the token is written, so the old one is replaced in a single update
+                let settings = AccountSettings {
+                    ilp_over_btp_incoming_token: Some(update.token),
+                    ..Default::default()
+                };
+                let account = store.modify_account_settings(id, settings).await?;
+                // New connections must use the new token right away, while the existing
+                // connection is kept until the peer reconnects (unless it should be dropped now)
+                if update.force_reconnect {
+                    btp.close_connection(&id);
+                }
+                Ok::<Json, Rejection>(warp::reply::json(&account_to_json(
+                    &account,
+                    account.asset_scale(),
+                )))
+            }
+        })
+        .boxed();
+
     // DELETE /accounts/:username
     let btp_clone = btp.clone();
     let delete_account = warp::delete()
         .and(warp::path("accounts"))
         .and(account_username_to_id.clone())
         .and(warp::path::end())
-        .and(admin_only)
+        .and(admin_only.clone())
         .and(with_store.clone())
         .and_then(move |id: Uuid, store: S| {
             let btp = btp_clone.clone();
@@ -285,7 +835,10 @@ where
                 let account = store.delete_account(id).await?;
                 // close the btp connection (if any)
                 btp.close_connection(&id);
-                Ok::<Json, Rejection>(warp::reply::json(&account))
+                Ok::<Json, Rejection>(warp::reply::json(&account_to_json(
+                    &account,
+                    account.asset_scale(),
+                )))
             }
         })
         .boxed();
@@ -299,10 +852,20 @@ where
         .and(warp::path::end())
         .and(deserialize_json())
         .and(with_store.clone())
-        .and_then(move |id: Uuid, settings: AccountSettings, store: S| {
+        .and_then(move |id: Uuid, mut body: Value, store: S| {
             let btp = btp.clone();
             let outgoing_handler = outgoing_handler_clone.clone();
+            let settlement_client = settlement_client.clone();
             async move {
+                if has_decimal_amounts(&body, SETTINGS_AMOUNT_FIELDS) {
+                    let accounts = store.get_accounts(vec![id]).await?;
+                    scale_decimal_amounts(
+                        &mut body,
+                        SETTINGS_AMOUNT_FIELDS,
+                        accounts[0].asset_scale(),
+                    )?;
+                }
+                let settings: AccountSettings = from_json(body)?;
                 if settings.ilp_over_btp_incoming_token.is_some() {
                     // if the BTP token was provided, assume that it's different
                     // from the existing one and drop the connection
@@ -318,16 +881,20 @@ where
                     modified_account.clone(),
                     store,
                     btp,
+                    settlement_client,
                 )
                 .await?;
-                Ok::<Json, Rejection>(warp::reply::json(&modified_account))
+                Ok::<Json, Rejection>(warp::reply::json(&account_to_json(
+                    &modified_account,
+                    modified_account.asset_scale(),
+                )))
             }
         })
         .boxed();
 
     // (Websocket) /accounts/:username/payments/incoming
     let incoming_payment_notifications = warp::path("accounts")
-        .and(admin_or_authorized_user_only)
+        .and(admin_or_authorized_user_only.clone())
         .and(warp::path("payments"))
         .and(warp::path("incoming"))
         .and(warp::path::end())
@@ -341,17 +908,21 @@ where
         .boxed();
 
     // POST /accounts/:username/payments
+    let spsp_client_clone = spsp_client.clone();
     let post_payments = warp::post()
         .and(warp::path("accounts"))
-        .and(authorized_user_only)
+        .and(authorized_user_only.clone())
         .and(warp::path("payments"))
         .and(warp::path::end())
         .and(deserialize_json())
-        .and(with_incoming_handler)
+        .and(with_incoming_handler.clone())
         .and(with_store.clone())
         .and_then(
-            move |account: A, pay_request: SpspPayRequest, incoming_handler: I, store: S| {
+            move |account: A, mut body: Value, incoming_handler: I, store: S| {
+                let spsp_client = spsp_client_clone.clone();
                 async move {
+                    scale_decimal_amounts(&mut body, PAYMENT_AMOUNT_FIELDS, account.asset_scale())?;
+                    let pay_request: SpspPayRequest = from_json(body)?;
                     let receipt = pay(
                         incoming_handler,
                         account.clone(),
@@ -359,100 +930,350 @@ where
                         &pay_request.receiver,
                         pay_request.source_amount,
                         pay_request.slippage,
+                        pay_request.packet_expiry(packet_expiry),
+                        pay_request.payment_timeout(),
+                        RetryPolicy::default(),
+                        &spsp_client,
                     )
                     .map_err(|err| {
                         let msg = format!("Error sending SPSP payment: {}", err);
                         error!("{}", msg);
                         // TODO give a different error message depending on what type of error it is
-                        Rejection::from(ApiError::internal_server_error().detail(msg))
+                        let mut error = ApiError::internal_server_error().detail(msg);
+                        // The receiver's data is passed on so the client can act on it
+                        if let SpspError::StreamError(StreamError::ApplicationError(data)) = err {
+                            let mut members = error.extension_members.take().unwrap_or_default();
+                            members.insert("application_error".to_owned(), Value::from(data));
+                            error = error.extension_members(members);
+                        }
+                        Rejection::from(error)
                     })
                     .await?;
 
                     debug!("Sent SPSP payment, receipt: {:?}", receipt);
-                    Ok::<Json, Rejection>(warp::reply::json(&json!(receipt)))
+                    let mut body = json!(receipt);
+                    add_decimal_amounts(
+                        &mut body,
+                        SOURCE_AMOUNT_FIELDS,
+                        receipt.source_asset_scale,
+                    );
+                    if let Some(asset_scale) = receipt.destination_asset_scale {
+                        add_decimal_amounts(&mut body, DESTINATION_AMOUNT_FIELDS, asset_scale);
+                    }
+                    Ok::<Json, Rejection>(warp::reply::json(&body))
                 }
             },
         )
         .boxed();
 
-    // GET /accounts/:username/spsp
-    let server_secret_clone = server_secret.clone();
-    let get_spsp = warp::get()
+    // POST /accounts/:username/payments/test
+    // Sends a small payment from the account to check that routing, exchange rates and
+    // settlement work end-to-end. The outcome is returned rather than an error status
+    let test_payment_server_secret = server_secret.clone();
+    let test_payment_spsp_client = spsp_client.clone();
+    let post_test_payment = warp::post()
         .and(warp::path("accounts"))
-        .and(account_username_to_id)
-        .and(warp::path("spsp"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("payments"))
+        .and(warp::path("test"))
         .and(warp::path::end())
+        .and(admin_only)
+        .and(deserialize_json())
+        .and(with_incoming_handler.clone())
         .and(with_store.clone())
-        .and_then(move |id: Uuid, store: S| {
-            let server_secret_clone = server_secret_clone.clone();
-            async move {
-                let accounts = store.get_accounts(vec![id]).await?;
-                // TODO return the response without instantiating an SpspResponder (use a simple fn)
-                Ok::<_, Rejection>(
-                    SpspResponder::new(
-                        accounts[0].ilp_address().clone(),
-                        server_secret_clone.clone(),
-                    )
-                    .generate_http_response(),
-                )
-            }
-        })
-        .boxed();
-
-    // GET /.well-known/pay
-    // This is the endpoint a [Payment Pointer](https://github.com/interledger/rfcs/blob/master/0026-payment-pointers/0026-payment-pointers.md)
-    // with no path resolves to
-    let get_spsp_well_known = warp::get()
-        .and(warp::path(".well-known"))
-        .and(warp::path("pay"))
-        .and(warp::path::end())
-        .and(with_store)
-        .and_then(move |store: S| {
-            let default_spsp_account = default_spsp_account.clone();
-            let server_secret_clone = server_secret.clone();
-            async move {
-                // TODO don't clone this
-                if let Some(username) = default_spsp_account.clone() {
-                    let id = store.get_account_id_from_username(&username).await?;
-
-                    // TODO this shouldn't take multiple store calls
+        .and_then(
+            move |id: Uuid, request: TestPaymentRequest, incoming_handler: I, store: S| {
+                let server_secret = test_payment_server_secret.clone();
+                let spsp_client = test_payment_spsp_client.clone();
+                async move {
                     let mut accounts = store.get_accounts(vec![id]).await?;
-
                     let account = accounts.pop().unwrap();
-                    // TODO return the response without instantiating an SpspResponder (use a simple fn)
-                    Ok::<_, Rejection>(
-                        SpspResponder::new(
-                            account.ilp_address().clone(),
-                            server_secret_clone.clone(),
-                        )
-                        .generate_http_response(),
-                    )
-                } else {
-                    Err(Rejection::from(
-                        ApiError::not_found().detail("no default spsp account was configured"),
-                    ))
+                    let (receiver, result) = match request.receiver {
+                        Some(receiver) => {
+                            let result = pay(
+                                incoming_handler,
+                                account.clone(),
+                                store,
+                                &receiver,
+                                request.source_amount,
+                                request.slippage,
+                                packet_expiry,
+                                None,
+                                RetryPolicy::default(),
+                                &spsp_client,
+                            )
+                            .await
+                            .map_err(|err| err.to_string());
+                            (receiver, result)
+                        }
+                        None => {
+                            let (destination, shared_secret) = ConnectionGenerator::new(
+                                bytes04::Bytes::from(server_secret.as_ref()),
+                            )
+                            .generate_address_and_secret(account.ilp_address());
+                            let result = send_money(
+                                incoming_handler,
+                                &account,
+                                store,
+                                destination.clone(),
+                                &shared_secret[..],
+                                request.source_amount,
+                                request.slippage,
+                                packet_expiry,
+                                None,
+                                RetryPolicy::default(),
+                            )
+                            .await
+                            .map_err(|err| err.to_string());
+                            (destination.to_string(), result)
+                        }
+                    };
+
+                    let response = match result {
+                        Ok(receipt) => {
+                            debug!("Sent test payment to {}, receipt: {:?}", receiver, receipt);
+                            TestPaymentResponse {
+                                success: true,
+                                receiver,
+                                source_amount: request.source_amount,
+                                delivered_amount: receipt.delivered_amount,
+                                error: None,
+                            }
+                        }
+                        Err(err) => {
+                            warn!(
+                                "Test payment from account {} to {} failed: {}",
+                                account.username(),
+                                receiver,
+                                err
+                            );
+                            TestPaymentResponse {
+                                success: false,
+                                receiver,
+                                source_amount: request.source_amount,
+                                delivered_amount: 0,
+                                error: Some(err),
+                            }
+                        }
+                    };
+                    Ok::<Json, Rejection>(warp::reply::json(&response))
                 }
-            }
-        })
+            },
+        )
         .boxed();
 
-    get_spsp
-        .or(get_spsp_well_known)
-        .or(post_accounts)
-        .or(get_accounts)
-        .or(put_account)
-        .or(delete_account)
-        .or(get_account)
-        .or(get_account_balance)
-        .or(put_account_settings)
-        .or(incoming_payment_notifications)
-        .or(post_payments)
-        .boxed()
-}
-
-fn notify_user(
-    socket: warp::ws::WebSocket,
-    id: Uuid,
+    // POST /accounts/:username/packets
+    // Sends a Prepare from the account through the node's routing (as if the account had
+    // sent it), for protocols other than STREAM
+    let post_packets = warp::post()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only)
+        .and(warp::path("packets"))
+        .and(warp::path::end())
+        .and(deserialize_json())
+        .and(with_incoming_handler.clone())
+        .and(with_store.clone())
+        .and_then(
+            move |id: Uuid, request: PrepareRequest, mut incoming_handler: I, store: S| async move {
+                let prepare = request.into_prepare(packet_expiry)?;
+                let mut accounts = store.get_accounts(vec![id]).await?;
+                let from = accounts.pop().unwrap();
+                debug!(
+                    "Sending Prepare from account {} to {} for {}",
+                    from.username(),
+                    prepare.destination(),
+                    prepare.amount()
+                );
+                let result = incoming_handler
+                    .handle_request(IncomingRequest { from, prepare })
+                    .await;
+                Ok::<Json, Rejection>(warp::reply::json(&ilp_result_to_json(&result)))
+            },
+        )
+        .boxed();
+
+    // POST /accounts/:username/payments/quote
+    let post_payments_quote = warp::post()
+        .and(warp::path("accounts"))
+        .and(authorized_user_only)
+        .and(warp::path("payments"))
+        .and(warp::path("quote"))
+        .and(warp::path::end())
+        .and(deserialize_json())
+        .and(with_incoming_handler)
+        .and(with_store.clone())
+        .and_then(
+            move |account: A, mut body: Value, incoming_handler: I, store: S| {
+                let spsp_client = spsp_client.clone();
+                async move {
+                    scale_decimal_amounts(&mut body, PAYMENT_AMOUNT_FIELDS, account.asset_scale())?;
+                    let pay_request: SpspPayRequest = from_json(body)?;
+                    let quote = quote(
+                        incoming_handler,
+                        account.clone(),
+                        store,
+                        &pay_request.receiver,
+                        pay_request.source_amount,
+                        pay_request.slippage,
+                        pay_request.packet_expiry(packet_expiry),
+                        &spsp_client,
+                    )
+                    .map_err(|err| {
+                        let msg = format!("Error quoting SPSP payment: {}", err);
+                        error!("{}", msg);
+                        Rejection::from(ApiError::internal_server_error().detail(msg))
+                    })
+                    .await?;
+
+                    debug!("Quoted SPSP payment: {:?}", quote);
+                    let mut body = json!(quote);
+                    add_decimal_amounts(&mut body, SOURCE_AMOUNT_FIELDS, quote.source_asset_scale);
+                    add_decimal_amounts(
+                        &mut body,
+                        DESTINATION_AMOUNT_FIELDS,
+                        quote.destination_asset_scale,
+                    );
+                    Ok::<Json, Rejection>(warp::reply::json(&body))
+                }
+            },
+        )
+        .boxed();
+
+    post_accounts
+        .or(post_accounts_batch)
+        .or(get_accounts)
+        .or(get_export)
+        .or(post_import)
+        .or(put_account)
+        .or(delete_account)
+        .or(get_account)
+        .or(get_account_balance)
+        .or(post_balances_snapshot)
+        .or(get_account_status)
+        .or(get_account_traffic)
+        .or(put_account_draining)
+        .or(delete_account_draining)
+        .or(put_account_enabled)
+        .or(put_account_settings)
+        .or(put_btp_token)
+        .or(get_btp_sessions)
+        .or(delete_btp_session)
+        .or(incoming_payment_notifications)
+        .or(post_payments)
+        .or(post_payments_quote)
+        .or(post_test_payment)
+        .or(post_packets)
+        .boxed()
+}
+
+/// Returns the error a rejection of the accounts API is responded to with
+fn rejection_to_api_error(rejection: &Rejection) -> ApiError {
+    if let Some(api_error) = rejection.find::<ApiError>() {
+        api_error.clone()
+    } else if let Some(json_error) = rejection.find::<JsonDeserializeError>() {
+        ApiError::from(json_error.clone())
+    } else {
+        ApiError::internal_server_error().detail(format!("{:?}", rejection))
+    }
+}
+
+/// Creates the accounts of a `POST /accounts/batch` request with a single store call and
+/// responds with a JSON array with either the created `account` or the `error` of each of
+/// them, in the same order, so that one invalid account does not prevent the others from
+/// being created. Only failing to reach the store fails the whole batch.
+async fn insert_accounts_batch<O, A, S, B>(
+    body: Vec<Value>,
+    service: O,
+    store: S,
+    btp: BtpOutgoingService<B, A>,
+    settlement_client: SettlementClient,
+    asset_scales: RangeInclusive<u8>,
+) -> ApiResult
+where
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    A: CcpRoutingAccount
+        + BtpAccount
+        + SettlementAccount
+        + Serialize
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    S: NodeStore<Account = A>
+        + AddressStore
+        + BalanceStore
+        + ExchangeRateStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    B: OutgoingService<A> + Clone + 'static,
+{
+    let parsed: Vec<Result<AccountDetails, ApiError>> = body
+        .into_iter()
+        .map(|item| {
+            account_details_from_json(item)
+                .and_then(|details| check_asset_scale(&details, &asset_scales).map(|_| details))
+                .map_err(|err| rejection_to_api_error(&err))
+        })
+        .collect();
+    let valid = parsed
+        .iter()
+        .filter_map(|result| result.as_ref().ok().cloned())
+        .collect();
+    let mut inserted = store.insert_accounts(valid).await?.into_iter();
+
+    let mut results = Vec::with_capacity(parsed.len());
+    for result in parsed {
+        let result = match result {
+            Ok(_) => match inserted.next() {
+                Some(Ok(account)) => connect_to_external_services(
+                    service.clone(),
+                    account,
+                    store.clone(),
+                    btp.clone(),
+                    settlement_client.clone(),
+                )
+                .await
+                .map_err(|err| rejection_to_api_error(&err)),
+                Some(Err(err)) => Err(ApiError::from(err)),
+                None => Err(ApiError::internal_server_error()
+                    .detail("the store did not return the result of the account")),
+            },
+            Err(err) => Err(err),
+        };
+        results.push(match result {
+            Ok(account) => json!({ "account": account_to_json(&account, account.asset_scale()) }),
+            Err(err) => json!({ "error": err }),
+        });
+    }
+    let data = serde_json::to_vec(&results)
+        .map_err(|err| ApiError::internal_server_error().detail(err.to_string()))?;
+    Ok(ApiResponse::Data(Bytes::from(data)))
+}
+
+/// Rejects the accounts whose asset scale is outside of the allowed range
+fn check_asset_scale(
+    account_details: &AccountDetails,
+    asset_scales: &RangeInclusive<u8>,
+) -> Result<(), Rejection> {
+    if asset_scales.contains(&account_details.asset_scale) {
+        Ok(())
+    } else {
+        Err(ApiError::bad_request()
+            .detail(format!(
+                "asset_scale must be between {} and {}, but was {}",
+                asset_scales.start(),
+                asset_scales.end(),
+                account_details.asset_scale
+            ))
+            .into())
+    }
+}
+
+fn notify_user(
+    socket: warp::ws::WebSocket,
+    id: Uuid,
     store: impl StreamNotificationsStore,
 ) -> impl Future<Output = Result<(), ()>> {
     let (tx, rx) = futures::channel::mpsc::unbounded::<PaymentNotification>();
@@ -567,11 +1388,19 @@ async fn connect_to_external_services<O, A, S, B>(
     account: A,
     store: S,
     btp: BtpOutgoingService<B, A>,
+    settlement_client: SettlementClient,
 ) -> Result<A, warp::reject::Rejection>
 where
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     A: CcpRoutingAccount + BtpAccount + SettlementAccount + Clone + Send + Sync + 'static,
-    S: NodeStore<Account = A> + AddressStore + BalanceStore + Clone + Send + Sync + 'static,
+    S: NodeStore<Account = A>
+        + AddressStore
+        + BalanceStore
+        + ExchangeRateStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     B: OutgoingService<A> + Clone + 'static,
 {
     // Try to connect to the account's BTP socket if they have
@@ -601,14 +1430,13 @@ where
         .or(default_settlement_engine);
     if let Some(se_url) = settlement_engine_url {
         let id = account.id();
-        let http_client = SettlementClient::default();
         trace!(
             "Sending account {} creation request to settlement engine: {:?}",
             id,
             se_url.clone()
         );
 
-        let response = http_client
+        let response = settlement_client
             .create_engine_account(id, se_url.clone())
             .map_err(|err| {
                 Rejection::from(ApiError::internal_server_error().detail(err.to_string()))
@@ -620,16 +1448,34 @@ where
 
             // We will pre-fund our account with 0, which will return
             // the current settle_to value
-            let (_, amount_to_settle) = store.update_balances_for_fulfill(id, 0u64).await?;
+            let (_, amount_to_settle) = store.update_balances_for_fulfill(id, 0u64, None).await?;
 
             // prefund the absolute value
             if amount_to_settle > 0 {
-                http_client
-                    .send_settlement(id, se_url, amount_to_settle, account.asset_scale())
+                let conversion =
+                    convert_settlement(&store, &account, amount_to_settle).map_err(|_| {
+                        Rejection::from(
+                            ApiError::internal_server_error().detail(
+                                "Could not convert the prefunding into the settlement asset",
+                            ),
+                        )
+                    })?;
+                let (settlement_amount, settlement_scale) = match conversion {
+                    Some(ref conversion) => (
+                        conversion.settlement_amount,
+                        conversion.settlement_asset_scale,
+                    ),
+                    None => (amount_to_settle, account.asset_scale()),
+                };
+                settlement_client
+                    .send_settlement(id, se_url, settlement_amount, settlement_scale)
                     .map_err(|err| {
                         Rejection::from(ApiError::internal_server_error().detail(err.to_string()))
                     })
                     .await?;
+                if let Some(conversion) = conversion {
+                    store.save_settlement_conversion(id, conversion).await?;
+                }
             }
         } else {
             error!(
@@ -657,6 +1503,81 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn rejects_accounts_with_out_of_range_asset_scales() {
+        let api = test_accounts_api();
+        for asset_scale in &[0, 18] {
+            let mut details = DETAILS.clone().unwrap();
+            details["asset_scale"] = serde_json::json!(asset_scale);
+            let resp = api_call(&api, "POST", "/accounts", "admin", Some(details.clone())).await;
+            assert_eq!(resp.status().as_u16(), 200);
+            let resp = api_call(&api, "PUT", "/accounts/alice", "admin", Some(details)).await;
+            assert_eq!(resp.status().as_u16(), 200);
+        }
+
+        let mut details = DETAILS.clone().unwrap();
+        details["asset_scale"] = serde_json::json!(30);
+        let resp = api_call(&api, "POST", "/accounts", "admin", Some(details.clone())).await;
+        assert_eq!(resp.status().as_u16(), 400);
+        let problem: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            problem["detail"],
+            "asset_scale must be between 0 and 18, but was 30"
+        );
+        let resp = api_call(&api, "PUT", "/accounts/alice", "admin", Some(details)).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    #[tokio::test]
+    async fn creates_batches_of_accounts() {
+        let api = test_accounts_api();
+        let mut bob = DETAILS.clone().unwrap();
+        bob["username"] = serde_json::json!("bob");
+        bob["ilp_address"] = serde_json::json!("example.bob");
+        let batch = Some(serde_json::json!([DETAILS.clone().unwrap(), bob]));
+        let resp = api_call(&api, "POST", "/accounts/batch", "admin", batch.clone()).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let results: Vec<serde_json::Value> = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(results.len(), 2);
+        for result in results {
+            // The test store's accounts serialize to null
+            assert!(result.get("account").is_some());
+            assert!(result.get("error").is_none());
+        }
+
+        let resp = api_call(&api, "POST", "/accounts/batch", "wrong", batch).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_accounts_in_batches() {
+        let api = test_accounts_api();
+        let mut out_of_range = DETAILS.clone().unwrap();
+        out_of_range["asset_scale"] = serde_json::json!(30);
+        let mut missing_username = DETAILS.clone().unwrap();
+        missing_username.as_object_mut().unwrap().remove("username");
+        let batch = serde_json::json!([
+            DETAILS.clone().unwrap(),
+            out_of_range,
+            missing_username,
+            DETAILS.clone().unwrap(),
+        ]);
+        let resp = api_call(&api, "POST", "/accounts/batch", "admin", Some(batch)).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let results: Vec<serde_json::Value> = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(results.len(), 4);
+        assert!(results[0].get("account").is_some());
+        assert_eq!(results[1]["error"]["status"], 400);
+        assert_eq!(
+            results[1]["error"]["detail"],
+            "asset_scale must be between 0 and 18, but was 30"
+        );
+        assert_eq!(results[2]["error"]["invalid-params"][0]["name"], "username");
+        // The accounts after the invalid ones are still created
+        assert!(results[3].get("account").is_some());
+        assert!(results[3].get("error").is_none());
+    }
+
     #[tokio::test]
     async fn only_admin_can_delete_account() {
         let api = test_accounts_api();
@@ -677,6 +1598,187 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn only_admin_can_export_and_import() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/export", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let export: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(export["accounts"], serde_json::json!([]));
+
+        let resp = api_call(&api, "GET", "/export", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "POST", "/import", "admin", Some(export.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "POST", "/import", "wrong", Some(export)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_rotate_btp_token() {
+        let api = test_accounts_api();
+        let token = Some(serde_json::json!({ "token": "new_btp_token" }));
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/btp/token",
+            "admin",
+            token.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let force_reconnect = Some(serde_json::json!({
+            "token": "new_btp_token",
+            "force_reconnect": true,
+        }));
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/btp/token",
+            "admin",
+            force_reconnect,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/btp/token",
+            "password",
+            token.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "PUT", "/accounts/alice/btp/token", "wrong", token).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_get_account_status() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/accounts/alice/status", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        // the account never connected or sent any packets
+        let status: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            status,
+            serde_json::json!({
+                "btp_connected": false,
+                "draining": false,
+                "enabled": true,
+                "last_activity": null,
+                "recent_fulfilled": 0,
+                "recent_rejected": 0,
+            })
+        );
+
+        let resp = api_call(&api, "GET", "/accounts/alice/status", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/accounts/alice/status", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_manage_btp_sessions() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/btp/sessions", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let sessions: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(sessions, serde_json::json!([]));
+
+        let resp = api_call(&api, "GET", "/btp/sessions", READ_ONLY_TOKEN, None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "GET", "/btp/sessions", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let path = format!("/btp/sessions/{}", uuid::Uuid::new_v4());
+        let resp = api_call(&api, "DELETE", &path, "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let resp = api_call(&api, "DELETE", &path, READ_ONLY_TOKEN, None).await;
+        assert_eq!(resp.status().as_u16(), 403);
+
+        let resp = api_call(&api, "DELETE", &path, "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_get_account_traffic() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/accounts/alice/traffic", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let traffic: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            traffic,
+            serde_json::json!({
+                "packets_in": 3,
+                "packets_out": 2,
+                "bytes_in": 450,
+                "bytes_out": 380,
+                "amount_in": 1000,
+                "amount_out": 500,
+                "asset_code": "XYZ",
+                "asset_scale": 9,
+            })
+        );
+
+        let resp = api_call(&api, "GET", "/accounts/alice/traffic", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_drain_account() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "PUT", "/accounts/alice/draining", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body, serde_json::json!({ "draining": true }));
+
+        let resp = api_call(&api, "DELETE", "/accounts/alice/draining", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body, serde_json::json!({ "draining": false }));
+
+        let resp = api_call(&api, "PUT", "/accounts/alice/draining", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "DELETE", "/accounts/alice/draining", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_enable_or_disable_account() {
+        let api = test_accounts_api();
+        let disable = Some(serde_json::json!({ "enabled": false }));
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/enabled",
+            "admin",
+            disable.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body, serde_json::json!({ "enabled": false }));
+
+        let enable = Some(serde_json::json!({ "enabled": true }));
+        let resp = api_call(&api, "PUT", "/accounts/alice/enabled", "admin", enable).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body, serde_json::json!({ "enabled": true }));
+
+        let resp = api_call(&api, "PUT", "/accounts/alice/enabled", "password", disable).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_can_get_all_accounts() {
         let api = test_accounts_api();
@@ -715,6 +1817,86 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn read_only_admin_token_cannot_make_changes() {
+        let api = test_accounts_api();
+        for path in &["/accounts", "/accounts/alice", "/accounts/alice/balance"] {
+            let resp = api_call(&api, "GET", path, READ_ONLY_TOKEN, None).await;
+            assert_eq!(resp.status().as_u16(), 200);
+        }
+
+        let resp = api_call(&api, "POST", "/accounts", READ_ONLY_TOKEN, DETAILS.clone()).await;
+        assert_eq!(resp.status().as_u16(), 403);
+        let resp = api_call(&api, "DELETE", "/accounts/alice", READ_ONLY_TOKEN, None).await;
+        assert_eq!(resp.status().as_u16(), 403);
+        let settings = Some(serde_json::json!({ "ilp_over_http_url": "http://example.com" }));
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/settings",
+            READ_ONLY_TOKEN,
+            settings,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 403);
+        // the export contains the accounts' secrets
+        let resp = api_call(&api, "GET", "/export", READ_ONLY_TOKEN, None).await;
+        assert_eq!(resp.status().as_u16(), 403);
+
+        // the admin token can still do both
+        let resp = api_call(&api, "GET", "/accounts/alice/balance", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "POST", "/accounts", "admin", DETAILS.clone()).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "DELETE", "/accounts/alice", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn gets_last_settlement_with_balance() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/accounts/alice/balance", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let balance: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            balance,
+            serde_json::json!({
+                "balance": 1e-9,
+                "asset_code": "XYZ",
+                "last_settlement_amount": -2.0,
+                "last_settlement_at": "2020-02-01T12:00:00+00:00",
+                "last_settlement_conversion": {
+                    "direction": "outgoing",
+                    "amount": 1.5,
+                    "settlement_amount": 3.0,
+                    "settlement_asset_code": "USDC",
+                    "rate": 2.0,
+                    "rate_provenance": null,
+                    "converted_at": "2020-02-01T12:00:00+00:00",
+                },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn snapshots_all_balances() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "POST", "/balances/snapshot", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let balances: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let balance = serde_json::json!({
+            "username": "alice",
+            "balance": 1e-9,
+            "asset_code": "XYZ",
+        });
+        assert_eq!(balances, serde_json::json!([balance, balance]));
+
+        let resp = api_call(&api, "POST", "/balances/snapshot", READ_ONLY_TOKEN, None).await;
+        assert_eq!(resp.status().as_u16(), 403);
+        let resp = api_call(&api, "POST", "/balances/snapshot", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_or_user_can_modify_accounts_settings() {
         let api = test_accounts_api();
@@ -791,4 +1973,257 @@ mod tests {
         .await;
         assert_eq!(resp.status().as_u16(), 401);
     }
+
+    #[tokio::test]
+    async fn only_user_can_quote_payment() {
+        let payment: Option<serde_json::Value> = Some(serde_json::json!({
+            "receiver": "some_receiver",
+            "source_amount" : 10,
+        }));
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments/quote",
+            "password",
+            payment.clone(),
+        )
+        .await;
+        // The receiver is invalid, but the request made it past authorization
+        assert_eq!(resp.status().as_u16(), 500);
+
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments/quote",
+            "admin",
+            payment.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments/quote",
+            "wrong",
+            payment,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn sends_test_payment_to_self() {
+        let api = test_accounts_api_with_loopback();
+        let body = Some(serde_json::json!({ "source_amount": 10 }));
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments/test",
+            "password",
+            body.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "POST", "/accounts/alice/payments/test", "admin", body).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let result: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(result["success"], true);
+        assert_eq!(result["source_amount"], 10);
+        assert_eq!(result["delivered_amount"], 10);
+        assert!(result["error"].is_null());
+        // The payment looped back to one of the account's own STREAM addresses
+        assert!(result["receiver"]
+            .as_str()
+            .unwrap()
+            .starts_with("example.alice."));
+    }
+
+    #[tokio::test]
+    async fn returns_error_of_failed_test_payment() {
+        // The handler rejects all packets for the account as unreachable
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments/test",
+            "admin",
+            Some(serde_json::json!({})),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let result: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(result["success"], false);
+        assert_eq!(result["source_amount"], 1);
+        assert_eq!(result["delivered_amount"], 0);
+        let error = result["error"].as_str().unwrap();
+        assert!(error.contains("F02"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn sends_prepare_packets() {
+        let api = test_accounts_api();
+        let prepare = serde_json::json!({
+            "destination": "example.receiver",
+            "amount": 100,
+            "execution_condition": hex::encode(EXECUTION_CONDITION),
+            "data": base64::encode(b"custom protocol"),
+        });
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/packets",
+            "password",
+            Some(prepare.clone()),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let fulfill: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            fulfill,
+            serde_json::json!({
+                "type": "fulfill",
+                "fulfillment": hex::encode([0; 32]),
+                "data": base64::encode(b"received"),
+            })
+        );
+
+        // Admins may send packets from the account too
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/packets",
+            "admin",
+            Some(prepare.clone()),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let mut unknown_condition = prepare.clone();
+        unknown_condition["execution_condition"] = serde_json::json!(hex::encode([1; 32]));
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/packets",
+            "password",
+            Some(unknown_condition),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let reject: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            reject,
+            serde_json::json!({
+                "type": "reject",
+                "code": "F02",
+                "message": "No other incoming handler!",
+                "triggered_by": null,
+                "data": "",
+            })
+        );
+
+        let mut invalid_condition = prepare.clone();
+        invalid_condition["execution_condition"] = serde_json::json!("1234");
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/packets",
+            "password",
+            Some(invalid_condition),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/packets",
+            "wrong",
+            Some(prepare),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn rejects_decimal_amounts_which_lose_precision() {
+        let api = test_accounts_api();
+        let mut details = DETAILS.clone().unwrap();
+        details["settle_threshold"] = serde_json::json!("1.5");
+        let resp = api_call(&api, "POST", "/accounts", "admin", Some(details.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        // The account's asset scale is 9
+        details["settle_threshold"] = serde_json::json!("1.0000000001");
+        let resp = api_call(&api, "POST", "/accounts", "admin", Some(details)).await;
+        assert_eq!(resp.status().as_u16(), 400);
+        let error: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            error["detail"],
+            "Amount 1.0000000001 of `settle_threshold` has more decimal places than the asset scale of 9 allows"
+        );
+
+        let settings = Some(serde_json::json!({ "settle_to": "0.0000000001" }));
+        let resp = api_call(&api, "PUT", "/accounts/alice/settings", "admin", settings).await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let payment = Some(serde_json::json!({
+            "receiver": "some_receiver",
+            "source_amount": "0.0000000001",
+        }));
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments",
+            "password",
+            payment,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    #[test]
+    fn payment_request_overrides_packet_expiry() {
+        use super::SpspPayRequest;
+        use std::time::Duration;
+
+        let default = Duration::from_secs(30);
+        let request: SpspPayRequest = serde_json::from_value(serde_json::json!({
+            "receiver": "$example.com",
+            "source_amount": 100,
+        }))
+        .unwrap();
+        assert_eq!(request.packet_expiry(default), default);
+
+        let request: SpspPayRequest = serde_json::from_value(serde_json::json!({
+            "receiver": "$example.com",
+            "source_amount": 100,
+            "packet_expiry": "60000",
+        }))
+        .unwrap();
+        assert_eq!(request.packet_expiry(default), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn payment_request_sets_payment_timeout() {
+        use super::SpspPayRequest;
+        use std::time::Duration;
+
+        let request: SpspPayRequest = serde_json::from_value(serde_json::json!({
+            "receiver": "$example.com",
+            "source_amount": 100,
+        }))
+        .unwrap();
+        assert_eq!(request.payment_timeout(), None);
+
+        let request: SpspPayRequest = serde_json::from_value(serde_json::json!({
+            "receiver": "$example.com",
+            "source_amount": 100,
+            "payment_timeout": 5000,
+        }))
+        .unwrap();
+        assert_eq!(request.payment_timeout(), Some(Duration::from_secs(5)));
+    }
 }
@@ -0,0 +1,93 @@
+use interledger_errors::ApiError;
+use secrecy::{ExposeSecret, SecretString};
+use warp::{filters::BoxedFilter, http::Method, Filter, Rejection};
+
+/// The tokens with which admins authorize their requests to the APIs: the admin token,
+/// which authorizes every request, and optionally a read-only token for tools such as
+/// dashboards, which only authorizes the requests that do not change anything
+#[derive(Clone)]
+pub(crate) struct AdminAuth {
+    admin_auth_header: String,
+    read_only_auth_header: Option<String>,
+}
+
+impl AdminAuth {
+    pub(crate) fn new(admin_api_token: &str, read_only_admin_api_token: Option<&str>) -> Self {
+        AdminAuth {
+            admin_auth_header: format!("Bearer {}", admin_api_token),
+            read_only_auth_header: read_only_admin_api_token
+                .map(|token| format!("Bearer {}", token)),
+        }
+    }
+
+    /// Returns whether the authorization header belongs to an admin who may make a request
+    /// with the given method. Requests other than `GET` and `HEAD` which were made with the
+    /// read-only token are rejected as forbidden.
+    pub(crate) fn is_admin(
+        &self,
+        method: &Method,
+        authorization: &SecretString,
+    ) -> Result<bool, Rejection> {
+        let authorization = authorization.expose_secret();
+        if authorization == &self.admin_auth_header {
+            return Ok(true);
+        }
+        match self.read_only_auth_header {
+            Some(ref read_only) if authorization == read_only => {
+                if *method == Method::GET || *method == Method::HEAD {
+                    Ok(true)
+                } else {
+                    Err(ApiError::forbidden()
+                        .detail("the read-only admin auth token cannot make changes")
+                        .into())
+                }
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Lets through the requests which were authorized by an admin (see `is_admin`)
+    pub(crate) fn admin_only(&self) -> BoxedFilter<()> {
+        let auth = self.clone();
+        warp::method()
+            .and(warp::header::<SecretString>("authorization"))
+            .and_then(move |method: Method, authorization: SecretString| {
+                let is_admin = auth.is_admin(&method, &authorization);
+                async move {
+                    if is_admin? {
+                        Ok::<(), Rejection>(())
+                    } else {
+                        Err(Rejection::from(
+                            ApiError::unauthorized().detail("invalid admin auth token provided"),
+                        ))
+                    }
+                }
+            })
+            // This call makes it so we do not pass on a () value on
+            // success to the next filter, it just gets rid of it
+            .untuple_one()
+            .boxed()
+    }
+
+    /// Lets through the requests which were made with the admin token. Those made
+    /// with the read-only token are forbidden, even to read (e.g. secrets).
+    pub(crate) fn full_admin_only(&self) -> BoxedFilter<()> {
+        let auth = self.clone();
+        warp::header::<SecretString>("authorization")
+            .and_then(move |authorization: SecretString| {
+                let authorization = authorization.expose_secret();
+                let result = if authorization == &auth.admin_auth_header {
+                    Ok(())
+                } else if Some(authorization) == auth.read_only_auth_header.as_ref() {
+                    Err(ApiError::forbidden().detail(
+                        "the read-only admin auth token is not allowed to make this request",
+                    ))
+                } else {
+                    Err(ApiError::unauthorized().detail("invalid admin auth token provided"))
+                };
+                async move { result.map_err(Rejection::from) }
+            })
+            .untuple_one()
+            .boxed()
+    }
+}
@@ -0,0 +1,331 @@
+//! Amounts in the API are integers in the base unit of the account's asset (so 150 is
+//! 1.50 of an asset with scale 2), which is also how they are used internally.
+//! For convenience, request bodies may instead contain decimal amounts in the asset's unit,
+//! passed as strings with a decimal point (e.g. `"1.50"`). These are converted to the base
+//! unit with the account's asset scale before the body is deserialized. Responses include
+//! the decimal form of each amount next to the integer, in a field with the `_decimal` suffix.
+use crate::AccountDetails;
+use interledger_errors::{ApiError, JsonDeserializeError};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{convert::TryFrom, fmt};
+use warp::Rejection;
+
+/// The amount fields of accounts (and of the bodies used to create or modify them)
+pub(crate) const ACCOUNT_AMOUNT_FIELDS: &[&str] = &[
+    "max_packet_amount",
+    "min_balance",
+    "settle_threshold",
+    "settle_to",
+    "amount_per_minute_limit",
+];
+/// The amount fields of the account settings users may modify
+pub(crate) const SETTINGS_AMOUNT_FIELDS: &[&str] = &["settle_threshold", "settle_to"];
+/// The amount fields of payment and quote requests
+pub(crate) const PAYMENT_AMOUNT_FIELDS: &[&str] = &["source_amount"];
+/// The amount fields of payment receipts and quotes which are in the sender's asset
+pub(crate) const SOURCE_AMOUNT_FIELDS: &[&str] =
+    &["source_amount", "sent_amount", "in_flight_amount"];
+/// The amount fields of payment receipts and quotes which are in the receiver's asset
+pub(crate) const DESTINATION_AMOUNT_FIELDS: &[&str] = &[
+    "delivered_amount",
+    "estimated_delivered_amount",
+    "min_delivered_amount",
+];
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum DecimalAmountError {
+    /// The string is not a decimal number
+    InvalidFormat,
+    /// The amount has more decimal places than the asset scale, so it cannot
+    /// be represented in the base unit without rounding
+    TooPrecise { asset_scale: u8 },
+    /// The amount in the base unit does not fit into an integer
+    TooLarge,
+}
+
+impl fmt::Display for DecimalAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecimalAmountError::InvalidFormat => write!(f, "not a decimal number"),
+            DecimalAmountError::TooPrecise { asset_scale } => write!(
+                f,
+                "has more decimal places than the asset scale of {} allows",
+                asset_scale
+            ),
+            DecimalAmountError::TooLarge => write!(f, "is too large"),
+        }
+    }
+}
+
+/// Converts a decimal amount in the asset's unit to the asset's base unit
+pub(crate) fn decimal_to_base_units(
+    decimal: &str,
+    asset_scale: u8,
+) -> Result<i128, DecimalAmountError> {
+    let (negative, digits) = if decimal.starts_with('-') {
+        (true, &decimal[1..])
+    } else {
+        (false, decimal)
+    };
+    let mut parts = digits.splitn(2, '.');
+    let integer = parts.next().unwrap_or_default();
+    let fraction = parts.next().unwrap_or_default();
+    if (integer.is_empty() && fraction.is_empty())
+        || !integer
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(DecimalAmountError::InvalidFormat);
+    }
+
+    let scale = usize::from(asset_scale);
+    let (fraction, rest) = fraction.split_at(fraction.len().min(scale));
+    if rest.chars().any(|c| c != '0') {
+        return Err(DecimalAmountError::TooPrecise { asset_scale });
+    }
+    let base_units = format!(
+        "{}{}{}",
+        integer,
+        fraction,
+        "0".repeat(scale - fraction.len())
+    );
+    let base_units = base_units.trim_start_matches('0');
+    if base_units.is_empty() {
+        return Ok(0);
+    }
+    let amount: i128 = base_units
+        .parse()
+        .map_err(|_| DecimalAmountError::TooLarge)?;
+    Ok(if negative { -amount } else { amount })
+}
+
+/// Converts an amount in the asset's base unit to a decimal amount in the asset's unit.
+/// The decimal has exactly as many decimal places as the asset scale.
+pub(crate) fn base_units_to_decimal(amount: i128, asset_scale: u8) -> String {
+    let scale = usize::from(asset_scale);
+    let digits = amount.abs().to_string();
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - scale);
+    let sign = if amount < 0 { "-" } else { "" };
+    if fraction.is_empty() {
+        format!("{}{}", sign, integer)
+    } else {
+        format!("{}{}.{}", sign, integer, fraction)
+    }
+}
+
+/// Replaces the decimal amounts (strings with a decimal point) of the given fields of
+/// the request body with the (integer) amount in the base unit of the asset
+pub(crate) fn scale_decimal_amounts(
+    body: &mut Value,
+    fields: &[&str],
+    asset_scale: u8,
+) -> Result<(), ApiError> {
+    for field in fields {
+        if let Some(value) = body.get_mut(*field) {
+            if let Value::String(decimal) = value {
+                if decimal.contains('.') {
+                    let amount = decimal_to_base_units(decimal, asset_scale).map_err(|err| {
+                        ApiError::bad_request()
+                            .detail(format!("Amount {} of `{}` {}", decimal, field, err))
+                    })?;
+                    // The deserializers of the amounts parse strings, so this
+                    // is checked against the range of the field's integer type
+                    *value = Value::String(amount.to_string());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether any of the given fields of the request body is a decimal amount
+pub(crate) fn has_decimal_amounts(body: &Value, fields: &[&str]) -> bool {
+    fields.iter().any(|field| match body.get(*field) {
+        Some(Value::String(amount)) => amount.contains('.'),
+        _ => false,
+    })
+}
+
+/// Adds the decimal form of the (integer) amounts of the given fields
+/// to the response body, as `<field>_decimal`
+pub(crate) fn add_decimal_amounts(body: &mut Value, fields: &[&str], asset_scale: u8) {
+    if let Value::Object(map) = body {
+        for field in fields {
+            let amount = match map.get(*field) {
+                Some(Value::Number(number)) => number
+                    .as_i64()
+                    .map(i128::from)
+                    .or_else(|| number.as_u64().map(i128::from)),
+                _ => None,
+            };
+            if let Some(amount) = amount {
+                map.insert(
+                    format!("{}_decimal", field),
+                    Value::String(base_units_to_decimal(amount, asset_scale)),
+                );
+            }
+        }
+    }
+}
+
+/// Serializes an account for a response, including the decimal forms of its amounts
+pub(crate) fn account_to_json<A: Serialize>(account: &A, asset_scale: u8) -> Value {
+    let mut body = serde_json::to_value(account).unwrap_or(Value::Null);
+    add_decimal_amounts(&mut body, ACCOUNT_AMOUNT_FIELDS, asset_scale);
+    body
+}
+
+/// Deserializes the body of a request creating or replacing an account. Its decimal
+/// amounts are converted with the asset scale of the account in the same body.
+pub(crate) fn account_details_from_json(mut body: Value) -> Result<AccountDetails, Rejection> {
+    let asset_scale = match body.get("asset_scale") {
+        Some(Value::Number(scale)) => scale.as_u64(),
+        Some(Value::String(scale)) => scale.parse().ok(),
+        _ => None,
+    }
+    .and_then(|scale| u8::try_from(scale).ok());
+    // Without a valid scale, deserializing the body fails anyway
+    if let Some(asset_scale) = asset_scale {
+        scale_decimal_amounts(&mut body, ACCOUNT_AMOUNT_FIELDS, asset_scale)?;
+    }
+    from_json(body)
+}
+
+/// Deserializes the (converted) request body, with the same errors as
+/// [`deserialize_json`](../../interledger_http/fn.deserialize_json.html).
+/// The body is deserialized from its bytes because some types (like
+/// ILP addresses) can only be deserialized from borrowed strings.
+pub(crate) fn from_json<T: DeserializeOwned>(body: Value) -> Result<T, Rejection> {
+    let buf = serde_json::to_vec(&body).map_err(|err| {
+        Rejection::from(ApiError::internal_server_error().detail(err.to_string()))
+    })?;
+    let deserializer = &mut serde_json::Deserializer::from_slice(&buf);
+    serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        Rejection::from(JsonDeserializeError {
+            category: err.inner().classify(),
+            detail: err.inner().to_string(),
+            path: err.path().clone(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn converts_decimals_to_base_units() {
+        assert_eq!(decimal_to_base_units("1.50", 2), Ok(150));
+        assert_eq!(decimal_to_base_units("1.5", 2), Ok(150));
+        assert_eq!(decimal_to_base_units("1.", 2), Ok(100));
+        assert_eq!(decimal_to_base_units(".01", 2), Ok(1));
+        assert_eq!(decimal_to_base_units("-0.25", 2), Ok(-25));
+        assert_eq!(decimal_to_base_units("0.000", 2), Ok(0));
+        assert_eq!(decimal_to_base_units("12.0", 0), Ok(12));
+        assert_eq!(decimal_to_base_units("1.000000001", 9), Ok(1_000_000_001));
+        assert_eq!(
+            decimal_to_base_units("1.5", 18),
+            Ok(1_500_000_000_000_000_000)
+        );
+        // Trailing zeros beyond the scale do not lose precision
+        assert_eq!(decimal_to_base_units("1.5000", 2), Ok(150));
+    }
+
+    #[test]
+    fn rejects_decimals_which_lose_precision() {
+        assert_eq!(
+            decimal_to_base_units("1.505", 2),
+            Err(DecimalAmountError::TooPrecise { asset_scale: 2 })
+        );
+        assert_eq!(
+            decimal_to_base_units("0.5", 0),
+            Err(DecimalAmountError::TooPrecise { asset_scale: 0 })
+        );
+        assert_eq!(
+            decimal_to_base_units("0.0000000001", 9),
+            Err(DecimalAmountError::TooPrecise { asset_scale: 9 })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_decimals() {
+        for decimal in &[".", "", "-", "1.2.3", "1,50", "abc", "+1.0", "1e3", " 1.0"] {
+            assert_eq!(
+                decimal_to_base_units(decimal, 2),
+                Err(DecimalAmountError::InvalidFormat),
+                "{}",
+                decimal
+            );
+        }
+        assert_eq!(
+            decimal_to_base_units("1.0", 40),
+            Err(DecimalAmountError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn converts_base_units_to_decimals() {
+        assert_eq!(base_units_to_decimal(150, 2), "1.50");
+        assert_eq!(base_units_to_decimal(1, 2), "0.01");
+        assert_eq!(base_units_to_decimal(-25, 2), "-0.25");
+        assert_eq!(base_units_to_decimal(0, 9), "0.000000000");
+        assert_eq!(base_units_to_decimal(12, 0), "12");
+        assert_eq!(
+            base_units_to_decimal(i128::from(u64::max_value()), 9),
+            "18446744073.709551615"
+        );
+    }
+
+    #[test]
+    fn scales_decimal_amounts_of_body() {
+        let mut body = json!({
+            "settle_threshold": "1.50",
+            "settle_to": "100",
+            "min_balance": -500,
+            "username": "a.b",
+        });
+        scale_decimal_amounts(&mut body, ACCOUNT_AMOUNT_FIELDS, 2).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "settle_threshold": "150",
+                // Integers are left alone
+                "settle_to": "100",
+                "min_balance": -500,
+                // as are fields which are not amounts
+                "username": "a.b",
+            })
+        );
+
+        let mut body = json!({ "settle_to": "0.001" });
+        let err = scale_decimal_amounts(&mut body, ACCOUNT_AMOUNT_FIELDS, 2).unwrap_err();
+        assert_eq!(
+            err.detail.unwrap(),
+            "Amount 0.001 of `settle_to` has more decimal places than the asset scale of 2 allows"
+        );
+    }
+
+    #[test]
+    fn adds_decimal_amounts_to_body() {
+        let mut body = json!({
+            "settle_threshold": 150,
+            "settle_to": null,
+            "max_packet_amount": u64::max_value(),
+        });
+        add_decimal_amounts(&mut body, ACCOUNT_AMOUNT_FIELDS, 2);
+        assert_eq!(
+            body,
+            json!({
+                "settle_threshold": 150,
+                "settle_threshold_decimal": "1.50",
+                "settle_to": null,
+                "max_packet_amount": u64::max_value(),
+                "max_packet_amount_decimal": "184467440737095516.15",
+            })
+        );
+    }
+}
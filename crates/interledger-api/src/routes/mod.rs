@@ -1,8 +1,12 @@
 mod accounts;
+mod admin_auth;
+mod amounts;
 mod node_settings;
+mod spsp;
 
 pub use accounts::accounts_api;
 pub use node_settings::node_settings_api;
+pub use spsp::spsp_api;
 
 #[cfg(test)]
 pub mod test_helpers;
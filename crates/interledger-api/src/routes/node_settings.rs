@@ -1,24 +1,30 @@
+use super::admin_auth::AdminAuth;
 use crate::{ExchangeRates, NodeStore};
 use bytes::Bytes;
-use futures::TryFutureExt;
+use futures::{SinkExt, TryFutureExt};
 use interledger_errors::*;
 use interledger_http::{deserialize_json, HttpAccount};
 use interledger_packet::Address;
 use interledger_rates::ExchangeRateStore;
 use interledger_router::RouterStore;
 use interledger_service::{Account, AccountStore, AddressStore, Username};
-use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
-use log::{error, trace};
-use secrecy::{ExposeSecret, SecretString};
-use serde::Serialize;
+use interledger_service_util::{MaintenanceMode, PacketEvent};
+use interledger_settlement::core::{types::SettlementAccount, EngineHealth, SettlementClient};
+use log::{debug, error, trace, warn};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     iter::FromIterator,
     str::{self, FromStr},
+    time::Duration,
 };
+use tokio::sync::broadcast;
 use url::Url;
 use uuid::Uuid;
-use warp::{self, reply::Json, Filter, Rejection};
+use warp::{self, http::StatusCode, reply::Json, ws::WebSocket, Filter, Rejection};
+
+/// How long the readiness check waits for the store to respond
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
 
 // TODO add more to this response
 #[derive(Clone, Serialize)]
@@ -29,9 +35,99 @@ struct StatusResponse {
     version: Option<String>,
 }
 
+/// The public metadata of the node, which anyone may read
+#[derive(Serialize)]
+struct NodeInfo {
+    ilp_address: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    /// The asset of the default SPSP account, which receives the payments sent to the node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default_account: Option<AssetInfo>,
+    /// The optional features the node was built with
+    features: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct AssetInfo {
+    asset_code: String,
+    asset_scale: u8,
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    /// The dependencies which are not available, with the reason
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    failing: HashMap<&'static str, String>,
+}
+
+/// Where the route to a prefix in the routing table comes from
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RouteSource {
+    /// The address of one of the node's accounts
+    Local,
+    /// A static route set via the API or configuration
+    Configured,
+    /// The default route, which is used if no other route matches
+    Default,
+    /// A route advertised by a peer or parent via CCP. These are kept
+    /// until the account withdraws them, so they have no expiry
+    Ccp,
+}
+
+#[derive(Serialize)]
+struct RouteEntry {
+    prefix: String,
+    /// The username of the account the packets to the prefix are forwarded to
+    next_hop: Username,
+    account_id: Uuid,
+    source: RouteSource,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MaintenanceStatus {
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct RouteTableQuery {
+    /// Only return the routes whose prefix starts with this
+    prefix: Option<String>,
+}
+
+/// Checks the dependencies the node cannot process packets without
+async fn check_readiness<S: NodeStore>(store: &S) -> HealthResponse {
+    let mut failing = HashMap::new();
+    match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, store.check_health()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => {
+            failing.insert("store", err.to_string());
+        }
+        Err(_) => {
+            failing.insert("store", "timed out".to_string());
+        }
+    }
+    HealthResponse {
+        status: if failing.is_empty() {
+            "ok"
+        } else {
+            "unavailable"
+        },
+        failing,
+    }
+}
+
 pub fn node_settings_api<S, A>(
     admin_api_token: String,
+    read_only_admin_api_token: Option<String>,
     node_version: Option<String>,
+    default_spsp_account: Option<Username>,
+    features: Vec<String>,
+    packet_events: Option<broadcast::Sender<PacketEvent>>,
+    maintenance_mode: MaintenanceMode,
+    settlement_client: SettlementClient,
     store: S,
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
 where
@@ -43,27 +139,12 @@ where
     A: Account + HttpAccount + Send + Sync + SettlementAccount + Serialize + 'static,
 {
     // Helper filters
-    let admin_auth_header = format!("Bearer {}", admin_api_token);
-    let admin_only = warp::header::<SecretString>("authorization")
-        .and_then(move |authorization: SecretString| {
-            let admin_auth_header = admin_auth_header.clone();
-            async move {
-                if authorization.expose_secret() == &admin_auth_header {
-                    Ok::<(), Rejection>(())
-                } else {
-                    Err(Rejection::from(
-                        ApiError::unauthorized().detail("invalid admin auth token provided"),
-                    ))
-                }
-            }
-        })
-        // This call makes it so we do not pass on a () value on
-        // success to the next filter, it just gets rid of it
-        .untuple_one()
-        .boxed();
+    let admin_only =
+        AdminAuth::new(&admin_api_token, read_only_admin_api_token.as_deref()).admin_only();
     let with_store = warp::any().map(move || store.clone()).boxed();
 
     // GET /
+    let node_version_clone = node_version.clone();
     let get_root = warp::get()
         .and(warp::path::end())
         .and(with_store.clone())
@@ -71,11 +152,80 @@ where
             warp::reply::json(&StatusResponse {
                 status: "Ready".to_string(),
                 ilp_address: store.get_ilp_address(),
-                version: node_version.clone(),
+                version: node_version_clone.clone(),
+            })
+        })
+        .boxed();
+
+    // GET /info
+    // Public metadata for clients and tooling, so it must not contain any secrets
+    let get_info = warp::get()
+        .and(warp::path("info"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(move |store: S| {
+            let version = node_version.clone();
+            let default_spsp_account = default_spsp_account.clone();
+            let features = features.clone();
+            async move {
+                let default_account = match default_spsp_account {
+                    Some(ref username) => {
+                        match store.get_account_id_from_username(username).await {
+                            Ok(id) => store
+                                .get_accounts(vec![id])
+                                .await
+                                .ok()
+                                .and_then(|mut accounts| accounts.pop()),
+                            Err(_) => None,
+                        }
+                    }
+                    None => None,
+                };
+                Ok::<Json, Rejection>(warp::reply::json(&NodeInfo {
+                    ilp_address: store.get_ilp_address(),
+                    version,
+                    default_account: default_account.map(|account| AssetInfo {
+                        asset_code: account.asset_code().to_string(),
+                        asset_scale: account.asset_scale(),
+                    }),
+                    features,
+                }))
+            }
+        })
+        .boxed();
+
+    // GET /health/live
+    // Liveness: the node is running and serving requests
+    let get_liveness = warp::get()
+        .and(warp::path("health"))
+        .and(warp::path("live"))
+        .and(warp::path::end())
+        .map(|| {
+            warp::reply::json(&HealthResponse {
+                status: "ok",
+                failing: HashMap::new(),
             })
         })
         .boxed();
 
+    // GET /health
+    // Readiness: the node can reach its store
+    let get_readiness = warp::get()
+        .and(warp::path("health"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let health = check_readiness(&store).await;
+            let status = if health.failing.is_empty() {
+                StatusCode::OK
+            } else {
+                warn!("Readiness check failed: {:?}", health.failing);
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            Ok::<_, Rejection>(warp::reply::with_status(warp::reply::json(&health), status))
+        })
+        .boxed();
+
     // PUT /rates
     let put_rates = warp::put()
         .and(warp::path("rates"))
@@ -85,10 +235,39 @@ where
         .and(with_store.clone())
         .and_then(|rates: ExchangeRates, store: S| async move {
             store.set_exchange_rates(rates.0.clone())?;
+            // Rates set by the admin were not signed by anyone
+            store.set_rate_provenance(None)?;
+            store.set_rate_source(None)?;
             Ok::<_, Rejection>(warp::reply::json(&rates))
         })
         .boxed();
 
+    // GET /rates/provenance
+    // Response: Proof of where the current rates came from, or null
+    let get_rate_provenance = warp::get()
+        .and(warp::path("rates"))
+        .and(warp::path("provenance"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let provenance = store.get_rate_provenance()?;
+            Ok::<_, Rejection>(warp::reply::json(&provenance))
+        })
+        .boxed();
+
+    // GET /rates/source
+    // Response: The exchange rate provider the current rates came from, or null
+    let get_rate_source = warp::get()
+        .and(warp::path("rates"))
+        .and(warp::path("source"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let source = store.get_rate_source()?;
+            Ok::<_, Rejection>(warp::reply::json(&source))
+        })
+        .boxed();
+
     // GET /rates
     let get_rates = warp::get()
         .and(warp::path("rates"))
@@ -126,6 +305,65 @@ where
         })
         .boxed();
 
+    // GET /routes/table
+    // Response: Array of the routes with their next hop and source, sorted by prefix
+    let get_route_table = warp::get()
+        .and(warp::path("routes"))
+        .and(warp::path("table"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(warp::query::<RouteTableQuery>())
+        .and(with_store.clone())
+        .and_then(|query: RouteTableQuery, store: S| {
+            async move {
+                let routing_table = store.routing_table();
+                let mut routes: Vec<(&String, &Uuid)> = routing_table
+                    .iter()
+                    .filter(|(prefix, _)| match query.prefix {
+                        Some(ref filter) => prefix.starts_with(filter.as_str()),
+                        None => true,
+                    })
+                    .collect();
+                routes.sort_by(|a, b| a.0.cmp(b.0));
+
+                let static_routes = store.get_static_routes().await?;
+                let mut account_ids: Vec<Uuid> = routes.iter().map(|(_, id)| **id).collect();
+                account_ids.sort();
+                account_ids.dedup();
+                let accounts: HashMap<Uuid, A> = store
+                    .get_accounts(account_ids)
+                    .await?
+                    .into_iter()
+                    .map(|account| (account.id(), account))
+                    .collect();
+
+                let entries: Vec<RouteEntry> = routes
+                    .into_iter()
+                    .filter_map(|(prefix, id)| {
+                        let account = accounts.get(id)?;
+                        // Static routes take precedence over the others, like in the routing table
+                        let source = if static_routes.get(prefix) == Some(id) {
+                            RouteSource::Configured
+                        } else if prefix.is_empty() {
+                            RouteSource::Default
+                        } else if prefix.as_str() == &**account.ilp_address() {
+                            RouteSource::Local
+                        } else {
+                            RouteSource::Ccp
+                        };
+                        Some(RouteEntry {
+                            prefix: prefix.clone(),
+                            next_hop: account.username().clone(),
+                            account_id: *id,
+                            source,
+                        })
+                    })
+                    .collect();
+                Ok::<Json, Rejection>(warp::reply::json(&entries))
+            }
+        })
+        .boxed();
+
     // PUT /routes/static
     // Body: Map of ILP Address prefix -> Username
     let put_static_routes = warp::put()
@@ -185,6 +423,85 @@ where
         })
         .boxed();
 
+    // GET /maintenance
+    let maintenance_mode_clone = maintenance_mode.clone();
+    let get_maintenance = warp::get()
+        .and(warp::path("maintenance"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .map(move || {
+            warp::reply::json(&MaintenanceStatus {
+                enabled: maintenance_mode_clone.is_enabled(),
+            })
+        })
+        .boxed();
+
+    // PUT /maintenance
+    let put_maintenance = warp::put()
+        .and(warp::path("maintenance"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(deserialize_json())
+        .map(move |status: MaintenanceStatus| {
+            if status.enabled != maintenance_mode.is_enabled() {
+                warn!(
+                    "{} maintenance mode",
+                    if status.enabled {
+                        "Entering"
+                    } else {
+                        "Leaving"
+                    }
+                );
+            }
+            maintenance_mode.set_enabled(status.enabled);
+            warp::reply::json(&status)
+        })
+        .boxed();
+
+    // GET /packets/events (WebSocket)
+    let get_packet_events = warp::get()
+        .and(warp::path("packets"))
+        .and(warp::path("events"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(warp::ws())
+        .and_then(move |ws: warp::ws::Ws| {
+            let packet_events = packet_events.clone();
+            async move {
+                let sender = packet_events.ok_or_else(|| {
+                    Rejection::from(
+                        ApiError::not_found().detail("packet events are not enabled on this node"),
+                    )
+                })?;
+                let events = sender.subscribe();
+                Ok::<_, Rejection>(
+                    ws.on_upgrade(move |socket| stream_packet_events(socket, events)),
+                )
+            }
+        })
+        .boxed();
+
+    let with_settlement_client = warp::any().map(move || settlement_client.clone());
+
+    // GET /settlement/engines/health
+    // Response: Map of engine URL -> health of the engine
+    let get_settlement_engines_health = warp::get()
+        .and(warp::path("settlement"))
+        .and(warp::path("engines"))
+        .and(warp::path("health"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_settlement_client.clone())
+        .map(|client: SettlementClient| {
+            let health: HashMap<String, EngineHealth> = client
+                .engines_health()
+                .into_iter()
+                .map(|(url, health)| (url.to_string(), health))
+                .collect();
+            warp::reply::json(&health)
+        })
+        .boxed();
+
     // PUT /settlement/engines
     let put_settlement_engines = warp::put()
         .and(warp::path("settlement"))
@@ -193,7 +510,8 @@ where
         .and(admin_only)
         .and(warp::body::json())
         .and(with_store)
-        .and_then(move |asset_to_url_map: HashMap<String, Url>, store: S| async move {
+        .and(with_settlement_client)
+        .and_then(move |asset_to_url_map: HashMap<String, Url>, store: S, client: SettlementClient| async move {
             let asset_to_url_map_clone = asset_to_url_map.clone();
             store
                 .set_settlement_engines(asset_to_url_map.clone()).await?;
@@ -208,7 +526,6 @@ where
             // (even if this isn't called often, it could crash the node at some point)
             let accounts = store.get_all_accounts().await?;
 
-            let client = SettlementClient::default();
             // Try creating the account on the settlement engine if the settlement_engine_url of the
             // account is the one we just configured as the default for the account's asset code
             for account in accounts {
@@ -230,19 +547,67 @@ where
         .boxed();
 
     get_root
+        .or(get_info)
+        .or(get_liveness)
+        .or(get_readiness)
         .or(put_rates)
         .or(get_rates)
+        .or(get_rate_provenance)
+        .or(get_rate_source)
         .or(get_routes)
+        .or(get_route_table)
         .or(put_static_routes)
         .or(put_static_route)
         .or(put_settlement_engines)
+        .or(get_settlement_engines_health)
+        .or(get_packet_events)
+        .or(get_maintenance)
+        .or(put_maintenance)
         .boxed()
 }
 
+/// Forwards the packet events to the WebSocket client as JSON text messages.
+/// If the client is too slow to keep up, the events it missed are skipped.
+async fn stream_packet_events(mut socket: WebSocket, mut events: broadcast::Receiver<PacketEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                let msg = warp::ws::Message::text(serde_json::to_string(&event).unwrap());
+                if let Err(err) = socket.send(msg).await {
+                    debug!("Packet events subscriber disconnected: {}", err);
+                    return;
+                }
+            }
+            Err(broadcast::RecvError::Lagged(skipped)) => {
+                warn!(
+                    "Packet events subscriber is lagging behind, skipped {} events",
+                    skipped
+                );
+            }
+            Err(broadcast::RecvError::Closed) => return,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::routes::test_helpers::{api_call, test_node_settings_api};
+    use crate::routes::test_helpers::{
+        api_call, test_node_settings_api, test_node_settings_api_with_info,
+        test_node_settings_api_with_maintenance_mode, test_node_settings_api_with_packet_events,
+        test_node_settings_api_with_settlement_client, test_node_settings_api_with_unhealthy_store,
+        TestAccount, READ_ONLY_TOKEN,
+    };
+    use bytes::Bytes;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_service::{outgoing_service_fn, OutgoingRequest, OutgoingService};
+    use interledger_service_util::{MaintenanceMode, PacketEventsService, PacketOutcome};
+    use interledger_settlement::core::SettlementClient;
     use serde_json::{json, Value};
+    use std::{
+        str::{self, FromStr},
+        time::{Duration, SystemTime},
+    };
+    use tokio::sync::broadcast;
 
     #[tokio::test]
     async fn gets_status() {
@@ -255,6 +620,145 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn gets_public_info() {
+        let api = test_node_settings_api_with_info();
+        let resp = api_call(&api, "GET", "/info", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let info: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            info,
+            json!({
+                "ilp_address": "example.connector",
+                "version": "1.2.3",
+                "default_account": {
+                    "asset_code": "XYZ",
+                    "asset_scale": 9,
+                },
+                "features": ["redis"],
+            })
+        );
+        // no secrets, e.g. the admin token, are part of the info
+        assert!(!str::from_utf8(resp.body()).unwrap().contains("admin"));
+    }
+
+    #[tokio::test]
+    async fn reports_healthy_store() {
+        let api = test_node_settings_api();
+        // No admin token is needed
+        let resp = api_call(&api, "GET", "/health", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body(), &b"{\"status\":\"ok\"}"[..]);
+
+        let resp = api_call(&api, "GET", "/health/live", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body(), &b"{\"status\":\"ok\"}"[..]);
+    }
+
+    #[tokio::test]
+    async fn reports_unavailable_store() {
+        let api = test_node_settings_api_with_unhealthy_store();
+        let resp = api_call(&api, "GET", "/health", "", None).await;
+        assert_eq!(resp.status().as_u16(), 503);
+        let body: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            body,
+            json!({"status": "unavailable", "failing": {"store": "Connection refused"}})
+        );
+
+        // The node itself is still alive
+        let resp = api_call(&api, "GET", "/health/live", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn reports_health_of_settlement_engines() {
+        let mut client = SettlementClient::default();
+        client.health_failure_threshold(1);
+        // Nothing is listening on this port
+        client
+            .probe_engine("http://127.0.0.1:1".parse().unwrap())
+            .await;
+        let api = test_node_settings_api_with_settlement_client(client);
+
+        let resp = api_call(&api, "GET", "/settlement/engines/health", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/settlement/engines/health", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: Value = serde_json::from_slice(resp.body()).unwrap();
+        let health = &body["http://127.0.0.1:1/"];
+        assert_eq!(health["healthy"], false);
+        assert_eq!(health["consecutive_failures"], 1);
+        assert!(health["last_error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn streams_packet_events() {
+        let (sender, _) = broadcast::channel(16);
+        let api = test_node_settings_api_with_packet_events(sender.clone());
+        let mut client = warp::test::ws()
+            .path("/packets/events")
+            .header("Authorization", "Bearer admin")
+            .handshake(api)
+            .await
+            .unwrap();
+
+        let mut service = PacketEventsService::new(
+            sender,
+            outgoing_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        service
+            .send_request(OutgoingRequest {
+                from: TestAccount,
+                to: TestAccount,
+                original_amount: 100,
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount: 100,
+                    expires_at: SystemTime::now() + Duration::from_secs(30),
+                    execution_condition: &[0; 32],
+                    data: &[],
+                }
+                .build(),
+            })
+            .await
+            .unwrap();
+
+        let msg = client.recv().await.unwrap();
+        let event: Value = serde_json::from_str(msg.to_str().unwrap()).unwrap();
+        assert_eq!(event["outcome"], json!(PacketOutcome::Fulfilled));
+        assert_eq!(event["from_username"], "alice");
+        assert_eq!(event["destination"], "example.destination");
+        assert_eq!(event["incoming_amount"], 100);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_subscribe_to_packet_events() {
+        let (sender, _) = broadcast::channel(16);
+        let api = test_node_settings_api_with_packet_events(sender);
+        let result = warp::test::ws()
+            .path("/packets/events")
+            .header("Authorization", "Bearer wrong")
+            .handshake(api)
+            .await;
+        assert!(result.is_err());
+
+        // The endpoint does not exist if packet events are not enabled
+        let result = warp::test::ws()
+            .path("/packets/events")
+            .header("Authorization", "Bearer admin")
+            .handshake(test_node_settings_api())
+            .await;
+        assert!(result.is_err());
+    }
+
     #[tokio::test]
     async fn gets_rates() {
         let api = test_node_settings_api();
@@ -266,6 +770,33 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn gets_rate_provenance() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/rates/provenance", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({
+                "source": "example-oracle",
+                "timestamp": 1_600_000_000,
+                "signature": "c2ln",
+                "jws": "e30.e30.c2ln",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn gets_rate_source() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/rates/source", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"provider": "CoinCap", "fallback": true})
+        );
+    }
+
     #[tokio::test]
     async fn gets_routes() {
         let api = test_node_settings_api();
@@ -273,6 +804,17 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 200);
     }
 
+    #[tokio::test]
+    async fn only_admin_can_get_route_table() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/routes/table?prefix=example", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body(), &Bytes::from("[]"));
+
+        let resp = api_call(&api, "GET", "/routes/table", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_can_put_rates() {
         let api = test_node_settings_api();
@@ -284,6 +826,49 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn toggles_maintenance_mode() {
+        let maintenance_mode = MaintenanceMode::default();
+        let api = test_node_settings_api_with_maintenance_mode(maintenance_mode.clone());
+        let resp = api_call(&api, "GET", "/maintenance", READ_ONLY_TOKEN, None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body(), &b"{\"enabled\":false}"[..]);
+
+        let enable = json!({"enabled": true});
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/maintenance",
+            READ_ONLY_TOKEN,
+            Some(enable.clone()),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 403);
+        assert!(!maintenance_mode.is_enabled());
+
+        let resp = api_call(&api, "PUT", "/maintenance", "admin", Some(enable)).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert!(maintenance_mode.is_enabled());
+        let resp = api_call(&api, "GET", "/maintenance", "admin", None).await;
+        assert_eq!(resp.body(), &b"{\"enabled\":true}"[..]);
+
+        let disable = json!({"enabled": false});
+        let resp = api_call(&api, "PUT", "/maintenance", "admin", Some(disable)).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert!(!maintenance_mode.is_enabled());
+    }
+
+    #[tokio::test]
+    async fn read_only_admin_token_can_only_get() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/routes/table", READ_ONLY_TOKEN, None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let rates = json!({"ABC": 1.0});
+        let resp = api_call(&api, "PUT", "/rates", READ_ONLY_TOKEN, Some(rates)).await;
+        assert_eq!(resp.status().as_u16(), 403);
+    }
+
     #[tokio::test]
     async fn only_admin_can_put_static_routes() {
         let api = test_node_settings_api();
@@ -335,4 +920,50 @@ mod tests {
         let resp = api_call(&api, "PUT", "/settlement/engines", "wrong", Some(engines)).await;
         assert_eq!(resp.status().as_u16(), 401);
     }
+
+    #[tokio::test]
+    async fn unknown_paths_return_problem_details() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/does/not/exist", "admin", None).await;
+        assert_problem_details(resp, 404);
+    }
+
+    #[tokio::test]
+    async fn unauthorized_requests_return_problem_details() {
+        let api = test_node_settings_api();
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/settlement/engines/health")
+            .reply(&api)
+            .await;
+        assert_problem_details(resp, 401);
+
+        let resp = api_call(&api, "GET", "/settlement/engines/health", "wrong", None).await;
+        assert_problem_details(resp, 401);
+    }
+
+    #[tokio::test]
+    async fn malformed_bodies_return_problem_details() {
+        let api = test_node_settings_api();
+        for path in &["/settlement/engines", "/rates"] {
+            let resp = warp::test::request()
+                .method("PUT")
+                .path(path)
+                .header("Authorization", "Bearer admin")
+                .header("Content-Type", "application/json")
+                .body("{\"ABC\":")
+                .reply(&api)
+                .await;
+            assert_problem_details(resp, 400);
+        }
+    }
+
+    fn assert_problem_details(resp: http::Response<bytes::Bytes>, status: u16) {
+        assert_eq!(resp.status().as_u16(), status);
+        assert_eq!(resp.headers()["Content-Type"], "application/problem+json");
+        let body: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["status"], status);
+        assert!(body["type"].is_string());
+        assert!(body["title"].is_string());
+    }
 }
@@ -0,0 +1,95 @@
+use bytes::Bytes;
+use interledger_errors::ApiError;
+use interledger_service::{Account, AccountStore, Username};
+use interledger_spsp::{DisplayMetadata, SpspDisplayAccount, SpspResponder};
+use uuid::Uuid;
+use warp::{self, Filter, Rejection};
+
+/// The SPSP endpoints, which senders query to pay the node's accounts. They are public,
+/// so they are served next to the ILP over HTTP and BTP endpoints even if the rest of the
+/// API is bound to a separate admin address.
+pub fn spsp_api<S, A>(
+    server_secret: Bytes,
+    default_spsp_account: Option<Username>,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: AccountStore<Account = A> + Clone + Send + Sync + 'static,
+    A: Account + SpspDisplayAccount + Send + Sync + 'static,
+{
+    let with_store = warp::any().map(move || store.clone()).boxed();
+
+    // Converts an account username to an account id or errors out
+    let account_username_to_id = warp::path::param::<Username>()
+        .and(with_store.clone())
+        .and_then(move |username: Username, store: S| async move {
+            let id = store.get_account_id_from_username(&username).await?;
+            Ok::<_, Rejection>(id)
+        })
+        .boxed();
+
+    // GET /accounts/:username/spsp
+    let server_secret_clone = server_secret.clone();
+    let get_spsp = warp::get()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id)
+        .and(warp::path("spsp"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(move |id: Uuid, store: S| {
+            let server_secret_clone = server_secret_clone.clone();
+            async move {
+                let accounts = store.get_accounts(vec![id]).await?;
+                // TODO return the response without instantiating an SpspResponder (use a simple fn)
+                let mut responder = SpspResponder::new(
+                    accounts[0].ilp_address().clone(),
+                    server_secret_clone.clone(),
+                );
+                if let Some(display) = DisplayMetadata::from_account(&accounts[0]) {
+                    responder.display(display);
+                }
+                Ok::<_, Rejection>(responder.generate_http_response())
+            }
+        })
+        .boxed();
+
+    // GET /.well-known/pay
+    // This is the endpoint a [Payment Pointer](https://github.com/interledger/rfcs/blob/master/0026-payment-pointers/0026-payment-pointers.md)
+    // with no path resolves to
+    let get_spsp_well_known = warp::get()
+        .and(warp::path(".well-known"))
+        .and(warp::path("pay"))
+        .and(warp::path::end())
+        .and(with_store)
+        .and_then(move |store: S| {
+            let default_spsp_account = default_spsp_account.clone();
+            let server_secret_clone = server_secret.clone();
+            async move {
+                // TODO don't clone this
+                if let Some(username) = default_spsp_account.clone() {
+                    let id = store.get_account_id_from_username(&username).await?;
+
+                    // TODO this shouldn't take multiple store calls
+                    let mut accounts = store.get_accounts(vec![id]).await?;
+
+                    let account = accounts.pop().unwrap();
+                    // TODO return the response without instantiating an SpspResponder (use a simple fn)
+                    let mut responder = SpspResponder::new(
+                        account.ilp_address().clone(),
+                        server_secret_clone.clone(),
+                    );
+                    if let Some(display) = DisplayMetadata::from_account(&account) {
+                        responder.display(display);
+                    }
+                    Ok::<_, Rejection>(responder.generate_http_response())
+                } else {
+                    Err(Rejection::from(
+                        ApiError::not_found().detail("no default spsp account was configured"),
+                    ))
+                }
+            }
+        })
+        .boxed();
+
+    get_spsp.or(get_spsp_well_known).boxed()
+}
@@ -1,24 +1,36 @@
 use crate::{
     routes::{accounts_api, node_settings_api},
-    AccountDetails, AccountSettings, NodeStore,
+    AccountDetails, AccountSettings, NodeExport, NodeStore, DEFAULT_MAX_ASSET_SCALE,
 };
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures::channel::mpsc::UnboundedSender;
-use http::Response;
+use http::{Response, StatusCode};
 use interledger_btp::{BtpAccount, BtpOutgoingService};
 use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
 use interledger_errors::*;
 use interledger_http::{HttpAccount, HttpStore};
 use interledger_packet::{Address, ErrorCode, FulfillBuilder, RejectBuilder};
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{ExchangeRateStore, RateProvenance, RateSource};
 use interledger_router::RouterStore;
 use interledger_service::{
-    incoming_service_fn, outgoing_service_fn, Account, AccountStore, AddressStore, Username,
+    incoming_service_fn, outgoing_service_fn, Account, AccountStore, AddressStore, IlpResult,
+    IncomingRequest, IncomingService, OutgoingRequest, OutgoingService, Username,
+};
+use interledger_service_util::{
+    BalancePause, BalanceStore, LastSettlement, MaintenanceMode, PacketEvent, PeerActivity,
+    SettlementConversion, SettlementDirection, TrafficCounters, TrafficStore,
+};
+use interledger_settlement::core::{
+    idempotency::{IdempotentData, IdempotentStore},
+    types::{SettlementAccount, SettlementEngineDetails},
+    SettlementClient,
+};
+use interledger_spsp::SpspDisplayAccount;
+use interledger_stream::{
+    PaymentNotification, SourcePrefixAccount, StreamNotificationsStore, StreamReceiverService,
+    DEFAULT_PACKET_EXPIRY,
 };
-use interledger_service_util::BalanceStore;
-use interledger_settlement::core::types::{SettlementAccount, SettlementEngineDetails};
-use interledger_stream::{PaymentNotification, StreamNotificationsStore};
 use once_cell::sync::Lazy;
 use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
@@ -26,6 +38,7 @@ use serde_json::Value;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use url::Url;
 use uuid::Uuid;
 use warp::{self, Filter};
@@ -53,22 +66,186 @@ where
     ret.reply(api).await
 }
 
+/// The read-only admin token of the test APIs
+pub const READ_ONLY_TOKEN: &str = "read_only";
+
 pub fn test_node_settings_api(
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    node_settings_api("admin".to_owned(), None, TestStore).recover(default_rejection_handler)
+    node_settings_api(
+        "admin".to_owned(),
+        Some(READ_ONLY_TOKEN.to_owned()),
+        None,
+        None,
+        Vec::new(),
+        None,
+        MaintenanceMode::default(),
+        SettlementClient::default(),
+        TestStore { healthy: true },
+    )
+    .recover(default_rejection_handler)
 }
 
+pub fn test_node_settings_api_with_info(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    node_settings_api(
+        "admin".to_owned(),
+        Some(READ_ONLY_TOKEN.to_owned()),
+        Some("1.2.3".to_owned()),
+        Some(USERNAME.clone()),
+        vec!["redis".to_owned()],
+        None,
+        MaintenanceMode::default(),
+        SettlementClient::default(),
+        TestStore { healthy: true },
+    )
+    .recover(default_rejection_handler)
+}
+
+pub fn test_node_settings_api_with_unhealthy_store(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    node_settings_api(
+        "admin".to_owned(),
+        Some(READ_ONLY_TOKEN.to_owned()),
+        None,
+        None,
+        Vec::new(),
+        None,
+        MaintenanceMode::default(),
+        SettlementClient::default(),
+        TestStore { healthy: false },
+    )
+    .recover(default_rejection_handler)
+}
+
+pub fn test_node_settings_api_with_settlement_client(
+    settlement_client: SettlementClient,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    node_settings_api(
+        "admin".to_owned(),
+        Some(READ_ONLY_TOKEN.to_owned()),
+        None,
+        None,
+        Vec::new(),
+        None,
+        MaintenanceMode::default(),
+        settlement_client,
+        TestStore { healthy: true },
+    )
+    .recover(default_rejection_handler)
+}
+
+pub fn test_node_settings_api_with_packet_events(
+    sender: broadcast::Sender<PacketEvent>,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    node_settings_api(
+        "admin".to_owned(),
+        Some(READ_ONLY_TOKEN.to_owned()),
+        None,
+        None,
+        Vec::new(),
+        Some(sender),
+        MaintenanceMode::default(),
+        SettlementClient::default(),
+        TestStore { healthy: true },
+    )
+    .recover(default_rejection_handler)
+}
+
+pub fn test_node_settings_api_with_maintenance_mode(
+    maintenance_mode: MaintenanceMode,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    node_settings_api(
+        "admin".to_owned(),
+        Some(READ_ONLY_TOKEN.to_owned()),
+        None,
+        None,
+        Vec::new(),
+        None,
+        maintenance_mode,
+        SettlementClient::default(),
+        TestStore { healthy: true },
+    )
+    .recover(default_rejection_handler)
+}
+
+/// Secret of the STREAM server of the API and the loopback receiver
+static SERVER_SECRET: [u8; 32] = [0; 32];
+/// The address of the receiver which fulfills Prepares sent through the accounts API
+pub static RECEIVER_ADDRESS: Lazy<Address> =
+    Lazy::new(|| Address::from_str("example.receiver").unwrap());
+/// The SHA-256 hash of the all-zero fulfillment
+pub const EXECUTION_CONDITION: [u8; 32] = [
+    0x66, 0x68, 0x7a, 0xad, 0xf8, 0x62, 0xbd, 0x77, 0x6c, 0x8f, 0xc1, 0x8b, 0x8e, 0x9f, 0x8e, 0x20,
+    0x08, 0x97, 0x14, 0x85, 0x6e, 0xe2, 0x33, 0xb3, 0x90, 0x2a, 0x59, 0x1d, 0x0d, 0x5f, 0x29, 0x25,
+];
+
 pub fn test_accounts_api(
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    let incoming = incoming_service_fn(|_request| {
-        Err(RejectBuilder {
-            code: ErrorCode::F02_UNREACHABLE,
-            message: b"No other incoming handler!",
-            data: &[],
-            triggered_by: None,
+    // Fulfills the Prepares for the local receiver with the condition of the all-zero
+    // fulfillment, and rejects all others
+    let incoming = incoming_service_fn(|request| {
+        if request.prepare.destination() == *RECEIVER_ADDRESS
+            && request.prepare.execution_condition() == &EXECUTION_CONDITION[..]
+        {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"received",
+            }
+            .build())
+        } else {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: b"No other incoming handler!",
+                data: &[],
+                triggered_by: None,
+            }
+            .build())
         }
-        .build())
     });
+    test_accounts_api_with_incoming(incoming)
+}
+
+/// Returns the accounts API whose incoming packets are all received by a STREAM receiver
+/// for the accounts, like the packets a node routes to its own accounts
+pub fn test_accounts_api_with_loopback(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let receiver = StreamReceiverService::new(
+        bytes04::Bytes::from(&SERVER_SECRET[..]),
+        TestStore { healthy: true },
+        outgoing_service_fn(|_: OutgoingRequest<TestAccount>| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: b"No other outgoing handler!",
+                data: &[],
+                triggered_by: None,
+            }
+            .build())
+        }),
+    );
+    test_accounts_api_with_incoming(LoopbackService(receiver))
+}
+
+#[derive(Clone)]
+struct LoopbackService<O>(O);
+
+#[async_trait]
+impl<O> IncomingService<TestAccount> for LoopbackService<O>
+where
+    O: OutgoingService<TestAccount> + Send + Sync + 'static,
+{
+    async fn handle_request(&mut self, request: IncomingRequest<TestAccount>) -> IlpResult {
+        self.0
+            .send_request(request.into_outgoing(TestAccount))
+            .await
+    }
+}
+
+fn test_accounts_api_with_incoming<I>(
+    incoming: I,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    I: IncomingService<TestAccount> + Clone + Send + Sync + 'static,
+{
     let outgoing = outgoing_service_fn(move |_request| {
         Ok(FulfillBuilder {
             fulfillment: &[0; 32],
@@ -80,14 +257,21 @@ pub fn test_accounts_api(
         Address::from_str("example.alice").unwrap(),
         outgoing.clone(),
     );
-    let store = TestStore;
+    let store = TestStore { healthy: true };
     accounts_api(
-        Bytes::from("admin"),
+        Bytes::from(&SERVER_SECRET[..]),
         "admin".to_owned(),
-        None,
+        Some(READ_ONLY_TOKEN.to_owned()),
         incoming,
         outgoing,
         btp,
+        Vec::new(),
+        PeerActivity::new(),
+        BalancePause::default(),
+        DEFAULT_PACKET_EXPIRY,
+        0..=DEFAULT_MAX_ASSET_SCALE,
+        SettlementClient::default(),
+        reqwest::Client::new(),
         store,
     )
     .recover(default_rejection_handler)
@@ -99,7 +283,10 @@ pub fn test_accounts_api(
  */
 
 #[derive(Clone)]
-struct TestStore;
+struct TestStore {
+    /// Whether the store's database can be reached
+    healthy: bool,
+}
 
 use serde_json::json;
 pub static USERNAME: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
@@ -167,6 +354,10 @@ impl SettlementAccount for TestAccount {
     }
 }
 
+impl SpspDisplayAccount for TestAccount {}
+
+impl SourcePrefixAccount for TestAccount {}
+
 impl CcpRoutingAccount for TestAccount {
     fn routing_relation(&self) -> RoutingRelation {
         RoutingRelation::NonRoutingAccount
@@ -214,6 +405,22 @@ impl ExchangeRateStore for TestStore {
         ret.insert("XYZ".to_owned(), 2.0);
         Ok(ret)
     }
+
+    fn get_rate_provenance(&self) -> Result<Option<RateProvenance>, ExchangeRateStoreError> {
+        Ok(Some(RateProvenance {
+            source: "example-oracle".to_owned(),
+            timestamp: 1_600_000_000,
+            signature: "c2ln".to_owned(),
+            jws: "e30.e30.c2ln".to_owned(),
+        }))
+    }
+
+    fn get_rate_source(&self) -> Result<Option<RateSource>, ExchangeRateStoreError> {
+        Ok(Some(RateSource {
+            provider: "CoinCap".to_owned(),
+            fallback: true,
+        }))
+    }
 }
 
 impl RouterStore for TestStore {
@@ -272,10 +479,22 @@ impl NodeStore for TestStore {
         Ok(())
     }
 
+    async fn get_static_routes(&self) -> Result<HashMap<String, Uuid>, NodeStoreError> {
+        Ok(HashMap::new())
+    }
+
     async fn set_default_route(&self, _account_id: Uuid) -> Result<(), NodeStoreError> {
         unimplemented!()
     }
 
+    async fn set_account_draining(&self, _id: Uuid, _draining: bool) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn set_account_enabled(&self, _id: Uuid, _enabled: bool) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
     async fn set_settlement_engines(
         &self,
         _asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
@@ -289,6 +508,46 @@ impl NodeStore for TestStore {
     ) -> Result<Option<Url>, NodeStoreError> {
         Ok(None)
     }
+
+    async fn check_health(&self) -> Result<(), NodeStoreError> {
+        if self.healthy {
+            Ok(())
+        } else {
+            Err(NodeStoreError::Other(Box::new(std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                "Connection refused",
+            ))))
+        }
+    }
+
+    async fn export_node(&self) -> Result<NodeExport, NodeStoreError> {
+        Ok(NodeExport::default())
+    }
+
+    async fn import_node(&self, _export: NodeExport) -> Result<Vec<Self::Account>, NodeStoreError> {
+        Ok(vec![TestAccount])
+    }
+}
+
+// stub implementation, requests are not actually idempotent in these tests
+#[async_trait]
+impl IdempotentStore for TestStore {
+    async fn load_idempotent_data(
+        &self,
+        _idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        Ok(None)
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        _idempotency_key: String,
+        _input_hash: [u8; 32],
+        _status_code: StatusCode,
+        _data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -319,9 +578,7 @@ impl StreamNotificationsStore for TestStore {
         unimplemented!()
     }
 
-    fn publish_payment_notification(&self, _payment: PaymentNotification) {
-        unimplemented!()
-    }
+    fn publish_payment_notification(&self, _payment: PaymentNotification) {}
 }
 
 #[async_trait]
@@ -330,10 +587,37 @@ impl BalanceStore for TestStore {
         Ok(1)
     }
 
+    async fn get_last_settlement(
+        &self,
+        _: Uuid,
+    ) -> Result<Option<LastSettlement>, BalanceStoreError> {
+        Ok(Some(LastSettlement {
+            amount: -2_000_000_000,
+            timestamp: "2020-02-01T12:00:00+00:00".to_owned(),
+        }))
+    }
+
+    async fn get_last_settlement_conversion(
+        &self,
+        _: Uuid,
+    ) -> Result<Option<SettlementConversion>, BalanceStoreError> {
+        Ok(Some(SettlementConversion {
+            direction: SettlementDirection::Outgoing,
+            amount: 1_500_000_000,
+            settlement_amount: 3_000_000,
+            settlement_asset_code: "USDC".to_owned(),
+            settlement_asset_scale: 6,
+            rate: 2.0,
+            rate_provenance: None,
+            timestamp: "2020-02-01T12:00:00+00:00".to_owned(),
+        }))
+    }
+
     async fn update_balances_for_prepare(
         &self,
         _: Uuid,
         _incoming_amount: u64,
+        _: Option<[u8; 32]>,
     ) -> Result<(), BalanceStoreError> {
         unimplemented!()
     }
@@ -342,6 +626,7 @@ impl BalanceStore for TestStore {
         &self,
         _: Uuid,
         _outgoing_amount: u64,
+        _: Option<[u8; 32]>,
     ) -> Result<(i64, u64), BalanceStoreError> {
         unimplemented!()
     }
@@ -350,11 +635,30 @@ impl BalanceStore for TestStore {
         &self,
         _: Uuid,
         _incoming_amount: u64,
+        _: Option<[u8; 32]>,
     ) -> Result<(), BalanceStoreError> {
         unimplemented!()
     }
 }
 
+#[async_trait]
+impl TrafficStore for TestStore {
+    async fn get_traffic(&self, _: Uuid) -> Result<TrafficCounters, ()> {
+        Ok(TrafficCounters {
+            packets_in: 3,
+            packets_out: 2,
+            bytes_in: 450,
+            bytes_out: 380,
+            amount_in: 1000,
+            amount_out: 500,
+        })
+    }
+
+    async fn add_traffic(&self, _: Uuid, _: TrafficCounters) -> Result<(), ()> {
+        unimplemented!()
+    }
+}
+
 #[async_trait]
 impl HttpStore for TestStore {
     type Account = TestAccount;
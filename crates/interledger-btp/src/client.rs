@@ -1,17 +1,21 @@
 use super::packet::*;
 use super::service::BtpOutgoingService;
 use super::BtpAccount;
-use futures::{future::join_all, SinkExt, StreamExt, TryFutureExt};
+use futures::{channel::oneshot, future::join_all, SinkExt, StreamExt, TryFutureExt};
 use interledger_errors::ApiError;
 use interledger_packet::Address;
 use interledger_service::*;
-use log::{debug, error, trace};
+use log::{debug, error, trace, warn};
 use rand::random;
+use std::time::Duration;
 use thiserror::Error;
 use tokio_tungstenite::connect_async;
 use tungstenite::Message;
 use url::Url;
 
+/// Delay before the first attempt to reconnect, which is doubled on every further attempt
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
 /// Create a BtpOutgoingService wrapping BTP connections to the accounts specified.
 /// Calling `handle_incoming` with an `IncomingService` will turn the returned
 /// BtpOutgoingService into a bidirectional handler.
@@ -42,6 +46,120 @@ where
     Ok(service)
 }
 
+/// Like [`connect_client`](./fn.connect_client.html), but the connections which cannot be
+/// established or go away later are re-established in the background. The attempts to
+/// reconnect to each account are spaced out with [`ReconnectBackoff`](./struct.ReconnectBackoff.html),
+/// and they stop when the connection is closed with `close_connection` or the service is closed.
+pub async fn connect_client_with_reconnect<A, S>(
+    ilp_address: Address,
+    accounts: Vec<A>,
+    max_reconnect_delay: Duration,
+    next_outgoing: S,
+) -> BtpOutgoingService<S, A>
+where
+    S: OutgoingService<A> + Clone + Send + Sync + 'static,
+    A: BtpAccount + Send + Sync + 'static,
+{
+    let service = BtpOutgoingService::new(ilp_address, next_outgoing);
+    let connections = join_all(accounts.into_iter().map(|account| {
+        let service = service.clone();
+        async move {
+            let connection = add_service_account(account.clone(), service).await;
+            (account, connection)
+        }
+    }))
+    .await;
+    for (account, connection) in connections {
+        tokio::spawn(keep_connected(
+            account,
+            service.clone(),
+            connection,
+            ReconnectBackoff::new(max_reconnect_delay),
+        ));
+    }
+    service
+}
+
+/// Exponential backoff between the attempts to reconnect to a BTP peer. The delay starts
+/// at one second and doubles after every failed attempt up to the max delay. Each wait is
+/// randomly shortened by up to half, so that the nodes which lost their connection to the
+/// same peer at once do not all reconnect at the same time.
+#[derive(Clone, Debug)]
+pub struct ReconnectBackoff {
+    max_delay: Duration,
+    failed_attempts: u32,
+}
+
+impl ReconnectBackoff {
+    pub fn new(max_delay: Duration) -> Self {
+        ReconnectBackoff {
+            max_delay,
+            failed_attempts: 0,
+        }
+    }
+
+    /// Returns the delay (without the jitter) before the next attempt
+    pub fn delay(&self) -> Duration {
+        INITIAL_RECONNECT_DELAY
+            .checked_mul(2u32.saturating_pow(self.failed_attempts))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// Records a failed attempt and returns how long to wait before the next one
+    pub fn failed(&mut self) -> Duration {
+        let delay = self.delay();
+        self.failed_attempts = self.failed_attempts.saturating_add(1);
+        let half = delay / 2;
+        half + half.mul_f64(random::<f64>())
+    }
+
+    /// Returns to the initial delay once a connection was established
+    pub fn reset(&mut self) {
+        self.failed_attempts = 0;
+    }
+}
+
+/// Waits for the connection to go away and reconnects, until a connection is closed on purpose
+async fn keep_connected<O, A>(
+    account: A,
+    service: BtpOutgoingService<O, A>,
+    mut connection: Result<oneshot::Receiver<()>, BtpClientError>,
+    mut backoff: ReconnectBackoff,
+) where
+    O: OutgoingService<A> + Clone + 'static,
+    A: BtpAccount + Send + Sync + 'static,
+{
+    loop {
+        match connection {
+            Ok(closed) => {
+                backoff.reset();
+                let _ = closed.await;
+                if service.is_closed() || !service.connection_dropped(&account.id()) {
+                    return;
+                }
+                warn!(
+                    "BTP connection to account {} (id: {}) went away",
+                    account.username(),
+                    account.id()
+                );
+            }
+            Err(err) => warn!("{}", err),
+        }
+        let delay = backoff.failed();
+        debug!(
+            "Reconnecting to account {} in {}ms",
+            account.username(),
+            delay.as_millis()
+        );
+        tokio::time::delay_for(delay).await;
+        if service.is_closed() {
+            return;
+        }
+        connection = add_service_account(account.clone(), service.clone()).await;
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum BtpClientError {
     #[error("Cannot connect to BTP url: {0}. Got error {1}")]
@@ -69,6 +187,23 @@ pub async fn connect_to_service_account<O, A>(
     error_on_unavailable: bool,
     service: BtpOutgoingService<O, A>,
 ) -> Result<(), BtpClientError>
+where
+    O: OutgoingService<A> + Clone + 'static,
+    A: BtpAccount + Send + Sync + 'static,
+{
+    match add_service_account(account, service).await {
+        Ok(_) => Ok(()),
+        Err(BtpClientError::Unavailable(_)) if !error_on_unavailable => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Connects to the account and adds the connection to the service, returning
+/// a receiver which completes when the connection goes away
+async fn add_service_account<O, A>(
+    account: A,
+    service: BtpOutgoingService<O, A>,
+) -> Result<oneshot::Receiver<()>, BtpClientError>
 where
     O: OutgoingService<A> + Clone + 'static,
     A: BtpAccount + Send + Sync + 'static,
@@ -139,17 +274,49 @@ where
         Ok(_) => {
             debug!("Connected to account {}'s server", account.id());
             let connection = connection.filter_map(|v| async move { v.ok() });
-            service.add_connection(account, connection);
-            Ok(())
+            Ok(service.add_connection(account, Some(url.to_string()), connection))
         }
         Err(err) => {
             let msg = format!("Error sending auth packet on connection {}: {}", url, err);
             error!("{}", msg);
-            if error_on_unavailable {
-                Err(BtpClientError::Unavailable(msg))
-            } else {
-                Ok(())
-            }
+            Err(BtpClientError::Unavailable(msg))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_up_to_max_delay() {
+        let mut backoff = ReconnectBackoff::new(Duration::from_secs(5));
+        for expected in [1, 2, 4, 5, 5].iter() {
+            let expected = Duration::from_secs(*expected);
+            assert_eq!(backoff.delay(), expected);
+            let wait = backoff.failed();
+            assert!(wait <= expected);
+            assert!(wait >= expected / 2);
+        }
+    }
+
+    #[test]
+    fn backoff_is_reset_after_success() {
+        let mut backoff = ReconnectBackoff::new(Duration::from_secs(60));
+        for _ in 0..4 {
+            backoff.failed();
+        }
+        assert_eq!(backoff.delay(), Duration::from_secs(16));
+        backoff.reset();
+        assert_eq!(backoff.delay(), INITIAL_RECONNECT_DELAY);
+    }
+
+    #[test]
+    fn backoff_does_not_overflow() {
+        let mut backoff = ReconnectBackoff::new(Duration::from_secs(30));
+        for _ in 0..100 {
+            assert!(backoff.failed() <= Duration::from_secs(30));
         }
+        assert_eq!(backoff.delay(), Duration::from_secs(30));
     }
 }
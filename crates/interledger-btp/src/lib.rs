@@ -18,9 +18,11 @@ mod server;
 mod service;
 mod wrapped_ws;
 
-pub use self::client::{connect_client, connect_to_service_account};
+pub use self::client::{
+    connect_client, connect_client_with_reconnect, connect_to_service_account, ReconnectBackoff,
+};
 pub use self::server::btp_service_as_filter; // This is consumed only by the node.
-pub use self::service::{BtpOutgoingService, BtpService};
+pub use self::service::{BtpConnections, BtpOutgoingService, BtpService};
 
 use interledger_errors::BtpStoreError;
 
@@ -261,8 +263,11 @@ mod client_server {
             })
             .await;
         assert!(res.is_ok());
+        assert!(btp_service.connections().is_connected(&server_acc_id));
+        assert!(!btp_service.connections().is_connected(&Uuid::new_v4()));
 
         btp_service.close_connection(&server_acc_id);
+        assert!(!btp_service.connections().is_connected(&server_acc_id));
         // after removing the connection this will fail
         let mut btp_client_clone = btp_client.clone();
         let res = btp_client_clone
@@ -305,4 +310,205 @@ mod client_server {
 
         btp_service.close();
     }
+
+    /// Skips the Pings the server sends
+    async fn next_btp_packet<S, E>(connection: &mut S) -> packet::BtpPacket
+    where
+        S: futures::Stream<Item = Result<tungstenite::Message, E>> + Unpin,
+    {
+        use futures::StreamExt;
+        use packet::Serializable;
+        loop {
+            if let Ok(tungstenite::Message::Binary(data)) = connection.next().await.unwrap() {
+                return packet::BtpPacket::from_bytes(&data).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_packets() {
+        use crate::packet::*;
+        use bytes::BytesMut;
+        use futures::SinkExt;
+        use interledger_packet::Reject;
+        use std::convert::TryFrom;
+        use tungstenite::Message;
+
+        let receiver = metrics_runtime::Receiver::builder().build().unwrap();
+        let controller = receiver.controller();
+        receiver.install();
+
+        let bind_addr = get_open_port();
+        let server_store = TestStore {
+            accounts: Arc::new(vec![TestAccount {
+                id: Uuid::new_v4(),
+                ilp_over_btp_incoming_token: Some("test_auth_token".to_string()),
+                ilp_over_btp_outgoing_token: None,
+                ilp_over_btp_url: None,
+            }]),
+        };
+        let btp_service = BtpOutgoingService::new(
+            Address::from_str("example.server").unwrap(),
+            outgoing_service_fn(|_| unreachable!()),
+        );
+        btp_service
+            .clone()
+            .handle_incoming(incoming_service_fn(|_| -> IlpResult {
+                panic!("malformed packets must not be passed to the incoming service")
+            }))
+            .await;
+        let filter = btp_service_as_filter(btp_service.clone(), server_store);
+        tokio::spawn(warp::serve(filter).bind(bind_addr));
+
+        let (mut connection, _) =
+            tokio_tungstenite::connect_async(format!("ws://{}/accounts/alice/ilp/btp", bind_addr))
+                .await
+                .unwrap();
+        let btp_message = |protocol_name: &str, data: &[u8]| {
+            vec![ProtocolData {
+                protocol_name: protocol_name.to_string(),
+                content_type: ContentType::ApplicationOctetStream,
+                data: data.to_vec(),
+            }]
+        };
+        let auth = BtpMessage {
+            request_id: 0,
+            protocol_data: btp_message("auth_token", b"test_auth_token"),
+        };
+        connection
+            .send(Message::binary(auth.to_bytes()))
+            .await
+            .unwrap();
+
+        // The auth response
+        next_btp_packet(&mut connection).await;
+
+        for request_id in 1..=2 {
+            let malformed = BtpMessage {
+                request_id,
+                protocol_data: btp_message("ilp", b"not an ILP packet"),
+            };
+            connection
+                .send(Message::binary(malformed.to_bytes()))
+                .await
+                .unwrap();
+            // Malformed responses are dropped, so the next message the server
+            // sends is the Reject of the following request
+            let malformed_response = BtpResponse {
+                request_id: request_id + 100,
+                protocol_data: btp_message("ilp", b"not an ILP packet"),
+            };
+            connection
+                .send(Message::binary(malformed_response.to_bytes()))
+                .await
+                .unwrap();
+
+            let (response_id, protocol_data) = match next_btp_packet(&mut connection).await {
+                BtpPacket::Message(message) => (message.request_id, message.protocol_data),
+                BtpPacket::Response(response) => (response.request_id, response.protocol_data),
+                BtpPacket::Error(error) => panic!("Got BTP error: {:?}", error),
+            };
+            assert_eq!(response_id, request_id);
+            let reject = Reject::try_from(BytesMut::from(protocol_data[0].data.clone())).unwrap();
+            assert_eq!(reject.code(), ErrorCode::F01_INVALID_PACKET);
+        }
+
+        let malformed = controller
+            .snapshot()
+            .into_measurements()
+            .into_iter()
+            .find(|(key, _)| key.name() == "requests.incoming.malformed")
+            .map(|(key, measurement)| {
+                let labels: Vec<_> = key
+                    .labels()
+                    .map(|label| (label.key().to_string(), label.value().to_string()))
+                    .collect();
+                (labels, measurement)
+            });
+        match malformed {
+            Some((labels, metrics_runtime::Measurement::Counter(count))) => {
+                assert_eq!(
+                    labels,
+                    vec![
+                        ("transport".to_string(), "btp".to_string()),
+                        ("from_username".to_string(), "alice".to_string())
+                    ]
+                );
+                assert_eq!(count, 4);
+            }
+            other => panic!("Unexpected malformed packet metric: {:?}", other),
+        }
+
+        btp_service.close();
+    }
+
+    #[tokio::test]
+    async fn terminates_sessions() {
+        use crate::packet::*;
+        use futures::{SinkExt, StreamExt};
+        use tungstenite::Message;
+
+        let bind_addr = get_open_port();
+        let account_id = Uuid::new_v4();
+        let server_store = TestStore {
+            accounts: Arc::new(vec![TestAccount {
+                id: account_id,
+                ilp_over_btp_incoming_token: Some("test_auth_token".to_string()),
+                ilp_over_btp_outgoing_token: None,
+                ilp_over_btp_url: None,
+            }]),
+        };
+        let btp_service = BtpOutgoingService::new(
+            Address::from_str("example.server").unwrap(),
+            outgoing_service_fn(|_| unreachable!()),
+        );
+        let filter = btp_service_as_filter(btp_service.clone(), server_store);
+        tokio::spawn(warp::serve(filter).bind(bind_addr));
+
+        let (mut connection, _) =
+            tokio_tungstenite::connect_async(format!("ws://{}/accounts/alice/ilp/btp", bind_addr))
+                .await
+                .unwrap();
+        let auth = BtpMessage {
+            request_id: 0,
+            protocol_data: vec![ProtocolData {
+                protocol_name: "auth_token".to_string(),
+                content_type: ContentType::TextPlainUtf8,
+                data: b"test_auth_token".to_vec(),
+            }],
+        };
+        connection
+            .send(Message::binary(auth.to_bytes()))
+            .await
+            .unwrap();
+        next_btp_packet(&mut connection).await;
+        // The connection is added right after the auth response was sent
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+
+        let connections = btp_service.connections();
+        let sessions = connections.sessions();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].account_id, account_id);
+        assert!(sessions[0]
+            .remote_addr
+            .as_ref()
+            .unwrap()
+            .starts_with("127.0.0.1:"));
+        assert!(connections.is_connected(&account_id));
+
+        assert!(connections.terminate(&sessions[0].id));
+        assert!(connections.sessions().is_empty());
+        assert!(!connections.terminate(&sessions[0].id));
+
+        // The server closes the WebSocket
+        loop {
+            match connection.next().await {
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => continue,
+                Some(Err(err)) => panic!("WebSocket was not closed cleanly: {:?}", err),
+            }
+        }
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert!(!connections.is_connected(&account_id));
+    }
 }
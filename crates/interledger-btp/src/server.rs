@@ -5,7 +5,7 @@ use futures::{SinkExt, StreamExt, TryFutureExt};
 use interledger_service::*;
 use log::{debug, error, warn};
 use secrecy::{ExposeSecret, SecretString};
-use std::time::Duration;
+use std::{net::SocketAddr, time::Duration};
 use warp::{
     self,
     ws::{Message, WebSocket, Ws},
@@ -36,17 +36,26 @@ where
         .and(warp::path("btp"))
         .and(warp::path::end())
         .and(warp::ws())
-        .map(move |username: Username, ws: Ws| {
-            // warp Websocket
-            let service_clone = service.clone();
-            let store_clone = store.clone();
-            ws.max_message_size(MAX_MESSAGE_SIZE)
-                .on_upgrade(|socket: WebSocket| {
-                    // wrapper over tungstenite Websocket
-                    add_connections(socket, username, service_clone, store_clone)
+        .and(warp::addr::remote())
+        .map(
+            move |username: Username, ws: Ws, remote_addr: Option<SocketAddr>| {
+                // warp Websocket
+                let service_clone = service.clone();
+                let store_clone = store.clone();
+                ws.max_message_size(MAX_MESSAGE_SIZE)
+                    .on_upgrade(move |socket: WebSocket| {
+                        // wrapper over tungstenite Websocket
+                        add_connections(
+                            socket,
+                            username,
+                            remote_addr.map(|addr| addr.to_string()),
+                            service_clone,
+                            store_clone,
+                        )
                         .map(|result| result.unwrap())
-                })
-        })
+                    })
+            },
+        )
         .boxed()
 }
 
@@ -57,6 +66,7 @@ where
 async fn add_connections<O, S, A>(
     socket: WebSocket,
     username: Username,
+    remote_addr: Option<String>,
     service: BtpOutgoingService<O, A>,
     store: S,
 ) -> Result<(), ()>
@@ -86,7 +96,7 @@ where
     // We need to wrap our Warp connection in order to cast the Sink type
     // to tungstenite::Message. This probably can be implemented with SinkExt::with
     // but couldn't figure out how.
-    service.add_connection(account.clone(), WsWrap { connection });
+    service.add_connection(account.clone(), remote_addr, WsWrap { connection });
     debug!(
         "Added connection for account {}: (id: {})",
         account.username(),
@@ -1,6 +1,7 @@
 use super::{packet::*, BtpAccount};
 use async_trait::async_trait;
 use bytes::BytesMut;
+use chrono::{DateTime, Utc};
 use futures::{
     channel::{
         mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
@@ -11,6 +12,7 @@ use futures::{
 use interledger_packet::{Address, ErrorCode, Fulfill, Packet, Prepare, Reject, RejectBuilder};
 use interledger_service::*;
 use log::{debug, error, trace, warn};
+use metrics::counter;
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, RwLock};
 use rand::random;
@@ -33,6 +35,78 @@ const SEND_MSG_TIMEOUT: Duration = Duration::from_secs(30);
 
 type IlpResultChannel = oneshot::Sender<Result<Fulfill, Reject>>;
 type IncomingRequestBuffer<A> = UnboundedReceiver<(A, u32, Prepare)>;
+type Connections = Arc<RwLock<HashMap<Uuid, UnboundedSender<Message>>>>;
+type Sessions = Arc<Mutex<HashMap<Uuid, Session>>>;
+
+/// An open WebSocket connection of a [`BtpOutgoingService`](./struct.BtpOutgoingService.html)
+#[derive(Clone, Debug, PartialEq)]
+pub struct BtpSession {
+    /// Identifies the session, e.g. to terminate it
+    pub id: Uuid,
+    /// The account which is connected
+    pub account_id: Uuid,
+    /// The address of the peer's socket for incoming connections,
+    /// or the URL we connected to for outgoing ones
+    pub remote_addr: Option<String>,
+    pub connected_since: DateTime<Utc>,
+}
+
+struct Session {
+    info: BtpSession,
+    /// Closes the WebSocket when it is dropped
+    terminate: Trigger,
+}
+
+/// Handle to the WebSocket connections of a
+/// [`BtpOutgoingService`](./struct.BtpOutgoingService.html), used to
+/// report which accounts are currently connected and to terminate their sessions
+#[derive(Clone)]
+pub struct BtpConnections {
+    connections: Connections,
+    sessions: Sessions,
+}
+
+impl BtpConnections {
+    /// Returns whether the account has an open BTP connection. A connection is considered
+    /// closed as soon as we stop forwarding messages to its WebSocket, which happens at the
+    /// latest when the next Ping to a peer which went away fails.
+    pub fn is_connected(&self, account_id: &Uuid) -> bool {
+        self.connections
+            .read()
+            .get(account_id)
+            .map(|sender| !sender.is_closed())
+            .unwrap_or(false)
+    }
+
+    /// Returns the open sessions, oldest first
+    pub fn sessions(&self) -> Vec<BtpSession> {
+        let mut sessions: Vec<BtpSession> = self
+            .sessions
+            .lock()
+            .values()
+            .map(|session| session.info.clone())
+            .collect();
+        sessions.sort_by_key(|session| session.connected_since);
+        sessions
+    }
+
+    /// Closes the WebSocket of the session, returning false if there is no such session.
+    /// Peers may reconnect afterwards, as may we if the session is one of our
+    /// outgoing connections (the same as if the connection had dropped).
+    pub fn terminate(&self, session_id: &Uuid) -> bool {
+        match self.sessions.lock().remove(session_id) {
+            Some(session) => {
+                debug!(
+                    "Terminating BTP session {} of account {}",
+                    session_id, session.info.account_id
+                );
+                drop(session.terminate);
+                true
+            }
+            None => false,
+        }
+    }
+}
 
 /// The BtpOutgoingService wraps all BTP/WebSocket connections that come
 /// in on the given address. It implements OutgoingService for sending
@@ -46,7 +120,8 @@ type IncomingRequestBuffer<A> = UnboundedReceiver<(A, u32, Prepare)>;
 pub struct BtpOutgoingService<O, A: Account> {
     ilp_address: Address,
     /// Outgoing messages for the receiver of the websocket indexed by account uid
-    connections: Arc<RwLock<HashMap<Uuid, UnboundedSender<Message>>>>,
+    connections: Connections,
+    sessions: Sessions,
     pending_outgoing: Arc<Mutex<HashMap<u32, IlpResultChannel>>>,
     pending_incoming: Arc<Mutex<Option<IncomingRequestBuffer<A>>>>,
     incoming_sender: UnboundedSender<(A, u32, Prepare)>,
@@ -68,6 +143,7 @@ async fn handle_message<A: BtpAccount>(
     message: Message,
     tx_clone: UnboundedSender<Message>,
     account: A,
+    ilp_address: Address,
     pending_requests: Arc<Mutex<HashMap<u32, IlpResultChannel>>>,
     incoming_sender: UnboundedSender<(A, u32, Prepare)>,
 ) {
@@ -107,9 +183,34 @@ async fn handle_message<A: BtpAccount>(
                     );
                 }
             }
-            Err(_) => {
+            Err(IlpPacketError::Missing) => {
                 debug!("Unable to parse ILP packet from BTP packet (if this is the first time this appears, the packet was probably the auth response)");
-                // TODO Send error back
+            }
+            Err(IlpPacketError::Malformed { request_id }) => {
+                warn!(
+                    "Got malformed ILP packet from account {} (id: {})",
+                    account.username(),
+                    account.id()
+                );
+                counter!("requests.incoming.malformed", 1, "transport" => "btp", "from_username" => account.username().to_string());
+                // Only requests are answered, malformed responses are dropped
+                if let Some(request_id) = request_id {
+                    let reject = RejectBuilder {
+                        code: ErrorCode::F01_INVALID_PACKET,
+                        message: b"Invalid ILP packet",
+                        triggered_by: Some(&ilp_address),
+                        data: &[],
+                    }
+                    .build();
+                    let _ = tx_clone
+                        .unbounded_send(ilp_packet_to_ws_message(
+                            request_id,
+                            Packet::Reject(reject),
+                        ))
+                        .map_err(|err| {
+                            error!("Error sending Reject for malformed packet: {:?}", err)
+                        });
+                }
             }
         }
     } else if message.is_ping() {
@@ -132,6 +233,7 @@ where
         BtpOutgoingService {
             ilp_address,
             connections: Arc::new(RwLock::new(HashMap::new())),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
             pending_outgoing: Arc::new(Mutex::new(HashMap::new())),
             pending_incoming: Arc::new(Mutex::new(Some(incoming_receiver))),
             incoming_sender,
@@ -146,6 +248,15 @@ where
         self.connections.write().remove(account_id);
     }
 
+    /// Returns a handle for checking which accounts are connected to this service
+    /// and for terminating their sessions
+    pub fn connections(&self) -> BtpConnections {
+        BtpConnections {
+            connections: self.connections.clone(),
+            sessions: self.sessions.clone(),
+        }
+    }
+
     /// Close all of the open WebSocket connections
     // TODO is there some more automatic way of knowing when we should close the connections?
     // The problem is that the WS client can be a server too, so it's not clear when we are done with it
@@ -154,31 +265,70 @@ where
         self.close_all_connections.lock().take();
     }
 
+    /// Returns whether `close` was called
+    pub(crate) fn is_closed(&self) -> bool {
+        self.close_all_connections.lock().is_none()
+    }
+
+    /// Returns whether the account's connection went away without being closed or
+    /// replaced with `close_connection` or `add_connection`
+    pub(crate) fn connection_dropped(&self, account_id: &Uuid) -> bool {
+        self.connections
+            .read()
+            .get(account_id)
+            .map(|sender| sender.is_closed())
+            .unwrap_or(false)
+    }
+
     // Set up a WebSocket connection so that outgoing Prepare packets can be sent to it,
     // incoming Prepare packets are buffered in a channel (until an IncomingService is added
     // via the handle_incoming method), and ILP Fulfill and Reject packets will be
     // sent back to the Future that sent the outgoing request originally.
+    // The returned receiver completes once we stopped forwarding messages to the WebSocket.
     pub(crate) fn add_connection(
         &self,
         account: A,
+        remote_addr: Option<String>,
         ws_stream: impl Stream<Item = Message> + Sink<Message> + Send + 'static,
-    ) {
+    ) -> oneshot::Receiver<()> {
         let account_id = account.id();
         // Set up a channel to forward outgoing packets to the WebSocket connection
         let (client_tx, client_rx) = unbounded();
         let (write, read) = ws_stream.split();
         let (close_connection, valve) = Valve::new();
+        let (closed_tx, closed_rx) = oneshot::channel();
+
+        // Register the session, which can be terminated by dropping its trigger. This ends the
+        // stream of outgoing messages, so that the WebSocket is closed with a Close frame.
+        let session_id = Uuid::new_v4();
+        let (terminate, terminate_valve) = Valve::new();
+        self.sessions.lock().insert(
+            session_id,
+            Session {
+                info: BtpSession {
+                    id: session_id,
+                    account_id,
+                    remote_addr,
+                    connected_since: Utc::now(),
+                },
+                terminate,
+            },
+        );
+        let sessions = self.sessions.clone();
 
         // tx -> rx -> write -> our peer
         // Responsible mainly for responding to Pings
+        let client_rx = terminate_valve.wrap(client_rx);
         let write_to_ws = client_rx.map(Ok).forward(write).then(move |_| {
             async move {
                 debug!(
                     "Finished forwarding to WebSocket stream for account: {}",
                     account_id
                 );
+                sessions.lock().remove(&session_id);
                 // When this is dropped, the read valve will close
                 drop(close_connection);
+                let _ = closed_tx.send(());
                 Ok::<(), ()>(())
             }
         });
@@ -188,11 +338,13 @@ where
         let pending_outgoing = self.pending_outgoing.clone();
         let incoming_sender = self.incoming_sender.clone();
         let client_tx_clone = client_tx.clone();
+        let ilp_address = self.ilp_address.clone();
         let handle_message_fn = move |msg: Message| {
             handle_message(
                 msg,
                 client_tx_clone.clone(),
                 account.clone(),
+                ilp_address.clone(),
                 pending_outgoing.clone(),
                 incoming_sender.clone(),
             )
@@ -229,6 +381,7 @@ where
 
         // Save the sender side of the channel so we have a way to forward outgoing requests to the WebSocket
         self.connections.write().insert(account_id, client_tx);
+        closed_rx
     }
 
     /// Convert this BtpOutgoingService into a bidirectional BtpService by adding a handler for incoming requests.
@@ -445,44 +598,60 @@ where
     }
 }
 
-fn parse_ilp_packet(message: Message) -> Result<(u32, Packet), ()> {
+/// Why a WebSocket message could not be handled as an ILP packet
+enum IlpPacketError {
+    /// The message does not carry an ILP packet (like the response to the auth message)
+    Missing,
+    /// The message is not a valid BTP packet or its ILP packet cannot be parsed.
+    /// The request ID is set if the peer sent a request and is waiting for a response.
+    Malformed { request_id: Option<u32> },
+}
+
+fn parse_ilp_packet(message: Message) -> Result<(u32, Packet), IlpPacketError> {
     if let Message::Binary(data) = message {
-        let (request_id, ilp_data) = match BtpPacket::from_bytes(&data) {
+        let (request_id, ilp_data, is_request) = match BtpPacket::from_bytes(&data) {
             Ok(BtpPacket::Message(message)) => {
                 let ilp_data = message
                     .protocol_data
                     .into_iter()
                     .find(|proto| proto.protocol_name == "ilp")
-                    .ok_or(())?
+                    .ok_or(IlpPacketError::Missing)?
                     .data;
-                (message.request_id, ilp_data)
+                (message.request_id, ilp_data, true)
             }
             Ok(BtpPacket::Response(response)) => {
                 let ilp_data = response
                     .protocol_data
                     .into_iter()
                     .find(|proto| proto.protocol_name == "ilp")
-                    .ok_or(())?
+                    .ok_or(IlpPacketError::Missing)?
                     .data;
-                (response.request_id, ilp_data)
+                (response.request_id, ilp_data, false)
             }
             Ok(BtpPacket::Error(error)) => {
                 error!("Got BTP error: {:?}", error);
-                return Err(());
+                return Err(IlpPacketError::Missing);
             }
             Err(err) => {
                 error!("Error parsing BTP packet: {:?}", err);
-                return Err(());
+                return Err(IlpPacketError::Malformed { request_id: None });
             }
         };
-        if let Ok(packet) = Packet::try_from(BytesMut::from(ilp_data)) {
-            Ok((request_id, packet))
-        } else {
-            Err(())
+        match Packet::try_from(BytesMut::from(ilp_data)) {
+            Ok(packet) => Ok((request_id, packet)),
+            Err(err) => {
+                debug!(
+                    "Error parsing ILP packet of BTP packet {}: {}",
+                    request_id, err
+                );
+                Err(IlpPacketError::Malformed {
+                    request_id: if is_request { Some(request_id) } else { None },
+                })
+            }
         }
     } else {
         error!("Got a non-binary WebSocket message");
-        Err(())
+        Err(IlpPacketError::Missing)
     }
 }
 
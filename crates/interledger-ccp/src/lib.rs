@@ -25,6 +25,7 @@ mod server;
 mod test_helpers;
 
 pub use packet::{Mode, RouteControlRequest};
+pub use routing_table::RouteLimitPolicy;
 pub use server::{CcpRouteManager, CcpRouteManagerBuilder};
 
 use serde::{Deserialize, Serialize};
@@ -82,6 +83,14 @@ pub trait CcpRoutingAccount: Account {
     /// The type of relationship we have with this account
     fn routing_relation(&self) -> RoutingRelation;
 
+    /// The priority of the routes advertised by this account. If several accounts with the
+    /// same routing relation advertise a route to the same prefix, the one with the highest
+    /// priority is used (regardless of the length of the routes' paths), and the others are
+    /// only used if it withdraws the route. Defaults to 0.
+    fn route_priority(&self) -> u32 {
+        0
+    }
+
     /// Indicates whether we should send CCP Route Updates to this account
     fn should_send_routes(&self) -> bool {
         self.routing_relation() == RoutingRelation::Child
@@ -127,4 +136,14 @@ pub trait CcpRoutingStore: Clone {
         &mut self,
         routes: impl IntoIterator<Item = (String, Self::Account)> + Send + 'async_trait,
     ) -> Result<(), CcpRoutingStoreError>;
+
+    /// Sets the other accounts through which prefixes can be reached at the same cost
+    /// as through the accounts passed to `set_routes` (prefix -> account ids), so that
+    /// payments can be spread across them. These are discarded by default.
+    async fn set_multipath_routes(
+        &mut self,
+        _routes: HashMap<String, Vec<Uuid>>,
+    ) -> Result<(), CcpRoutingStoreError> {
+        Ok(())
+    }
 }
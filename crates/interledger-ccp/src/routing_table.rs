@@ -1,31 +1,82 @@
 use crate::packet::{Route, RouteUpdateRequest};
 use hex;
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use once_cell::sync::Lazy;
 use ring::rand::{SecureRandom, SystemRandom};
-use std::collections::HashMap;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 use std::iter::FromIterator;
+use std::time::Instant;
 
 static RANDOM: Lazy<SystemRandom> = Lazy::new(SystemRandom::new);
 
+/// What to do with the new routes a peer advertises once its routing table is full
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteLimitPolicy {
+    /// Ignore the new prefixes and log a warning
+    Refuse,
+    /// Remove the routes which were learned first to make room for the new ones
+    EvictOldest,
+}
+
+impl Default for RouteLimitPolicy {
+    fn default() -> Self {
+        RouteLimitPolicy::Refuse
+    }
+}
+
 #[derive(Debug, Clone)]
 struct PrefixMap<T> {
-    map: HashMap<String, T>,
+    /// Each item is stored with the number of its insertion
+    map: HashMap<String, (u64, T)>,
+    /// The prefixes by the number of their insertion, oldest first
+    order: BTreeMap<u64, String>,
+    next: u64,
 }
 
 impl<T> PrefixMap<T> {
     pub fn new() -> Self {
         PrefixMap {
             map: HashMap::new(),
+            order: BTreeMap::new(),
+            next: 0,
         }
     }
 
     pub fn insert(&mut self, prefix: String, item: T) -> bool {
-        self.map.insert(prefix, item).is_none()
+        // Replacing an item keeps the insertion number of the prefix
+        if let Some(entry) = self.map.get_mut(&prefix) {
+            entry.1 = item;
+            return false;
+        }
+        self.order.insert(self.next, prefix.clone());
+        self.map.insert(prefix, (self.next, item));
+        self.next += 1;
+        true
     }
 
     pub fn remove(&mut self, prefix: &str) -> bool {
-        self.map.remove(prefix).is_some()
+        match self.map.remove(prefix) {
+            Some((number, _)) => {
+                self.order.remove(&number);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn contains(&self, prefix: &str) -> bool {
+        self.map.contains_key(prefix)
+    }
+
+    /// Returns the prefix which was inserted first
+    pub fn oldest(&self) -> Option<&String> {
+        self.order.values().next()
     }
 
     pub fn resolve(&self, prefix: &str) -> Option<&T> {
@@ -34,7 +85,11 @@ impl<T> PrefixMap<T> {
             .iter()
             .filter(|(p, _)| prefix.starts_with(p.as_str()))
             .max_by_key(|(p, _)| p.len())
-            .map(|(_prefix, item)| item)
+            .map(|(_prefix, (_, item))| item)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &T)> {
+        self.map.iter().map(|(prefix, (_, item))| (prefix, item))
     }
 }
 
@@ -47,6 +102,11 @@ pub struct RoutingTable<A> {
     id: [u8; 16],
     epoch: u32,
     prefix_map: PrefixMap<(A, Route)>,
+    /// The maximum number of routes and what to do with the routes beyond it
+    route_limit: Option<(usize, RouteLimitPolicy)>,
+    /// When the peer last sent an update (or heartbeat) for this table,
+    /// which confirms that its routes are still current
+    last_updated: Instant,
 }
 
 impl<A> RoutingTable<A>
@@ -58,9 +118,16 @@ where
             id,
             epoch: 0,
             prefix_map: PrefixMap::new(),
+            route_limit: None,
+            last_updated: Instant::now(),
         }
     }
 
+    /// Limits the number of routes `handle_update_request` learns
+    pub(crate) fn set_route_limit(&mut self, max_routes: usize, policy: RouteLimitPolicy) {
+        self.route_limit = Some((max_routes, policy));
+    }
+
     #[cfg(test)]
     pub(crate) fn set_id(&mut self, id: [u8; 16]) {
         self.id = id;
@@ -72,6 +139,11 @@ where
         self.epoch = epoch;
     }
 
+    #[cfg(test)]
+    pub(crate) fn set_last_updated(&mut self, last_updated: Instant) {
+        self.last_updated = last_updated;
+    }
+
     pub(crate) fn id(&self) -> [u8; 16] {
         self.id
     }
@@ -80,6 +152,11 @@ where
         self.epoch
     }
 
+    /// When the table was last refreshed by an update request
+    pub(crate) fn last_updated(&self) -> Instant {
+        self.last_updated
+    }
+
     pub(crate) fn increment_epoch(&mut self) -> u32 {
         let epoch = self.epoch;
         self.epoch += 1;
@@ -108,10 +185,14 @@ where
         self.prefix_map.resolve(prefix)
     }
 
+    /// Iterate over the prefixes and their routes
+    pub(crate) fn get_routes(&self) -> impl Iterator<Item = (&String, &(A, Route))> {
+        self.prefix_map.iter()
+    }
+
     pub(crate) fn get_simplified_table(&self) -> HashMap<String, A> {
         HashMap::from_iter(
             self.prefix_map
-                .map
                 .iter()
                 .map(|(address, (account, _route))| (address.clone(), account.clone())),
         )
@@ -142,6 +223,9 @@ where
             ));
         }
 
+        // Even updates from old epochs show that the peer still has the same table
+        self.last_updated = Instant::now();
+
         // It is OK to receive epochs with the same index as our current epoch
         if request.to_epoch_index < self.epoch {
             trace!(
@@ -172,12 +256,39 @@ where
             }
         }
 
+        let mut refused_routes = 0;
         for route in request.new_routes.into_iter() {
             let prefix = route.prefix.clone();
+            if let Some((max_routes, policy)) = self.route_limit {
+                if !self.prefix_map.contains(&prefix) && self.prefix_map.len() >= max_routes {
+                    let oldest = match policy {
+                        RouteLimitPolicy::EvictOldest => self.prefix_map.oldest().cloned(),
+                        RouteLimitPolicy::Refuse => None,
+                    };
+                    match oldest {
+                        Some(oldest) => {
+                            self.delete_route(&oldest);
+                            changed_prefixes.push(oldest);
+                        }
+                        // A limit of 0 leaves nothing to evict
+                        None => {
+                            refused_routes += 1;
+                            continue;
+                        }
+                    }
+                }
+            }
             if self.add_route(account.clone(), route) {
                 changed_prefixes.push(prefix);
             }
         }
+        if refused_routes > 0 {
+            warn!(
+                "Routing table {} is full, refused {} new routes",
+                hex::encode(&self.id[..]),
+                refused_routes
+            );
+        }
 
         trace!(
             "Updated routing table {} to epoch: {}",
@@ -231,6 +342,20 @@ mod prefix_map {
         assert_eq!(map.resolve("example.a.b.c.d.e").unwrap(), &2);
         assert!(map.resolve("example.other").is_none());
     }
+
+    #[test]
+    fn tracks_oldest_entry() {
+        let mut map = PrefixMap::new();
+        map.insert("example.a".to_string(), 1);
+        map.insert("example.b".to_string(), 2);
+        // Replacing an entry does not make it newer
+        map.insert("example.a".to_string(), 3);
+        assert_eq!(map.oldest().unwrap(), "example.a");
+
+        map.remove("example.a");
+        assert_eq!(map.oldest().unwrap(), "example.b");
+        assert_eq!(map.len(), 1);
+    }
 }
 
 #[cfg(test)]
@@ -339,4 +464,82 @@ mod table {
             Uuid::from_slice(&[2; 16]).unwrap()
         );
     }
+
+    fn update_with_routes(prefixes: &[&str]) -> RouteUpdateRequest {
+        let mut request = UPDATE_REQUEST_SIMPLE.clone();
+        request.from_epoch_index = 0;
+        request.to_epoch_index = 1;
+        request.new_routes = prefixes
+            .iter()
+            .map(|prefix| Route {
+                prefix: prefix.to_string(),
+                path: Vec::new(),
+                props: Vec::new(),
+                auth: [0; 32],
+            })
+            .collect();
+        request
+    }
+
+    #[test]
+    fn refuses_routes_beyond_limit() {
+        let mut table = RoutingTable::new(UPDATE_REQUEST_SIMPLE.routing_table_id);
+        table.set_route_limit(2, RouteLimitPolicy::Refuse);
+        let updated_routes = table
+            .handle_update_request(
+                ROUTING_ACCOUNT.clone(),
+                update_with_routes(&["example.a", "example.b", "example.c"]),
+            )
+            .unwrap();
+        assert_eq!(updated_routes, vec!["example.a", "example.b"]);
+        assert_eq!(table.get_routes().count(), 2);
+        assert!(table.get_route("example.c").is_none());
+
+        // Routes which are already known can still be updated
+        let mut request = update_with_routes(&["example.b", "example.d"]);
+        request.from_epoch_index = 1;
+        request.to_epoch_index = 2;
+        let updated_routes = table
+            .handle_update_request(ROUTING_ACCOUNT.clone(), request)
+            .unwrap();
+        assert!(updated_routes.is_empty());
+        assert_eq!(table.get_routes().count(), 2);
+    }
+
+    #[test]
+    fn evicts_oldest_routes_beyond_limit() {
+        let mut table = RoutingTable::new(UPDATE_REQUEST_SIMPLE.routing_table_id);
+        table.set_route_limit(2, RouteLimitPolicy::EvictOldest);
+        let updated_routes = table
+            .handle_update_request(
+                ROUTING_ACCOUNT.clone(),
+                update_with_routes(&["example.a", "example.b", "example.c", "example.d"]),
+            )
+            .unwrap();
+        assert_eq!(
+            updated_routes,
+            vec![
+                "example.a",
+                "example.b",
+                "example.a",
+                "example.c",
+                "example.b",
+                "example.d"
+            ]
+        );
+        assert_eq!(table.get_routes().count(), 2);
+        assert!(table.get_route("example.c").is_some());
+        assert!(table.get_route("example.d").is_some());
+    }
+
+    #[test]
+    fn refuses_all_routes_with_limit_of_zero() {
+        let mut table = RoutingTable::new(UPDATE_REQUEST_SIMPLE.routing_table_id);
+        table.set_route_limit(0, RouteLimitPolicy::EvictOldest);
+        let updated_routes = table
+            .handle_update_request(ROUTING_ACCOUNT.clone(), update_with_routes(&["example.a"]))
+            .unwrap();
+        assert!(updated_routes.is_empty());
+        assert_eq!(table.get_routes().count(), 0);
+    }
 }
@@ -3,11 +3,11 @@ use crate::{
         Mode, Route, RouteControlRequest, RouteUpdateRequest, CCP_CONTROL_DESTINATION,
         CCP_RESPONSE, CCP_UPDATE_DESTINATION,
     },
-    routing_table::RoutingTable,
+    routing_table::{RouteLimitPolicy, RoutingTable},
     CcpRoutingAccount, CcpRoutingStore, RoutingRelation,
 };
 use async_trait::async_trait;
-use futures::future::join_all;
+use futures::{future::join_all, stream, StreamExt};
 use interledger_errors::CcpRoutingStoreError;
 use interledger_packet::{Address, ErrorCode, RejectBuilder};
 use interledger_service::{
@@ -15,12 +15,16 @@ use interledger_service::{
     OutgoingService,
 };
 use log::{debug, error, trace, warn};
+use metrics::gauge;
 use parking_lot::{Mutex, RwLock};
-use ring::digest::{digest, SHA256};
+use ring::{
+    digest::{digest, SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
 use std::cmp::Ordering as StdOrdering;
 use std::collections::HashMap;
 use std::{
-    cmp::min,
+    cmp::{max, min},
     convert::TryFrom,
     str,
     sync::{
@@ -35,7 +39,6 @@ use uuid::Uuid;
 use crate::packet::PEER_PROTOCOL_CONDITION;
 #[cfg(test)]
 use futures::TryFutureExt;
-#[cfg(test)]
 use once_cell::sync::Lazy;
 
 // TODO should the route expiry be longer? we use 30 seconds now
@@ -44,14 +47,30 @@ use once_cell::sync::Lazy;
 // comes after the expiry shortener
 const DEFAULT_ROUTE_EXPIRY_TIME: u32 = 30000;
 const DEFAULT_BROADCAST_INTERVAL: u64 = 30000;
+const DEFAULT_BROADCAST_CONCURRENCY: usize = 50;
+const DEFAULT_STALE_ROUTE_THRESHOLD: u64 = 60000;
 const DUMMY_ROUTING_TABLE_ID: [u8; 16] = [0; 16];
 
+static RANDOM: Lazy<SystemRandom> = Lazy::new(SystemRandom::new);
+
 fn hash(preimage: &[u8; 32]) -> [u8; 32] {
     let mut out = [0; 32];
     out.copy_from_slice(digest(&SHA256, preimage).as_ref());
     out
 }
 
+/// Returns the interval randomly lengthened or shortened by up to `jitter_percent` percent
+fn jittered_interval(interval: u64, jitter_percent: u8) -> Duration {
+    let max_jitter = interval * u64::from(min(jitter_percent, 100)) / 100;
+    if max_jitter == 0 {
+        return Duration::from_millis(interval);
+    }
+    let mut random = [0; 8];
+    RANDOM.fill(&mut random).expect("Unable to get randomness");
+    let offset = u64::from_be_bytes(random) % (2 * max_jitter + 1);
+    Duration::from_millis(interval - max_jitter + offset)
+}
+
 type NewAndWithdrawnRoutes = (Vec<Route>, Vec<String>);
 
 /// Builder for [CcpRouteManager](./CcpRouteManager.html)
@@ -68,6 +87,11 @@ pub struct CcpRouteManagerBuilder<I, O, S> {
     store: S,
     ilp_address: Address,
     broadcast_interval: u64,
+    broadcast_delay: u64,
+    broadcast_jitter: u8,
+    broadcast_concurrency: usize,
+    route_limit: Option<(usize, RouteLimitPolicy)>,
+    stale_route_threshold: u64,
 }
 
 impl<I, O, S, A> CcpRouteManagerBuilder<I, O, S>
@@ -84,6 +108,11 @@ where
             outgoing,
             store,
             broadcast_interval: DEFAULT_BROADCAST_INTERVAL,
+            broadcast_delay: 0,
+            broadcast_jitter: 0,
+            broadcast_concurrency: DEFAULT_BROADCAST_CONCURRENCY,
+            route_limit: None,
+            stale_route_threshold: DEFAULT_STALE_ROUTE_THRESHOLD,
         }
     }
 
@@ -98,6 +127,52 @@ where
         self
     }
 
+    /// Set how long (in milliseconds) to wait after startup before the first broadcast,
+    /// so that the routes provisioned while the node starts are included in it
+    /// (defaults to 0, broadcasting right away)
+    pub fn broadcast_delay(&mut self, ms: u64) -> &mut Self {
+        self.broadcast_delay = ms;
+        self
+    }
+
+    /// Set the maximum percentage by which each broadcast interval is randomly
+    /// lengthened or shortened, so that peers configured with the same interval
+    /// do not all broadcast at the same time (defaults to 0, values above 100 are
+    /// treated as 100)
+    pub fn broadcast_jitter(&mut self, percent: u8) -> &mut Self {
+        self.broadcast_jitter = percent;
+        self
+    }
+
+    /// Set the maximum number of peers each route broadcast is sent to at the same time,
+    /// so that slow peers do not delay the updates to the others (defaults to 50, a value
+    /// of 0 is treated as 1)
+    pub fn broadcast_concurrency(&mut self, limit: usize) -> &mut Self {
+        self.broadcast_concurrency = limit;
+        self
+    }
+
+    /// Set the maximum number of routes learned from each peer, so that a peer advertising
+    /// too many prefixes can not exhaust our memory. Once a peer's table is full, the policy
+    /// decides whether its new prefixes are refused or replace its oldest routes
+    /// (defaults to no limit)
+    pub fn max_routes_per_peer(
+        &mut self,
+        max_routes: usize,
+        policy: RouteLimitPolicy,
+    ) -> &mut Self {
+        self.route_limit = Some((max_routes, policy));
+        self
+    }
+
+    /// Set how long (in milliseconds) a route learned from a peer may go without the peer
+    /// refreshing it with an update or heartbeat, before it is counted as stale in the
+    /// `routes.stale` metric (defaults to 60000, two of the default broadcast intervals)
+    pub fn stale_route_threshold(&mut self, ms: u64) -> &mut Self {
+        self.stale_route_threshold = ms;
+        self
+    }
+
     pub fn to_service(&self) -> CcpRouteManager<I, O, S, A> {
         #[allow(clippy::let_and_return)]
         let service = CcpRouteManager {
@@ -111,6 +186,11 @@ where
             local_table: Arc::new(RwLock::new(RoutingTable::default())),
             incoming_tables: Arc::new(RwLock::new(HashMap::new())),
             unavailable_accounts: Arc::new(Mutex::new(HashMap::new())),
+            broadcast_delay: Duration::from_millis(self.broadcast_delay),
+            broadcast_jitter: self.broadcast_jitter,
+            broadcast_concurrency: max(self.broadcast_concurrency, 1),
+            route_limit: self.route_limit,
+            stale_route_threshold: Duration::from_millis(self.stale_route_threshold),
         };
 
         #[cfg(not(test))]
@@ -175,6 +255,16 @@ pub struct CcpRouteManager<I, O, S, A: Account> {
     /// This maps the account ID to the number of route brodcast intervals
     /// we should wait before trying again
     unavailable_accounts: Arc<Mutex<HashMap<Uuid, BackoffParams>>>,
+    /// Time to wait after startup before the first broadcast
+    broadcast_delay: Duration,
+    /// Maximum percentage by which each broadcast interval is randomly changed
+    broadcast_jitter: u8,
+    /// Maximum number of peers a route broadcast is sent to at the same time
+    broadcast_concurrency: usize,
+    /// Maximum number of routes learned from each peer and what to do with the ones beyond it
+    route_limit: Option<(usize, RouteLimitPolicy)>,
+    /// Age since the last refresh after which the routes learned from peers are stale
+    stale_route_threshold: Duration,
 }
 
 impl<I, O, S, A> CcpRouteManager<I, O, S, A>
@@ -185,12 +275,15 @@ where
     A: CcpRoutingAccount + Send + Sync + 'static,
 {
     /// Returns a future that will trigger this service to update its routes and broadcast
-    /// updates to peers on the given interval. `interval` is in milliseconds
+    /// updates to peers on the given interval. `interval` is in milliseconds and
+    /// each interval is randomly changed by up to the configured jitter. The first
+    /// broadcast is sent after the configured delay.
     pub async fn start_broadcast_interval(&self, interval: u64) {
         self.request_all_routes().await;
-        let mut interval = tokio::time::interval(Duration::from_millis(interval));
+        let mut next_broadcast = tokio::time::Instant::now() + self.broadcast_delay;
         loop {
-            interval.tick().await;
+            tokio::time::delay_until(next_broadcast).await;
+            next_broadcast += jittered_interval(interval, self.broadcast_jitter);
             // ensure we have the latest ILP Address from the store
             self.update_ilp_address();
             // Do not consume the result if an error since we want to keep the loop going
@@ -214,9 +307,53 @@ where
 
     pub async fn broadcast_routes(&self) -> Result<(), CcpRoutingStoreError> {
         self.update_best_routes(None).await?;
+        self.record_route_freshness();
         self.send_route_updates().await
     }
 
+    /// Returns the time since each of the best routes which were learned from peers was
+    /// last refreshed, i.e. since the peer it was learned from last sent an update or a
+    /// heartbeat. Configured and local routes are always fresh, so they are not included.
+    pub fn route_ages(&self) -> HashMap<String, Duration> {
+        let local_table = self.local_table.read();
+        let incoming_tables = self.incoming_tables.read();
+        local_table
+            .get_routes()
+            .filter_map(|(prefix, (account, _))| {
+                let table = incoming_tables.get(&account.id())?;
+                match table.get_route(prefix) {
+                    Some((_, route)) if route.prefix == *prefix => {
+                        Some((prefix.clone(), table.last_updated().elapsed()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Records the age of the oldest route learned from peers and the number of stale routes,
+    /// so that routes can be alerted on before their peer's hold down time expires them.
+    /// The ages aren't recorded per prefix, since the gauges of routes which are gone would
+    /// never be cleared. Returns the number of stale routes.
+    fn record_route_freshness(&self) -> usize {
+        let mut stale_routes = 0;
+        let mut max_age = Duration::from_secs(0);
+        for (prefix, age) in self.route_ages() {
+            max_age = max_age.max(age);
+            if age > self.stale_route_threshold {
+                warn!(
+                    "Route for prefix {} was not refreshed for {}ms",
+                    prefix,
+                    age.as_millis()
+                );
+                stale_routes += 1;
+            }
+        }
+        gauge!("routes.max_age_ms", max_age.as_millis() as i64);
+        gauge!("routes.stale", stale_routes as i64);
+        stale_routes
+    }
+
     /// Request routes from all the peers we are willing to receive routes from.
     /// This is mostly intended for when the CCP server starts up and doesn't have any routes from peers.
     async fn request_all_routes(&self) {
@@ -383,10 +520,11 @@ where
         let result = {
             let mut incoming_tables = self.incoming_tables.write();
             if !&incoming_tables.contains_key(&request.from.id()) {
-                incoming_tables.insert(
-                    request.from.id(),
-                    RoutingTable::new(update.routing_table_id),
-                );
+                let mut table = RoutingTable::new(update.routing_table_id);
+                if let Some((max_routes, policy)) = self.route_limit {
+                    table.set_route_limit(max_routes, policy);
+                }
+                incoming_tables.insert(request.from.id(), table);
             }
             incoming_tables
                 .get_mut(&request.from.id())
@@ -651,11 +789,17 @@ where
                 store.set_routes(local_table.get_simplified_table())
             };
 
-            update_routes.await
-        } else {
-            // The routing table hasn't changed
-            Ok(())
+            update_routes.await?;
         }
+
+        // Peers may have advertised equally good routes even if the best ones didn't change
+        let multipath_routes = get_multipath_routes(
+            &local_routes,
+            &configured_routes,
+            &local_table.read(),
+            &incoming_tables.read(),
+        );
+        store.set_multipath_routes(multipath_routes).await
     }
 
     /// Send RouteUpdateRequests to all peers that we send routing messages to
@@ -716,22 +860,28 @@ where
                 }
             );
 
-            // TODO: How can this be converted to a join_all expression?
-            // futures 0.1 version worked by doing `join_all(accounts.into_iter().map(...)).and_then(...)`
-            // It is odd that the same but with `.await` instead does not work.
-            let mut outgoing = self_clone.outgoing.clone();
-            let mut results = Vec::new();
-            for account in accounts.into_iter() {
-                let res = outgoing
-                    .send_request(OutgoingRequest {
-                        from: account.clone(),
-                        to: account.clone(),
-                        original_amount: prepare.amount(),
-                        prepare: prepare.clone(),
-                    })
-                    .await;
-                results.push((account, res));
-            }
+            // Send the updates to several accounts at once, so that a slow account only
+            // delays the accounts after it if all of the others are slow too
+            let outgoing = self_clone.outgoing.clone();
+            let results: Vec<(A, IlpResult)> = stream::iter(accounts)
+                .map(|account| {
+                    let mut outgoing = outgoing.clone();
+                    let prepare = prepare.clone();
+                    async move {
+                        let res = outgoing
+                            .send_request(OutgoingRequest {
+                                from: account.clone(),
+                                to: account.clone(),
+                                original_amount: prepare.amount(),
+                                prepare,
+                            })
+                            .await;
+                        (account, res)
+                    }
+                })
+                .buffer_unordered(self.broadcast_concurrency)
+                .collect()
+                .await;
 
             // Handle the results of the route broadcast attempts
             trace!("Updating unavailable accounts");
@@ -889,14 +1039,14 @@ where
     }
 }
 
-fn get_best_route_for_prefix<A: CcpRoutingAccount>(
-    local_routes: &HashMap<String, A>,
-    configured_routes: &HashMap<String, A>,
-    incoming_tables: &HashMap<Uuid, RoutingTable<A>>,
+/// Returns the configured route for that specific prefix or any shorter prefix
+/// ("example.a.b.c" will match "example.a.b" and "example.a") or else the local route
+/// for the prefix. These always take precedence over the routes broadcast by peers.
+fn get_configured_or_local_route<'a, A: CcpRoutingAccount>(
+    local_routes: &'a HashMap<String, A>,
+    configured_routes: &'a HashMap<String, A>,
     prefix: &str,
-) -> Option<(A, Route)> {
-    // Check if we have a configured route for that specific prefix
-    // or any shorter prefix ("example.a.b.c" will match "example.a.b" and "example.a")
+) -> Option<&'a A> {
     // Note that this logic is duplicated from the Address type. We are not using
     // Addresses here because the prefixes may not be valid ILP addresses ("example." is
     // a valid prefix but not a valid address)
@@ -904,19 +1054,20 @@ fn get_best_route_for_prefix<A: CcpRoutingAccount>(
     for i in 0..segments.len() {
         let prefix = &segments[0..segments.len() - i].join(".");
         if let Some(account) = configured_routes.get(prefix) {
-            return Some((
-                account.clone(),
-                Route {
-                    prefix: account.ilp_address().to_string(),
-                    auth: [0; 32],
-                    path: Vec::new(),
-                    props: Vec::new(),
-                },
-            ));
+            return Some(account);
         }
     }
 
-    if let Some(account) = local_routes.get(prefix) {
+    local_routes.get(prefix)
+}
+
+fn get_best_route_for_prefix<A: CcpRoutingAccount>(
+    local_routes: &HashMap<String, A>,
+    configured_routes: &HashMap<String, A>,
+    incoming_tables: &HashMap<Uuid, RoutingTable<A>>,
+    prefix: &str,
+) -> Option<(A, Route)> {
+    if let Some(account) = get_configured_or_local_route(local_routes, configured_routes, prefix) {
         return Some((
             account.clone(),
             Route {
@@ -943,16 +1094,24 @@ fn get_best_route_for_prefix<A: CcpRoutingAccount>(
                     StdOrdering::Greater => (best_account, best_route),
                     StdOrdering::Less => (account, route),
                     _ => {
-                        // Prioritize shortest path
-                        match best_route.path.len().cmp(&route.path.len()) {
-                            StdOrdering::Less => (best_account, best_route),
-                            StdOrdering::Greater => (account, route),
+                        // Prioritize the configured route priority
+                        match best_account.route_priority().cmp(&account.route_priority()) {
+                            StdOrdering::Greater => (best_account, best_route),
+                            StdOrdering::Less => (account, route),
                             _ => {
-                                // Finally base it on account ID
-                                if best_account.id().to_string() < account.id().to_string() {
-                                    (best_account, best_route)
-                                } else {
-                                    (account, route)
+                                // Prioritize shortest path
+                                match best_route.path.len().cmp(&route.path.len()) {
+                                    StdOrdering::Less => (best_account, best_route),
+                                    StdOrdering::Greater => (account, route),
+                                    _ => {
+                                        // Finally base it on account ID
+                                        if best_account.id().to_string() < account.id().to_string()
+                                        {
+                                            (best_account, best_route)
+                                        } else {
+                                            (account, route)
+                                        }
+                                    }
                                 }
                             }
                         }
@@ -966,6 +1125,44 @@ fn get_best_route_for_prefix<A: CcpRoutingAccount>(
     }
 }
 
+/// Finds the other accounts which advertised routes that are as good as the ones in the local
+/// routing table (same prefix, routing relation, route priority and path length), so that
+/// payments to those prefixes can be spread across all of them. Configured and local routes
+/// are never spread.
+fn get_multipath_routes<A: CcpRoutingAccount>(
+    local_routes: &HashMap<String, A>,
+    configured_routes: &HashMap<String, A>,
+    local_table: &RoutingTable<A>,
+    incoming_tables: &HashMap<Uuid, RoutingTable<A>>,
+) -> HashMap<String, Vec<Uuid>> {
+    local_table
+        .get_routes()
+        .filter(|(prefix, _)| {
+            get_configured_or_local_route(local_routes, configured_routes, prefix).is_none()
+        })
+        .filter_map(|(prefix, (best_account, best_route))| {
+            let alternatives: Vec<Uuid> = incoming_tables
+                .values()
+                .filter_map(|incoming_table| incoming_table.get_route(prefix))
+                .filter(|(account, route)| {
+                    // get_route also returns routes for shorter prefixes
+                    &route.prefix == prefix
+                        && account.id() != best_account.id()
+                        && account.routing_relation() == best_account.routing_relation()
+                        && account.route_priority() == best_account.route_priority()
+                        && route.path.len() == best_route.path.len()
+                })
+                .map(|(account, _route)| account.id())
+                .collect();
+            if alternatives.is_empty() {
+                None
+            } else {
+                Some((prefix.clone(), alternatives))
+            }
+        })
+        .collect()
+}
+
 #[async_trait]
 impl<I, O, S, A> IncomingService<A> for CcpRouteManager<I, O, S, A>
 where
@@ -1130,11 +1327,117 @@ mod ranking_routes {
         );
     }
 
+    #[test]
+    fn prioritizes_peers_with_higher_route_priority() {
+        let mut incoming = INCOMING.clone();
+        let mut backup_table = RoutingTable::default();
+        let mut backup = TestAccount::new(Uuid::from_slice(&[9; 16]).unwrap(), "example.backup");
+        backup.priority = 1;
+        backup_table.add_route(
+            backup,
+            Route {
+                prefix: "example.e".to_string(),
+                path: vec!["example.one".to_string(), "example.two".to_string()],
+                auth: [0; 32],
+                props: Vec::new(),
+            },
+        );
+        incoming.insert(Uuid::from_slice(&[9; 16]).unwrap(), backup_table);
+        let mut preferred_table = RoutingTable::default();
+        let mut preferred =
+            TestAccount::new(Uuid::from_slice(&[10; 16]).unwrap(), "example.preferred");
+        preferred.priority = 2;
+        preferred_table.add_route(
+            preferred,
+            Route {
+                // The path is longer than the other peers' ones
+                prefix: "example.e".to_string(),
+                path: vec![
+                    "example.one".to_string(),
+                    "example.two".to_string(),
+                    "example.three".to_string(),
+                ],
+                auth: [0; 32],
+                props: Vec::new(),
+            },
+        );
+        incoming.insert(Uuid::from_slice(&[10; 16]).unwrap(), preferred_table);
+
+        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &incoming, "example.e");
+        assert_eq!(
+            best_route.unwrap().0.id(),
+            Uuid::from_slice(&[10; 16]).unwrap()
+        );
+
+        // Once the preferred peer withdraws the route, the next one by priority is used
+        incoming
+            .get_mut(&Uuid::from_slice(&[10; 16]).unwrap())
+            .unwrap()
+            .delete_route("example.e");
+        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &incoming, "example.e");
+        assert_eq!(
+            best_route.unwrap().0.id(),
+            Uuid::from_slice(&[9; 16]).unwrap()
+        );
+
+        // Routes of lower priority are not spread across
+        let mut local_table = RoutingTable::default();
+        let (account, route) =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &incoming, "example.e").unwrap();
+        local_table.set_route("example.e".to_string(), account, route);
+        assert!(get_multipath_routes(&LOCAL, &CONFIGURED, &local_table, &incoming).is_empty());
+    }
+
     #[test]
     fn returns_none_for_no_route() {
         let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.z");
         assert!(best_route.is_none());
     }
+
+    #[test]
+    fn finds_equal_cost_routes() {
+        let mut incoming = INCOMING.clone();
+        let mut peer_table_3 = RoutingTable::default();
+        let peer_3 = TestAccount::new(Uuid::from_slice(&[9; 16]).unwrap(), "example.peer3");
+        peer_table_3.add_route(
+            peer_3.clone(),
+            Route {
+                prefix: "example.e".to_string(),
+                path: vec!["example.three".to_string()],
+                auth: [0; 32],
+                props: Vec::new(),
+            },
+        );
+        peer_table_3.add_route(
+            peer_3,
+            Route {
+                // Configured routes are never spread
+                prefix: "example.a.sub-prefix".to_string(),
+                path: vec!["example.one".to_string()],
+                auth: [0; 32],
+                props: Vec::new(),
+            },
+        );
+        incoming.insert(Uuid::from_slice(&[9; 16]).unwrap(), peer_table_3);
+
+        let mut local_table = RoutingTable::default();
+        for &prefix in &["example.a.sub-prefix", "example.d", "example.e"] {
+            let (account, route) =
+                get_best_route_for_prefix(&LOCAL, &CONFIGURED, &incoming, prefix).unwrap();
+            local_table.set_route(prefix.to_string(), account, route);
+        }
+
+        // The peer's route to example.d doesn't count because children are preferred
+        // and peer 2's route to example.e has a longer path
+        let multipath_routes = get_multipath_routes(&LOCAL, &CONFIGURED, &local_table, &incoming);
+        assert_eq!(
+            multipath_routes,
+            HashMap::from_iter(vec![(
+                "example.e".to_string(),
+                vec![Uuid::from_slice(&[9; 16]).unwrap()]
+            )])
+        );
+    }
 }
 
 #[cfg(test)]
@@ -1501,6 +1804,97 @@ mod handle_route_update_request {
         );
     }
 
+    #[tokio::test]
+    async fn counts_routes_which_were_not_refreshed_as_stale() {
+        let mut service = test_service();
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.to_epoch_index = 1;
+        request.from_epoch_index = 0;
+        service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: request.to_prepare(),
+            })
+            .await
+            .unwrap();
+        let ages = service.route_ages();
+        assert!(ages["example.prefix1"] < service.stale_route_threshold);
+        assert!(ages["example.prefix2"] < service.stale_route_threshold);
+        assert_eq!(service.record_route_freshness(), 0);
+
+        // The peer did not send an update or heartbeat within the threshold
+        let last_updated =
+            std::time::Instant::now() - service.stale_route_threshold - Duration::from_secs(1);
+        service
+            .incoming_tables
+            .write()
+            .get_mut(&ROUTING_ACCOUNT.id())
+            .unwrap()
+            .set_last_updated(last_updated);
+        let ages = service.route_ages();
+        assert!(ages["example.prefix1"] > service.stale_route_threshold);
+        assert_eq!(service.record_route_freshness(), 2);
+
+        // A heartbeat refreshes the routes
+        let mut heartbeat = UPDATE_REQUEST_SIMPLE.clone();
+        heartbeat.routing_table_id = request.routing_table_id;
+        heartbeat.from_epoch_index = 1;
+        heartbeat.to_epoch_index = 1;
+        service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: heartbeat.to_prepare(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(service.record_route_freshness(), 0);
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_lower_priority_peer() {
+        let mut service = test_service();
+        let mut preferred = TestAccount::new(Uuid::new_v4(), "example.preferred");
+        preferred.priority = 1;
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.to_epoch_index = 1;
+        request.from_epoch_index = 0;
+        for account in &[ROUTING_ACCOUNT.clone(), preferred.clone()] {
+            service
+                .handle_request(IncomingRequest {
+                    from: account.clone(),
+                    prepare: request.to_prepare(),
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            service.store.routes.lock()["example.prefix1"].id(),
+            preferred.id()
+        );
+
+        service
+            .handle_request(IncomingRequest {
+                from: preferred,
+                prepare: RouteUpdateRequest {
+                    routing_table_id: UPDATE_REQUEST_COMPLEX.routing_table_id,
+                    from_epoch_index: 1,
+                    to_epoch_index: 2,
+                    current_epoch_index: 2,
+                    hold_down_time: 45000,
+                    speaker: UPDATE_REQUEST_COMPLEX.speaker.clone(),
+                    new_routes: Vec::new(),
+                    withdrawn_routes: vec!["example.prefix1".to_string()],
+                }
+                .to_prepare(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            service.store.routes.lock()["example.prefix1"].id(),
+            ROUTING_ACCOUNT.id()
+        );
+    }
+
     #[tokio::test]
     async fn doesnt_overwrite_configured_or_local_routes() {
         let mut service = test_service();
@@ -1908,6 +2302,7 @@ mod send_route_updates {
                     id: id2,
                     ilp_address: Address::from_str("example.connector.other-local").unwrap(),
                     relation: RoutingRelation::Child,
+                    priority: 0,
                 },
             ),
         ]);
@@ -1996,6 +2391,7 @@ mod send_route_updates {
             id: id2,
             ilp_address: Address::from_str("example.connector.other-local").unwrap(),
             relation: RoutingRelation::Child,
+            priority: 0,
         };
         let local_routes = HashMap::from_iter(vec![
             (
@@ -2073,4 +2469,159 @@ mod send_route_updates {
         // When we send again, we don't skip the child because we got a request from them
         assert_eq!(outgoing_requests.lock().len(), 2);
     }
+
+    #[tokio::test]
+    async fn slow_accounts_do_not_delay_the_others() {
+        let ids: Vec<Uuid> = (1..=4)
+            .map(|i| Uuid::from_slice(&[i; 16]).unwrap())
+            .collect();
+        let (service, peers) =
+            service_with_peers(&ids, vec![(ids[0], Duration::from_millis(200))], 10);
+        let service_clone = service.clone();
+        let broadcast = tokio::spawn(async move { service_clone.send_route_updates().await });
+
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+        let updated: HashSet<Uuid> = peers.updated.lock().iter().cloned().collect();
+        assert_eq!(updated, HashSet::from_iter(ids[1..].iter().cloned()));
+
+        broadcast.await.unwrap().unwrap();
+        assert_eq!(peers.updated.lock().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn limits_the_accounts_broadcast_to_at_once() {
+        let ids: Vec<Uuid> = (1..=6)
+            .map(|i| Uuid::from_slice(&[i; 16]).unwrap())
+            .collect();
+        let delays = ids
+            .iter()
+            .map(|id| (*id, Duration::from_millis(20)))
+            .collect();
+        let (service, peers) = service_with_peers(&ids, delays, 2);
+        service.send_route_updates().await.unwrap();
+
+        assert_eq!(peers.updated.lock().len(), 6);
+        assert_eq!(*peers.max_in_flight.lock(), 2);
+    }
+
+    /// Route manager with a local route to each of the given peers, which broadcasts to
+    /// the given number of them at once
+    fn service_with_peers(
+        ids: &[Uuid],
+        delays: Vec<(Uuid, Duration)>,
+        concurrency: usize,
+    ) -> (
+        CcpRouteManager<
+            impl IncomingService<TestAccount> + Clone,
+            SlowPeers,
+            TestStore,
+            TestAccount,
+        >,
+        SlowPeers,
+    ) {
+        let local_routes = HashMap::from_iter(ids.iter().enumerate().map(|(i, id)| {
+            let address = format!("example.peer.{}", i);
+            (address.clone(), TestAccount::new(*id, &address))
+        }));
+        let peers = SlowPeers {
+            delays: Arc::new(HashMap::from_iter(delays)),
+            updated: Arc::new(Mutex::new(Vec::new())),
+            in_flight: Arc::new(Mutex::new(0)),
+            max_in_flight: Arc::new(Mutex::new(0)),
+        };
+        let service = CcpRouteManagerBuilder::new(
+            Address::from_str("example.connector").unwrap(),
+            TestStore::with_routes(local_routes, HashMap::new()),
+            peers.clone(),
+            incoming_service_fn(|_request| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other incoming handler!",
+                    data: &[],
+                    triggered_by: Some(&EXAMPLE_CONNECTOR),
+                }
+                .build())
+            }),
+        )
+        .broadcast_concurrency(concurrency)
+        .to_service();
+        (service, peers)
+    }
+
+    /// Accepts route updates after the delay configured for the account they are sent to
+    #[derive(Clone)]
+    struct SlowPeers {
+        delays: Arc<HashMap<Uuid, Duration>>,
+        /// The accounts which accepted an update, in the order they did
+        updated: Arc<Mutex<Vec<Uuid>>>,
+        in_flight: Arc<Mutex<usize>>,
+        max_in_flight: Arc<Mutex<usize>>,
+    }
+
+    #[async_trait]
+    impl OutgoingService<TestAccount> for SlowPeers {
+        async fn send_request(&mut self, request: OutgoingRequest<TestAccount>) -> IlpResult {
+            {
+                let mut in_flight = self.in_flight.lock();
+                *in_flight += 1;
+                let mut max_in_flight = self.max_in_flight.lock();
+                *max_in_flight = max(*max_in_flight, *in_flight);
+            }
+            if let Some(delay) = self.delays.get(&request.to.id()) {
+                tokio::time::delay_for(*delay).await;
+            }
+            *self.in_flight.lock() -= 1;
+            self.updated.lock().push(request.to.id());
+            Ok(CCP_RESPONSE.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod broadcast_interval {
+    use super::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn no_jitter_by_default() {
+        for _ in 0..10 {
+            assert_eq!(jittered_interval(30000, 0), Duration::from_millis(30000));
+        }
+    }
+
+    #[test]
+    fn jittered_intervals_vary_within_bound() {
+        let intervals: Vec<Duration> = (0..100).map(|_| jittered_interval(30000, 10)).collect();
+        for interval in &intervals {
+            assert!(*interval >= Duration::from_millis(27000));
+            assert!(*interval <= Duration::from_millis(33000));
+        }
+        assert!(intervals.iter().any(|interval| *interval != intervals[0]));
+    }
+
+    #[test]
+    fn jitter_is_capped_at_100_percent() {
+        for _ in 0..100 {
+            assert!(jittered_interval(1000, 255) <= Duration::from_millis(2000));
+        }
+    }
+
+    #[tokio::test]
+    async fn waits_for_the_delay_before_the_first_broadcast() {
+        let (mut service, outgoing_requests) = test_service_with_routes();
+        service.broadcast_delay = Duration::from_millis(200);
+        let broadcasts = move || {
+            outgoing_requests
+                .lock()
+                .iter()
+                .filter(|request| request.prepare.destination() == *CCP_UPDATE_DESTINATION)
+                .count()
+        };
+        tokio::spawn(async move { service.start_broadcast_interval(60_000).await });
+
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        assert_eq!(broadcasts(), 0);
+        tokio::time::delay_for(Duration::from_millis(300)).await;
+        assert!(broadcasts() > 0);
+    }
 }
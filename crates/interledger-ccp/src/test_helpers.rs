@@ -18,16 +18,19 @@ pub static ROUTING_ACCOUNT: Lazy<TestAccount> = Lazy::new(|| TestAccount {
     id: Uuid::new_v4(),
     ilp_address: Address::from_str("example.peer").unwrap(),
     relation: RoutingRelation::Peer,
+    priority: 0,
 });
 pub static NON_ROUTING_ACCOUNT: Lazy<TestAccount> = Lazy::new(|| TestAccount {
     id: Uuid::new_v4(),
     ilp_address: Address::from_str("example.me.nonroutingaccount").unwrap(),
     relation: RoutingRelation::NonRoutingAccount,
+    priority: 0,
 });
 pub static CHILD_ACCOUNT: Lazy<TestAccount> = Lazy::new(|| TestAccount {
     id: Uuid::new_v4(),
     ilp_address: Address::from_str("example.me.child").unwrap(),
     relation: RoutingRelation::Child,
+    priority: 0,
 });
 pub static EXAMPLE_CONNECTOR: Lazy<Address> =
     Lazy::new(|| Address::from_str("example.connector").unwrap());
@@ -38,6 +41,7 @@ pub struct TestAccount {
     pub id: Uuid,
     pub ilp_address: Address,
     pub relation: RoutingRelation,
+    pub priority: u32,
 }
 
 impl TestAccount {
@@ -46,6 +50,7 @@ impl TestAccount {
             id,
             ilp_address: Address::from_str(ilp_address).unwrap(),
             relation: RoutingRelation::Peer,
+            priority: 0,
         }
     }
 }
@@ -76,6 +81,10 @@ impl CcpRoutingAccount for TestAccount {
     fn routing_relation(&self) -> RoutingRelation {
         self.relation
     }
+
+    fn route_priority(&self) -> u32 {
+        self.priority
+    }
 }
 
 #[derive(Clone)]
@@ -232,6 +241,7 @@ pub fn test_service_with_routes() -> (
                 id: Uuid::from_slice(&[3; 16]).unwrap(),
                 ilp_address: Address::from_str("example.connector.other-local").unwrap(),
                 relation: RoutingRelation::NonRoutingAccount,
+                priority: 0,
             },
         ),
     ]);
@@ -18,8 +18,18 @@ pub enum CreateAccountError {
     InvalidBtpUrl(UrlParseError),
     #[error("the provided routing relation is not valid: {0}")]
     InvalidRoutingRelation(String),
+    #[error("the provided client certificate fingerprint is not a hex-encoded SHA-256 hash: {0}")]
+    InvalidClientCertificateFingerprint(String),
+    #[error("the provided ILP over HTTP signature header is not a valid header name: {0}")]
+    InvalidSignatureHeader(String),
+    #[error("the ILP over HTTP signature header and secret must be provided together")]
+    IncompleteSignatureKey,
     #[error("the provided value for parameter `{0}` was too large")]
     ParamTooLarge(String),
+    #[error("the provided value for parameter `{0}` is out of range: {1}")]
+    ParamOutOfRange(String, String),
+    #[error("the exported secret `{0}` is not hex-encoded or cannot be decrypted by this node")]
+    InvalidExportedSecret(String),
 }
 
 impl From<CreateAccountError> for ApiError {
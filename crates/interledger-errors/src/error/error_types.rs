@@ -26,6 +26,13 @@ pub const DEFAULT_UNAUTHORIZED_TYPE: ApiErrorType = ApiErrorType {
     status: StatusCode::UNAUTHORIZED,
 };
 
+/// 403 Forbidden HTTP Status Code
+pub const DEFAULT_FORBIDDEN_TYPE: ApiErrorType = ApiErrorType {
+    r#type: &ProblemType::Default,
+    title: "Forbidden",
+    status: StatusCode::FORBIDDEN,
+};
+
 /// 404 Not Found HTTP Status Code
 pub const DEFAULT_NOT_FOUND_TYPE: ApiErrorType = ApiErrorType {
     r#type: &ProblemType::Default,
@@ -54,6 +61,34 @@ pub const DEFAULT_IDEMPOTENT_CONFLICT_TYPE: ApiErrorType = ApiErrorType {
     status: StatusCode::CONFLICT,
 };
 
+/// 411 Length Required HTTP Status Code
+pub const DEFAULT_LENGTH_REQUIRED_TYPE: ApiErrorType = ApiErrorType {
+    r#type: &ProblemType::Default,
+    title: "Length Required",
+    status: StatusCode::LENGTH_REQUIRED,
+};
+
+/// 413 Payload Too Large HTTP Status Code
+pub const DEFAULT_PAYLOAD_TOO_LARGE_TYPE: ApiErrorType = ApiErrorType {
+    r#type: &ProblemType::Default,
+    title: "Payload Too Large",
+    status: StatusCode::PAYLOAD_TOO_LARGE,
+};
+
+/// 415 Unsupported Media Type HTTP Status Code
+pub const DEFAULT_UNSUPPORTED_MEDIA_TYPE_TYPE: ApiErrorType = ApiErrorType {
+    r#type: &ProblemType::Default,
+    title: "Unsupported Media Type",
+    status: StatusCode::UNSUPPORTED_MEDIA_TYPE,
+};
+
+/// 503 Service Unavailable HTTP Status Code
+pub const DEFAULT_SERVICE_UNAVAILABLE_TYPE: ApiErrorType = ApiErrorType {
+    r#type: &ProblemType::Default,
+    title: "Service Unavailable",
+    status: StatusCode::SERVICE_UNAVAILABLE,
+};
+
 // ILP over HTTP specific errors
 
 /// ILP over HTTP invalid packet error type  (400 Bad Request)
@@ -148,6 +148,11 @@ impl ApiError {
         ApiError::from_api_error_type(&DEFAULT_UNAUTHORIZED_TYPE)
     }
 
+    /// Returns a Forbidden [ApiError](./struct.ApiError.html)
+    pub fn forbidden() -> Self {
+        ApiError::from_api_error_type(&DEFAULT_FORBIDDEN_TYPE)
+    }
+
     #[allow(dead_code)]
     /// Returns an Error Not Found [ApiError](./struct.ApiError.html)
     pub fn not_found() -> Self {
@@ -180,6 +185,11 @@ impl ApiError {
         ApiError::from_api_error_type(&DEFAULT_CONFLICT_TYPE)
     }
 
+    /// Returns a Service Unavailable [ApiError](./struct.ApiError.html)
+    pub fn service_unavailable() -> Self {
+        ApiError::from_api_error_type(&DEFAULT_SERVICE_UNAVAILABLE_TYPE)
+    }
+
     /// Returns an Invalid Account Id [ApiError](./struct.ApiError.html)
     pub fn invalid_account_id(invalid_account_id: Option<&str>) -> Self {
         let detail = match invalid_account_id {
@@ -290,19 +300,19 @@ impl Display for JsonDeserializeError {
     }
 }
 
-impl Reply for JsonDeserializeError {
-    fn into_response(self) -> Response {
+impl From<JsonDeserializeError> for ApiError {
+    fn from(src: JsonDeserializeError) -> Self {
         let mut extension_members = Map::new();
 
         // invalid-params should be a plural form even if it is always an array with a single value
         // for the future extendability.
 
         // if `path` has segments and the first value is not Unknown
-        if let Some(segment) = self.path.iter().next() {
+        if let Some(segment) = src.path.iter().next() {
             match segment {
                 serde_path_to_error::Segment::Unknown => {}
                 _ => {
-                    let invalid_params = serde_json::json!([ { "name": self.path.to_string() } ]);
+                    let invalid_params = serde_json::json!([ { "name": src.path.to_string() } ]);
                     extension_members.insert("invalid-params".to_string(), invalid_params);
                 }
             }
@@ -310,7 +320,7 @@ impl Reply for JsonDeserializeError {
 
         // if detail contains missing field error
         // it seems that there is no way to handle this cleanly
-        if let Some(captures) = MISSING_FIELD_REGEX.captures(&self.detail) {
+        if let Some(captures) = MISSING_FIELD_REGEX.captures(&src.detail) {
             if let Some(r#match) = captures.get(1) {
                 let invalid_params =
                     serde_json::json!([ { "name": r#match.as_str(), "type": "missing" } ]);
@@ -318,13 +328,13 @@ impl Reply for JsonDeserializeError {
             }
         }
 
-        let api_error_type = match self.category {
+        let api_error_type = match src.category {
             Category::Syntax => &JSON_SYNTAX_TYPE,
             Category::Data => &JSON_DATA_TYPE,
             Category::Eof => &JSON_EOF_TYPE,
             Category::Io => &JSON_IO_TYPE,
         };
-        let detail = self.detail;
+        let detail = src.detail;
 
         let mut error = ApiError::from_api_error_type(api_error_type).detail(detail);
 
@@ -332,7 +342,13 @@ impl Reply for JsonDeserializeError {
             error = error.extension_members(extension_members);
         }
 
-        error.into_response()
+        error
+    }
+}
+
+impl Reply for JsonDeserializeError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
     }
 }
 
@@ -343,13 +359,45 @@ impl From<JsonDeserializeError> for Rejection {
 }
 
 // Receives `ApiError`s and `JsonDeserializeError` and return it in the RFC7807 format.
+// The rejections of warp's own filters (unknown paths, missing headers or malformed bodies)
+// are returned in the same format, so that clients can handle all errors the same way.
 pub async fn default_rejection_handler(err: warp::Rejection) -> Result<impl Reply, Rejection> {
     if let Some(api_error) = err.find::<ApiError>() {
         Ok(api_error.clone().into_response())
     } else if let Some(json_error) = err.find::<JsonDeserializeError>() {
         Ok(json_error.clone().into_response())
-    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
-        Ok(ApiError::from_api_error_type(&DEFAULT_METHOD_NOT_ALLOWED_TYPE).into_response())
+    } else if let Some(missing_header) = err.find::<warp::reject::MissingHeader>() {
+        let detail = missing_header.to_string();
+        // The authorization header is the only one the APIs require
+        let error = if detail.to_lowercase().contains("authorization") {
+            ApiError::unauthorized()
+        } else {
+            ApiError::bad_request()
+        };
+        Ok(error.detail(detail).into_response())
+    } else if let Some(invalid_header) = err.find::<warp::reject::InvalidHeader>() {
+        Ok(ApiError::bad_request()
+            .detail(invalid_header.to_string())
+            .into_response())
+    } else if let Some(invalid_query) = err.find::<warp::reject::InvalidQuery>() {
+        Ok(ApiError::bad_request()
+            .detail(invalid_query.to_string())
+            .into_response())
+    } else if let Some(body_error) = err.find::<warp::body::BodyDeserializeError>() {
+        Ok(ApiError::from_api_error_type(&JSON_DATA_TYPE)
+            .detail(body_error.to_string())
+            .into_response())
+    } else if err.find::<warp::reject::UnsupportedMediaType>().is_some() {
+        Ok(ApiError::from_api_error_type(&DEFAULT_UNSUPPORTED_MEDIA_TYPE_TYPE).into_response())
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        Ok(ApiError::from_api_error_type(&DEFAULT_PAYLOAD_TOO_LARGE_TYPE).into_response())
+    } else if err.find::<warp::reject::LengthRequired>().is_some() {
+        Ok(ApiError::from_api_error_type(&DEFAULT_LENGTH_REQUIRED_TYPE).into_response())
+    } else if err.is_not_found() || err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        // The routes match the method before the path and warp drops the `NotFound` rejections
+        // of the other routes when combining them, so a request to an unknown path only leaves
+        // the `MethodNotAllowed` rejections of the routes using other methods behind
+        Ok(ApiError::not_found().into_response())
     } else {
         Err(err)
     }
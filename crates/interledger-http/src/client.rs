@@ -7,8 +7,9 @@ use interledger_service::*;
 use log::{error, trace};
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
-    Client, ClientBuilder, Response as HttpResponse,
+    Certificate, Client, ClientBuilder, Response as HttpResponse,
 };
+use ring::hmac;
 use secrecy::{ExposeSecret, SecretString};
 use std::{convert::TryFrom, marker::PhantomData, sync::Arc, time::Duration};
 
@@ -16,11 +17,29 @@ use std::{convert::TryFrom, marker::PhantomData, sync::Arc, time::Duration};
 /// for sending ILP Prepare packets over to the HTTP URL associated with the provided account
 /// If no [ILP-over-HTTP](https://interledger.org/rfcs/0035-ilp-over-http) URL is specified for
 /// the account in the request, then it is forwarded to the next service.
+///
+/// Connections to peers are reused across packets. The platform's native TLS implementation
+/// is used for peers with `https://` URLs, unless [`rustls`](#method.rustls) is enabled.
+/// With rustls, HTTP/2 is negotiated via ALPN with those peers, which lets concurrent packets
+/// to the same peer share a single connection, falling back to HTTP/1.1 if the peer does not
+/// support it. Peers with `http://` URLs are only sent packets over HTTP/2 if
+/// [`http2_prior_knowledge`](#method.http2_prior_knowledge) is enabled.
+///
+/// If the account has a [signature key](../trait.HttpAccount.html#method.get_http_signature_key),
+/// the requests to it carry the HMAC of the packet in the configured header, so that the peer
+/// can audit the packets it received.
 #[derive(Clone)]
 pub struct HttpClientService<S, O, A> {
     /// An HTTP client configured with a 30 second timeout by default. It is used to send the
     /// ILP over HTTP messages to the peer
     client: Client,
+    /// An HTTP client which only speaks HTTP/2, used for peers with cleartext `http://` URLs
+    /// if HTTP/2 prior knowledge is enabled
+    h2c_client: Option<Client>,
+    /// Whether rustls is used instead of the native TLS implementation
+    rustls: bool,
+    /// Certificate authorities trusted in addition to the built-in root certificates
+    root_certificates: Vec<Certificate>,
     /// The store used by the client to get the node's ILP Address,
     /// used to populate the `triggered_by` field in Reject packets
     store: Arc<S>,
@@ -38,24 +57,84 @@ where
 {
     /// Constructs the HttpClientService
     pub fn new(store: S, next: O) -> Self {
-        let mut headers = HeaderMap::with_capacity(2);
-        headers.insert(
-            HeaderName::from_static("content-type"),
-            HeaderValue::from_static("application/octet-stream"),
-        );
-        let client = ClientBuilder::new()
-            .default_headers(headers)
-            .timeout(Duration::from_secs(30))
-            .build()
-            .unwrap();
-
         HttpClientService {
-            client,
+            client: build_client(false, false, &[]),
+            h2c_client: None,
+            rustls: false,
+            root_certificates: Vec::new(),
             store: Arc::new(store),
             next,
             account_type: PhantomData,
         }
     }
+
+    /// Send packets to peers with cleartext `http://` URLs over HTTP/2 without negotiating it
+    /// first (which is only possible over TLS). All of the node's cleartext ILP-over-HTTP peers
+    /// must support HTTP/2 if this is enabled. Disabled by default.
+    pub fn http2_prior_knowledge(&mut self, enabled: bool) -> &mut Self {
+        self.h2c_client = if enabled {
+            Some(build_client(true, self.rustls, &self.root_certificates))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Use rustls instead of the platform's native TLS implementation (e.g. OpenSSL) for
+    /// peers with `https://` URLs, which negotiates HTTP/2 with the peers supporting it.
+    /// Disabled by default.
+    pub fn rustls(&mut self, enabled: bool) -> &mut Self {
+        self.rustls = enabled;
+        self.rebuild_clients();
+        self
+    }
+
+    /// Trust the given certificate authorities (in addition to the built-in root certificates)
+    /// when connecting to peers with `https://` URLs, for example if the connections go through
+    /// a proxy with an internal CA
+    pub fn root_certificates(&mut self, certificates: Vec<Certificate>) -> &mut Self {
+        self.root_certificates = certificates;
+        self.rebuild_clients();
+        self
+    }
+
+    fn rebuild_clients(&mut self) {
+        self.client = build_client(false, self.rustls, &self.root_certificates);
+        if self.h2c_client.is_some() {
+            self.h2c_client = Some(build_client(true, self.rustls, &self.root_certificates));
+        }
+    }
+}
+
+fn build_client(
+    http2_prior_knowledge: bool,
+    rustls: bool,
+    root_certificates: &[Certificate],
+) -> Client {
+    let mut headers = HeaderMap::with_capacity(2);
+    headers.insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    let builder = ClientBuilder::new()
+        .default_headers(headers)
+        .timeout(Duration::from_secs(30));
+    // Unlike the native TLS backend, rustls negotiates HTTP/2 via ALPN
+    let builder = if rustls {
+        builder.use_rustls_tls()
+    } else {
+        builder
+    };
+    let builder = root_certificates
+        .iter()
+        .cloned()
+        .fold(builder, ClientBuilder::add_root_certificate);
+    let builder = if http2_prior_knowledge {
+        builder.http2_prior_knowledge()
+    } else {
+        builder
+    };
+    builder.build().unwrap()
 }
 
 #[async_trait]
@@ -82,10 +161,17 @@ where
                 .unwrap_or_else(|| SecretString::new("".to_owned()));
             let header = format!("Bearer {}", token.expose_secret());
             let body = request.prepare.as_ref().to_owned();
-            let resp = self_clone
-                .client
-                .post(url.as_ref())
-                .header("authorization", &header)
+            let client = match self_clone.h2c_client {
+                Some(ref h2c_client) if url.scheme() == "http" => h2c_client,
+                _ => &self_clone.client,
+            };
+            let mut builder = client.post(url.as_ref()).header("authorization", &header);
+            if let Some((name, secret)) = request.to.get_http_signature_key() {
+                let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+                let signature = hex::encode(hmac::sign(&key, &body).as_ref());
+                builder = builder.header(name, signature);
+            }
+            let resp = builder
                 .body(body)
                 .send()
                 .map_err(move |err| {
@@ -171,3 +257,316 @@ async fn parse_packet_from_response(response: HttpResponse, ilp_address: Address
         .build()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HttpServer;
+    use futures::stream;
+    use http::Version;
+    use interledger_errors::{AddressStoreError, HttpStoreError};
+    use interledger_packet::{FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+    use std::time::SystemTime;
+    use tokio::net::TcpListener;
+    use url::Url;
+    use uuid::Uuid;
+    use warp::Filter;
+
+    static USERNAME: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.alice").unwrap());
+    const AUTH_PASSWORD: &str = "password";
+    const SIGNATURE_HEADER: &str = "x-packet-signature";
+
+    #[tokio::test]
+    async fn multiplexes_concurrent_packets_over_one_http2_connection() {
+        // Count the connections and record the protocol version of each request
+        let connections = Arc::new(AtomicUsize::new(0));
+        let versions = Arc::new(Mutex::new(Vec::new()));
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/accounts/alice/ilp",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let connections_clone = connections.clone();
+        let incoming = stream::poll_fn(move |cx| {
+            listener.poll_accept(cx).map(|result| {
+                connections_clone.fetch_add(1, Ordering::SeqCst);
+                Some(result.map(|(stream, _)| stream))
+            })
+        });
+        let versions_clone = versions.clone();
+        let server = HttpServer::new(
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"test data",
+                }
+                .build())
+            }),
+            TestStore { url: None },
+        );
+        let api = server.as_filter().with(warp::log::custom(move |info| {
+            versions_clone.lock().unwrap().push(info.version())
+        }));
+        tokio::spawn(warp::serve(api).serve_incoming(incoming));
+
+        let mut client = HttpClientService::new(
+            TestStore {
+                url: Some(url.clone()),
+            },
+            outgoing_service_fn(|_| panic!("the packet should be sent over HTTP")),
+        );
+        client.http2_prior_knowledge(true);
+        let requests = (0..20).map(|_| {
+            let mut client = client.clone();
+            let to = TestAccount {
+                url: Some(url.clone()),
+                signature_secret: None,
+            };
+            async move {
+                client
+                    .send_request(OutgoingRequest {
+                        from: to.clone(),
+                        to,
+                        original_amount: 100,
+                        prepare: PrepareBuilder {
+                            destination: ILP_ADDRESS.clone(),
+                            amount: 100,
+                            expires_at: SystemTime::now() + Duration::from_secs(30),
+                            execution_condition: &[0; 32],
+                            data: &[],
+                        }
+                        .build(),
+                    })
+                    .await
+            }
+        });
+        let results = futures::future::join_all(requests).await;
+
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        let versions = versions.lock().unwrap();
+        assert_eq!(versions.len(), 20);
+        assert!(versions.iter().all(|version| *version == Version::HTTP_2));
+    }
+
+    #[tokio::test]
+    async fn trusts_configured_root_certificates() {
+        use crate::fixtures::{CA_CERTIFICATE, SERVER_CERTIFICATE, SERVER_PRIVATE_KEY};
+        use crate::{parse_ca_certificates, serve_tls, tls_server_config};
+
+        // Find a free port for the server
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let url = Url::parse(&format!(
+            "https://localhost:{}/accounts/alice/ilp",
+            addr.port()
+        ))
+        .unwrap();
+        let server = HttpServer::new(
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"test data",
+                }
+                .build())
+            }),
+            TestStore { url: None },
+        );
+        let config = tls_server_config(
+            SERVER_CERTIFICATE.as_bytes(),
+            SERVER_PRIVATE_KEY.as_bytes(),
+            None,
+        )
+        .unwrap();
+        tokio::spawn(serve_tls(server.as_filter().boxed(), addr, config));
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+
+        let send_packet = |mut client: HttpClientService<_, _, _>| {
+            let to = TestAccount {
+                url: Some(url.clone()),
+                signature_secret: None,
+            };
+            async move {
+                client
+                    .send_request(OutgoingRequest {
+                        from: to.clone(),
+                        to,
+                        original_amount: 100,
+                        prepare: PrepareBuilder {
+                            destination: ILP_ADDRESS.clone(),
+                            amount: 100,
+                            expires_at: SystemTime::now() + Duration::from_secs(30),
+                            execution_condition: &[0; 32],
+                            data: &[],
+                        }
+                        .build(),
+                    })
+                    .await
+            }
+        };
+        let new_client = || {
+            HttpClientService::new(
+                TestStore { url: None },
+                outgoing_service_fn(|_| panic!("the packet should be sent over HTTP")),
+            )
+        };
+
+        // The server's certificate is not signed by a built-in root
+        let reject = send_packet(new_client()).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T01_PEER_UNREACHABLE);
+
+        let mut client = new_client();
+        client.root_certificates(parse_ca_certificates(CA_CERTIFICATE.as_bytes()).unwrap());
+        let fulfill = send_packet(client).await.unwrap();
+        assert_eq!(fulfill.data(), b"test data");
+    }
+
+    #[tokio::test]
+    async fn signs_packets_for_accounts_with_a_signature_key() {
+        // Replies with the value of the signature header, if there is one
+        let api = warp::post()
+            .and(warp::header::optional::<String>(SIGNATURE_HEADER))
+            .map(|signature: Option<String>| {
+                let fulfill = FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: signature.unwrap_or_default().as_bytes(),
+                }
+                .build();
+                BytesMut::from(fulfill).to_vec()
+            });
+        let (addr, server) = warp::serve(api).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let url = Url::parse(&format!("http://{}/accounts/alice/ilp", addr)).unwrap();
+
+        let prepare = PrepareBuilder {
+            destination: ILP_ADDRESS.clone(),
+            amount: 100,
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            execution_condition: &[0; 32],
+            data: b"test data",
+        }
+        .build();
+        let send_packet = |signature_secret| {
+            let mut client = HttpClientService::new(
+                TestStore { url: None },
+                outgoing_service_fn(|_| panic!("the packet should be sent over HTTP")),
+            );
+            let to = TestAccount {
+                url: Some(url.clone()),
+                signature_secret,
+            };
+            let prepare = prepare.clone();
+            async move {
+                client
+                    .send_request(OutgoingRequest {
+                        from: to.clone(),
+                        to,
+                        original_amount: 100,
+                        prepare,
+                    })
+                    .await
+            }
+        };
+
+        let fulfill = send_packet(Some(b"shared secret")).await.unwrap();
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"shared secret");
+        let expected = hex::encode(hmac::sign(&key, prepare.as_ref()).as_ref());
+        assert_eq!(fulfill.data(), expected.as_bytes());
+
+        let fulfill = send_packet(None).await.unwrap();
+        assert!(fulfill.data().is_empty());
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestAccount {
+        url: Option<Url>,
+        signature_secret: Option<&'static [u8]>,
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::new_v4()
+        }
+
+        fn username(&self) -> &Username {
+            &USERNAME
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &ILP_ADDRESS
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+    }
+
+    impl HttpAccount for TestAccount {
+        fn get_http_url(&self) -> Option<&Url> {
+            self.url.as_ref()
+        }
+
+        fn get_http_auth_token(&self) -> Option<SecretString> {
+            Some(SecretString::new(AUTH_PASSWORD.to_string()))
+        }
+
+        fn get_http_signature_key(&self) -> Option<(&str, &[u8])> {
+            self.signature_secret
+                .map(|secret| (SIGNATURE_HEADER, secret))
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestStore {
+        url: Option<Url>,
+    }
+
+    #[async_trait]
+    impl HttpStore for TestStore {
+        type Account = TestAccount;
+
+        async fn get_account_from_http_auth(
+            &self,
+            username: &Username,
+            token: &str,
+        ) -> Result<Self::Account, HttpStoreError> {
+            if username == &*USERNAME && token == AUTH_PASSWORD {
+                Ok(TestAccount {
+                    url: self.url.clone(),
+                    signature_secret: None,
+                })
+            } else {
+                Err(HttpStoreError::Unauthorized(username.to_string()))
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _ilp_address: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            ILP_ADDRESS.clone()
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use futures::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{delay_for, Delay, Instant};
+
+/// Connection which fails with a `TimedOut` error once nothing was read from or written
+/// to it for the timeout, so that the server serving it closes the connection.
+///
+/// This frees the resources of keep-alive connections which peers hold open between
+/// bursts of packets. Without a timeout, the stream is used as it is.
+pub struct IdleTimeout<S> {
+    stream: S,
+    timeout: Option<Duration>,
+    delay: Option<Delay>,
+}
+
+impl<S> IdleTimeout<S> {
+    pub fn new(stream: S, timeout: Option<Duration>) -> Self {
+        IdleTimeout {
+            stream,
+            timeout,
+            delay: timeout.map(delay_for),
+        }
+    }
+
+    /// Returns the wrapped stream
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    fn reset(&mut self) {
+        if let (Some(delay), Some(timeout)) = (self.delay.as_mut(), self.timeout) {
+            delay.reset(Instant::now() + timeout);
+        }
+    }
+
+    /// Returns an error if the connection was idle for longer than the timeout.
+    /// Registers the task to be woken up when the timeout expires otherwise.
+    fn poll_idle(&mut self, cx: &mut Context) -> io::Result<()> {
+        if let Some(delay) = self.delay.as_mut() {
+            if Pin::new(delay).poll(cx).is_ready() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Connection was idle for too long",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Resets the timeout if the operation made progress, or checks whether it expired
+    /// if the operation is still pending
+    fn track<T>(&mut self, cx: &mut Context, poll: Poll<io::Result<T>>) -> Poll<io::Result<T>> {
+        match poll {
+            Poll::Ready(result) => {
+                self.reset();
+                Poll::Ready(result)
+            }
+            Poll::Pending => {
+                self.poll_idle(cx)?;
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<S> AsyncRead for IdleTimeout<S>
+where
+    S: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_read(cx, buf);
+        this.track(cx, poll)
+    }
+}
+
+impl<S> AsyncWrite for IdleTimeout<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_write(cx, buf);
+        this.track(cx, poll)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use warp::hyper::{server::conn::Http, service::service_fn, Body, Response};
+
+    const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n";
+
+    /// Serves one keep-alive connection with the timeout and returns the client's end of it
+    async fn connect(timeout: Duration) -> TcpStream {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|_| async {
+                Ok::<_, warp::hyper::Error>(Response::new(Body::from("ok")))
+            });
+            let _ = Http::new()
+                .serve_connection(IdleTimeout::new(stream, Some(timeout)), service)
+                .await;
+        });
+        TcpStream::connect(addr).await.unwrap()
+    }
+
+    async fn request(client: &mut TcpStream) -> String {
+        client.write_all(REQUEST).await.unwrap();
+        let mut buf = [0; 1024];
+        let len = client.read(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf[..len]).to_string()
+    }
+
+    #[tokio::test]
+    async fn closes_idle_connections() {
+        let mut client = connect(Duration::from_millis(100)).await;
+        assert!(request(&mut client).await.starts_with("HTTP/1.1 200 OK"));
+
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+        let mut buf = [0; 16];
+        assert_eq!(client.read(&mut buf).await.unwrap_or(0), 0);
+    }
+
+    #[tokio::test]
+    async fn keeps_active_connections() {
+        let mut client = connect(Duration::from_millis(100)).await;
+        for _ in 0..6 {
+            assert!(request(&mut client).await.starts_with("HTTP/1.1 200 OK"));
+            tokio::time::delay_for(Duration::from_millis(50)).await;
+        }
+    }
+}
@@ -14,11 +14,23 @@ use warp::{self, Filter, Rejection};
 
 /// [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/) Outgoing Service
 mod client;
+/// Timeout closing connections which are idle
+mod idle_timeout;
 /// [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/) API (implemented with [Warp](https://docs.rs/warp/0.2.0/warp/))
 mod server;
+/// TLS server which lets peers authenticate with client certificates
+mod tls;
+
+#[cfg(test)]
+mod fixtures;
 
 pub use self::client::HttpClientService;
-pub use self::server::HttpServer;
+pub use self::idle_timeout::IdleTimeout;
+pub use self::server::{HttpServer, REQUEST_ID_HEADER};
+pub use self::tls::{
+    normalize_fingerprint, parse_ca_certificates, restrict_tls_server_config, serve_tls,
+    serve_tls_incoming, tls_server_config, ClientCertificate, TlsVersion,
+};
 
 /// Extension trait for [Account](../interledger_service/trait.Account.html) with [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/) related information
 pub trait HttpAccount: Account {
@@ -26,6 +38,18 @@ pub trait HttpAccount: Account {
     fn get_http_url(&self) -> Option<&Url>;
     /// Returns the HTTP token which is sent as an HTTP header on each ILP over HTTP request
     fn get_http_auth_token(&self) -> Option<SecretString>;
+    /// Returns the fingerprints of the TLS client certificates the peer may authenticate
+    /// incoming ILP over HTTP requests with. If there are any, requests are only accepted
+    /// if the peer presented one of them (see [`ClientCertificate`](./struct.ClientCertificate.html))
+    fn get_http_client_certificates(&self) -> &[String] {
+        &[]
+    }
+    /// Returns the name of the header and the shared secret with which ILP over HTTP requests
+    /// to this account are signed, if the peer requires them to carry an HMAC of the packet.
+    /// The header's value is the hex-encoded HMAC-SHA256 of the serialized ILP Prepare
+    fn get_http_signature_key(&self) -> Option<(&str, &[u8])> {
+        None
+    }
 }
 
 /// The interface for Stores that can be used with the HttpServerService.
@@ -41,6 +65,18 @@ pub trait HttpStore: Clone + Send + Sync + 'static {
         username: &Username,
         token: &str,
     ) -> Result<Self::Account, HttpStoreError>;
+
+    /// Load account details based on the fingerprint of the TLS client certificate
+    /// the peer presented when connecting.
+    /// Returns `None` if the account does not accept any client certificates, in
+    /// which case the request is authenticated with its bearer token instead.
+    async fn get_account_from_client_certificate(
+        &self,
+        _username: &Username,
+        _fingerprint: &str,
+    ) -> Result<Option<Self::Account>, HttpStoreError> {
+        Ok(None)
+    }
 }
 
 // TODO: Do we really need this custom deserialization function?
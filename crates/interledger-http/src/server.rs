@@ -1,20 +1,30 @@
-use super::HttpStore;
+use super::{ClientCertificate, HttpAccount, HttpStore};
 use bytes::{Bytes, BytesMut};
 use interledger_errors::ApiError;
-use interledger_packet::Prepare;
+use interledger_packet::{ErrorCode, Prepare, RejectBuilder};
 use interledger_service::Username;
-use interledger_service::{IncomingRequest, IncomingService};
-use log::error;
+use interledger_service::{Account, AddressStore, IncomingRequest, IncomingService};
+use log::warn;
+use metrics::counter;
 use secrecy::{ExposeSecret, SecretString};
 use std::convert::TryFrom;
 use std::net::SocketAddr;
-use warp::{Filter, Rejection};
+use tracing::error_span;
+use tracing_futures::Instrument;
+use uuid::Uuid;
+use warp::Filter;
 
 /// Max message size that is allowed to transfer from a request or a message.
 pub const MAX_PACKET_SIZE: u64 = 40000;
 /// The offset after which the bearer token should be in an ILP over HTTP request
 /// e.g. in `token = "Bearer: MyAuthToken"`, `MyAuthToken` can be taken via token[BEARER_TOKEN_START..]
 pub const BEARER_TOKEN_START: usize = 7;
+/// Header carrying the ID used to correlate the logs of every node which handles a packet.
+/// If the request does not include one, a new ID is generated. Either way the ID is
+/// returned in the same header of the response.
+pub static REQUEST_ID_HEADER: &str = "Request-Id";
+/// Request IDs longer than this are ignored (and replaced by a generated one)
+const MAX_REQUEST_ID_LENGTH: usize = 128;
 
 /// A warp filter that parses incoming ILP-Over-HTTP requests, validates the authorization,
 /// and passes the request to an IncomingService handler.
@@ -27,82 +37,159 @@ pub struct HttpServer<I, S> {
 }
 
 #[inline]
-/// Returns the account which matches the provided username/password combination
-/// from the store, or returns an error if the account was not found or if the
-/// credentials were incorrect
+/// Returns the account which matches the provided client certificate or
+/// username/password combination from the store, or returns an error if the
+/// account was not found or if the credentials were incorrect.
+/// Accounts which accept client certificates cannot be authenticated with a
+/// password alone.
 async fn get_account<S>(
     store: S,
     path_username: &Username,
-    password: &SecretString,
+    password: Option<&SecretString>,
+    certificate: Option<&ClientCertificate>,
 ) -> Result<S::Account, ApiError>
 where
     S: HttpStore,
 {
+    if let Some(certificate) = certificate {
+        if let Some(account) = store
+            .get_account_from_client_certificate(path_username, certificate.fingerprint())
+            .await?
+        {
+            return Ok(account);
+        }
+    }
+
+    let password = password.ok_or_else(|| {
+        ApiError::unauthorized().detail("no bearer token or accepted client certificate provided")
+    })?;
     if password.expose_secret().len() < BEARER_TOKEN_START {
         return Err(ApiError::unauthorized().detail("provided token was not a bearer token"));
     }
-    Ok(store
+    let account = store
         .get_account_from_http_auth(
             &path_username,
             &password.expose_secret()[BEARER_TOKEN_START..],
         )
-        .await?)
+        .await?;
+    if !account.get_http_client_certificates().is_empty() {
+        return Err(ApiError::unauthorized().detail("account requires a client certificate"));
+    }
+    Ok(account)
+}
+
+/// Extracts the client certificate which [`serve_tls`](./fn.serve_tls.html)
+/// adds to the request if the peer presented one
+fn client_certificate(
+) -> impl Filter<Extract = (Option<ClientCertificate>,), Error = std::convert::Infallible> + Clone {
+    warp::ext::get::<ClientCertificate>()
+        .map(Some)
+        .or(warp::any().map(|| None))
+        .unify()
+}
+
+/// Extracts the request ID header. Unlike the other headers, an invalid value
+/// does not fail the request since the ID is only used for logging
+fn request_id() -> impl Filter<Extract = (Option<String>,), Error = std::convert::Infallible> + Clone
+{
+    warp::header::headers_cloned().map(|headers: http::HeaderMap| {
+        headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    })
 }
 
 #[inline]
-/// Implements ILP over HTTP. If account authentication is valid
+/// Implements ILP over HTTP. If account authentication (with the client
+/// certificate or bearer token) is valid
 /// and the provided packet can be parsed as a
 /// [Prepare](../../interledger_packet/struct.Prepare.html) packet,
 /// then it is forwarded to the next incoming service which will return
 /// an Ok result if the response is a [Fulfill](../../interledger_packet/struct.Fulfill.html).
+/// The packet is handled within a tracing span carrying its request ID, so every
+/// log line produced by the following services includes the ID.
 ///
 /// # Errors
 /// 1. Unauthorized account if invalid credentials are provided
-/// 1. The provided `body` could not be parsed as a Prepare packet
-/// 1. A Reject packet was returned by the next incoming service
+///
+/// If the provided `body` cannot be parsed as a Prepare packet, the peer gets an
+/// `F01 Invalid Packet` Reject and the `requests.incoming.malformed` counter is incremented.
 async fn ilp_over_http<S, I>(
     path_username: Username,
-    password: SecretString,
+    password: Option<SecretString>,
+    certificate: Option<ClientCertificate>,
+    request_id: Option<String>,
     body: Bytes,
     store: S,
     incoming: I,
 ) -> Result<impl warp::Reply, warp::Rejection>
 where
-    S: HttpStore,
+    S: HttpStore + AddressStore,
     I: IncomingService<S::Account> + Clone,
 {
     let mut incoming = incoming.clone();
-    let account = get_account(store, &path_username, &password).await?;
+    let ilp_address = store.get_ilp_address();
+    let account = get_account(
+        store,
+        &path_username,
+        password.as_ref(),
+        certificate.as_ref(),
+    )
+    .await?;
 
+    let request_id = request_id
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
     let buffer = bytes::BytesMut::from(body.as_ref());
-    if let Ok(prepare) = Prepare::try_from(buffer) {
-        let result = incoming
-            .handle_request(IncomingRequest {
-                from: account,
-                prepare,
-            })
-            .await;
+    let result = match Prepare::try_from(buffer) {
+        Ok(prepare) => {
+            incoming
+                .handle_request(IncomingRequest {
+                    from: account,
+                    prepare,
+                })
+                .instrument(error_span!(target: "interledger-http",
+                    "ilp_over_http",
+                    request.id = %request_id
+                ))
+                .await
+        }
+        Err(err) => {
+            warn!(
+                "Got malformed ILP packet from account {} (id: {}): {}",
+                account.username(),
+                account.id(),
+                err
+            );
+            counter!("requests.incoming.malformed", 1, "transport" => "http", "from_username" => account.username().to_string());
+            Err(RejectBuilder {
+                code: ErrorCode::F01_INVALID_PACKET,
+                message: b"Invalid ILP packet",
+                triggered_by: Some(&ilp_address),
+                data: &[],
+            }
+            .build())
+        }
+    };
 
-        let bytes: BytesMut = match result {
-            Ok(fulfill) => fulfill.into(),
-            Err(reject) => reject.into(),
-        };
-
-        Ok(warp::http::Response::builder()
-            .header("Content-Type", "application/octet-stream")
-            .status(200)
-            .body(bytes.freeze()) // TODO: bring this back
-            .unwrap())
-    } else {
-        error!("Body was not a valid Prepare packet");
-        Err(Rejection::from(ApiError::invalid_ilp_packet()))
-    }
+    let bytes: BytesMut = match result {
+        Ok(fulfill) => fulfill.into(),
+        Err(reject) => reject.into(),
+    };
+
+    Ok(warp::http::Response::builder()
+        .header("Content-Type", "application/octet-stream")
+        .header(REQUEST_ID_HEADER, request_id)
+        .status(200)
+        .body(bytes.freeze()) // TODO: bring this back
+        .unwrap())
 }
 
 impl<I, S> HttpServer<I, S>
 where
     I: IncomingService<S::Account> + Clone + Send + Sync,
-    S: HttpStore + Clone,
+    S: HttpStore + AddressStore + Clone,
 {
     pub fn new(incoming: I, store: S) -> Self {
         HttpServer { incoming, store }
@@ -110,6 +197,7 @@ where
 
     /// Returns a Warp filter which exposes per-account endpoints for [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/).
     /// The endpoint is /accounts/:username/ilp.
+    /// Serve it with [`serve_tls`](./fn.serve_tls.html) to let peers authenticate with client certificates.
     pub fn as_filter(
         &self,
     ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
@@ -122,7 +210,9 @@ where
             .and(warp::path::param::<Username>())
             .and(warp::path("ilp"))
             .and(warp::path::end())
-            .and(warp::header::<SecretString>("authorization"))
+            .and(warp::header::optional::<SecretString>("authorization"))
+            .and(client_certificate())
+            .and(request_id())
             .and(warp::body::content_length_limit(MAX_PACKET_SIZE))
             .and(warp::body::bytes())
             .and(with_store)
@@ -144,19 +234,34 @@ mod tests {
     use async_trait::async_trait;
     use bytes::BytesMut;
     use http::Response;
-    use interledger_errors::{default_rejection_handler, HttpStoreError};
-    use interledger_packet::{Address, ErrorCode, PrepareBuilder, RejectBuilder};
-    use interledger_service::{incoming_service_fn, Account};
+    use interledger_errors::{default_rejection_handler, AddressStoreError, HttpStoreError};
+    use interledger_packet::{Address, FulfillBuilder, Packet, PacketType, PrepareBuilder};
+    use interledger_service::{incoming_service_fn, IlpResult};
     use once_cell::sync::Lazy;
     use secrecy::SecretString;
     use std::convert::TryInto;
     use std::str::FromStr;
     use std::time::SystemTime;
+    use std::{
+        fmt,
+        sync::{Arc, Mutex},
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id},
+        Event, Subscriber,
+    };
+    use tracing_subscriber::{
+        layer::{Context, Layer, SubscriberExt},
+        registry::LookupSpan,
+        Registry,
+    };
     use url::Url;
     use uuid::Uuid;
 
     static USERNAME: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
     static ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.alice").unwrap());
+    static NODE_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.node").unwrap());
     pub static PREPARE_BYTES: Lazy<BytesMut> = Lazy::new(|| {
         PrepareBuilder {
             amount: 0,
@@ -193,7 +298,7 @@ mod tests {
 
     #[tokio::test]
     async fn new_api_test() {
-        let store = TestStore;
+        let store = TestStore::default();
         let incoming = incoming_service_fn(|_request| {
             Err(RejectBuilder {
                 code: ErrorCode::F02_UNREACHABLE,
@@ -221,8 +326,255 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 200);
     }
 
+    fn fulfilling_server(
+        store: TestStore,
+    ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        let incoming = incoming_service_fn(|_request| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        });
+        HttpServer::new(incoming, store)
+            .as_filter()
+            .recover(default_rejection_handler)
+    }
+
+    async fn call_with_certificate<F>(
+        api: &F,
+        certificate: &ClientCertificate,
+        auth: Option<&str>,
+    ) -> Response<Bytes>
+    where
+        F: warp::Filter + 'static,
+        F::Extract: warp::Reply,
+    {
+        let mut request = warp::test::request()
+            .method("POST")
+            .path("/accounts/alice/ilp")
+            .extension(certificate.clone())
+            .body(PREPARE_BYTES.clone());
+        if let Some(auth) = auth {
+            request = request.header("Authorization", format!("Bearer {}", auth));
+        }
+        request.reply(api).await
+    }
+
+    #[tokio::test]
+    async fn authenticates_with_client_certificate() {
+        let alice = ClientCertificate::from_der(b"alice's certificate");
+        let bob = ClientCertificate::from_der(b"bob's certificate");
+        let api = fulfilling_server(TestStore {
+            client_certificates: vec![alice.fingerprint().to_string()],
+        });
+
+        // The certificate replaces the token
+        let resp = call_with_certificate(&api, &alice, None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body()[0], PacketType::Fulfill as u8);
+
+        // Certificates which are not accepted for the account are rejected,
+        // even with the right token
+        let resp = call_with_certificate(&api, &bob, Some(AUTH_PASSWORD)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        // The token alone is not enough when the account requires a certificate
+        let resp = api_call(&api, "/accounts/alice/ilp", AUTH_PASSWORD).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn uses_bearer_token_if_account_has_no_client_certificates() {
+        let certificate = ClientCertificate::from_der(b"alice's certificate");
+        let api = fulfilling_server(TestStore::default());
+
+        let resp = call_with_certificate(&api, &certificate, Some(AUTH_PASSWORD)).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body()[0], PacketType::Fulfill as u8);
+
+        let resp = call_with_certificate(&api, &certificate, None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_packets() {
+        let receiver = metrics_runtime::Receiver::builder().build().unwrap();
+        let controller = receiver.controller();
+        receiver.install();
+
+        let incoming = incoming_service_fn(|_request| -> IlpResult {
+            panic!("malformed packets must not be passed to the incoming service")
+        });
+        let api = HttpServer::new(incoming, TestStore::default())
+            .as_filter()
+            .recover(default_rejection_handler);
+
+        for body in &[&b"not an ILP packet"[..], &PREPARE_BYTES[..10], &[]] {
+            let resp = warp::test::request()
+                .method("POST")
+                .path("/accounts/alice/ilp")
+                .header("Authorization", format!("Bearer {}", AUTH_PASSWORD))
+                .body(body.to_vec())
+                .reply(&api)
+                .await;
+            assert_eq!(resp.status().as_u16(), 200);
+            match Packet::try_from(BytesMut::from(resp.body().as_ref())).unwrap() {
+                Packet::Reject(reject) => {
+                    assert_eq!(reject.code(), ErrorCode::F01_INVALID_PACKET);
+                    assert_eq!(reject.triggered_by(), Some(NODE_ADDRESS.clone()));
+                }
+                packet => panic!("Expected a Reject, got {:?}", packet),
+            }
+        }
+
+        let malformed = controller
+            .snapshot()
+            .into_measurements()
+            .into_iter()
+            .find(|(key, _)| key.name() == "requests.incoming.malformed")
+            .map(|(key, measurement)| {
+                let labels: Vec<_> = key
+                    .labels()
+                    .map(|label| (label.key().to_string(), label.value().to_string()))
+                    .collect();
+                (labels, measurement)
+            });
+        match malformed {
+            Some((labels, metrics_runtime::Measurement::Counter(count))) => {
+                assert_eq!(
+                    labels,
+                    vec![
+                        ("transport".to_string(), "http".to_string()),
+                        ("from_username".to_string(), "alice".to_string())
+                    ]
+                );
+                assert_eq!(count, 3);
+            }
+            other => panic!("Unexpected malformed packet metric: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn reject_logs_carry_request_id() {
+        let logs = CapturedLogs::default();
+        let _ = tracing_log::LogTracer::init();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(logs.clone()));
+
+        let incoming = incoming_service_fn(|_request| {
+            // Like the validator's logs, this goes through the `log` crate
+            log::error!("Rejecting packet");
+            Err(RejectBuilder {
+                code: ErrorCode::R00_TRANSFER_TIMED_OUT,
+                message: &[],
+                data: &[],
+                triggered_by: None,
+            }
+            .build())
+        });
+        let api = HttpServer::new(incoming, TestStore::default())
+            .as_filter()
+            .recover(default_rejection_handler);
+
+        let first = api_call(&api, "/accounts/alice/ilp", AUTH_PASSWORD).await;
+        let second = api_call(&api, "/accounts/alice/ilp", AUTH_PASSWORD).await;
+        let first_id = first.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        let second_id = second.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert_ne!(first_id, second_id);
+
+        // The ID provided by the peer is used instead of a generated one
+        let third = warp::test::request()
+            .method("POST")
+            .path("/accounts/alice/ilp")
+            .header("Authorization", format!("Bearer {}", AUTH_PASSWORD))
+            .header(REQUEST_ID_HEADER, "peer-request-id")
+            .body(PREPARE_BYTES.clone())
+            .reply(&api)
+            .await;
+        assert_eq!(third.headers()[REQUEST_ID_HEADER], "peer-request-id");
+
+        let logged_ids: Vec<Option<String>> = logs
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(message, _)| message == "Rejecting packet")
+            .map(|(_, request_id)| request_id.clone())
+            .collect();
+        assert_eq!(
+            logged_ids,
+            vec![
+                Some(first_id.to_string()),
+                Some(second_id.to_string()),
+                Some("peer-request-id".to_string())
+            ]
+        );
+    }
+
+    struct RequestId(String);
+
+    /// Log messages with the request ID of the span they were logged in
+    type LogLines = Vec<(String, Option<String>)>;
+
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<LogLines>>);
+
+    struct FieldVisitor<'a> {
+        name: &'static str,
+        value: &'a mut Option<String>,
+    }
+
+    impl<'a> Visit for FieldVisitor<'a> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == self.name {
+                *self.value = Some(format!("{:?}", value));
+            }
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == self.name {
+                *self.value = Some(value.to_string());
+            }
+        }
+    }
+
+    impl<S> Layer<S> for CapturedLogs
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let mut request_id = None;
+            attrs.record(&mut FieldVisitor {
+                name: "request.id",
+                value: &mut request_id,
+            });
+            if let Some(request_id) = request_id {
+                let span = ctx.span(id).unwrap();
+                span.extensions_mut().insert(RequestId(request_id));
+            }
+        }
+
+        fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+            let mut message = None;
+            event.record(&mut FieldVisitor {
+                name: "message",
+                value: &mut message,
+            });
+            let request_id = ctx.scope().find_map(|span| {
+                span.extensions()
+                    .get::<RequestId>()
+                    .map(|request_id| request_id.0.clone())
+            });
+            if let Some(message) = message {
+                self.0.lock().unwrap().push((message, request_id));
+            }
+        }
+    }
+
     #[derive(Debug, Clone)]
-    struct TestAccount;
+    struct TestAccount {
+        client_certificates: Vec<String>,
+    }
     impl Account for TestAccount {
         fn id(&self) -> Uuid {
             Uuid::new_v4()
@@ -251,10 +603,24 @@ mod tests {
         fn get_http_url(&self) -> Option<&Url> {
             unimplemented!()
         }
+
+        fn get_http_client_certificates(&self) -> &[String] {
+            &self.client_certificates
+        }
     }
 
-    #[derive(Debug, Clone)]
-    struct TestStore;
+    #[derive(Debug, Clone, Default)]
+    struct TestStore {
+        client_certificates: Vec<String>,
+    }
+
+    impl TestStore {
+        fn account(&self) -> TestAccount {
+            TestAccount {
+                client_certificates: self.client_certificates.clone(),
+            }
+        }
+    }
 
     #[async_trait]
     impl HttpStore for TestStore {
@@ -266,10 +632,39 @@ mod tests {
             token: &str,
         ) -> Result<Self::Account, HttpStoreError> {
             if username == &*USERNAME && token == AUTH_PASSWORD {
-                Ok(TestAccount)
+                Ok(self.account())
             } else {
                 Err(HttpStoreError::Unauthorized(username.to_string()))
             }
         }
+
+        async fn get_account_from_client_certificate(
+            &self,
+            username: &Username,
+            fingerprint: &str,
+        ) -> Result<Option<Self::Account>, HttpStoreError> {
+            if username != &*USERNAME || self.client_certificates.is_empty() {
+                Ok(None)
+            } else if self.client_certificates.iter().any(|f| f == fingerprint) {
+                Ok(Some(self.account()))
+            } else {
+                Err(HttpStoreError::Unauthorized(username.to_string()))
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _ilp_address: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            NODE_ADDRESS.clone()
+        }
     }
 }
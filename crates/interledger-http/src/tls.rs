@@ -0,0 +1,450 @@
+use crate::IdleTimeout;
+use futures::{Stream, StreamExt};
+use log::{debug, error};
+use reqwest::Certificate;
+use ring::digest::{digest, SHA256};
+use serde::Deserialize;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::{
+    internal::pemfile, AllowAnyAnonymousOrAuthenticatedClient, NoClientAuth, ProtocolVersion,
+    RootCertStore, ServerConfig, Session, ALL_CIPHERSUITES,
+};
+use tokio_rustls::TlsAcceptor;
+use warp::filters::BoxedFilter;
+use warp::hyper::{server::conn::Http, service::service_fn, service::Service};
+use warp::Reply;
+
+/// The verified TLS client certificate the peer presented when connecting.
+///
+/// [`serve_tls`](./fn.serve_tls.html) adds it to the extensions of every request
+/// received on the connection, where the [`HttpServer`](./struct.HttpServer.html)
+/// uses it to authenticate the account.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientCertificate {
+    fingerprint: String,
+}
+
+impl ClientCertificate {
+    /// Creates the certificate from its DER encoding
+    pub fn from_der(certificate: &[u8]) -> Self {
+        let fingerprint = digest(&SHA256, certificate)
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        ClientCertificate { fingerprint }
+    }
+
+    /// The lowercase hex-encoded SHA-256 hash of the DER-encoded certificate
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+}
+
+/// Converts a certificate fingerprint to the format of
+/// [`ClientCertificate::fingerprint`](./struct.ClientCertificate.html#method.fingerprint).
+/// Both upper and lowercase hex are accepted and the bytes may be separated by colons,
+/// like in the output of `openssl x509 -noout -fingerprint -sha256`.
+///
+/// Returns `None` if the fingerprint is not a hex-encoded SHA-256 hash.
+pub fn normalize_fingerprint(fingerprint: &str) -> Option<String> {
+    let normalized: String = fingerprint
+        .chars()
+        .filter(|c| *c != ':')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    if normalized.len() == 64 && normalized.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(normalized)
+    } else {
+        None
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Parses the PEM-encoded certificates of certificate authorities (for example a CA bundle),
+/// so that outgoing HTTPS clients can trust them in addition to the system's root certificates.
+pub fn parse_ca_certificates(pem: &[u8]) -> io::Result<Vec<Certificate>> {
+    let certificates =
+        pemfile::certs(&mut &pem[..]).map_err(|_| invalid_data("invalid CA certificates"))?;
+    if certificates.is_empty() {
+        return Err(invalid_data("no CA certificates found"));
+    }
+    certificates
+        .iter()
+        .map(|certificate| {
+            Certificate::from_der(&certificate.0).map_err(|err| invalid_data(&err.to_string()))
+        })
+        .collect()
+}
+
+/// Creates the TLS configuration of a server using the PEM-encoded certificate chain
+/// and (PKCS8 or RSA) private key.
+///
+/// If the PEM-encoded certificates of the authorities which issue the peers' client
+/// certificates are provided, peers may authenticate with a client certificate.
+/// Peers which do not have one can still connect (and use their bearer token).
+pub fn tls_server_config(
+    certificate_chain: &[u8],
+    private_key: &[u8],
+    client_ca: Option<&[u8]>,
+) -> io::Result<ServerConfig> {
+    let mut config = if let Some(client_ca) = client_ca {
+        let mut roots = RootCertStore::empty();
+        match roots.add_pem_file(&mut &client_ca[..]) {
+            Ok((valid, _)) if valid > 0 => {}
+            _ => return Err(invalid_data("no valid client CA certificates found")),
+        }
+        ServerConfig::new(AllowAnyAnonymousOrAuthenticatedClient::new(roots))
+    } else {
+        ServerConfig::new(NoClientAuth::new())
+    };
+
+    let certificate_chain = pemfile::certs(&mut &certificate_chain[..])
+        .map_err(|_| invalid_data("invalid certificate chain"))?;
+    let mut keys = pemfile::pkcs8_private_keys(&mut &private_key[..])
+        .map_err(|_| invalid_data("invalid private key"))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut &private_key[..])
+            .map_err(|_| invalid_data("invalid private key"))?;
+    }
+    let key = keys
+        .into_iter()
+        .next()
+        .ok_or_else(|| invalid_data("no private key found"))?;
+    config
+        .set_single_cert(certificate_chain, key)
+        .map_err(|err| invalid_data(&err.to_string()))?;
+    config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+    Ok(config)
+}
+
+/// The oldest TLS version a server negotiates. Versions older than TLS 1.2 are never supported.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+impl Default for TlsVersion {
+    fn default() -> Self {
+        TlsVersion::Tls12
+    }
+}
+
+/// Restricts the server to the `min_version` (or newer TLS versions) and to the cipher
+/// suites with the given names, e.g. `TLS13_AES_256_GCM_SHA384` or
+/// `TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256`. All supported cipher suites are allowed if
+/// no names are given. Clients which can not negotiate any of them are refused.
+///
+/// Returns an error if a cipher suite is not supported, or if none of the allowed ones
+/// can be used with the allowed versions.
+pub fn restrict_tls_server_config(
+    config: &mut ServerConfig,
+    min_version: TlsVersion,
+    cipher_suites: &[String],
+) -> io::Result<()> {
+    config.versions = match min_version {
+        TlsVersion::Tls12 => vec![ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2],
+        TlsVersion::Tls13 => vec![ProtocolVersion::TLSv1_3],
+    };
+    if !cipher_suites.is_empty() {
+        config.ciphersuites = cipher_suites
+            .iter()
+            .map(|name| {
+                ALL_CIPHERSUITES
+                    .iter()
+                    .copied()
+                    .find(|suite| format!("{:?}", suite.suite).eq_ignore_ascii_case(name))
+                    .ok_or_else(|| invalid_data(&format!("unsupported cipher suite {}", name)))
+            })
+            .collect::<io::Result<_>>()?;
+    }
+    let versions = &config.versions;
+    if config.ciphersuites.iter().any(|suite| {
+        versions
+            .iter()
+            .any(|version| suite.usable_for_version(*version))
+    }) {
+        Ok(())
+    } else {
+        Err(invalid_data(
+            "none of the cipher suites can be used with the TLS versions",
+        ))
+    }
+}
+
+/// Serves the filter over TLS (HTTP/1.1 or HTTP/2) on the given address.
+///
+/// Unlike warp's own TLS server, this makes the peer's verified
+/// [`ClientCertificate`](./struct.ClientCertificate.html) available to the filter as a
+/// request extension. Only returns if the address cannot be bound.
+pub async fn serve_tls<T>(
+    filter: BoxedFilter<(T,)>,
+    addr: SocketAddr,
+    config: ServerConfig,
+) -> io::Result<()>
+where
+    T: Reply + Send + 'static,
+{
+    let mut listener = TcpListener::bind(addr).await?;
+    serve_tls_incoming(filter, listener.incoming(), config, None).await;
+    Ok(())
+}
+
+/// Serves the filter over TLS like [`serve_tls`](./fn.serve_tls.html), but on the
+/// connections of the given stream, e.g. those accepted by a listener whose socket
+/// options were set by the caller. Connections which are idle for longer than the
+/// `idle_timeout` (if there is one) are closed. Only returns once the stream ends.
+pub async fn serve_tls_incoming<T, I>(
+    filter: BoxedFilter<(T,)>,
+    mut incoming: I,
+    config: ServerConfig,
+    idle_timeout: Option<Duration>,
+) where
+    T: Reply + Send + 'static,
+    I: Stream<Item = io::Result<TcpStream>> + Unpin,
+{
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let service = warp::service(filter);
+    while let Some(stream) = incoming.next().await {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                error!("Error accepting connection: {}", err);
+                continue;
+            }
+        };
+        let remote_addr = match stream.peer_addr() {
+            Ok(addr) => addr,
+            Err(err) => {
+                debug!("Error getting the address of a connection: {}", err);
+                continue;
+            }
+        };
+        let stream = IdleTimeout::new(stream, idle_timeout);
+        let acceptor = acceptor.clone();
+        let service = service.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!("TLS handshake with {} failed: {}", remote_addr, err);
+                    return;
+                }
+            };
+            let certificate = stream
+                .get_ref()
+                .1
+                .get_peer_certificates()
+                .and_then(|chain| {
+                    chain
+                        .first()
+                        .map(|cert| ClientCertificate::from_der(&cert.0))
+                });
+            let handler = service_fn(move |mut request| {
+                if let Some(ref certificate) = certificate {
+                    request.extensions_mut().insert(certificate.clone());
+                }
+                service.clone().call(request)
+            });
+            if let Err(err) = Http::new().serve_connection(stream, handler).await {
+                debug!("Error serving connection from {}: {}", remote_addr, err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+    use tokio_rustls::rustls::ClientConfig;
+    use tokio_rustls::webpki::DNSNameRef;
+    use tokio_rustls::TlsConnector;
+    use warp::Filter;
+
+    #[test]
+    fn normalizes_fingerprints() {
+        let fingerprint = "ab".repeat(32);
+        assert_eq!(
+            normalize_fingerprint(&fingerprint.to_uppercase()),
+            Some(fingerprint.clone())
+        );
+        let with_colons = vec!["AB"; 32].join(":");
+        assert_eq!(normalize_fingerprint(&with_colons), Some(fingerprint));
+        assert_eq!(normalize_fingerprint("abcd"), None);
+        assert_eq!(normalize_fingerprint(&"xy".repeat(32)), None);
+    }
+
+    #[test]
+    fn parses_ca_certificates() {
+        let bundle = format!("{}{}", CA_CERTIFICATE, ALICE_CERTIFICATE);
+        assert_eq!(parse_ca_certificates(bundle.as_bytes()).unwrap().len(), 2);
+        assert!(parse_ca_certificates(b"not a certificate").is_err());
+    }
+
+    /// Serves an empty response over TLS with the given versions and cipher suites
+    async fn serve_restricted(min_version: TlsVersion, cipher_suites: &[&str]) -> SocketAddr {
+        let mut config = tls_server_config(
+            SERVER_CERTIFICATE.as_bytes(),
+            SERVER_PRIVATE_KEY.as_bytes(),
+            None,
+        )
+        .unwrap();
+        let cipher_suites: Vec<String> = cipher_suites.iter().map(|s| s.to_string()).collect();
+        restrict_tls_server_config(&mut config, min_version, &cipher_suites).unwrap();
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        tokio::spawn(serve_tls(
+            warp::any().map(warp::reply).boxed(),
+            addr,
+            config,
+        ));
+        tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+        addr
+    }
+
+    /// Completes a TLS handshake with a client which only offers the given version
+    /// (and cipher suite, if there is one)
+    async fn handshake(
+        addr: SocketAddr,
+        version: ProtocolVersion,
+        cipher_suite: Option<&str>,
+    ) -> io::Result<()> {
+        let mut config = ClientConfig::new();
+        config
+            .root_store
+            .add_pem_file(&mut CA_CERTIFICATE.as_bytes())
+            .unwrap();
+        config.versions = vec![version];
+        if let Some(name) = cipher_suite {
+            config.ciphersuites = ALL_CIPHERSUITES
+                .iter()
+                .copied()
+                .filter(|suite| format!("{:?}", suite.suite) == name)
+                .collect();
+        }
+        let stream = TcpStream::connect(addr).await?;
+        let domain = DNSNameRef::try_from_ascii_str("localhost").unwrap();
+        TlsConnector::from(Arc::new(config))
+            .connect(domain, stream)
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_old_tls_versions() {
+        let addr = serve_restricted(TlsVersion::Tls13, &[]).await;
+        assert!(handshake(addr, ProtocolVersion::TLSv1_2, None)
+            .await
+            .is_err());
+        assert!(handshake(addr, ProtocolVersion::TLSv1_3, None)
+            .await
+            .is_ok());
+
+        // TLS 1.2 is allowed by default
+        let addr = serve_restricted(TlsVersion::default(), &[]).await;
+        assert!(handshake(addr, ProtocolVersion::TLSv1_2, None)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn refuses_disallowed_cipher_suites() {
+        let addr = serve_restricted(TlsVersion::Tls12, &["TLS13_AES_256_GCM_SHA384"]).await;
+        assert!(handshake(
+            addr,
+            ProtocolVersion::TLSv1_3,
+            Some("TLS13_CHACHA20_POLY1305_SHA256")
+        )
+        .await
+        .is_err());
+        assert!(handshake(
+            addr,
+            ProtocolVersion::TLSv1_3,
+            Some("TLS13_AES_256_GCM_SHA384")
+        )
+        .await
+        .is_ok());
+    }
+
+    #[test]
+    fn rejects_unusable_cipher_suites() {
+        let mut config = tls_server_config(
+            SERVER_CERTIFICATE.as_bytes(),
+            SERVER_PRIVATE_KEY.as_bytes(),
+            None,
+        )
+        .unwrap();
+        assert!(
+            restrict_tls_server_config(&mut config, TlsVersion::Tls12, &["RC4".to_string()])
+                .is_err()
+        );
+        // TLS 1.2 cipher suites can not be used if only TLS 1.3 is allowed
+        let tls12_only = vec!["TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256".to_string()];
+        assert!(restrict_tls_server_config(&mut config, TlsVersion::Tls13, &tls12_only).is_err());
+        assert!(restrict_tls_server_config(&mut config, TlsVersion::Tls12, &tls12_only).is_ok());
+    }
+
+    #[tokio::test]
+    async fn passes_client_certificate_to_filter() {
+        let filter = warp::ext::get::<ClientCertificate>()
+            .map(|certificate: ClientCertificate| certificate.fingerprint().to_string())
+            .or(warp::any().map(|| "none".to_string()))
+            .unify()
+            .boxed();
+        let config = tls_server_config(
+            SERVER_CERTIFICATE.as_bytes(),
+            SERVER_PRIVATE_KEY.as_bytes(),
+            Some(CA_CERTIFICATE.as_bytes()),
+        )
+        .unwrap();
+        // Find a free port for the server
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        tokio::spawn(serve_tls(filter, addr, config));
+        tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+
+        let url = format!("https://localhost:{}", addr.port());
+        let client = |identity: Option<&str>| {
+            let mut builder = reqwest::Client::builder()
+                .use_rustls_tls()
+                .add_root_certificate(
+                    reqwest::Certificate::from_pem(CA_CERTIFICATE.as_bytes()).unwrap(),
+                );
+            if let Some(identity) = identity {
+                builder =
+                    builder.identity(reqwest::Identity::from_pem(identity.as_bytes()).unwrap());
+            }
+            builder.build().unwrap()
+        };
+
+        for (key, certificate) in &[
+            (ALICE_PRIVATE_KEY, ALICE_CERTIFICATE),
+            (BOB_PRIVATE_KEY, BOB_CERTIFICATE),
+        ] {
+            let identity = format!("{}{}", key, certificate);
+            let response = client(Some(&identity)).get(&url).send().await.unwrap();
+            let expected = ClientCertificate::from_der(
+                &pemfile::certs(&mut certificate.as_bytes()).unwrap()[0].0,
+            );
+            assert_eq!(response.text().await.unwrap(), expected.fingerprint());
+        }
+
+        let response = client(None).get(&url).send().await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "none");
+    }
+}
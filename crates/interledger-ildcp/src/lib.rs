@@ -3,6 +3,7 @@
 //! Client and server implementations of the [Interledger Dynamic Configuration Protocol (ILDCP)](https://github.com/interledger/rfcs/blob/master/0031-dynamic-configuration-protocol/0031-dynamic-configuration-protocol.md).
 //!
 //! This is used by clients to query for their ILP address and asset details such as asset code and scale.
+//! The server only answers the requests of accounts which are children of the node.
 
 use interledger_service::Account;
 
@@ -13,3 +14,11 @@ mod server;
 pub use client::get_ildcp_info;
 pub use packet::*;
 pub use server::IldcpService;
+
+/// Extension trait for [Account](../interledger_service/trait.Account.html) with the
+/// information the [`IldcpService`](./struct.IldcpService.html) needs to answer its requests
+pub trait IldcpAccount: Account {
+    /// Whether the account is a child of this node, which is assigned its ILP address
+    /// (and asset details) via ILDCP. Requests from other accounts are rejected
+    fn is_ildcp_child(&self) -> bool;
+}
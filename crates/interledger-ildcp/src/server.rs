@@ -1,13 +1,16 @@
 use super::packet::*;
-use super::Account;
+use super::IldcpAccount;
 use async_trait::async_trait;
 use interledger_packet::*;
 use interledger_service::*;
-use log::debug;
+use log::{debug, warn};
 use std::marker::PhantomData;
 
 /// A simple service that intercepts incoming ILDCP requests
 /// and responds using the information in the Account struct.
+///
+/// Only children of the node (see [`IldcpAccount`](./trait.IldcpAccount.html)) are
+/// assigned their address, the requests of other accounts are rejected.
 #[derive(Clone)]
 pub struct IldcpService<I, A> {
     next: I,
@@ -17,7 +20,7 @@ pub struct IldcpService<I, A> {
 impl<I, A> IldcpService<I, A>
 where
     I: IncomingService<A>,
-    A: Account,
+    A: IldcpAccount,
 {
     pub fn new(next: I) -> Self {
         IldcpService {
@@ -31,10 +34,23 @@ where
 impl<I, A> IncomingService<A> for IldcpService<I, A>
 where
     I: IncomingService<A> + Send,
-    A: Account,
+    A: IldcpAccount,
 {
     async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
         if is_ildcp_request(&request.prepare) {
+            if !request.from.is_ildcp_child() {
+                warn!(
+                    "Rejecting ildcp request from account {}, which is not a child",
+                    request.from.username()
+                );
+                return Err(RejectBuilder {
+                    code: ErrorCode::F00_BAD_REQUEST,
+                    message: b"ILDCP requests are only answered for child accounts",
+                    data: &[],
+                    triggered_by: None,
+                }
+                .build());
+            }
             let from = request.from.ilp_address();
             let builder = IldcpResponseBuilder {
                 ilp_address: &from,
@@ -63,7 +79,9 @@ mod tests {
         Lazy::new(|| Address::from_str("example.alice").unwrap());
 
     #[derive(Clone, Debug, Copy)]
-    struct TestAccount;
+    struct TestAccount {
+        is_child: bool,
+    }
 
     impl Account for TestAccount {
         fn id(&self) -> Uuid {
@@ -87,12 +105,14 @@ mod tests {
         }
     }
 
-    #[tokio::test]
-    async fn handles_request() {
-        let from = TestAccount;
-        let prepare = IldcpRequest {}.to_prepare();
-        let req = IncomingRequest { from, prepare };
-        let mut service = IldcpService::new(incoming_service_fn(|_| {
+    impl IldcpAccount for TestAccount {
+        fn is_ildcp_child(&self) -> bool {
+            self.is_child
+        }
+    }
+
+    fn test_service() -> IldcpService<impl IncomingService<TestAccount> + Clone, TestAccount> {
+        IldcpService::new(incoming_service_fn(|_| {
             Err(RejectBuilder {
                 code: ErrorCode::F02_UNREACHABLE,
                 message: b"No other incoming handler!",
@@ -100,7 +120,15 @@ mod tests {
                 triggered_by: None,
             }
             .build())
-        }));
+        }))
+    }
+
+    #[tokio::test]
+    async fn handles_request() {
+        let from = TestAccount { is_child: true };
+        let prepare = IldcpRequest {}.to_prepare();
+        let req = IncomingRequest { from, prepare };
+        let mut service = test_service();
 
         let result = service.handle_request(req).await.unwrap();
         assert_eq!(result.data().len(), 19);
@@ -110,4 +138,17 @@ mod tests {
         assert_eq!(ildpc_info.asset_code(), b"XYZ");
         assert_eq!(ildpc_info.asset_scale(), 9);
     }
+
+    #[tokio::test]
+    async fn rejects_request_from_non_child() {
+        let from = TestAccount { is_child: false };
+        let prepare = IldcpRequest {}.to_prepare();
+        let reject = test_service()
+            .handle_request(IncomingRequest { from, prepare })
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F00_BAD_REQUEST);
+
+        assert!(get_ildcp_info(&mut test_service(), from).await.is_err());
+    }
 }
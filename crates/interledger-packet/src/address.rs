@@ -75,6 +75,27 @@ impl TryFrom<Bytes> for Address {
     }
 }
 
+impl Address {
+    /// Creates an ILP address from bytes which only have to be a non-empty UTF-8 string,
+    /// without checking them against the address requirements.
+    /// Used for the destinations of the Prepare packets, which are checked by the services
+    /// handling them (see [`is_valid`](#method.is_valid)).
+    pub(crate) fn try_from_lenient(bytes: Bytes) -> Result<Self, ParseError> {
+        if bytes.is_empty() {
+            return Err(ParseError::InvalidAddress(AddressError::InvalidLength(0)));
+        }
+        str::from_utf8(&bytes)?;
+        Ok(Address(bytes))
+    }
+
+    /// Returns whether the address meets the [address
+    /// requirements](https://interledger.org/rfcs/0015-ilp-addresses/#address-requirements),
+    /// which is always the case unless it is the destination of a parsed Prepare packet.
+    pub fn is_valid(&self) -> bool {
+        self.len() <= MAX_ADDRESS_LENGTH && ADDRESS_PATTERN.is_match(self)
+    }
+}
+
 impl TryFrom<&[u8]> for Address {
     type Error = ParseError;
 
@@ -188,8 +209,9 @@ impl<'de> serde::Deserialize<'de> for Address {
     where
         D: serde::Deserializer<'de>,
     {
-        let string = <&str>::deserialize(deserializer)?;
-        Address::from_str(string).map_err(serde::de::Error::custom)
+        // Owned strings are accepted as well, which is what e.g. serde_json's `Value` provides
+        let string = String::deserialize(deserializer)?;
+        Address::from_str(&string).map_err(serde::de::Error::custom)
     }
 }
 
@@ -264,12 +286,39 @@ mod test_address {
         assert!(Address::try_from(too_long_address).is_err());
     }
 
+    #[test]
+    fn test_try_from_lenient() {
+        for address in VALID_ADDRESSES {
+            let address = Address::try_from_lenient(Bytes::from(*address)).unwrap();
+            assert!(address.is_valid(), "address: {:?}", address);
+        }
+        let longest_address = Address::try_from_lenient(Bytes::from(make_address(1023))).unwrap();
+        assert!(longest_address.is_valid());
+
+        // Malformed addresses are accepted as long as they are non-empty UTF-8 strings
+        for address in INVALID_ADDRESSES {
+            match Address::try_from_lenient(Bytes::from(*address)) {
+                Ok(address) => assert!(!address.is_valid(), "address: {:?}", address),
+                Err(_) => assert!(address.is_empty() || str::from_utf8(address).is_err()),
+            }
+        }
+        let too_long_address = Address::try_from_lenient(Bytes::from(make_address(1024))).unwrap();
+        assert!(!too_long_address.is_valid());
+
+        assert!(Address::try_from_lenient(Bytes::from("")).is_err());
+        assert!(Address::try_from_lenient(Bytes::from(&b"test.alic\xF0"[..])).is_err());
+    }
+
     #[test]
     fn test_deserialize() {
         assert_de_tokens(
             &Address::try_from(Bytes::from("test.alice")).unwrap(),
             &[Token::BorrowedStr("test.alice")],
         );
+        assert_de_tokens(
+            &Address::try_from(Bytes::from("test.alice")).unwrap(),
+            &[Token::String("test.alice")],
+        );
         assert_de_tokens_error::<Address>(
             &[Token::BorrowedStr("test.alice ")],
             "Invalid Address: Invalid address format",
@@ -0,0 +1,142 @@
+//! Helpers for converting amounts between assets with different scales.
+//!
+//! An amount of `1` with an asset scale of `2` represents `0.01` units of the asset, so the
+//! same value is represented by an amount of `10` with an asset scale of `3`.
+
+/// Error returned when an amount cannot be converted between two asset scales
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum AmountConversionError {
+    #[error("amount {amount} with scale {from_scale} does not fit in a u64 with scale {to_scale}")]
+    Overflow {
+        amount: u64,
+        from_scale: u8,
+        to_scale: u8,
+    },
+    #[error("amount {amount} with scale {from_scale} cannot be represented with scale {to_scale} without losing precision")]
+    PrecisionLoss {
+        amount: u64,
+        from_scale: u8,
+        to_scale: u8,
+    },
+}
+
+/// Converts the amount from one asset scale to another.
+///
+/// Returns an error if the converted amount would overflow a `u64`, or if
+/// it would lose precision (e.g. converting 15 with scale 1 to scale 0).
+pub fn convert_scale(
+    amount: u64,
+    from_scale: u8,
+    to_scale: u8,
+) -> Result<u64, AmountConversionError> {
+    match convert_scale_with_remainder(amount, from_scale, to_scale)? {
+        (converted, 0) => Ok(converted),
+        _ => Err(AmountConversionError::PrecisionLoss {
+            amount,
+            from_scale,
+            to_scale,
+        }),
+    }
+}
+
+/// Converts the amount from one asset scale to another, rounding down.
+///
+/// Returns the converted amount and the remainder which could not be represented
+/// with the new scale, denominated in the original scale. The remainder is only
+/// ever non-zero when converting to a smaller scale.
+///
+/// Returns an error if the converted amount would overflow a `u64`.
+pub fn convert_scale_with_remainder(
+    amount: u64,
+    from_scale: u8,
+    to_scale: u8,
+) -> Result<(u64, u64), AmountConversionError> {
+    let overflow = AmountConversionError::Overflow {
+        amount,
+        from_scale,
+        to_scale,
+    };
+    if to_scale >= from_scale {
+        if amount == 0 {
+            return Ok((0, 0));
+        }
+        let factor = 10u64
+            .checked_pow(u32::from(to_scale - from_scale))
+            .ok_or(overflow)?;
+        let converted = amount.checked_mul(factor).ok_or(overflow)?;
+        Ok((converted, 0))
+    } else {
+        match 10u64.checked_pow(u32::from(from_scale - to_scale)) {
+            Some(factor) => Ok((amount / factor, amount % factor)),
+            // The factor is larger than any u64, so nothing is left of the amount
+            None => Ok((0, amount)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_scale() {
+        assert_eq!(convert_scale(123, 9, 9), Ok(123));
+        assert_eq!(convert_scale_with_remainder(123, 9, 9), Ok((123, 0)));
+    }
+
+    #[test]
+    fn upscales() {
+        assert_eq!(convert_scale(15, 2, 6), Ok(150_000));
+        assert_eq!(convert_scale_with_remainder(15, 2, 6), Ok((150_000, 0)));
+        assert_eq!(convert_scale(0, 0, 255), Ok(0));
+    }
+
+    #[test]
+    fn downscales_without_remainder() {
+        assert_eq!(convert_scale(150_000, 6, 2), Ok(15));
+        assert_eq!(convert_scale_with_remainder(150_000, 6, 2), Ok((15, 0)));
+    }
+
+    #[test]
+    fn downscales_with_remainder() {
+        assert_eq!(
+            convert_scale(150_001, 6, 2),
+            Err(AmountConversionError::PrecisionLoss {
+                amount: 150_001,
+                from_scale: 6,
+                to_scale: 2,
+            })
+        );
+        assert_eq!(convert_scale_with_remainder(150_001, 6, 2), Ok((15, 1)));
+        // the whole amount is lost if it is smaller than one unit with the new scale
+        assert_eq!(convert_scale_with_remainder(99, 2, 0), Ok((0, 99)));
+        assert_eq!(
+            convert_scale_with_remainder(std::u64::MAX, 255, 0),
+            Ok((0, std::u64::MAX))
+        );
+    }
+
+    #[test]
+    fn overflows() {
+        let err = AmountConversionError::Overflow {
+            amount: std::u64::MAX / 10 + 1,
+            from_scale: 0,
+            to_scale: 1,
+        };
+        assert_eq!(convert_scale(std::u64::MAX / 10 + 1, 0, 1), Err(err));
+        assert_eq!(
+            convert_scale_with_remainder(std::u64::MAX / 10 + 1, 0, 1),
+            Err(err)
+        );
+        assert_eq!(
+            convert_scale(std::u64::MAX / 10, 0, 1),
+            Ok(std::u64::MAX / 10 * 10)
+        );
+        // the scaling factor itself does not fit in a u64
+        assert!(convert_scale(1, 0, 20).is_err());
+        assert_eq!(
+            err.to_string(),
+            "amount 1844674407370955162 with scale 0 does not fit in a u64 with scale 1"
+        );
+    }
+}
@@ -3,6 +3,7 @@
 //! Interledger packet serialization/deserialization.
 
 mod address;
+pub mod amount;
 
 mod error;
 mod errors;
@@ -15,6 +16,6 @@ pub use self::address::{Address, AddressError};
 pub use self::error::{ErrorClass, ErrorCode};
 pub use self::errors::ParseError;
 
-pub use self::packet::MaxPacketAmountDetails;
+pub use self::packet::{set_debug_data, MaxPacketAmountDetails};
 pub use self::packet::{Fulfill, Packet, PacketType, Prepare, Reject};
 pub use self::packet::{FulfillBuilder, PrepareBuilder, RejectBuilder};
@@ -3,10 +3,11 @@ use std::fmt;
 use std::io::prelude::*;
 use std::io::Cursor;
 use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::SystemTime;
 
 use byteorder::{BigEndian, ReadBytesExt};
-use bytes::{BufMut, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 use chrono::{DateTime, TimeZone, Utc};
 
 use super::oer::{self, BufOerExt, MutBufOerExt};
@@ -21,6 +22,33 @@ const ERROR_CODE_LEN: usize = 3;
 
 static INTERLEDGER_TIMESTAMP_FORMAT: &str = "%Y%m%d%H%M%S%3f";
 
+static DEBUG_DATA: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether the `Debug` output of packets (which is what ends up in the logs) includes
+/// their `data`, hex-encoded. By default it only includes the length of the `data`, so that
+/// the payloads passed through a connector are never logged.
+pub fn set_debug_data(enabled: bool) {
+    DEBUG_DATA.store(enabled, Ordering::Relaxed);
+}
+
+/// The `Debug` output of a packet, which only includes its `data` if asked for
+trait DebugPacket {
+    fn fmt_packet(&self, formatter: &mut fmt::Formatter, include_data: bool) -> fmt::Result;
+}
+
+/// Formats a packet as allowed by `set_debug_data`
+fn fmt_debug<P: DebugPacket>(packet: &P, formatter: &mut fmt::Formatter) -> fmt::Result {
+    packet.fmt_packet(formatter, DEBUG_DATA.load(Ordering::Relaxed))
+}
+
+/// Adds the `data` of a packet to its `Debug` output
+fn debug_data(debug: &mut fmt::DebugStruct, data: &[u8], include_data: bool) {
+    debug.field("data_length", &data.len());
+    if include_data {
+        debug.field("data", &hex::encode(data));
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum PacketType {
@@ -150,7 +178,7 @@ impl TryFrom<BytesMut> for Prepare {
         // Skip execution condition.
         content.skip(CONDITION_LEN)?;
 
-        let destination = Address::try_from(content.read_var_octet_string()?)?;
+        let destination = Address::try_from_lenient(Bytes::from(content.read_var_octet_string()?))?;
 
         // Skip the data.
         let data_offset = content_offset + content_len - content.len();
@@ -206,6 +234,10 @@ impl Prepare {
         &self.buffer[begin..end]
     }
 
+    /// The destination of the packet. Parsed packets are only required to have a non-empty
+    /// UTF-8 destination, use [`Address::is_valid`](./struct.Address.html#method.is_valid)
+    /// to check it against the [ILP address
+    /// requirements](https://interledger.org/rfcs/0015-ilp-addresses/#address-requirements)
     #[inline]
     pub fn destination(&self) -> Address {
         self.destination.clone()
@@ -233,8 +265,14 @@ impl AsRef<[u8]> for Prepare {
 
 impl fmt::Debug for Prepare {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter
-            .debug_struct("Prepare")
+        fmt_debug(self, formatter)
+    }
+}
+
+impl DebugPacket for Prepare {
+    fn fmt_packet(&self, formatter: &mut fmt::Formatter, include_data: bool) -> fmt::Result {
+        let mut debug = formatter.debug_struct("Prepare");
+        debug
             .field("destination", &self.destination())
             .field("amount", &self.amount())
             .field(
@@ -244,9 +282,9 @@ impl fmt::Debug for Prepare {
             .field(
                 "execution_condition",
                 &hex::encode(self.execution_condition()),
-            )
-            .field("data_length", &self.data().len())
-            .finish()
+            );
+        debug_data(&mut debug, self.data(), include_data);
+        debug.finish()
     }
 }
 
@@ -355,11 +393,16 @@ impl From<Fulfill> for BytesMut {
 
 impl fmt::Debug for Fulfill {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter
-            .debug_struct("Fulfill")
-            .field("fulfillment", &hex::encode(self.fulfillment()))
-            .field("data_length", &self.data().len())
-            .finish()
+        fmt_debug(self, formatter)
+    }
+}
+
+impl DebugPacket for Fulfill {
+    fn fmt_packet(&self, formatter: &mut fmt::Formatter, include_data: bool) -> fmt::Result {
+        let mut debug = formatter.debug_struct("Fulfill");
+        debug.field("fulfillment", &hex::encode(self.fulfillment()));
+        debug_data(&mut debug, self.data(), include_data);
+        debug.finish()
     }
 }
 
@@ -477,16 +520,22 @@ impl From<Reject> for BytesMut {
 
 impl fmt::Debug for Reject {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter
-            .debug_struct("Reject")
+        fmt_debug(self, formatter)
+    }
+}
+
+impl DebugPacket for Reject {
+    fn fmt_packet(&self, formatter: &mut fmt::Formatter, include_data: bool) -> fmt::Result {
+        let mut debug = formatter.debug_struct("Reject");
+        debug
             .field("code", &self.code())
             .field(
                 "message",
                 &str::from_utf8(self.message()).map_err(|_| fmt::Error)?,
             )
-            .field("triggered_by", &self.triggered_by())
-            .field("data_length", &self.data().len())
-            .finish()
+            .field("triggered_by", &self.triggered_by());
+        debug_data(&mut debug, self.data(), include_data);
+        debug.finish()
     }
 }
 
@@ -716,8 +765,16 @@ mod test_prepare {
 
     #[test]
     fn test_invalid_address() {
+        // Malformed destinations are parsed, so that services can decide what to do with them
         let mut prep = BytesMut::from(PREPARE_BYTES);
         prep[67] = 42; // convert a byte from the address to a junk character
+        let prepare = Prepare::try_from(prep).unwrap();
+        assert!(!prepare.destination().is_valid());
+        assert!(PREPARE.destination().is_valid());
+
+        // But they must be UTF-8
+        let mut prep = BytesMut::from(PREPARE_BYTES);
+        prep[67] = 0xF0;
         assert!(Prepare::try_from(prep).is_err());
     }
 
@@ -929,6 +986,54 @@ mod test_reject {
     }
 }
 
+#[cfg(test)]
+mod test_debug_data {
+    use super::*;
+    use crate::fixtures::{DATA, FULFILL, PREPARE, REJECT};
+
+    /// Formats a packet with the given setting, without changing the global one
+    struct WithData<'a, P>(&'a P, bool);
+
+    impl<'a, P: DebugPacket> fmt::Debug for WithData<'a, P> {
+        fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            self.0.fmt_packet(formatter, self.1)
+        }
+    }
+
+    fn outputs(include_data: bool) -> Vec<String> {
+        vec![
+            format!("{:?}", WithData(&*PREPARE, include_data)),
+            format!("{:?}", WithData(&*FULFILL, include_data)),
+            format!("{:?}", WithData(&*REJECT, include_data)),
+        ]
+    }
+
+    #[test]
+    fn only_includes_data_if_enabled() {
+        let data = hex::encode(DATA);
+        for output in outputs(false) {
+            assert!(output.contains(&format!("data_length: {}", DATA.len())));
+            assert!(!output.contains(&data));
+        }
+        for output in outputs(true) {
+            assert!(output.contains(&format!("data_length: {}", DATA.len())));
+            assert!(output.contains(&data));
+        }
+    }
+
+    #[test]
+    fn excludes_data_by_default() {
+        let data = hex::encode(DATA);
+        for output in &[
+            format!("{:?}", *PREPARE),
+            format!("{:?}", *FULFILL),
+            format!("{:?}", *REJECT),
+        ] {
+            assert!(!output.contains(&data));
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_max_packet_amount_details {
     use super::*;
@@ -1,3 +1,5 @@
+use crate::RateProvider;
+use async_trait::async_trait;
 use futures::TryFutureExt;
 use log::{error, warn};
 use once_cell::sync::Lazy;
@@ -25,7 +27,31 @@ struct RateResponse {
     data: Vec<Rate>,
 }
 
-pub async fn query_coincap(client: &Client) -> Result<HashMap<String, f64>, ()> {
+/// Polls the [CoinCap](https://coincap.io/) API for exchange rates
+#[derive(Debug, Clone)]
+pub struct CoinCapProvider {
+    client: Client,
+}
+
+impl CoinCapProvider {
+    /// Simple constructor
+    pub fn new(client: Client) -> Self {
+        CoinCapProvider { client }
+    }
+}
+
+#[async_trait]
+impl RateProvider for CoinCapProvider {
+    async fn get_rates(&self) -> Result<HashMap<String, f64>, ()> {
+        query_coincap(&self.client).await
+    }
+
+    fn name(&self) -> &str {
+        "CoinCap"
+    }
+}
+
+async fn query_coincap(client: &Client) -> Result<HashMap<String, f64>, ()> {
     let (assets, rates) = futures::future::join(
         query_coincap_endpoint(client, COINCAP_ASSETS_URL.clone()),
         query_coincap_endpoint(client, COINCAP_RATES_URL.clone()),
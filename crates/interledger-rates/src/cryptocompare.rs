@@ -1,3 +1,5 @@
+use crate::RateProvider;
+use async_trait::async_trait;
 use futures::TryFutureExt;
 use log::error;
 use once_cell::sync::Lazy;
@@ -45,7 +47,32 @@ struct Response {
     data: Vec<Record>,
 }
 
-pub async fn query_cryptocompare(
+/// Polls the [CryptoCompare](https://cryptocompare.com) API for exchange rates
+#[derive(Debug, Clone)]
+pub struct CryptoCompareProvider {
+    client: Client,
+    api_key: SecretString,
+}
+
+impl CryptoCompareProvider {
+    /// Simple constructor
+    pub fn new(client: Client, api_key: SecretString) -> Self {
+        CryptoCompareProvider { client, api_key }
+    }
+}
+
+#[async_trait]
+impl RateProvider for CryptoCompareProvider {
+    async fn get_rates(&self) -> Result<HashMap<String, f64>, ()> {
+        query_cryptocompare(&self.client, &self.api_key).await
+    }
+
+    fn name(&self) -> &str {
+        "CryptoCompare"
+    }
+}
+
+async fn query_cryptocompare(
     client: &Client,
     api_key: &SecretString,
 ) -> Result<HashMap<String, f64>, ()> {
@@ -1,18 +1,51 @@
-use futures::TryFutureExt;
+use async_trait::async_trait;
 use interledger_errors::ExchangeRateStoreError;
-use log::{debug, error, trace, warn};
+use log::{debug, error, info, trace, warn};
+use once_cell::sync::Lazy;
 use reqwest::Client;
+use ring::rand::{SecureRandom, SystemRandom};
 use secrecy::SecretString;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio;
 
+static RANDOM: Lazy<SystemRandom> = Lazy::new(SystemRandom::new);
+
 mod cryptocompare;
+pub use cryptocompare::CryptoCompareProvider;
 
 mod coincap;
+pub use coincap::CoinCapProvider;
+
+mod signed;
+pub use signed::{SignedRatesConfig, SignedRatesProvider};
+
+/// Proof of where a set of exchange rates came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateProvenance {
+    /// Who published the rates, as stated in the signed bundle
+    pub source: String,
+    /// When the rates were signed, in seconds since the UNIX epoch
+    pub timestamp: u64,
+    /// The base64url-encoded signature of the bundle
+    pub signature: String,
+    /// The whole signed bundle (a JWS in the compact serialization), so that the
+    /// signature can be verified again later
+    pub jws: String,
+}
+
+/// Which of the [`ExchangeRateFetcher`](./struct.ExchangeRateFetcher.html)'s providers
+/// the current exchange rates came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateSource {
+    /// The [name](./trait.RateProvider.html#method.name) of the provider
+    pub provider: String,
+    /// Whether the provider is a fallback, which is only used while the primary provider fails
+    pub fallback: bool,
+}
 
 pub trait ExchangeRateStore: Clone {
     // TODO we may want to make this async if/when we use pubsub to broadcast
@@ -29,6 +62,52 @@ pub trait ExchangeRateStore: Clone {
     // but in the normal case of getting the rate between two assets, we don't want to
     // copy all the rate data
     fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError>;
+
+    /// Saves the proof of where the current rates came from (`None` if they came from
+    /// a provider without one). Stores which do not support it discard the provenance.
+    fn set_rate_provenance(
+        &self,
+        _provenance: Option<RateProvenance>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        Ok(())
+    }
+
+    /// Returns the proof of where the current rates came from, if there is one
+    fn get_rate_provenance(&self) -> Result<Option<RateProvenance>, ExchangeRateStoreError> {
+        Ok(None)
+    }
+
+    /// Saves which provider the current rates came from (`None` if they were set via
+    /// the API or cleared). Stores which do not support it discard the source.
+    fn set_rate_source(&self, _source: Option<RateSource>) -> Result<(), ExchangeRateStoreError> {
+        Ok(())
+    }
+
+    /// Returns which provider the current rates came from, if they came from one
+    fn get_rate_source(&self) -> Result<Option<RateSource>, ExchangeRateStoreError> {
+        Ok(None)
+    }
+}
+
+/// A source of exchange rates which is polled by the [`ExchangeRateFetcher`](./struct.ExchangeRateFetcher.html).
+///
+/// Implement this trait to use a custom pricing service instead of one of the
+/// built-in [`ExchangeRateProvider`](./enum.ExchangeRateProvider.html)s.
+#[async_trait]
+pub trait RateProvider {
+    /// Fetches the current rates, as a map of asset codes to the price of
+    /// one unit of that asset in USD
+    async fn get_rates(&self) -> Result<HashMap<String, f64>, ()>;
+
+    /// The proof of where the rates returned last came from, if the provider has one
+    fn provenance(&self) -> Option<RateProvenance> {
+        None
+    }
+
+    /// The name the rates of this provider are attributed to in the logs and the API
+    fn name(&self) -> &str {
+        "custom"
+    }
 }
 
 /// This determines which external API service to poll for exchange rates.
@@ -52,16 +131,53 @@ pub enum ExchangeRateProvider {
     /// [CryptoCompare]: https://cryptocompare.com
     #[serde(alias = "cryptocompare")]
     CryptoCompare(SecretString),
+    /// Poll a feed of rates which are signed with an Ed25519 key (as a JWS), and
+    /// only accept them if the signature is valid
+    ///
+    /// Note that when configured with YAML, this MUST be specified as
+    /// "SignedFeed", not "signed_feed".
+    #[serde(alias = "signedfeed")]
+    SignedFeed(SignedRatesConfig),
 }
 
+impl ExchangeRateProvider {
+    /// Creates the [`RateProvider`](./trait.RateProvider.html) which polls the selected API
+    /// with the given HTTP client
+    pub fn into_rate_provider(
+        self,
+        client: Client,
+    ) -> Result<Arc<dyn RateProvider + Send + Sync>, ()> {
+        Ok(match self {
+            ExchangeRateProvider::CoinCap => Arc::new(CoinCapProvider::new(client)),
+            ExchangeRateProvider::CryptoCompare(api_key) => {
+                Arc::new(CryptoCompareProvider::new(client, api_key))
+            }
+            ExchangeRateProvider::SignedFeed(config) => {
+                Arc::new(SignedRatesProvider::new(client, config)?)
+            }
+        })
+    }
+}
+
+/// Position of the active provider before any provider returned rates
+const NO_ACTIVE_PROVIDER: usize = usize::MAX;
+
 /// Poll exchange rate providers for the current exchange rates
 #[derive(Clone)]
 pub struct ExchangeRateFetcher<S> {
-    provider: ExchangeRateProvider,
+    /// The primary provider, followed by the fallbacks in the order they are tried
+    providers: Vec<Arc<dyn RateProvider + Send + Sync>>,
+    /// Position of the provider the current rates came from
+    active_provider: Arc<AtomicUsize>,
     consecutive_failed_polls: Arc<AtomicU32>,
     failed_polls_before_invalidation: u32,
+    /// The longest delay between polls while the provider keeps failing
+    max_backoff: Duration,
+    /// Fraction of the delay by which each poll is randomly moved
+    jitter: f64,
+    /// The providers' codes of the asset codes which differ from them
+    aliases: HashMap<String, String>,
     store: S,
-    client: Client,
 }
 
 impl<S> ExchangeRateFetcher<S>
@@ -70,82 +186,500 @@ where
 {
     /// Simple constructor
     pub fn new(
-        provider: ExchangeRateProvider,
+        provider: Arc<dyn RateProvider + Send + Sync>,
         failed_polls_before_invalidation: u32,
         store: S,
     ) -> Self {
         ExchangeRateFetcher {
-            provider,
+            providers: vec![provider],
+            active_provider: Arc::new(AtomicUsize::new(NO_ACTIVE_PROVIDER)),
             consecutive_failed_polls: Arc::new(AtomicU32::new(0)),
             failed_polls_before_invalidation,
+            max_backoff: Duration::from_secs(0),
+            jitter: 0.0,
+            aliases: HashMap::new(),
             store,
-            client: Client::new(),
         }
     }
 
-    /// Spawns a future which calls [`self.update_rates()`](./struct.ExchangeRateFetcher.html#method.update_rates) every `interval`
+    /// Sets the providers which are polled, in order, when the primary provider fails.
+    /// The rates are taken from the first provider which returns them, so the fetcher
+    /// switches back to the primary provider as soon as it recovers. The polls only
+    /// count as failed (see `failed_polls_before_invalidation`) if all providers fail
+    pub fn fallback_providers(
+        &mut self,
+        fallbacks: Vec<Arc<dyn RateProvider + Send + Sync>>,
+    ) -> &mut Self {
+        self.providers.truncate(1);
+        self.providers.extend(fallbacks);
+        self
+    }
+
+    /// Sets the longest delay between polls while the provider keeps failing. The poll
+    /// interval is doubled after each consecutive failed poll until it reaches this delay,
+    /// and returns to normal after a successful poll. Without it, failed polls are not
+    /// followed by a longer delay
+    pub fn max_backoff(&mut self, max_backoff: Duration) -> &mut Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets the fraction (between 0 and 1) of the delay by which each poll is randomly
+    /// advanced or postponed, so that many nodes started at the same time do not all
+    /// poll the provider at once
+    pub fn jitter(&mut self, jitter: f64) -> &mut Self {
+        self.jitter = jitter.max(0.0).min(1.0);
+        self
+    }
+
+    /// Sets the codes under which the providers publish the rates of assets whose codes
+    /// differ from them, as a map of the asset codes of the accounts to the providers'
+    /// codes (e.g. `XBT` to `BTC`). The rate of the provider's code is also saved under the
+    /// asset code, so that it is found when the asset code is looked up. Asset codes without
+    /// an alias are looked up as they are
+    pub fn asset_code_aliases(&mut self, aliases: HashMap<String, String>) -> &mut Self {
+        self.aliases = aliases;
+        self
+    }
+
+    /// Returns how long to wait after the last poll before polling again (without the jitter).
+    /// This is the `interval`, doubled for each consecutive failed poll up to the max backoff
+    pub fn poll_delay(&self, interval: Duration) -> Duration {
+        let failed_polls = self.consecutive_failed_polls.load(Ordering::Relaxed);
+        interval
+            .checked_mul(2u32.saturating_pow(failed_polls))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+            .max(interval)
+    }
+
+    /// Randomly lengthens or shortens the delay by up to the jitter
+    fn add_jitter(&self, delay: Duration) -> Duration {
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        let mut random = [0; 4];
+        RANDOM.fill(&mut random).expect("Unable to get randomness");
+        // Uniformly distributed between -1 and 1
+        let random =
+            f64::from(u32::from_be_bytes(random)) / f64::from(u32::max_value()) * 2.0 - 1.0;
+        delay.mul_f64(1.0 + self.jitter * random)
+    }
+
+    /// Spawns a future which calls [`self.update_rates()`](./struct.ExchangeRateFetcher.html#method.update_rates) every `interval`,
+    /// backing off while the provider fails (see [`poll_delay`](./struct.ExchangeRateFetcher.html#method.poll_delay))
     pub fn spawn_interval(self, interval: Duration) {
         debug!(
-            "Starting interval to poll exchange rate provider: {:?} for rates",
-            self.provider
+            "Starting interval to poll exchange rate provider every {:?}",
+            interval
         );
-        let interval = async move {
-            let mut interval = tokio::time::interval(interval);
+        let polls = async move {
             loop {
-                interval.tick().await;
-                // Ignore errors so that they don't cause the Interval to stop
+                // Ignore errors so that they don't cause the polling to stop
                 let _ = self.update_rates().await;
+                let delay = self.add_jitter(self.poll_delay(interval));
+                trace!("Polling exchange rate provider again in {:?}", delay);
+                tokio::time::delay_for(delay).await;
             }
         };
-        tokio::spawn(interval);
-    }
-
-    /// Calls the proper exchange rate provider
-    async fn fetch_rates(&self) -> Result<HashMap<String, f64>, ()> {
-        match self.provider {
-            ExchangeRateProvider::CryptoCompare(ref api_key) => {
-                cryptocompare::query_cryptocompare(&self.client, api_key).await
-            }
-            ExchangeRateProvider::CoinCap => coincap::query_coincap(&self.client).await,
-        }
+        tokio::spawn(polls);
     }
 
     /// Gets the exchange rates and proceeds to update the store with the newly polled values
-    async fn update_rates(&self) -> Result<(), ()> {
-        let consecutive_failed_polls = self.consecutive_failed_polls.clone();
-        let consecutive_failed_polls_zeroer = consecutive_failed_polls.clone();
-        let failed_polls_before_invalidation = self.failed_polls_before_invalidation;
-        let store = self.store.clone();
-        let store_clone = self.store.clone();
-        let provider = self.provider.clone();
-        let mut rates = self.fetch_rates()
-            .map_err(move |_| {
+    pub async fn update_rates(&self) -> Result<(), ()> {
+        let (index, mut rates) = match self.poll_providers().await {
+            Some(polled) => polled,
+            None => {
                 // Note that a race between the read on this line and the check on the line after
                 // is quite unlikely as long as the interval between polls is reasonable.
-                let failed_polls = consecutive_failed_polls.fetch_add(1, Ordering::Relaxed);
-                if failed_polls < failed_polls_before_invalidation {
+                let failed_polls = self
+                    .consecutive_failed_polls
+                    .fetch_add(1, Ordering::Relaxed);
+                if failed_polls < self.failed_polls_before_invalidation {
                     warn!("Failed to update exchange rates (previous consecutive failed attempts: {})", failed_polls);
                 } else {
                     error!("Failed to update exchange rates (previous consecutive failed attempts: {}), removing old rates for safety", failed_polls);
                     // Clear out all of the old rates
-                    if store.set_exchange_rates(HashMap::new()).is_err() {
+                    if self.store.set_exchange_rates(HashMap::new()).is_err()
+                        || self.store.set_rate_provenance(None).is_err()
+                        || self.store.set_rate_source(None).is_err()
+                    {
                         error!("Failed to clear exchange rates cache after exchange rates server became unresponsive; panicking");
                         panic!("Failed to clear exchange rates cache after exchange rates server became unresponsive");
                     }
+                    self.active_provider
+                        .store(NO_ACTIVE_PROVIDER, Ordering::Relaxed);
                 }
-            }).await?;
+                return Err(());
+            }
+        };
 
         trace!("Fetched exchange rates: {:?}", rates);
+        for (asset_code, provider_code) in self.aliases.iter() {
+            match rates.get(provider_code) {
+                Some(rate) => {
+                    let rate = *rate;
+                    rates.insert(asset_code.clone(), rate);
+                }
+                None => warn!(
+                    "No exchange rate for {} (the alias of {}) was fetched",
+                    provider_code, asset_code
+                ),
+            }
+        }
         let num_rates = rates.len();
         rates.insert("USD".to_string(), 1.0);
-        if store_clone.set_exchange_rates(rates).is_ok() {
+        let provider = &self.providers[index];
+        let provenance = provider.provenance();
+        if let Some(ref provenance) = provenance {
+            debug!(
+                "Fetched exchange rates from {} signed at {}",
+                provenance.source, provenance.timestamp
+            );
+        }
+        let source = RateSource {
+            provider: provider.name().to_string(),
+            fallback: index > 0,
+        };
+        if self.store.set_exchange_rates(rates).is_ok()
+            && self.store.set_rate_provenance(provenance).is_ok()
+            && self.store.set_rate_source(Some(source)).is_ok()
+        {
             // Reset our invalidation counter
-            consecutive_failed_polls_zeroer.store(0, Ordering::Relaxed);
-            debug!("Updated {} exchange rates from {:?}", num_rates, provider);
+            self.consecutive_failed_polls.store(0, Ordering::Relaxed);
+            if self.active_provider.swap(index, Ordering::Relaxed) != index {
+                if index == 0 {
+                    info!("Using exchange rates from provider {}", provider.name());
+                } else {
+                    warn!(
+                        "Using exchange rates from fallback provider {} (number {} of {} fallbacks)",
+                        provider.name(),
+                        index,
+                        self.providers.len() - 1
+                    );
+                }
+            }
+            debug!("Updated {} exchange rates", num_rates);
             Ok(())
         } else {
             error!("Error setting exchange rates in store");
             Err(())
         }
     }
+
+    /// Polls the providers in order and returns the position and the rates
+    /// of the first one which returned rates
+    async fn poll_providers(&self) -> Option<(usize, HashMap<String, f64>)> {
+        for (index, provider) in self.providers.iter().enumerate() {
+            if let Ok(rates) = provider.get_rates().await {
+                return Some((index, rates));
+            }
+            if index + 1 < self.providers.len() {
+                warn!(
+                    "Failed to get exchange rates from provider {}, trying the next one",
+                    provider.name()
+                );
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::RwLock;
+    use std::iter::FromIterator;
+    use std::sync::atomic::AtomicBool;
+
+    #[derive(Clone)]
+    struct TestProvider(Option<HashMap<String, f64>>, Option<RateProvenance>);
+
+    #[async_trait]
+    impl RateProvider for TestProvider {
+        async fn get_rates(&self) -> Result<HashMap<String, f64>, ()> {
+            self.0.clone().ok_or(())
+        }
+
+        fn provenance(&self) -> Option<RateProvenance> {
+            self.1.clone()
+        }
+    }
+
+    /// Fails to return its rates while `failing` is set
+    struct FlakyProvider {
+        name: &'static str,
+        rates: HashMap<String, f64>,
+        failing: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl RateProvider for FlakyProvider {
+        async fn get_rates(&self) -> Result<HashMap<String, f64>, ()> {
+            if self.failing.load(Ordering::Relaxed) {
+                Err(())
+            } else {
+                Ok(self.rates.clone())
+            }
+        }
+
+        fn name(&self) -> &str {
+            self.name
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct TestStore {
+        rates: Arc<RwLock<HashMap<String, f64>>>,
+        provenance: Arc<RwLock<Option<RateProvenance>>>,
+        source: Arc<RwLock<Option<RateSource>>>,
+    }
+
+    impl ExchangeRateStore for TestStore {
+        fn set_exchange_rates(
+            &self,
+            rates: HashMap<String, f64>,
+        ) -> Result<(), ExchangeRateStoreError> {
+            *self.rates.write() = rates;
+            Ok(())
+        }
+
+        fn get_exchange_rates(
+            &self,
+            _asset_codes: &[&str],
+        ) -> Result<Vec<f64>, ExchangeRateStoreError> {
+            unimplemented!()
+        }
+
+        fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+            Ok(self.rates.read().clone())
+        }
+
+        fn set_rate_provenance(
+            &self,
+            provenance: Option<RateProvenance>,
+        ) -> Result<(), ExchangeRateStoreError> {
+            *self.provenance.write() = provenance;
+            Ok(())
+        }
+
+        fn get_rate_provenance(&self) -> Result<Option<RateProvenance>, ExchangeRateStoreError> {
+            Ok(self.provenance.read().clone())
+        }
+
+        fn set_rate_source(
+            &self,
+            source: Option<RateSource>,
+        ) -> Result<(), ExchangeRateStoreError> {
+            *self.source.write() = source;
+            Ok(())
+        }
+
+        fn get_rate_source(&self) -> Result<Option<RateSource>, ExchangeRateStoreError> {
+            Ok(self.source.read().clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn saves_rates_from_custom_provider() {
+        let mut rates = HashMap::new();
+        rates.insert("ABC".to_string(), 2.5);
+        let store = TestStore::default();
+        let fetcher =
+            ExchangeRateFetcher::new(Arc::new(TestProvider(Some(rates), None)), 1, store.clone());
+        fetcher.update_rates().await.unwrap();
+
+        assert_eq!(
+            store.get_all_exchange_rates().unwrap(),
+            HashMap::from_iter(vec![("ABC".to_string(), 2.5), ("USD".to_string(), 1.0)])
+        );
+    }
+
+    #[tokio::test]
+    async fn saves_rates_of_aliased_asset_codes() {
+        let rates = HashMap::from_iter(vec![
+            ("BTC".to_string(), 10_000.0),
+            ("ETH".to_string(), 200.0),
+        ]);
+        let store = TestStore::default();
+        let mut fetcher =
+            ExchangeRateFetcher::new(Arc::new(TestProvider(Some(rates), None)), 1, store.clone());
+        fetcher.asset_code_aliases(HashMap::from_iter(vec![
+            ("XBT".to_string(), "BTC".to_string()),
+            ("XYZ".to_string(), "NOT_PUBLISHED".to_string()),
+        ]));
+        fetcher.update_rates().await.unwrap();
+
+        let rates = store.get_all_exchange_rates().unwrap();
+        assert_eq!(rates["XBT"], 10_000.0);
+        // Codes without an alias are saved as they are
+        assert_eq!(rates["BTC"], 10_000.0);
+        assert_eq!(rates["ETH"], 200.0);
+        assert!(!rates.contains_key("XYZ"));
+    }
+
+    #[tokio::test]
+    async fn clears_rates_after_failed_polls() {
+        let store = TestStore::default();
+        store
+            .set_exchange_rates(HashMap::from_iter(vec![("ABC".to_string(), 2.5)]))
+            .unwrap();
+        let fetcher =
+            ExchangeRateFetcher::new(Arc::new(TestProvider(None, None)), 1, store.clone());
+
+        assert!(fetcher.update_rates().await.is_err());
+        assert_eq!(store.get_all_exchange_rates().unwrap().len(), 1);
+        assert!(fetcher.update_rates().await.is_err());
+        assert!(store.get_all_exchange_rates().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn saves_provenance_of_rates() {
+        let mut rates = HashMap::new();
+        rates.insert("ABC".to_string(), 2.5);
+        let provenance = RateProvenance {
+            source: "example-oracle".to_string(),
+            timestamp: 1_600_000_000,
+            signature: "c2lnbmF0dXJl".to_string(),
+            jws: "e30.e30.c2lnbmF0dXJl".to_string(),
+        };
+        let store = TestStore::default();
+        let provider = TestProvider(Some(rates), Some(provenance.clone()));
+        ExchangeRateFetcher::new(Arc::new(provider), 0, store.clone())
+            .update_rates()
+            .await
+            .unwrap();
+        assert_eq!(store.get_rate_provenance().unwrap(), Some(provenance));
+
+        // the provenance is cleared along with the rates
+        let fetcher =
+            ExchangeRateFetcher::new(Arc::new(TestProvider(None, None)), 0, store.clone());
+        assert!(fetcher.update_rates().await.is_err());
+        assert!(store.get_all_exchange_rates().unwrap().is_empty());
+        assert_eq!(store.get_rate_provenance().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn falls_back_while_primary_provider_fails() {
+        let primary_failing = Arc::new(AtomicBool::new(false));
+        let primary = FlakyProvider {
+            name: "primary",
+            rates: HashMap::from_iter(vec![("ABC".to_string(), 2.0)]),
+            failing: primary_failing.clone(),
+        };
+        let secondary = FlakyProvider {
+            name: "secondary",
+            rates: HashMap::from_iter(vec![("ABC".to_string(), 3.0)]),
+            failing: Arc::new(AtomicBool::new(false)),
+        };
+        let store = TestStore::default();
+        let mut fetcher = ExchangeRateFetcher::new(Arc::new(primary), 0, store.clone());
+        fetcher.fallback_providers(vec![Arc::new(secondary)]);
+
+        fetcher.update_rates().await.unwrap();
+        assert_eq!(store.get_all_exchange_rates().unwrap()["ABC"], 2.0);
+        assert_eq!(
+            store.get_rate_source().unwrap(),
+            Some(RateSource {
+                provider: "primary".to_string(),
+                fallback: false,
+            })
+        );
+
+        // The secondary's rates are used while the primary fails, which does
+        // not count as a failed poll
+        primary_failing.store(true, Ordering::Relaxed);
+        fetcher.update_rates().await.unwrap();
+        fetcher.update_rates().await.unwrap();
+        assert_eq!(store.get_all_exchange_rates().unwrap()["ABC"], 3.0);
+        assert_eq!(
+            store.get_rate_source().unwrap(),
+            Some(RateSource {
+                provider: "secondary".to_string(),
+                fallback: true,
+            })
+        );
+
+        // and the primary's once it recovers
+        primary_failing.store(false, Ordering::Relaxed);
+        fetcher.update_rates().await.unwrap();
+        assert_eq!(store.get_all_exchange_rates().unwrap()["ABC"], 2.0);
+        assert_eq!(
+            store
+                .get_rate_source()
+                .unwrap()
+                .map(|source| source.fallback),
+            Some(false)
+        );
+    }
+
+    #[tokio::test]
+    async fn clears_rates_after_all_providers_failed() {
+        let store = TestStore::default();
+        let mut fetcher =
+            ExchangeRateFetcher::new(Arc::new(TestProvider(None, None)), 1, store.clone());
+        fetcher.fallback_providers(vec![Arc::new(TestProvider(
+            Some(HashMap::from_iter(vec![("ABC".to_string(), 2.5)])),
+            None,
+        ))]);
+        fetcher.update_rates().await.unwrap();
+        assert!(store.get_rate_source().unwrap().unwrap().fallback);
+
+        fetcher.fallback_providers(vec![Arc::new(TestProvider(None, None))]);
+        assert!(fetcher.update_rates().await.is_err());
+        assert_eq!(store.get_all_exchange_rates().unwrap().len(), 2);
+        assert!(fetcher.update_rates().await.is_err());
+        assert!(store.get_all_exchange_rates().unwrap().is_empty());
+        assert_eq!(store.get_rate_source().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn backs_off_while_polls_fail() {
+        let failing = Arc::new(AtomicBool::new(true));
+        let provider = FlakyProvider {
+            name: "flaky",
+            rates: HashMap::new(),
+            failing: failing.clone(),
+        };
+        let mut fetcher = ExchangeRateFetcher::new(Arc::new(provider), 5, TestStore::default());
+        fetcher.max_backoff(Duration::from_secs(4));
+        let interval = Duration::from_secs(1);
+        assert_eq!(fetcher.poll_delay(interval), interval);
+
+        let mut delays = Vec::new();
+        for _ in 0..4 {
+            assert!(fetcher.update_rates().await.is_err());
+            delays.push(fetcher.poll_delay(interval).as_secs());
+        }
+        assert_eq!(delays, vec![2, 4, 4, 4]);
+
+        // the interval returns to normal once the provider recovers
+        failing.store(false, Ordering::Relaxed);
+        fetcher.update_rates().await.unwrap();
+        assert_eq!(fetcher.poll_delay(interval), interval);
+    }
+
+    #[tokio::test]
+    async fn does_not_back_off_without_max_backoff() {
+        let fetcher =
+            ExchangeRateFetcher::new(Arc::new(TestProvider(None, None)), 5, TestStore::default());
+        assert!(fetcher.update_rates().await.is_err());
+        assert_eq!(
+            fetcher.poll_delay(Duration::from_secs(1)),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn jitters_delay_within_bounds() {
+        let mut fetcher =
+            ExchangeRateFetcher::new(Arc::new(TestProvider(None, None)), 5, TestStore::default());
+        fetcher.jitter(0.1);
+        let delay = Duration::from_secs(10);
+        let delays: Vec<Duration> = (0..100).map(|_| fetcher.add_jitter(delay)).collect();
+        assert!(delays
+            .iter()
+            .all(|d| *d >= Duration::from_secs(9) && *d <= Duration::from_secs(11)));
+        assert!(delays.iter().any(|d| *d != delay));
+    }
 }
@@ -0,0 +1,276 @@
+use crate::{RateProvenance, RateProvider};
+use async_trait::async_trait;
+use futures::TryFutureExt;
+use log::{error, warn};
+use reqwest::{Client, Url};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{de, Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Configuration of a feed of signed exchange rates
+#[derive(Debug, Clone, Deserialize)]
+pub struct SignedRatesConfig {
+    /// URL of the feed, which responds with the current rates as a JWS
+    /// in the compact serialization
+    pub url: String,
+    /// The base64-encoded Ed25519 public key the rates must be signed with
+    #[serde(deserialize_with = "deserialize_public_key")]
+    pub public_key: Vec<u8>,
+}
+
+fn deserialize_public_key<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let key = base64::decode(&String::deserialize(deserializer)?).map_err(de::Error::custom)?;
+    if key.len() == 32 {
+        Ok(key)
+    } else {
+        Err(de::Error::custom(
+            "Ed25519 public keys must be 32 bytes long",
+        ))
+    }
+}
+
+/// The header of a rate bundle
+#[derive(Deserialize)]
+struct JwsHeader {
+    alg: String,
+}
+
+/// The payload of a rate bundle
+#[derive(Deserialize)]
+struct RateBundle {
+    /// Who published the rates
+    source: String,
+    /// When the rates were signed, in seconds since the UNIX epoch
+    timestamp: u64,
+    /// The price of one unit of each asset in USD
+    rates: HashMap<String, f64>,
+}
+
+/// Polls a feed of exchange rates signed with an Ed25519 key (JWS with the `EdDSA` algorithm)
+///
+/// Bundles which are not signed with the configured key, or which were signed before the
+/// bundle accepted last, are rejected (keeping the previous rates). The provenance of the
+/// bundle accepted last is returned by [`provenance`](#method.provenance).
+pub struct SignedRatesProvider {
+    client: Client,
+    url: Url,
+    public_key: Vec<u8>,
+    provenance: Mutex<Option<RateProvenance>>,
+}
+
+impl SignedRatesProvider {
+    /// Creates a provider which polls the configured feed with the given HTTP client
+    pub fn new(client: Client, config: SignedRatesConfig) -> Result<Self, ()> {
+        let url = Url::parse(&config.url)
+            .map_err(|err| error!("Invalid URL of the signed rates feed: {:?}", err))?;
+        Ok(SignedRatesProvider {
+            client,
+            url,
+            public_key: config.public_key,
+            provenance: Mutex::new(None),
+        })
+    }
+
+    /// Verifies the bundle and returns its rates, if it is newer than the bundle accepted last
+    fn accept_bundle(&self, jws: &str) -> Result<HashMap<String, f64>, ()> {
+        let (bundle, provenance) = verify_bundle(jws, &self.public_key)?;
+        let mut last = self.provenance.lock().unwrap();
+        if let Some(ref last) = *last {
+            if bundle.timestamp < last.timestamp {
+                warn!(
+                    "Ignoring signed exchange rates from {} signed at {}, which are older than the current ones (signed at {})",
+                    bundle.source, bundle.timestamp, last.timestamp
+                );
+                return Err(());
+            }
+        }
+        *last = Some(provenance);
+        Ok(bundle.rates)
+    }
+}
+
+#[async_trait]
+impl RateProvider for SignedRatesProvider {
+    async fn get_rates(&self) -> Result<HashMap<String, f64>, ()> {
+        let res = self
+            .client
+            .get(self.url.clone())
+            .send()
+            .map_err(|err| error!("Error fetching signed exchange rates: {:?}", err))
+            .await?;
+        let res = res
+            .error_for_status()
+            .map_err(|err| error!("HTTP error getting signed exchange rates: {:?}", err))?;
+        let jws = res
+            .text()
+            .map_err(|err| error!("Error getting signed exchange rates body: {:?}", err))
+            .await?;
+        self.accept_bundle(jws.trim())
+    }
+
+    fn name(&self) -> &str {
+        "SignedFeed"
+    }
+
+    fn provenance(&self) -> Option<RateProvenance> {
+        self.provenance.lock().unwrap().clone()
+    }
+}
+
+/// Checks the signature of a JWS in the compact serialization and returns its rate bundle
+fn verify_bundle(jws: &str, public_key: &[u8]) -> Result<(RateBundle, RateProvenance), ()> {
+    let parts: Vec<&str> = jws.split('.').collect();
+    if parts.len() != 3 {
+        error!("Signed exchange rates are not a JWS in the compact serialization");
+        return Err(());
+    }
+    let decode = |part: &str| base64::decode_config(part, base64::URL_SAFE_NO_PAD);
+    let (header, payload, signature) = match (decode(parts[0]), decode(parts[1]), decode(parts[2]))
+    {
+        (Ok(header), Ok(payload), Ok(signature)) => (header, payload, signature),
+        _ => {
+            error!("Signed exchange rates are not base64url-encoded");
+            return Err(());
+        }
+    };
+
+    // Only the algorithm the key is for is accepted, so that unsigned (`none`) bundles are rejected
+    let header: JwsHeader = serde_json::from_slice(&header)
+        .map_err(|err| error!("Invalid header of signed exchange rates: {:?}", err))?;
+    if header.alg != "EdDSA" {
+        error!(
+            "Rejecting exchange rates signed with unsupported algorithm: {}",
+            header.alg
+        );
+        return Err(());
+    }
+    let signing_input = &jws[..parts[0].len() + 1 + parts[1].len()];
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(signing_input.as_bytes(), &signature)
+        .map_err(|_| error!("Rejecting exchange rates with an invalid signature"))?;
+
+    let bundle: RateBundle = serde_json::from_slice(&payload)
+        .map_err(|err| error!("Invalid payload of signed exchange rates: {:?}", err))?;
+    let provenance = RateProvenance {
+        source: bundle.source.clone(),
+        timestamp: bundle.timestamp,
+        signature: parts[2].to_string(),
+        jws: jws.to_string(),
+    };
+    Ok((bundle, provenance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+    use serde_json::json;
+
+    fn encode(data: &[u8]) -> String {
+        base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+    }
+
+    fn sign(key_pair: &Ed25519KeyPair, header: &str, payload: &str) -> String {
+        let signing_input = format!(
+            "{}.{}",
+            encode(header.as_bytes()),
+            encode(payload.as_bytes())
+        );
+        let signature = key_pair.sign(signing_input.as_bytes());
+        format!("{}.{}", signing_input, encode(signature.as_ref()))
+    }
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    fn provider(key_pair: &Ed25519KeyPair) -> SignedRatesProvider {
+        SignedRatesProvider::new(
+            Client::new(),
+            SignedRatesConfig {
+                url: "http://localhost/rates".to_string(),
+                public_key: key_pair.public_key().as_ref().to_vec(),
+            },
+        )
+        .unwrap()
+    }
+
+    fn bundle(timestamp: u64, rate: f64) -> String {
+        json!({"source": "example-oracle", "timestamp": timestamp, "rates": {"ABC": rate}})
+            .to_string()
+    }
+
+    #[test]
+    fn accepts_valid_signed_bundle() {
+        let key_pair = key_pair();
+        let provider = provider(&key_pair);
+        let jws = sign(&key_pair, r#"{"alg":"EdDSA"}"#, &bundle(1_600_000_000, 1.5));
+
+        let rates = provider.accept_bundle(&jws).unwrap();
+        assert_eq!(rates.get("ABC"), Some(&1.5));
+        let provenance = provider.provenance().unwrap();
+        assert_eq!(provenance.source, "example-oracle");
+        assert_eq!(provenance.timestamp, 1_600_000_000);
+        assert_eq!(provenance.signature, jws.rsplit('.').next().unwrap());
+        assert_eq!(provenance.jws, jws);
+    }
+
+    #[test]
+    fn rejects_tampered_bundle() {
+        let key_pair = key_pair();
+        let provider = provider(&key_pair);
+        let jws = sign(&key_pair, r#"{"alg":"EdDSA"}"#, &bundle(1_600_000_000, 1.5));
+        let parts: Vec<&str> = jws.split('.').collect();
+        let tampered = format!(
+            "{}.{}.{}",
+            parts[0],
+            encode(bundle(1_600_000_000, 15.0).as_bytes()),
+            parts[2]
+        );
+
+        assert!(provider.accept_bundle(&tampered).is_err());
+        assert!(provider.provenance().is_none());
+    }
+
+    #[test]
+    fn rejects_unsigned_and_foreign_bundles() {
+        let key_pair = key_pair();
+        let provider = provider(&key_pair);
+        let unsigned = format!(
+            "{}.{}.",
+            encode(br#"{"alg":"none"}"#),
+            encode(bundle(1_600_000_000, 1.5).as_bytes())
+        );
+        assert!(provider.accept_bundle(&unsigned).is_err());
+
+        let other_key_pair = self::key_pair();
+        let jws = sign(
+            &other_key_pair,
+            r#"{"alg":"EdDSA"}"#,
+            &bundle(1_600_000_000, 1.5),
+        );
+        assert!(provider.accept_bundle(&jws).is_err());
+        assert!(provider.accept_bundle("not a jws").is_err());
+        assert!(provider.provenance().is_none());
+    }
+
+    #[test]
+    fn rejects_bundles_older_than_current_one() {
+        let key_pair = key_pair();
+        let provider = provider(&key_pair);
+        let newer = sign(&key_pair, r#"{"alg":"EdDSA"}"#, &bundle(1_600_000_060, 1.5));
+        let older = sign(&key_pair, r#"{"alg":"EdDSA"}"#, &bundle(1_600_000_000, 1.4));
+
+        assert!(provider.accept_bundle(&newer).is_ok());
+        assert!(provider.accept_bundle(&older).is_err());
+        assert_eq!(provider.provenance().unwrap().timestamp, 1_600_000_060);
+    }
+}
@@ -12,8 +12,11 @@
 //! store can either be configured or populated using the `CcpRouteManager`
 //! (see the `interledger-ccp` crate for more details).
 
-use interledger_service::AccountStore;
-use std::{collections::HashMap, sync::Arc};
+use interledger_service::{Account, AccountStore};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 use uuid::Uuid;
 
 mod router;
@@ -28,4 +31,43 @@ pub trait RouterStore: AccountStore + Clone + Send + Sync + 'static {
     /// This ensures that individual packets can be routed without hitting the underlying store.
     /// An Arc is returned to avoid copying the underlying data while processing each packet.
     fn routing_table(&self) -> Arc<HashMap<String, Uuid>>;
+
+    /// **Synchronously** return the other accounts through which prefixes of the routing
+    /// table can be reached at the same cost as through the account in the routing table.
+    /// Payments to those prefixes are spread across all of the accounts according to their
+    /// [routing weights](./trait.RouterAccount.html#method.routing_weight).
+    ///
+    /// By default there are no alternative routes.
+    fn multipath_routing_table(&self) -> Arc<HashMap<String, Vec<Uuid>>> {
+        Arc::new(HashMap::new())
+    }
+
+    /// **Synchronously** return the accounts which are being drained, for example before
+    /// the peer is taken down for maintenance. No new packets are routed through these
+    /// accounts, but the packets which were already forwarded to them complete as usual.
+    ///
+    /// By default no accounts are drained.
+    fn draining_accounts(&self) -> Arc<HashSet<Uuid>> {
+        Arc::new(HashSet::new())
+    }
+
+    /// **Synchronously** return the accounts which are disabled, for example because their
+    /// credentials may have been compromised. Packets from these accounts are rejected
+    /// and no packets are routed through them.
+    ///
+    /// By default no accounts are disabled.
+    fn disabled_accounts(&self) -> Arc<HashSet<Uuid>> {
+        Arc::new(HashSet::new())
+    }
+}
+
+/// Extension trait for [Account](../interledger_service/trait.Account.html) with the
+/// settings used to choose between several accounts which can reach the same destination
+pub trait RouterAccount: Account {
+    /// The share of payments routed through this account, relative to the weights of the other
+    /// accounts which can reach the destination at the same cost. Accounts with a weight of 0
+    /// are only used if all of the other accounts' weights are 0 too. Defaults to 1.
+    fn routing_weight(&self) -> u32 {
+        1
+    }
 }
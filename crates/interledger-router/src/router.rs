@@ -1,9 +1,12 @@
-use super::RouterStore;
+use super::{RouterAccount, RouterStore};
 use async_trait::async_trait;
 use interledger_packet::{ErrorCode, RejectBuilder};
 use interledger_service::*;
-use log::{error, trace};
+use log::{error, trace, warn};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::str;
+use uuid::Uuid;
 
 /// # Interledger Router
 ///
@@ -21,6 +24,23 @@ use std::str;
 ///   - reduce the Prepare packet's expiry
 ///
 /// That is done by OutgoingServices.
+///
+/// If the store has several equal-cost routes for the destination's prefix, the router
+/// spreads the payments across them proportionally to the accounts' routing weights.
+/// The choice is based on the destination address and the account the packet came from,
+/// so all packets of a STREAM payment (which share the destination) take the same path.
+///
+/// Accounts which are being drained are skipped when choosing the next hop, so their
+/// in-flight packets complete while new ones are sent through the alternative routes.
+/// Packets which can only be routed through draining accounts are rejected with
+/// `T04_INSUFFICIENT_LIQUIDITY`, so that the senders may retry later.
+///
+/// Disabled accounts are skipped the same way, but packets which can only be routed
+/// through them are rejected with `F02_UNREACHABLE`. Packets from disabled accounts
+/// are rejected with `F00_BAD_REQUEST`.
+///
+/// Packets addressed to the node's own address are never routed, but rejected with
+/// `F02_UNREACHABLE` (the services before the router may handle them instead).
 
 #[derive(Clone)]
 pub struct Router<S, O> {
@@ -38,10 +58,69 @@ where
     }
 }
 
+/// Loads the next hop among the given account and its alternatives, picking the
+/// same one for every packet with the same key
+async fn select_next_hop<S>(
+    store: &S,
+    account_id: Uuid,
+    alternatives: &[Uuid],
+    key: u64,
+) -> Option<S::Account>
+where
+    S: RouterStore,
+    S::Account: RouterAccount,
+{
+    let mut candidates: Vec<Uuid> = alternatives.to_vec();
+    candidates.push(account_id);
+    // Sort the candidates so that the choice doesn't depend on which one is the primary route
+    candidates.sort();
+    candidates.dedup();
+    match store.get_accounts(candidates).await {
+        Ok(accounts) => Some(select_weighted(accounts, key)),
+        Err(err) => {
+            warn!(
+                "Error loading the alternative next hops for account {}, using only the primary route: {:?}",
+                account_id, err
+            );
+            store
+                .get_accounts(vec![account_id])
+                .await
+                .ok()
+                .map(|mut accounts| accounts.remove(0))
+        }
+    }
+}
+
+/// Picks one of the accounts with a probability proportional to its routing weight.
+/// The choice is fully determined by the key (and the order of the accounts).
+fn select_weighted<A: RouterAccount>(mut accounts: Vec<A>, key: u64) -> A {
+    let weights: Vec<u64> = accounts
+        .iter()
+        .map(|account| u64::from(account.routing_weight()))
+        .collect();
+    let total: u64 = weights.iter().sum();
+    if total == 0 {
+        let index = key % accounts.len() as u64;
+        return accounts.swap_remove(index as usize);
+    }
+
+    let mut point = key % total;
+    let mut index = 0;
+    for (i, weight) in weights.iter().enumerate() {
+        if point < *weight {
+            index = i;
+            break;
+        }
+        point -= weight;
+    }
+    accounts.swap_remove(index)
+}
+
 #[async_trait]
 impl<S, O> IncomingService<S::Account> for Router<S, O>
 where
     S: AddressStore + RouterStore,
+    S::Account: RouterAccount,
     O: OutgoingService<S::Account> + Clone + Send + 'static,
 {
     /// Figures out the next node to pass the received Prepare packet to.
@@ -49,12 +128,48 @@ where
     /// Firstly, it checks if there is a direct path for that account and uses that.
     /// If not it scans through the routing table and checks if the route prefix matches
     /// the prepare packet's destination or if it's a catch-all address (i.e. empty prefix)
+    ///
+    /// If there are alternative routes for the chosen prefix, one of the accounts is selected
+    /// based on their routing weights.
     async fn handle_request(&mut self, request: IncomingRequest<S::Account>) -> IlpResult {
         let destination = request.prepare.destination();
         let mut next_hop = None;
+        let mut alternatives = None;
         let routing_table = self.store.routing_table();
+        let multipath_routing_table = self.store.multipath_routing_table();
         let ilp_address = self.store.get_ilp_address();
 
+        let disabled = self.store.disabled_accounts();
+        if disabled.contains(&request.from.id()) {
+            warn!(
+                "Rejecting request from account {} because it is disabled",
+                request.from.id()
+            );
+            return Err(RejectBuilder {
+                code: ErrorCode::F00_BAD_REQUEST,
+                message: b"Account is disabled",
+                triggered_by: Some(&ilp_address),
+                data: &[],
+            }
+            .build());
+        }
+
+        // Packets addressed to the node itself which were not handled by the services
+        // before the router would otherwise be sent to a catch-all route, e.g. back to the parent
+        if destination == ilp_address {
+            warn!(
+                "Rejecting request from account {} because it is addressed to this node",
+                request.from.id()
+            );
+            return Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: b"Packet is addressed to this node",
+                triggered_by: Some(&ilp_address),
+                data: &[],
+            }
+            .build());
+        }
+
         // Check if we have a direct path for that account or if we need to scan
         // through the routing table
         let dest: &str = &destination;
@@ -65,6 +180,7 @@ where
                 account_id
             );
             next_hop = Some(*account_id);
+            alternatives = multipath_routing_table.get(dest);
         } else if !routing_table.is_empty() {
             let mut matching_prefix = "";
             let routing_table = self.store.routing_table();
@@ -84,19 +200,86 @@ where
                     matching_prefix,
                     account_id,
                 );
+                alternatives = multipath_routing_table.get(matching_prefix);
             }
         } else {
             error!("Unable to route request because routing table is empty");
         }
 
         if let Some(account_id) = next_hop {
+            let draining = self.store.draining_accounts();
+            let mut alternatives: Vec<Uuid> = alternatives
+                .map(|alternatives| {
+                    alternatives
+                        .iter()
+                        .filter(|id| !draining.contains(*id) && !disabled.contains(*id))
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default();
+            let account_id = if disabled.contains(&account_id) {
+                if alternatives.is_empty() {
+                    warn!(
+                        "Rejecting request to {} because its next hop {} is disabled",
+                        destination, account_id
+                    );
+                    return Err(RejectBuilder {
+                        code: ErrorCode::F02_UNREACHABLE,
+                        message: &[],
+                        triggered_by: Some(&ilp_address),
+                        data: &[],
+                    }
+                    .build());
+                }
+                trace!(
+                    "Not routing request to {} through account {} because it is disabled",
+                    destination,
+                    account_id
+                );
+                alternatives.remove(0)
+            } else if draining.contains(&account_id) {
+                if alternatives.is_empty() {
+                    warn!(
+                        "Rejecting request to {} because its next hop {} is draining",
+                        destination, account_id
+                    );
+                    return Err(RejectBuilder {
+                        code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                        message: b"Next hop is temporarily unavailable",
+                        triggered_by: Some(&ilp_address),
+                        data: &[],
+                    }
+                    .build());
+                }
+                trace!(
+                    "Not routing request to {} through account {} because it is draining",
+                    destination,
+                    account_id
+                );
+                alternatives.remove(0)
+            } else {
+                account_id
+            };
+
             let mut next = self.next.clone();
-            match self.store.get_accounts(vec![account_id]).await {
-                Ok(mut accounts) => {
-                    let request = request.into_outgoing(accounts.remove(0));
+            let account = if !alternatives.is_empty() {
+                let mut hasher = DefaultHasher::new();
+                request.from.id().hash(&mut hasher);
+                destination.hash(&mut hasher);
+                select_next_hop(&self.store, account_id, &alternatives, hasher.finish()).await
+            } else {
+                self.store
+                    .get_accounts(vec![account_id])
+                    .await
+                    .ok()
+                    .map(|mut accounts| accounts.remove(0))
+            };
+            match account {
+                Some(account) => {
+                    let request = request.into_outgoing(account);
                     next.send_request(request).await
                 }
-                Err(_) => {
+                None => {
                     error!("No record found for account: {}", account_id);
                     Err(RejectBuilder {
                         code: ErrorCode::F02_UNREACHABLE,
@@ -145,11 +328,12 @@ mod tests {
     use interledger_service::outgoing_service_fn;
     use once_cell::sync::Lazy;
     use parking_lot::Mutex;
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
     use std::iter::FromIterator;
     use std::str::FromStr;
     use std::sync::Arc;
     use std::time::UNIX_EPOCH;
+    use tokio::sync::Semaphore;
     use uuid::Uuid;
 
     #[derive(Debug, Clone)]
@@ -181,9 +365,27 @@ mod tests {
         }
     }
 
+    // Accounts which aren't listed here have the default weight
+    static ROUTING_WEIGHTS: Lazy<HashMap<Uuid, u32>> = Lazy::new(|| {
+        HashMap::from_iter(vec![
+            (Uuid::from_slice(&[3; 16]).unwrap(), 2),
+            (Uuid::from_slice(&[4; 16]).unwrap(), 0),
+            (Uuid::from_slice(&[5; 16]).unwrap(), 0),
+        ])
+    });
+
+    impl RouterAccount for TestAccount {
+        fn routing_weight(&self) -> u32 {
+            ROUTING_WEIGHTS.get(&self.0).cloned().unwrap_or(1)
+        }
+    }
+
     #[derive(Clone)]
     struct TestStore {
         routes: HashMap<String, Uuid>,
+        multipath_routes: HashMap<String, Vec<Uuid>>,
+        draining: Arc<Mutex<HashSet<Uuid>>>,
+        disabled: Arc<Mutex<HashSet<Uuid>>>,
     }
 
     #[async_trait]
@@ -227,6 +429,18 @@ mod tests {
         fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
             Arc::new(self.routes.clone())
         }
+
+        fn multipath_routing_table(&self) -> Arc<HashMap<String, Vec<Uuid>>> {
+            Arc::new(self.multipath_routes.clone())
+        }
+
+        fn draining_accounts(&self) -> Arc<HashSet<Uuid>> {
+            Arc::new(self.draining.lock().clone())
+        }
+
+        fn disabled_accounts(&self) -> Arc<HashSet<Uuid>> {
+            Arc::new(self.disabled.lock().clone())
+        }
     }
 
     #[tokio::test]
@@ -234,6 +448,9 @@ mod tests {
         let mut router = Router::new(
             TestStore {
                 routes: HashMap::new(),
+                multipath_routes: HashMap::new(),
+                draining: Default::default(),
+                disabled: Default::default(),
             },
             outgoing_service_fn(|_| {
                 Ok(FulfillBuilder {
@@ -267,6 +484,9 @@ mod tests {
                 routes: HashMap::from_iter(
                     vec![("example.other".to_string(), Uuid::new_v4())].into_iter(),
                 ),
+                multipath_routes: HashMap::new(),
+                draining: Default::default(),
+                disabled: Default::default(),
             },
             outgoing_service_fn(|_| {
                 Ok(FulfillBuilder {
@@ -300,6 +520,9 @@ mod tests {
                 routes: HashMap::from_iter(
                     vec![("example.destination".to_string(), Uuid::new_v4())].into_iter(),
                 ),
+                multipath_routes: HashMap::new(),
+                draining: Default::default(),
+                disabled: Default::default(),
             },
             outgoing_service_fn(|_| {
                 Ok(FulfillBuilder {
@@ -326,11 +549,56 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn forwards_data_untouched() {
+        // Every byte value, in a packet bigger than most
+        let data: Vec<u8> = (0..=255).cycle().take(32 * 1024).collect();
+        let expected = data.clone();
+        let mut router = Router::new(
+            TestStore {
+                routes: HashMap::from_iter(
+                    vec![("example.destination".to_string(), Uuid::new_v4())].into_iter(),
+                ),
+                multipath_routes: HashMap::new(),
+                draining: Default::default(),
+                disabled: Default::default(),
+            },
+            outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+                assert_eq!(request.prepare.data(), &expected[..]);
+                // Echoes the data back, so that the response is checked too
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: request.prepare.data(),
+                }
+                .build())
+            }),
+        );
+
+        let fulfill = router
+            .handle_request(IncomingRequest {
+                from: TestAccount(Uuid::new_v4()),
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount: 100,
+                    execution_condition: &[1; 32],
+                    expires_at: UNIX_EPOCH,
+                    data: &data,
+                }
+                .build(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(fulfill.data(), &data[..]);
+    }
+
     #[tokio::test]
     async fn catch_all_route() {
         let mut router = Router::new(
             TestStore {
                 routes: HashMap::from_iter(vec![(String::new(), Uuid::new_v4())].into_iter()),
+                multipath_routes: HashMap::new(),
+                draining: Default::default(),
+                disabled: Default::default(),
             },
             outgoing_service_fn(|_| {
                 Ok(FulfillBuilder {
@@ -357,6 +625,38 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn does_not_route_packets_to_own_address() {
+        let mut router = Router::new(
+            TestStore {
+                routes: HashMap::from_iter(vec![(String::new(), Uuid::new_v4())].into_iter()),
+                multipath_routes: HashMap::new(),
+                draining: Default::default(),
+                disabled: Default::default(),
+            },
+            outgoing_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+
+        let reject = router
+            .handle_request(request_to("example.connector"))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+        assert_eq!(reject.message(), b"Packet is addressed to this node");
+
+        // The node's sub-accounts are still routed
+        router
+            .handle_request(request_to("example.connector.alice"))
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn finds_matching_prefix() {
         let mut router = Router::new(
@@ -364,6 +664,9 @@ mod tests {
                 routes: HashMap::from_iter(
                     vec![("example.".to_string(), Uuid::new_v4())].into_iter(),
                 ),
+                multipath_routes: HashMap::new(),
+                draining: Default::default(),
+                disabled: Default::default(),
             },
             outgoing_service_fn(|_| {
                 Ok(FulfillBuilder {
@@ -407,6 +710,9 @@ mod tests {
                     ]
                     .into_iter(),
                 ),
+                multipath_routes: HashMap::new(),
+                draining: Default::default(),
+                disabled: Default::default(),
             },
             outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
                 *to_clone.lock() = Some(request.to);
@@ -435,4 +741,281 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(to.lock().take().unwrap().0, id2);
     }
+
+    /// Router whose routing table has a route through account 1 for "example.", which
+    /// can also be reached through accounts 2 (default weight), 3 (weight 2) and 4 (weight 0).
+    /// Returns the router and the ids of the next hops it sent packets to
+    fn multipath_router() -> (
+        Router<TestStore, impl OutgoingService<TestAccount> + Clone>,
+        Arc<Mutex<Vec<Uuid>>>,
+    ) {
+        let ids: Vec<Uuid> = (1..=4)
+            .map(|i| Uuid::from_slice(&[i; 16]).unwrap())
+            .collect();
+        let next_hops = Arc::new(Mutex::new(Vec::new()));
+        let next_hops_clone = next_hops.clone();
+        let router = Router::new(
+            TestStore {
+                routes: HashMap::from_iter(vec![("example.".to_string(), ids[0])]),
+                multipath_routes: HashMap::from_iter(vec![(
+                    "example.".to_string(),
+                    ids[1..].to_vec(),
+                )]),
+                draining: Default::default(),
+                disabled: Default::default(),
+            },
+            outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+                next_hops_clone.lock().push(request.to.0);
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        (router, next_hops)
+    }
+
+    fn request_to(destination: &str) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount(Uuid::from_slice(&[0; 16]).unwrap()),
+            prepare: PrepareBuilder {
+                destination: Address::from_str(destination).unwrap(),
+                amount: 100,
+                execution_condition: &[1; 32],
+                expires_at: UNIX_EPOCH,
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[tokio::test]
+    async fn spreads_payments_according_to_weights() {
+        let (mut router, next_hops) = multipath_router();
+        let payments = 4000;
+        for i in 0..payments {
+            router
+                .handle_request(request_to(&format!("example.receiver.{}", i)))
+                .await
+                .unwrap();
+        }
+
+        let next_hops = next_hops.lock();
+        let share = |i: u8| {
+            let id = Uuid::from_slice(&[i; 16]).unwrap();
+            next_hops.iter().filter(|next_hop| **next_hop == id).count() as f64 / payments as f64
+        };
+        // The total weight is 1 + 1 + 2 + 0 = 4
+        assert!((share(1) - 0.25).abs() < 0.03, "{}", share(1));
+        assert!((share(2) - 0.25).abs() < 0.03, "{}", share(2));
+        assert!((share(3) - 0.5).abs() < 0.03, "{}", share(3));
+        assert!(!next_hops.contains(&Uuid::from_slice(&[4; 16]).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn all_packets_of_a_payment_take_the_same_path() {
+        let (mut router, next_hops) = multipath_router();
+        for _ in 0..20 {
+            router
+                .handle_request(request_to("example.receiver.payment"))
+                .await
+                .unwrap();
+        }
+        let mut used = next_hops.lock().clone();
+        used.dedup();
+        assert_eq!(used.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn skips_draining_accounts() {
+        let (mut router, next_hops) = multipath_router();
+        let id = |i: u8| Uuid::from_slice(&[i; 16]).unwrap();
+        router.store.draining.lock().extend(vec![id(1), id(3)]);
+        for i in 0..100 {
+            router
+                .handle_request(request_to(&format!("example.receiver.{}", i)))
+                .await
+                .unwrap();
+        }
+        // Account 4 has a weight of 0, so all packets go through account 2
+        assert!(next_hops.lock().iter().all(|next_hop| *next_hop == id(2)));
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_only_routable_through_draining_accounts() {
+        let id = Uuid::from_slice(&[1; 16]).unwrap();
+        let mut router = Router::new(
+            TestStore {
+                routes: HashMap::from_iter(vec![("example.destination".to_string(), id)]),
+                multipath_routes: HashMap::new(),
+                draining: Default::default(),
+                disabled: Default::default(),
+            },
+            outgoing_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        router.store.draining.lock().insert(id);
+        let reject = router
+            .handle_request(request_to("example.destination"))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T04_INSUFFICIENT_LIQUIDITY);
+        assert_eq!(
+            reject.triggered_by().unwrap().to_string(),
+            "example.connector"
+        );
+
+        // the account is routed to again once it is not draining anymore
+        router.store.draining.lock().remove(&id);
+        assert!(router
+            .handle_request(request_to("example.destination"))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_from_disabled_accounts() {
+        let (mut router, next_hops) = multipath_router();
+        let sender = Uuid::from_slice(&[0; 16]).unwrap();
+        router.store.disabled.lock().insert(sender);
+        let reject = router
+            .handle_request(request_to("example.receiver"))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F00_BAD_REQUEST);
+        assert!(next_hops.lock().is_empty());
+
+        // the account may send packets again once it is enabled
+        router.store.disabled.lock().remove(&sender);
+        assert!(router
+            .handle_request(request_to("example.receiver"))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn skips_disabled_accounts() {
+        let (mut router, next_hops) = multipath_router();
+        let id = |i: u8| Uuid::from_slice(&[i; 16]).unwrap();
+        router.store.disabled.lock().extend(vec![id(1), id(3)]);
+        for i in 0..100 {
+            router
+                .handle_request(request_to(&format!("example.receiver.{}", i)))
+                .await
+                .unwrap();
+        }
+        assert!(next_hops.lock().iter().all(|next_hop| *next_hop == id(2)));
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_only_routable_through_disabled_accounts() {
+        let id = Uuid::from_slice(&[1; 16]).unwrap();
+        let mut router = Router::new(
+            TestStore {
+                routes: HashMap::from_iter(vec![("example.destination".to_string(), id)]),
+                multipath_routes: HashMap::new(),
+                draining: Default::default(),
+                disabled: Default::default(),
+            },
+            outgoing_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        router.store.disabled.lock().insert(id);
+        let reject = router
+            .handle_request(request_to("example.destination"))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+
+        // the account is routed to again once it is enabled
+        router.store.disabled.lock().remove(&id);
+        assert!(router
+            .handle_request(request_to("example.destination"))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn completes_in_flight_packets_of_draining_accounts() {
+        let id = Uuid::from_slice(&[1; 16]).unwrap();
+        let next = GatedService::default();
+        let mut router = Router::new(
+            TestStore {
+                routes: HashMap::from_iter(vec![("example.destination".to_string(), id)]),
+                multipath_routes: HashMap::new(),
+                draining: Default::default(),
+                disabled: Default::default(),
+            },
+            next.clone(),
+        );
+        let mut router_clone = router.clone();
+        let in_flight = tokio::spawn(async move {
+            router_clone
+                .handle_request(request_to("example.destination"))
+                .await
+        });
+        // wait until the packet was forwarded to the account
+        next.entered.acquire().await.forget();
+
+        router.store.draining.lock().insert(id);
+        let reject = router
+            .handle_request(request_to("example.destination"))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T04_INSUFFICIENT_LIQUIDITY);
+        assert_eq!(next.entered.available_permits(), 0);
+
+        next.gate.add_permits(1);
+        assert!(in_flight.await.unwrap().is_ok());
+    }
+
+    /// Fulfills the packets it is sent, but only once it is released
+    #[derive(Clone)]
+    struct GatedService {
+        entered: Arc<Semaphore>,
+        gate: Arc<Semaphore>,
+    }
+
+    impl Default for GatedService {
+        fn default() -> Self {
+            GatedService {
+                entered: Arc::new(Semaphore::new(0)),
+                gate: Arc::new(Semaphore::new(0)),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl OutgoingService<TestAccount> for GatedService {
+        async fn send_request(&mut self, _: OutgoingRequest<TestAccount>) -> IlpResult {
+            self.entered.add_permits(1);
+            let _ = self.gate.acquire().await;
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        }
+    }
+
+    #[test]
+    fn selects_equally_if_all_weights_are_zero() {
+        let accounts = vec![
+            TestAccount(Uuid::from_slice(&[4; 16]).unwrap()),
+            TestAccount(Uuid::from_slice(&[5; 16]).unwrap()),
+        ];
+        assert_eq!(select_weighted(accounts.clone(), 0).0, accounts[0].0);
+        assert_eq!(select_weighted(accounts.clone(), 1).0, accounts[1].0);
+    }
 }
@@ -1,16 +1,79 @@
 use async_trait::async_trait;
+use chrono::Utc;
 use futures::TryFutureExt;
 use interledger_errors::BalanceStoreError;
 use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_rates::{ExchangeRateStore, RateProvenance};
 use interledger_service::*;
 use interledger_settlement::core::{
-    types::{SettlementAccount, SettlementStore},
+    types::{Convert, ConvertDetails, SettlementAccount, SettlementAsset, SettlementStore},
     SettlementClient,
 };
-use log::{debug, error};
-use std::marker::PhantomData;
+use log::{debug, error, warn};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
 use uuid::Uuid;
 
+/// How long balance changes are paused for at most to take a snapshot of the balances
+pub const DEFAULT_MAX_BALANCE_PAUSE: Duration = Duration::from_millis(500);
+
+/// The last settlement which changed an account's balance
+#[derive(Clone, Debug, PartialEq)]
+pub struct LastSettlement {
+    /// The amount by which the settlement changed the balance: positive for
+    /// incoming settlements and negative for outgoing ones
+    pub amount: i64,
+    /// The time the settlement was applied to the balance in RFC3339 format
+    pub timestamp: String,
+}
+
+/// Whether a converted settlement was received from or sent to the peer
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettlementDirection {
+    /// The settlement was received and credited to the balance
+    Incoming,
+    /// The settlement was sent and deducted from the balance
+    Outgoing,
+}
+
+impl Default for SettlementDirection {
+    // Only outgoing settlements were converted before the direction was recorded
+    fn default() -> Self {
+        SettlementDirection::Outgoing
+    }
+}
+
+/// How a settlement was converted between the account's asset and its settlement asset
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SettlementConversion {
+    /// Whether the settlement was received or sent
+    #[serde(default)]
+    pub direction: SettlementDirection,
+    /// The amount the account's balance was changed by, in its asset and scale
+    pub amount: u64,
+    /// The amount which was received from or sent to the settlement engine, in the
+    /// settlement asset and the scale below
+    pub settlement_amount: u64,
+    /// Asset code of the settlement asset
+    pub settlement_asset_code: String,
+    /// Asset scale of the settlement amount
+    pub settlement_asset_scale: u8,
+    /// The exchange rate from the account's asset to the settlement asset (in their base units)
+    pub rate: f64,
+    /// The proof of where the rate came from, if the rates were signed
+    pub rate_provenance: Option<RateProvenance>,
+    /// The time the settlement was converted in RFC3339 format
+    pub timestamp: String,
+}
+
 // TODO: Remove AccountStore dependency, use `AccountId: ToString` as associated type
 /// Trait responsible for managing an account's balance in the store
 /// as ILP Packets get routed
@@ -19,11 +82,54 @@ pub trait BalanceStore {
     /// Fetch the current balance for the given account id.
     async fn get_balance(&self, account_id: Uuid) -> Result<i64, BalanceStoreError>;
 
-    /// Decreases the sending account's balance before forwarding out a prepare packet
+    /// Fetch the last settlement which was applied to the given account's balance, if any.
+    /// Outgoing settlements are recorded when the balance is reduced for them and
+    /// removed again if they are refunded.
+    async fn get_last_settlement(
+        &self,
+        _account_id: Uuid,
+    ) -> Result<Option<LastSettlement>, BalanceStoreError> {
+        Ok(None)
+    }
+
+    /// Saves the conversion of the last (incoming or outgoing) settlement of an account which
+    /// settles in a different asset. Stores which do not support it discard the conversion.
+    async fn save_settlement_conversion(
+        &self,
+        _account_id: Uuid,
+        _conversion: SettlementConversion,
+    ) -> Result<(), BalanceStoreError> {
+        Ok(())
+    }
+
+    /// Fetch the conversion of the last settlement which was received or sent in the
+    /// account's settlement asset, if any
+    async fn get_last_settlement_conversion(
+        &self,
+        _account_id: Uuid,
+    ) -> Result<Option<SettlementConversion>, BalanceStoreError> {
+        Ok(None)
+    }
+
+    /// Fetch the current balances of the given accounts, in the same order. Stores should
+    /// read all of them at the same point in time; by default they are fetched one by one.
+    async fn get_balances(&self, account_ids: Vec<Uuid>) -> Result<Vec<i64>, BalanceStoreError> {
+        let mut balances = Vec::with_capacity(account_ids.len());
+        for account_id in account_ids {
+            balances.push(self.get_balance(account_id).await?);
+        }
+        Ok(balances)
+    }
+
+    /// Decreases the sending account's balance before forwarding out a prepare packet.
+    ///
+    /// The `packet_id` of the balance updates is the execution condition of the packet,
+    /// which stores may record with the change (e.g. in balance events) to identify it.
     async fn update_balances_for_prepare(
         &self,
         from_account_id: Uuid,
         incoming_amount: u64,
+        packet_id: Option<[u8; 32]>,
     ) -> Result<(), BalanceStoreError>;
 
     /// Increases the receiving account's balance, and returns the updated balance
@@ -32,31 +138,298 @@ pub trait BalanceStore {
         &self,
         to_account_id: Uuid,
         outgoing_amount: u64,
+        packet_id: Option<[u8; 32]>,
     ) -> Result<(i64, u64), BalanceStoreError>;
 
     async fn update_balances_for_reject(
         &self,
         from_account_id: Uuid,
         incoming_amount: u64,
+        packet_id: Option<[u8; 32]>,
     ) -> Result<(), BalanceStoreError>;
 }
 
+#[derive(Debug, Default)]
+struct PauseState {
+    paused: bool,
+    /// The number of balance changes which were started but not finished yet
+    in_flight: usize,
+}
+
+struct PauseShared {
+    state: Mutex<PauseState>,
+    /// Notifies the balance changes and snapshots which are waiting whenever the state changes
+    changed: watch::Sender<()>,
+}
+
+impl PauseShared {
+    fn update(&self, update: impl FnOnce(&mut PauseState)) {
+        update(&mut self.state.lock());
+        // The BalancePause keeps a receiver, so this never fails
+        let _ = self.changed.broadcast(());
+    }
+}
+
+/// Pauses the balance changes of the [`BalanceService`](./struct.BalanceService.html)
+/// which it was given to, so that a consistent snapshot of all balances can be taken.
+///
+/// While paused, new balance changes (the prepares, fulfills and rejects of packets)
+/// wait before they are applied, and the snapshot waits for the balance changes which
+/// are being applied to finish. Packets which are being forwarded to the next node are
+/// included with their prepare applied. The pause never lasts longer than the max pause,
+/// so packets are only delayed rather than rejected.
+#[derive(Clone)]
+pub struct BalancePause {
+    shared: Arc<PauseShared>,
+    changes: watch::Receiver<()>,
+    max_pause: Duration,
+}
+
+impl Default for BalancePause {
+    fn default() -> Self {
+        BalancePause::new(DEFAULT_MAX_BALANCE_PAUSE)
+    }
+}
+
+/// Marks a balance change as in flight until it is dropped
+struct BalanceChange(Arc<PauseShared>);
+
+impl Drop for BalanceChange {
+    fn drop(&mut self) {
+        self.0.update(|state| state.in_flight -= 1);
+    }
+}
+
+/// Resumes the balance changes when it is dropped, even if the snapshot was cancelled
+struct Resume(Arc<PauseShared>);
+
+impl Drop for Resume {
+    fn drop(&mut self) {
+        self.0.update(|state| state.paused = false);
+    }
+}
+
+impl BalancePause {
+    /// Simple constructor
+    pub fn new(max_pause: Duration) -> Self {
+        let (changed, changes) = watch::channel(());
+        BalancePause {
+            shared: Arc::new(PauseShared {
+                state: Mutex::new(PauseState::default()),
+                changed,
+            }),
+            changes,
+            max_pause,
+        }
+    }
+
+    /// Pauses the balance changes, waits for the ones in flight to finish and then
+    /// runs the provided read of the balances before resuming. Returns `None` if the
+    /// balance changes in flight (or another snapshot) did not finish, or if the read
+    /// did not complete, within the max pause.
+    pub async fn snapshot<F>(&self, read: F) -> Option<F::Output>
+    where
+        F: Future,
+    {
+        let deadline = Instant::now() + self.max_pause;
+        // The receiver is cloned before the state is checked, so that no change is missed
+        let mut changes = self.changes.clone();
+        let pause = async {
+            loop {
+                {
+                    let mut state = self.shared.state.lock();
+                    if !state.paused {
+                        state.paused = true;
+                        return;
+                    }
+                }
+                changes.recv().await;
+            }
+        };
+        tokio::time::timeout(self.max_pause, pause).await.ok()?;
+        let _resume = Resume(self.shared.clone());
+
+        let finish_in_flight = async {
+            while self.shared.state.lock().in_flight > 0 {
+                changes.recv().await;
+            }
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if tokio::time::timeout(remaining, finish_in_flight)
+            .await
+            .is_err()
+        {
+            debug!(
+                "Not taking a snapshot of the balances because balance changes are still in flight"
+            );
+            return None;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        tokio::time::timeout(remaining, read).await.ok()
+    }
+
+    /// Waits until the balance changes are not paused and starts one
+    async fn start_change(&self) -> BalanceChange {
+        let mut changes = self.changes.clone();
+        loop {
+            {
+                let mut state = self.shared.state.lock();
+                if !state.paused {
+                    state.in_flight += 1;
+                    return BalanceChange(self.shared.clone());
+                }
+            }
+            changes.recv().await;
+        }
+    }
+}
+
+/// Starts a balance change if the balance changes can be paused
+async fn start_balance_change(pause: &Option<BalancePause>) -> Option<BalanceChange> {
+    match pause {
+        Some(pause) => Some(pause.start_change().await),
+        None => None,
+    }
+}
+
+/// Returns the exchange rate from the account's asset to its settlement asset (in their base units)
+fn settlement_rate<S, A>(
+    store: &S,
+    account: &A,
+    settlement_asset: &SettlementAsset,
+) -> Result<f64, ()>
+where
+    S: ExchangeRateStore,
+    A: SettlementAccount,
+{
+    if settlement_asset.asset_code == account.asset_code() {
+        return Ok(1.0);
+    }
+    let rates = store
+        .get_exchange_rates(&[account.asset_code(), &settlement_asset.asset_code])
+        .map_err(|err| {
+            warn!(
+                "No exchange rate available from asset: {} to settlement asset: {}: {}",
+                account.asset_code(),
+                settlement_asset.asset_code,
+                err
+            )
+        })?;
+    // Same as for packets, multiply by the account asset's rate and divide by the settlement asset's
+    Ok(rates[0] / rates[1])
+}
+
+/// Converts the amount to settle with an account into its [settlement asset](../interledger_settlement/core/types/struct.SettlementAsset.html)
+/// at the current exchange rate. Returns `None` if the account settles in its own asset.
+/// The settlement amount is rounded down to the settlement asset's scale.
+pub fn convert_settlement<S, A>(
+    store: &S,
+    account: &A,
+    amount: u64,
+) -> Result<Option<SettlementConversion>, ()>
+where
+    S: ExchangeRateStore,
+    A: SettlementAccount,
+{
+    let settlement_asset = match account.settlement_asset() {
+        Some(settlement_asset) => settlement_asset,
+        None => return Ok(None),
+    };
+    let rate = settlement_rate(store, account, &settlement_asset)?;
+    let settlement_amount = (amount as f64 * rate)
+        .normalize_scale(ConvertDetails {
+            from: account.asset_scale(),
+            to: settlement_asset.asset_scale,
+        })?
+        .floor();
+    if !settlement_amount.is_finite()
+        || settlement_amount < 0.0
+        || settlement_amount >= 2f64.powi(64)
+    {
+        warn!(
+            "Could not convert settlement of {} {} with rate {} to settlement asset: {}",
+            amount,
+            account.asset_code(),
+            rate,
+            settlement_asset.asset_code
+        );
+        return Err(());
+    }
+    Ok(Some(SettlementConversion {
+        direction: SettlementDirection::Outgoing,
+        amount,
+        settlement_amount: settlement_amount as u64,
+        settlement_asset_code: settlement_asset.asset_code,
+        settlement_asset_scale: settlement_asset.asset_scale,
+        rate,
+        rate_provenance: store.get_rate_provenance().unwrap_or(None),
+        timestamp: Utc::now().to_rfc3339(),
+    }))
+}
+
+/// Converts an incoming settlement of an account which settles in a different asset from its
+/// [settlement asset](../interledger_settlement/core/types/struct.SettlementAsset.html) into the
+/// account's asset at the current exchange rate. The settlement amount has to be in the scale of
+/// the account, which the settlement API already scales the engine's amount to. Returns `None`
+/// if the account settles in its own asset. The credited amount is rounded down.
+pub fn convert_incoming_settlement<S, A>(
+    store: &S,
+    account: &A,
+    settlement_amount: u64,
+) -> Result<Option<SettlementConversion>, ()>
+where
+    S: ExchangeRateStore,
+    A: SettlementAccount,
+{
+    let settlement_asset = match account.settlement_asset() {
+        Some(settlement_asset) => settlement_asset,
+        None => return Ok(None),
+    };
+    let rate = settlement_rate(store, account, &settlement_asset)?;
+    let amount = (settlement_amount as f64 / rate).floor();
+    if !amount.is_finite() || amount < 0.0 || amount >= 2f64.powi(64) {
+        warn!(
+            "Could not convert incoming settlement of {} {} with rate {} to asset: {}",
+            settlement_amount,
+            settlement_asset.asset_code,
+            rate,
+            account.asset_code()
+        );
+        return Err(());
+    }
+    Ok(Some(SettlementConversion {
+        direction: SettlementDirection::Incoming,
+        amount: amount as u64,
+        settlement_amount,
+        settlement_asset_code: settlement_asset.asset_code,
+        settlement_asset_scale: account.asset_scale(),
+        rate,
+        rate_provenance: store.get_rate_provenance().unwrap_or(None),
+        timestamp: Utc::now().to_rfc3339(),
+    }))
+}
+
 /// # Balance Service
 ///
 /// Responsible for managing the balances of the account and the interaction with the Settlement Engine
 ///
 /// Requires an `Account` and a `BalanceStore`
+///
+/// Accounts which settle in a different asset than their own have their outgoing settlements
+/// converted at the exchange rate at the time of the settlement. Their balances stay
+/// denominated in their own asset, and the conversion is saved in the store.
 #[derive(Clone)]
 pub struct BalanceService<S, O, A> {
     store: S,
     next: O,
     settlement_client: SettlementClient,
+    pause: Option<BalancePause>,
     account_type: PhantomData<A>,
 }
 
 impl<S, O, A> BalanceService<S, O, A>
 where
-    S: AddressStore + BalanceStore + SettlementStore<Account = A>,
+    S: AddressStore + BalanceStore + SettlementStore<Account = A> + ExchangeRateStore,
     O: OutgoingService<A>,
     A: Account + SettlementAccount,
 {
@@ -65,15 +438,37 @@ where
             store,
             next,
             settlement_client: SettlementClient::default(),
+            pause: None,
             account_type: PhantomData,
         }
     }
+
+    /// Sets the client used to send settlement requests to the settlement engines
+    pub fn settlement_client(&mut self, settlement_client: SettlementClient) -> &mut Self {
+        self.settlement_client = settlement_client;
+        self
+    }
+
+    /// Sets the pause which allows taking consistent snapshots of the balances. The
+    /// balance changes of every packet (its prepare and then its fulfill or reject, but
+    /// not the settlements that follow) are then tracked while they are applied.
+    pub fn pause(&mut self, pause: BalancePause) -> &mut Self {
+        self.pause = Some(pause);
+        self
+    }
 }
 
 #[async_trait]
 impl<S, O, A> OutgoingService<A> for BalanceService<S, O, A>
 where
-    S: AddressStore + BalanceStore + SettlementStore<Account = A> + Clone + Send + Sync + 'static,
+    S: AddressStore
+        + BalanceStore
+        + SettlementStore<Account = A>
+        + ExchangeRateStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     O: OutgoingService<A> + Send + Clone + 'static,
     A: SettlementAccount + Send + Sync + 'static,
 {
@@ -106,6 +501,7 @@ where
         let outgoing_amount = request.prepare.amount();
         let ilp_address = self.store.get_ilp_address();
         let settlement_client = self.settlement_client.clone();
+        let pause = self.pause.clone();
 
         // Update the balance _before_ sending the settlement so that we don't accidentally send
         // multiple settlements for the same balance. While there will be a small moment of time (the delta
@@ -117,19 +513,24 @@ where
         //  _eventually_ be completed. Because of this settlement_engine guarantee, the Connector can
         // operate as-if the settlement engine has completed. Finally, if the request to the settlement-engine
         // fails, this amount will be re-added back to balance.
-        self.store
-            .update_balances_for_prepare(from.id(), incoming_amount)
-            .map_err(move |_| {
-                debug!("Rejecting packet because it would exceed a balance limit");
-                RejectBuilder {
-                    code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
-                    message: &[],
-                    triggered_by: Some(&ilp_address),
-                    data: &[],
-                }
-                .build()
-            })
-            .await?;
+        let mut packet_id = [0; 32];
+        packet_id.copy_from_slice(request.prepare.execution_condition());
+        let balance_change = start_balance_change(&pause).await;
+        let prepare_result = self
+            .store
+            .update_balances_for_prepare(from.id(), incoming_amount, Some(packet_id))
+            .await;
+        drop(balance_change);
+        prepare_result.map_err(move |_| {
+            debug!("Rejecting packet because it would exceed a balance limit");
+            RejectBuilder {
+                code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                message: &[],
+                triggered_by: Some(&ilp_address),
+                data: &[],
+            }
+            .build()
+        })?;
 
         match next.send_request(request).await {
             Ok(fulfill) => {
@@ -143,10 +544,13 @@ where
                     // for the packet we forwarded. Note this means that we will
                     // relay the fulfillment _even if saving to the DB fails._
                     tokio::spawn(async move {
-                        let (balance, amount_to_settle) = store
-                            .update_balances_for_fulfill(to.id(), outgoing_amount)
-                            .map_err(|err| error!("Error applying balance changes for fulfill from account: {} to account: {}. Incoming amount was: {}, outgoing amount was: {}. Error: {}", from_id, to_id, incoming_amount, outgoing_amount, err))
-                            .await?;
+                        let balance_change = start_balance_change(&pause).await;
+                        let fulfill_result = store
+                            .update_balances_for_fulfill(to.id(), outgoing_amount, Some(packet_id))
+                            .await;
+                        drop(balance_change);
+                        let (balance, amount_to_settle) = fulfill_result
+                            .map_err(|err| error!("Error applying balance changes for fulfill from account: {} to account: {}. Incoming amount was: {}, outgoing amount was: {}. Error: {}", from_id, to_id, incoming_amount, outgoing_amount, err))?;
                         debug!(
                             "Account balance after fulfill: {}. Amount that needs to be settled: {}",
                             balance, amount_to_settle
@@ -154,6 +558,19 @@ where
                         if amount_to_settle > 0 {
                             if let Some(engine_details) = to.settlement_engine_details() {
                                 let engine_url = engine_details.url;
+                                // While the engine is unhealthy, the amount is kept in the balance
+                                // and settled with the next settlement after the engine recovers
+                                if !settlement_client.is_engine_healthy(&engine_url) {
+                                    warn!(
+                                        "Not settling {} for account {} because its settlement engine {} is unhealthy",
+                                        amount_to_settle, to_id, engine_url
+                                    );
+                                    store
+                                        .refund_settlement(to_id, amount_to_settle)
+                                        .map_err(|_| ())
+                                        .await?;
+                                    return Ok(());
+                                }
                                 // Note that if this program crashes after changing the balance (in the PROCESS_FULFILL script)
                                 // and the send_settlement fails but the program isn't alive to hear that, the balance will be incorrect.
                                 // No other instance will know that it was trying to send an outgoing settlement. We could
@@ -162,12 +579,31 @@ where
                                 // settlement engine for the status of each
                                 // outgoing settlement and putting unnecessary
                                 // load on the settlement engine.
+                                let conversion =
+                                    match convert_settlement(&store, &to, amount_to_settle) {
+                                        Ok(conversion) => conversion,
+                                        Err(()) => {
+                                            // Without a rate the amount is kept in the balance, same as above
+                                            store
+                                                .refund_settlement(to_id, amount_to_settle)
+                                                .map_err(|_| ())
+                                                .await?;
+                                            return Ok(());
+                                        }
+                                    };
+                                let (settlement_amount, settlement_scale) = match conversion {
+                                    Some(ref conversion) => (
+                                        conversion.settlement_amount,
+                                        conversion.settlement_asset_scale,
+                                    ),
+                                    None => (amount_to_settle, to.asset_scale()),
+                                };
                                 if settlement_client
                                     .send_settlement(
                                         to.id(),
                                         engine_url,
-                                        amount_to_settle,
-                                        to.asset_scale(),
+                                        settlement_amount,
+                                        settlement_scale,
                                     )
                                     .await
                                     .is_err()
@@ -176,6 +612,19 @@ where
                                         .refund_settlement(to_id, amount_to_settle)
                                         .map_err(|_| ())
                                         .await?;
+                                } else if let Some(conversion) = conversion {
+                                    debug!(
+                                        "Settled {} with account {} as {} {} at rate {}",
+                                        amount_to_settle,
+                                        to_id,
+                                        conversion.settlement_amount,
+                                        conversion.settlement_asset_code,
+                                        conversion.rate
+                                    );
+                                    store
+                                        .save_settlement_conversion(to_id, conversion)
+                                        .map_err(|err| error!("Error saving settlement conversion for account {}: {}", to_id, err))
+                                        .await?;
                                 }
                             }
                         }
@@ -198,10 +647,14 @@ where
                 tokio::spawn({
                     let store_clone = self.store.clone();
                     async move {
-                        store_clone.update_balances_for_reject(
+                        let balance_change = start_balance_change(&pause).await;
+                        let result = store_clone.update_balances_for_reject(
                             from_clone.id(),
                             incoming_amount,
-                        ).map_err(move |_| error!("Error rolling back balance change for accounts: {} and {}. Incoming amount was: {}, outgoing amount was: {}", from_clone.id(), to_clone.id(), incoming_amount, outgoing_amount)).await
+                            Some(packet_id),
+                        ).map_err(move |_| error!("Error rolling back balance change for accounts: {} and {}. Incoming amount was: {}, outgoing amount was: {}", from_clone.id(), to_clone.id(), incoming_amount, outgoing_amount)).await;
+                        drop(balance_change);
+                        result
                     }
                 });
 
@@ -214,11 +667,12 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use interledger_errors::{AddressStoreError, SettlementStoreError};
+    use interledger_errors::{AddressStoreError, ExchangeRateStoreError, SettlementStoreError};
     use interledger_packet::{Address, FulfillBuilder, PrepareBuilder, RejectBuilder};
-    use interledger_settlement::core::types::SettlementEngineDetails;
+    use interledger_settlement::core::types::{SettlementAsset, SettlementEngineDetails};
     use once_cell::sync::Lazy;
     use parking_lot::RwLock;
+    use std::collections::HashMap;
     use std::str::FromStr;
     use std::sync::Arc;
     use std::time::Duration;
@@ -291,6 +745,96 @@ mod tests {
         assert_eq!(*store.rejected_message.read(), false);
     }
 
+    #[allow(clippy::float_cmp)]
+    #[tokio::test]
+    async fn converts_settlement_into_settlement_asset() {
+        // 1.5 XYZ (at 2 USDC each) are settled as 3 USDC
+        let mock = mockito::mock("POST", mockito::Matcher::Any)
+            .match_body(r#"{"amount":"3000000","scale":6}"#)
+            .create();
+        let next = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let store = TestStore::new(1_500_000_000);
+        let mut service = BalanceService::new(store.clone(), next);
+        service
+            .send_request(settling_in_usdc(TEST_REQUEST.clone()))
+            .await
+            .unwrap();
+
+        tokio::time::delay_for(Duration::from_millis(100u64)).await;
+        mock.assert();
+        assert_eq!(*store.refunded_settlement.read(), false);
+        let conversion = store.settlement_conversion.read().clone().unwrap();
+        assert_eq!(conversion.amount, 1_500_000_000);
+        assert_eq!(conversion.settlement_amount, 3_000_000);
+        assert_eq!(conversion.settlement_asset_code, "USDC");
+        assert_eq!(conversion.settlement_asset_scale, 6);
+        assert_eq!(conversion.rate, 2.0);
+        assert_eq!(conversion.direction, SettlementDirection::Outgoing);
+    }
+
+    #[allow(clippy::float_cmp)]
+    #[test]
+    fn converts_incoming_settlement_from_settlement_asset() {
+        let store = TestStore::new(0);
+        let mut account = TestAccount {
+            engine_url: Url::parse("http://localhost:3000").unwrap(),
+            settlement_asset: None,
+        };
+        // accounts settling in their own asset are credited what the engine reports
+        assert_eq!(convert_incoming_settlement(&store, &account, 100), Ok(None));
+
+        // 3 USDC (at 2 USDC each) are credited as 1.5 XYZ
+        account.settlement_asset = Some(SettlementAsset {
+            asset_code: "USDC".to_string(),
+            asset_scale: 6,
+        });
+        let conversion = convert_incoming_settlement(&store, &account, 3_000_000_000)
+            .unwrap()
+            .unwrap();
+        assert_eq!(conversion.direction, SettlementDirection::Incoming);
+        assert_eq!(conversion.amount, 1_500_000_000);
+        assert_eq!(conversion.settlement_amount, 3_000_000_000);
+        assert_eq!(conversion.settlement_asset_code, "USDC");
+        assert_eq!(conversion.settlement_asset_scale, 9);
+        assert_eq!(conversion.rate, 2.0);
+
+        // without a rate the settlement cannot be credited
+        store.rates.write().remove("USDC");
+        assert!(convert_incoming_settlement(&store, &account, 100).is_err());
+    }
+
+    #[tokio::test]
+    async fn refunds_settlement_without_rate() {
+        let mock = mockito::mock("POST", mockito::Matcher::Any)
+            .create()
+            .expect(0);
+        let next = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let store = TestStore::new(1_500_000_000);
+        store.rates.write().remove("USDC");
+        let mut service = BalanceService::new(store.clone(), next);
+        service
+            .send_request(settling_in_usdc(TEST_REQUEST.clone()))
+            .await
+            .unwrap();
+
+        tokio::time::delay_for(Duration::from_millis(100u64)).await;
+        mock.assert();
+        assert_eq!(*store.refunded_settlement.read(), true);
+        assert_eq!(*store.settlement_conversion.read(), None);
+    }
+
     #[tokio::test]
     async fn updates_for_reject() {
         let mock = mockito::mock("POST", mockito::Matcher::Any)
@@ -318,9 +862,196 @@ mod tests {
         assert_eq!(*store.rejected_message.read(), true);
     }
 
+    #[tokio::test]
+    async fn rejects_packets_exceeding_the_credit_line() {
+        let next = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        });
+        let mut store = TestStore::new(0);
+        store.min_balance = Some(-200);
+        let mut service = BalanceService::new(store.clone(), next);
+        // the first packet fits within the credit line and the second one exactly hits it
+        service.send_request(TEST_REQUEST.clone()).await.unwrap();
+        service.send_request(TEST_REQUEST.clone()).await.unwrap();
+        assert_eq!(*store.balance.read(), -200);
+
+        let reject = service
+            .send_request(TEST_REQUEST.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T04_INSUFFICIENT_LIQUIDITY);
+        assert_eq!(
+            reject.triggered_by().unwrap().to_string(),
+            "example.connector"
+        );
+        assert_eq!(*store.balance.read(), -200);
+    }
+
+    #[tokio::test]
+    async fn pauses_settlement_while_engine_is_unhealthy() {
+        let next = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let engine_url = Url::parse(&mockito::server_url()).unwrap();
+        let mut settlement_client = SettlementClient::default();
+        settlement_client.health_failure_threshold(1);
+        let store = TestStore::new(1);
+        let mut service = BalanceService::new(store.clone(), next);
+        service.settlement_client(settlement_client.clone());
+
+        // The engine goes down
+        let engine_down = mockito::mock("GET", "/").with_status(503).create();
+        assert!(!settlement_client.probe_engine(engine_url.clone()).await);
+        drop(engine_down);
+        let settlement = mockito::mock("POST", mockito::Matcher::Any)
+            .create()
+            .expect(0);
+        service.send_request(TEST_REQUEST.clone()).await.unwrap();
+        tokio::time::delay_for(Duration::from_millis(100u64)).await;
+        settlement.assert();
+        // The amount stays in the balance instead
+        assert_eq!(*store.refunded_settlement.read(), true);
+
+        // The engine comes back
+        *store.refunded_settlement.write() = false;
+        let _engine_up = mockito::mock("GET", "/").create();
+        assert!(settlement_client.probe_engine(engine_url).await);
+        let settlement = mockito::mock("POST", mockito::Matcher::Any).create();
+        service.send_request(TEST_REQUEST.clone()).await.unwrap();
+        tokio::time::delay_for(Duration::from_millis(100u64)).await;
+        settlement.assert();
+        assert_eq!(*store.refunded_settlement.read(), false);
+    }
+
+    #[tokio::test]
+    async fn snapshots_consistent_balances_under_load() {
+        let store = TestStore::new(0);
+        // Packets are always being forwarded, but the pause only waits for their balance changes
+        let pause = BalancePause::new(Duration::from_millis(50));
+        let mut service = BalanceService::new(store.clone(), SlowPeer(Duration::from_millis(20)));
+        service.pause(pause.clone());
+
+        let packets: Vec<_> = (1..=200u64)
+            .map(|amount| {
+                let mut service = service.clone();
+                let mut request = TEST_REQUEST.clone();
+                request.original_amount = amount;
+                request.prepare.set_amount(amount);
+                tokio::spawn(async move {
+                    let _ = service.send_request(request).await;
+                })
+            })
+            .collect();
+        for _ in 0..10 {
+            tokio::time::delay_for(Duration::from_millis(3)).await;
+            let balances = pause
+                .snapshot(store.get_balances(Vec::new()))
+                .await
+                .expect("balance changes in flight should finish within the max pause")
+                .unwrap();
+            // Whatever was taken from the sender was either refunded, given to the
+            // receiver or is still being forwarded
+            assert!(balances[0] + balances[1] <= 0);
+            assert!(balances[1] >= 0);
+        }
+        for packet in packets {
+            packet.await.unwrap();
+        }
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert_eq!(pause.shared.state.lock().in_flight, 0);
+
+        // Only the fulfilled packets, which had even amounts, were paid
+        let balances = pause.snapshot(store.get_balances(Vec::new())).await;
+        assert_eq!(balances.unwrap().unwrap(), vec![-10100, 10100]);
+    }
+
+    #[tokio::test]
+    async fn snapshots_balances_while_packets_are_forwarded() {
+        let store = TestStore::new(0);
+        let pause = BalancePause::new(Duration::from_millis(20));
+        let mut service = BalanceService::new(store.clone(), SlowPeer(Duration::from_millis(100)));
+        service.pause(pause.clone());
+
+        let packet = tokio::spawn(async move { service.send_request(TEST_REQUEST.clone()).await });
+        tokio::time::delay_for(Duration::from_millis(5)).await;
+        // The prepare was applied, and the fulfill was not yet
+        let balances = pause.snapshot(store.get_balances(Vec::new())).await;
+        assert_eq!(balances.unwrap().unwrap(), vec![-100, 0]);
+
+        assert!(packet.await.unwrap().is_ok());
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        let balances = pause.snapshot(store.get_balances(Vec::new())).await;
+        assert_eq!(balances.unwrap().unwrap(), vec![-100, 100]);
+    }
+
+    #[tokio::test]
+    async fn gives_up_snapshot_while_balance_changes_stay_in_flight() {
+        let mut store = TestStore::new(0);
+        store.prepare_delay = Duration::from_millis(100);
+        let pause = BalancePause::new(Duration::from_millis(20));
+        let mut service = BalanceService::new(store.clone(), SlowPeer(Duration::from_millis(1)));
+        service.pause(pause.clone());
+
+        let packet = tokio::spawn(async move { service.send_request(TEST_REQUEST.clone()).await });
+        tokio::time::delay_for(Duration::from_millis(5)).await;
+        assert!(pause
+            .snapshot(store.get_balances(Vec::new()))
+            .await
+            .is_none());
+
+        // The packet was not rejected because of the snapshot
+        assert!(packet.await.unwrap().is_ok());
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        let balances = pause.snapshot(store.get_balances(Vec::new())).await;
+        assert_eq!(balances.unwrap().unwrap(), vec![-100, 100]);
+    }
+
+    /// Peer which fulfills packets with even amounts and rejects the others after a delay
+    #[derive(Clone)]
+    struct SlowPeer(Duration);
+
+    #[async_trait]
+    impl OutgoingService<TestAccount> for SlowPeer {
+        async fn send_request(&mut self, request: OutgoingRequest<TestAccount>) -> IlpResult {
+            tokio::time::delay_for(self.0).await;
+            if request.prepare.amount() % 2 == 0 {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            } else {
+                Err(RejectBuilder {
+                    code: ErrorCode::F99_APPLICATION_ERROR,
+                    message: &[],
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build())
+            }
+        }
+    }
+
     #[derive(Debug, Clone)]
     struct TestAccount {
         pub engine_url: Url,
+        pub settlement_asset: Option<SettlementAsset>,
+    }
+
+    fn settling_in_usdc(mut request: OutgoingRequest<TestAccount>) -> OutgoingRequest<TestAccount> {
+        request.to.settlement_asset = Some(SettlementAsset {
+            asset_code: "USDC".to_string(),
+            asset_scale: 6,
+        });
+        request
     }
 
     static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
@@ -356,6 +1087,10 @@ mod tests {
                 url: self.engine_url.clone(),
             })
         }
+
+        fn settlement_asset(&self) -> Option<SettlementAsset> {
+            self.settlement_asset.clone()
+        }
     }
 
     #[derive(Clone)]
@@ -363,18 +1098,63 @@ mod tests {
         amount_to_settle: u64,
         rejected_message: Arc<RwLock<bool>>,
         refunded_settlement: Arc<RwLock<bool>>,
+        balance: Arc<RwLock<i64>>,
+        /// The balance of the account the packets are forwarded to
+        outgoing_balance: Arc<RwLock<i64>>,
+        min_balance: Option<i64>,
+        rates: Arc<RwLock<HashMap<String, f64>>>,
+        settlement_conversion: Arc<RwLock<Option<SettlementConversion>>>,
+        /// How long applying a prepare takes
+        prepare_delay: Duration,
     }
 
     impl TestStore {
         fn new(amount_to_settle: u64) -> Self {
+            let mut rates = HashMap::new();
+            rates.insert("XYZ".to_string(), 2.0);
+            rates.insert("USDC".to_string(), 1.0);
             TestStore {
                 amount_to_settle,
                 rejected_message: Arc::new(RwLock::new(false)),
                 refunded_settlement: Arc::new(RwLock::new(false)),
+                balance: Arc::new(RwLock::new(0)),
+                outgoing_balance: Arc::new(RwLock::new(0)),
+                min_balance: None,
+                rates: Arc::new(RwLock::new(rates)),
+                settlement_conversion: Arc::new(RwLock::new(None)),
+                prepare_delay: Duration::from_millis(0),
             }
         }
     }
 
+    impl ExchangeRateStore for TestStore {
+        fn get_exchange_rates(
+            &self,
+            asset_codes: &[&str],
+        ) -> Result<Vec<f64>, ExchangeRateStoreError> {
+            let rates = self.rates.read();
+            asset_codes
+                .iter()
+                .map(|code| rates.get(*code).cloned())
+                .collect::<Option<Vec<f64>>>()
+                .ok_or_else(|| ExchangeRateStoreError::PairNotFound {
+                    from: asset_codes[0].to_owned(),
+                    to: asset_codes[1].to_owned(),
+                })
+        }
+
+        fn set_exchange_rates(
+            &self,
+            _: HashMap<String, f64>,
+        ) -> Result<(), ExchangeRateStoreError> {
+            unimplemented!()
+        }
+
+        fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+            unimplemented!()
+        }
+    }
+
     #[async_trait]
     impl AddressStore for TestStore {
         async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
@@ -396,30 +1176,61 @@ mod tests {
             unimplemented!()
         }
 
+        async fn get_balances(&self, _: Vec<Uuid>) -> Result<Vec<i64>, BalanceStoreError> {
+            Ok(vec![*self.balance.read(), *self.outgoing_balance.read()])
+        }
+
         async fn update_balances_for_prepare(
             &self,
             _: Uuid,
-            _: u64,
+            amount: u64,
+            _: Option<[u8; 32]>,
         ) -> Result<(), BalanceStoreError> {
+            if self.prepare_delay > Duration::from_millis(0) {
+                tokio::time::delay_for(self.prepare_delay).await;
+            }
+            let mut balance = self.balance.write();
+            if let Some(min_balance) = self.min_balance {
+                if *balance - (amount as i64) < min_balance {
+                    return Err(BalanceStoreError::Other(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        "would exceed the credit line",
+                    ))));
+                }
+            }
+            *balance -= amount as i64;
             Ok(())
         }
 
         async fn update_balances_for_fulfill(
             &self,
             _: Uuid,
-            _: u64,
+            amount: u64,
+            _: Option<[u8; 32]>,
         ) -> Result<(i64, u64), BalanceStoreError> {
+            *self.outgoing_balance.write() += amount as i64;
             Ok((0, self.amount_to_settle))
         }
 
         async fn update_balances_for_reject(
             &self,
             _: Uuid,
-            _: u64,
+            amount: u64,
+            _: Option<[u8; 32]>,
         ) -> Result<(), BalanceStoreError> {
+            *self.balance.write() += amount as i64;
             *self.rejected_message.write() = true;
             Ok(())
         }
+
+        async fn save_settlement_conversion(
+            &self,
+            _: Uuid,
+            conversion: SettlementConversion,
+        ) -> Result<(), BalanceStoreError> {
+            *self.settlement_conversion.write() = Some(conversion);
+            Ok(())
+        }
     }
 
     #[async_trait]
@@ -446,9 +1257,11 @@ mod tests {
         OutgoingRequest {
             to: TestAccount {
                 engine_url: Url::parse(&url).unwrap(),
+                settlement_asset: None,
             },
             from: TestAccount {
                 engine_url: Url::parse(&url).unwrap(),
+                settlement_asset: None,
             },
             original_amount: 100,
             prepare: PrepareBuilder {
@@ -0,0 +1,297 @@
+use async_trait::async_trait;
+use interledger_packet::{ErrorCode, Reject, RejectBuilder};
+use interledger_service::{Account, AddressStore, IlpResult, IncomingRequest, IncomingService};
+use log::warn;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+/// # Concurrency Limit Service
+///
+/// Incoming Service which caps the number of packets that are in flight (received, but not
+/// yet fulfilled or rejected) at the same time, in total and for each account they are
+/// received from. Packets over either cap are rejected with `T03_CONNECTOR_BUSY` instead of
+/// being forwarded, so a burst of packets cannot exhaust the node's memory or database connections.
+///
+/// Both caps are disabled unless they are set with [`max_in_flight`](#method.max_in_flight)
+/// and [`max_in_flight_per_account`](#method.max_in_flight_per_account).
+#[derive(Clone)]
+pub struct ConcurrencyLimitService<S, I, A> {
+    store: S,
+    next: I,
+    in_flight: Option<Arc<Semaphore>>,
+    per_account: Option<usize>,
+    account_in_flight: Arc<Mutex<HashMap<Uuid, Arc<Semaphore>>>>,
+    account_type: PhantomData<A>,
+}
+
+impl<S, I, A> ConcurrencyLimitService<S, I, A>
+where
+    S: AddressStore,
+    I: IncomingService<A>,
+    A: Account,
+{
+    pub fn new(store: S, next: I) -> Self {
+        ConcurrencyLimitService {
+            store,
+            next,
+            in_flight: None,
+            per_account: None,
+            account_in_flight: Arc::new(Mutex::new(HashMap::new())),
+            account_type: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of packets in flight from all accounts together
+    pub fn max_in_flight(&mut self, limit: usize) -> &mut Self {
+        self.in_flight = Some(Arc::new(Semaphore::new(limit)));
+        self
+    }
+
+    /// Sets the maximum number of packets in flight from any single account
+    pub fn max_in_flight_per_account(&mut self, limit: usize) -> &mut Self {
+        self.per_account = Some(limit);
+        self
+    }
+
+    fn connector_busy(&self) -> Reject {
+        RejectBuilder {
+            code: ErrorCode::T03_CONNECTOR_BUSY,
+            message: b"Too many packets in flight",
+            triggered_by: Some(&self.store.get_ilp_address()),
+            data: &[],
+        }
+        .build()
+    }
+}
+
+#[async_trait]
+impl<S, I, A> IncomingService<A> for ConcurrencyLimitService<S, I, A>
+where
+    S: AddressStore + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: Account + Sync + 'static,
+{
+    /// On receiving a request:
+    /// 1. Takes a permit from the node's and from the sending account's cap
+    ///     - If either has no permits left, returns a reject
+    /// 1. Forwards the request and returns the permits once it was fulfilled or rejected
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let in_flight = self.in_flight.clone();
+        let _permit = match in_flight {
+            Some(ref semaphore) => Some(semaphore.try_acquire().map_err(|_| {
+                warn!("Rejecting packet because the node has too many packets in flight");
+                self.connector_busy()
+            })?),
+            None => None,
+        };
+
+        let account_in_flight = self.per_account.map(|limit| {
+            self.account_in_flight
+                .lock()
+                .entry(request.from.id())
+                .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+                .clone()
+        });
+        let _account_permit = match account_in_flight {
+            Some(ref semaphore) => Some(semaphore.try_acquire().map_err(|_| {
+                warn!(
+                    "Rejecting packet because account {} has too many packets in flight",
+                    request.from.id()
+                );
+                self.connector_busy()
+            })?),
+            None => None,
+        };
+
+        self.next.handle_request(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_service::Username;
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, SystemTime};
+
+    #[tokio::test]
+    async fn rejects_packets_over_the_cap() {
+        let next = GatedService::default();
+        let mut service = ConcurrencyLimitService::new(TestStore, next.clone());
+        service.max_in_flight(2);
+
+        let first = spawn_request(&service, ALICE_ID);
+        let second = spawn_request(&service, BOB_ID);
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert_eq!(next.entered.load(Ordering::SeqCst), 2);
+
+        let reject = service
+            .handle_request(request_from(ALICE_ID))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T03_CONNECTOR_BUSY);
+        assert_eq!(
+            reject.triggered_by().unwrap().to_string(),
+            "example.connector"
+        );
+        assert_eq!(next.entered.load(Ordering::SeqCst), 2);
+
+        // the capacity is released once the packets complete
+        next.release();
+        assert!(first.await.unwrap().is_ok());
+        assert!(second.await.unwrap().is_ok());
+        assert!(service.handle_request(request_from(ALICE_ID)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_over_the_account_cap() {
+        let next = GatedService::default();
+        let mut service = ConcurrencyLimitService::new(TestStore, next.clone());
+        service.max_in_flight_per_account(1);
+
+        let first = spawn_request(&service, ALICE_ID);
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        let reject = service
+            .handle_request(request_from(ALICE_ID))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T03_CONNECTOR_BUSY);
+
+        // other accounts have their own cap
+        let second = spawn_request(&service, BOB_ID);
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert_eq!(next.entered.load(Ordering::SeqCst), 2);
+
+        next.release();
+        assert!(first.await.unwrap().is_ok());
+        assert!(second.await.unwrap().is_ok());
+        assert!(service.handle_request(request_from(ALICE_ID)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn forwards_everything_without_caps() {
+        let next = GatedService::default();
+        next.release();
+        let mut service = ConcurrencyLimitService::new(TestStore, next.clone());
+        for _ in 0..10 {
+            assert!(service.handle_request(request_from(ALICE_ID)).await.is_ok());
+        }
+        assert_eq!(next.entered.load(Ordering::SeqCst), 10);
+    }
+
+    fn spawn_request(
+        service: &ConcurrencyLimitService<TestStore, GatedService, TestAccount>,
+        id: u128,
+    ) -> tokio::task::JoinHandle<IlpResult> {
+        let mut service = service.clone();
+        tokio::spawn(async move { service.handle_request(request_from(id)).await })
+    }
+
+    /// Fulfills the packets it receives, but only once it is released
+    #[derive(Clone)]
+    struct GatedService {
+        gate: Arc<Semaphore>,
+        entered: Arc<AtomicUsize>,
+    }
+
+    impl Default for GatedService {
+        fn default() -> Self {
+            GatedService {
+                gate: Arc::new(Semaphore::new(0)),
+                entered: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+    }
+
+    impl GatedService {
+        fn release(&self) {
+            self.gate.add_permits(1);
+        }
+    }
+
+    #[async_trait]
+    impl IncomingService<TestAccount> for GatedService {
+        async fn handle_request(&mut self, _: IncomingRequest<TestAccount>) -> IlpResult {
+            self.entered.fetch_add(1, Ordering::SeqCst);
+            // The permit is returned right away, so all packets pass once released
+            let _ = self.gate.acquire().await;
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        }
+    }
+
+    const ALICE_ID: u128 = 1;
+    const BOB_ID: u128 = 2;
+
+    fn request_from(id: u128) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount(Uuid::from_u128(id)),
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestAccount(Uuid);
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.0
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use async_trait::async_trait;
+use interledger_packet::{Address, ErrorCode, RejectBuilder};
+use interledger_service::*;
+use log::debug;
+
+/// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the
+/// prefixes of the addresses this account may send packets to
+pub trait DestinationAllowlistAccount: Account {
+    /// The prefixes the destinations of this account's packets must be within.
+    /// Defaults to none, which allows every destination.
+    fn allowed_destination_prefixes(&self) -> &[Address] {
+        &[]
+    }
+}
+
+/// Returns whether the address is the prefix itself or within its subtree
+fn is_within(destination: &Address, prefix: &Address) -> bool {
+    let destination: &[u8] = destination.as_ref();
+    let prefix: &[u8] = prefix.as_ref();
+    destination.starts_with(prefix)
+        && (destination.len() == prefix.len() || destination[prefix.len()] == b'.')
+}
+
+/// # Destination Allowlist Service
+///
+/// Incoming Service which restricts the destinations that constrained accounts (for example
+/// a prepaid card) may pay to. Packets from an account with allowed destination prefixes are
+/// rejected with `F02: Unreachable` unless their destination is within one of them; packets
+/// from other accounts are forwarded as is. It should be placed in front of the
+/// [`Router`](../interledger_router/struct.Router.html), since this is a policy limit of the
+/// sending account rather than a matter of routing.
+///
+/// Requires a `DestinationAllowlistAccount` and an `AddressStore` (for the `triggered_by`
+/// field of the rejection).
+#[derive(Clone)]
+pub struct DestinationAllowlistService<I, S> {
+    next: I,
+    store: S,
+}
+
+impl<I, S> DestinationAllowlistService<I, S> {
+    /// Simple constructor
+    pub fn new(store: S, next: I) -> Self {
+        DestinationAllowlistService { store, next }
+    }
+}
+
+#[async_trait]
+impl<I, S, A> IncomingService<A> for DestinationAllowlistService<I, S>
+where
+    I: IncomingService<A> + Send + Sync + 'static,
+    S: AddressStore + Send + Sync + 'static,
+    A: DestinationAllowlistAccount + Send + Sync + 'static,
+{
+    /// On receive request:
+    /// 1. if the sending account has no allowed destination prefixes, or the destination is
+    ///    within one of them, forward the request, else reject it
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let allowed = request.from.allowed_destination_prefixes();
+        let destination = request.prepare.destination();
+        if allowed.is_empty() || allowed.iter().any(|prefix| is_within(&destination, prefix)) {
+            return self.next.handle_request(request).await;
+        }
+
+        debug!(
+            "Rejecting packet from account {} to {}, which is outside of its allowed destinations",
+            request.from.id(),
+            destination
+        );
+        Err(RejectBuilder {
+            code: ErrorCode::F02_UNREACHABLE,
+            message: b"Destination is not allowed for this account",
+            triggered_by: Some(&self.store.get_ilp_address()),
+            data: &[],
+        }
+        .build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    #[derive(Debug, Clone)]
+    struct TestAccount(Vec<Address>);
+
+    impl DestinationAllowlistAccount for TestAccount {
+        fn allowed_destination_prefixes(&self) -> &[Address] {
+            &self.0
+        }
+    }
+
+    fn request_to(from: TestAccount, destination: &str) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from,
+            prepare: PrepareBuilder {
+                destination: Address::from_str(destination).unwrap(),
+                amount: 100,
+                expires_at: std::time::SystemTime::now() + std::time::Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: b"test data",
+            }
+            .build(),
+        }
+    }
+
+    fn test_service() -> DestinationAllowlistService<impl IncomingService<TestAccount>, TestStore> {
+        let next = incoming_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        DestinationAllowlistService::new(TestStore, next)
+    }
+
+    #[tokio::test]
+    async fn forwards_allowed_destinations() {
+        let card = TestAccount(vec![
+            Address::from_str("example.merchant").unwrap(),
+            Address::from_str("example.other").unwrap(),
+        ]);
+        let mut service = test_service();
+        for destination in &[
+            "example.merchant",
+            "example.merchant.shop",
+            "example.other.x",
+        ] {
+            let request = request_to(card.clone(), destination);
+            assert!(service.handle_request(request).await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_other_destinations_of_that_account_only() {
+        let card = TestAccount(vec![Address::from_str("example.merchant").unwrap()]);
+        let mut service = test_service();
+        for destination in &["example.other", "example.merchant2", "test.merchant"] {
+            let reject = service
+                .handle_request(request_to(card.clone(), destination))
+                .await
+                .unwrap_err();
+            assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+            assert_eq!(reject.triggered_by(), Some(TestStore.get_ilp_address()));
+        }
+
+        // Accounts without an allowlist can still send anywhere
+        let request = request_to(TestAccount(Vec::new()), "example.other");
+        assert!(service.handle_request(request).await.is_ok());
+    }
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::new_v4()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+}
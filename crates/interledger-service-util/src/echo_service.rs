@@ -3,7 +3,8 @@ use byteorder::ReadBytesExt;
 use bytes::{BufMut, BytesMut};
 use core::borrow::Borrow;
 use interledger_packet::{
-    oer::BufOerExt, Address, ErrorCode, Prepare, PrepareBuilder, RejectBuilder,
+    oer::{self, BufOerExt, MutBufOerExt},
+    Address, ErrorCode, Prepare, PrepareBuilder, RejectBuilder,
 };
 use interledger_service::*;
 use log::debug;
@@ -23,6 +24,9 @@ enum EchoPacketType {
 }
 
 /// A service that implements the Echo Protocol.
+/// Echo requests addressed to the node are bounced back to the source address contained in the request,
+/// which lets a remote tester check connectivity and measure the round trip time to this node.
+/// All other packets are passed through to the next service untouched.
 /// Currently, this service only supports bidirectional mode (unidirectional mode is not supported yet).
 /// The service doesn't shorten expiry as it expects the expiry to be shortened by another service
 /// like `ExpiryShortenerService`.
@@ -76,7 +80,7 @@ where
         let echo_packet_type = match reader.read_u8() {
             Ok(value) => value,
             Err(error) => {
-                eprintln!("Could not read packet type: {:?}", error);
+                debug!("Could not read packet type: {:?}", error);
                 return Err(RejectBuilder {
                     code: ErrorCode::F01_INVALID_PACKET,
                     message: b"Could not read echo packet type.",
@@ -92,7 +96,7 @@ where
             return self.next.handle_request(request).await;
         }
         if echo_packet_type != EchoPacketType::Request as u8 {
-            eprintln!("The packet type is not acceptable: {}", echo_packet_type);
+            debug!("The packet type is not acceptable: {}", echo_packet_type);
             return Err(RejectBuilder {
                 code: ErrorCode::F01_INVALID_PACKET,
                 message: format!(
@@ -111,7 +115,7 @@ where
             Ok(value) => match Address::try_from(value) {
                 Ok(value) => value,
                 Err(error) => {
-                    eprintln!(
+                    debug!(
                         "Could not parse source address from echo packet: {:?}",
                         error
                     );
@@ -125,7 +129,7 @@ where
                 }
             },
             Err(error) => {
-                eprintln!("Could not read source address: {:?}", error);
+                debug!("Could not read source address: {:?}", error);
                 return Err(RejectBuilder {
                     code: ErrorCode::F01_INVALID_PACKET,
                     message: b"Could not read source address.",
@@ -136,8 +140,6 @@ where
             }
         };
 
-        let source_address = Address::try_from(source_address).unwrap();
-
         // create a new prepare packet to echo the prepare
         let execution_condition =
             <[u8; 32]>::try_from(request.prepare.execution_condition()).unwrap();
@@ -153,10 +155,9 @@ where
     }
 }
 
-#[cfg(test)]
-use interledger_packet::{oer, oer::MutBufOerExt};
-// This should be exported when we have a use for it outside of the tests
-#[cfg(test)]
+/// Builds an Echo protocol request which, when sent to `destination`,
+/// will be echoed back to the `source_address` by the destination node.
+/// This can be used to test connectivity between nodes.
 pub struct EchoRequestBuilder<'a> {
     pub amount: u64,
     pub expires_at: SystemTime,
@@ -167,22 +168,19 @@ pub struct EchoRequestBuilder<'a> {
     pub source_address: &'a Address,
 }
 
-#[cfg(test)]
 impl<'a> EchoRequestBuilder<'a> {
     pub fn build(&self) -> Prepare {
-        use bytes04::BufMut as BufMut04;
         let source_address_len = oer::predict_var_octet_string(self.source_address.len());
-        let mut data_buffer =
-            bytes04::BytesMut::with_capacity(ECHO_PREFIX_LEN + 1 + source_address_len);
-        data_buffer.put(ECHO_PREFIX.as_bytes());
-        data_buffer.put_u8(EchoPacketType::Request as u8);
+        let mut data_buffer = Vec::with_capacity(ECHO_PREFIX_LEN + 1 + source_address_len);
+        data_buffer.extend_from_slice(ECHO_PREFIX.as_bytes());
+        data_buffer.push(EchoPacketType::Request as u8);
         data_buffer.put_var_octet_string(self.source_address.as_ref() as &[u8]);
         PrepareBuilder {
             amount: self.amount,
             expires_at: self.expires_at,
             execution_condition: self.execution_condition,
             destination: self.destination.clone(),
-            data: data_buffer.borrow(),
+            data: &data_buffer,
         }
         .build()
     }
@@ -367,6 +365,52 @@ mod echo_tests {
         assert!(result.is_ok());
     }
 
+    /// Packets destined to the node's address which are not echo packets
+    /// are passed through to the next service untouched.
+    #[tokio::test]
+    async fn test_non_echo_packet_to_node_address() {
+        let amount = 1;
+        let expires_at = SystemTime::now() + Duration::from_secs(30);
+        let fulfillment = &get_random_fulfillment();
+        let execution_condition = &get_hash_of(fulfillment);
+        let destination = Address::from_str("example.node").unwrap();
+        let data = b"some other protocol";
+        let node_address = Address::from_str("example.node").unwrap();
+
+        // setup service
+        let dest = destination.clone();
+        let handler = incoming_service_fn(|request| {
+            assert_eq!(request.prepare.amount(), amount);
+            assert_eq!(request.prepare.expires_at(), expires_at);
+            assert_eq!(request.prepare.execution_condition(), execution_condition);
+            assert_eq!(request.prepare.destination(), dest);
+            assert_eq!(request.prepare.data(), &data[..]);
+            Ok(FulfillBuilder {
+                fulfillment: &fulfillment,
+                data: &[],
+            }
+            .build())
+        });
+        let mut echo_service = EchoService::new(TestStore(node_address), handler);
+
+        // setup request
+        let prepare = PrepareBuilder {
+            amount,
+            expires_at,
+            execution_condition,
+            destination,
+            data,
+        }
+        .build();
+        let from = TestAccount(Uuid::new_v4());
+
+        // test
+        let result = echo_service
+            .handle_request(IncomingRequest { prepare, from })
+            .await;
+        assert!(result.is_ok());
+    }
+
     /// If the destination of the packet is the node's address and the echo packet type is
     /// request, the service will echo the packet modifying destination to the `source_address`.
     #[tokio::test]
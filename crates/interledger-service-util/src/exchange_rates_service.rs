@@ -1,18 +1,152 @@
 use async_trait::async_trait;
-use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_packet::{
+    amount::convert_scale_with_remainder, Address, ErrorCode, Reject, RejectBuilder,
+};
 use interledger_rates::ExchangeRateStore;
 use interledger_service::*;
 use interledger_settlement::core::types::{Convert, ConvertDetails};
 use log::{error, trace, warn};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::{fmt, str::FromStr, sync::Arc};
+use uuid::Uuid;
+
+/// Extension trait for [Account](../interledger_service/trait.Account.html) with the spread
+/// the node charges on packets received from that account, and how their converted amounts are rounded
+pub trait SpreadAccount: Account {
+    /// The account's spread, if it overrides the service's global spread
+    fn spread(&self) -> Option<f64> {
+        None
+    }
+
+    /// The account's rounding policy, if it overrides the service's global policy
+    fn rounding(&self) -> Option<RoundingPolicy> {
+        None
+    }
+}
+
+/// How the outgoing amount is rounded if the converted amount is not an integer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingPolicy {
+    /// Round down, so the node never forwards more than it received
+    Floor,
+    /// Round up, so the receiver never gets less than the converted amount
+    Ceil,
+    /// Round to the nearest integer, and up if the fraction is exactly one half
+    RoundHalfUp,
+    /// Reject packets whose amount cannot be converted exactly with `F03_INVALID_AMOUNT`
+    Reject,
+    /// Round down, and add the fraction which was dropped to the next packets between the
+    /// same accounts once they are fulfilled, so that no value is lost over many packets
+    Carry,
+}
+
+impl Default for RoundingPolicy {
+    fn default() -> Self {
+        RoundingPolicy::Floor
+    }
+}
+
+impl FromStr for RoundingPolicy {
+    type Err = ();
+
+    fn from_str(string: &str) -> Result<Self, ()> {
+        match string.to_lowercase().as_str() {
+            "floor" => Ok(RoundingPolicy::Floor),
+            "ceil" => Ok(RoundingPolicy::Ceil),
+            "round_half_up" => Ok(RoundingPolicy::RoundHalfUp),
+            "reject" => Ok(RoundingPolicy::Reject),
+            "carry" => Ok(RoundingPolicy::Carry),
+            _ => Err(()),
+        }
+    }
+}
+
+impl AsRef<str> for RoundingPolicy {
+    fn as_ref(&self) -> &'static str {
+        match self {
+            RoundingPolicy::Floor => "floor",
+            RoundingPolicy::Ceil => "ceil",
+            RoundingPolicy::RoundHalfUp => "round_half_up",
+            RoundingPolicy::Reject => "reject",
+            RoundingPolicy::Carry => "carry",
+        }
+    }
+}
+
+impl fmt::Display for RoundingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+impl RoundingPolicy {
+    /// Rounds the (non-negative) converted amount to an integer
+    fn round(self, amount: f64) -> f64 {
+        match self {
+            RoundingPolicy::Floor | RoundingPolicy::Reject | RoundingPolicy::Carry => {
+                amount.floor()
+            }
+            RoundingPolicy::Ceil => amount.ceil(),
+            RoundingPolicy::RoundHalfUp => amount.round(),
+        }
+    }
+
+    /// Returns whether an amount which was rescaled by rounding down has to be rounded up,
+    /// given the remainder which was dropped and the size of a unit of the new scale
+    /// (both in the original scale). Units which do not fit into a `u64` are `None`.
+    fn rounds_up(self, remainder: u64, unit: Option<u64>) -> bool {
+        match self {
+            RoundingPolicy::Floor | RoundingPolicy::Reject | RoundingPolicy::Carry => false,
+            RoundingPolicy::Ceil => remainder > 0,
+            // Any remainder is less than half of a unit which does not fit into a u64
+            RoundingPolicy::RoundHalfUp => unit.map_or(false, |unit| remainder >= unit - remainder),
+        }
+    }
+}
+
+/// What was left over when packets between two accounts were converted with the
+/// `Carry` policy, which is added to the next packet between them
+#[derive(Clone, Copy, Debug, Default)]
+struct Leftovers {
+    /// Remainder of converting between the accounts' asset scales, in the incoming scale
+    remainder: u64,
+    /// Fraction of converting with an exchange rate, in the outgoing scale
+    fraction: f64,
+}
 
 /// # Exchange Rates Service
 ///
 /// Responsible for getting the exchange rates for the two assets in the outgoing request (`request.from.asset_code`, `request.to.asset_code`).
 /// Requires a `ExchangeRateStore`
+///
+/// The spread applied to a packet is the one of the account it was received from (`request.from.spread()`),
+/// if it has one configured. Otherwise the global spread the service was created with is used.
+///
+/// Outgoing amounts which are not integers after the conversion are rounded down, unless
+/// a different [`RoundingPolicy`](./enum.RoundingPolicy.html) is set with [`rounding`](#method.rounding).
+/// Like the spread, the policy of the account the packet was received from (`request.from.rounding()`)
+/// takes precedence. This applies both to conversions with an exchange rate and to packets
+/// which are only converted between the asset scales of accounts with the same asset.
+///
+/// Packets between accounts with the same asset and scale are not converted at all: their
+/// outgoing amount is the incoming amount minus the spread, which is calculated with integers
+/// so that e.g. a spread of `0.01` deducts exactly 1% of any amount.
+///
+/// A flat fee set with [`flat_fee`](#method.flat_fee) is deducted from the outgoing amount
+/// of every packet after the spread was applied. Packets whose outgoing amount is smaller
+/// than the fee are rejected with `R01_INSUFFICIENT_SOURCE_AMOUNT`.
 #[derive(Clone)]
 pub struct ExchangeRateService<S, O, A> {
     spread: f64,
+    rounding: RoundingPolicy,
+    /// Deducted from the outgoing amount of each packet, in the outgoing account's asset scale
+    flat_fee: u64,
+    /// The leftovers of the `Carry` policy by the incoming and outgoing account's ids
+    leftovers: Arc<Mutex<HashMap<(Uuid, Uuid), Leftovers>>>,
     store: S,
     next: O,
     account_type: PhantomData<A>,
@@ -22,16 +156,32 @@ impl<S, O, A> ExchangeRateService<S, O, A>
 where
     S: AddressStore + ExchangeRateStore,
     O: OutgoingService<A>,
-    A: Account,
+    A: SpreadAccount,
 {
     pub fn new(spread: f64, store: S, next: O) -> Self {
         ExchangeRateService {
             spread,
+            rounding: RoundingPolicy::default(),
+            flat_fee: 0,
+            leftovers: Arc::new(Mutex::new(HashMap::new())),
             store,
             next,
             account_type: PhantomData,
         }
     }
+
+    /// Sets how outgoing amounts are rounded if they are not integers after the conversion
+    pub fn rounding(&mut self, rounding: RoundingPolicy) -> &mut Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Sets the fee deducted from the outgoing amount of each packet, in the smallest unit
+    /// of the outgoing account's asset (defaults to 0)
+    pub fn flat_fee(&mut self, fee: u64) -> &mut Self {
+        self.flat_fee = fee;
+        self
+    }
 }
 
 #[async_trait]
@@ -40,132 +190,432 @@ where
     // TODO can we make these non-'static?
     S: AddressStore + ExchangeRateStore + Clone + Send + Sync + 'static,
     O: OutgoingService<A> + Send + Sync + Clone + 'static,
-    A: Account + Send + Sync + 'static,
+    A: SpreadAccount + Send + Sync + 'static,
 {
     /// On send request:
     /// 1. If the prepare packet's amount is 0, it just forwards
+    /// 1. Takes the leftovers of earlier packets between the same accounts, if they use the `Carry` policy
+    /// 1. If both accounts use the same asset and scale, it only deducts the spread from the amount
+    ///    (rounding with the account's or the service's policy) and forwards it
+    /// 1. If both accounts use the same asset and no spread applies, it only converts the
+    ///    amount between the accounts' asset scales (rounding with the account's or the service's policy) and forwards it
     /// 1. Retrieves the exchange rate from the store (the store independently is responsible for polling the rates)
     ///     - return reject if the call to the store fails
     /// 1. Calculates the exchange rate AND scales it up/down depending on how many decimals each asset requires
+    /// 1. Applies the incoming account's spread, or the global spread if the account has none
+    /// 1. Rounds the outgoing amount with the account's or the service's rounding policy
+    /// 1. Deducts the flat fee from the outgoing amount
+    ///     - return reject if the fee is larger than the outgoing amount
     /// 1. Updates the amount in the prepare packet and forwards it
+    /// 1. Keeps what was left over for the next packet if it was fulfilled, or returns the taken
+    ///    leftovers if it was rejected
     async fn send_request(&mut self, mut request: OutgoingRequest<A>) -> IlpResult {
+        if request.prepare.amount() == 0 {
+            return self.next.send_request(request).await;
+        }
+
         let ilp_address = self.store.get_ilp_address();
-        if request.prepare.amount() > 0 {
-            let rate: f64 = if request.from.asset_code() == request.to.asset_code() {
-                1f64
-            } else if let Ok(rates) = self
-                .store
-                .get_exchange_rates(&[&request.from.asset_code(), &request.to.asset_code()])
-            {
-                // Exchange rates are expressed as `base asset / asset`. To calculate the outgoing amount,
-                // we multiply by the incoming asset's rate and divide by the outgoing asset's rate. For example,
-                // if an incoming packet is denominated in an asset worth 1 USD and the outgoing asset is worth
-                // 10 USD, the outgoing amount will be 1/10th of the source amount.
-                rates[0] / rates[1]
-            } else {
-                error!(
-                    "No exchange rates available for assets: {}, {}",
-                    request.from.asset_code(),
-                    request.to.asset_code()
-                );
+        let spread = request.from.spread().unwrap_or(self.spread);
+        let rounding = request.from.rounding().unwrap_or(self.rounding);
+        let accounts = (request.from.id(), request.to.id());
+        let taken = if rounding == RoundingPolicy::Carry {
+            self.leftovers.lock().remove(&accounts).unwrap_or_default()
+        } else {
+            Leftovers::default()
+        };
+
+        let same_asset = request.from.asset_code() == request.to.asset_code();
+        let same_scale = request.from.asset_scale() == request.to.asset_scale();
+        let decimal_spread = if same_asset && same_scale {
+            decimal_spread(spread)
+        } else {
+            None
+        };
+
+        let converted = if let Some(decimal_spread) = decimal_spread {
+            deduct_spread(
+                &request,
+                decimal_spread,
+                rounding,
+                taken.fraction,
+                &ilp_address,
+            )
+            .map(|(amount, fraction)| {
+                let leftovers = Leftovers {
+                    remainder: taken.remainder,
+                    fraction,
+                };
+                (amount, leftovers)
+            })
+        } else if same_asset && spread == 0.0 {
+            rescale_amount(&request, rounding, taken.remainder, &ilp_address).map(
+                |(amount, remainder)| {
+                    let leftovers = Leftovers {
+                        remainder,
+                        fraction: taken.fraction,
+                    };
+                    (amount, leftovers)
+                },
+            )
+        } else {
+            self.convert_amount(&request, spread, rounding, taken.fraction, &ilp_address)
+                .map(|(amount, fraction)| {
+                    let leftovers = Leftovers {
+                        remainder: taken.remainder,
+                        fraction,
+                    };
+                    (amount, leftovers)
+                })
+        };
+
+        let (outgoing_amount, leftovers) = match converted {
+            Ok(converted) => converted,
+            Err(reject) => {
+                self.carry(accounts, taken);
+                return Err(reject);
+            }
+        };
+        let outgoing_amount = match outgoing_amount.checked_sub(self.flat_fee) {
+            Some(outgoing_amount) => outgoing_amount,
+            None => {
+                self.carry(accounts, taken);
                 return Err(RejectBuilder {
-                    code: ErrorCode::T00_INTERNAL_ERROR,
+                    code: ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
                     message: format!(
-                        "No exchange rate available from asset: {} to: {}",
-                        request.from.asset_code(),
-                        request.to.asset_code()
+                        "Amount too small to cover the fee of {}: {}",
+                        self.flat_fee, outgoing_amount
                     )
                     .as_bytes(),
                     triggered_by: Some(&ilp_address),
                     data: &[],
                 }
                 .build());
-            };
+            }
+        };
+        request.prepare.set_amount(outgoing_amount);
+        if rounding != RoundingPolicy::Carry {
+            return self.next.send_request(request).await;
+        }
 
-            // Apply spread
-            // TODO should this be applied differently for "local" or same-currency packets?
-            let rate = rate * (1.0 - self.spread);
-            let rate = if rate.is_finite() && rate.is_sign_positive() {
+        let result = self.next.send_request(request).await;
+        // The sender is refunded if the packet is rejected, so nothing was left over
+        self.carry(accounts, if result.is_ok() { leftovers } else { taken });
+        result
+    }
+}
+
+impl<S, O, A> ExchangeRateService<S, O, A>
+where
+    S: AddressStore + ExchangeRateStore,
+    O: OutgoingService<A>,
+    A: SpreadAccount,
+{
+    /// Converts the amount of the request with the exchange rate between the accounts' assets
+    /// and the spread, adding the given fraction left over by earlier packets. Returns the
+    /// outgoing amount and the fraction which was rounded off.
+    fn convert_amount(
+        &self,
+        request: &OutgoingRequest<A>,
+        spread: f64,
+        rounding: RoundingPolicy,
+        carried: f64,
+        ilp_address: &Address,
+    ) -> Result<(u64, f64), Reject> {
+        let rate: f64 = if request.from.asset_code() == request.to.asset_code() {
+            1f64
+        } else if let Ok(rates) = self
+            .store
+            .get_exchange_rates(&[&request.from.asset_code(), &request.to.asset_code()])
+        {
+            // Exchange rates are expressed as `base asset / asset`. To calculate the outgoing amount,
+            // we multiply by the incoming asset's rate and divide by the outgoing asset's rate. For example,
+            // if an incoming packet is denominated in an asset worth 1 USD and the outgoing asset is worth
+            // 10 USD, the outgoing amount will be 1/10th of the source amount.
+            rates[0] / rates[1]
+        } else {
+            error!(
+                "No exchange rates available for assets: {}, {}",
+                request.from.asset_code(),
+                request.to.asset_code()
+            );
+            return Err(RejectBuilder {
+                code: ErrorCode::T00_INTERNAL_ERROR,
+                message: format!(
+                    "No exchange rate available from asset: {} to: {}",
+                    request.from.asset_code(),
+                    request.to.asset_code()
+                )
+                .as_bytes(),
+                triggered_by: Some(ilp_address),
+                data: &[],
+            }
+            .build());
+        };
+
+        // Apply spread
+        // TODO should this be applied differently for "local" or same-currency packets?
+        let rate = rate * (1.0 - spread);
+        let rate = if rate.is_finite() && rate.is_sign_positive() {
+            rate
+        } else {
+            warn!(
+                "Exchange rate would have been {} based on rate and spread, using 0.0 instead",
                 rate
-            } else {
-                warn!(
-                    "Exchange rate would have been {} based on rate and spread, using 0.0 instead",
-                    rate
-                );
-                0.0
-            };
+            );
+            0.0
+        };
 
-            // Can we overflow here?
-            let outgoing_amount = (request.prepare.amount() as f64) * rate;
-            let outgoing_amount = outgoing_amount.normalize_scale(ConvertDetails {
-                from: request.from.asset_scale(),
-                to: request.to.asset_scale(),
-            });
-
-            match outgoing_amount {
-                Ok(outgoing_amount) => {
-                    // The conversion succeeded, but the produced f64
-                    // is larger than the maximum value for a u64.
-                    // When it gets cast to a u64, it will end up being 0.
-                    if outgoing_amount != 0.0 && outgoing_amount as u64 == 0 {
-                        let (code, message) = if outgoing_amount < 1.0 {
-                            // user wanted to send a positive value but it got rounded down to 0
-                            (
-                                ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
-                                format!(
-                                    "Could not cast to f64, amount too small: {}",
-                                    outgoing_amount
-                                ),
-                            )
-                        } else {
-                            // amount that arrived was too large for us to forward
-                            (
-                                ErrorCode::F08_AMOUNT_TOO_LARGE,
-                                format!(
-                                    "Could not cast to f64, amount too large: {}",
-                                    outgoing_amount
-                                ),
-                            )
-                        };
-
-                        return Err(RejectBuilder {
-                            code,
-                            message: message.as_bytes(),
-                            triggered_by: Some(&ilp_address),
-                            data: &[],
-                        }
-                        .build());
-                    }
-                    request.prepare.set_amount(outgoing_amount as u64);
-                    trace!("Converted incoming amount of: {} {} (scale {}) from account {} to outgoing amount of: {} {} (scale {}) for account {}",
-                        request.original_amount, request.from.asset_code(), request.from.asset_scale(), request.from.id(),
-                        outgoing_amount, request.to.asset_code(), request.to.asset_scale(), request.to.id());
-                }
-                Err(_) => {
-                    // This branch gets executed when the `Convert` trait
-                    // returns an error. Happens due to float
-                    // multiplication overflow .
-                    // (float overflow in Rust produces +inf)
+        // Overflows produce +inf, which is rejected by `normalize_scale` or `checked_amount`
+        let outgoing_amount = (request.prepare.amount() as f64) * rate;
+        let outgoing_amount = outgoing_amount.normalize_scale(ConvertDetails {
+            from: request.from.asset_scale(),
+            to: request.to.asset_scale(),
+        });
+
+        match outgoing_amount {
+            Ok(outgoing_amount) => {
+                let outgoing_amount = outgoing_amount + carried;
+                if rounding == RoundingPolicy::Reject && outgoing_amount.fract() != 0.0 {
                     return Err(RejectBuilder {
-                        code: ErrorCode::F08_AMOUNT_TOO_LARGE,
-                        message: format!(
-                            "Could not convert exchange rate from {}:{} to: {}:{}. Got incoming amount: {}",
-                            request.from.asset_code(),
-                            request.from.asset_scale(),
-                            request.to.asset_code(),
-                            request.to.asset_scale(),
-                            request.prepare.amount(),
-                        )
-                        .as_bytes(),
-                        triggered_by: Some(&ilp_address),
+                        code: ErrorCode::F03_INVALID_AMOUNT,
+                        message: format!("Amount cannot be converted exactly: {}", outgoing_amount)
+                            .as_bytes(),
+                        triggered_by: Some(ilp_address),
                         data: &[],
                     }
                     .build());
                 }
+                let rounded_amount = rounding.round(outgoing_amount);
+                let (code, message) = match checked_amount(rounded_amount) {
+                    // user wanted to send a positive value but it got rounded down to 0
+                    Some(0) if outgoing_amount != 0.0 => (
+                        ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
+                        format!(
+                            "Could not cast to f64, amount too small: {}",
+                            outgoing_amount
+                        ),
+                    ),
+                    Some(amount) => {
+                        // The provenance of the rates is only looked up if the line is logged
+                        trace!("Converted incoming amount of: {} {} (scale {}) from account {} to outgoing amount of: {} {} (scale {}) for account {} with rates from: {:?}",
+                            request.original_amount, request.from.asset_code(), request.from.asset_scale(), request.from.id(),
+                            amount, request.to.asset_code(), request.to.asset_scale(), request.to.id(),
+                            self.store.get_rate_provenance().ok().and_then(|provenance| provenance.map(|p| (p.source, p.timestamp))));
+                        return Ok((amount, outgoing_amount - rounded_amount));
+                    }
+                    // amount that arrived was too large for us to forward
+                    None => (
+                        ErrorCode::F08_AMOUNT_TOO_LARGE,
+                        format!(
+                            "Could not cast to f64, amount too large: {}",
+                            outgoing_amount
+                        ),
+                    ),
+                };
+                Err(RejectBuilder {
+                    code,
+                    message: message.as_bytes(),
+                    triggered_by: Some(ilp_address),
+                    data: &[],
+                }
+                .build())
+            }
+            Err(_) => {
+                // This branch gets executed when the `Convert` trait
+                // returns an error. Happens due to float
+                // multiplication overflow .
+                // (float overflow in Rust produces +inf)
+                Err(RejectBuilder {
+                    code: ErrorCode::F08_AMOUNT_TOO_LARGE,
+                    message: format!(
+                        "Could not convert exchange rate from {}:{} to: {}:{}. Got incoming amount: {}",
+                        request.from.asset_code(),
+                        request.from.asset_scale(),
+                        request.to.asset_code(),
+                        request.to.asset_scale(),
+                        request.prepare.amount(),
+                    )
+                    .as_bytes(),
+                    triggered_by: Some(ilp_address),
+                    data: &[],
+                }
+                .build())
             }
         }
+    }
 
-        self.next.send_request(request).await
+    /// Adds what was left over by a packet between the given accounts to their leftovers
+    fn carry(&self, accounts: (Uuid, Uuid), leftovers: Leftovers) {
+        if leftovers.remainder == 0 && leftovers.fraction == 0.0 {
+            return;
+        }
+        let mut all_leftovers = self.leftovers.lock();
+        let entry = all_leftovers.entry(accounts).or_default();
+        entry.remainder = entry.remainder.saturating_add(leftovers.remainder);
+        entry.fraction += leftovers.fraction;
+    }
+}
+
+/// Returns the rounded amount as a `u64`, unless it is negative, not finite or too large
+/// to fit into one (casting it would saturate at `u64::MAX` rather than fail)
+fn checked_amount(amount: f64) -> Option<u64> {
+    if amount.is_finite() && amount >= 0.0 && amount < 2f64.powi(64) {
+        Some(amount as u64)
+    } else {
+        None
+    }
+}
+
+/// Converts the amount of the request (plus the remainder left over by earlier packets)
+/// from the asset scale of the account it was received from to the scale of the account
+/// it is sent to, without applying any exchange rate. Returns the outgoing amount and
+/// the remainder which was rounded off.
+fn rescale_amount<A: Account>(
+    request: &OutgoingRequest<A>,
+    rounding: RoundingPolicy,
+    carried: u64,
+    ilp_address: &Address,
+) -> Result<(u64, u64), Reject> {
+    let (from_scale, to_scale) = (request.from.asset_scale(), request.to.asset_scale());
+    let converted = request
+        .prepare
+        .amount()
+        .checked_add(carried)
+        .ok_or_else(|| "Amount too large to add the remainder of earlier packets".to_string())
+        .and_then(|amount| {
+            convert_scale_with_remainder(amount, from_scale, to_scale)
+                .map_err(|err| err.to_string())
+        });
+    let (code, message) = match converted {
+        Ok((_, remainder)) if remainder > 0 && rounding == RoundingPolicy::Reject => (
+            ErrorCode::F03_INVALID_AMOUNT,
+            format!(
+                "Amount cannot be represented exactly with scale {}: {}",
+                to_scale,
+                request.prepare.amount()
+            ),
+        ),
+        Ok((amount, remainder)) => {
+            let unit = 10u64.checked_pow(u32::from(from_scale.saturating_sub(to_scale)));
+            // The amount was divided if there is a remainder, so this cannot overflow
+            let amount = if rounding.rounds_up(remainder, unit) {
+                amount + 1
+            } else {
+                amount
+            };
+            if amount > 0 {
+                trace!("Converted incoming amount of: {} {} (scale {}) from account {} to outgoing amount of: {} {} (scale {}) for account {}",
+                    request.original_amount, request.from.asset_code(), from_scale, request.from.id(),
+                    amount, request.to.asset_code(), to_scale, request.to.id());
+                return Ok((amount, remainder));
+            }
+            // user wanted to send a positive value but it got rounded down to 0
+            (
+                ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
+                format!(
+                    "Amount too small to be represented with scale {}: {}",
+                    to_scale,
+                    request.prepare.amount()
+                ),
+            )
+        }
+        Err(message) => (ErrorCode::F08_AMOUNT_TOO_LARGE, message),
+    };
+    Err(RejectBuilder {
+        code,
+        message: message.as_bytes(),
+        triggered_by: Some(ilp_address),
+        data: &[],
+    }
+    .build())
+}
+
+/// Returns the spread as the decimal fraction `numerator / denominator` it was written as
+/// (e.g. `1 / 100` for `0.01`), if it is between 0 and 1 and the fraction fits into integers
+fn decimal_spread(spread: f64) -> Option<(u128, u128)> {
+    if !(0.0..=1.0).contains(&spread) {
+        return None;
+    }
+    // Floats are displayed with the fewest digits which parse back to the same float,
+    // and never in the exponent notation
+    let formatted = spread.to_string();
+    let mut parts = formatted.splitn(2, '.');
+    let integer = parts.next().unwrap_or_default();
+    let fraction = parts.next().unwrap_or_default();
+    let denominator = 10u128.checked_pow(fraction.len() as u32)?;
+    let numerator = format!("{}{}", integer, fraction).parse().ok()?;
+    Some((numerator, denominator))
+}
+
+/// Deducts the spread from the amount of a request between accounts with the same asset and
+/// scale, adding the given fraction left over by earlier packets. Returns the outgoing amount
+/// and the fraction which was rounded off.
+fn deduct_spread<A: Account>(
+    request: &OutgoingRequest<A>,
+    (numerator, denominator): (u128, u128),
+    rounding: RoundingPolicy,
+    carried: f64,
+    ilp_address: &Address,
+) -> Result<(u64, f64), Reject> {
+    let amount = request.prepare.amount();
+    let reject = |code, message: String| {
+        Err(RejectBuilder {
+            code,
+            message: message.as_bytes(),
+            triggered_by: Some(ilp_address),
+            data: &[],
+        }
+        .build())
+    };
+    // The spread is at most 1, so the deducted amount is at most the incoming amount
+    let deducted = match u128::from(amount).checked_mul(numerator) {
+        Some(deducted) => deducted,
+        None => {
+            return reject(
+                ErrorCode::F08_AMOUNT_TOO_LARGE,
+                format!("Amount too large to deduct the spread: {}", amount),
+            )
+        }
+    };
+    let remaining = amount - (deducted / denominator) as u64;
+    // The outgoing amount is `remaining + fraction`, where the fraction is between -1 and 1
+    let fraction = carried - (deducted % denominator) as f64 / denominator as f64;
+    if rounding == RoundingPolicy::Reject && fraction != 0.0 {
+        return reject(
+            ErrorCode::F03_INVALID_AMOUNT,
+            format!("Amount cannot be converted exactly: {}", amount),
+        );
+    }
+    let rounded_fraction = match rounding {
+        RoundingPolicy::Floor | RoundingPolicy::Reject | RoundingPolicy::Carry => fraction.floor(),
+        RoundingPolicy::Ceil => fraction.ceil(),
+        // f64::round rounds negative halves away from zero
+        RoundingPolicy::RoundHalfUp => (fraction + 0.5).floor(),
+    };
+    let outgoing_amount = if rounded_fraction < 0.0 {
+        remaining.checked_sub((-rounded_fraction) as u64)
+    } else {
+        remaining.checked_add(rounded_fraction as u64)
+    };
+    match outgoing_amount {
+        Some(outgoing_amount) if outgoing_amount > 0 || remaining as f64 + fraction <= 0.0 => {
+            trace!("Deducted spread of {}/{} from incoming amount of: {} {} (scale {}) from account {} to outgoing amount of: {} for account {}",
+                numerator, denominator, request.original_amount, request.from.asset_code(), request.from.asset_scale(), request.from.id(),
+                outgoing_amount, request.to.id());
+            Ok((outgoing_amount, fraction - rounded_fraction))
+        }
+        // user wanted to send a positive value but it got rounded down to 0
+        Some(_) => reject(
+            ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
+            format!("Amount too small to deduct the spread: {}", amount),
+        ),
+        None => reject(
+            ErrorCode::F08_AMOUNT_TOO_LARGE,
+            format!(
+                "Amount too large to add the fraction of earlier packets: {}",
+                amount
+            ),
+        ),
     }
 }
 
@@ -173,13 +623,17 @@ where
 mod tests {
     use super::*;
     use interledger_errors::{AddressStoreError, ExchangeRateStoreError};
-    use interledger_packet::{Address, Fulfill, FulfillBuilder, PrepareBuilder, Reject};
+    use interledger_packet::{Fulfill, FulfillBuilder, PrepareBuilder};
+    use interledger_rates::{ExchangeRateFetcher, RateProvider};
     use interledger_service::{outgoing_service_fn, Account};
     use once_cell::sync::Lazy;
     use std::collections::HashMap;
     use std::str::FromStr;
     use std::{
-        sync::{Arc, Mutex},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
         time::SystemTime,
     };
     use uuid::Uuid;
@@ -222,6 +676,141 @@ mod tests {
         assert!(reject.message().starts_with(b"Could not convert"));
     }
 
+    #[tokio::test]
+    async fn rejects_amounts_which_overflow() {
+        let too_large = |ret: (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>)| {
+            assert_eq!(ret.0.unwrap_err().code(), ErrorCode::F08_AMOUNT_TOO_LARGE);
+            assert!(ret.1.is_empty());
+        };
+        // converted amounts just above u64::MAX are not saturated to u64::MAX
+        too_large(exchange_rate(std::u64::MAX, 9, 1.000_001, 9, 1.0, 0.0).await);
+        too_large(exchange_rate(std::u64::MAX / 2 + 4096, 9, 2.0, 9, 1.0, 0.0).await);
+        // high rates combined with a higher outgoing scale
+        too_large(exchange_rate(1_000_000, 0, 1e15, 9, 1.0, 0.0).await);
+        too_large(exchange_rate(1, 0, 1.0, 30, 2.0, 0.0).await);
+        // the product overflows the f64 itself
+        too_large(exchange_rate(std::u64::MAX, 0, 1e300, 0, 1.0, 0.0).await);
+        let abc = |scale| TestAccount::new("ABC".to_owned(), scale);
+        too_large(send_between(TestStore::default(), abc(0), abc(30), 1, 0.0).await);
+
+        // amounts which still fit are forwarded
+        let ret = exchange_rate(std::u64::MAX / 4, 9, 2.0, 9, 1.0, 0.0).await;
+        assert_eq!(ret.1[0].prepare.amount(), 1 << 63);
+        // while scaling down by more than a u64 can hold leaves nothing to forward
+        let ret = send_between(TestStore::default(), abc(200), abc(0), std::u64::MAX, 0.0).await;
+        assert_eq!(
+            ret.0.unwrap_err().code(),
+            ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT
+        );
+        let ret = exchange_rate(std::u64::MAX, 200, 1.0, 0, 1.0, 0.0).await;
+        assert_eq!(
+            ret.0.unwrap_err().code(),
+            ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT
+        );
+    }
+
+    #[tokio::test]
+    async fn rescales_same_asset_exactly() {
+        let abc = |scale| TestAccount::new("ABC".to_owned(), scale);
+        // amounts that can't be represented exactly by an f64 are not rounded
+        let amount = (1 << 60) + 1;
+        let ret = send_between(TestStore::default(), abc(9), abc(9), amount, 0.0).await;
+        assert_eq!(ret.1[0].prepare.amount(), amount);
+
+        let ret = send_between(TestStore::default(), abc(2), abc(6), 15, 0.0).await;
+        assert_eq!(ret.1[0].prepare.amount(), 150_000);
+
+        // the remainder which can't be represented with the outgoing scale is dropped
+        let ret = send_between(TestStore::default(), abc(6), abc(2), 150_001, 0.0).await;
+        assert_eq!(ret.1[0].prepare.amount(), 15);
+
+        let ret = send_between(TestStore::default(), abc(2), abc(0), 99, 0.0).await;
+        let reject = ret.0.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT);
+        assert!(reject.message().starts_with(b"Amount too small"));
+
+        let ret = send_between(TestStore::default(), abc(0), abc(1), std::u64::MAX, 0.0).await;
+        let reject = ret.0.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F08_AMOUNT_TOO_LARGE);
+        assert!(ret.1.is_empty());
+    }
+
+    #[tokio::test]
+    async fn deducts_spread_from_same_asset_exactly() {
+        let abc = || TestAccount::new("ABC".to_owned(), 9);
+        // converting with the rate 0.99 as an f64 would be off by 10
+        let amount = (1 << 60) + 1;
+        let ret = send_between(TestStore::default(), abc(), abc(), amount, 0.01).await;
+        assert_eq!(ret.1[0].prepare.amount(), amount - (amount + 99) / 100);
+
+        let ret = send_between(TestStore::default(), abc(), abc(), std::u64::MAX, 0.0).await;
+        assert_eq!(ret.1[0].prepare.amount(), std::u64::MAX);
+
+        let ret = send_between(TestStore::default(), abc(), abc(), 1000, 1.0).await;
+        assert_eq!(ret.1[0].prepare.amount(), 0);
+
+        // 150 minus 1% is exactly 148.5
+        let deduct =
+            |rounding| send_with_rounding(TestStore::default(), abc(), abc(), 150, 0.01, rounding);
+        let amount = |ret: (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>)| {
+            ret.1[0].prepare.amount()
+        };
+        assert_eq!(amount(deduct(RoundingPolicy::Floor).await), 148);
+        assert_eq!(amount(deduct(RoundingPolicy::Ceil).await), 149);
+        assert_eq!(amount(deduct(RoundingPolicy::RoundHalfUp).await), 149);
+        let ret = deduct(RoundingPolicy::Reject).await;
+        assert_eq!(ret.0.unwrap_err().code(), ErrorCode::F03_INVALID_AMOUNT);
+        assert!(ret.1.is_empty());
+
+        let ret = send_between(TestStore::default(), abc(), abc(), 1, 0.5).await;
+        let reject = ret.0.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT);
+        assert!(reject.message().starts_with(b"Amount too small"));
+    }
+
+    #[tokio::test]
+    async fn does_not_convert_same_asset_and_scale() {
+        // half of the amount would be rounded to 2^63 if it was converted as an f64
+        let abc = || TestAccount::new("ABC".to_owned(), 9);
+        let ret = send_between(TestStore::default(), abc(), abc(), std::u64::MAX, 0.5).await;
+        assert_eq!(ret.1[0].prepare.amount(), std::u64::MAX / 2);
+
+        // spreads outside of 0 and 1 are still applied with the rate
+        let xyz = || TestAccount::new("XYZ".to_owned(), 1);
+        let ret = send_between(TestStore::default(), xyz(), xyz(), 200, -0.01).await;
+        assert_eq!(ret.1[0].prepare.amount(), 202);
+
+        assert_eq!(decimal_spread(0.01), Some((1, 100)));
+        assert_eq!(decimal_spread(0.0), Some((0, 1)));
+        assert_eq!(decimal_spread(1.0), Some((1, 1)));
+        assert_eq!(decimal_spread(0.123), Some((123, 1000)));
+        assert_eq!(decimal_spread(1.5), None);
+        assert_eq!(decimal_spread(1e-50), None);
+    }
+
+    #[tokio::test]
+    async fn carries_fractions_of_the_spread() {
+        let reject = Arc::new(AtomicBool::new(false));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let mut service = carrying_service(TestStore::default(), &reject, &requests);
+        let from = TestAccount::new("ABC".to_owned(), 9).with_spread(0.01);
+        let to = TestAccount::new("ABC".to_owned(), 9);
+        for _ in 0..4 {
+            assert!(service
+                .send_request(outgoing_request(from.clone(), to.clone(), 150))
+                .await
+                .is_ok());
+        }
+
+        let amounts: Vec<u64> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.prepare.amount())
+            .collect();
+        assert_eq!(amounts, vec![148, 149, 148, 149]);
+    }
+
     #[tokio::test]
     async fn applies_spread() {
         let ret = exchange_rate(100, 1, 1.0, 1, 2.0, 0.01).await;
@@ -245,6 +834,335 @@ mod tests {
         assert_eq!(ret.1[0].prepare.amount(), 0);
     }
 
+    #[tokio::test]
+    async fn deducts_flat_fee() {
+        let send = |amount, spread, fee| {
+            let from = TestAccount::new("ABC".to_owned(), 9);
+            let to = TestAccount::new("XYZ".to_owned(), 9);
+            send_with_fee(test_store(1.0, 2.0), from, to, amount, spread, fee)
+        };
+
+        // 1000 / 2 = 500, minus the fee
+        let ret = send(1000, 0.0, 10).await;
+        assert_eq!(ret.1[0].prepare.amount(), 490);
+
+        // The fee is deducted after the spread: 1000 / 2 * 0.9 = 450
+        let ret = send(1000, 0.1, 10).await;
+        assert_eq!(ret.1[0].prepare.amount(), 440);
+
+        // The fee can take the whole amount
+        let ret = send(1000, 0.1, 450).await;
+        assert_eq!(ret.1[0].prepare.amount(), 0);
+
+        let ret = send(1000, 0.1, 451).await;
+        let reject = ret.0.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT);
+        assert!(reject
+            .message()
+            .starts_with(b"Amount too small to cover the fee"));
+        assert!(ret.1.is_empty());
+
+        // Packets between accounts with the same asset are charged too
+        let abc = TestAccount::new("ABC".to_owned(), 9);
+        let ret = send_with_fee(TestStore::default(), abc.clone(), abc, 100, 0.0, 1).await;
+        assert_eq!(ret.1[0].prepare.amount(), 99);
+    }
+
+    #[tokio::test]
+    async fn rounds_with_policy() {
+        let convert = |amount, rounding| {
+            let from = TestAccount::new("ABC".to_owned(), 1);
+            let to = TestAccount::new("XYZ".to_owned(), 1);
+            // the rate is 1:3, so amounts which aren't a multiple of 3 have a fraction
+            send_with_rounding(test_store(1.0, 3.0), from, to, amount, 0.0, rounding)
+        };
+        let amount = |ret: (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>)| {
+            ret.1[0].prepare.amount()
+        };
+
+        // 10 / 3 = 3.33...
+        assert_eq!(amount(convert(10, RoundingPolicy::Floor).await), 3);
+        assert_eq!(amount(convert(10, RoundingPolicy::Ceil).await), 4);
+        assert_eq!(amount(convert(10, RoundingPolicy::RoundHalfUp).await), 3);
+        // 11 / 3 = 3.66...
+        assert_eq!(amount(convert(11, RoundingPolicy::Floor).await), 3);
+        assert_eq!(amount(convert(11, RoundingPolicy::Ceil).await), 4);
+        assert_eq!(amount(convert(11, RoundingPolicy::RoundHalfUp).await), 4);
+        // integers are not rounded
+        assert_eq!(amount(convert(12, RoundingPolicy::Ceil).await), 4);
+        assert_eq!(amount(convert(12, RoundingPolicy::RoundHalfUp).await), 4);
+
+        // amounts which would otherwise be rounded down to 0 are forwarded
+        let ret = convert(1, RoundingPolicy::Floor).await;
+        assert_eq!(
+            ret.0.unwrap_err().code(),
+            ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT
+        );
+        assert_eq!(amount(convert(1, RoundingPolicy::Ceil).await), 1);
+        assert_eq!(amount(convert(2, RoundingPolicy::RoundHalfUp).await), 1);
+
+        // halves are rounded up
+        let from = TestAccount::new("ABC".to_owned(), 1);
+        let to = TestAccount::new("XYZ".to_owned(), 1);
+        let ret = send_with_rounding(
+            test_store(1.0, 2.0),
+            from,
+            to,
+            5,
+            0.0,
+            RoundingPolicy::RoundHalfUp,
+        )
+        .await;
+        assert_eq!(amount(ret), 3);
+    }
+
+    #[tokio::test]
+    async fn rescales_same_asset_with_policy() {
+        let abc = |scale| TestAccount::new("ABC".to_owned(), scale);
+        let rescale = |amount, rounding| {
+            send_with_rounding(TestStore::default(), abc(2), abc(0), amount, 0.0, rounding)
+        };
+        let amount = |ret: (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>)| {
+            ret.1[0].prepare.amount()
+        };
+
+        assert_eq!(amount(rescale(149, RoundingPolicy::Floor).await), 1);
+        assert_eq!(amount(rescale(149, RoundingPolicy::Ceil).await), 2);
+        assert_eq!(amount(rescale(149, RoundingPolicy::RoundHalfUp).await), 1);
+        assert_eq!(amount(rescale(150, RoundingPolicy::RoundHalfUp).await), 2);
+        assert_eq!(amount(rescale(200, RoundingPolicy::Ceil).await), 2);
+        assert_eq!(amount(rescale(1, RoundingPolicy::Ceil).await), 1);
+        let ret = rescale(49, RoundingPolicy::RoundHalfUp).await;
+        assert_eq!(
+            ret.0.unwrap_err().code(),
+            ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT
+        );
+
+        // units larger than a u64 are never reached by rounding half up
+        let ret = send_with_rounding(
+            TestStore::default(),
+            abc(25),
+            abc(0),
+            std::u64::MAX,
+            0.0,
+            RoundingPolicy::RoundHalfUp,
+        )
+        .await;
+        assert!(ret.0.is_err());
+        let ret = send_with_rounding(
+            TestStore::default(),
+            abc(25),
+            abc(0),
+            1,
+            0.0,
+            RoundingPolicy::Ceil,
+        )
+        .await;
+        assert_eq!(amount(ret), 1);
+    }
+
+    #[tokio::test]
+    async fn rescales_uneven_amounts_between_scales_9_and_6() {
+        let abc = |scale| TestAccount::new("ABC".to_owned(), scale);
+        let rescale = |from_scale, to_scale, amount, rounding| {
+            send_with_rounding(
+                TestStore::default(),
+                abc(from_scale),
+                abc(to_scale),
+                amount,
+                0.0,
+                rounding,
+            )
+        };
+        let amount = |ret: (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>)| {
+            ret.1[0].prepare.amount()
+        };
+
+        // 1_000_500 at scale 9 is 1000.5 at scale 6
+        assert_eq!(
+            amount(rescale(9, 6, 1_000_500, RoundingPolicy::Floor).await),
+            1000
+        );
+        assert_eq!(
+            amount(rescale(9, 6, 1_000_500, RoundingPolicy::Ceil).await),
+            1001
+        );
+        assert_eq!(
+            amount(rescale(9, 6, 1_000_500, RoundingPolicy::RoundHalfUp).await),
+            1001
+        );
+        assert_eq!(
+            amount(rescale(9, 6, 1_000_500, RoundingPolicy::Carry).await),
+            1000
+        );
+        let ret = rescale(9, 6, 1_000_500, RoundingPolicy::Reject).await;
+        let reject = ret.0.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F03_INVALID_AMOUNT);
+        assert!(reject
+            .message()
+            .starts_with(b"Amount cannot be represented exactly"));
+        assert!(ret.1.is_empty());
+        assert_eq!(
+            amount(rescale(9, 6, 1_001_000, RoundingPolicy::Reject).await),
+            1001
+        );
+
+        // scaling up never leaves a remainder
+        for rounding in &[
+            RoundingPolicy::Floor,
+            RoundingPolicy::Ceil,
+            RoundingPolicy::Reject,
+            RoundingPolicy::Carry,
+        ] {
+            assert_eq!(amount(rescale(6, 9, 1_001, *rounding).await), 1_001_000);
+        }
+    }
+
+    #[tokio::test]
+    async fn converts_uneven_amounts_between_scales_6_and_9() {
+        let convert = |from_scale, to_scale, amount, rounding| {
+            let from = TestAccount::new("ABC".to_owned(), from_scale);
+            let to = TestAccount::new("XYZ".to_owned(), to_scale);
+            // the rate is 1:1024, which an f64 represents exactly
+            send_with_rounding(test_store(1.0, 1024.0), from, to, amount, 0.0, rounding)
+        };
+        let amount = |ret: (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>)| {
+            ret.1[0].prepare.amount()
+        };
+
+        // 3 at scale 6 is 3000 at scale 9, which converts to 2.9296875
+        assert_eq!(amount(convert(6, 9, 3, RoundingPolicy::Floor).await), 2);
+        assert_eq!(amount(convert(6, 9, 3, RoundingPolicy::Ceil).await), 3);
+        assert_eq!(
+            amount(convert(6, 9, 3, RoundingPolicy::RoundHalfUp).await),
+            3
+        );
+        assert_eq!(amount(convert(6, 9, 3, RoundingPolicy::Carry).await), 2);
+        let ret = convert(6, 9, 3, RoundingPolicy::Reject).await;
+        assert_eq!(ret.0.unwrap_err().code(), ErrorCode::F03_INVALID_AMOUNT);
+        assert!(ret.1.is_empty());
+
+        // 3_000_000 at scale 9 is 3000 at scale 6, which converts to 2.9296875
+        assert_eq!(
+            amount(convert(9, 6, 3_000_000, RoundingPolicy::Floor).await),
+            2
+        );
+        assert_eq!(
+            amount(convert(9, 6, 3_000_000, RoundingPolicy::Ceil).await),
+            3
+        );
+        let ret = convert(9, 6, 3_000_000, RoundingPolicy::Reject).await;
+        assert_eq!(ret.0.unwrap_err().code(), ErrorCode::F03_INVALID_AMOUNT);
+        assert_eq!(
+            amount(convert(9, 6, 1_024_000, RoundingPolicy::Reject).await),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn carries_leftovers_to_the_next_packets() {
+        let reject = Arc::new(AtomicBool::new(false));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let mut service = carrying_service(test_store(1.0, 1024.0), &reject, &requests);
+        let from = TestAccount::new("ABC".to_owned(), 9);
+        let to = TestAccount::new("ABC".to_owned(), 6);
+
+        // the remainders of 1000.5 add up to another unit at scale 6
+        assert!(service
+            .send_request(outgoing_request(from.clone(), to.clone(), 1_000_500))
+            .await
+            .is_ok());
+        reject.store(true, Ordering::SeqCst);
+        // rejected packets leave nothing over, and give back what they took
+        assert!(service
+            .send_request(outgoing_request(from.clone(), to.clone(), 1_000_500))
+            .await
+            .is_err());
+        reject.store(false, Ordering::SeqCst);
+        assert!(service
+            .send_request(outgoing_request(from.clone(), to.clone(), 1_000_500))
+            .await
+            .is_ok());
+        assert!(service
+            .send_request(outgoing_request(from.clone(), to.clone(), 1_000_500))
+            .await
+            .is_ok());
+
+        // fractions of converting with a rate are carried as well: 2.9296875 each
+        let from = TestAccount::new("ABC".to_owned(), 6);
+        let to = TestAccount::new("XYZ".to_owned(), 9);
+        for _ in 0..3 {
+            assert!(service
+                .send_request(outgoing_request(from.clone(), to.clone(), 3))
+                .await
+                .is_ok());
+        }
+
+        let amounts: Vec<u64> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.prepare.amount())
+            .collect();
+        assert_eq!(amounts, vec![1000, 1001, 1001, 1000, 2, 3, 3]);
+    }
+
+    #[tokio::test]
+    async fn applies_account_rounding() {
+        let from = TestAccount::new("ABC".to_owned(), 1).with_rounding(RoundingPolicy::Ceil);
+        let ret = send_from(test_store(1.0, 3.0), from, 10, 1, 0.0).await;
+        // the account's policy overrides the global one
+        assert_eq!(ret.1[0].prepare.amount(), 4);
+
+        let from = TestAccount::new("ABC".to_owned(), 1);
+        let ret = send_from(test_store(1.0, 3.0), from, 10, 1, 0.0).await;
+        assert_eq!(ret.1[0].prepare.amount(), 3);
+    }
+
+    #[tokio::test]
+    async fn applies_account_spread() {
+        let from = TestAccount::new("ABC".to_owned(), 1).with_spread(0.1);
+        let ret = send_from(test_store(1.0, 2.0), from, 200, 1, 0.01).await;
+        // the account's spread overrides the global one
+        assert_eq!(ret.1[0].prepare.amount(), 90);
+
+        // accounts without a spread fall back to the global spread
+        let from = TestAccount::new("ABC".to_owned(), 1);
+        let ret = send_from(test_store(1.0, 2.0), from, 200, 1, 0.01).await;
+        assert_eq!(ret.1[0].prepare.amount(), 99);
+
+        // a spread of 0 on the account also overrides the global spread
+        let from = TestAccount::new("ABC".to_owned(), 1).with_spread(0.0);
+        let ret = send_from(test_store(1.0, 2.0), from, 200, 1, 0.01).await;
+        assert_eq!(ret.1[0].prepare.amount(), 100);
+    }
+
+    #[tokio::test]
+    async fn uses_rates_from_custom_provider() {
+        struct FixedRates;
+
+        #[async_trait]
+        impl RateProvider for FixedRates {
+            async fn get_rates(&self) -> Result<HashMap<String, f64>, ()> {
+                let mut rates = HashMap::new();
+                rates.insert("ABC".to_owned(), 1.0);
+                rates.insert("XYZ".to_owned(), 2.0);
+                Ok(rates)
+            }
+        }
+
+        let store = TestStore::default();
+        let ret = send_with_store(store.clone(), 200, 1, 1, 0.0).await;
+        assert_eq!(ret.0.unwrap_err().code(), ErrorCode::T00_INTERNAL_ERROR);
+
+        ExchangeRateFetcher::new(Arc::new(FixedRates), 5, store.clone())
+            .update_rates()
+            .await
+            .unwrap();
+        let ret = send_with_store(store, 200, 1, 1, 0.0).await;
+        assert_eq!(ret.1[0].prepare.amount(), 100);
+    }
+
     // Instantiates an exchange rate service and returns the fulfill/reject
     // packet and the outgoing request after performing an asset conversion
     async fn exchange_rate(
@@ -254,6 +1172,49 @@ mod tests {
         scale2: u8,
         rate2: f64,
         spread: f64,
+    ) -> (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>) {
+        send_with_store(test_store(rate1, rate2), amount, scale1, scale2, spread).await
+    }
+
+    async fn send_with_store(
+        store: TestStore,
+        amount: u64,
+        scale1: u8,
+        scale2: u8,
+        spread: f64,
+    ) -> (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>) {
+        let from = TestAccount::new("ABC".to_owned(), scale1);
+        send_from(store, from, amount, scale2, spread).await
+    }
+
+    async fn send_from(
+        store: TestStore,
+        from: TestAccount,
+        amount: u64,
+        scale2: u8,
+        spread: f64,
+    ) -> (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>) {
+        let to = TestAccount::new("XYZ".to_owned(), scale2);
+        send_between(store, from, to, amount, spread).await
+    }
+
+    async fn send_between(
+        store: TestStore,
+        from: TestAccount,
+        to: TestAccount,
+        amount: u64,
+        spread: f64,
+    ) -> (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>) {
+        send_with_rounding(store, from, to, amount, spread, RoundingPolicy::default()).await
+    }
+
+    async fn send_with_rounding(
+        store: TestStore,
+        from: TestAccount,
+        to: TestAccount,
+        amount: u64,
+        spread: f64,
+        rounding: RoundingPolicy,
     ) -> (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>) {
         let requests = Arc::new(Mutex::new(Vec::new()));
         let requests_clone = requests.clone();
@@ -265,41 +1226,135 @@ mod tests {
             }
             .build())
         });
-        let mut service = test_service(rate1, rate2, spread, outgoing);
+        let mut service = ExchangeRateService::new(spread, store, outgoing);
+        service.rounding(rounding);
         let result = service
-            .send_request(OutgoingRequest {
-                from: TestAccount::new("ABC".to_owned(), scale1),
-                to: TestAccount::new("XYZ".to_owned(), scale2),
-                original_amount: amount,
-                prepare: PrepareBuilder {
-                    destination: Address::from_str("example.destination").unwrap(),
-                    amount,
-                    expires_at: SystemTime::now(),
-                    execution_condition: &[1; 32],
-                    data: b"hello",
-                }
-                .build(),
-            })
+            .send_request(outgoing_request(from, to, amount))
             .await;
 
         let reqs = requests.lock().unwrap();
         (result, reqs.clone())
     }
 
+    async fn send_with_fee(
+        store: TestStore,
+        from: TestAccount,
+        to: TestAccount,
+        amount: u64,
+        spread: f64,
+        fee: u64,
+    ) -> (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = requests.clone();
+        let outgoing = outgoing_service_fn(move |request| {
+            requests_clone.lock().unwrap().push(request);
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"hello!",
+            }
+            .build())
+        });
+        let mut service = ExchangeRateService::new(spread, store, outgoing);
+        service.flat_fee(fee);
+        let result = service
+            .send_request(outgoing_request(from, to, amount))
+            .await;
+
+        let reqs = requests.lock().unwrap();
+        (result, reqs.clone())
+    }
+
+    /// Instantiates an exchange rate service with the `Carry` policy, which records the
+    /// requests it forwards and rejects them while `reject` is set
+    fn carrying_service(
+        store: TestStore,
+        reject: &Arc<AtomicBool>,
+        requests: &Arc<Mutex<Vec<OutgoingRequest<TestAccount>>>>,
+    ) -> impl OutgoingService<TestAccount> {
+        let reject = reject.clone();
+        let requests = requests.clone();
+        let outgoing = outgoing_service_fn(move |request| {
+            requests.lock().unwrap().push(request);
+            if reject.load(Ordering::SeqCst) {
+                return Err(RejectBuilder {
+                    code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                    message: &[],
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build());
+            }
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"hello!",
+            }
+            .build())
+        });
+        let mut service = ExchangeRateService::new(0.0, store, outgoing);
+        service.rounding(RoundingPolicy::Carry);
+        service
+    }
+
+    fn outgoing_request(
+        from: TestAccount,
+        to: TestAccount,
+        amount: u64,
+    ) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from,
+            to,
+            original_amount: amount,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: SystemTime::now(),
+                execution_condition: &[1; 32],
+                data: b"hello",
+            }
+            .build(),
+        }
+    }
+
     #[derive(Debug, Clone)]
     struct TestAccount {
+        id: Uuid,
         ilp_address: Address,
         asset_code: String,
         asset_scale: u8,
+        spread: Option<f64>,
+        rounding: Option<RoundingPolicy>,
     }
     impl TestAccount {
         fn new(asset_code: String, asset_scale: u8) -> Self {
             TestAccount {
+                id: Uuid::new_v4(),
                 ilp_address: Address::from_str("example.alice").unwrap(),
                 asset_code,
                 asset_scale,
+                spread: None,
+                rounding: None,
             }
         }
+
+        fn with_spread(mut self, spread: f64) -> Self {
+            self.spread = Some(spread);
+            self
+        }
+
+        fn with_rounding(mut self, rounding: RoundingPolicy) -> Self {
+            self.rounding = Some(rounding);
+            self
+        }
+    }
+
+    impl SpreadAccount for TestAccount {
+        fn spread(&self) -> Option<f64> {
+            self.spread
+        }
+
+        fn rounding(&self) -> Option<RoundingPolicy> {
+            self.rounding
+        }
     }
 
     #[async_trait]
@@ -321,7 +1376,7 @@ mod tests {
 
     impl Account for TestAccount {
         fn id(&self) -> Uuid {
-            Uuid::new_v4()
+            self.id
         }
 
         fn username(&self) -> &Username {
@@ -341,9 +1396,9 @@ mod tests {
         }
     }
 
-    #[derive(Debug, Clone)]
+    #[derive(Debug, Clone, Default)]
     struct TestStore {
-        rates: HashMap<Vec<String>, (f64, f64)>,
+        rates: Arc<Mutex<HashMap<String, f64>>>,
     }
 
     impl ExchangeRateStore for TestStore {
@@ -351,26 +1406,23 @@ mod tests {
             &self,
             asset_codes: &[&str],
         ) -> Result<Vec<f64>, ExchangeRateStoreError> {
-            let mut ret = Vec::new();
-            let key = vec![asset_codes[0].to_owned(), asset_codes[1].to_owned()];
-            let v = self.rates.get(&key);
-            if let Some(v) = v {
-                ret.push(v.0);
-                ret.push(v.1);
-            } else {
-                return Err(ExchangeRateStoreError::PairNotFound {
-                    from: key[0].clone(),
-                    to: key[1].clone(),
-                });
-            }
-            Ok(ret)
+            let rates = self.rates.lock().unwrap();
+            asset_codes
+                .iter()
+                .map(|code| rates.get(*code).cloned())
+                .collect::<Option<Vec<f64>>>()
+                .ok_or_else(|| ExchangeRateStoreError::PairNotFound {
+                    from: asset_codes[0].to_owned(),
+                    to: asset_codes[1].to_owned(),
+                })
         }
 
         fn set_exchange_rates(
             &self,
-            _rates: HashMap<String, f64>,
+            rates: HashMap<String, f64>,
         ) -> Result<(), ExchangeRateStoreError> {
-            unimplemented!()
+            *self.rates.lock().unwrap() = rates;
+            Ok(())
         }
 
         fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
@@ -379,22 +1431,12 @@ mod tests {
     }
 
     fn test_store(rate1: f64, rate2: f64) -> TestStore {
-        let mut rates = HashMap::new();
-        rates.insert(vec!["ABC".to_owned(), "XYZ".to_owned()], (rate1, rate2));
-        TestStore { rates }
-    }
-
-    fn test_service(
-        rate1: f64,
-        rate2: f64,
-        spread: f64,
-        handler: impl OutgoingService<TestAccount> + Clone + Send + Sync,
-    ) -> ExchangeRateService<
-        TestStore,
-        impl OutgoingService<TestAccount> + Clone + Send + Sync,
-        TestAccount,
-    > {
-        let store = test_store(rate1, rate2);
-        ExchangeRateService::new(spread, store, handler)
+        let store = TestStore::default();
+        {
+            let mut rates = store.rates.lock().unwrap();
+            rates.insert("ABC".to_owned(), rate1);
+            rates.insert("XYZ".to_owned(), rate2);
+        }
+        store
     }
 }
@@ -1,7 +1,8 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
-use interledger_service::{Account, IlpResult, OutgoingRequest, OutgoingService};
-use log::trace;
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::{Account, AddressStore, IlpResult, OutgoingRequest, OutgoingService};
+use log::{debug, trace};
 
 pub const DEFAULT_ROUND_TRIP_TIME: u32 = 500;
 pub const DEFAULT_MAX_EXPIRY_DURATION: u32 = 30000;
@@ -22,18 +23,25 @@ pub trait RoundTripTimeAccount: Account {
 /// they will still have enough time to pass the fulfillment to the previous node before it expires.
 ///
 /// This service reduces the expiry time of each packet before forwarding it out.
-/// Requires a `RoundtripTimeAccount` and _no store_
+/// On top of the accounts' round trip times, it can reserve a minimum hold time for the node
+/// itself, and rejects packets with `R00_TRANSFER_TIMED_OUT` if the time left after
+/// shortening would not be enough to forward them.
+/// Requires a `RoundtripTimeAccount` and an `AddressStore` (for the `triggered_by` field of the rejection).
 #[derive(Clone)]
-pub struct ExpiryShortenerService<O> {
+pub struct ExpiryShortenerService<S, O> {
+    store: S,
     next: O,
     max_expiry_duration: u32,
+    min_hold_time: u32,
 }
 
-impl<O> ExpiryShortenerService<O> {
-    pub fn new(next: O) -> Self {
+impl<S, O> ExpiryShortenerService<S, O> {
+    pub fn new(store: S, next: O) -> Self {
         ExpiryShortenerService {
+            store,
             next,
             max_expiry_duration: DEFAULT_MAX_EXPIRY_DURATION,
+            min_hold_time: 0,
         }
     }
 
@@ -43,27 +51,54 @@ impl<O> ExpiryShortenerService<O> {
         self.max_expiry_duration = milliseconds;
         self
     }
+
+    /// Sets the time, in milliseconds, the node reserves for itself to pass the fulfillment
+    /// back to the previous node. Each outgoing packet's expiry is reduced by this amount on top
+    /// of the accounts' round trip times (default 0)
+    pub fn min_hold_time(&mut self, milliseconds: u32) -> &mut Self {
+        self.min_hold_time = milliseconds;
+        self
+    }
 }
 
 #[async_trait]
-impl<O, A> OutgoingService<A> for ExpiryShortenerService<O>
+impl<S, O, A> OutgoingService<A> for ExpiryShortenerService<S, O>
 where
+    S: AddressStore + Send + Sync + 'static,
     O: OutgoingService<A> + Send + Sync + 'static,
     A: RoundTripTimeAccount + Send + Sync + 'static,
 {
     /// On send request:
     /// 1. Get the sender and receiver's roundtrip time (default 1000ms)
-    /// 2. Reduce the packet's expiry by that amount
-    /// 3. Ensure that the packet expiry does not exceed the maximum expiry duration
-    /// 4. Forward the request
+    /// 2. Reduce the packet's expiry by that amount and the minimum hold time
+    /// 3. Reject the request if the shortened expiry has already passed
+    /// 4. Ensure that the packet expiry does not exceed the maximum expiry duration
+    /// 5. Forward the request
     async fn send_request(&mut self, mut request: OutgoingRequest<A>) -> IlpResult {
-        let time_to_subtract =
-            i64::from(request.from.round_trip_time() + request.to.round_trip_time());
+        let time_to_subtract = i64::from(request.from.round_trip_time())
+            + i64::from(request.to.round_trip_time())
+            + i64::from(self.min_hold_time);
         let new_expiry = DateTime::<Utc>::from(request.prepare.expires_at())
             - Duration::milliseconds(time_to_subtract);
 
+        let now = Utc::now();
+        if new_expiry <= now {
+            debug!(
+                "Not enough time left to forward packet: it expires in {}ms but {}ms are reserved",
+                (DateTime::<Utc>::from(request.prepare.expires_at()) - now).num_milliseconds(),
+                time_to_subtract,
+            );
+            return Err(RejectBuilder {
+                code: ErrorCode::R00_TRANSFER_TIMED_OUT,
+                message: b"Not enough time left to forward the packet",
+                triggered_by: Some(&self.store.get_ilp_address()),
+                data: &[],
+            }
+            .build());
+        }
+
         let latest_allowable_expiry =
-            Utc::now() + Duration::milliseconds(i64::from(self.max_expiry_duration));
+            now + Duration::milliseconds(i64::from(self.max_expiry_duration));
         let new_expiry = if new_expiry > latest_allowable_expiry {
             trace!(
                 "Shortening packet expiry duration to {}ms in the future",
@@ -82,7 +117,8 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use interledger_packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder, RejectBuilder};
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
     use interledger_service::{outgoing_service_fn, Username};
     use std::str::FromStr;
     use uuid::Uuid;
@@ -124,28 +160,49 @@ mod tests {
         }
     }
 
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
     #[tokio::test]
     async fn shortens_expiry_by_round_trip_time() {
         let original_expiry = Utc::now() + Duration::milliseconds(30000);
-        let mut service = ExpiryShortenerService::new(outgoing_service_fn(move |request| {
-            if DateTime::<Utc>::from(request.prepare.expires_at())
-                == original_expiry - Duration::milliseconds(1300)
-            {
-                Ok(FulfillBuilder {
-                    fulfillment: &[0; 32],
-                    data: &[],
-                }
-                .build())
-            } else {
-                Err(RejectBuilder {
-                    code: ErrorCode::F00_BAD_REQUEST,
-                    message: &[],
-                    data: &[],
-                    triggered_by: None,
+        let mut service = ExpiryShortenerService::new(
+            TestStore,
+            outgoing_service_fn(move |request| {
+                if DateTime::<Utc>::from(request.prepare.expires_at())
+                    == original_expiry - Duration::milliseconds(1300)
+                {
+                    Ok(FulfillBuilder {
+                        fulfillment: &[0; 32],
+                        data: &[],
+                    }
+                    .build())
+                } else {
+                    Err(RejectBuilder {
+                        code: ErrorCode::F00_BAD_REQUEST,
+                        message: &[],
+                        data: &[],
+                        triggered_by: None,
+                    }
+                    .build())
                 }
-                .build())
-            }
-        }));
+            }),
+        );
         service
             .send_request(OutgoingRequest {
                 from: TestAccount(Uuid::new_v4(), 600),
@@ -166,25 +223,28 @@ mod tests {
 
     #[tokio::test]
     async fn reduces_expiry_to_max_duration() {
-        let mut service = ExpiryShortenerService::new(outgoing_service_fn(move |request| {
-            if DateTime::<Utc>::from(request.prepare.expires_at()) - Utc::now()
-                <= Duration::milliseconds(30000)
-            {
-                Ok(FulfillBuilder {
-                    fulfillment: &[0; 32],
-                    data: &[],
-                }
-                .build())
-            } else {
-                Err(RejectBuilder {
-                    code: ErrorCode::F00_BAD_REQUEST,
-                    message: &[],
-                    data: &[],
-                    triggered_by: None,
+        let mut service = ExpiryShortenerService::new(
+            TestStore,
+            outgoing_service_fn(move |request| {
+                if DateTime::<Utc>::from(request.prepare.expires_at()) - Utc::now()
+                    <= Duration::milliseconds(30000)
+                {
+                    Ok(FulfillBuilder {
+                        fulfillment: &[0; 32],
+                        data: &[],
+                    }
+                    .build())
+                } else {
+                    Err(RejectBuilder {
+                        code: ErrorCode::F00_BAD_REQUEST,
+                        message: &[],
+                        data: &[],
+                        triggered_by: None,
+                    }
+                    .build())
                 }
-                .build())
-            }
-        }));
+            }),
+        );
         service
             .send_request(OutgoingRequest {
                 from: TestAccount(Uuid::new_v4(), 500),
@@ -202,4 +262,80 @@ mod tests {
             .await
             .expect("Should have shortened expiry");
     }
+
+    #[tokio::test]
+    async fn shortens_expiry_by_min_hold_time() {
+        let original_expiry = Utc::now() + Duration::milliseconds(30000);
+        let mut service = ExpiryShortenerService::new(
+            TestStore,
+            outgoing_service_fn(move |request| {
+                if DateTime::<Utc>::from(request.prepare.expires_at())
+                    == original_expiry - Duration::milliseconds(3300)
+                {
+                    Ok(FulfillBuilder {
+                        fulfillment: &[0; 32],
+                        data: &[],
+                    }
+                    .build())
+                } else {
+                    Err(RejectBuilder {
+                        code: ErrorCode::F00_BAD_REQUEST,
+                        message: &[],
+                        data: &[],
+                        triggered_by: None,
+                    }
+                    .build())
+                }
+            }),
+        );
+        service.min_hold_time(2000);
+        service
+            .send_request(OutgoingRequest {
+                from: TestAccount(Uuid::new_v4(), 600),
+                to: TestAccount(Uuid::new_v4(), 700),
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount: 10,
+                    expires_at: original_expiry.into(),
+                    data: &[],
+                    execution_condition: &[0; 32],
+                }
+                .build(),
+                original_amount: 10,
+            })
+            .await
+            .expect("Should have shortened expiry");
+    }
+
+    #[tokio::test]
+    async fn rejects_if_not_enough_time_left() {
+        let mut service = ExpiryShortenerService::new(
+            TestStore,
+            outgoing_service_fn(move |_| -> IlpResult {
+                panic!("Should not have forwarded the packet");
+            }),
+        );
+        service.min_hold_time(2000);
+        let reject = service
+            .send_request(OutgoingRequest {
+                from: TestAccount(Uuid::new_v4(), 500),
+                to: TestAccount(Uuid::new_v4(), 500),
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount: 10,
+                    expires_at: (Utc::now() + Duration::milliseconds(2500)).into(),
+                    data: &[],
+                    execution_condition: &[0; 32],
+                }
+                .build(),
+                original_amount: 10,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R00_TRANSFER_TIMED_OUT);
+        assert_eq!(
+            reject.triggered_by(),
+            Some(Address::from_str("example.connector").unwrap())
+        );
+    }
 }
@@ -0,0 +1,389 @@
+use crate::rate_limit_service::{RateLimitAccount, RateLimitError, RateLimitStore};
+use async_trait::async_trait;
+use interledger_errors::AddressStoreError;
+use interledger_packet::Address;
+use interledger_service::AddressStore;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// The window over which the account's limits are refilled
+const REFILL_WINDOW: Duration = Duration::from_secs(60);
+
+/// A token bucket which holds at most `capacity` tokens and refills
+/// `capacity` tokens evenly over the course of the refill window
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket
+    fn new(capacity: u64, now: Instant) -> Self {
+        TokenBucket {
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            last_refill: now,
+        }
+    }
+
+    /// Adds the tokens accrued since the last refill. If the account's limit was
+    /// changed in the meantime, the bucket is resized to the new capacity
+    fn refill(&mut self, capacity: u64, now: Instant) {
+        self.capacity = capacity as f64;
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        let accrued = elapsed.as_secs_f64() / REFILL_WINDOW.as_secs_f64() * self.capacity;
+        self.tokens = (self.tokens + accrued).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    fn has(&self, amount: u64) -> bool {
+        self.tokens >= amount as f64
+    }
+
+    fn take(&mut self, amount: u64) {
+        self.tokens -= amount as f64;
+    }
+
+    fn give_back(&mut self, amount: u64) {
+        self.tokens = (self.tokens + amount as f64).min(self.capacity);
+    }
+}
+
+#[derive(Debug, Default)]
+struct AccountBuckets {
+    packets: Option<TokenBucket>,
+    amount: Option<TokenBucket>,
+}
+
+/// # In-Memory Rate Limit Store
+///
+/// A [`RateLimitStore`](./trait.RateLimitStore.html) which keeps a token bucket per account
+/// in the node's memory and delegates everything related to the node's ILP address
+/// to the wrapped store. Accounts without any limits configured are never limited.
+///
+/// This is useful for stores which cannot enforce rate limits themselves and for
+/// single-instance or ephemeral nodes.
+///
+/// **Caveat:** the buckets are not shared between processes, so if the node is horizontally
+/// scaled every instance enforces the limits independently (i.e. an account may send up to
+/// `instances * limit` per minute). Use a store-backed implementation such as the
+/// Redis store's if the limits must be consistent across a deployment.
+#[derive(Clone)]
+pub struct InMemoryRateLimitStore<S, A> {
+    store: S,
+    buckets: Arc<Mutex<HashMap<Uuid, AccountBuckets>>>,
+    account_type: PhantomData<A>,
+}
+
+impl<S, A> InMemoryRateLimitStore<S, A> {
+    /// Simple constructor
+    pub fn new(store: S) -> Self {
+        InMemoryRateLimitStore {
+            store,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            account_type: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<S, A> AddressStore for InMemoryRateLimitStore<S, A>
+where
+    S: AddressStore + Send + Sync,
+    A: Send + Sync,
+{
+    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        self.store.set_ilp_address(ilp_address).await
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        self.store.clear_ilp_address().await
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        self.store.get_ilp_address()
+    }
+}
+
+#[async_trait]
+impl<S, A> RateLimitStore for InMemoryRateLimitStore<S, A>
+where
+    S: Send + Sync,
+    A: RateLimitAccount + Sync + 'static,
+{
+    type Account = A;
+
+    /// Takes one token from the account's packet bucket and `prepare_amount`
+    /// tokens from its amount bucket. Nothing is taken unless both buckets
+    /// have enough tokens left.
+    async fn apply_rate_limits(
+        &self,
+        account: Self::Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        let packets_limit = account.packets_per_minute_limit();
+        let amount_limit = account.amount_per_minute_limit();
+        if packets_limit.is_none() && amount_limit.is_none() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+        let buckets = buckets.entry(account.id()).or_default();
+
+        if let Some(limit) = packets_limit {
+            let bucket = buckets
+                .packets
+                .get_or_insert_with(|| TokenBucket::new(u64::from(limit), now));
+            bucket.refill(u64::from(limit), now);
+            if !bucket.has(1) {
+                return Err(RateLimitError::PacketLimitExceeded);
+            }
+        } else {
+            buckets.packets = None;
+        }
+
+        if let Some(limit) = amount_limit {
+            let bucket = buckets
+                .amount
+                .get_or_insert_with(|| TokenBucket::new(limit, now));
+            bucket.refill(limit, now);
+            if !bucket.has(prepare_amount) {
+                return Err(RateLimitError::ThroughputLimitExceeded);
+            }
+        } else {
+            buckets.amount = None;
+        }
+
+        if let Some(ref mut bucket) = buckets.packets {
+            bucket.take(1);
+        }
+        if let Some(ref mut bucket) = buckets.amount {
+            bucket.take(prepare_amount);
+        }
+        Ok(())
+    }
+
+    async fn refund_throughput_limit(
+        &self,
+        account: Self::Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        if let Some(bucket) = self
+            .buckets
+            .lock()
+            .get_mut(&account.id())
+            .and_then(|buckets| buckets.amount.as_mut())
+        {
+            bucket.give_back(prepare_amount);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RateLimitService;
+    use interledger_packet::{ErrorCode, FulfillBuilder, PrepareBuilder};
+    use interledger_service::{
+        incoming_service_fn, Account, IncomingRequest, IncomingService, Username,
+    };
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+
+    #[test]
+    fn token_bucket_refills_over_time() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(60, start);
+        bucket.take(60);
+        assert!(!bucket.has(1));
+
+        bucket.refill(60, start + Duration::from_secs(30));
+        assert!(bucket.has(30));
+        assert!(!bucket.has(31));
+
+        // never refills past the capacity
+        bucket.refill(60, start + Duration::from_secs(600));
+        assert!(bucket.has(60));
+        assert!(!bucket.has(61));
+    }
+
+    #[tokio::test]
+    async fn burst_within_packet_limit_passes() {
+        let mut service = test_service();
+        let account = TestAccount::new(Some(3), None);
+        for _ in 0..3 {
+            assert!(service
+                .handle_request(test_request(account.clone(), 10))
+                .await
+                .is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn burst_beyond_packet_limit_is_rate_limited() {
+        let mut service = test_service();
+        let account = TestAccount::new(Some(3), None);
+        for _ in 0..3 {
+            assert!(service
+                .handle_request(test_request(account.clone(), 10))
+                .await
+                .is_ok());
+        }
+        let reject = service
+            .handle_request(test_request(account.clone(), 10))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T05_RATE_LIMITED);
+
+        // other accounts are not affected
+        let other = TestAccount::new(Some(3), None);
+        assert!(service
+            .handle_request(test_request(other, 10))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn burst_beyond_amount_limit_is_throughput_limited() {
+        let mut service = test_service();
+        let account = TestAccount::new(None, Some(100));
+        assert!(service
+            .handle_request(test_request(account.clone(), 60))
+            .await
+            .is_ok());
+        let reject = service
+            .handle_request(test_request(account.clone(), 60))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T04_INSUFFICIENT_LIQUIDITY);
+        assert!(service
+            .handle_request(test_request(account.clone(), 40))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn unlimited_by_default() {
+        let mut service = test_service();
+        let account = TestAccount::new(None, None);
+        for _ in 0..100 {
+            assert!(service
+                .handle_request(test_request(account.clone(), u64::max_value()))
+                .await
+                .is_ok());
+        }
+    }
+
+    fn test_service() -> RateLimitService<
+        InMemoryRateLimitStore<TestStore, TestAccount>,
+        impl IncomingService<TestAccount> + Clone,
+        TestAccount,
+    > {
+        let next = incoming_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        RateLimitService::new(InMemoryRateLimitStore::new(TestStore), next)
+    }
+
+    fn test_request(account: TestAccount, amount: u64) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: account,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: std::time::SystemTime::now() + std::time::Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: b"test data",
+            }
+            .build(),
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestAccount {
+        id: Uuid,
+        packets_per_minute_limit: Option<u32>,
+        amount_per_minute_limit: Option<u64>,
+    }
+
+    impl TestAccount {
+        fn new(
+            packets_per_minute_limit: Option<u32>,
+            amount_per_minute_limit: Option<u64>,
+        ) -> Self {
+            TestAccount {
+                id: Uuid::new_v4(),
+                packets_per_minute_limit,
+                amount_per_minute_limit,
+            }
+        }
+    }
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.id
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    impl RateLimitAccount for TestAccount {
+        fn packets_per_minute_limit(&self) -> Option<u32> {
+            self.packets_per_minute_limit
+        }
+
+        fn amount_per_minute_limit(&self) -> Option<u64> {
+            self.amount_per_minute_limit
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+}
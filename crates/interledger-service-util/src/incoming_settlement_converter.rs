@@ -0,0 +1,202 @@
+use super::{convert_incoming_settlement, BalanceStore};
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::StatusCode;
+use interledger_errors::{
+    AccountStoreError, IdempotentStoreError, LeftoversStoreError, SettlementStoreError,
+};
+use interledger_rates::ExchangeRateStore;
+use interledger_service::{Account, AccountStore, Username};
+use interledger_settlement::core::{
+    idempotency::{IdempotentData, IdempotentStore},
+    types::{LeftoversStore, SettlementAccount, SettlementStore},
+};
+use log::{debug, error};
+use uuid::Uuid;
+
+/// # Incoming Settlement Converter
+///
+/// Wraps the store given to the [settlement API](../interledger_settlement/api/fn.create_settlements_filter.html)
+/// so that the incoming settlements of accounts which settle in a different asset are converted
+/// into the account's asset with [`convert_incoming_settlement`](./fn.convert_incoming_settlement.html)
+/// before they are credited, and the conversion is saved like the ones of outgoing settlements.
+///
+/// If no exchange rate is available, the settlement is not credited and the settlement
+/// engine's request fails. Everything else is forwarded to the wrapped store.
+#[derive(Clone)]
+pub struct IncomingSettlementConverter<S> {
+    store: S,
+}
+
+impl<S> IncomingSettlementConverter<S> {
+    /// Wraps the store, which has to hold the exchange rates and save the conversions
+    pub fn new(store: S) -> Self {
+        IncomingSettlementConverter { store }
+    }
+}
+
+#[async_trait]
+impl<S, A> SettlementStore for IncomingSettlementConverter<S>
+where
+    S: SettlementStore<Account = A>
+        + AccountStore<Account = A>
+        + BalanceStore
+        + ExchangeRateStore
+        + Send
+        + Sync,
+    A: SettlementAccount + Send + Sync + 'static,
+{
+    type Account = A;
+
+    async fn update_balance_for_incoming_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: Option<String>,
+    ) -> Result<(), SettlementStoreError> {
+        let mut accounts = self
+            .store
+            .get_accounts(vec![account_id])
+            .await
+            .map_err(|err| SettlementStoreError::Other(Box::new(err)))?;
+        let account = accounts
+            .pop()
+            .ok_or(SettlementStoreError::BalanceUpdateFailure)?;
+        let conversion = convert_incoming_settlement(&self.store, &account, amount)
+            .map_err(|_| SettlementStoreError::BalanceUpdateFailure)?;
+        let credited_amount = conversion
+            .as_ref()
+            .map(|conversion| conversion.amount)
+            .unwrap_or(amount);
+
+        self.store
+            .update_balance_for_incoming_settlement(account_id, credited_amount, idempotency_key)
+            .await?;
+        if let Some(conversion) = conversion {
+            debug!(
+                "Received settlement of {} {} from account {} as {} at rate {}",
+                conversion.settlement_amount,
+                conversion.settlement_asset_code,
+                account_id,
+                conversion.amount,
+                conversion.rate
+            );
+            if let Err(err) = self
+                .store
+                .save_settlement_conversion(account_id, conversion)
+                .await
+            {
+                // The balance was already credited, so the settlement must not fail now
+                error!(
+                    "Error saving settlement conversion for account {}: {}",
+                    account_id, err
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn refund_settlement(
+        &self,
+        account_id: Uuid,
+        settle_amount: u64,
+    ) -> Result<(), SettlementStoreError> {
+        self.store
+            .refund_settlement(account_id, settle_amount)
+            .await
+    }
+}
+
+#[async_trait]
+impl<S, A> AccountStore for IncomingSettlementConverter<S>
+where
+    S: AccountStore<Account = A> + Send + Sync,
+    A: Account + 'static,
+{
+    type Account = A;
+
+    async fn get_accounts(&self, account_ids: Vec<Uuid>) -> Result<Vec<A>, AccountStoreError> {
+        self.store.get_accounts(account_ids).await
+    }
+
+    async fn get_account_id_from_username(
+        &self,
+        username: &Username,
+    ) -> Result<Uuid, AccountStoreError> {
+        self.store.get_account_id_from_username(username).await
+    }
+}
+
+#[async_trait]
+impl<S> LeftoversStore for IncomingSettlementConverter<S>
+where
+    S: LeftoversStore + Send + Sync,
+    S::AccountId: Send + 'static,
+    S::AssetType: Send + 'static,
+{
+    type AccountId = S::AccountId;
+    type AssetType = S::AssetType;
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+        uncredited_settlement_amount: (Self::AssetType, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        self.store
+            .save_uncredited_settlement_amount(account_id, uncredited_settlement_amount)
+            .await
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+        local_scale: u8,
+    ) -> Result<Self::AssetType, LeftoversStoreError> {
+        self.store
+            .load_uncredited_settlement_amount(account_id, local_scale)
+            .await
+    }
+
+    async fn clear_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+    ) -> Result<(), LeftoversStoreError> {
+        self.store
+            .clear_uncredited_settlement_amount(account_id)
+            .await
+    }
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        self.store
+            .get_uncredited_settlement_amount(account_id)
+            .await
+    }
+}
+
+#[async_trait]
+impl<S> IdempotentStore for IncomingSettlementConverter<S>
+where
+    S: IdempotentStore + Send + Sync,
+{
+    async fn load_idempotent_data(
+        &self,
+        idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        self.store.load_idempotent_data(idempotency_key).await
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        idempotency_key: String,
+        input_hash: [u8; 32],
+        status_code: StatusCode,
+        data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        self.store
+            .save_idempotent_data(idempotency_key, input_hash, status_code, data)
+            .await
+    }
+}
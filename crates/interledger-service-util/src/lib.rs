@@ -4,6 +4,10 @@
 
 /// Balance tracking service
 mod balance_service;
+/// Service responsible for capping the number of packets in flight at the same time
+mod concurrency_limit_service;
+/// Service responsible for restricting which destinations an account may send packets to
+mod destination_allowlist_service;
 /// Service which implements the echo protocol
 mod echo_service;
 /// Service responsible for setting and fetching dollar denominated exchange rates
@@ -11,22 +15,65 @@ mod exchange_rates_service;
 /// Service responsible for shortening the expiry time of packets,
 /// to take into account for network latency
 mod expiry_shortener_service;
+/// In-memory store for enforcing the rate limits of accounts
+mod in_memory_rate_limit_store;
+/// Store wrapper converting incoming settlements from the accounts' settlement assets
+mod incoming_settlement_converter;
+/// Service responsible for rejecting new packets while the node is in maintenance mode
+mod maintenance_mode_service;
 /// Service responsible for capping the amount an account can send in a packet
 mod max_packet_amount_service;
+/// Service responsible for capping the number of requests in flight to each account, queueing the others
+mod outgoing_concurrency_limit_service;
+/// Service responsible for handling the packets addressed to the node's own address
+mod own_address_service;
+/// Service which publishes the outcome of every forwarded packet to a broadcast channel
+mod packet_events_service;
+mod peer_activity_service;
 /// Service responsible for capping the amount of packets and amount in packets an account can send
 mod rate_limit_service;
+/// Service responsible for rejecting packets whose execution condition was already fulfilled
+mod replay_protection_service;
+/// Service responsible for mirroring the packets sent to accounts to their shadow accounts
+mod shadow_service;
+/// Service responsible for counting the packets, bytes and amounts exchanged with each account
+mod traffic_counters_service;
 /// Service responsible for checking that packets are not expired and that prepare packets' fulfillment conditions
 /// match the fulfillment inside the incoming fulfills
 mod validator_service;
+/// Service responsible for alerting when the amount sent to an account within a window crosses its threshold
+mod volume_alert_service;
 
-pub use self::balance_service::{BalanceService, BalanceStore};
-pub use self::echo_service::EchoService;
-pub use self::exchange_rates_service::ExchangeRateService;
+pub use self::balance_service::{
+    convert_incoming_settlement, convert_settlement, BalancePause, BalanceService, BalanceStore,
+    LastSettlement, SettlementConversion, SettlementDirection, DEFAULT_MAX_BALANCE_PAUSE,
+};
+pub use self::concurrency_limit_service::ConcurrencyLimitService;
+pub use self::destination_allowlist_service::{
+    DestinationAllowlistAccount, DestinationAllowlistService,
+};
+pub use self::echo_service::{EchoRequestBuilder, EchoService};
+pub use self::exchange_rates_service::{ExchangeRateService, RoundingPolicy, SpreadAccount};
 pub use self::expiry_shortener_service::{
     ExpiryShortenerService, RoundTripTimeAccount, DEFAULT_ROUND_TRIP_TIME,
 };
+pub use self::in_memory_rate_limit_store::InMemoryRateLimitStore;
+pub use self::incoming_settlement_converter::IncomingSettlementConverter;
+pub use self::maintenance_mode_service::{MaintenanceMode, MaintenanceModeService};
 pub use self::max_packet_amount_service::{MaxPacketAmountAccount, MaxPacketAmountService};
+pub use self::outgoing_concurrency_limit_service::{
+    OutgoingConcurrencyAccount, OutgoingConcurrencyLimitService, DEFAULT_MAX_QUEUED_PER_ACCOUNT,
+};
+pub use self::own_address_service::{OwnAddressPolicy, OwnAddressService};
+pub use self::packet_events_service::{PacketEvent, PacketEventsService, PacketOutcome};
+pub use self::peer_activity_service::{PeerActivity, PeerActivityService, PeerActivitySummary};
 pub use self::rate_limit_service::{
     RateLimitAccount, RateLimitError, RateLimitService, RateLimitStore,
 };
-pub use self::validator_service::ValidatorService;
+pub use self::replay_protection_service::{FulfilledConditionStore, ReplayProtectionService};
+pub use self::shadow_service::{ShadowAccount, ShadowService};
+pub use self::traffic_counters_service::{TrafficCounters, TrafficCountersService, TrafficStore};
+pub use self::validator_service::{AddressValidation, ValidatorService, MAX_RESPONSE_DATA_LEN};
+pub use self::volume_alert_service::{
+    VolumeAlert, VolumeAlertAccount, VolumeAlertService, VolumeStore,
+};
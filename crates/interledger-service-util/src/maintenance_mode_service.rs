@@ -0,0 +1,249 @@
+use async_trait::async_trait;
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::{Account, AddressStore, IlpResult, IncomingRequest, IncomingService};
+use log::trace;
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Switch which puts the node into maintenance mode, and the reject which is sent
+/// for the Prepares the node receives while it is in maintenance mode.
+/// Clones share the same switch, so the mode can be toggled e.g. via the API.
+#[derive(Clone, Debug)]
+pub struct MaintenanceMode {
+    enabled: Arc<AtomicBool>,
+    code: ErrorCode,
+    message: Arc<String>,
+}
+
+impl Default for MaintenanceMode {
+    /// Disabled, rejecting with `T00_INTERNAL_ERROR` once it is enabled
+    fn default() -> Self {
+        MaintenanceMode::new(
+            false,
+            ErrorCode::T00_INTERNAL_ERROR,
+            "The node is down for maintenance".to_string(),
+        )
+    }
+}
+
+impl MaintenanceMode {
+    pub fn new(enabled: bool, code: ErrorCode, message: String) -> Self {
+        MaintenanceMode {
+            enabled: Arc::new(AtomicBool::new(enabled)),
+            code,
+            message: Arc::new(message),
+        }
+    }
+
+    /// Returns whether the node is in maintenance mode
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Puts the node into maintenance mode or takes it out of it
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+}
+
+/// # Maintenance Mode Service
+///
+/// Incoming Service which rejects every new Prepare while the node is in
+/// [maintenance mode](./struct.MaintenanceMode.html), so that peers back off until the
+/// maintenance is over. The messages of the peer protocols (whose destinations start
+/// with `peer.`, e.g. CCP, IL-DCP and settlement messages) are still handled, and so are
+/// the packets which were already in flight when maintenance mode was enabled.
+#[derive(Clone)]
+pub struct MaintenanceModeService<S, I, A> {
+    store: S,
+    maintenance_mode: MaintenanceMode,
+    next: I,
+    account_type: PhantomData<A>,
+}
+
+impl<S, I, A> MaintenanceModeService<S, I, A>
+where
+    S: AddressStore,
+    I: IncomingService<A>,
+    A: Account,
+{
+    pub fn new(store: S, maintenance_mode: MaintenanceMode, next: I) -> Self {
+        MaintenanceModeService {
+            store,
+            maintenance_mode,
+            next,
+            account_type: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<S, I, A> IncomingService<A> for MaintenanceModeService<S, I, A>
+where
+    S: AddressStore + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    /// On receive request:
+    /// 1. If the node is not in maintenance mode, or the request is a peer protocol message,
+    ///    forwards it
+    /// 1. Otherwise rejects it with the configured code and message
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        if !self.maintenance_mode.is_enabled() || request.prepare.destination().scheme() == "peer" {
+            return self.next.handle_request(request).await;
+        }
+        trace!(
+            "Rejecting packet from account {} because the node is in maintenance mode",
+            request.from.id()
+        );
+        Err(RejectBuilder {
+            code: self.maintenance_mode.code,
+            message: self.maintenance_mode.message.as_bytes(),
+            triggered_by: Some(&self.store.get_ilp_address()),
+            data: &[],
+        }
+        .build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_service::{incoming_service_fn, Username};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
+    use uuid::Uuid;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.connector").unwrap());
+
+    #[tokio::test]
+    async fn rejects_new_packets_in_maintenance_mode() {
+        let maintenance_mode = MaintenanceMode::new(
+            true,
+            ErrorCode::T03_CONNECTOR_BUSY,
+            "Back in 10 minutes".to_string(),
+        );
+        let mut service = test_service(maintenance_mode.clone());
+
+        let reject = service
+            .handle_request(test_request("example.destination"))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T03_CONNECTOR_BUSY);
+        assert_eq!(reject.message(), b"Back in 10 minutes");
+        assert_eq!(reject.triggered_by().unwrap(), *EXAMPLE_ADDRESS);
+
+        // Peer protocol messages are still handled
+        service
+            .handle_request(test_request("peer.route.update"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn handles_packets_again_when_disabled() {
+        let maintenance_mode = MaintenanceMode::default();
+        let mut service = test_service(maintenance_mode.clone());
+        service
+            .handle_request(test_request("example.destination"))
+            .await
+            .unwrap();
+
+        maintenance_mode.set_enabled(true);
+        let reject = service
+            .handle_request(test_request("example.destination"))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T00_INTERNAL_ERROR);
+
+        maintenance_mode.set_enabled(false);
+        service
+            .handle_request(test_request("example.destination"))
+            .await
+            .unwrap();
+    }
+
+    fn test_service(
+        maintenance_mode: MaintenanceMode,
+    ) -> MaintenanceModeService<TestStore, impl IncomingService<TestAccount> + Clone, TestAccount>
+    {
+        MaintenanceModeService::new(
+            TestStore,
+            maintenance_mode,
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        )
+    }
+
+    fn test_request(destination: &str) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount,
+            prepare: PrepareBuilder {
+                destination: Address::from_str(destination).unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            EXAMPLE_ADDRESS.clone()
+        }
+    }
+}
@@ -7,6 +7,13 @@ use log::debug;
 /// allowed for this account
 pub trait MaxPacketAmountAccount: Account {
     fn max_packet_amount(&self) -> u64;
+
+    /// The smallest amount of the packets this account may send, so that peers
+    /// cannot burn the node's resources with packets of negligible value.
+    /// Defaults to 0, which allows every packet.
+    fn min_packet_amount(&self) -> u64 {
+        0
+    }
 }
 
 /// # MaxPacketAmount Service
@@ -16,7 +23,10 @@ pub trait MaxPacketAmountAccount: Account {
 /// - Liquidity: a node operator may not way to allow a single high-value packet to tie up a large portion of its liquidity at once (especially because they do not know whether the packet will be fulfilled or rejected)
 /// - Security: each packet carries some risk, due to the possibility that a node's failure to pass back the fulfillment within the available time window would cause that node to lose money. Keeping the value of each individual packet low may help reduce the impact of such a failure
 /// Signaling: nodes SHOULD set the maximum packet amount _lower_ than the maximum amount in flight (also known as the payment or money bandwidth). `T04: Insufficient Liquidity` errors do not communicate to the sender how much they can send, largely because the "available liquidity" may be time based or based on the rate of other payments going through and thus difficult to communicate effectively. In contrast, the `F08: Amount Too Large` error conveys the maximum back to the sender, because this limit is assumed to be a static value, and alllows sender-side software like STREAM implementations to respond accordingly. Therefore, setting the maximum packet amount lower than the total money bandwidth allows client implementations to quickly adjust their packet amounts to appropriate levels.
-/// Requires a `MaxPacketAmountAccount` and _no store_.
+/// Accounts which have no limit configured should report `u64::max_value()`, in which case every packet is forwarded.
+/// Packets below the account's minimum packet amount are rejected with `F00: Bad Request`. Note that this includes
+/// zero-amount packets, which STREAM uses for some control frames (e.g. to close a connection).
+/// Requires a `MaxPacketAmountAccount` and an `AddressStore` (for the `triggered_by` field of the rejection).
 #[derive(Clone)]
 pub struct MaxPacketAmountService<I, S> {
     next: I,
@@ -38,9 +48,25 @@ where
     A: MaxPacketAmountAccount + Send + Sync + 'static,
 {
     /// On receive request:
-    /// 1. if request.prepare.amount <= request.from.max_packet_amount forward the request, else error
+    /// 1. if request.prepare.amount < request.from.min_packet_amount error
+    /// 2. if request.prepare.amount <= request.from.max_packet_amount forward the request, else error
     async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
         let ilp_address = self.store.get_ilp_address();
+        let min_packet_amount = request.from.min_packet_amount();
+        if request.prepare.amount() < min_packet_amount {
+            debug!(
+                "Prepare amount:{} is below min_packet_amount: {}",
+                request.prepare.amount(),
+                min_packet_amount
+            );
+            return Err(RejectBuilder {
+                code: ErrorCode::F00_BAD_REQUEST,
+                message: b"Packet amount is below the minimum",
+                triggered_by: Some(&ilp_address),
+                data: &[],
+            }
+            .build());
+        }
         let max_packet_amount = request.from.max_packet_amount();
         if request.prepare.amount() <= max_packet_amount {
             self.next.handle_request(request).await
@@ -72,13 +98,18 @@ mod tests {
     use std::str::FromStr;
     use uuid::Uuid;
 
+    /// An account with the given max and min packet amounts
     #[derive(Debug, Clone)]
-    struct TestAccount(u64);
+    struct TestAccount(u64, u64);
 
     impl MaxPacketAmountAccount for TestAccount {
         fn max_packet_amount(&self) -> u64 {
             self.0
         }
+
+        fn min_packet_amount(&self) -> u64 {
+            self.1
+        }
     }
 
     #[tokio::test]
@@ -93,7 +124,7 @@ mod tests {
         let store = TestStore;
 
         let request = IncomingRequest {
-            from: TestAccount(101),
+            from: TestAccount(101, 0),
             prepare: PrepareBuilder {
                 destination: Address::from_str("example.destination").unwrap(),
                 amount: 100,
@@ -121,7 +152,7 @@ mod tests {
         let store = TestStore;
 
         let request = IncomingRequest {
-            from: TestAccount(99),
+            from: TestAccount(99, 0),
             prepare: PrepareBuilder {
                 destination: Address::from_str("example.destination").unwrap(),
                 amount: 100,
@@ -135,6 +166,82 @@ mod tests {
         let mut service = MaxPacketAmountService::new(store.clone(), next);
         let reject = service.handle_request(request).await.unwrap_err();
         assert_eq!(reject.code(), ErrorCode::F08_AMOUNT_TOO_LARGE);
+        assert_eq!(
+            reject.triggered_by(),
+            Some(Address::from_str("example.connector").unwrap())
+        );
+        let details = MaxPacketAmountDetails::from_bytes(reject.data()).unwrap();
+        assert_eq!(details.amount_received(), 100);
+        assert_eq!(details.max_amount(), 99);
+    }
+
+    #[tokio::test]
+    async fn no_max_amount_configured() {
+        let next = incoming_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let store = TestStore;
+
+        let request = IncomingRequest {
+            from: TestAccount(u64::max_value(), 0),
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: u64::max_value(),
+                expires_at: std::time::SystemTime::now() + std::time::Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: b"test data",
+            }
+            .build(),
+        };
+
+        let mut service = MaxPacketAmountService::new(store.clone(), next);
+        let fulfill = service.handle_request(request).await.unwrap();
+        assert_eq!(fulfill.data(), b"test data");
+    }
+
+    #[tokio::test]
+    async fn min_amount() {
+        let next = incoming_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let mut service = MaxPacketAmountService::new(TestStore, next);
+        let request_with_amount = |amount| IncomingRequest {
+            from: TestAccount(u64::max_value(), 100),
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: std::time::SystemTime::now() + std::time::Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: b"test data",
+            }
+            .build(),
+        };
+
+        let reject = service
+            .handle_request(request_with_amount(99))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F00_BAD_REQUEST);
+        assert_eq!(
+            reject.triggered_by(),
+            Some(Address::from_str("example.connector").unwrap())
+        );
+
+        for amount in &[100, 101] {
+            let fulfill = service
+                .handle_request(request_with_amount(*amount))
+                .await
+                .unwrap();
+            assert_eq!(fulfill.data(), b"test data");
+        }
     }
 
     #[derive(Clone)]
@@ -0,0 +1,411 @@
+use async_trait::async_trait;
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::{Account, AddressStore, IlpResult, OutgoingRequest, OutgoingService};
+use log::warn;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+/// How many requests to a single account wait for the account's concurrency limit,
+/// unless set with [`max_queued_per_account`](struct.OutgoingConcurrencyLimitService.html#method.max_queued_per_account)
+pub const DEFAULT_MAX_QUEUED_PER_ACCOUNT: usize = 100;
+
+/// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the
+/// number of requests which may be sent to the account at the same time
+pub trait OutgoingConcurrencyAccount: Account {
+    /// The maximum number of requests sent to this account which are in flight at the same
+    /// time. Defaults to no limit.
+    fn max_concurrent_outgoing_requests(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// The requests in flight to an account and the ones waiting for them to complete
+struct AccountLimit {
+    limit: u32,
+    in_flight: Semaphore,
+    queued: AtomicUsize,
+}
+
+/// Counts a request as queued until it is dropped, even if its future is cancelled
+struct QueuedRequest<'a>(&'a AtomicUsize);
+
+impl Drop for QueuedRequest<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// # Outgoing Concurrency Limit Service
+///
+/// Outgoing Service which caps the number of requests in flight (sent, but not yet fulfilled
+/// or rejected) to each account which has a limit configured, so that slow or rate-limited
+/// peers are not flooded. Requests over the limit are queued in the order they arrive and
+/// sent once earlier requests complete. Requests which find the queue full are rejected
+/// with `T03_CONNECTOR_BUSY`, and queued requests which expire before they could be sent
+/// are rejected with `R00_TRANSFER_TIMED_OUT`.
+///
+/// Requires an `AddressStore` (for the `triggered_by` field of the rejection) and accounts
+/// implementing `OutgoingConcurrencyAccount`. Accounts without a limit are not queued.
+#[derive(Clone)]
+pub struct OutgoingConcurrencyLimitService<S, O, A> {
+    store: S,
+    next: O,
+    max_queued: usize,
+    limits: Arc<Mutex<HashMap<Uuid, Arc<AccountLimit>>>>,
+    account_type: PhantomData<A>,
+}
+
+impl<S, O, A> OutgoingConcurrencyLimitService<S, O, A>
+where
+    S: AddressStore,
+    O: OutgoingService<A>,
+    A: OutgoingConcurrencyAccount,
+{
+    pub fn new(store: S, next: O) -> Self {
+        OutgoingConcurrencyLimitService {
+            store,
+            next,
+            max_queued: DEFAULT_MAX_QUEUED_PER_ACCOUNT,
+            limits: Arc::new(Mutex::new(HashMap::new())),
+            account_type: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of requests to a single account which wait for the
+    /// account's concurrency limit. Zero rejects every request over the limit right away
+    pub fn max_queued_per_account(&mut self, limit: usize) -> &mut Self {
+        self.max_queued = limit;
+        self
+    }
+
+    /// Returns the tracked requests of the account, starting over if its limit was changed
+    fn account_limit(&self, account_id: Uuid, limit: u32) -> Arc<AccountLimit> {
+        let mut limits = self.limits.lock();
+        let account_limit = limits.entry(account_id).or_insert_with(|| new_limit(limit));
+        if account_limit.limit != limit {
+            *account_limit = new_limit(limit);
+        }
+        account_limit.clone()
+    }
+}
+
+fn new_limit(limit: u32) -> Arc<AccountLimit> {
+    Arc::new(AccountLimit {
+        limit,
+        in_flight: Semaphore::new(limit as usize),
+        queued: AtomicUsize::new(0),
+    })
+}
+
+#[async_trait]
+impl<S, O, A> OutgoingService<A> for OutgoingConcurrencyLimitService<S, O, A>
+where
+    S: AddressStore + Send + Sync + 'static,
+    O: OutgoingService<A> + Send + Sync + 'static,
+    A: OutgoingConcurrencyAccount + Sync + 'static,
+{
+    /// On sending a request:
+    /// 1. Forwards it right away if the account has no limit
+    /// 1. Takes a permit from the account's limit, waiting for one in the account's queue
+    ///     - If the queue is full, returns a reject
+    ///     - If the request expires while it is queued, returns a reject
+    /// 1. Forwards the request and returns the permit once it was fulfilled or rejected
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        let limit = match request.to.max_concurrent_outgoing_requests() {
+            Some(limit) => limit,
+            None => return self.next.send_request(request).await,
+        };
+        let account_limit = self.account_limit(request.to.id(), limit);
+
+        let _permit = match account_limit.in_flight.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                if account_limit.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+                    account_limit.queued.fetch_sub(1, Ordering::SeqCst);
+                    warn!(
+                        "Rejecting packet because the queue of requests to account {} is full",
+                        request.to.id()
+                    );
+                    return Err(RejectBuilder {
+                        code: ErrorCode::T03_CONNECTOR_BUSY,
+                        message: b"Too many requests queued for the next hop",
+                        triggered_by: Some(&self.store.get_ilp_address()),
+                        data: &[],
+                    }
+                    .build());
+                }
+                let _queued = QueuedRequest(&account_limit.queued);
+                let time_left = request
+                    .prepare
+                    .expires_at()
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default();
+                match timeout(time_left, account_limit.in_flight.acquire()).await {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        warn!(
+                            "Rejecting packet because it expired while queued for account {}",
+                            request.to.id()
+                        );
+                        return Err(RejectBuilder {
+                            code: ErrorCode::R00_TRANSFER_TIMED_OUT,
+                            message: b"Packet expired while waiting for the next hop",
+                            triggered_by: Some(&self.store.get_ilp_address()),
+                            data: &[],
+                        }
+                        .build());
+                    }
+                }
+            }
+        };
+
+        self.next.send_request(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_service::Username;
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
+
+    #[tokio::test]
+    async fn serializes_requests_to_the_limit() {
+        let next = GatedService::default();
+        let mut service = OutgoingConcurrencyLimitService::new(TestStore, next.clone());
+        service.max_queued_per_account(10);
+
+        let requests: Vec<_> = (0..5)
+            .map(|_| spawn_request(&service, ALICE_ID, Some(2)))
+            .collect();
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert_eq!(next.entered.load(Ordering::SeqCst), 2);
+
+        // each completed request lets one of the queued ones through
+        next.release(1);
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert_eq!(next.entered.load(Ordering::SeqCst), 3);
+
+        next.release(4);
+        for request in requests {
+            assert!(request.await.unwrap().is_ok());
+        }
+        assert_eq!(next.entered.load(Ordering::SeqCst), 5);
+        assert_eq!(*next.max_in_flight.lock(), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_once_the_queue_is_full() {
+        let next = GatedService::default();
+        let mut service = OutgoingConcurrencyLimitService::new(TestStore, next.clone());
+        service.max_queued_per_account(1);
+
+        let first = spawn_request(&service, ALICE_ID, Some(1));
+        let second = spawn_request(&service, ALICE_ID, Some(1));
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        let reject = service
+            .send_request(request_to(ALICE_ID, Some(1)))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T03_CONNECTOR_BUSY);
+        assert_eq!(
+            reject.triggered_by().unwrap().to_string(),
+            "example.connector"
+        );
+
+        // other accounts have their own limit and queue
+        let third = spawn_request(&service, BOB_ID, Some(1));
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert_eq!(next.entered.load(Ordering::SeqCst), 2);
+
+        next.release(3);
+        assert!(first.await.unwrap().is_ok());
+        assert!(second.await.unwrap().is_ok());
+        assert!(third.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_queued_requests_which_expire() {
+        let next = GatedService::default();
+        let mut service = OutgoingConcurrencyLimitService::new(TestStore, next.clone());
+        service.max_queued_per_account(10);
+
+        let first = spawn_request(&service, ALICE_ID, Some(1));
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        let mut request = request_to(ALICE_ID, Some(1));
+        request.prepare = PrepareBuilder {
+            destination: Address::from_str("example.destination").unwrap(),
+            amount: 100,
+            expires_at: SystemTime::now() + Duration::from_millis(50),
+            execution_condition: &[0; 32],
+            data: &[],
+        }
+        .build();
+        let reject = service.send_request(request).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R00_TRANSFER_TIMED_OUT);
+        assert_eq!(next.entered.load(Ordering::SeqCst), 1);
+
+        // the expired request left the queue
+        next.release(1);
+        assert!(first.await.unwrap().is_ok());
+        let account_limit = service.account_limit(Uuid::from_u128(ALICE_ID), 1);
+        assert_eq!(account_limit.queued.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn forwards_everything_without_limit() {
+        let next = GatedService::default();
+        let mut service = OutgoingConcurrencyLimitService::new(TestStore, next.clone());
+        service.max_queued_per_account(0);
+
+        let requests: Vec<_> = (0..5)
+            .map(|_| spawn_request(&service, ALICE_ID, None))
+            .collect();
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert_eq!(next.entered.load(Ordering::SeqCst), 5);
+
+        next.release(5);
+        for request in requests {
+            assert!(request.await.unwrap().is_ok());
+        }
+    }
+
+    fn spawn_request(
+        service: &OutgoingConcurrencyLimitService<TestStore, GatedService, TestAccount>,
+        id: u128,
+        limit: Option<u32>,
+    ) -> tokio::task::JoinHandle<IlpResult> {
+        let mut service = service.clone();
+        tokio::spawn(async move { service.send_request(request_to(id, limit)).await })
+    }
+
+    /// Fulfills the requests it receives once it is released, and remembers how many
+    /// of them were in flight at the same time
+    #[derive(Clone)]
+    struct GatedService {
+        gate: Arc<Semaphore>,
+        entered: Arc<AtomicUsize>,
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<Mutex<usize>>,
+    }
+
+    impl Default for GatedService {
+        fn default() -> Self {
+            GatedService {
+                gate: Arc::new(Semaphore::new(0)),
+                entered: Arc::new(AtomicUsize::new(0)),
+                in_flight: Arc::new(AtomicUsize::new(0)),
+                max_in_flight: Arc::new(Mutex::new(0)),
+            }
+        }
+    }
+
+    impl GatedService {
+        fn release(&self, requests: usize) {
+            self.gate.add_permits(requests);
+        }
+    }
+
+    #[async_trait]
+    impl OutgoingService<TestAccount> for GatedService {
+        async fn send_request(&mut self, _: OutgoingRequest<TestAccount>) -> IlpResult {
+            self.entered.fetch_add(1, Ordering::SeqCst);
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            {
+                let mut max_in_flight = self.max_in_flight.lock();
+                *max_in_flight = (*max_in_flight).max(in_flight);
+            }
+            // Each release lets exactly one request complete
+            self.gate.acquire().await.forget();
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        }
+    }
+
+    const ALICE_ID: u128 = 1;
+    const BOB_ID: u128 = 2;
+
+    fn request_to(id: u128, limit: Option<u32>) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: TestAccount(Uuid::nil(), None),
+            to: TestAccount(Uuid::from_u128(id), limit),
+            original_amount: 100,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestAccount(Uuid, Option<u32>);
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.0
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    impl OutgoingConcurrencyAccount for TestAccount {
+        fn max_concurrent_outgoing_requests(&self) -> Option<u32> {
+            self.1
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+}
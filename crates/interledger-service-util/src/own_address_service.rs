@@ -0,0 +1,328 @@
+use async_trait::async_trait;
+use interledger_packet::{Address, ErrorCode, Reject, RejectBuilder};
+use interledger_service::{
+    Account, AccountStore, AddressStore, IlpResult, IncomingRequest, IncomingService,
+    OutgoingService, Username,
+};
+use log::{error, trace};
+use serde::Deserialize;
+use std::marker::PhantomData;
+
+/// What the node does with the packets addressed exactly to its own ILP address
+/// (rather than to one of its accounts' addresses)
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OwnAddressPolicy {
+    /// Hand them to the node's protocol handlers, such as the echo service. The
+    /// packets those do not handle are rejected with `F02_UNREACHABLE` by the router
+    Protocols,
+    /// Reject them with `F02_UNREACHABLE`, including echo requests
+    Reject,
+    /// Forward them to the local account with the username
+    Account(Username),
+}
+
+impl Default for OwnAddressPolicy {
+    fn default() -> Self {
+        OwnAddressPolicy::Protocols
+    }
+}
+
+fn unreachable(ilp_address: &Address) -> Reject {
+    RejectBuilder {
+        code: ErrorCode::F02_UNREACHABLE,
+        message: b"Packet is addressed to this node",
+        triggered_by: Some(ilp_address),
+        data: &[],
+    }
+    .build()
+}
+
+/// # Own Address Service
+///
+/// Incoming Service which handles the packets addressed to the node's own ILP address
+/// according to its [`OwnAddressPolicy`](./enum.OwnAddressPolicy.html). Packets for the
+/// `Account` policy are sent to the outgoing service, like the router would; all other
+/// packets are passed to the next incoming service.
+///
+/// This must wrap the echo service, so that the `Reject` and `Account` policies apply
+/// to echo requests as well.
+#[derive(Clone)]
+pub struct OwnAddressService<S, I, O, A> {
+    store: S,
+    policy: OwnAddressPolicy,
+    next: I,
+    outgoing: O,
+    account_type: PhantomData<A>,
+}
+
+impl<S, I, O, A> OwnAddressService<S, I, O, A>
+where
+    S: AddressStore + AccountStore<Account = A>,
+    I: IncomingService<A>,
+    O: OutgoingService<A>,
+    A: Account,
+{
+    pub fn new(store: S, policy: OwnAddressPolicy, next: I, outgoing: O) -> Self {
+        OwnAddressService {
+            store,
+            policy,
+            next,
+            outgoing,
+            account_type: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<S, I, O, A> IncomingService<A> for OwnAddressService<S, I, O, A>
+where
+    S: AddressStore + AccountStore<Account = A> + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync + 'static,
+    O: OutgoingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    /// On receive request:
+    /// 1. If the packet is not addressed to the node's own address, or the policy is
+    ///    `Protocols`, forwards it to the next incoming service
+    /// 1. With the `Reject` policy, rejects it
+    /// 1. With the `Account` policy, loads the account and sends the packet to it
+    ///     - return reject if the account does not exist
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let ilp_address = self.store.get_ilp_address();
+        if request.prepare.destination() != ilp_address {
+            return self.next.handle_request(request).await;
+        }
+        let username = match self.policy {
+            OwnAddressPolicy::Protocols => return self.next.handle_request(request).await,
+            OwnAddressPolicy::Reject => {
+                trace!(
+                    "Rejecting packet from account {} addressed to this node",
+                    request.from.id()
+                );
+                return Err(unreachable(&ilp_address));
+            }
+            OwnAddressPolicy::Account(ref username) => username.clone(),
+        };
+        let account = match self.store.get_account_id_from_username(&username).await {
+            Ok(id) => self
+                .store
+                .get_accounts(vec![id])
+                .await
+                .ok()
+                .and_then(|mut accounts| accounts.pop()),
+            Err(_) => None,
+        };
+        match account {
+            Some(account) => {
+                trace!(
+                    "Forwarding packet addressed to this node to account {}",
+                    username
+                );
+                self.outgoing
+                    .send_request(request.into_outgoing(account))
+                    .await
+            }
+            None => {
+                error!(
+                    "Account {} which receives the packets addressed to this node was not found",
+                    username
+                );
+                Err(unreachable(&ilp_address))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::{AccountStoreError, AddressStoreError};
+    use interledger_packet::{FulfillBuilder, PrepareBuilder};
+    use interledger_service::{incoming_service_fn, outgoing_service_fn, OutgoingRequest};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
+    use uuid::Uuid;
+
+    static NODE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.connector").unwrap());
+
+    #[tokio::test]
+    async fn hands_packets_to_protocol_handlers() {
+        let mut service = test_service(OwnAddressPolicy::default());
+        let fulfill = service
+            .handle_request(test_request("example.connector"))
+            .await
+            .unwrap();
+        assert_eq!(fulfill.data(), b"incoming");
+    }
+
+    #[tokio::test]
+    async fn rejects_packets() {
+        let mut service = test_service(OwnAddressPolicy::Reject);
+        let reject = service
+            .handle_request(test_request("example.connector"))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+        assert_eq!(reject.triggered_by().unwrap(), *NODE_ADDRESS);
+
+        // Other packets are still passed on
+        let fulfill = service
+            .handle_request(test_request("example.connector.alice"))
+            .await
+            .unwrap();
+        assert_eq!(fulfill.data(), b"incoming");
+    }
+
+    #[tokio::test]
+    async fn forwards_packets_to_account() {
+        let mut service = test_service(OwnAddressPolicy::Account(
+            Username::from_str("local").unwrap(),
+        ));
+        let fulfill = service
+            .handle_request(test_request("example.connector"))
+            .await
+            .unwrap();
+        assert_eq!(fulfill.data(), b"local");
+
+        let fulfill = service
+            .handle_request(test_request("example.other"))
+            .await
+            .unwrap();
+        assert_eq!(fulfill.data(), b"incoming");
+
+        let mut service = test_service(OwnAddressPolicy::Account(
+            Username::from_str("nobody").unwrap(),
+        ));
+        let reject = service
+            .handle_request(test_request("example.connector"))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+    }
+
+    /// The next incoming service fulfills with `incoming` as the data, the outgoing
+    /// service with the username of the account the packet was sent to
+    fn test_service(
+        policy: OwnAddressPolicy,
+    ) -> OwnAddressService<
+        TestStore,
+        impl IncomingService<TestAccount>,
+        impl OutgoingService<TestAccount>,
+        TestAccount,
+    > {
+        OwnAddressService::new(
+            TestStore,
+            policy,
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"incoming",
+                }
+                .build())
+            }),
+            outgoing_service_fn(|request: OutgoingRequest<TestAccount>| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: request.to.username.as_ref().as_bytes(),
+                }
+                .build())
+            }),
+        )
+    }
+
+    fn test_request(destination: &str) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount::new("sender"),
+            prepare: PrepareBuilder {
+                destination: Address::from_str(destination).unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestAccount {
+        username: Username,
+        ilp_address: Address,
+    }
+
+    impl TestAccount {
+        fn new(username: &str) -> Self {
+            TestAccount {
+                username: Username::from_str(username).unwrap(),
+                ilp_address: NODE_ADDRESS.with_suffix(username.as_bytes()).unwrap(),
+            }
+        }
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &self.username
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &self.ilp_address
+        }
+    }
+
+    /// Only knows the account `local`
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AccountStore for TestStore {
+        type Account = TestAccount;
+
+        async fn get_accounts(
+            &self,
+            _account_ids: Vec<Uuid>,
+        ) -> Result<Vec<TestAccount>, AccountStoreError> {
+            Ok(vec![TestAccount::new("local")])
+        }
+
+        async fn get_account_id_from_username(
+            &self,
+            username: &Username,
+        ) -> Result<Uuid, AccountStoreError> {
+            if username.as_ref() == "local" {
+                Ok(Uuid::nil())
+            } else {
+                Err(AccountStoreError::AccountNotFound(username.to_string()))
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            NODE_ADDRESS.clone()
+        }
+    }
+}
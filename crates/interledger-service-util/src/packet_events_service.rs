@@ -0,0 +1,232 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use interledger_packet::Address;
+use interledger_service::{Account, IlpResult, OutgoingRequest, OutgoingService, Username};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use tokio::sync::broadcast;
+
+/// Whether the packet was fulfilled or rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PacketOutcome {
+    Fulfilled,
+    Rejected,
+}
+
+/// Record of a packet which was forwarded by the node, emitted by the
+/// [`PacketEventsService`](./struct.PacketEventsService.html)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PacketEvent {
+    pub outcome: PacketOutcome,
+    /// The username of the account the packet was received from
+    pub from_username: Username,
+    /// The username of the account the packet was forwarded to
+    pub to_username: Username,
+    pub destination: Address,
+    /// The amount of the incoming packet, denominated in the asset of the `from` account
+    pub incoming_amount: u64,
+    /// The amount of the outgoing packet, denominated in the asset of the `to` account
+    pub outgoing_amount: u64,
+    /// The ILP error code of the reject, if the packet was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// The time the response was received in RFC3339 format
+    pub timestamp: String,
+}
+
+/// # Packet Events Service
+///
+/// Outgoing Service which publishes a [`PacketEvent`](./struct.PacketEvent.html) to a
+/// broadcast channel every time it receives the Fulfill or Reject for a forwarded packet.
+///
+/// Publishing never blocks: if nobody is subscribed the event is discarded, and subscribers
+/// that fall more than the channel's capacity behind miss the oldest events.
+#[derive(Clone)]
+pub struct PacketEventsService<O, A> {
+    sender: broadcast::Sender<PacketEvent>,
+    next: O,
+    account_type: PhantomData<A>,
+}
+
+impl<O, A> PacketEventsService<O, A>
+where
+    O: OutgoingService<A>,
+    A: Account,
+{
+    pub fn new(sender: broadcast::Sender<PacketEvent>, next: O) -> Self {
+        PacketEventsService {
+            sender,
+            next,
+            account_type: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<O, A> OutgoingService<A> for PacketEventsService<O, A>
+where
+    O: OutgoingService<A> + Send + Sync + Clone + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    /// Forwards the request and publishes an event for the response,
+    /// unless no one is subscribed to the events
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        if self.sender.receiver_count() == 0 {
+            return self.next.send_request(request).await;
+        }
+
+        let from_username = request.from.username().clone();
+        let to_username = request.to.username().clone();
+        let destination = request.prepare.destination();
+        let incoming_amount = request.original_amount;
+        let outgoing_amount = request.prepare.amount();
+
+        let result = self.next.send_request(request).await;
+
+        let (outcome, error_code) = match result {
+            Ok(_) => (PacketOutcome::Fulfilled, None),
+            Err(ref reject) => (PacketOutcome::Rejected, Some(reject.code().to_string())),
+        };
+        // This only fails if all subscribers went away in the meantime
+        let _ = self.sender.send(PacketEvent {
+            outcome,
+            from_username,
+            to_username,
+            destination,
+            incoming_amount,
+            outgoing_amount,
+            error_code,
+            timestamp: Utc::now().to_rfc3339(),
+        });
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::{ErrorCode, FulfillBuilder, PrepareBuilder, RejectBuilder};
+    use interledger_service::outgoing_service_fn;
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn publishes_fulfilled_packets() {
+        let (sender, mut receiver) = broadcast::channel(16);
+        let mut service = PacketEventsService::new(
+            sender,
+            outgoing_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        service.send_request(test_request()).await.unwrap();
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.outcome, PacketOutcome::Fulfilled);
+        assert_eq!(event.from_username, *ALICE);
+        assert_eq!(event.incoming_amount, 100);
+        assert_eq!(event.outgoing_amount, 90);
+        assert_eq!(event.error_code, None);
+    }
+
+    #[tokio::test]
+    async fn publishes_rejected_packets() {
+        let (sender, mut receiver) = broadcast::channel(16);
+        let mut service = PacketEventsService::new(
+            sender,
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: &[],
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        service.send_request(test_request()).await.unwrap_err();
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.outcome, PacketOutcome::Rejected);
+        assert_eq!(event.error_code, Some("F02".to_string()));
+    }
+
+    #[tokio::test]
+    async fn lagging_subscribers_miss_events() {
+        let (sender, mut receiver) = broadcast::channel(2);
+        let mut service = PacketEventsService::new(
+            sender,
+            outgoing_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        // The data path is never blocked by a subscriber which isn't reading
+        for _ in 0..5 {
+            service.send_request(test_request()).await.unwrap();
+        }
+
+        assert_eq!(
+            receiver.recv().await.unwrap_err(),
+            broadcast::RecvError::Lagged(3)
+        );
+        assert!(receiver.recv().await.is_ok());
+        assert!(receiver.recv().await.is_ok());
+    }
+
+    fn test_request() -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: TestAccount,
+            to: TestAccount,
+            original_amount: 100,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 90,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[derive(Debug, Clone)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::new_v4()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+}
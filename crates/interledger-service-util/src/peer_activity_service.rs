@@ -0,0 +1,290 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use interledger_service::{
+    Account, IlpResult, IncomingRequest, IncomingService, OutgoingRequest, OutgoingService,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    marker::PhantomData,
+    sync::Arc,
+};
+use uuid::Uuid;
+
+/// The number of most recent packets sent to a peer whose outcome is remembered
+const RECENT_OUTCOMES: usize = 100;
+
+#[derive(Debug)]
+struct PeerStats {
+    last_activity: Option<DateTime<Utc>>,
+    /// Whether each of the most recent packets sent to the peer was fulfilled, oldest first
+    recent_outcomes: VecDeque<bool>,
+}
+
+impl Default for PeerStats {
+    fn default() -> Self {
+        PeerStats {
+            last_activity: None,
+            recent_outcomes: VecDeque::with_capacity(RECENT_OUTCOMES),
+        }
+    }
+}
+
+/// Summary of a peer's recent activity, as reported by [`PeerActivity`](./struct.PeerActivity.html)
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PeerActivitySummary {
+    /// The last time the peer sent us a packet or fulfilled one of ours, in RFC3339 format
+    pub last_activity: Option<String>,
+    /// How many of the most recent packets sent to the peer were fulfilled
+    pub recent_fulfilled: usize,
+    /// How many of the most recent packets sent to the peer were rejected
+    pub recent_rejected: usize,
+}
+
+/// In-memory registry of the activity of the node's peers, which is filled
+/// by the [`PeerActivityService`](./struct.PeerActivityService.html).
+///
+/// Only the outcomes of the last 100 packets sent to every peer are kept. Nothing is
+/// persisted, so all peers are reported as inactive after the node restarts.
+#[derive(Clone, Default)]
+pub struct PeerActivity {
+    peers: Arc<Mutex<HashMap<Uuid, PeerStats>>>,
+}
+
+impl PeerActivity {
+    /// Simple constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the activity of the account (which is empty if the account was never seen)
+    pub fn get(&self, account_id: &Uuid) -> PeerActivitySummary {
+        let peers = self.peers.lock();
+        let stats = match peers.get(account_id) {
+            Some(stats) => stats,
+            None => return PeerActivitySummary::default(),
+        };
+        let recent_fulfilled = stats.recent_outcomes.iter().filter(|f| **f).count();
+        PeerActivitySummary {
+            last_activity: stats.last_activity.map(|time| time.to_rfc3339()),
+            recent_fulfilled,
+            recent_rejected: stats.recent_outcomes.len() - recent_fulfilled,
+        }
+    }
+
+    fn record_activity(&self, account_id: Uuid) {
+        self.peers
+            .lock()
+            .entry(account_id)
+            .or_default()
+            .last_activity = Some(Utc::now());
+    }
+
+    fn record_outcome(&self, account_id: Uuid, fulfilled: bool) {
+        let mut peers = self.peers.lock();
+        let stats = peers.entry(account_id).or_default();
+        if stats.recent_outcomes.len() == RECENT_OUTCOMES {
+            stats.recent_outcomes.pop_front();
+        }
+        stats.recent_outcomes.push_back(fulfilled);
+        if fulfilled {
+            stats.last_activity = Some(Utc::now());
+        }
+    }
+}
+
+/// # Peer Activity Service
+///
+/// Incoming or Outgoing Service which records the activity of the node's peers in a
+/// [`PeerActivity`](./struct.PeerActivity.html) registry. As an Incoming Service it records
+/// every packet received from a peer. As an Outgoing Service it records whether the packets
+/// sent to a peer were fulfilled or rejected.
+///
+/// This is mostly useful for peers connected over ILP-over-HTTP, which (unlike BTP peers)
+/// do not have a connection the node could check.
+#[derive(Clone)]
+pub struct PeerActivityService<S, A> {
+    activity: PeerActivity,
+    next: S,
+    account_type: PhantomData<A>,
+}
+
+impl<S, A> PeerActivityService<S, A>
+where
+    A: Account,
+{
+    pub fn new(activity: PeerActivity, next: S) -> Self {
+        PeerActivityService {
+            activity,
+            next,
+            account_type: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<I, A> IncomingService<A> for PeerActivityService<I, A>
+where
+    I: IncomingService<A> + Send + Sync,
+    A: Account + Send + Sync,
+{
+    /// Records that the peer was active and forwards the request
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        self.activity.record_activity(request.from.id());
+        self.next.handle_request(request).await
+    }
+}
+
+#[async_trait]
+impl<O, A> OutgoingService<A> for PeerActivityService<O, A>
+where
+    O: OutgoingService<A> + Send + Sync + Clone + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    /// Forwards the request and records whether the peer fulfilled or rejected it
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        let account_id = request.to.id();
+        let result = self.next.send_request(request).await;
+        self.activity.record_outcome(account_id, result.is_ok());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder, RejectBuilder};
+    use interledger_service::{incoming_service_fn, outgoing_service_fn, Username};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn never_seen_peer_has_no_activity() {
+        let activity = PeerActivity::new();
+        assert_eq!(
+            activity.get(&Uuid::new_v4()),
+            PeerActivitySummary::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn records_incoming_packets() {
+        let activity = PeerActivity::new();
+        let mut service = PeerActivityService::new(
+            activity.clone(),
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        service
+            .handle_request(IncomingRequest {
+                from: TestAccount,
+                prepare: test_prepare(),
+            })
+            .await
+            .unwrap();
+
+        let summary = activity.get(&ALICE_ID);
+        assert!(summary.last_activity.is_some());
+        // only the packets sent to the peer are counted
+        assert_eq!(summary.recent_fulfilled, 0);
+        assert_eq!(summary.recent_rejected, 0);
+    }
+
+    #[tokio::test]
+    async fn counts_recent_outcomes() {
+        let activity = PeerActivity::new();
+        let mut service = PeerActivityService::new(
+            activity.clone(),
+            outgoing_service_fn(|request| {
+                if request.prepare.amount() > 0 {
+                    Ok(FulfillBuilder {
+                        fulfillment: &[0; 32],
+                        data: &[],
+                    }
+                    .build())
+                } else {
+                    Err(RejectBuilder {
+                        code: ErrorCode::F02_UNREACHABLE,
+                        message: &[],
+                        triggered_by: None,
+                        data: &[],
+                    }
+                    .build())
+                }
+            }),
+        );
+
+        let mut rejected = test_request();
+        rejected.prepare.set_amount(0);
+        service.send_request(rejected).await.unwrap_err();
+        let summary = activity.get(&ALICE_ID);
+        // rejects don't prove that the peer is reachable
+        assert_eq!(summary.last_activity, None);
+        assert_eq!(summary.recent_rejected, 1);
+
+        for _ in 0..RECENT_OUTCOMES {
+            service.send_request(test_request()).await.unwrap();
+        }
+        let summary = activity.get(&ALICE_ID);
+        assert!(summary.last_activity.is_some());
+        assert_eq!(summary.recent_fulfilled, RECENT_OUTCOMES);
+        assert_eq!(summary.recent_rejected, 0);
+    }
+
+    fn test_prepare() -> interledger_packet::Prepare {
+        PrepareBuilder {
+            destination: Address::from_str("example.destination").unwrap(),
+            amount: 100,
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            execution_condition: &[0; 32],
+            data: &[],
+        }
+        .build()
+    }
+
+    fn test_request() -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: TestAccount,
+            to: TestAccount,
+            original_amount: 100,
+            prepare: test_prepare(),
+        }
+    }
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static ALICE_ID: Lazy<Uuid> = Lazy::new(Uuid::new_v4);
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[derive(Debug, Clone)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            *ALICE_ID
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use async_trait::async_trait;
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::*;
+use log::{error, warn};
+use std::{convert::TryFrom, time::Duration};
+
+/// Store trait which remembers the execution conditions of the fulfilled packets
+#[async_trait]
+pub trait FulfilledConditionStore {
+    /// Returns whether a packet with the execution condition was fulfilled within its window
+    async fn was_condition_fulfilled(&self, condition: [u8; 32]) -> Result<bool, ()>;
+
+    /// Remembers that a packet with the execution condition was fulfilled.
+    /// The condition is forgotten once the window passed.
+    async fn save_fulfilled_condition(
+        &self,
+        condition: [u8; 32],
+        window: Duration,
+    ) -> Result<(), ()>;
+}
+
+/// # Replay Protection Service
+///
+/// Incoming Service which rejects Prepare packets with `F00_BAD_REQUEST` if a packet with
+/// the same execution condition was already fulfilled within the configured window.
+/// This lets receivers guarantee that each condition is only ever processed once, even if
+/// a sender replays a packet with a fresh expiry.
+///
+/// Until a window is set, every packet is forwarded and no conditions are remembered.
+/// Requires an `AddressStore` (for the `triggered_by` field of the rejection) and a
+/// `FulfilledConditionStore`.
+#[derive(Clone)]
+pub struct ReplayProtectionService<S, I> {
+    store: S,
+    next: I,
+    window: Option<Duration>,
+}
+
+impl<S, I> ReplayProtectionService<S, I> {
+    pub fn new(store: S, next: I) -> Self {
+        ReplayProtectionService {
+            store,
+            next,
+            window: None,
+        }
+    }
+
+    /// Sets how long the conditions of fulfilled packets are remembered. This should be at
+    /// least the longest expiry the node accepts, after which replayed packets are rejected
+    /// as expired anyway
+    pub fn window(&mut self, window: Duration) -> &mut Self {
+        self.window = Some(window);
+        self
+    }
+}
+
+#[async_trait]
+impl<S, I, A> IncomingService<A> for ReplayProtectionService<S, I>
+where
+    S: AddressStore + FulfilledConditionStore + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    /// On receive request:
+    /// 1. Reject the request if its execution condition was fulfilled within the window
+    /// 2. Forward the request and, if it was fulfilled, remember its execution condition
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let window = match self.window {
+            Some(window) => window,
+            None => return self.next.handle_request(request).await,
+        };
+        let condition = <[u8; 32]>::try_from(request.prepare.execution_condition()).unwrap();
+
+        match self.store.was_condition_fulfilled(condition).await {
+            Ok(true) => {
+                warn!(
+                    "Rejecting packet from account {} because its execution condition was already fulfilled",
+                    request.from.id()
+                );
+                return Err(RejectBuilder {
+                    code: ErrorCode::F00_BAD_REQUEST,
+                    message: b"Execution condition was already fulfilled",
+                    triggered_by: Some(&self.store.get_ilp_address()),
+                    data: &[],
+                }
+                .build());
+            }
+            Ok(false) => {}
+            // Packets are not rejected just because the store is unavailable
+            Err(_) => error!("Error checking whether the execution condition was fulfilled"),
+        }
+
+        let result = self.next.handle_request(request).await;
+
+        if result.is_ok() {
+            // This is awaited so that a packet replayed right after the fulfill was
+            // returned can not slip through
+            let _ = self
+                .store
+                .save_fulfilled_condition(condition, window)
+                .await
+                .map_err(|_| error!("Error saving the fulfilled execution condition"));
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::collections::HashSet;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::SystemTime;
+    use uuid::Uuid;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[tokio::test]
+    async fn forwards_fresh_conditions() {
+        let (mut service, store) = test_service();
+        service.handle_request(test_request([1; 32])).await.unwrap();
+        service.handle_request(test_request([2; 32])).await.unwrap();
+        assert_eq!(store.conditions.lock().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_replayed_conditions() {
+        let (mut service, _store) = test_service();
+        service.handle_request(test_request([1; 32])).await.unwrap();
+
+        let reject = service
+            .handle_request(test_request([1; 32]))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F00_BAD_REQUEST);
+        assert_eq!(reject.triggered_by().unwrap(), *EXAMPLE_ADDRESS);
+    }
+
+    #[tokio::test]
+    async fn forwards_everything_without_window() {
+        let store = TestStore::default();
+        let mut service = ReplayProtectionService::new(store.clone(), test_next());
+        service.handle_request(test_request([1; 32])).await.unwrap();
+        service.handle_request(test_request([1; 32])).await.unwrap();
+        assert!(store.conditions.lock().is_empty());
+    }
+
+    fn test_service() -> (
+        ReplayProtectionService<TestStore, impl IncomingService<TestAccount>>,
+        TestStore,
+    ) {
+        let store = TestStore::default();
+        let mut service = ReplayProtectionService::new(store.clone(), test_next());
+        service.window(Duration::from_secs(30));
+        (service, store)
+    }
+
+    fn test_next() -> impl IncomingService<TestAccount> + Clone {
+        incoming_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        })
+    }
+
+    fn test_request(condition: [u8; 32]) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &condition,
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct TestStore {
+        conditions: Arc<Mutex<HashSet<[u8; 32]>>>,
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            EXAMPLE_ADDRESS.clone()
+        }
+    }
+
+    #[async_trait]
+    impl FulfilledConditionStore for TestStore {
+        async fn was_condition_fulfilled(&self, condition: [u8; 32]) -> Result<bool, ()> {
+            Ok(self.conditions.lock().contains(&condition))
+        }
+
+        async fn save_fulfilled_condition(
+            &self,
+            condition: [u8; 32],
+            _: Duration,
+        ) -> Result<(), ()> {
+            self.conditions.lock().insert(condition);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use async_trait::async_trait;
+use interledger_packet::{Prepare, PrepareBuilder};
+use interledger_service::{
+    Account, AccountStore, IlpResult, OutgoingRequest, OutgoingService, Username,
+};
+use log::{debug, warn};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::marker::PhantomData;
+use tokio::sync::oneshot;
+
+/// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the
+/// account which the packets sent to this one are mirrored to
+pub trait ShadowAccount: Account {
+    /// The username of the account which is sent a copy of every packet sent to this one,
+    /// e.g. a new peer which is validated before traffic is cut over to it. Defaults to none.
+    fn shadow_account(&self) -> Option<&Username> {
+        None
+    }
+}
+
+/// Returns the outcome of a packet for the logs
+fn outcome(result: &IlpResult) -> String {
+    match result {
+        Ok(_) => "fulfilled".to_string(),
+        Err(reject) => format!("rejected with {}", reject.code()),
+    }
+}
+
+/// Copies the prepare with a random execution condition, so that nobody can fulfill the copy
+fn shadow_prepare(prepare: &Prepare) -> Prepare {
+    let mut execution_condition = [0; 32];
+    SystemRandom::new()
+        .fill(&mut execution_condition)
+        .expect("Failed to securely generate a random execution condition");
+    PrepareBuilder {
+        destination: prepare.destination(),
+        amount: prepare.amount(),
+        expires_at: prepare.expires_at(),
+        execution_condition: &execution_condition,
+        data: prepare.data(),
+    }
+    .build()
+}
+
+/// # Shadow Service
+///
+/// Outgoing Service which mirrors the packets sent to accounts with a
+/// [`shadow_account`](./trait.ShadowAccount.html#method.shadow_account) to that account,
+/// so that a new peer can be validated with real traffic before it is used. The shadow
+/// account's response is only logged, next to the outcome of the original packet, and the
+/// original packet is forwarded and responded to as if there was no shadow account.
+///
+/// The copy is sent with a random execution condition, so that it can never be fulfilled
+/// (which would pay the receiver twice). A peer which would have forwarded the packet
+/// therefore responds with the receiver's reject rather than e.g. `F02_UNREACHABLE`.
+/// Copies are only sent to shadow accounts with the same asset as the original account.
+///
+/// This must be wrapped by the balance service, so that the copies do not change the balances.
+#[derive(Clone)]
+pub struct ShadowService<S, O, A> {
+    store: S,
+    next: O,
+    account_type: PhantomData<A>,
+}
+
+impl<S, O, A> ShadowService<S, O, A>
+where
+    S: AccountStore<Account = A>,
+    O: OutgoingService<A>,
+    A: ShadowAccount,
+{
+    pub fn new(store: S, next: O) -> Self {
+        ShadowService {
+            store,
+            next,
+            account_type: PhantomData,
+        }
+    }
+}
+
+impl<S, O, A> ShadowService<S, O, A>
+where
+    S: AccountStore<Account = A> + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    A: ShadowAccount + Send + Sync + 'static,
+{
+    /// Sends a copy of the request to the shadow account in the background and logs its
+    /// outcome once the outcome of the original request is sent on the returned channel
+    fn mirror(&self, request: &OutgoingRequest<A>, shadow: Username) -> oneshot::Sender<String> {
+        let (sender, receiver) = oneshot::channel();
+        let store = self.store.clone();
+        let mut next = self.next.clone();
+        let primary = request.to.clone();
+        let from = request.from.clone();
+        let original_amount = request.original_amount;
+        let prepare = shadow_prepare(&request.prepare);
+        tokio::spawn(async move {
+            let account = match store.get_account_id_from_username(&shadow).await {
+                Ok(id) => store
+                    .get_accounts(vec![id])
+                    .await
+                    .ok()
+                    .and_then(|mut a| a.pop()),
+                Err(_) => None,
+            };
+            let account = match account {
+                Some(account) => account,
+                None => {
+                    warn!(
+                        "Shadow account {} of account {} was not found",
+                        shadow,
+                        primary.username()
+                    );
+                    return;
+                }
+            };
+            if account.asset_code() != primary.asset_code()
+                || account.asset_scale() != primary.asset_scale()
+            {
+                warn!(
+                    "Not mirroring packet to shadow account {} because its asset differs from account {}'s",
+                    shadow,
+                    primary.username()
+                );
+                return;
+            }
+            let result = next
+                .send_request(OutgoingRequest {
+                    from,
+                    to: account,
+                    original_amount,
+                    prepare,
+                })
+                .await;
+            let primary_outcome = receiver.await.unwrap_or_else(|_| "cancelled".to_string());
+            debug!(
+                "Packet sent to account {} was {}, its copy sent to shadow account {} was {}",
+                primary.username(),
+                primary_outcome,
+                shadow,
+                outcome(&result)
+            );
+        });
+        sender
+    }
+}
+
+#[async_trait]
+impl<S, O, A> OutgoingService<A> for ShadowService<S, O, A>
+where
+    S: AccountStore<Account = A> + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    A: ShadowAccount + Send + Sync + 'static,
+{
+    /// On send request:
+    /// 1. If the account has a shadow account, sends a copy of the request to it in the background
+    /// 1. Forwards the request and returns its result, whatever the shadow account responds
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        let shadow = match request.to.shadow_account() {
+            Some(shadow) if shadow != request.to.username() => shadow.clone(),
+            _ => return self.next.send_request(request).await,
+        };
+        let primary_outcome = self.mirror(&request, shadow);
+        let result = self.next.send_request(request).await;
+        let _ = primary_outcome.send(outcome(&result));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AccountStoreError;
+    use interledger_packet::{Address, ErrorCode, FulfillBuilder, RejectBuilder};
+    use interledger_service::outgoing_service_fn;
+    use parking_lot::Mutex;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn mirrors_packets_to_shadow_account() {
+        let (mut service, received) = test_service();
+        let prepare = test_prepare();
+        let fulfill = service
+            .send_request(test_request(
+                TestAccount::new("primary", Some("shadow")),
+                prepare.clone(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(fulfill.data(), b"primary");
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+
+        let received = received.lock();
+        assert_eq!(received.len(), 2);
+        let (_, copy) = received
+            .iter()
+            .find(|(to, _)| to.as_ref() == "shadow")
+            .unwrap();
+        assert_eq!(copy.destination(), prepare.destination());
+        assert_eq!(copy.amount(), prepare.amount());
+        assert_eq!(copy.expires_at(), prepare.expires_at());
+        assert_eq!(copy.data(), prepare.data());
+        assert_ne!(copy.execution_condition(), prepare.execution_condition());
+    }
+
+    #[tokio::test]
+    async fn returns_result_of_primary_account() {
+        // The shadow account fulfills, but the packet sent to the primary account is rejected
+        let (mut service, received) = test_service();
+        let reject = service
+            .send_request(test_request(
+                TestAccount::new("rejecter", Some("shadow")),
+                test_prepare(),
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert_eq!(received.lock().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn only_forwards_packets_without_shadow_account() {
+        let (mut service, received) = test_service();
+        service
+            .send_request(test_request(
+                TestAccount::new("primary", None),
+                test_prepare(),
+            ))
+            .await
+            .unwrap();
+        // Unknown shadow accounts are skipped
+        service
+            .send_request(test_request(
+                TestAccount::new("primary", Some("unknown")),
+                test_prepare(),
+            ))
+            .await
+            .unwrap();
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        let received = received.lock();
+        assert_eq!(received.len(), 2);
+        assert!(received.iter().all(|(to, _)| to.as_ref() == "primary"));
+    }
+
+    type Received = Arc<Mutex<Vec<(Username, Prepare)>>>;
+
+    /// Fulfills every packet with the username of the account it was sent to as the data,
+    /// except for the ones sent to `rejecter`
+    fn test_service() -> (
+        ShadowService<TestStore, impl OutgoingService<TestAccount> + Clone, TestAccount>,
+        Received,
+    ) {
+        let received: Received = Arc::new(Mutex::new(Vec::new()));
+        let received_clone = received.clone();
+        let service = ShadowService::new(
+            TestStore,
+            outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+                received_clone
+                    .lock()
+                    .push((request.to.username.clone(), request.prepare.clone()));
+                if request.to.username.as_ref() == "rejecter" {
+                    return Err(RejectBuilder {
+                        code: ErrorCode::F02_UNREACHABLE,
+                        message: &[],
+                        triggered_by: None,
+                        data: &[],
+                    }
+                    .build());
+                }
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: request.to.username.as_ref().as_bytes(),
+                }
+                .build())
+            }),
+        );
+        (service, received)
+    }
+
+    fn test_prepare() -> Prepare {
+        PrepareBuilder {
+            destination: Address::from_str("example.destination").unwrap(),
+            amount: 100,
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            execution_condition: &[1; 32],
+            data: b"some data",
+        }
+        .build()
+    }
+
+    fn test_request(to: TestAccount, prepare: Prepare) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: TestAccount::new("sender", None),
+            to,
+            original_amount: prepare.amount(),
+            prepare,
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestAccount {
+        id: Uuid,
+        username: Username,
+        ilp_address: Address,
+        shadow_account: Option<Username>,
+    }
+
+    impl TestAccount {
+        fn new(username: &str, shadow_account: Option<&str>) -> Self {
+            TestAccount {
+                id: Uuid::new_v4(),
+                username: Username::from_str(username).unwrap(),
+                ilp_address: Address::from_str(&format!("example.{}", username)).unwrap(),
+                shadow_account: shadow_account
+                    .map(|username| Username::from_str(username).unwrap()),
+            }
+        }
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.id
+        }
+
+        fn username(&self) -> &Username {
+            &self.username
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &self.ilp_address
+        }
+    }
+
+    impl ShadowAccount for TestAccount {
+        fn shadow_account(&self) -> Option<&Username> {
+            self.shadow_account.as_ref()
+        }
+    }
+
+    /// Only knows the account `shadow`
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AccountStore for TestStore {
+        type Account = TestAccount;
+
+        async fn get_accounts(
+            &self,
+            _account_ids: Vec<Uuid>,
+        ) -> Result<Vec<TestAccount>, AccountStoreError> {
+            Ok(vec![TestAccount::new("shadow", None)])
+        }
+
+        async fn get_account_id_from_username(
+            &self,
+            username: &Username,
+        ) -> Result<Uuid, AccountStoreError> {
+            if username.as_ref() == "shadow" {
+                Ok(Uuid::new_v4())
+            } else {
+                Err(AccountStoreError::AccountNotFound(username.to_string()))
+            }
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use async_trait::async_trait;
+use interledger_service::{
+    Account, IlpResult, IncomingRequest, IncomingService, OutgoingRequest, OutgoingService,
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use uuid::Uuid;
+
+/// Cumulative counters of the packets exchanged with an account, as counted by the
+/// [`TrafficCountersService`](./struct.TrafficCountersService.html)
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrafficCounters {
+    /// The number of Prepare packets received from the account
+    pub packets_in: u64,
+    /// The number of Prepare packets sent to the account
+    pub packets_out: u64,
+    /// The size of the packets received from the account (its Prepare packets and
+    /// its responses to ours) in bytes
+    pub bytes_in: u64,
+    /// The size of the packets sent to the account (our Prepare packets and our
+    /// responses to its) in bytes
+    pub bytes_out: u64,
+    /// The amount of the fulfilled packets received from the account, denominated in its asset
+    pub amount_in: u64,
+    /// The amount of the fulfilled packets sent to the account, denominated in its asset
+    pub amount_out: u64,
+}
+
+impl TrafficCounters {
+    /// Adds the other counters to these ones (saturating at `u64::MAX`)
+    pub fn add(&mut self, other: &TrafficCounters) {
+        self.packets_in = self.packets_in.saturating_add(other.packets_in);
+        self.packets_out = self.packets_out.saturating_add(other.packets_out);
+        self.bytes_in = self.bytes_in.saturating_add(other.bytes_in);
+        self.bytes_out = self.bytes_out.saturating_add(other.bytes_out);
+        self.amount_in = self.amount_in.saturating_add(other.amount_in);
+        self.amount_out = self.amount_out.saturating_add(other.amount_out);
+    }
+}
+
+/// Store trait which keeps the traffic counters of each account
+#[async_trait]
+pub trait TrafficStore {
+    /// Returns the counters of the account (which are all zero if it never sent or received a packet)
+    async fn get_traffic(&self, account_id: Uuid) -> Result<TrafficCounters, ()>;
+
+    /// Adds the traffic to the counters of the account
+    async fn add_traffic(&self, account_id: Uuid, traffic: TrafficCounters) -> Result<(), ()>;
+}
+
+/// Returns the size of the response to a packet in bytes
+fn response_len(result: &IlpResult) -> u64 {
+    match result {
+        Ok(fulfill) => fulfill.as_ref().len() as u64,
+        Err(reject) => reject.as_ref().len() as u64,
+    }
+}
+
+/// # Traffic Counters Service
+///
+/// Incoming or Outgoing Service which counts the packets, bytes and amounts exchanged with
+/// each account in a [`TrafficStore`](./trait.TrafficStore.html), for billing and capacity
+/// planning. As an Incoming Service it counts the packets received from the accounts, as an
+/// Outgoing Service the packets sent to them. Amounts are only counted if the packet was fulfilled.
+///
+/// The counters are updated in the background, so that packets are not delayed by the store.
+#[derive(Clone)]
+pub struct TrafficCountersService<S, N, A> {
+    store: S,
+    next: N,
+    account_type: PhantomData<A>,
+}
+
+impl<S, N, A> TrafficCountersService<S, N, A>
+where
+    S: TrafficStore,
+    A: Account,
+{
+    pub fn new(store: S, next: N) -> Self {
+        TrafficCountersService {
+            store,
+            next,
+            account_type: PhantomData,
+        }
+    }
+}
+
+impl<S, N, A> TrafficCountersService<S, N, A>
+where
+    S: TrafficStore + Clone + Send + Sync + 'static,
+{
+    fn record(&self, account_id: Uuid, traffic: TrafficCounters) {
+        let store = self.store.clone();
+        tokio::spawn(async move {
+            if store.add_traffic(account_id, traffic).await.is_err() {
+                error!(
+                    "Error updating the traffic counters of account {}",
+                    account_id
+                );
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl<S, I, A> IncomingService<A> for TrafficCountersService<S, I, A>
+where
+    S: TrafficStore + Clone + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync,
+    A: Account + Send + Sync,
+{
+    /// Forwards the request and counts it, and its response, as traffic from the account
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let account_id = request.from.id();
+        let amount = request.prepare.amount();
+        let prepare_len = request.prepare.as_ref().len() as u64;
+        let result = self.next.handle_request(request).await;
+        self.record(
+            account_id,
+            TrafficCounters {
+                packets_in: 1,
+                bytes_in: prepare_len,
+                bytes_out: response_len(&result),
+                amount_in: if result.is_ok() { amount } else { 0 },
+                ..TrafficCounters::default()
+            },
+        );
+        result
+    }
+}
+
+#[async_trait]
+impl<S, O, A> OutgoingService<A> for TrafficCountersService<S, O, A>
+where
+    S: TrafficStore + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Send + Sync,
+    A: Account + Send + Sync,
+{
+    /// Forwards the request and counts it, and its response, as traffic to the account
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        let account_id = request.to.id();
+        let amount = request.prepare.amount();
+        let prepare_len = request.prepare.as_ref().len() as u64;
+        let result = self.next.send_request(request).await;
+        self.record(
+            account_id,
+            TrafficCounters {
+                packets_out: 1,
+                bytes_out: prepare_len,
+                bytes_in: response_len(&result),
+                amount_out: if result.is_ok() { amount } else { 0 },
+                ..TrafficCounters::default()
+            },
+        );
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder, RejectBuilder};
+    use interledger_service::{outgoing_service_fn, Username};
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    static ALICE: Lazy<TestAccount> = Lazy::new(|| TestAccount::new("alice"));
+    static BOB: Lazy<TestAccount> = Lazy::new(|| TestAccount::new("bob"));
+
+    #[tokio::test]
+    async fn counts_forwarded_packets_for_both_accounts() {
+        let store = TestStore::default();
+        let outgoing = TrafficCountersService::new(
+            store.clone(),
+            outgoing_service_fn(|request: OutgoingRequest<TestAccount>| {
+                if request.prepare.amount() > 0 {
+                    Ok(FulfillBuilder {
+                        fulfillment: &[0; 32],
+                        data: b"receipt",
+                    }
+                    .build())
+                } else {
+                    Err(RejectBuilder {
+                        code: ErrorCode::F99_APPLICATION_ERROR,
+                        message: b"No zero-amount packets",
+                        triggered_by: None,
+                        data: &[],
+                    }
+                    .build())
+                }
+            }),
+        );
+        let mut incoming = TrafficCountersService::new(store.clone(), Forwarder(outgoing));
+        let prepare = test_prepare(100);
+        let prepare_len = prepare.as_ref().len() as u64;
+        let fulfill = incoming
+            .handle_request(IncomingRequest {
+                from: ALICE.clone(),
+                prepare,
+            })
+            .await
+            .unwrap();
+        let fulfill_len = fulfill.as_ref().len() as u64;
+        let reject = incoming
+            .handle_request(IncomingRequest {
+                from: ALICE.clone(),
+                prepare: test_prepare(0),
+            })
+            .await
+            .unwrap_err();
+        let reject_len = reject.as_ref().len() as u64;
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+
+        assert_eq!(
+            store.traffic(&ALICE.id),
+            TrafficCounters {
+                packets_in: 2,
+                packets_out: 0,
+                bytes_in: 2 * prepare_len,
+                bytes_out: fulfill_len + reject_len,
+                amount_in: 100,
+                amount_out: 0,
+            }
+        );
+        assert_eq!(
+            store.traffic(&BOB.id),
+            TrafficCounters {
+                packets_in: 0,
+                packets_out: 2,
+                bytes_in: fulfill_len + reject_len,
+                bytes_out: 2 * prepare_len,
+                amount_in: 0,
+                amount_out: 200,
+            }
+        );
+    }
+
+    #[test]
+    fn adding_counters_saturates() {
+        let mut counters = TrafficCounters {
+            amount_out: u64::MAX - 1,
+            ..TrafficCounters::default()
+        };
+        counters.add(&TrafficCounters {
+            packets_out: 1,
+            amount_out: 10,
+            ..TrafficCounters::default()
+        });
+        assert_eq!(counters.packets_out, 1);
+        assert_eq!(counters.amount_out, u64::MAX);
+    }
+
+    /// Forwards every packet to bob, converting the amounts 1:2
+    #[derive(Clone)]
+    struct Forwarder<O>(O);
+
+    #[async_trait]
+    impl<O> IncomingService<TestAccount> for Forwarder<O>
+    where
+        O: OutgoingService<TestAccount> + Send + Sync,
+    {
+        async fn handle_request(&mut self, request: IncomingRequest<TestAccount>) -> IlpResult {
+            let mut prepare = request.prepare;
+            let original_amount = prepare.amount();
+            prepare.set_amount(original_amount * 2);
+            self.0
+                .send_request(OutgoingRequest {
+                    from: request.from,
+                    to: BOB.clone(),
+                    original_amount,
+                    prepare,
+                })
+                .await
+        }
+    }
+
+    fn test_prepare(amount: u64) -> interledger_packet::Prepare {
+        PrepareBuilder {
+            destination: Address::from_str("example.bob").unwrap(),
+            amount,
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            execution_condition: &[0; 32],
+            data: b"some data",
+        }
+        .build()
+    }
+
+    #[derive(Clone, Default)]
+    struct TestStore {
+        traffic: Arc<Mutex<HashMap<Uuid, TrafficCounters>>>,
+    }
+
+    impl TestStore {
+        fn traffic(&self, account_id: &Uuid) -> TrafficCounters {
+            self.traffic
+                .lock()
+                .get(account_id)
+                .cloned()
+                .unwrap_or_default()
+        }
+    }
+
+    #[async_trait]
+    impl TrafficStore for TestStore {
+        async fn get_traffic(&self, account_id: Uuid) -> Result<TrafficCounters, ()> {
+            Ok(self.traffic(&account_id))
+        }
+
+        async fn add_traffic(&self, account_id: Uuid, traffic: TrafficCounters) -> Result<(), ()> {
+            self.traffic
+                .lock()
+                .entry(account_id)
+                .or_default()
+                .add(&traffic);
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestAccount {
+        id: Uuid,
+        username: Username,
+        ilp_address: Address,
+    }
+
+    impl TestAccount {
+        fn new(username: &str) -> Self {
+            TestAccount {
+                id: Uuid::new_v4(),
+                username: Username::from_str(username).unwrap(),
+                ilp_address: Address::from_str(&format!("example.{}", username)).unwrap(),
+            }
+        }
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.id
+        }
+
+        fn username(&self) -> &Username {
+            &self.username
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &self.ilp_address
+        }
+    }
+}
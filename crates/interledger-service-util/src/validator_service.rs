@@ -3,21 +3,48 @@ use chrono::{DateTime, Duration, Utc};
 use hex;
 use interledger_packet::{ErrorCode, RejectBuilder};
 use interledger_service::*;
-use log::error;
+use log::{error, warn};
 use ring::digest::{digest, SHA256};
+use serde::Deserialize;
 use std::marker::PhantomData;
 use tokio::time::timeout;
 
+/// The maximum length of the data of Fulfill and Reject packets
+/// allowed by [RFC 27](https://interledger.org/rfcs/0027-interledger-protocol-4/#specification)
+pub const MAX_RESPONSE_DATA_LEN: usize = 32767;
+
+/// How incoming validators check the destinations of the Prepare packets against the
+/// [ILP address requirements](https://interledger.org/rfcs/0015-ilp-addresses/#address-requirements)
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressValidation {
+    /// Reject the packets with malformed destinations with `F01_INVALID_PACKET`
+    Strict,
+    /// Forward the packets with malformed destinations, which are then usually
+    /// rejected with `F02_UNREACHABLE` because no route matches them
+    Lenient,
+}
+
+impl Default for AddressValidation {
+    fn default() -> Self {
+        AddressValidation::Strict
+    }
+}
+
 /// # Validator Service
 ///
 /// Incoming or Outgoing Service responsible for rejecting timed out
 /// requests and checking that fulfillments received match the `execution_condition` from the original `Prepare` packets.
+/// Incoming validators also check the destinations of the packets according to their [`AddressValidation`](./enum.AddressValidation.html).
+/// Outgoing validators also replace responses with more data than allowed with a reject.
 /// Forwards everything else.
 #[derive(Clone)]
 pub struct ValidatorService<IO, S, A> {
     store: S,
     next: IO,
     account_type: PhantomData<A>,
+    max_response_data_len: usize,
+    address_validation: AddressValidation,
 }
 
 impl<I, S, A> ValidatorService<I, S, A>
@@ -33,8 +60,17 @@ where
             store,
             next,
             account_type: PhantomData,
+            max_response_data_len: MAX_RESPONSE_DATA_LEN,
+            address_validation: AddressValidation::default(),
         }
     }
+
+    /// Sets how the destinations of incoming packets are checked.
+    /// Defaults to [`AddressValidation::Strict`](./enum.AddressValidation.html#variant.Strict).
+    pub fn address_validation(&mut self, address_validation: AddressValidation) -> &mut Self {
+        self.address_validation = address_validation;
+        self
+    }
 }
 
 impl<O, S, A> ValidatorService<O, S, A>
@@ -51,8 +87,18 @@ where
             store,
             next,
             account_type: PhantomData,
+            max_response_data_len: MAX_RESPONSE_DATA_LEN,
+            address_validation: AddressValidation::default(),
         }
     }
+
+    /// Sets the maximum length of the data of the Fulfills and Rejects returned by peers.
+    /// Responses with more data are replaced with an `F09_INVALID_PEER_RESPONSE` reject.
+    /// Defaults to [`MAX_RESPONSE_DATA_LEN`](./constant.MAX_RESPONSE_DATA_LEN.html).
+    pub fn max_response_data_len(&mut self, limit: usize) -> &mut Self {
+        self.max_response_data_len = limit;
+        self
+    }
 }
 
 #[async_trait]
@@ -63,8 +109,27 @@ where
     A: Account + Send + Sync,
 {
     /// On receiving a request:
+    /// 1. If the validation is strict and the destination of the prepare packet is malformed, return a reject
     /// 1. If the prepare packet in the request is not expired, forward it, otherwise return a reject
     async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        if self.address_validation == AddressValidation::Strict
+            && !request.prepare.destination().is_valid()
+        {
+            warn!(
+                "Got packet with invalid destination {:?} from account {} (id: {})",
+                request.prepare.destination(),
+                request.from.username(),
+                request.from.id()
+            );
+            return Err(RejectBuilder {
+                code: ErrorCode::F01_INVALID_PACKET,
+                message: b"Invalid destination address",
+                triggered_by: Some(&self.store.get_ilp_address()),
+                data: &[],
+            }
+            .build());
+        }
+
         let expires_at = DateTime::<Utc>::from(request.prepare.expires_at());
         let now = Utc::now();
         if expires_at >= now {
@@ -98,6 +163,7 @@ where
     /// 1. If the outgoing packet has expired, return a reject with the appropriate ErrorCode
     /// 1. Tries to forward the request
     ///     - If no response is received before the prepare packet's expiration, it assumes that the outgoing request has timed out.
+    ///     - If the response has more data than allowed, it is replaced with a reject
     ///     - If no timeout occurred, but still errored it will just return the reject
     ///     - If the forwarding is successful, it should receive a fulfill packet. Depending on if the hash of the fulfillment condition inside the fulfill is a preimage of the condition of the prepare:
     ///         - return the fulfill if it matches
@@ -110,6 +176,8 @@ where
         let now = Utc::now();
         let time_left = expires_at - now;
         let ilp_address = self.store.get_ilp_address();
+        let peer_id = request.to.id();
+        let peer_username = request.to.username().clone();
         if time_left > Duration::zero() {
             // Result of the future
             let result = timeout(
@@ -118,10 +186,10 @@ where
             )
             .await;
 
-            let fulfill = match result {
+            let result = match result {
                 // If the future completed in time, it returns an IlpResult,
-                // which gives us the fulfill packet
-                Ok(packet) => packet?,
+                // which gives us the fulfill or reject packet
+                Ok(result) => result,
                 // If the future timed out, then it results in an error
                 Err(_) => {
                     error!(
@@ -139,6 +207,25 @@ where
                 }
             };
 
+            let data_len = match result {
+                Ok(ref fulfill) => fulfill.data().len(),
+                Err(ref reject) => reject.data().len(),
+            };
+            if data_len > self.max_response_data_len {
+                error!(
+                    "Peer {} (account {}) responded with {} bytes of data, more than the maximum of {}",
+                    peer_username, peer_id, data_len, self.max_response_data_len,
+                );
+                return Err(RejectBuilder {
+                    code: ErrorCode::F09_INVALID_PEER_RESPONSE,
+                    message: b"Response data too large",
+                    triggered_by: Some(&ilp_address),
+                    data: &[],
+                }
+                .build());
+            }
+            let fulfill = result?;
+
             let generated_condition = digest(&SHA256, fulfill.fulfillment());
             if generated_condition.as_ref() == condition {
                 Ok(fulfill)
@@ -321,6 +408,143 @@ mod incoming {
     }
 }
 
+#[cfg(test)]
+mod address_validation {
+    use super::*;
+    use bytes::BytesMut;
+    use interledger_packet::*;
+    use interledger_service::incoming_service_fn;
+    use std::convert::TryFrom;
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
+
+    static VALID: &[&str] = &["example.destination", "g.us-fed.ach.0.acmebank"];
+    // Addresses right at the limits of the address requirements
+    static BORDERLINE_VALID: &[&str] = &["test3.alice", "local.a", "test.-_~"];
+    static BORDERLINE_INVALID: &[&str] = &["test4.alice", "test.alice.", "test.alice..bob"];
+    static CLEARLY_INVALID: &[&str] = &["what.alice", "test.alice 123", "test.alice!/123", "."];
+
+    /// Serializes a Prepare by hand because the builder only accepts valid addresses
+    fn prepare_to(destination: &str) -> Prepare {
+        let mut content = 100u64.to_be_bytes().to_vec();
+        content.extend_from_slice(
+            DateTime::<Utc>::from(SystemTime::now() + Duration::from_secs(30))
+                .format("%Y%m%d%H%M%S%3f")
+                .to_string()
+                .as_bytes(),
+        );
+        content.extend_from_slice(&[0; 32]);
+        put_var_octet_string(&mut content, destination.as_bytes());
+        put_var_octet_string(&mut content, b"test data");
+        let mut buffer = vec![PacketType::Prepare as u8];
+        put_var_octet_string(&mut buffer, &content);
+        Prepare::try_from(BytesMut::from(&buffer[..])).unwrap()
+    }
+
+    fn put_var_octet_string(buffer: &mut Vec<u8>, bytes: &[u8]) {
+        if bytes.len() < 128 {
+            buffer.push(bytes.len() as u8);
+        } else {
+            buffer.push(0x82);
+            buffer.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+        }
+        buffer.extend_from_slice(bytes);
+    }
+
+    fn long_address(length: usize) -> String {
+        let mut address = "test.".to_string();
+        address.extend(std::iter::repeat('a').take(length - address.len()));
+        address
+    }
+
+    async fn validate(validation: AddressValidation, destination: &str) -> (IlpResult, usize) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = requests.clone();
+        let mut validator = ValidatorService::incoming(
+            TestStore,
+            incoming_service_fn(move |request| {
+                requests_clone.lock().unwrap().push(request);
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"test data",
+                }
+                .build())
+            }),
+        );
+        validator.address_validation(validation);
+        let result = validator
+            .handle_request(IncomingRequest {
+                from: TestAccount(Uuid::new_v4()),
+                prepare: prepare_to(destination),
+            })
+            .await;
+        let forwarded = requests.lock().unwrap().len();
+        (result, forwarded)
+    }
+
+    #[tokio::test]
+    async fn strict_forwards_valid_destinations() {
+        let longest = long_address(1023);
+        for destination in VALID
+            .iter()
+            .chain(BORDERLINE_VALID)
+            .copied()
+            .chain(std::iter::once(longest.as_str()))
+        {
+            let (result, forwarded) = validate(AddressValidation::Strict, destination).await;
+            assert!(result.is_ok(), "destination: {}", destination);
+            assert_eq!(forwarded, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn strict_rejects_invalid_destinations() {
+        let too_long = long_address(1024);
+        for destination in BORDERLINE_INVALID
+            .iter()
+            .chain(CLEARLY_INVALID)
+            .copied()
+            .chain(std::iter::once(too_long.as_str()))
+        {
+            let (result, forwarded) = validate(AddressValidation::Strict, destination).await;
+            let reject = result.unwrap_err();
+            assert_eq!(
+                reject.code(),
+                ErrorCode::F01_INVALID_PACKET,
+                "destination: {}",
+                destination
+            );
+            assert_eq!(reject.triggered_by().unwrap(), TestStore.get_ilp_address());
+            assert_eq!(forwarded, 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn lenient_forwards_all_destinations() {
+        let longest = long_address(1023);
+        let too_long = long_address(1024);
+        for destination in VALID
+            .iter()
+            .chain(BORDERLINE_VALID)
+            .chain(BORDERLINE_INVALID)
+            .chain(CLEARLY_INVALID)
+            .copied()
+            .chain(vec![longest.as_str(), too_long.as_str()])
+        {
+            let (result, forwarded) = validate(AddressValidation::Lenient, destination).await;
+            assert!(result.is_ok(), "destination: {}", destination);
+            assert_eq!(forwarded, 1);
+        }
+    }
+
+    #[test]
+    fn defaults_to_strict() {
+        assert_eq!(AddressValidation::default(), AddressValidation::Strict);
+    }
+}
+
 #[cfg(test)]
 mod outgoing {
     use super::*;
@@ -410,4 +634,80 @@ mod outgoing {
             ErrorCode::F09_INVALID_PEER_RESPONSE
         );
     }
+
+    fn validator_returning(
+        response: IlpResult,
+    ) -> ValidatorService<impl OutgoingService<TestAccount> + Clone, TestStore, TestAccount> {
+        ValidatorService::outgoing(TestStore, outgoing_service_fn(move |_| response.clone()))
+    }
+
+    fn request() -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: TestAccount(Uuid::new_v4()),
+            to: TestAccount(Uuid::new_v4()),
+            original_amount: 100,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[
+                    102, 104, 122, 173, 248, 98, 189, 119, 108, 143, 193, 139, 142, 159, 142, 32,
+                    8, 151, 20, 133, 110, 226, 51, 179, 144, 42, 89, 29, 13, 95, 41, 37,
+                ],
+                data: b"test data",
+            }
+            .build(),
+        }
+    }
+
+    #[tokio::test]
+    async fn lets_through_responses_with_data_within_the_limit() {
+        let fulfill = FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: &[1; MAX_RESPONSE_DATA_LEN],
+        }
+        .build();
+        let mut validator = validator_returning(Ok(fulfill));
+        let fulfill = validator.send_request(request()).await.unwrap();
+        assert_eq!(fulfill.data().len(), MAX_RESPONSE_DATA_LEN);
+
+        let reject = RejectBuilder {
+            code: ErrorCode::F99_APPLICATION_ERROR,
+            message: &[],
+            triggered_by: None,
+            data: &[1; 100],
+        }
+        .build();
+        let mut validator = validator_returning(Err(reject));
+        validator.max_response_data_len(100);
+        let reject = validator.send_request(request()).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F99_APPLICATION_ERROR);
+        assert_eq!(reject.data().len(), 100);
+    }
+
+    #[tokio::test]
+    async fn rejects_responses_with_data_over_the_limit() {
+        let fulfill = FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: &[1; 101],
+        }
+        .build();
+        let mut validator = validator_returning(Ok(fulfill));
+        validator.max_response_data_len(100);
+        let reject = validator.send_request(request()).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F09_INVALID_PEER_RESPONSE);
+        assert!(reject.data().is_empty());
+
+        let reject = RejectBuilder {
+            code: ErrorCode::F99_APPLICATION_ERROR,
+            message: &[],
+            triggered_by: None,
+            data: &[1; 101],
+        }
+        .build();
+        let mut validator = validator_returning(Err(reject));
+        validator.max_response_data_len(100);
+        let reject = validator.send_request(request()).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F09_INVALID_PEER_RESPONSE);
+    }
 }
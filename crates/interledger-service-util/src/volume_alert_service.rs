@@ -0,0 +1,357 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::*;
+use log::{error, warn};
+use serde::Serialize;
+use std::{marker::PhantomData, sync::Arc, time::Duration};
+use uuid::Uuid;
+
+/// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the
+/// outgoing volume above which the node raises an alert
+pub trait VolumeAlertAccount: Account {
+    /// The amount, denominated in the account's asset, which may be sent to this account
+    /// within each window before an alert is raised. Defaults to no threshold.
+    fn volume_alert_threshold(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Store trait which keeps count of the amounts sent to each account
+#[async_trait]
+pub trait VolumeStore {
+    /// Returns the amount sent to the account in the current window of the given length
+    async fn get_outgoing_volume(&self, account_id: Uuid, window: Duration) -> Result<u64, ()>;
+
+    /// Adds the amount to the volume sent to the account in the current window of the
+    /// given length and returns the new volume. The volume is reset when the window rolls.
+    async fn add_outgoing_volume(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        window: Duration,
+    ) -> Result<u64, ()>;
+}
+
+/// The alert which is raised when the volume sent to an account crosses its threshold
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VolumeAlert {
+    /// The id of the account the volume was sent to
+    pub account_id: Uuid,
+    /// The username of the account the volume was sent to
+    pub username: Username,
+    /// The amount sent to the account in the current window, denominated in its asset
+    pub volume: u64,
+    /// The account's threshold
+    pub threshold: u64,
+    /// The length of the window in seconds
+    pub window: u64,
+    /// The time the threshold was crossed in RFC3339 format
+    pub timestamp: String,
+}
+
+type VolumeAlertCallback = Arc<dyn Fn(VolumeAlert) + Send + Sync>;
+
+/// # Volume Alert Service
+///
+/// Outgoing Service which counts the amounts of the fulfilled packets sent to each account
+/// with a [volume alert threshold](./trait.VolumeAlertAccount.html#method.volume_alert_threshold)
+/// and calls the configured callback once per window when the volume crosses the threshold.
+/// Optionally, further packets to the account are rejected with `T04_INSUFFICIENT_LIQUIDITY`
+/// until the window rolls.
+///
+/// Requires a `VolumeAlertAccount` and a `VolumeStore`.
+#[derive(Clone)]
+pub struct VolumeAlertService<S, O, A> {
+    store: S,
+    next: O,
+    window: Duration,
+    reject_over_threshold: bool,
+    callback: Option<VolumeAlertCallback>,
+    account_type: PhantomData<A>,
+}
+
+impl<S, O, A> VolumeAlertService<S, O, A>
+where
+    S: AddressStore + VolumeStore,
+    O: OutgoingService<A>,
+    A: VolumeAlertAccount,
+{
+    /// Creates a service which counts the volume sent to each account over windows of the given length
+    pub fn new(store: S, window: Duration, next: O) -> Self {
+        VolumeAlertService {
+            store,
+            next,
+            window,
+            reject_over_threshold: false,
+            callback: None,
+            account_type: PhantomData,
+        }
+    }
+
+    /// Rejects the packets to accounts whose volume crossed the threshold until the window rolls
+    pub fn reject_over_threshold(&mut self, reject: bool) -> &mut Self {
+        self.reject_over_threshold = reject;
+        self
+    }
+
+    /// Sets the function which is called with every alert
+    pub fn on_alert<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(VolumeAlert) + Send + Sync + 'static,
+    {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+}
+
+#[async_trait]
+impl<S, O, A> OutgoingService<A> for VolumeAlertService<S, O, A>
+where
+    S: AddressStore + VolumeStore + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Send + Sync + Clone + 'static,
+    A: VolumeAlertAccount + Send + Sync + 'static,
+{
+    /// On send request:
+    /// 1. If rejecting is enabled, rejects the request if the volume sent to the account
+    ///    in the current window already reached its threshold
+    /// 1. Forwards the request and, if it was fulfilled, adds its amount to the volume in
+    ///    the background. If that makes the volume cross the threshold, the alert is raised
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        let threshold = match request.to.volume_alert_threshold() {
+            Some(threshold) => threshold,
+            None => return self.next.send_request(request).await,
+        };
+        let to = request.to.clone();
+        let amount = request.prepare.amount();
+
+        if self.reject_over_threshold {
+            match self.store.get_outgoing_volume(to.id(), self.window).await {
+                Ok(volume) if volume >= threshold => {
+                    warn!(
+                        "Rejecting packet to account {} because the volume sent to it reached its alert threshold of {}",
+                        to.id(),
+                        threshold
+                    );
+                    return Err(RejectBuilder {
+                        code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                        message: b"Outgoing volume threshold reached",
+                        triggered_by: Some(&self.store.get_ilp_address()),
+                        data: &[],
+                    }
+                    .build());
+                }
+                Ok(_) => {}
+                // Packets are not rejected just because the volume is unknown
+                Err(_) => error!("Error loading the outgoing volume of account {}", to.id()),
+            }
+        }
+
+        let result = self.next.send_request(request).await;
+
+        if result.is_ok() && amount > 0 {
+            let store = self.store.clone();
+            let window = self.window;
+            let callback = self.callback.clone();
+            tokio::spawn(async move {
+                let volume = store
+                    .add_outgoing_volume(to.id(), amount, window)
+                    .await
+                    .map_err(|_| {
+                        error!("Error adding to the outgoing volume of account {}", to.id())
+                    })?;
+                // Only the packet which crosses the threshold raises the alert
+                if volume >= threshold && volume - amount < threshold {
+                    warn!(
+                        "Volume sent to account {} ({}) crossed its alert threshold: {} >= {}",
+                        to.username(),
+                        to.id(),
+                        volume,
+                        threshold
+                    );
+                    if let Some(callback) = callback {
+                        callback(VolumeAlert {
+                            account_id: to.id(),
+                            username: to.username().clone(),
+                            volume,
+                            threshold,
+                            window: window.as_secs(),
+                            timestamp: Utc::now().to_rfc3339(),
+                        });
+                    }
+                }
+                Ok::<(), ()>(())
+            });
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static ALICE_ID: Lazy<Uuid> = Lazy::new(Uuid::new_v4);
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[tokio::test]
+    async fn lets_through_volume_under_the_threshold() {
+        let (mut service, store, alerts) = test_service(true);
+        for _ in 0..2 {
+            service.send_request(test_request(400)).await.unwrap();
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        assert_eq!(*store.volume.lock(), 800);
+        assert!(alerts.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn alerts_once_when_crossing_the_threshold() {
+        let (mut service, store, alerts) = test_service(false);
+        for _ in 0..4 {
+            service.send_request(test_request(400)).await.unwrap();
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        // Packets are still forwarded if rejecting is not enabled
+        assert_eq!(*store.volume.lock(), 1600);
+        let alerts = alerts.lock();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].account_id, *ALICE_ID);
+        assert_eq!(alerts[0].username, *ALICE);
+        assert_eq!(alerts[0].volume, 1200);
+        assert_eq!(alerts[0].threshold, 1000);
+        assert_eq!(alerts[0].window, 60);
+    }
+
+    #[tokio::test]
+    async fn rejects_once_the_threshold_is_reached() {
+        let (mut service, store, alerts) = test_service(true);
+        for _ in 0..3 {
+            service.send_request(test_request(400)).await.unwrap();
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        assert_eq!(alerts.lock().len(), 1);
+
+        let reject = service.send_request(test_request(400)).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T04_INSUFFICIENT_LIQUIDITY);
+        assert_eq!(reject.triggered_by().unwrap(), *EXAMPLE_ADDRESS);
+        assert_eq!(*store.volume.lock(), 1200);
+
+        // Packets are let through again once the window rolled
+        *store.volume.lock() = 0;
+        service.send_request(test_request(400)).await.unwrap();
+    }
+
+    fn test_service(
+        reject: bool,
+    ) -> (
+        VolumeAlertService<TestStore, impl OutgoingService<TestAccount> + Clone, TestAccount>,
+        TestStore,
+        Arc<Mutex<Vec<VolumeAlert>>>,
+    ) {
+        let store = TestStore::default();
+        let next = outgoing_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        });
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let alerts_clone = alerts.clone();
+        let mut service = VolumeAlertService::new(store.clone(), Duration::from_secs(60), next);
+        service
+            .reject_over_threshold(reject)
+            .on_alert(move |alert| alerts_clone.lock().push(alert));
+        (service, store, alerts)
+    }
+
+    fn test_request(amount: u64) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: TestAccount,
+            to: TestAccount,
+            original_amount: amount,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            *ALICE_ID
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    impl VolumeAlertAccount for TestAccount {
+        fn volume_alert_threshold(&self) -> Option<u64> {
+            Some(1000)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct TestStore {
+        volume: Arc<Mutex<u64>>,
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            EXAMPLE_ADDRESS.clone()
+        }
+    }
+
+    #[async_trait]
+    impl VolumeStore for TestStore {
+        async fn get_outgoing_volume(&self, _: Uuid, _: Duration) -> Result<u64, ()> {
+            Ok(*self.volume.lock())
+        }
+
+        async fn add_outgoing_volume(&self, _: Uuid, amount: u64, _: Duration) -> Result<u64, ()> {
+            let mut volume = self.volume.lock();
+            *volume += amount;
+            Ok(*volume)
+        }
+    }
+}
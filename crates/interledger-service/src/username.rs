@@ -17,7 +17,7 @@ static USERNAME_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\w{2,32}$").un
 /// 2. Checks the string is 2-32 word characters only (no special characters except `_`)
 /// 3. Uses [case folding](https://www.w3.org/International/wiki/Case_folding) to convert to lowercase in a language-aware manner
 #[derive(Clone, Debug, Serialize, Deserialize, PartialOrd, Ord)]
-#[serde(try_from = "&str")]
+#[serde(try_from = "String")]
 pub struct Username(String);
 
 impl PartialEq for Username {
@@ -57,6 +57,14 @@ impl TryFrom<&str> for Username {
     }
 }
 
+impl TryFrom<String> for Username {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Username, String> {
+        Username::from_str(&value)
+    }
+}
+
 impl FromStr for Username {
     type Err = String;
 
@@ -97,6 +105,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn deserializes_owned_and_borrowed_strings() {
+        let expected = Username::from_str("alice").unwrap();
+        assert_eq!(
+            serde_json::from_value::<Username>(serde_json::json!("alice")).unwrap(),
+            expected
+        );
+        assert_eq!(
+            serde_json::from_str::<Username>("\"alice\"").unwrap(),
+            expected
+        );
+        assert!(serde_json::from_value::<Username>(serde_json::json!("a")).is_err());
+    }
+
     #[test]
     fn too_long_name() {
         assert!(Username::from_str("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa").is_err());
@@ -36,6 +36,12 @@ where
             account_type: PhantomData,
         }
     }
+
+    /// Sets the client used to forward messages to the settlement engines
+    pub fn settlement_client(&mut self, client: SettlementClient) -> &mut Self {
+        self.client = client;
+        self
+    }
 }
 
 #[async_trait]
@@ -8,7 +8,7 @@ pub mod backends_common;
 pub mod engines_api;
 
 mod settlement_client;
-pub use settlement_client::SettlementClient;
+pub use settlement_client::{EngineHealth, HealthCheckPolicy, RetryPolicy, SettlementClient};
 
 /// Expose useful utilities for implementing idempotent functionalities
 pub mod idempotency;
@@ -1,8 +1,11 @@
 use crate::core::types::Quantity;
-use futures_retry::{ErrorHandler, FutureRetry, RetryPolicy};
-use log::{debug, trace};
-use reqwest::Client;
+use futures_retry::{ErrorHandler, FutureRetry, RetryPolicy as RetryDecision};
+use log::{debug, info, trace, warn};
+use reqwest::{Certificate, Client};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use url::Url;
 use uuid::Uuid;
@@ -14,20 +17,247 @@ static ACCOUNTS_ENDPOINT: &str = "accounts";
 const MAX_RETRIES: usize = 10;
 const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_millis(5000);
 
+/// Determines how failed requests to a settlement engine are retried.
+///
+/// The n-th retry is made after `base_backoff * 2^(n - 1)` milliseconds, but at most
+/// `max_backoff` milliseconds, plus a random delay of up to `jitter` milliseconds. Requests which the engine rejected with a 4xx
+/// status are never retried. Retries reuse the original request's idempotency key, so
+/// the engine processes each settlement and message at most once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub struct RetryPolicy {
+    /// The number of times a failed request is retried. Defaults to 10.
+    #[serde(default = "RetryPolicy::default_max_retries")]
+    pub max_retries: usize,
+    /// Delay before the first retry, defined in milliseconds. Defaults to 1000ms.
+    #[serde(default = "RetryPolicy::default_base_backoff")]
+    pub base_backoff: u64,
+    /// Longest delay between two retries (without the jitter), defined in milliseconds,
+    /// so that a failing request does not stay in flight for long. Defaults to 5000ms.
+    #[serde(default = "RetryPolicy::default_max_backoff")]
+    pub max_backoff: u64,
+    /// Maximum random delay, defined in milliseconds, which is added to every backoff
+    /// so that requests failing at the same time are not all retried at once. Defaults to 500ms.
+    #[serde(default = "RetryPolicy::default_jitter")]
+    pub jitter: u64,
+}
+
+impl RetryPolicy {
+    fn default_max_retries() -> usize {
+        MAX_RETRIES
+    }
+    fn default_base_backoff() -> u64 {
+        1000
+    }
+    fn default_max_backoff() -> u64 {
+        5000
+    }
+    fn default_jitter() -> u64 {
+        500
+    }
+
+    /// The delay before the given (1-indexed) retry
+    fn backoff(&self, retry: usize) -> Duration {
+        let exponent = (retry.saturating_sub(1)).min(31) as u32;
+        let backoff = self
+            .base_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        let jitter = if self.jitter > 0 {
+            rand::random::<u64>() % (self.jitter + 1)
+        } else {
+            0
+        };
+        Duration::from_millis(backoff.saturating_add(jitter))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: RetryPolicy::default_max_retries(),
+            base_backoff: RetryPolicy::default_base_backoff(),
+            max_backoff: RetryPolicy::default_max_backoff(),
+            jitter: RetryPolicy::default_jitter(),
+        }
+    }
+}
+
+/// Determines how often the settlement engines are probed and when they are considered unhealthy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub struct HealthCheckPolicy {
+    /// Interval between the probes of each engine, defined in milliseconds. Defaults to 30000ms.
+    #[serde(default = "HealthCheckPolicy::default_interval")]
+    pub interval: u64,
+    /// The number of consecutive failed probes after which an engine is considered
+    /// unhealthy. Defaults to 3.
+    #[serde(default = "HealthCheckPolicy::default_failure_threshold")]
+    pub failure_threshold: usize,
+}
+
+impl HealthCheckPolicy {
+    fn default_interval() -> u64 {
+        30000
+    }
+    fn default_failure_threshold() -> usize {
+        3
+    }
+}
+
+impl Default for HealthCheckPolicy {
+    fn default() -> Self {
+        HealthCheckPolicy {
+            interval: HealthCheckPolicy::default_interval(),
+            failure_threshold: HealthCheckPolicy::default_failure_threshold(),
+        }
+    }
+}
+
+/// The outcome of the recent health probes of a settlement engine
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EngineHealth {
+    /// Whether settlements are sent to the engine
+    pub healthy: bool,
+    /// The number of probes which failed since the engine last responded
+    pub consecutive_failures: usize,
+    /// Why the last probe failed, if it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
 /// Helper struct to execute settlements
 #[derive(Clone)]
 pub struct SettlementClient {
     /// Asynchronous reqwest client
     client: Client,
-    max_retries: usize,
+    timeout: Duration,
+    retry_policy: RetryPolicy,
+    failure_threshold: usize,
+    /// The health of the probed engines, shared by all clones of the client
+    health: Arc<RwLock<HashMap<Url, EngineHealth>>>,
 }
 
 impl SettlementClient {
     /// Simple constructor
     pub fn new(timeout: Duration, max_retries: usize) -> Self {
+        SettlementClient::with_timeout_and_retry_policy(
+            timeout,
+            RetryPolicy {
+                max_retries,
+                ..RetryPolicy::default()
+            },
+        )
+    }
+
+    /// Creates a client which retries requests according to the provided policy
+    pub fn with_timeout_and_retry_policy(timeout: Duration, retry_policy: RetryPolicy) -> Self {
         SettlementClient {
             client: Client::builder().timeout(timeout).build().unwrap(),
-            max_retries,
+            timeout,
+            retry_policy,
+            failure_threshold: HealthCheckPolicy::default_failure_threshold(),
+            health: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Sets the number of consecutive failed probes after which an engine is considered unhealthy
+    pub fn health_failure_threshold(&mut self, failure_threshold: usize) -> &mut Self {
+        self.failure_threshold = failure_threshold;
+        self
+    }
+
+    /// Trust the given certificate authorities (in addition to the built-in root certificates)
+    /// when connecting to engines with `https://` URLs
+    pub fn root_certificates(&mut self, certificates: Vec<Certificate>) -> &mut Self {
+        self.client = certificates
+            .into_iter()
+            .fold(
+                Client::builder().timeout(self.timeout),
+                reqwest::ClientBuilder::add_root_certificate,
+            )
+            .build()
+            .unwrap();
+        self
+    }
+
+    /// Creates a client with the default timeout which retries requests according to the provided policy
+    pub fn with_retry_policy(retry_policy: RetryPolicy) -> Self {
+        SettlementClient::with_timeout_and_retry_policy(DEFAULT_HTTP_TIMEOUT, retry_policy)
+    }
+
+    /// Returns whether settlements should be sent to the engine. Engines which
+    /// were never probed are considered healthy.
+    pub fn is_engine_healthy(&self, engine_url: &Url) -> bool {
+        self.health
+            .read()
+            .unwrap()
+            .get(engine_url)
+            .map(|health| health.healthy)
+            .unwrap_or(true)
+    }
+
+    /// Returns the health of every engine which was probed
+    pub fn engines_health(&self) -> HashMap<Url, EngineHealth> {
+        self.health.read().unwrap().clone()
+    }
+
+    /// Probes the engine once (without retrying) by sending a GET request to its URL, and
+    /// returns whether it is healthy afterwards. Any response other than a 5xx counts as
+    /// the engine being reachable. The engine is marked unhealthy once the configured number
+    /// of consecutive probes failed, and healthy again as soon as one succeeds.
+    pub async fn probe_engine(&self, engine_url: Url) -> bool {
+        let error = match self.client.get(engine_url.as_ref()).send().await {
+            Ok(response) if response.status().is_server_error() => {
+                Some(format!("Responded with HTTP code: {}", response.status()))
+            }
+            Ok(_) => None,
+            Err(err) => Some(err.to_string()),
+        };
+
+        let mut health = self.health.write().unwrap();
+        let health = health.entry(engine_url.clone()).or_insert(EngineHealth {
+            healthy: true,
+            consecutive_failures: 0,
+            last_error: None,
+        });
+        match error {
+            Some(error) => {
+                health.consecutive_failures += 1;
+                debug!(
+                    "Health probe of settlement engine {} failed ({} consecutive failures): {}",
+                    engine_url, health.consecutive_failures, error
+                );
+                if health.healthy && health.consecutive_failures >= self.failure_threshold {
+                    warn!("Settlement engine {} is unhealthy, pausing settlements to it until it recovers. Last error: {}", engine_url, error);
+                    health.healthy = false;
+                }
+                health.last_error = Some(error);
+            }
+            None => {
+                if !health.healthy {
+                    info!(
+                        "Settlement engine {} recovered, resuming settlements to it",
+                        engine_url
+                    );
+                }
+                *health = EngineHealth {
+                    healthy: true,
+                    consecutive_failures: 0,
+                    last_error: None,
+                };
+            }
+        }
+        health.healthy
+    }
+
+    /// Probes each of the engines once. Engines which are not among them are no
+    /// longer tracked (and considered healthy).
+    pub async fn probe_engines(&self, engine_urls: HashSet<Url>) {
+        self.health
+            .write()
+            .unwrap()
+            .retain(|url, _| engine_urls.contains(url));
+        for engine_url in engine_urls {
+            self.probe_engine(engine_url).await;
         }
     }
 
@@ -35,8 +265,8 @@ impl SettlementClient {
     /// This is done by sending a POST to /accounts with the provided `id` as the request's body
     pub async fn create_engine_account(&self, id: Uuid, engine_url: Url) -> Response {
         FutureRetry::new(
-            move || self.create_engine_account_once(id.clone(), engine_url.clone()),
-            RequestErrorHandler::new(self.max_retries),
+            move || self.create_engine_account_once(id, engine_url.clone()),
+            RequestErrorHandler::new(self.retry_policy),
         )
         .await
     }
@@ -45,14 +275,29 @@ impl SettlementClient {
     /// This is done by sending a POST to /accounts/:id/messages with the provided `message`
     /// as the request's body
     pub async fn send_message(&self, id: Uuid, engine_url: Url, message: Vec<u8>) -> Response {
+        // Every retry uses the same idempotency key so the message is only processed once
+        let idempotency_key = Uuid::new_v4().to_hyphenated().to_string();
         FutureRetry::new(
-            move || self.send_message_once(id.clone(), engine_url.clone(), message.clone()),
-            RequestErrorHandler::new(self.max_retries),
+            move || {
+                self.send_message_once(
+                    id,
+                    engine_url.clone(),
+                    message.clone(),
+                    idempotency_key.clone(),
+                )
+            },
+            RequestErrorHandler::new(self.retry_policy),
         )
         .await
     }
 
-    async fn send_message_once(&self, id: Uuid, engine_url: Url, message: Vec<u8>) -> Response {
+    async fn send_message_once(
+        &self,
+        id: Uuid,
+        engine_url: Url,
+        message: Vec<u8>,
+        idempotency_key: String,
+    ) -> Response {
         // The `Prepare` packet's data was sent by the peer's settlement
         // engine so we assume it is in a format that our settlement engine
         // will understand
@@ -64,12 +309,11 @@ impl SettlementClient {
             .push("accounts")
             .push(&id.to_string())
             .push("messages");
-        let idempotency_uuid = uuid::Uuid::new_v4().to_hyphenated().to_string();
         self.client
             .post(settlement_engine_url.as_ref())
             .header("Content-Type", "application/octet-stream")
-            .header("Idempotency-Key", idempotency_uuid.clone())
-            .body(message.clone())
+            .header("Idempotency-Key", idempotency_key)
+            .body(message)
             .send()
             .await
     }
@@ -84,9 +328,19 @@ impl SettlementClient {
         amount: u64,
         asset_scale: u8,
     ) -> Response {
+        // Every retry uses the same idempotency key so the engine settles the amount only once
+        let idempotency_key = Uuid::new_v4().to_hyphenated().to_string();
         FutureRetry::new(
-            move || self.send_settlement_once(id, engine_url.clone(), amount, asset_scale),
-            RequestErrorHandler::new(self.max_retries),
+            move || {
+                self.send_settlement_once(
+                    id,
+                    engine_url.clone(),
+                    amount,
+                    asset_scale,
+                    idempotency_key.clone(),
+                )
+            },
+            RequestErrorHandler::new(self.retry_policy),
         )
         .await
     }
@@ -112,12 +366,13 @@ impl SettlementClient {
             .await?)
     }
 
-    pub async fn send_settlement_once(
+    async fn send_settlement_once(
         &self,
         id: Uuid,
         engine_url: Url,
         amount: u64,
         asset_scale: u8,
+        idempotency_key: String,
     ) -> Response {
         let mut settlement_engine_url = engine_url;
 
@@ -133,14 +388,12 @@ impl SettlementClient {
             amount, settlement_engine_url
         );
 
-        // Mark the request as idempotent
-        let idempotency_uuid = Uuid::new_v4().to_hyphenated().to_string();
-
         // Make the POST request future
         let response = self
             .client
             .post(settlement_engine_url.as_ref())
-            .header("Idempotency-Key", idempotency_uuid)
+            // Mark the request as idempotent
+            .header("Idempotency-Key", idempotency_key)
             .json(&json!(Quantity::new(amount, asset_scale)))
             .send()
             .await?;
@@ -150,14 +403,14 @@ impl SettlementClient {
 }
 
 struct RequestErrorHandler {
-    max_attempts: usize,
+    policy: RetryPolicy,
     current_attempt: usize,
 }
 
 impl RequestErrorHandler {
-    fn new(max_attempts: usize) -> Self {
+    fn new(policy: RetryPolicy) -> Self {
         RequestErrorHandler {
-            max_attempts,
+            policy,
             current_attempt: 0,
         }
     }
@@ -167,43 +420,43 @@ impl ErrorHandler<reqwest::Error> for RequestErrorHandler {
     type OutError = reqwest::Error;
 
     /// Handler of errors for the retry logic
-    fn handle(&mut self, e: reqwest::Error) -> RetryPolicy<reqwest::Error> {
+    fn handle(&mut self, e: reqwest::Error) -> RetryDecision<reqwest::Error> {
         self.current_attempt += 1;
-        if self.current_attempt > self.max_attempts {
-            return RetryPolicy::ForwardError(e);
+        if self.current_attempt > self.policy.max_retries {
+            return RetryDecision::ForwardError(e);
         }
-        if e.is_timeout() {
-            RetryPolicy::WaitRetry(Duration::from_secs(5))
-        } else if let Some(status) = e.status() {
-            if status.is_client_error() {
-                // do not retry 4xx
-                RetryPolicy::ForwardError(e)
-            } else if status.is_server_error() {
-                // Retry 5xx every 5 seconds
-                RetryPolicy::WaitRetry(Duration::from_secs(5))
-            } else {
-                // Otherwise just retry every second
-                RetryPolicy::WaitRetry(Duration::from_secs(1))
+        match e.status() {
+            // do not retry 4xx
+            Some(status) if status.is_client_error() => RetryDecision::ForwardError(e),
+            // Timeouts, 5xx and connection errors (e.g. because the engine
+            // has not started yet) are retried with exponential backoff
+            _ => {
+                let backoff = self.policy.backoff(self.current_attempt);
+                debug!(
+                    "Request to settlement engine failed: {}. Retrying in {:?}",
+                    e, backoff
+                );
+                RetryDecision::WaitRetry(backoff)
             }
-        } else {
-            // Retry other errors slightly more frequently since they may be
-            // related to the engine not having started yet
-            RetryPolicy::WaitRetry(Duration::from_secs(1))
         }
     }
 }
 
 impl Default for SettlementClient {
     fn default() -> Self {
-        SettlementClient::new(DEFAULT_HTTP_TIMEOUT, MAX_RETRIES)
+        SettlementClient::with_retry_policy(RetryPolicy::default())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use http::StatusCode;
     use mockito::{mock, Matcher};
     use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use warp::Filter;
 
     pub static SETTLEMENT_API: Lazy<Matcher> = Lazy::new(|| {
         Matcher::Regex(r"^/accounts/[0-9A-Fa-f]{8}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{12}/settlements$".to_string())
@@ -242,7 +495,8 @@ mod tests {
             .match_header("Idempotency-Key", Matcher::Any)
             .create()
             .expect(2); // It will hit it twice because it will retry
-        let client = SettlementClient::new(Duration::from_secs(1), 1);
+        let client =
+            SettlementClient::with_timeout_and_retry_policy(Duration::from_secs(1), test_policy(1));
 
         let ret = client
             .send_settlement(
@@ -256,4 +510,175 @@ mod tests {
         m.assert();
         assert!(ret.is_err());
     }
+
+    #[tokio::test]
+    async fn retries_until_engine_recovers() {
+        let (engine_url, idempotency_keys) = spawn_test_engine(2, StatusCode::SERVICE_UNAVAILABLE);
+        let client =
+            SettlementClient::with_timeout_and_retry_policy(Duration::from_secs(1), test_policy(2));
+
+        let ret = client
+            .send_settlement(Uuid::new_v4(), engine_url, 100, 6)
+            .await;
+
+        assert!(ret.is_ok());
+        let keys = idempotency_keys.lock();
+        assert_eq!(keys.len(), 3);
+        // All retries are sent with the idempotency key of the first attempt
+        assert!(keys.iter().all(|key| key == &keys[0]));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let (engine_url, idempotency_keys) = spawn_test_engine(3, StatusCode::SERVICE_UNAVAILABLE);
+        let client =
+            SettlementClient::with_timeout_and_retry_policy(Duration::from_secs(1), test_policy(2));
+
+        let ret = client
+            .send_settlement(Uuid::new_v4(), engine_url, 100, 6)
+            .await;
+
+        assert!(ret.is_err());
+        assert_eq!(idempotency_keys.lock().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let (engine_url, idempotency_keys) = spawn_test_engine(1, StatusCode::BAD_REQUEST);
+        let client =
+            SettlementClient::with_timeout_and_retry_policy(Duration::from_secs(1), test_policy(2));
+
+        let ret = client
+            .send_settlement(Uuid::new_v4(), engine_url, 100, 6)
+            .await;
+
+        assert!(ret.is_err());
+        assert_eq!(idempotency_keys.lock().len(), 1);
+    }
+
+    #[test]
+    fn backoff_is_exponential_with_bounded_jitter() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_backoff: 100,
+            max_backoff: 500,
+            jitter: 0,
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(500));
+        assert_eq!(policy.backoff(64), Duration::from_millis(500));
+
+        let policy = RetryPolicy {
+            jitter: 50,
+            ..policy
+        };
+        for _ in 0..100 {
+            let backoff = policy.backoff(1);
+            assert!(backoff >= Duration::from_millis(100));
+            assert!(backoff <= Duration::from_millis(150));
+        }
+    }
+
+    /// Spawns an engine which responds to the first `failures` requests with the
+    /// given status and then succeeds. Returns the engine's URL and the idempotency
+    /// keys of all requests it received.
+    fn spawn_test_engine(failures: usize, status: StatusCode) -> (Url, Arc<Mutex<Vec<String>>>) {
+        let idempotency_keys = Arc::new(Mutex::new(Vec::new()));
+        let idempotency_keys_clone = idempotency_keys.clone();
+        let engine = warp::post()
+            .and(warp::header::<String>("Idempotency-Key"))
+            .map(move |idempotency_key: String| {
+                let mut keys = idempotency_keys_clone.lock();
+                keys.push(idempotency_key);
+                if keys.len() <= failures {
+                    status
+                } else {
+                    StatusCode::OK
+                }
+            });
+        let (addr, server) = warp::serve(engine).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (
+            format!("http://{}", addr).parse().unwrap(),
+            idempotency_keys,
+        )
+    }
+
+    #[tokio::test]
+    async fn marks_engine_unhealthy_after_repeated_failures() {
+        let engine_url: Url = mockito::server_url().parse().unwrap();
+        let mut client = SettlementClient::default();
+        client.health_failure_threshold(2);
+        assert!(client.is_engine_healthy(&engine_url));
+
+        let engine_down = mock("GET", "/").with_status(503).create().expect(2);
+        assert!(client.probe_engine(engine_url.clone()).await);
+        assert!(!client.probe_engine(engine_url.clone()).await);
+        engine_down.assert();
+        assert!(!client.is_engine_healthy(&engine_url));
+        let health = client.engines_health()[&engine_url].clone();
+        assert_eq!(health.consecutive_failures, 2);
+        assert!(health.last_error.unwrap().contains("503"));
+
+        // The state is shared by the clones of the client
+        let clone = client.clone();
+        let _engine_up = mock("GET", "/").with_status(404).create();
+        assert!(clone.probe_engine(engine_url.clone()).await);
+        assert!(client.is_engine_healthy(&engine_url));
+        assert_eq!(
+            client.engines_health()[&engine_url],
+            EngineHealth {
+                healthy: true,
+                consecutive_failures: 0,
+                last_error: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn forgets_engines_which_are_no_longer_probed() {
+        let mut client =
+            SettlementClient::with_timeout_and_retry_policy(Duration::from_secs(1), test_policy(0));
+        client.health_failure_threshold(1);
+        // Nothing is listening on this port
+        let unreachable: Url = "http://127.0.0.1:1".parse().unwrap();
+        client
+            .probe_engines(vec![unreachable.clone()].into_iter().collect())
+            .await;
+        assert!(!client.is_engine_healthy(&unreachable));
+
+        client.probe_engines(HashSet::new()).await;
+        assert!(client.is_engine_healthy(&unreachable));
+        assert!(client.engines_health().is_empty());
+    }
+
+    #[test]
+    fn defaults_to_ten_retries() {
+        assert_eq!(RetryPolicy::default().max_retries, 10);
+        let policy: RetryPolicy = serde_json::from_str("{\"base_backoff\":10}").unwrap();
+        assert_eq!(policy.max_retries, 10);
+        assert_eq!(policy.base_backoff, 10);
+        assert_eq!(policy.max_backoff, 5000);
+    }
+
+    #[test]
+    fn default_policy_gives_up_within_a_minute() {
+        let policy = RetryPolicy::default();
+        let total_delay: Duration = (1..=policy.max_retries)
+            .map(|retry| policy.backoff(retry))
+            .sum();
+        assert!(total_delay <= Duration::from_secs(50), "{:?}", total_delay);
+        assert!(policy.backoff(policy.max_retries) <= Duration::from_millis(5500));
+    }
+
+    fn test_policy(max_retries: usize) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_backoff: 10,
+            max_backoff: 100,
+            jitter: 10,
+        }
+    }
 }
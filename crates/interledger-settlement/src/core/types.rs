@@ -101,12 +101,27 @@ pub struct SettlementEngineDetails {
     pub url: Url,
 }
 
+/// The asset an account settles in, if it differs from the asset its balance is denominated in
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettlementAsset {
+    /// Asset code of the settlement asset (e.g. a stablecoin)
+    pub asset_code: String,
+    /// Asset scale of the amounts sent to the settlement engine
+    pub asset_scale: u8,
+}
+
 /// Extension trait for [Account](../interledger_service/trait.Account.html) with [settlement](https://interledger.org/rfcs/0038-settlement-engines/) related information
 pub trait SettlementAccount: Account {
     /// The [SettlementEngineDetails](./struct.SettlementEngineDetails.html) (if any) associated with that account
     fn settlement_engine_details(&self) -> Option<SettlementEngineDetails> {
         None
     }
+
+    /// The [SettlementAsset](./struct.SettlementAsset.html) outgoing settlements are converted
+    /// into (and incoming ones from), if the account does not settle in its own asset
+    fn settlement_asset(&self) -> Option<SettlementAsset> {
+        None
+    }
 }
 
 #[async_trait]
@@ -192,6 +207,13 @@ pub struct ConvertDetails {
     pub to: u8,
 }
 
+impl ConvertDetails {
+    /// Returns the number of decimal places between the two scales
+    fn scale_diff(&self) -> u32 {
+        u32::from(self.from.max(self.to) - self.from.min(self.to))
+    }
+}
+
 /// Helper trait for u64 and f64 asset code conversions for amounts and rates
 pub trait Convert {
     type Item: Sized;
@@ -204,17 +226,15 @@ impl Convert for u64 {
     type Item = u64;
 
     fn normalize_scale(&self, details: ConvertDetails) -> Result<Self::Item, ()> {
-        let scale_diff = (details.from as i8 - details.to as i8).abs() as u8;
-        let scale = 10u64.pow(scale_diff.into());
-        let (res, overflow) = if details.to >= details.from {
-            self.overflowing_mul(scale)
-        } else {
-            self.overflowing_div(scale)
-        };
-        if overflow {
-            Err(())
+        let scale = 10u64.checked_pow(details.scale_diff());
+        if details.to >= details.from {
+            if *self == 0 {
+                return Ok(0);
+            }
+            scale.and_then(|scale| self.checked_mul(scale)).ok_or(())
         } else {
-            Ok(res)
+            // A scale larger than any u64 leaves nothing of the amount
+            Ok(scale.map_or(0, |scale| self / scale))
         }
     }
 }
@@ -224,14 +244,13 @@ impl Convert for f64 {
     // Not overflow safe. Would require using a package for Big floating point
     // numbers such as BigDecimal
     fn normalize_scale(&self, details: ConvertDetails) -> Result<Self::Item, ()> {
-        let scale_diff = (details.from as i8 - details.to as i8).abs() as u8;
-        let scale = 10f64.powi(scale_diff.into());
+        let scale = 10f64.powi(details.scale_diff() as i32);
         let res = if details.to >= details.from {
             self * scale
         } else {
             self / scale
         };
-        if res == std::f64::INFINITY {
+        if !res.is_finite() {
             Err(())
         } else {
             Ok(res)
@@ -243,8 +262,7 @@ impl Convert for BigUint {
     type Item = BigUint;
 
     fn normalize_scale(&self, details: ConvertDetails) -> Result<Self::Item, ()> {
-        let scale_diff = (details.from as i8 - details.to as i8).abs() as u8;
-        let scale = 10u64.pow(scale_diff.into());
+        let scale = num_traits::pow(BigUint::from(10u8), details.scale_diff() as usize);
         if details.to >= details.from {
             Ok(self.mul(scale))
         } else {
@@ -343,6 +361,22 @@ mod tests {
                 .unwrap(),
             1
         );
+
+        // scale differences too large for a u64 factor do not panic
+        assert!(1u64
+            .normalize_scale(ConvertDetails { from: 0, to: 20 })
+            .is_err());
+        assert_eq!(
+            0u64.normalize_scale(ConvertDetails { from: 0, to: 200 })
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            std::u64::MAX
+                .normalize_scale(ConvertDetails { from: 200, to: 0 })
+                .unwrap(),
+            0
+        );
     }
 
     #[allow(clippy::float_cmp)]
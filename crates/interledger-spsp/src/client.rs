@@ -3,17 +3,23 @@ use futures::TryFutureExt;
 use interledger_packet::Address;
 use interledger_rates::ExchangeRateStore;
 use interledger_service::{Account, IncomingService};
-use interledger_stream::{send_money, StreamDelivery};
+use interledger_stream::{send_money, RetryPolicy, StreamDelivery, StreamQuote};
 use log::{debug, error, trace};
 use reqwest::Client;
 use std::convert::TryFrom;
+use std::time::Duration;
 
 /// Get an ILP Address and shared secret by the receiver of this payment for this connection
 pub async fn query(server: &str) -> Result<SpspResponse, Error> {
+    query_with_client(&Client::new(), server).await
+}
+
+/// Like [`query`](./fn.query.html), but sends the request with the given HTTP client
+/// (for example one which trusts additional certificate authorities)
+pub async fn query_with_client(client: &Client, server: &str) -> Result<SpspResponse, Error> {
     let server = payment_pointer_to_url(server);
     trace!("Querying receiver: {}", server);
 
-    let client = Client::new();
     let res = client
         .get(&server)
         .header("Accept", "application/spsp4+json")
@@ -33,6 +39,12 @@ pub async fn query(server: &str) -> Result<SpspResponse, Error> {
 /// Query the details of the given Payment Pointer and send a payment using the STREAM protocol.
 ///
 /// This returns the amount delivered, as reported by the receiver and in the receiver's asset's units.
+/// Every Prepare expires after the given `packet_expiry`.
+/// If a `payment_timeout` is given, the payment stops once it elapsed and the receipt
+/// reports the amount that was sent and delivered by then.
+/// Temporary rejects are retried according to the `retry_policy`.
+/// The receiver is queried with the given HTTP client.
+#[allow(clippy::too_many_arguments)]
 pub async fn pay<I, A, S>(
     service: I,
     from_account: A,
@@ -40,13 +52,17 @@ pub async fn pay<I, A, S>(
     receiver: &str,
     source_amount: u64,
     slippage: f64,
+    packet_expiry: Duration,
+    payment_timeout: Option<Duration>,
+    retry_policy: RetryPolicy,
+    http_client: &Client,
 ) -> Result<StreamDelivery, Error>
 where
     I: IncomingService<A> + Clone + Send + Sync + 'static,
     A: Account + Send + Sync + 'static,
     S: ExchangeRateStore + Send + Sync + 'static,
 {
-    let spsp = query(receiver).await?;
+    let spsp = query_with_client(http_client, receiver).await?;
     let shared_secret = spsp.shared_secret;
     let dest = spsp.destination_account;
     let addr = Address::try_from(dest).map_err(move |err| {
@@ -63,6 +79,9 @@ where
         &shared_secret,
         source_amount,
         slippage,
+        packet_expiry,
+        payment_timeout,
+        retry_policy,
     )
     .map_err(move |err| {
         error!("Error sending payment: {:?}", err);
@@ -74,6 +93,55 @@ where
     Ok(receipt)
 }
 
+/// Query the details of the given Payment Pointer and estimate how much a STREAM payment of the
+/// source amount would deliver.
+///
+/// This only sends unfulfillable probe packets, so no money is moved.
+#[allow(clippy::too_many_arguments)]
+pub async fn quote<I, A, S>(
+    service: I,
+    from_account: A,
+    store: S,
+    receiver: &str,
+    source_amount: u64,
+    slippage: f64,
+    packet_expiry: Duration,
+    http_client: &Client,
+) -> Result<StreamQuote, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let spsp = query_with_client(http_client, receiver).await?;
+    let shared_secret = spsp.shared_secret;
+    let dest = spsp.destination_account;
+    let addr = Address::try_from(dest).map_err(move |err| {
+        error!("Error parsing address");
+        Error::InvalidSpspServerResponseError(err.to_string())
+    })?;
+    debug!("Quoting SPSP payment to address: {}", addr);
+
+    let quote = interledger_stream::quote(
+        service,
+        &from_account,
+        store,
+        addr,
+        &shared_secret,
+        source_amount,
+        slippage,
+        packet_expiry,
+    )
+    .map_err(move |err| {
+        error!("Error quoting payment: {:?}", err);
+        Error::StreamError(err)
+    })
+    .await?;
+
+    debug!("Got SPSP quote: {:?}", quote);
+    Ok(quote)
+}
+
 fn payment_pointer_to_url(payment_pointer: &str) -> String {
     let mut url: String = if payment_pointer.starts_with('$') {
         let mut url = "https://".to_string();
@@ -6,15 +6,16 @@
 //! authenticate ILP packets sent between them. SPSP uses the STREAM transport protocol for sending money and data over ILP.
 
 use interledger_packet::Address;
+use interledger_service::Account;
 use interledger_stream::Error as StreamError;
 use serde::{Deserialize, Serialize};
 
-/// An SPSP client which can query an SPSP Server's payment pointer and initiate a STREAM payment
+/// An SPSP client which can query an SPSP Server's payment pointer and quote or initiate a STREAM payment
 mod client;
 /// An SPSP Server implementing an HTTP Service which generates ILP Addresses and Shared Secrets
 mod server;
 
-pub use client::{pay, query};
+pub use client::{pay, query, query_with_client, quote};
 pub use server::SpspResponder;
 
 #[derive(Debug, thiserror::Error)]
@@ -33,6 +34,46 @@ pub enum Error {
     InvalidPaymentPointerError(String),
 }
 
+/// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the
+/// details returned to SPSP clients to help them display the amounts received by the account
+pub trait SpspDisplayAccount: Account {
+    /// The symbol of the currency amounts are displayed in, e.g. `$`. Defaults to none.
+    fn display_symbol(&self) -> Option<&str> {
+        None
+    }
+
+    /// The number of decimals amounts are displayed with. Defaults to none.
+    fn display_decimals(&self) -> Option<u8> {
+        None
+    }
+}
+
+/// Non-standard details included in SPSP responses to help wallets display amounts
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct DisplayMetadata {
+    /// The symbol of the currency amounts are displayed in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    /// The number of decimals amounts are displayed with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals: Option<u8>,
+}
+
+impl DisplayMetadata {
+    /// Returns the account's display metadata, if any of it is configured
+    pub fn from_account<A: SpspDisplayAccount>(account: &A) -> Option<Self> {
+        let metadata = DisplayMetadata {
+            symbol: account.display_symbol().map(str::to_string),
+            decimals: account.display_decimals(),
+        };
+        if metadata == DisplayMetadata::default() {
+            None
+        } else {
+            Some(metadata)
+        }
+    }
+}
+
 /// An SPSP Response returned by the SPSP server
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SpspResponse {
@@ -42,6 +83,16 @@ pub struct SpspResponse {
     /// to be consumed for the STREAM connection
     #[serde(with = "serde_base64")]
     shared_secret: Vec<u8>,
+    /// Optional details on how to display amounts, which clients ignoring them can skip
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display: Option<DisplayMetadata>,
+}
+
+impl SpspResponse {
+    /// Returns the details on how to display amounts, if the server included them
+    pub fn display(&self) -> Option<&DisplayMetadata> {
+        self.display.as_ref()
+    }
 }
 
 // From https://github.com/serde-rs/json/issues/360#issuecomment-330095360
@@ -1,4 +1,4 @@
-use super::SpspResponse;
+use super::{DisplayMetadata, SpspResponse};
 use bytes::Bytes;
 use hyper::{service::Service as HttpService, Body, Error, Request, Response};
 use interledger_packet::Address;
@@ -16,6 +16,7 @@ use std::{
 pub struct SpspResponder {
     ilp_address: Address,
     connection_generator: ConnectionGenerator,
+    display: Option<DisplayMetadata>,
 }
 
 impl SpspResponder {
@@ -26,9 +27,16 @@ impl SpspResponder {
         SpspResponder {
             ilp_address,
             connection_generator,
+            display: None,
         }
     }
 
+    /// Includes the details on how to display amounts in the responses
+    pub fn display(&mut self, display: DisplayMetadata) -> &mut Self {
+        self.display = Some(display);
+        self
+    }
+
     /// Returns an HTTP Response containing the destination account
     /// and shared secret for this connection
     /// These fields are generated via [Stream's `ConnectionGenerator`](../interledger_stream/struct.ConnectionGenerator.html#method.generate_address_and_secret)
@@ -43,6 +51,7 @@ impl SpspResponder {
         let response = SpspResponse {
             destination_account,
             shared_secret: shared_secret.to_vec(),
+            display: self.display.clone(),
         };
 
         Response::builder()
@@ -113,4 +122,32 @@ mod spsp_server_test {
             "max-age=60"
         );
     }
+
+    #[tokio::test]
+    async fn includes_display_metadata_if_configured() {
+        let addr = Address::from_str("example.receiver").unwrap();
+        let mut responder = SpspResponder::new(addr, Bytes::from(&[0; 32][..]));
+        let body = hyper::body::to_bytes(responder.generate_http_response().into_body())
+            .await
+            .unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(response.get("display").is_none());
+        assert!(response.get("destination_account").is_some());
+
+        responder.display(DisplayMetadata {
+            symbol: Some("$".to_string()),
+            decimals: Some(2),
+        });
+        let body = hyper::body::to_bytes(responder.generate_http_response().into_body())
+            .await
+            .unwrap();
+        let response: SpspResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            response.display(),
+            Some(&DisplayMetadata {
+                symbol: Some("$".to_string()),
+                decimals: Some(2),
+            })
+        );
+    }
 }
@@ -1,15 +1,24 @@
-use super::crypto::{decrypt_token, encrypt_token};
-use interledger_api::AccountDetails;
+use super::crypto::{decrypt_token, encrypt_token, hash_token, verify_token_hash};
+use http::header::HeaderName;
+use interledger_api::{AccountDetails, ExportedAccount, ExportedSecrets};
 use interledger_btp::BtpAccount;
 use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
 use interledger_errors::CreateAccountError;
-use interledger_http::HttpAccount;
+use interledger_http::{normalize_fingerprint, HttpAccount};
+use interledger_ildcp::IldcpAccount;
 use interledger_packet::Address;
+use interledger_router::RouterAccount;
 use interledger_service::{Account as AccountTrait, Username};
 use interledger_service_util::{
-    MaxPacketAmountAccount, RateLimitAccount, RoundTripTimeAccount, DEFAULT_ROUND_TRIP_TIME,
+    DestinationAllowlistAccount, MaxPacketAmountAccount, OutgoingConcurrencyAccount,
+    RateLimitAccount, RoundTripTimeAccount, RoundingPolicy, ShadowAccount, SpreadAccount,
+    VolumeAlertAccount, DEFAULT_ROUND_TRIP_TIME,
 };
-use interledger_settlement::core::types::{SettlementAccount, SettlementEngineDetails};
+use interledger_settlement::core::types::{
+    SettlementAccount, SettlementAsset, SettlementEngineDetails,
+};
+use interledger_spsp::SpspDisplayAccount;
+use interledger_stream::SourcePrefixAccount;
 use log::error;
 use ring::aead;
 use secrecy::{ExposeSecret, SecretBytesMut, SecretString};
@@ -38,6 +47,8 @@ pub struct Account {
     pub(crate) asset_scale: u8,
     /// The max amount per packet which can be routed for this account
     pub(crate) max_packet_amount: u64,
+    /// The smallest amount of the packets this account may send. Defaults to no minimum
+    pub(crate) min_packet_amount: Option<u64>,
     /// The minimum balance this account can have (consider this as a credit/trust limit)
     pub(crate) min_balance: Option<i64>,
     /// The account's ILP over HTTP URL (this is where packets are sent over HTTP from your node)
@@ -54,12 +65,21 @@ pub struct Account {
     /// This must match the ILP over HTTP incoming token on the peer's node if sending
     /// packets to that peer
     pub(crate) ilp_over_http_outgoing_token: Option<SecretBytesMut>,
+    /// The fingerprints of the TLS client certificates accepted instead of the
+    /// incoming ILP over HTTP token
+    #[serde(default)]
+    pub(crate) ilp_over_http_client_certificates: Vec<String>,
+    /// The header in which ILP over HTTP requests to this account carry the HMAC of the packet
+    pub(crate) ilp_over_http_signature_header: Option<String>,
+    #[serde(serialize_with = "optional_secret_bytes_to_utf8")]
+    /// The secret shared with the peer that ILP over HTTP requests are signed with
+    pub(crate) ilp_over_http_signature_secret: Option<SecretBytesMut>,
     /// The account's ILP over BTP URL (this is where packets are sent over WebSockets from your node)
     pub(crate) ilp_over_btp_url: Option<Url>,
     #[serde(serialize_with = "optional_secret_bytes_to_utf8")]
     /// The account's incoming ILP over BTP token.
     /// This must match the ILP over BTP outgoing token on the peer's node if exchanging
-    /// packets with that peer
+    /// packets with that peer. It is only ever compared, so it is stored hashed
     pub(crate) ilp_over_btp_incoming_token: Option<SecretBytesMut>,
     #[serde(serialize_with = "optional_secret_bytes_to_utf8")]
     /// The account's outgoing ILP over BTP token.
@@ -79,10 +99,45 @@ pub struct Account {
     pub(crate) packets_per_minute_limit: Option<u32>,
     /// The maximum amount the account can send per minute
     pub(crate) amount_per_minute_limit: Option<u64>,
+    /// The spread charged on packets received from this account. Overrides the
+    /// node's global exchange rate spread if set
+    pub(crate) spread: Option<f64>,
+    /// How the amounts of packets received from this account are rounded. Overrides
+    /// the node's global rounding policy if set
+    pub(crate) rounding: Option<RoundingPolicy>,
+    /// The share of the payments for prefixes reachable through several accounts
+    /// equally well that are sent through this account. Defaults to 1
+    pub(crate) routing_weight: Option<u32>,
+    /// The priority of the routes advertised by this account over the same routes
+    /// advertised by other accounts. Defaults to 0
+    pub(crate) route_priority: Option<u32>,
+    /// The amount which may be sent to this account within each volume alert window
+    /// before an alert is raised. Defaults to no threshold
+    pub(crate) volume_alert_threshold: Option<u64>,
+    /// The currency symbol returned to SPSP clients to display amounts
+    pub(crate) spsp_display_symbol: Option<String>,
+    /// The number of decimals returned to SPSP clients to display amounts
+    pub(crate) spsp_display_decimals: Option<u8>,
+    /// The prefix which the source addresses claimed in STREAM packets received from
+    /// this account must be within. Defaults to allowing any address
+    pub(crate) allowed_source_prefix: Option<Address>,
+    /// The prefixes which the destinations of the packets sent by this account must
+    /// be within. Defaults to allowing any destination
+    pub(crate) allowed_destination_prefixes: Vec<Address>,
+    /// The maximum number of requests sent to this account which are in flight at the
+    /// same time. Further requests are queued. Defaults to no limit
+    pub(crate) max_concurrent_outgoing_requests: Option<u32>,
+    /// The account which is sent a copy of every packet sent to this one, for validating
+    /// a new peer. Its responses are ignored. Defaults to none
+    pub(crate) shadow_account: Option<Username>,
     /// The account's settlement engine URL. If a global engine url is configured
     /// for the account's asset code,  that will be used instead (even if the account is
     /// configured with a specific one)
     pub(crate) settlement_engine_url: Option<Url>,
+    /// The asset settlements are converted into and from, if it is not the account's asset
+    pub(crate) settlement_asset_code: Option<String>,
+    /// The asset scale of the settlement asset. Defaults to the account's asset scale
+    pub(crate) settlement_asset_scale: Option<u8>,
 }
 
 fn address_to_string<S>(address: &Address, serializer: S) -> Result<S::Ok, S::Error>
@@ -131,6 +186,35 @@ impl Account {
             None
         };
 
+        let ilp_over_http_client_certificates = details
+            .ilp_over_http_client_certificates
+            .iter()
+            .map(|fingerprint| {
+                normalize_fingerprint(fingerprint).ok_or_else(|| {
+                    CreateAccountError::InvalidClientCertificateFingerprint(fingerprint.clone())
+                })
+            })
+            .collect::<Result<Vec<String>, CreateAccountError>>()?;
+
+        match (
+            &details.ilp_over_http_signature_header,
+            &details.ilp_over_http_signature_secret,
+        ) {
+            (Some(header), Some(_)) => {
+                HeaderName::from_bytes(header.as_bytes())
+                    .map_err(|_| CreateAccountError::InvalidSignatureHeader(header.clone()))?;
+            }
+            (None, None) => {}
+            _ => return Err(CreateAccountError::IncompleteSignatureKey),
+        }
+
+        if details.max_concurrent_outgoing_requests == Some(0) {
+            return Err(CreateAccountError::ParamOutOfRange(
+                "max_concurrent_outgoing_requests".to_string(),
+                "must be greater than 0".to_string(),
+            ));
+        }
+
         let routing_relation = if let Some(ref relation) = details.routing_relation {
             RoutingRelation::from_str(relation)
                 .map_err(|_| CreateAccountError::InvalidRoutingRelation(relation.to_string()))?
@@ -151,6 +235,7 @@ impl Account {
             asset_code: details.asset_code.to_uppercase(),
             asset_scale: details.asset_scale,
             max_packet_amount: details.max_packet_amount,
+            min_packet_amount: details.min_packet_amount,
             min_balance: details.min_balance,
             ilp_over_http_url,
             ilp_over_http_incoming_token: details
@@ -159,6 +244,11 @@ impl Account {
             ilp_over_http_outgoing_token: details
                 .ilp_over_http_outgoing_token
                 .map(|token| SecretBytesMut::new(token.expose_secret().as_str())),
+            ilp_over_http_client_certificates,
+            ilp_over_http_signature_header: details.ilp_over_http_signature_header,
+            ilp_over_http_signature_secret: details
+                .ilp_over_http_signature_secret
+                .map(|secret| SecretBytesMut::new(secret.expose_secret().as_str())),
             ilp_over_btp_url,
             ilp_over_btp_incoming_token: details
                 .ilp_over_btp_incoming_token
@@ -172,11 +262,27 @@ impl Account {
             round_trip_time: details.round_trip_time.unwrap_or(DEFAULT_ROUND_TRIP_TIME),
             packets_per_minute_limit: details.packets_per_minute_limit,
             amount_per_minute_limit: details.amount_per_minute_limit,
+            spread: details.spread,
+            rounding: details.rounding,
+            routing_weight: details.routing_weight,
+            route_priority: details.route_priority,
+            volume_alert_threshold: details.volume_alert_threshold,
+            spsp_display_symbol: details.spsp_display_symbol,
+            spsp_display_decimals: details.spsp_display_decimals,
+            allowed_source_prefix: details.allowed_source_prefix,
+            allowed_destination_prefixes: details.allowed_destination_prefixes,
+            max_concurrent_outgoing_requests: details.max_concurrent_outgoing_requests,
+            shadow_account: details.shadow_account,
             settlement_engine_url,
+            settlement_asset_code: details
+                .settlement_asset_code
+                .map(|code| code.to_uppercase()),
+            settlement_asset_scale: details.settlement_asset_scale,
         })
     }
 
-    /// Encrypts the account's incoming/outgoing BTP and HTTP keys with the provided encryption key
+    /// Encrypts the account's outgoing BTP and incoming/outgoing HTTP keys and its HTTP
+    /// signature secret with the provided encryption key. The incoming BTP key is hashed instead
     pub fn encrypt_tokens(
         mut self,
         encryption_key: &aead::LessSafeKey,
@@ -193,12 +299,16 @@ impl Account {
                 &token.expose_secret(),
             )));
         }
-        if let Some(ref token) = self.ilp_over_btp_incoming_token {
-            self.ilp_over_btp_incoming_token = Some(SecretBytesMut::from(encrypt_token(
+        if let Some(ref secret) = self.ilp_over_http_signature_secret {
+            self.ilp_over_http_signature_secret = Some(SecretBytesMut::from(encrypt_token(
                 encryption_key,
-                &token.expose_secret(),
+                &secret.expose_secret(),
             )));
         }
+        if let Some(ref token) = self.ilp_over_btp_incoming_token {
+            self.ilp_over_btp_incoming_token =
+                Some(SecretBytesMut::from(hash_token(&token.expose_secret())));
+        }
         if let Some(ref token) = self.ilp_over_http_incoming_token {
             self.ilp_over_http_incoming_token = Some(SecretBytesMut::from(encrypt_token(
                 encryption_key,
@@ -207,6 +317,108 @@ impl Account {
         }
         AccountWithEncryptedTokens { account: self }
     }
+
+    /// Exports the account with the provided balances. Its secrets are exported in the
+    /// form the account holds them in, so they are only encrypted if its tokens are
+    pub(crate) fn export(&self, balance: i64, prepaid_amount: i64) -> ExportedAccount {
+        let export_secret = |secret: &Option<SecretBytesMut>| {
+            secret.as_ref().map(|s| hex::encode(&s.expose_secret()))
+        };
+        ExportedAccount {
+            id: self.id,
+            details: AccountDetails {
+                ilp_address: Some(self.ilp_address.clone()),
+                username: self.username.clone(),
+                asset_code: self.asset_code.clone(),
+                asset_scale: self.asset_scale,
+                max_packet_amount: self.max_packet_amount,
+                min_packet_amount: self.min_packet_amount,
+                min_balance: self.min_balance,
+                ilp_over_http_url: self.ilp_over_http_url.as_ref().map(Url::to_string),
+                ilp_over_http_incoming_token: None,
+                ilp_over_http_outgoing_token: None,
+                ilp_over_http_client_certificates: self.ilp_over_http_client_certificates.clone(),
+                ilp_over_http_signature_header: self.ilp_over_http_signature_header.clone(),
+                ilp_over_http_signature_secret: None,
+                ilp_over_btp_url: self.ilp_over_btp_url.as_ref().map(Url::to_string),
+                ilp_over_btp_outgoing_token: None,
+                ilp_over_btp_incoming_token: None,
+                settle_threshold: self.settle_threshold,
+                settle_to: self.settle_to,
+                routing_relation: Some(self.routing_relation.to_string()),
+                round_trip_time: Some(self.round_trip_time),
+                amount_per_minute_limit: self.amount_per_minute_limit,
+                packets_per_minute_limit: self.packets_per_minute_limit,
+                spread: self.spread,
+                rounding: self.rounding,
+                routing_weight: self.routing_weight,
+                route_priority: self.route_priority,
+                volume_alert_threshold: self.volume_alert_threshold,
+                spsp_display_symbol: self.spsp_display_symbol.clone(),
+                spsp_display_decimals: self.spsp_display_decimals,
+                allowed_source_prefix: self.allowed_source_prefix.clone(),
+                allowed_destination_prefixes: self.allowed_destination_prefixes.clone(),
+                max_concurrent_outgoing_requests: self.max_concurrent_outgoing_requests,
+                shadow_account: self.shadow_account.clone(),
+                settlement_engine_url: self.settlement_engine_url.as_ref().map(Url::to_string),
+                settlement_asset_code: self.settlement_asset_code.clone(),
+                settlement_asset_scale: self.settlement_asset_scale,
+            },
+            secrets: ExportedSecrets {
+                ilp_over_http_incoming_token: export_secret(&self.ilp_over_http_incoming_token),
+                ilp_over_http_outgoing_token: export_secret(&self.ilp_over_http_outgoing_token),
+                ilp_over_http_signature_secret: export_secret(&self.ilp_over_http_signature_secret),
+                ilp_over_btp_incoming_token: export_secret(&self.ilp_over_btp_incoming_token),
+                ilp_over_btp_outgoing_token: export_secret(&self.ilp_over_btp_outgoing_token),
+            },
+            balance,
+            prepaid_amount,
+        }
+    }
+
+    /// Recreates an exported account. Its secrets are used as they are, so they
+    /// must be in the form the store holds them in
+    pub(crate) fn from_export(
+        exported: ExportedAccount,
+        node_ilp_address: Address,
+    ) -> Result<Account, CreateAccountError> {
+        let import_secret = |name: &str, secret: Option<String>| match secret {
+            Some(secret) => hex::decode(&secret)
+                .map(|bytes| Some(SecretBytesMut::new(&bytes[..])))
+                .map_err(|_| CreateAccountError::InvalidExportedSecret(name.to_owned())),
+            None => Ok(None),
+        };
+        let secrets = exported.secrets;
+        let mut details = exported.details;
+        // The signature secret is only validated together with its header
+        details.ilp_over_http_signature_secret = secrets
+            .ilp_over_http_signature_secret
+            .as_ref()
+            .map(|_| SecretString::new(String::new()));
+
+        let mut account = Account::try_from(exported.id, details, node_ilp_address)?;
+        account.ilp_over_http_incoming_token = import_secret(
+            "ilp_over_http_incoming_token",
+            secrets.ilp_over_http_incoming_token,
+        )?;
+        account.ilp_over_http_outgoing_token = import_secret(
+            "ilp_over_http_outgoing_token",
+            secrets.ilp_over_http_outgoing_token,
+        )?;
+        account.ilp_over_http_signature_secret = import_secret(
+            "ilp_over_http_signature_secret",
+            secrets.ilp_over_http_signature_secret,
+        )?;
+        account.ilp_over_btp_incoming_token = import_secret(
+            "ilp_over_btp_incoming_token",
+            secrets.ilp_over_btp_incoming_token,
+        )?;
+        account.ilp_over_btp_outgoing_token = import_secret(
+            "ilp_over_btp_outgoing_token",
+            secrets.ilp_over_btp_outgoing_token,
+        )?;
+        Ok(account)
+    }
 }
 
 /// A wrapper over the [`Account`](./struct.Account.html) which contains their encrypt tokens.
@@ -216,7 +428,60 @@ pub struct AccountWithEncryptedTokens {
 }
 
 impl AccountWithEncryptedTokens {
-    /// Decrypts the account's incoming/outgoing BTP and HTTP keys with the provided decryption key
+    /// Checks the provided token against the account's incoming BTP token. Tokens that were
+    /// saved before they were hashed are still encrypted, so those are decrypted and compared
+    pub fn verify_btp_incoming_token(
+        &self,
+        decryption_key: &aead::LessSafeKey,
+        token: &[u8],
+    ) -> bool {
+        if let Some(ref stored) = self.account.ilp_over_btp_incoming_token {
+            let stored = stored.expose_secret();
+            verify_token_hash(&stored, token)
+                || decrypt_token(decryption_key, &stored)
+                    .map(|decrypted| decrypted.expose_secret().as_ref() == token)
+                    .unwrap_or(false)
+        } else {
+            false
+        }
+    }
+
+    /// Returns the name of the first encrypted secret which can not be decrypted with the
+    /// provided decryption key, e.g. because it was encrypted by a node with another secret
+    pub(crate) fn undecryptable_secret(
+        &self,
+        decryption_key: &aead::LessSafeKey,
+    ) -> Option<&'static str> {
+        let secrets = [
+            (
+                "ilp_over_http_incoming_token",
+                &self.account.ilp_over_http_incoming_token,
+            ),
+            (
+                "ilp_over_http_outgoing_token",
+                &self.account.ilp_over_http_outgoing_token,
+            ),
+            (
+                "ilp_over_http_signature_secret",
+                &self.account.ilp_over_http_signature_secret,
+            ),
+            (
+                "ilp_over_btp_outgoing_token",
+                &self.account.ilp_over_btp_outgoing_token,
+            ),
+        ];
+        secrets.iter().find_map(|(name, secret)| match secret {
+            Some(encrypted)
+                if decrypt_token(decryption_key, &encrypted.expose_secret()).is_err() =>
+            {
+                Some(*name)
+            }
+            _ => None,
+        })
+    }
+
+    /// Decrypts the account's outgoing BTP and incoming/outgoing HTTP keys and its HTTP
+    /// signature secret with the provided decryption key. The incoming BTP key remains hashed
     pub fn decrypt_tokens(mut self, decryption_key: &aead::LessSafeKey) -> Account {
         if let Some(ref encrypted) = self.account.ilp_over_btp_outgoing_token {
             self.account.ilp_over_btp_outgoing_token =
@@ -240,12 +505,12 @@ impl AccountWithEncryptedTokens {
                     })
                     .ok();
         }
-        if let Some(ref encrypted) = self.account.ilp_over_btp_incoming_token {
-            self.account.ilp_over_btp_incoming_token =
+        if let Some(ref encrypted) = self.account.ilp_over_http_signature_secret {
+            self.account.ilp_over_http_signature_secret =
                 decrypt_token(decryption_key, &encrypted.expose_secret())
                     .map_err(|_| {
                         error!(
-                            "Unable to decrypt ilp_over_btp_incoming_token for account {}",
+                            "Unable to decrypt ilp_over_http_signature_secret for account {}",
                             self.account.id
                         )
                     })
@@ -305,6 +570,22 @@ impl HttpAccount for Account {
             )
         })
     }
+
+    fn get_http_client_certificates(&self) -> &[String] {
+        &self.ilp_over_http_client_certificates
+    }
+
+    fn get_http_signature_key(&self) -> Option<(&str, &[u8])> {
+        match (
+            &self.ilp_over_http_signature_header,
+            &self.ilp_over_http_signature_secret,
+        ) {
+            (Some(header), Some(secret)) => {
+                Some((header.as_str(), secret.expose_secret().as_ref()))
+            }
+            _ => None,
+        }
+    }
 }
 
 impl BtpAccount for Account {
@@ -325,12 +606,26 @@ impl MaxPacketAmountAccount for Account {
     fn max_packet_amount(&self) -> u64 {
         self.max_packet_amount
     }
+
+    fn min_packet_amount(&self) -> u64 {
+        self.min_packet_amount.unwrap_or(0)
+    }
 }
 
 impl CcpRoutingAccount for Account {
     fn routing_relation(&self) -> RoutingRelation {
         self.routing_relation
     }
+
+    fn route_priority(&self) -> u32 {
+        self.route_priority.unwrap_or(0)
+    }
+}
+
+impl IldcpAccount for Account {
+    fn is_ildcp_child(&self) -> bool {
+        self.routing_relation == RoutingRelation::Child
+    }
 }
 
 impl RoundTripTimeAccount for Account {
@@ -349,6 +644,62 @@ impl RateLimitAccount for Account {
     }
 }
 
+impl SpreadAccount for Account {
+    fn spread(&self) -> Option<f64> {
+        self.spread
+    }
+
+    fn rounding(&self) -> Option<RoundingPolicy> {
+        self.rounding
+    }
+}
+
+impl RouterAccount for Account {
+    fn routing_weight(&self) -> u32 {
+        self.routing_weight.unwrap_or(1)
+    }
+}
+
+impl VolumeAlertAccount for Account {
+    fn volume_alert_threshold(&self) -> Option<u64> {
+        self.volume_alert_threshold
+    }
+}
+
+impl OutgoingConcurrencyAccount for Account {
+    fn max_concurrent_outgoing_requests(&self) -> Option<u32> {
+        self.max_concurrent_outgoing_requests
+    }
+}
+
+impl ShadowAccount for Account {
+    fn shadow_account(&self) -> Option<&Username> {
+        self.shadow_account.as_ref()
+    }
+}
+
+impl SourcePrefixAccount for Account {
+    fn allowed_source_prefix(&self) -> Option<&Address> {
+        self.allowed_source_prefix.as_ref()
+    }
+}
+
+impl DestinationAllowlistAccount for Account {
+    fn allowed_destination_prefixes(&self) -> &[Address] {
+        &self.allowed_destination_prefixes
+    }
+}
+
+impl SpspDisplayAccount for Account {
+    fn display_symbol(&self) -> Option<&str> {
+        self.spsp_display_symbol.as_deref()
+    }
+
+    fn display_decimals(&self) -> Option<u8> {
+        self.spsp_display_decimals
+    }
+}
+
 impl SettlementAccount for Account {
     fn settlement_engine_details(&self) -> Option<SettlementEngineDetails> {
         match &self.settlement_engine_url {
@@ -356,11 +707,21 @@ impl SettlementAccount for Account {
             _ => None,
         }
     }
+
+    fn settlement_asset(&self) -> Option<SettlementAsset> {
+        self.settlement_asset_code
+            .as_ref()
+            .map(|asset_code| SettlementAsset {
+                asset_code: asset_code.clone(),
+                asset_scale: self.settlement_asset_scale.unwrap_or(self.asset_scale),
+            })
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::crypto::generate_keys;
     use once_cell::sync::Lazy;
     use secrecy::SecretString;
 
@@ -370,11 +731,15 @@ mod test {
         asset_scale: 6,
         asset_code: "XYZ".to_string(),
         max_packet_amount: 1000,
+        min_packet_amount: None,
         min_balance: Some(-1000),
         // we are Bob and we're using this account to peer with Alice
         ilp_over_http_url: Some("http://example.com/accounts/bob/ilp".to_string()),
         ilp_over_http_incoming_token: Some(SecretString::new("incoming_auth_token".to_string())),
         ilp_over_http_outgoing_token: Some(SecretString::new("outgoing_auth_token".to_string())),
+        ilp_over_http_client_certificates: Vec::new(),
+        ilp_over_http_signature_header: None,
+        ilp_over_http_signature_secret: None,
         ilp_over_btp_url: Some("btp+ws://example.com/accounts/bob/ilp/btp".to_string()),
         ilp_over_btp_incoming_token: Some(SecretString::new("incoming_btp_token".to_string())),
         ilp_over_btp_outgoing_token: Some(SecretString::new("outgoing_btp_token".to_string())),
@@ -384,7 +749,20 @@ mod test {
         round_trip_time: Some(600),
         amount_per_minute_limit: None,
         packets_per_minute_limit: None,
+        spread: None,
+        rounding: None,
+        routing_weight: None,
+        route_priority: None,
+        volume_alert_threshold: None,
+        spsp_display_symbol: None,
+        spsp_display_decimals: None,
+        allowed_source_prefix: None,
+        allowed_destination_prefixes: Vec::new(),
+        max_concurrent_outgoing_requests: None,
+        shadow_account: None,
         settlement_engine_url: None,
+        settlement_asset_code: None,
+        settlement_asset_scale: None,
     });
 
     #[test]
@@ -415,5 +793,176 @@ mod test {
             "http://example.com/accounts/bob/ilp",
         );
         assert_eq!(account.routing_relation(), RoutingRelation::Peer);
+        assert!(!account.is_ildcp_child());
+    }
+
+    #[test]
+    fn derives_child_address_from_node_address() {
+        let mut details = ACCOUNT_DETAILS.clone();
+        details.ilp_address = None;
+        details.routing_relation = Some("Child".to_string());
+        let account = Account::try_from(
+            Uuid::new_v4(),
+            details,
+            Address::from_str("example.node").unwrap(),
+        )
+        .unwrap();
+        assert!(account.is_ildcp_child());
+        assert_eq!(
+            account.ilp_address(),
+            &Address::from_str("example.node.alice").unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_concurrency_limit_of_zero() {
+        let mut details = ACCOUNT_DETAILS.clone();
+        details.max_concurrent_outgoing_requests = Some(0);
+        let result = Account::try_from(
+            Uuid::new_v4(),
+            details,
+            Address::from_str("example.account").unwrap(),
+        );
+        match result {
+            Err(CreateAccountError::ParamOutOfRange(param, _)) => {
+                assert_eq!(param, "max_concurrent_outgoing_requests")
+            }
+            _ => panic!("Expected the concurrency limit to be rejected"),
+        }
+    }
+
+    #[test]
+    fn normalizes_client_certificate_fingerprints() {
+        let mut details = ACCOUNT_DETAILS.clone();
+        details.ilp_over_http_client_certificates = vec![format!("AB:{}", "cd".repeat(31))];
+        let account = Account::try_from(
+            Uuid::new_v4(),
+            details.clone(),
+            Address::from_str("example.account").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            account.get_http_client_certificates(),
+            &[format!("ab{}", "cd".repeat(31))][..]
+        );
+
+        details.ilp_over_http_client_certificates = vec!["not a fingerprint".to_string()];
+        let result = Account::try_from(
+            Uuid::new_v4(),
+            details,
+            Address::from_str("example.account").unwrap(),
+        );
+        match result {
+            Err(CreateAccountError::InvalidClientCertificateFingerprint(fingerprint)) => {
+                assert_eq!(fingerprint, "not a fingerprint")
+            }
+            _ => panic!("Expected the fingerprint to be rejected"),
+        }
+    }
+
+    #[test]
+    fn encrypts_http_signature_secret() {
+        let (encryption_key, decryption_key) = generate_keys(&[0; 32]);
+        let mut details = ACCOUNT_DETAILS.clone();
+        details.ilp_over_http_signature_header = Some("X-Packet-Signature".to_string());
+        details.ilp_over_http_signature_secret = Some(SecretString::new("secret".to_string()));
+        let account = Account::try_from(
+            Uuid::new_v4(),
+            details.clone(),
+            Address::from_str("example.account").unwrap(),
+        )
+        .unwrap();
+        let encrypted = account.encrypt_tokens(&encryption_key.expose_secret().0);
+        assert_ne!(
+            encrypted
+                .account
+                .ilp_over_http_signature_secret
+                .as_ref()
+                .unwrap()
+                .expose_secret()
+                .as_ref(),
+            b"secret"
+        );
+        let account = encrypted.decrypt_tokens(&decryption_key.expose_secret().0);
+        assert_eq!(
+            account.get_http_signature_key(),
+            Some(("X-Packet-Signature", &b"secret"[..]))
+        );
+
+        details.ilp_over_http_signature_secret = None;
+        let result = Account::try_from(
+            Uuid::new_v4(),
+            details.clone(),
+            Address::from_str("example.account").unwrap(),
+        );
+        match result {
+            Err(CreateAccountError::IncompleteSignatureKey) => {}
+            _ => panic!("Expected the header without a secret to be rejected"),
+        }
+
+        details.ilp_over_http_signature_header = Some("not a header".to_string());
+        details.ilp_over_http_signature_secret = Some(SecretString::new("secret".to_string()));
+        let result = Account::try_from(
+            Uuid::new_v4(),
+            details,
+            Address::from_str("example.account").unwrap(),
+        );
+        match result {
+            Err(CreateAccountError::InvalidSignatureHeader(header)) => {
+                assert_eq!(header, "not a header")
+            }
+            _ => panic!("Expected the header to be rejected"),
+        }
+    }
+
+    #[test]
+    fn hashes_btp_incoming_token() {
+        let (encryption_key, decryption_key) = generate_keys(&[0; 32]);
+        let account = Account::try_from(
+            Uuid::new_v4(),
+            ACCOUNT_DETAILS.clone(),
+            Address::from_str("example.account").unwrap(),
+        )
+        .unwrap();
+        let encrypted = account.encrypt_tokens(&encryption_key.expose_secret().0);
+        let decryption_key = &decryption_key.expose_secret().0;
+        assert!(encrypted.verify_btp_incoming_token(decryption_key, b"incoming_btp_token"));
+        assert!(!encrypted.verify_btp_incoming_token(decryption_key, b"other_btp_token"));
+
+        // the token can never be read back, but the other tokens can
+        let account = encrypted.decrypt_tokens(decryption_key);
+        assert_ne!(
+            account
+                .ilp_over_btp_incoming_token
+                .as_ref()
+                .unwrap()
+                .expose_secret()
+                .as_ref(),
+            b"incoming_btp_token"
+        );
+        assert_eq!(
+            account.get_ilp_over_btp_outgoing_token().unwrap(),
+            b"outgoing_btp_token",
+        );
+    }
+
+    #[test]
+    fn verifies_encrypted_btp_incoming_token() {
+        // Tokens saved before incoming BTP tokens were hashed are still accepted
+        let (encryption_key, decryption_key) = generate_keys(&[0; 32]);
+        let mut account = Account::try_from(
+            Uuid::new_v4(),
+            ACCOUNT_DETAILS.clone(),
+            Address::from_str("example.account").unwrap(),
+        )
+        .unwrap();
+        account.ilp_over_btp_incoming_token = Some(SecretBytesMut::from(encrypt_token(
+            &encryption_key.expose_secret().0,
+            b"incoming_btp_token",
+        )));
+        let encrypted = AccountWithEncryptedTokens { account };
+        let decryption_key = &decryption_key.expose_secret().0;
+        assert!(encrypted.verify_btp_incoming_token(decryption_key, b"incoming_btp_token"));
+        assert!(!encrypted.verify_btp_incoming_token(decryption_key, b"other_btp_token"));
     }
 }
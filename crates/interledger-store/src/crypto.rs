@@ -1,6 +1,6 @@
 use bytes::BytesMut;
 use ring::{
-    aead, hmac,
+    aead, constant_time, digest, hmac,
     rand::{SecureRandom, SystemRandom},
 };
 
@@ -156,6 +156,17 @@ pub fn decrypt_token(
     }
 }
 
+/// Hashes a token which never has to be read back, only compared with the one a peer presents
+pub fn hash_token(token: &[u8]) -> BytesMut {
+    BytesMut::from(digest::digest(&digest::SHA256, token).as_ref())
+}
+
+/// Checks (in constant time) whether the token matches the stored hash
+pub fn verify_token_hash(hash: &[u8], token: &[u8]) -> bool {
+    constant_time::verify_slices_are_equal(hash, digest::digest(&digest::SHA256, token).as_ref())
+        .is_ok()
+}
+
 #[cfg(test)]
 mod encryption {
     use super::*;
@@ -172,4 +183,12 @@ mod encryption {
             "test test"
         );
     }
+
+    #[test]
+    fn verifies_hashed_tokens() {
+        let hash = hash_token(b"test test");
+        assert_ne!(hash.as_ref(), b"test test");
+        assert!(verify_token_hash(&hash, b"test test"));
+        assert!(!verify_token_hash(&hash, b"test"));
+    }
 }
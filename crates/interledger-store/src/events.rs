@@ -0,0 +1,173 @@
+use chrono::Utc;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "redis")]
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use uuid::Uuid;
+
+/// The reason an account's balance changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceChangeReason {
+    /// A Prepare packet from the account was forwarded
+    Prepare,
+    /// A Prepare packet sent to the account was fulfilled
+    Fulfill,
+    /// A Prepare packet from the account was rejected, so its amount was credited back
+    Reject,
+    /// A settlement was triggered because the balance reached the settle threshold
+    OutgoingSettlement,
+    /// An outgoing settlement failed, so its amount was credited back
+    SettlementRefund,
+    /// The account's settlement engine received a settlement
+    IncomingSettlement,
+}
+
+/// A record of a single change to an account's balance
+///
+/// Balances include the account's prepaid amount, like the value returned by
+/// [`BalanceStore::get_balance`](../../interledger_service_util/trait.BalanceStore.html#tymethod.get_balance).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BalanceEvent {
+    pub account_id: Uuid,
+    pub old_balance: i64,
+    pub new_balance: i64,
+    pub reason: BalanceChangeReason,
+    /// The hex-encoded execution condition of the packet, for changes caused by packets
+    /// (and outgoing settlements triggered by fulfilling them)
+    pub packet_id: Option<String>,
+    /// The idempotency key of the settlement, for incoming settlements
+    pub settlement_id: Option<String>,
+    /// The time the balance changed in RFC3339 format
+    pub timestamp: String,
+}
+
+/// What to do with an event if the sink's buffer is full
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Drop the event (and log a warning) so packets are never delayed
+    Drop,
+    /// Wait until there is room in the buffer, which slows down packet processing
+    /// if events are consumed slower than balances change
+    Block,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> Self {
+        OverflowPolicy::Drop
+    }
+}
+
+/// Buffered channel the stores push a [`BalanceEvent`](./struct.BalanceEvent.html)
+/// into every time they change an account's balance.
+///
+/// The events are read from the `Receiver` returned by [`new`](#method.new), which
+/// is usually handed to a task publishing them to a message queue (see
+/// [`publish_to_redis_stream`](./fn.publish_to_redis_stream.html)).
+/// Events are sent after the balance was changed, so events of concurrent
+/// changes to the same account may be received out of order.
+#[derive(Clone)]
+pub struct BalanceEventSink {
+    sender: Sender<BalanceEvent>,
+    overflow: OverflowPolicy,
+}
+
+impl BalanceEventSink {
+    /// Creates a sink buffering up to `buffer_size` events
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer_size` is 0.
+    pub fn new(buffer_size: usize, overflow: OverflowPolicy) -> (Self, Receiver<BalanceEvent>) {
+        assert!(buffer_size > 0, "balance event buffer size must be at least 1");
+        let (sender, receiver) = mpsc::channel(buffer_size);
+        (BalanceEventSink { sender, overflow }, receiver)
+    }
+
+    pub(crate) async fn publish(
+        &self,
+        account_id: Uuid,
+        old_balance: i64,
+        new_balance: i64,
+        reason: BalanceChangeReason,
+        packet_id: Option<[u8; 32]>,
+        settlement_id: Option<String>,
+    ) {
+        let event = BalanceEvent {
+            account_id,
+            old_balance,
+            new_balance,
+            reason,
+            packet_id: packet_id.map(hex::encode),
+            settlement_id,
+            timestamp: Utc::now().to_rfc3339(),
+        };
+        let mut sender = self.sender.clone();
+        match self.overflow {
+            OverflowPolicy::Drop => match sender.try_send(event) {
+                Ok(_) => {}
+                Err(TrySendError::Full(event)) => {
+                    warn!("Balance event buffer is full, dropping event: {:?}", event)
+                }
+                Err(TrySendError::Closed(_)) => {
+                    debug!("Balance event receiver was dropped, not sending event")
+                }
+            },
+            OverflowPolicy::Block => {
+                if sender.send(event).await.is_err() {
+                    debug!("Balance event receiver was dropped, not sending event");
+                }
+            }
+        }
+    }
+}
+
+/// Longest delay between the attempts to connect to the Redis server the events are published to
+#[cfg(feature = "redis")]
+const MAX_CONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Publishes the events to the given [Redis Stream](https://redis.io/topics/streams-intro)
+/// until the sink is dropped. Every entry has a single `event` field containing the JSON-serialized event.
+///
+/// If Redis cannot be reached, connecting is retried with exponential backoff (up to 30 seconds
+/// apart) while the events are buffered by the sink. Events which cannot be published once the
+/// connection was established are logged and dropped.
+#[cfg(feature = "redis")]
+pub async fn publish_to_redis_stream(
+    mut events: Receiver<BalanceEvent>,
+    redis_info: redis_crate::ConnectionInfo,
+    stream_key: String,
+) {
+    use crate::redis::RedisReconnect;
+    use log::error;
+
+    let mut delay = Duration::from_millis(100);
+    let mut connection = loop {
+        match RedisReconnect::connect(redis_info.clone()).await {
+            Ok(connection) => break connection,
+            Err(err) => {
+                error!(
+                    "Error connecting to Redis to publish balance events, retrying in {:?}: {:?}",
+                    delay, err
+                );
+                tokio::time::delay_for(delay).await;
+                delay = std::cmp::min(delay * 2, MAX_CONNECT_DELAY);
+            }
+        }
+    };
+    while let Some(event) = events.recv().await {
+        let payload = serde_json::to_string(&event).unwrap();
+        let result: Result<String, _> = redis_crate::cmd("XADD")
+            .arg(&stream_key)
+            .arg("*")
+            .arg("event")
+            .arg(&payload)
+            .query_async(&mut connection)
+            .await;
+        if let Err(err) = result {
+            error!("Error publishing balance event {}: {:?}", payload, err);
+        }
+    }
+}
@@ -6,6 +6,10 @@
 pub mod account;
 /// Cryptographic utilities for encrypting/decrypting data as well as clearing data from memory
 pub mod crypto;
+/// Records of balance changes which can be published to a message queue
+pub mod events;
+/// An in-memory backend which isn't persisted (for tests and ephemeral nodes)
+pub mod memory;
 /// A redis backend using [redis-rs](https://github.com/mitsuhiko/redis-rs/)
 #[cfg(feature = "redis")]
 pub mod redis;
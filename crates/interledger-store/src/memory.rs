@@ -0,0 +1,1561 @@
+// The in-memory store mirrors the behavior of the Redis store (including the
+// logic of its Lua scripts) so that the two can be used interchangeably.
+use super::account::Account;
+use super::crypto::{hash_token, verify_token_hash};
+use super::events::{BalanceChangeReason, BalanceEventSink};
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::Utc;
+use futures::channel::mpsc::UnboundedSender;
+use http::StatusCode;
+use interledger_api::{AccountDetails, AccountSettings, NodeExport, NodeStore};
+use interledger_btp::BtpStore;
+use interledger_ccp::{CcpRoutingAccount, CcpRoutingStore, RoutingRelation};
+use interledger_errors::*;
+use interledger_http::HttpStore;
+use interledger_packet::Address;
+use interledger_rates::{ExchangeRateStore, RateProvenance, RateSource};
+use interledger_router::RouterStore;
+use interledger_service::{Account as AccountTrait, AccountStore, AddressStore, Username};
+use interledger_service_util::{
+    BalanceStore, FulfilledConditionStore, InMemoryRateLimitStore, LastSettlement, RateLimitError,
+    RateLimitStore, SettlementConversion, TrafficCounters, TrafficStore, VolumeStore,
+};
+use interledger_settlement::core::{
+    idempotency::{IdempotentData, IdempotentStore},
+    scale_with_precision_loss,
+    types::{Convert, ConvertDetails, LeftoversStore, SettlementStore},
+};
+use interledger_stream::{PaymentNotification, StreamNotificationsStore};
+use log::{debug, error, trace, warn};
+use num_bigint::BigUint;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use secrecy::{ExposeSecret, SecretBytesMut};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use url::Url;
+use uuid::Uuid;
+
+/// How long the responses saved for idempotency keys are kept
+const IDEMPOTENCY_KEY_EXPIRY: Duration = Duration::from_secs(86400);
+
+/// The node's default ILP Address
+static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("local.host").unwrap());
+
+/// Error returned when a Prepare would bring an account under its minimum balance
+#[derive(Error, Debug)]
+#[error("Incoming prepare of {amount} would bring account {account_id} under its minimum balance. Current balance: {balance}, min balance: {min_balance}")]
+struct MinBalanceExceeded {
+    amount: u64,
+    account_id: Uuid,
+    balance: i64,
+    min_balance: i64,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Balance {
+    balance: i64,
+    prepaid_amount: i64,
+}
+
+#[derive(Default)]
+struct MemoryStoreData {
+    /// The Store's ILP Address
+    ilp_address: Option<Address>,
+    /// The address that was configured for us by our parent, if we have one
+    parent_ilp_address: Option<Address>,
+    accounts: HashMap<Uuid, Account>,
+    usernames: HashMap<String, Uuid>,
+    balances: HashMap<Uuid, Balance>,
+    last_settlements: HashMap<Uuid, LastSettlement>,
+    settlement_conversions: HashMap<Uuid, SettlementConversion>,
+    /// Routes to the local accounts, which are replaced by the route manager's routes
+    routes: HashMap<String, Uuid>,
+    static_routes: HashMap<String, Uuid>,
+    default_route: Option<Uuid>,
+    /// The routing table built from the routes above, which is returned to the Router
+    routing_table: Arc<HashMap<String, Uuid>>,
+    multipath_routes: Arc<HashMap<String, Vec<Uuid>>>,
+    /// Accounts which no new packets are routed through
+    draining_accounts: Arc<HashSet<Uuid>>,
+    /// Accounts which cannot authenticate, send or receive packets
+    disabled_accounts: Arc<HashSet<Uuid>>,
+    exchange_rates: HashMap<String, f64>,
+    rate_provenance: Option<RateProvenance>,
+    rate_source: Option<RateSource>,
+    settlement_engines: HashMap<String, Url>,
+    subscriptions: HashMap<Uuid, UnboundedSender<PaymentNotification>>,
+    idempotent_data: HashMap<String, (IdempotentData, Instant)>,
+    /// Idempotency keys of the incoming settlements that were already credited
+    settlement_idempotency_keys: HashSet<String>,
+    uncredited_settlement_amounts: HashMap<Uuid, Vec<(BigUint, u8)>>,
+    /// The index of the current volume alert window and the volume sent to each account in it
+    outgoing_volumes: HashMap<Uuid, (u64, u64)>,
+    traffic: HashMap<Uuid, TrafficCounters>,
+    /// The execution conditions of fulfilled packets and when they are forgotten
+    fulfilled_conditions: HashMap<[u8; 32], Instant>,
+}
+
+impl MemoryStoreData {
+    fn ilp_address(&self) -> Address {
+        self.ilp_address
+            .clone()
+            .unwrap_or_else(|| DEFAULT_ILP_ADDRESS.clone())
+    }
+
+    /// Returns a copy of the account. If the account does not have a settlement engine
+    /// URL but there is one configured for its currency, that one is used
+    fn load_account(&self, id: &Uuid) -> Option<Account> {
+        self.accounts.get(id).map(|account| {
+            let mut account = account.clone();
+            if account.settlement_engine_url.is_none() {
+                account.settlement_engine_url =
+                    self.settlement_engines.get(&account.asset_code).cloned();
+            }
+            account
+        })
+    }
+
+    fn balance_mut(&mut self, id: Uuid) -> Result<&mut Balance, AccountStoreError> {
+        self.balances
+            .get_mut(&id)
+            .ok_or_else(|| AccountStoreError::AccountNotFound(id.to_string()))
+    }
+
+    /// Saves the account and adds the route to its address. The incoming
+    /// BTP token is only ever compared, so it is stored hashed
+    fn save_account(&mut self, mut account: Account) {
+        if let Some(ref token) = account.ilp_over_btp_incoming_token {
+            account.ilp_over_btp_incoming_token =
+                Some(SecretBytesMut::from(hash_token(&token.expose_secret())));
+        }
+        self.save_account_as_is(account);
+    }
+
+    /// Saves the account with its tokens as they are (e.g. because they were exported
+    /// by a store) and adds the route to its address
+    fn save_account_as_is(&mut self, account: Account) {
+        self.usernames
+            .insert(account.username.to_string(), account.id);
+        self.routes
+            .insert(account.ilp_address.to_string(), account.id);
+        self.balances.entry(account.id).or_default();
+        self.accounts.insert(account.id, account);
+        self.update_routing_table();
+    }
+
+    /// Rebuilds the routing table from the current routes, the default route
+    /// and the static routes (which take precedence over the others)
+    fn update_routing_table(&mut self) {
+        let mut routing_table = self.routes.clone();
+        if let Some(default_route) = self.default_route {
+            routing_table.insert(String::new(), default_route);
+        }
+        routing_table.extend(
+            self.static_routes
+                .iter()
+                .map(|(prefix, id)| (prefix.clone(), *id)),
+        );
+        trace!("Routing table is: {:?}", routing_table);
+        self.routing_table = Arc::new(routing_table);
+    }
+
+    fn accounts_where(&self, predicate: impl Fn(&Account) -> bool) -> Vec<Account> {
+        self.accounts
+            .values()
+            .filter(|account| predicate(account))
+            .filter_map(|account| self.load_account(&account.id))
+            .collect()
+    }
+}
+
+/// A Store that keeps all of its data in the node's memory.
+///
+/// It behaves like the [`RedisStore`](../redis/struct.RedisStore.html) but nothing is ever
+/// persisted, so all accounts, balances and routes are lost when the node is stopped. It also
+/// cannot be shared between multiple instances of a node. This makes it suitable for
+/// tests, demos and ephemeral nodes only.
+#[derive(Clone)]
+pub struct MemoryStore {
+    data: Arc<RwLock<MemoryStoreData>>,
+    rate_limits: InMemoryRateLimitStore<(), Account>,
+    balance_events: Option<BalanceEventSink>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store for a node with the given ILP address
+    pub fn new(node_ilp_address: Address) -> Self {
+        let data = MemoryStoreData {
+            ilp_address: Some(node_ilp_address),
+            ..Default::default()
+        };
+        MemoryStore {
+            data: Arc::new(RwLock::new(data)),
+            rate_limits: InMemoryRateLimitStore::new(()),
+            balance_events: None,
+        }
+    }
+
+    /// Sets the sink which is sent an event for every change to an account's balance
+    pub fn balance_events(&mut self, sink: BalanceEventSink) -> &mut Self {
+        self.balance_events = Some(sink);
+        self
+    }
+
+    /// Sends an event for the balance change to the sink (if there is one)
+    async fn publish_balance_event(
+        &self,
+        account_id: Uuid,
+        old_balance: i64,
+        new_balance: i64,
+        reason: BalanceChangeReason,
+        packet_id: Option<[u8; 32]>,
+        settlement_id: Option<String>,
+    ) {
+        if let Some(ref sink) = self.balance_events {
+            sink.publish(
+                account_id,
+                old_balance,
+                new_balance,
+                reason,
+                packet_id,
+                settlement_id,
+            )
+            .await;
+        }
+    }
+
+    fn get_account_from_username(&self, username: &Username) -> Option<Account> {
+        let data = self.data.read();
+        data.usernames
+            .get(username.as_ref())
+            .and_then(|id| data.accounts.get(id).cloned())
+    }
+
+    fn is_disabled(&self, account: &Account) -> bool {
+        let disabled = self.data.read().disabled_accounts.contains(&account.id);
+        if disabled {
+            debug!("Account {} is disabled", account.username);
+        }
+        disabled
+    }
+}
+
+#[async_trait]
+impl AccountStore for MemoryStore {
+    type Account = Account;
+
+    async fn get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<Account>, AccountStoreError> {
+        let data = self.data.read();
+        let accounts: Vec<Account> = account_ids
+            .iter()
+            .filter_map(|id| data.load_account(id))
+            .collect();
+        if accounts.len() == account_ids.len() {
+            Ok(accounts)
+        } else {
+            Err(AccountStoreError::WrongLength {
+                expected: account_ids.len(),
+                actual: accounts.len(),
+            })
+        }
+    }
+
+    async fn get_account_id_from_username(
+        &self,
+        username: &Username,
+    ) -> Result<Uuid, AccountStoreError> {
+        match self.data.read().usernames.get(username.as_ref()) {
+            Some(id) => Ok(*id),
+            None => {
+                debug!("Username not found: {}", username);
+                Err(AccountStoreError::AccountNotFound(username.to_string()))
+            }
+        }
+    }
+}
+
+impl StreamNotificationsStore for MemoryStore {
+    type Account = Account;
+
+    fn add_payment_notification_subscription(
+        &self,
+        id: Uuid,
+        sender: UnboundedSender<PaymentNotification>,
+    ) {
+        trace!("Added payment notification listener for {}", id);
+        self.data.write().subscriptions.insert(id, sender);
+    }
+
+    fn publish_payment_notification(&self, payment: PaymentNotification) {
+        let data = self.data.read();
+        let account_id = match data.usernames.get(payment.to_username.as_ref()) {
+            Some(id) => *id,
+            None => {
+                error!(
+                    "Failed to find account ID corresponding to username: {}",
+                    payment.to_username
+                );
+                return;
+            }
+        };
+        debug!(
+            "Publishing payment notification {:?} for account {}",
+            payment, account_id
+        );
+        match data.subscriptions.get(&account_id) {
+            Some(sender) => {
+                if let Err(err) = sender.unbounded_send(payment) {
+                    error!("Failed to send message: {}", err);
+                }
+            }
+            None => trace!(
+                "Ignoring message for account {} because there were no open subscriptions",
+                account_id
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl BalanceStore for MemoryStore {
+    /// Returns the balance **from the account holder's perspective**, meaning the sum of
+    /// the Payable Balance and Pending Outgoing minus the Receivable Balance and the Pending Incoming.
+    async fn get_balance(&self, account_id: Uuid) -> Result<i64, BalanceStoreError> {
+        let data = self.data.read();
+        let balance = data.balances.get(&account_id).ok_or_else(|| {
+            BalanceStoreError::Other(Box::new(AccountStoreError::AccountNotFound(
+                account_id.to_string(),
+            )))
+        })?;
+        Ok(balance.balance + balance.prepaid_amount)
+    }
+
+    async fn get_balances(&self, account_ids: Vec<Uuid>) -> Result<Vec<i64>, BalanceStoreError> {
+        let data = self.data.read();
+        account_ids
+            .iter()
+            .map(|account_id| {
+                let balance = data.balances.get(account_id).ok_or_else(|| {
+                    BalanceStoreError::Other(Box::new(AccountStoreError::AccountNotFound(
+                        account_id.to_string(),
+                    )))
+                })?;
+                Ok(balance.balance + balance.prepaid_amount)
+            })
+            .collect()
+    }
+
+    async fn get_last_settlement(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Option<LastSettlement>, BalanceStoreError> {
+        Ok(self.data.read().last_settlements.get(&account_id).cloned())
+    }
+
+    async fn save_settlement_conversion(
+        &self,
+        account_id: Uuid,
+        conversion: SettlementConversion,
+    ) -> Result<(), BalanceStoreError> {
+        self.data
+            .write()
+            .settlement_conversions
+            .insert(account_id, conversion);
+        Ok(())
+    }
+
+    async fn get_last_settlement_conversion(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Option<SettlementConversion>, BalanceStoreError> {
+        Ok(self
+            .data
+            .read()
+            .settlement_conversions
+            .get(&account_id)
+            .cloned())
+    }
+
+    async fn update_balances_for_prepare(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+        packet_id: Option<[u8; 32]>,
+    ) -> Result<(), BalanceStoreError> {
+        // Don't do anything if the amount was 0
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let new_balance = {
+            let mut data = self.data.write();
+            let min_balance = data
+                .accounts
+                .get(&from_account_id)
+                .and_then(|account| account.min_balance);
+            let balance = data
+                .balance_mut(from_account_id)
+                .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+
+            // Check that the prepare wouldn't go under the account's minimum balance
+            let amount = incoming_amount as i64;
+            if let Some(min_balance) = min_balance {
+                if balance.balance + balance.prepaid_amount - amount < min_balance {
+                    return Err(BalanceStoreError::Other(Box::new(MinBalanceExceeded {
+                        amount: incoming_amount,
+                        account_id: from_account_id,
+                        balance: balance.balance,
+                        min_balance,
+                    })));
+                }
+            }
+
+            // Deduct the amount from the prepaid amount and/or the balance
+            if balance.prepaid_amount >= amount {
+                balance.prepaid_amount -= amount;
+            } else if balance.prepaid_amount > 0 {
+                balance.balance -= amount - balance.prepaid_amount;
+                balance.prepaid_amount = 0;
+            } else {
+                balance.balance -= amount;
+            }
+
+            trace!(
+            "Processed prepare with incoming amount: {}. Account {} has balance (including prepaid amount): {} ",
+            incoming_amount, from_account_id, balance.balance + balance.prepaid_amount
+        );
+            balance.balance + balance.prepaid_amount
+        };
+        self.publish_balance_event(
+            from_account_id,
+            new_balance + incoming_amount as i64,
+            new_balance,
+            BalanceChangeReason::Prepare,
+            packet_id,
+            None,
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn update_balances_for_fulfill(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+        packet_id: Option<[u8; 32]>,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let (new_balance, amount_to_settle) = {
+            let mut data = self.data.write();
+            let (settle_threshold, settle_to) = data
+                .accounts
+                .get(&to_account_id)
+                .map(|account| (account.settle_threshold, account.settle_to))
+                .unwrap_or((None, None));
+            let balance = data
+                .balance_mut(to_account_id)
+                .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+            balance.balance += outgoing_amount as i64;
+
+            // Settle if the threshold was reached, unless the account is configured
+            // to settle to an amount that is higher than the threshold
+            let mut amount_to_settle = 0;
+            if let (Some(settle_threshold), Some(settle_to)) = (settle_threshold, settle_to) {
+                if balance.balance >= settle_threshold && settle_threshold > settle_to {
+                    amount_to_settle = (balance.balance - settle_to) as u64;
+                    // The balance is updated before the settlement is sent so that we don't
+                    // send multiple settlements for the same balance. If the settlement fails
+                    // the amount is refunded
+                    balance.balance = settle_to;
+                }
+            }
+            let new_balance = balance.balance + balance.prepaid_amount;
+            if amount_to_settle > 0 {
+                data.last_settlements.insert(
+                    to_account_id,
+                    LastSettlement {
+                        amount: -(amount_to_settle as i64),
+                        timestamp: Utc::now().to_rfc3339(),
+                    },
+                );
+            }
+
+            trace!(
+            "Processed fulfill for account {} for outgoing amount {}. Fulfill call result: {} {}",
+            to_account_id,
+            outgoing_amount,
+            new_balance,
+            amount_to_settle,
+        );
+            (new_balance, amount_to_settle)
+        };
+        let balance_before_settlement = new_balance + amount_to_settle as i64;
+        self.publish_balance_event(
+            to_account_id,
+            balance_before_settlement - outgoing_amount as i64,
+            balance_before_settlement,
+            BalanceChangeReason::Fulfill,
+            packet_id,
+            None,
+        )
+        .await;
+        if amount_to_settle > 0 {
+            self.publish_balance_event(
+                to_account_id,
+                balance_before_settlement,
+                new_balance,
+                BalanceChangeReason::OutgoingSettlement,
+                packet_id,
+                None,
+            )
+            .await;
+        }
+        Ok((new_balance, amount_to_settle))
+    }
+
+    async fn update_balances_for_reject(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+        packet_id: Option<[u8; 32]>,
+    ) -> Result<(), BalanceStoreError> {
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let new_balance = {
+            let mut data = self.data.write();
+            let balance = data
+                .balance_mut(from_account_id)
+                .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+            balance.balance += incoming_amount as i64;
+            balance.balance + balance.prepaid_amount
+        };
+
+        trace!(
+            "Processed reject for incoming amount: {}. Account {} has balance (including prepaid amount): {}",
+            incoming_amount, from_account_id, new_balance
+        );
+        self.publish_balance_event(
+            from_account_id,
+            new_balance - incoming_amount as i64,
+            new_balance,
+            BalanceChangeReason::Reject,
+            packet_id,
+            None,
+        )
+        .await;
+        Ok(())
+    }
+}
+
+impl ExchangeRateStore for MemoryStore {
+    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        let data = self.data.read();
+        let rates: Vec<f64> = asset_codes
+            .iter()
+            .filter_map(|code| data.exchange_rates.get(*code).cloned())
+            .collect();
+        if rates.len() == asset_codes.len() {
+            Ok(rates)
+        } else {
+            Err(ExchangeRateStoreError::PairNotFound {
+                from: asset_codes[0].to_string(),
+                to: asset_codes[1].to_string(),
+            })
+        }
+    }
+
+    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        Ok(self.data.read().exchange_rates.clone())
+    }
+
+    fn set_exchange_rates(
+        &self,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        self.data.write().exchange_rates = rates;
+        Ok(())
+    }
+
+    fn set_rate_provenance(
+        &self,
+        provenance: Option<RateProvenance>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        self.data.write().rate_provenance = provenance;
+        Ok(())
+    }
+
+    fn get_rate_provenance(&self) -> Result<Option<RateProvenance>, ExchangeRateStoreError> {
+        Ok(self.data.read().rate_provenance.clone())
+    }
+
+    fn set_rate_source(&self, source: Option<RateSource>) -> Result<(), ExchangeRateStoreError> {
+        self.data.write().rate_source = source;
+        Ok(())
+    }
+
+    fn get_rate_source(&self) -> Result<Option<RateSource>, ExchangeRateStoreError> {
+        Ok(self.data.read().rate_source.clone())
+    }
+}
+
+#[async_trait]
+impl BtpStore for MemoryStore {
+    type Account = Account;
+
+    async fn get_account_from_btp_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, BtpStoreError> {
+        let account = self.get_account_from_username(username).ok_or_else(|| {
+            warn!("No account found with BTP token");
+            BtpStoreError::AccountNotFound(username.to_string())
+        })?;
+        if self.is_disabled(&account) {
+            return Err(BtpStoreError::Unauthorized(username.to_string()));
+        }
+
+        let authorized = match account.ilp_over_btp_incoming_token {
+            Some(ref hash) => verify_token_hash(&hash.expose_secret(), token.as_bytes()),
+            None => {
+                debug!(
+                    "Account {} does not have an incoming btp token configured",
+                    account.username
+                );
+                false
+            }
+        };
+        if authorized {
+            Ok(account)
+        } else {
+            debug!(
+                "Found account {} but BTP auth token was wrong",
+                account.username
+            );
+            Err(BtpStoreError::Unauthorized(username.to_string()))
+        }
+    }
+
+    async fn get_btp_outgoing_accounts(&self) -> Result<Vec<Self::Account>, BtpStoreError> {
+        Ok(self
+            .data
+            .read()
+            .accounts_where(|account| account.ilp_over_btp_url.is_some()))
+    }
+}
+
+#[async_trait]
+impl HttpStore for MemoryStore {
+    type Account = Account;
+
+    /// Checks if the stored token for the provided account id matches the
+    /// provided token, and if so, returns the account associated with that token
+    async fn get_account_from_http_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, HttpStoreError> {
+        let account = self.get_account_from_username(username).ok_or_else(|| {
+            warn!("No account found with given HTTP auth");
+            HttpStoreError::AccountNotFound(username.to_string())
+        })?;
+        if self.is_disabled(&account) {
+            return Err(HttpStoreError::Unauthorized(username.to_string()));
+        }
+
+        match account.ilp_over_http_incoming_token {
+            Some(ref t) if t.expose_secret().as_ref() == token.as_bytes() => Ok(account),
+            _ => Err(HttpStoreError::Unauthorized(username.to_string())),
+        }
+    }
+
+    async fn get_account_from_client_certificate(
+        &self,
+        username: &Username,
+        fingerprint: &str,
+    ) -> Result<Option<Self::Account>, HttpStoreError> {
+        let account = self.get_account_from_username(username).ok_or_else(|| {
+            warn!("No account found with given client certificate");
+            HttpStoreError::AccountNotFound(username.to_string())
+        })?;
+        if self.is_disabled(&account) {
+            return Err(HttpStoreError::Unauthorized(username.to_string()));
+        }
+
+        if account.ilp_over_http_client_certificates.is_empty() {
+            Ok(None)
+        } else if account
+            .ilp_over_http_client_certificates
+            .iter()
+            .any(|accepted| accepted == fingerprint)
+        {
+            Ok(Some(account))
+        } else {
+            Err(HttpStoreError::Unauthorized(username.to_string()))
+        }
+    }
+}
+
+impl RouterStore for MemoryStore {
+    fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
+        self.data.read().routing_table.clone()
+    }
+
+    fn multipath_routing_table(&self) -> Arc<HashMap<String, Vec<Uuid>>> {
+        self.data.read().multipath_routes.clone()
+    }
+
+    fn draining_accounts(&self) -> Arc<HashSet<Uuid>> {
+        self.data.read().draining_accounts.clone()
+    }
+
+    fn disabled_accounts(&self) -> Arc<HashSet<Uuid>> {
+        self.data.read().disabled_accounts.clone()
+    }
+}
+
+#[async_trait]
+impl NodeStore for MemoryStore {
+    type Account = Account;
+
+    async fn insert_account(
+        &self,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let mut data = self.data.write();
+        let id = Uuid::new_v4();
+        let account = Account::try_from(id, account, data.ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+        debug!(
+            "Generated account id for {}: {}",
+            account.username, account.id
+        );
+
+        // Check that there isn't already an account with values that MUST be unique
+        if data.usernames.contains_key(account.username.as_ref())
+            || (account.routing_relation == RoutingRelation::Parent
+                && data.parent_ilp_address.is_some())
+        {
+            warn!(
+                "An account already exists with the same {}. Cannot insert account: {:?}",
+                account.id, account
+            );
+            return Err(NodeStoreError::AccountExists(account.username.to_string()));
+        }
+
+        data.save_account(account.clone());
+        debug!(
+            "Inserted account {} (ILP address: {})",
+            account.id, account.ilp_address
+        );
+        Ok(account)
+    }
+
+    async fn delete_account(&self, id: Uuid) -> Result<Account, NodeStoreError> {
+        let mut data = self.data.write();
+        let account = data
+            .accounts
+            .remove(&id)
+            .ok_or_else(|| NodeStoreError::AccountNotFound(id.to_string()))?;
+        data.usernames.remove(account.username.as_ref());
+        data.balances.remove(&id);
+        data.last_settlements.remove(&id);
+        data.settlement_conversions.remove(&id);
+        data.uncredited_settlement_amounts.remove(&id);
+        data.routes.remove(&account.ilp_address.to_string());
+        data.update_routing_table();
+        Arc::make_mut(&mut data.draining_accounts).remove(&id);
+        Arc::make_mut(&mut data.disabled_accounts).remove(&id);
+        debug!("Deleted account {}", account.id);
+        Ok(account)
+    }
+
+    async fn update_account(
+        &self,
+        id: Uuid,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let mut data = self.data.write();
+        let account = Account::try_from(id, account, data.ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+
+        let (old_username, old_ilp_address) = match data.accounts.get(&id) {
+            Some(old) => (old.username.to_string(), old.ilp_address.to_string()),
+            None => {
+                warn!(
+                    "No account exists with ID {}, cannot update account {:?}",
+                    account.id, account
+                );
+                return Err(NodeStoreError::AccountNotFound(account.id.to_string()));
+            }
+        };
+        if old_username != account.username.as_ref() {
+            if data.usernames.contains_key(account.username.as_ref()) {
+                return Err(NodeStoreError::AccountExists(account.username.to_string()));
+            }
+            data.usernames.remove(&old_username);
+        }
+        data.routes.remove(&old_ilp_address);
+
+        data.save_account(account.clone());
+        debug!(
+            "Updated account {} (id: {}, ILP address: {})",
+            account.username, account.id, account.ilp_address
+        );
+        Ok(account)
+    }
+
+    async fn modify_account_settings(
+        &self,
+        id: Uuid,
+        settings: AccountSettings,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let settle_to = match settings.settle_to {
+            // Balances are signed 64-bit integers, like in the Redis store
+            Some(settle_to) if settle_to > std::i64::MAX as u64 => {
+                return Err(NodeStoreError::InvalidAccount(
+                    CreateAccountError::ParamTooLarge("settle_to".to_owned()),
+                ));
+            }
+            settle_to => settle_to.map(|settle_to| settle_to as i64),
+        };
+        let ilp_over_http_url = match settings.ilp_over_http_url {
+            Some(ref url) => Some(Url::parse(url).map_err(|err| {
+                NodeStoreError::InvalidAccount(CreateAccountError::InvalidHttpUrl(err))
+            })?),
+            None => None,
+        };
+        let ilp_over_btp_url = match settings.ilp_over_btp_url {
+            Some(ref url) => Some(Url::parse(url).map_err(|err| {
+                NodeStoreError::InvalidAccount(CreateAccountError::InvalidBtpUrl(err))
+            })?),
+            None => None,
+        };
+
+        let mut data = self.data.write();
+        let account = data
+            .accounts
+            .get_mut(&id)
+            .ok_or_else(|| NodeStoreError::AccountNotFound(id.to_string()))?;
+        if let Some(url) = ilp_over_http_url {
+            account.ilp_over_http_url = Some(url);
+        }
+        if let Some(url) = ilp_over_btp_url {
+            account.ilp_over_btp_url = Some(url);
+        }
+        if let Some(token) = settings.ilp_over_http_incoming_token {
+            account.ilp_over_http_incoming_token =
+                Some(SecretBytesMut::new(token.expose_secret().as_str()));
+        }
+        if let Some(token) = settings.ilp_over_http_outgoing_token {
+            account.ilp_over_http_outgoing_token =
+                Some(SecretBytesMut::new(token.expose_secret().as_str()));
+        }
+        if let Some(token) = settings.ilp_over_btp_incoming_token {
+            account.ilp_over_btp_incoming_token = Some(SecretBytesMut::from(hash_token(
+                token.expose_secret().as_bytes(),
+            )));
+        }
+        if let Some(token) = settings.ilp_over_btp_outgoing_token {
+            account.ilp_over_btp_outgoing_token =
+                Some(SecretBytesMut::new(token.expose_secret().as_str()));
+        }
+        if let Some(settle_threshold) = settings.settle_threshold {
+            account.settle_threshold = Some(settle_threshold);
+        }
+        if settle_to.is_some() {
+            account.settle_to = settle_to;
+        }
+
+        // return the updated account
+        data.load_account(&id)
+            .ok_or_else(|| NodeStoreError::AccountNotFound(id.to_string()))
+    }
+
+    async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError> {
+        Ok(self.data.read().accounts_where(|_| true))
+    }
+
+    async fn set_static_routes<R>(&self, routes: R) -> Result<(), NodeStoreError>
+    where
+        R: IntoIterator<Item = (String, Uuid)> + Send + 'async_trait,
+    {
+        let routes: HashMap<String, Uuid> = routes.into_iter().collect();
+        let mut data = self.data.write();
+        if !routes.values().all(|id| data.accounts.contains_key(id)) {
+            error!("Error setting static routes because not all of the given accounts exist");
+            return Err(NodeStoreError::MissingAccounts);
+        }
+
+        data.static_routes = routes;
+        data.update_routing_table();
+        Ok(())
+    }
+
+    async fn set_static_route(
+        &self,
+        prefix: String,
+        account_id: Uuid,
+    ) -> Result<(), NodeStoreError> {
+        let mut data = self.data.write();
+        if !data.accounts.contains_key(&account_id) {
+            error!(
+                "Cannot set static route for prefix: {} because account {} does not exist",
+                prefix, account_id
+            );
+            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+        }
+
+        data.static_routes.insert(prefix, account_id);
+        data.update_routing_table();
+        Ok(())
+    }
+
+    async fn get_static_routes(&self) -> Result<HashMap<String, Uuid>, NodeStoreError> {
+        Ok(self.data.read().static_routes.clone())
+    }
+
+    async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError> {
+        let mut data = self.data.write();
+        if !data.accounts.contains_key(&account_id) {
+            error!(
+                "Cannot set default route because account {} does not exist",
+                account_id
+            );
+            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+        }
+
+        data.default_route = Some(account_id);
+        debug!("Set default route to account id: {}", account_id);
+        data.update_routing_table();
+        Ok(())
+    }
+
+    async fn set_account_draining(&self, id: Uuid, draining: bool) -> Result<(), NodeStoreError> {
+        let mut data = self.data.write();
+        if !data.accounts.contains_key(&id) {
+            return Err(NodeStoreError::AccountNotFound(id.to_string()));
+        }
+
+        let draining_accounts = Arc::make_mut(&mut data.draining_accounts);
+        if draining {
+            draining_accounts.insert(id);
+            debug!("Draining account {}", id);
+        } else {
+            draining_accounts.remove(&id);
+            debug!("Stopped draining account {}", id);
+        }
+        Ok(())
+    }
+
+    async fn set_account_enabled(&self, id: Uuid, enabled: bool) -> Result<(), NodeStoreError> {
+        let mut data = self.data.write();
+        if !data.accounts.contains_key(&id) {
+            return Err(NodeStoreError::AccountNotFound(id.to_string()));
+        }
+
+        let disabled_accounts = Arc::make_mut(&mut data.disabled_accounts);
+        if enabled {
+            disabled_accounts.remove(&id);
+            debug!("Enabled account {}", id);
+        } else {
+            disabled_accounts.insert(id);
+            debug!("Disabled account {}", id);
+        }
+        Ok(())
+    }
+
+    async fn set_settlement_engines(
+        &self,
+        asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
+    ) -> Result<(), NodeStoreError> {
+        let asset_to_url_map: Vec<(String, Url)> = asset_to_url_map.into_iter().collect();
+        debug!("Setting settlement engines to {:?}", asset_to_url_map);
+        self.data
+            .write()
+            .settlement_engines
+            .extend(asset_to_url_map);
+        Ok(())
+    }
+
+    async fn get_asset_settlement_engine(
+        &self,
+        asset_code: &str,
+    ) -> Result<Option<Url>, NodeStoreError> {
+        Ok(self.data.read().settlement_engines.get(asset_code).cloned())
+    }
+
+    async fn export_node(&self) -> Result<NodeExport, NodeStoreError> {
+        let data = self.data.read();
+        let accounts = data
+            .accounts
+            .values()
+            .map(|account| {
+                let balance = data.balances.get(&account.id).cloned().unwrap_or_default();
+                account.export(balance.balance, balance.prepaid_amount)
+            })
+            .collect();
+        Ok(NodeExport {
+            accounts,
+            static_routes: data.static_routes.clone(),
+            default_route: data.default_route,
+        })
+    }
+
+    async fn import_node(&self, export: NodeExport) -> Result<Vec<Self::Account>, NodeStoreError> {
+        let mut data = self.data.write();
+        let node_ilp_address = data.ilp_address();
+        let mut accounts = Vec::with_capacity(export.accounts.len());
+        for exported in export.accounts {
+            let balance = Balance {
+                balance: exported.balance,
+                prepaid_amount: exported.prepaid_amount,
+            };
+            let account = Account::from_export(exported, node_ilp_address.clone())
+                .map_err(NodeStoreError::InvalidAccount)?;
+            accounts.push((account, balance));
+        }
+
+        // Check all accounts before saving any of them, so that nothing is imported
+        // if one of them already exists
+        let mut ids = HashSet::new();
+        let mut usernames = HashSet::new();
+        let mut parent_exists = data.parent_ilp_address.is_some();
+        for (account, _) in accounts.iter() {
+            let is_parent = account.routing_relation == RoutingRelation::Parent;
+            if data.accounts.contains_key(&account.id)
+                || data.usernames.contains_key(account.username.as_ref())
+                || !ids.insert(account.id)
+                || !usernames.insert(account.username.to_string())
+                || (is_parent && parent_exists)
+            {
+                warn!(
+                    "An account already exists with the same {}. Cannot import account: {:?}",
+                    account.id, account
+                );
+                return Err(NodeStoreError::AccountExists(account.username.to_string()));
+            }
+            parent_exists |= is_parent;
+        }
+        if !export
+            .static_routes
+            .values()
+            .chain(export.default_route.iter())
+            .all(|id| ids.contains(id))
+        {
+            error!("Error importing routes because not all of their accounts are imported");
+            return Err(NodeStoreError::MissingAccounts);
+        }
+
+        for (account, balance) in accounts.iter() {
+            data.save_account_as_is(account.clone());
+            data.balances.insert(account.id, *balance);
+        }
+        data.static_routes.extend(export.static_routes);
+        if export.default_route.is_some() {
+            data.default_route = export.default_route;
+        }
+        data.update_routing_table();
+        debug!("Imported {} accounts", accounts.len());
+        Ok(accounts
+            .iter()
+            .filter_map(|(account, _)| data.load_account(&account.id))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl AddressStore for MemoryStore {
+    // Updates the ILP address of the store & iterates over all children and
+    // updates their ILP Address to match the new address.
+    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        debug!("Setting ILP address to: {}", ilp_address);
+        let mut data = self.data.write();
+        data.ilp_address = Some(ilp_address.clone());
+        data.parent_ilp_address = Some(ilp_address.clone());
+
+        let first_segment = ilp_address
+            .segments()
+            .rev()
+            .next()
+            .expect("address did not have a first segment, this should be impossible");
+        let data = &mut *data;
+        for account in data.accounts.values_mut() {
+            // Update the address and routes of all children and non-routing accounts.
+            if account.routing_relation() != RoutingRelation::Parent
+                && account.routing_relation() != RoutingRelation::Peer
+            {
+                data.routes.remove(&account.ilp_address.to_string());
+
+                // if the username of the account ends with the
+                // node's address, we're already configured so no
+                // need to append anything.
+                account.ilp_address = if first_segment == account.username().to_string() {
+                    ilp_address.clone()
+                } else {
+                    ilp_address
+                        .with_suffix(account.username().as_bytes())
+                        .unwrap()
+                };
+                data.routes
+                    .insert(account.ilp_address.to_string(), account.id);
+            }
+        }
+        data.update_routing_table();
+        Ok(())
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        let mut data = self.data.write();
+        data.parent_ilp_address = None;
+        // overwrite the ilp address with the default value
+        data.ilp_address = None;
+        Ok(())
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        self.data.read().ilp_address()
+    }
+}
+
+type RoutingTable<A> = HashMap<String, A>;
+
+#[async_trait]
+impl CcpRoutingStore for MemoryStore {
+    type Account = Account;
+
+    async fn get_accounts_to_send_routes_to(
+        &self,
+        ignore_accounts: Vec<Uuid>,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        Ok(self.data.read().accounts_where(|account| {
+            account.should_send_routes() && !ignore_accounts.contains(&account.id)
+        }))
+    }
+
+    async fn get_accounts_to_receive_routes_from(
+        &self,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        Ok(self
+            .data
+            .read()
+            .accounts_where(|account| account.should_receive_routes()))
+    }
+
+    async fn get_local_and_configured_routes(
+        &self,
+    ) -> Result<(RoutingTable<Account>, RoutingTable<Account>), CcpRoutingStoreError> {
+        let data = self.data.read();
+        let local_table = data
+            .accounts_where(|_| true)
+            .into_iter()
+            .map(|account| (account.ilp_address.to_string(), account))
+            .collect();
+        let configured_table = data
+            .static_routes
+            .iter()
+            .filter_map(|(prefix, account_id)| {
+                if let Some(account) = data.load_account(account_id) {
+                    Some((prefix.clone(), account))
+                } else {
+                    warn!(
+                        "No account for ID: {}, ignoring configured route for prefix: {}",
+                        account_id, prefix
+                    );
+                    None
+                }
+            })
+            .collect();
+
+        Ok((local_table, configured_table))
+    }
+
+    async fn set_routes(
+        &mut self,
+        routes: impl IntoIterator<Item = (String, Account)> + Send + 'async_trait,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let routes: HashMap<String, Uuid> = routes
+            .into_iter()
+            .map(|(prefix, account)| (prefix, account.id))
+            .collect();
+        trace!("Saved {} routes", routes.len());
+        let mut data = self.data.write();
+        data.routes = routes;
+        data.update_routing_table();
+        Ok(())
+    }
+
+    async fn set_multipath_routes(
+        &mut self,
+        routes: HashMap<String, Vec<Uuid>>,
+    ) -> Result<(), CcpRoutingStoreError> {
+        self.data.write().multipath_routes = Arc::new(routes);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    type Account = Account;
+
+    async fn apply_rate_limits(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        self.rate_limits
+            .apply_rate_limits(account, prepare_amount)
+            .await
+    }
+
+    async fn refund_throughput_limit(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        self.rate_limits
+            .refund_throughput_limit(account, prepare_amount)
+            .await
+    }
+}
+
+/// Returns the index of the current window of the given length
+fn window_index(window: Duration) -> u64 {
+    Utc::now().timestamp() as u64 / window.as_secs().max(1)
+}
+
+#[async_trait]
+impl VolumeStore for MemoryStore {
+    async fn get_outgoing_volume(&self, account_id: Uuid, window: Duration) -> Result<u64, ()> {
+        let index = window_index(window);
+        match self.data.read().outgoing_volumes.get(&account_id) {
+            Some((window, volume)) if *window == index => Ok(*volume),
+            _ => Ok(0),
+        }
+    }
+
+    async fn add_outgoing_volume(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        window: Duration,
+    ) -> Result<u64, ()> {
+        let index = window_index(window);
+        let mut data = self.data.write();
+        let entry = data
+            .outgoing_volumes
+            .entry(account_id)
+            .or_insert((index, 0));
+        if entry.0 != index {
+            *entry = (index, 0);
+        }
+        entry.1 = entry.1.saturating_add(amount);
+        Ok(entry.1)
+    }
+}
+
+#[async_trait]
+impl TrafficStore for MemoryStore {
+    async fn get_traffic(&self, account_id: Uuid) -> Result<TrafficCounters, ()> {
+        Ok(self
+            .data
+            .read()
+            .traffic
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn add_traffic(&self, account_id: Uuid, traffic: TrafficCounters) -> Result<(), ()> {
+        self.data
+            .write()
+            .traffic
+            .entry(account_id)
+            .or_default()
+            .add(&traffic);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FulfilledConditionStore for MemoryStore {
+    async fn was_condition_fulfilled(&self, condition: [u8; 32]) -> Result<bool, ()> {
+        match self.data.read().fulfilled_conditions.get(&condition) {
+            Some(expires_at) => Ok(*expires_at > Instant::now()),
+            None => Ok(false),
+        }
+    }
+
+    async fn save_fulfilled_condition(
+        &self,
+        condition: [u8; 32],
+        window: Duration,
+    ) -> Result<(), ()> {
+        let now = Instant::now();
+        let mut data = self.data.write();
+        // Forget the expired conditions, as Redis would
+        data.fulfilled_conditions
+            .retain(|_, expires_at| *expires_at > now);
+        data.fulfilled_conditions.insert(condition, now + window);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IdempotentStore for MemoryStore {
+    async fn load_idempotent_data(
+        &self,
+        idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        let data = self.data.read();
+        match data.idempotent_data.get(&idempotency_key) {
+            Some((idempotent_data, saved_at)) if saved_at.elapsed() < IDEMPOTENCY_KEY_EXPIRY => {
+                trace!(
+                    "Loaded idempotency key {:?} - {:?}",
+                    idempotency_key,
+                    idempotent_data
+                );
+                Ok(Some(idempotent_data.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        idempotency_key: String,
+        input_hash: [u8; 32],
+        status_code: StatusCode,
+        data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        trace!(
+            "Cached {:?}: {:?}, {:?}",
+            idempotency_key,
+            status_code,
+            data,
+        );
+        let mut store_data = self.data.write();
+        // Drop the expired keys so they don't accumulate
+        store_data
+            .idempotent_data
+            .retain(|_, (_, saved_at)| saved_at.elapsed() < IDEMPOTENCY_KEY_EXPIRY);
+        store_data.idempotent_data.insert(
+            idempotency_key,
+            (
+                IdempotentData::new(status_code, data, input_hash),
+                Instant::now(),
+            ),
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SettlementStore for MemoryStore {
+    type Account = Account;
+
+    async fn update_balance_for_incoming_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: Option<String>,
+    ) -> Result<(), SettlementStoreError> {
+        let new_balance = {
+            let mut data = self.data.write();
+            // If the idempotency key has been used, then do not perform any operations
+            if let Some(ref idempotency_key) = idempotency_key {
+                if !data
+                    .settlement_idempotency_keys
+                    .insert(idempotency_key.clone())
+                {
+                    return Ok(());
+                }
+            }
+
+            let balance = data
+                .balance_mut(account_id)
+                .map_err(|err| SettlementStoreError::Other(Box::new(err)))?;
+            // Credit the incoming settlement to the balance and/or prepaid amount,
+            // depending on whether that account currently owes money or not
+            let amount = amount as i64;
+            if balance.balance >= 0 {
+                balance.prepaid_amount += amount;
+            } else if balance.balance.abs() >= amount {
+                balance.balance += amount;
+            } else {
+                balance.prepaid_amount += amount + balance.balance;
+                balance.balance = 0;
+            }
+
+            let new_balance = balance.balance + balance.prepaid_amount;
+            data.last_settlements.insert(
+                account_id,
+                LastSettlement {
+                    amount,
+                    timestamp: Utc::now().to_rfc3339(),
+                },
+            );
+
+            trace!(
+                "Processed incoming settlement from account: {} for amount: {}. Balance is now: {}",
+                account_id,
+                amount,
+                new_balance
+            );
+            new_balance
+        };
+        self.publish_balance_event(
+            account_id,
+            new_balance - amount as i64,
+            new_balance,
+            BalanceChangeReason::IncomingSettlement,
+            None,
+            idempotency_key,
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn refund_settlement(
+        &self,
+        account_id: Uuid,
+        settle_amount: u64,
+    ) -> Result<(), SettlementStoreError> {
+        trace!(
+            "Refunding settlement for account: {} of amount: {}",
+            account_id,
+            settle_amount
+        );
+        let new_balance = {
+            let mut data = self.data.write();
+            let balance = data
+                .balance_mut(account_id)
+                .map_err(|err| SettlementStoreError::Other(Box::new(err)))?;
+            balance.balance += settle_amount as i64;
+            let new_balance = balance.balance + balance.prepaid_amount;
+            // Forget the settlement unless another one was applied in the meantime
+            let refunded = data
+                .last_settlements
+                .get(&account_id)
+                .map_or(false, |last| last.amount == -(settle_amount as i64));
+            if refunded {
+                data.last_settlements.remove(&account_id);
+            }
+            new_balance
+        };
+
+        trace!(
+            "Refunded settlement for account: {} of amount: {}. Balance is now: {}",
+            account_id,
+            settle_amount,
+            new_balance
+        );
+        self.publish_balance_event(
+            account_id,
+            new_balance - settle_amount as i64,
+            new_balance,
+            BalanceChangeReason::SettlementRefund,
+            None,
+            None,
+        )
+        .await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LeftoversStore for MemoryStore {
+    type AccountId = Uuid;
+    type AssetType = BigUint;
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        let amounts = self
+            .data
+            .write()
+            .uncredited_settlement_amounts
+            .remove(&account_id)
+            .unwrap_or_default();
+
+        // We must scale the amounts to the largest scale, and then add them together
+        let max_scale = amounts.iter().map(|(_, scale)| *scale).max().unwrap_or(0);
+        let mut sum = BigUint::from(0u32);
+        for (num, scale) in amounts {
+            sum += num
+                .normalize_scale(ConvertDetails {
+                    from: scale,
+                    to: max_scale,
+                })
+                .unwrap();
+        }
+        Ok((sum, max_scale))
+    }
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        uncredited_settlement_amount: (Self::AssetType, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        trace!(
+            "Saving uncredited_settlement_amount {:?} {:?}",
+            account_id,
+            uncredited_settlement_amount
+        );
+        self.data
+            .write()
+            .uncredited_settlement_amounts
+            .entry(account_id)
+            .or_default()
+            .push(uncredited_settlement_amount);
+        Ok(())
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        local_scale: u8,
+    ) -> Result<Self::AssetType, LeftoversStoreError> {
+        trace!("Loading uncredited_settlement_amount {:?}", account_id);
+        let amount = self.get_uncredited_settlement_amount(account_id).await?;
+        // scale the amount from the max scale to the local scale, and then
+        // save any potential leftovers to the store
+        let (scaled_amount, precision_loss) =
+            scale_with_precision_loss(amount.0, local_scale, amount.1);
+
+        if precision_loss > BigUint::from(0u32) {
+            self.data
+                .write()
+                .uncredited_settlement_amounts
+                .entry(account_id)
+                .or_default()
+                .push((precision_loss, std::cmp::max(local_scale, amount.1)));
+        }
+
+        Ok(scaled_amount)
+    }
+
+    async fn clear_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(), LeftoversStoreError> {
+        trace!("Clearing uncredited_settlement_amount {:?}", account_id);
+        self.data
+            .write()
+            .uncredited_settlement_amounts
+            .remove(&account_id);
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 local to_account = 'accounts:' .. ARGV[1]
 local to_amount = tonumber(ARGV[2])
+local timestamp = ARGV[3]
 
 local balance = redis.call('HINCRBY', to_account, 'balance', to_amount)
 local prepaid_amount, settle_threshold, settle_to = unpack(redis.call('HMGET', to_account, 'prepaid_amount', 'settle_threshold', 'settle_to'))
@@ -17,6 +18,8 @@ if (settle_threshold and settle_to) and (balance >= tonumber(settle_threshold))
     -- the balance change by re-adding the amount back to the balance
     balance = settle_to
     redis.call('HSET', to_account, 'balance', balance)
+    -- Outgoing settlements are recorded with a negative amount, as they reduce the balance
+    redis.call('HMSET', to_account, 'last_settlement_amount', -settle_amount, 'last_settlement_at', timestamp)
 end
 
 return {balance + prepaid_amount, settle_amount}
@@ -1,12 +1,13 @@
 local account = 'accounts:' .. ARGV[1]
 local amount = tonumber(ARGV[2])
 local idempotency_key = ARGV[3]
+local timestamp = ARGV[4]
 
 local balance, prepaid_amount = unpack(redis.call('HMGET', account, 'balance', 'prepaid_amount'))
 
 -- If idempotency key has been used, then do not perform any operations
 if redis.call('EXISTS', idempotency_key) == 1 then
-    return balance + prepaid_amount
+    return {balance + prepaid_amount, 0}
 end
 
 -- Otherwise, set it to true and make it expire after 24h (86400 sec)
@@ -23,5 +24,6 @@ else
     balance = 0
     redis.call('HSET', account, 'balance', 0)
 end
+redis.call('HMSET', account, 'last_settlement_amount', amount, 'last_settlement_at', timestamp)
 
-return balance + prepaid_amount
+return {balance + prepaid_amount, 1}
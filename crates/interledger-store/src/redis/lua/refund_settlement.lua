@@ -2,4 +2,10 @@ local account = 'accounts:' .. ARGV[1]
 local settle_amount = tonumber(ARGV[2])
 
 local balance = redis.call('HINCRBY', account, 'balance', settle_amount)
-return balance
+local prepaid_amount = redis.call('HGET', account, 'prepaid_amount')
+
+-- Forget the settlement unless another one was applied in the meantime
+if tonumber(redis.call('HGET', account, 'last_settlement_amount')) == -settle_amount then
+    redis.call('HDEL', account, 'last_settlement_amount', 'last_settlement_at')
+end
+return balance + prepaid_amount
@@ -6,6 +6,7 @@
 //   routes:current         hash        dynamic routing table
 //   routes:static          hash        static routing table
 //   accounts:<id>          hash        information for each account
+//   fulfilled:<condition>  string      execution conditions of fulfilled packets, for replay protection
 //   btp_outgoing
 // For interactive exploration of the store,
 // use the redis-cli tool included with your redis install.
@@ -15,25 +16,32 @@
 //    get <key>             get the value of a key
 //    hgetall <key>         the flattened list of every key/value entry within a hash
 mod reconnect;
-use reconnect::RedisReconnect;
+pub(crate) use reconnect::RedisReconnect;
+pub use reconnect::{ConnectionStatus, DEFAULT_RETRY_TIMEOUT};
 
 use super::account::{Account, AccountWithEncryptedTokens};
-use super::crypto::{encrypt_token, generate_keys, DecryptionKey, EncryptionKey};
+use super::crypto::{encrypt_token, generate_keys, hash_token, DecryptionKey, EncryptionKey};
+use super::events::{BalanceChangeReason, BalanceEventSink};
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
+use chrono::Utc;
 use futures::channel::mpsc::UnboundedSender;
 use http::StatusCode;
-use interledger_api::{AccountDetails, AccountSettings, EncryptedAccountSettings, NodeStore};
+use interledger_api::{
+    AccountDetails, AccountSettings, EncryptedAccountSettings, NodeExport, NodeStore,
+};
 use interledger_btp::BtpStore;
 use interledger_ccp::{CcpRoutingAccount, CcpRoutingStore, RoutingRelation};
 use interledger_errors::*;
 use interledger_http::HttpStore;
 use interledger_packet::Address;
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{ExchangeRateStore, RateProvenance, RateSource};
 use interledger_router::RouterStore;
 use interledger_service::{Account as AccountTrait, AccountStore, AddressStore, Username};
 use interledger_service_util::{
-    BalanceStore, RateLimitError, RateLimitStore, DEFAULT_ROUND_TRIP_TIME,
+    BalanceStore, FulfilledConditionStore, LastSettlement, RateLimitError, RateLimitStore,
+    RoundingPolicy, SettlementConversion, TrafficCounters, TrafficStore, VolumeStore,
+    DEFAULT_ROUND_TRIP_TIME,
 };
 use interledger_settlement::core::{
     idempotency::{IdempotentData, IdempotentStore},
@@ -62,20 +70,23 @@ use std::{
     str,
     str::FromStr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use url::Url;
 use uuid::Uuid;
 use zeroize::Zeroize;
 
 const DEFAULT_POLL_INTERVAL: u64 = 30000; // 30 seconds
-const ACCOUNT_DETAILS_FIELDS: usize = 21;
+const ACCOUNT_DETAILS_FIELDS: usize = 37;
 
 static PARENT_ILP_KEY: &str = "parent_node_account_address";
 static ROUTES_KEY: &str = "routes:current";
 static STATIC_ROUTES_KEY: &str = "routes:static";
 static DEFAULT_ROUTE_KEY: &str = "routes:default";
+static DRAINING_ACCOUNTS_KEY: &str = "draining_accounts";
+static DISABLED_ACCOUNTS_KEY: &str = "disabled_accounts";
 static STREAM_NOTIFICATIONS_PREFIX: &str = "stream_notifications:";
+static HTTP_AUTH_INVALIDATIONS_PREFIX: &str = "http_auth_invalidations:";
 static SETTLEMENT_ENGINES_KEY: &str = "settlement_engines";
 
 /// Domain separator for leftover amounts
@@ -93,6 +104,10 @@ fn accounts_key(account_id: Uuid) -> String {
     format!("accounts:{}", account_id)
 }
 
+fn settlement_conversion_key(account_id: Uuid) -> String {
+    format!("settlement-conversion:{}", account_id)
+}
+
 // TODO: Add descriptive errors inside the lua scripts!
 
 // The following are Lua scripts that are used to atomically execute the given logic
@@ -143,6 +158,9 @@ pub struct RedisStoreBuilder {
     poll_interval: u64,
     /// Connector's ILP Address. Used to insert `Child` accounts as
     node_ilp_address: Address,
+    balance_events: Option<BalanceEventSink>,
+    http_auth_cache_ttl: Option<Duration>,
+    retry_timeout: Duration,
 }
 
 impl RedisStoreBuilder {
@@ -153,6 +171,9 @@ impl RedisStoreBuilder {
             secret,
             poll_interval: DEFAULT_POLL_INTERVAL,
             node_ilp_address: DEFAULT_ILP_ADDRESS.clone(),
+            balance_events: None,
+            http_auth_cache_ttl: None,
+            retry_timeout: DEFAULT_RETRY_TIMEOUT,
         }
     }
 
@@ -168,6 +189,30 @@ impl RedisStoreBuilder {
         self
     }
 
+    /// Sets the sink which is sent an event for every change to an account's balance
+    pub fn balance_events(&mut self, sink: BalanceEventSink) -> &mut Self {
+        self.balance_events = Some(sink);
+        self
+    }
+
+    /// Caches the accounts which authenticated with an ILP over HTTP bearer token for
+    /// the given time, so that packets with the same token are not checked against Redis.
+    /// The cached accounts of all stores using the same database are dropped as soon as
+    /// the account is modified or deleted.
+    pub fn http_auth_cache_ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.http_auth_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Sets how long commands wait for the store to reconnect after the connection to
+    /// Redis dropped, e.g. while Redis fails over to a replica. Only the commands which
+    /// were not sent yet or only read data are sent after reconnecting.
+    /// Defaults to 1 second
+    pub fn retry_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.retry_timeout = timeout;
+        self
+    }
+
     /// Connects to the Redis Store
     ///
     /// Specifically
@@ -189,6 +234,7 @@ impl RedisStoreBuilder {
         let mut connection = RedisReconnect::connect(redis_info.clone())
             .map_err(|_| ())
             .await?;
+        connection.retry_timeout(self.retry_timeout);
         let mut sub_connection = client
             .get_connection()
             .map_err(|err| error!("Error connecting subscription client to Redis: {:?}", err))?;
@@ -216,29 +262,46 @@ impl RedisStoreBuilder {
             connection,
             subscriptions: Arc::new(RwLock::new(HashMap::new())),
             exchange_rates: Arc::new(RwLock::new(HashMap::new())),
+            rate_provenance: Arc::new(RwLock::new(None)),
+            rate_source: Arc::new(RwLock::new(None)),
             routes: Arc::new(RwLock::new(Arc::new(HashMap::new()))),
+            multipath_routes: Arc::new(RwLock::new(Arc::new(HashMap::new()))),
+            draining_accounts: Arc::new(RwLock::new(Arc::new(HashSet::new()))),
+            disabled_accounts: Arc::new(RwLock::new(Arc::new(HashSet::new()))),
             encryption_key: Arc::new(encryption_key),
             decryption_key: Arc::new(decryption_key),
+            balance_events: self.balance_events.clone(),
+            http_auth_cache: Arc::new(RwLock::new(HashMap::new())),
+            http_auth_cache_ttl: self.http_auth_cache_ttl,
         };
 
         // Poll for routing table updates
         // Note: if this behavior changes, make sure to update the Drop implementation
-        let connection_clone = Arc::downgrade(&store.connection.conn);
-        let redis_info = store.connection.redis_info.clone();
+        let connection_clone = store.connection.downgrade();
         let routing_table = store.routes.clone();
+        let draining_accounts = store.draining_accounts.clone();
+        let disabled_accounts = store.disabled_accounts.clone();
 
         let poll_routes = async move {
             let mut interval = tokio::time::interval(Duration::from_millis(poll_interval));
             // Irrefutable while pattern, can we do something here?
             loop {
                 interval.tick().await;
-                if let Some(conn) = connection_clone.upgrade() {
-                    let _ = update_routes(
-                        RedisReconnect {
-                            conn,
-                            redis_info: redis_info.clone(),
-                        },
-                        routing_table.clone(),
+                if let Some(connection) = connection_clone.upgrade() {
+                    let _ = update_routes(connection.clone(), routing_table.clone())
+                        .map_err(|err| error!("{}", err))
+                        .await;
+                    let _ = update_account_set(
+                        connection.clone(),
+                        DRAINING_ACCOUNTS_KEY,
+                        draining_accounts.clone(),
+                    )
+                    .map_err(|err| error!("{}", err))
+                    .await;
+                    let _ = update_account_set(
+                        connection,
+                        DISABLED_ACCOUNTS_KEY,
+                        disabled_accounts.clone(),
                     )
                     .map_err(|err| error!("{}", err))
                     .await;
@@ -256,6 +319,7 @@ impl RedisStoreBuilder {
         // This currently must be a thread rather than a task due to the redis-rs driver
         // not yet supporting asynchronous subscriptions (see https://github.com/mitsuhiko/redis-rs/issues/183).
         let subscriptions_clone = store.subscriptions.clone();
+        let http_auth_cache = store.http_auth_cache.clone();
         std::thread::spawn(move || {
             let sub_status =
                 sub_connection.psubscribe::<_, _, Vec<String>>(&["*"], move |msg| {
@@ -281,6 +345,13 @@ impl RedisStoreBuilder {
                         } else {
                             error!("Invalid Uuid in channel name: {}", channel_name);
                         }
+                    } else if channel_name.starts_with(HTTP_AUTH_INVALIDATIONS_PREFIX) {
+                        if let Ok(account_id) = Uuid::from_str(&channel_name[HTTP_AUTH_INVALIDATIONS_PREFIX.len()..]) {
+                            trace!("Dropping cached HTTP auth of account {}", account_id);
+                            http_auth_cache.write().retain(|_, (account, _)| account.id != account_id);
+                        } else {
+                            error!("Invalid Uuid in channel name: {}", channel_name);
+                        }
                     } else {
                         warn!("Ignoring unexpected message from Redis subscription for channel: {}", channel_name);
                     }
@@ -311,6 +382,10 @@ pub struct RedisStore {
     /// WebSocket sender which publishes incoming payment updates
     subscriptions: Arc<RwLock<HashMap<Uuid, UnboundedSender<PaymentNotification>>>>,
     exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// Proof of where the exchange rates came from, if their provider has one
+    rate_provenance: Arc<RwLock<Option<RateProvenance>>>,
+    /// Which exchange rate provider the rates came from
+    rate_source: Arc<RwLock<Option<RateSource>>>,
     /// The store keeps the routing table in memory so that it can be returned
     /// synchronously while the Router is processing packets.
     /// The outer `Arc<RwLock>` is used so that we can update the stored routing
@@ -318,13 +393,82 @@ pub struct RedisStore {
     /// The inner `Arc<HashMap>` is used so that the `routing_table` method can
     /// return a reference to the routing table without cloning the underlying data.
     routes: Arc<RwLock<Arc<HashMap<String, Uuid>>>>,
+    /// The other accounts through which prefixes can be reached equally well, as found
+    /// by this node's route manager. Unlike the routing table, these are only kept in memory
+    /// so they are not shared with other nodes using the same Redis database.
+    multipath_routes: Arc<RwLock<Arc<MultipathRoutingTable>>>,
+    /// Accounts which no new packets are routed through. Like the routing table, these
+    /// are kept in memory and polled from Redis, so all nodes using the database drain them.
+    draining_accounts: Arc<RwLock<Arc<HashSet<Uuid>>>>,
+    /// Accounts which cannot authenticate, send or receive packets, kept in memory
+    /// and polled from Redis like the draining accounts
+    disabled_accounts: Arc<RwLock<Arc<HashSet<Uuid>>>>,
     /// Encryption Key so that the no cleartext data are stored
     encryption_key: Arc<Secret<EncryptionKey>>,
     /// Decryption Key to provide cleartext data to users
     decryption_key: Arc<Secret<DecryptionKey>>,
+    /// Sink which is sent an event every time a balance changes
+    balance_events: Option<BalanceEventSink>,
+    /// Accounts which authenticated with an ILP over HTTP token, by their username,
+    /// along with the time they were loaded from Redis
+    http_auth_cache: Arc<RwLock<HashMap<String, (Account, Instant)>>>,
+    /// How long the accounts are cached for (no accounts are cached if this is not set)
+    http_auth_cache_ttl: Option<Duration>,
 }
 
 impl RedisStore {
+    /// Returns whether the store is currently connected to Redis
+    pub fn connection_status(&self) -> ConnectionStatus {
+        self.connection.status()
+    }
+
+    fn is_disabled(&self, id: Uuid) -> bool {
+        self.disabled_accounts.read().contains(&id)
+    }
+
+    /// Sends an event for the balance change to the sink (if there is one)
+    async fn publish_balance_event(
+        &self,
+        account_id: Uuid,
+        old_balance: i64,
+        new_balance: i64,
+        reason: BalanceChangeReason,
+        packet_id: Option<[u8; 32]>,
+        settlement_id: Option<String>,
+    ) {
+        if let Some(ref sink) = self.balance_events {
+            sink.publish(
+                account_id,
+                old_balance,
+                new_balance,
+                reason,
+                packet_id,
+                settlement_id,
+            )
+            .await;
+        }
+    }
+
+    /// Drops the cached HTTP auth of the account from this store, and publishes the change
+    /// so that the other stores using the same database drop it too
+    async fn invalidate_http_auth(&self, account_id: Uuid) {
+        self.http_auth_cache
+            .write()
+            .retain(|_, (account, _)| account.id != account_id);
+        let channel = format!("{}{}", HTTP_AUTH_INVALIDATIONS_PREFIX, account_id);
+        let result: Result<(), RedisError> = redis_crate::cmd("PUBLISH")
+            .arg(channel)
+            .arg("")
+            .query_async(&mut self.connection.clone())
+            .await;
+        if let Err(err) = result {
+            error!(
+                "Error publishing HTTP auth invalidation to Redis: {:?}",
+                err
+            );
+        }
+    }
+
     /// Gets all the account ids from Redis
     async fn get_all_accounts_ids(&self) -> Result<Vec<Uuid>, NodeStoreError> {
         let mut connection = self.connection.clone();
@@ -339,7 +483,6 @@ impl RedisStore {
         encrypted: &AccountWithEncryptedTokens,
     ) -> Result<(), NodeStoreError> {
         let account = &encrypted.account;
-        let id = accounts_key(account.id);
         let mut connection = self.connection.clone();
         let routing_table = self.routes.clone();
         // Check that there isn't already an account with values that MUST be unique
@@ -361,47 +504,7 @@ impl RedisStore {
 
         let mut pipe = redis_crate::pipe();
         pipe.atomic();
-
-        // Add the account key to the list of accounts
-        pipe.sadd("accounts", RedisAccountId(account.id)).ignore();
-
-        // Save map for Username -> Account ID
-        pipe.hset(
-            "usernames",
-            account.username().as_ref(),
-            RedisAccountId(account.id),
-        )
-        .ignore();
-
-        // Set balance-related details
-        pipe.hset_multiple(&id, &[("balance", 0), ("prepaid_amount", 0)])
-            .ignore();
-
-        if account.should_send_routes() {
-            pipe.sadd("send_routes_to", RedisAccountId(account.id))
-                .ignore();
-        }
-
-        if account.should_receive_routes() {
-            pipe.sadd("receive_routes_from", RedisAccountId(account.id))
-                .ignore();
-        }
-
-        if account.ilp_over_btp_url.is_some() {
-            pipe.sadd("btp_outgoing", RedisAccountId(account.id))
-                .ignore();
-        }
-
-        // Add route to routing table
-        pipe.hset(
-            ROUTES_KEY,
-            account.ilp_address.as_bytes(),
-            RedisAccountId(account.id),
-        )
-        .ignore();
-
-        // Set account details
-        pipe.cmd("HMSET").arg(&id).arg(encrypted).ignore();
+        add_insert_account_commands(&mut pipe, encrypted);
 
         // The parent account settings are done via the API. We just
         // had to check for the existence of a parent
@@ -603,10 +706,28 @@ impl RedisStore {
             .ignore();
 
         pipe.del(uncredited_amount_key(id));
+        pipe.del(settlement_conversion_key(id)).ignore();
+
+        pipe.srem(DRAINING_ACCOUNTS_KEY, RedisAccountId(account.id))
+            .ignore();
+        pipe.srem(DISABLED_ACCOUNTS_KEY, RedisAccountId(account.id))
+            .ignore();
 
         let mut connection = self.connection.clone();
         pipe.query_async(&mut connection).await?;
-        update_routes(connection, self.routes.clone()).await?;
+        update_routes(connection.clone(), self.routes.clone()).await?;
+        update_account_set(
+            connection.clone(),
+            DRAINING_ACCOUNTS_KEY,
+            self.draining_accounts.clone(),
+        )
+        .await?;
+        update_account_set(
+            connection,
+            DISABLED_ACCOUNTS_KEY,
+            self.disabled_accounts.clone(),
+        )
+        .await?;
         debug!("Deleted account {}", account.id);
         Ok(encrypted)
     }
@@ -729,10 +850,74 @@ impl BalanceStore for RedisStore {
         Ok(balance + prepaid_amount)
     }
 
+    /// Reads the balances of all given accounts in one transaction
+    async fn get_balances(&self, account_ids: Vec<Uuid>) -> Result<Vec<i64>, BalanceStoreError> {
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        for account_id in account_ids {
+            pipe.hget(accounts_key(account_id), &["balance", "prepaid_amount"]);
+        }
+        let values: Vec<(i64, i64)> = pipe.query_async(&mut self.connection.clone()).await?;
+        Ok(values
+            .into_iter()
+            .map(|(balance, prepaid_amount)| balance + prepaid_amount)
+            .collect())
+    }
+
+    async fn get_last_settlement(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Option<LastSettlement>, BalanceStoreError> {
+        let (amount, timestamp): (Option<i64>, Option<String>) = self
+            .connection
+            .clone()
+            .hget(
+                accounts_key(account_id),
+                &["last_settlement_amount", "last_settlement_at"],
+            )
+            .await?;
+        Ok(amount
+            .and_then(|amount| timestamp.map(|timestamp| LastSettlement { amount, timestamp })))
+    }
+
+    /// Saves the conversion as JSON, replacing the previous one
+    async fn save_settlement_conversion(
+        &self,
+        account_id: Uuid,
+        conversion: SettlementConversion,
+    ) -> Result<(), BalanceStoreError> {
+        let conversion = serde_json::to_string(&conversion)
+            .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+        self.connection
+            .clone()
+            .set(settlement_conversion_key(account_id), conversion)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_last_settlement_conversion(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Option<SettlementConversion>, BalanceStoreError> {
+        let conversion: Option<String> = self
+            .connection
+            .clone()
+            .get(settlement_conversion_key(account_id))
+            .await?;
+        match conversion {
+            Some(conversion) => Ok(Some(
+                serde_json::from_str(&conversion)
+                    .map_err(|err| BalanceStoreError::Other(Box::new(err)))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
     async fn update_balances_for_prepare(
         &self,
         from_account_id: Uuid,
         incoming_amount: u64,
+        packet_id: Option<[u8; 32]>,
     ) -> Result<(), BalanceStoreError> {
         // Don't do anything if the amount was 0
         if incoming_amount == 0 {
@@ -749,6 +934,15 @@ impl BalanceStore for RedisStore {
             "Processed prepare with incoming amount: {}. Account {} has balance (including prepaid amount): {} ",
             incoming_amount, from_account_id, balance
         );
+        self.publish_balance_event(
+            from_account_id,
+            balance + incoming_amount as i64,
+            balance,
+            BalanceChangeReason::Prepare,
+            packet_id,
+            None,
+        )
+        .await;
         Ok(())
     }
 
@@ -756,10 +950,12 @@ impl BalanceStore for RedisStore {
         &self,
         to_account_id: Uuid,
         outgoing_amount: u64,
+        packet_id: Option<[u8; 32]>,
     ) -> Result<(i64, u64), BalanceStoreError> {
         let (balance, amount_to_settle): (i64, u64) = PROCESS_FULFILL
             .arg(RedisAccountId(to_account_id))
             .arg(outgoing_amount)
+            .arg(Utc::now().to_rfc3339())
             .invoke_async(&mut self.connection.clone())
             .await?;
 
@@ -770,6 +966,29 @@ impl BalanceStore for RedisStore {
             balance,
             amount_to_settle,
         );
+        // The script triggers the settlement right after applying the fulfill,
+        // so the returned balance already has the settlement amount deducted
+        let balance_before_settlement = balance + amount_to_settle as i64;
+        self.publish_balance_event(
+            to_account_id,
+            balance_before_settlement - outgoing_amount as i64,
+            balance_before_settlement,
+            BalanceChangeReason::Fulfill,
+            packet_id,
+            None,
+        )
+        .await;
+        if amount_to_settle > 0 {
+            self.publish_balance_event(
+                to_account_id,
+                balance_before_settlement,
+                balance,
+                BalanceChangeReason::OutgoingSettlement,
+                packet_id,
+                None,
+            )
+            .await;
+        }
         Ok((balance, amount_to_settle))
     }
 
@@ -777,6 +996,7 @@ impl BalanceStore for RedisStore {
         &self,
         from_account_id: Uuid,
         incoming_amount: u64,
+        packet_id: Option<[u8; 32]>,
     ) -> Result<(), BalanceStoreError> {
         if incoming_amount == 0 {
             return Ok(());
@@ -792,6 +1012,15 @@ impl BalanceStore for RedisStore {
             "Processed reject for incoming amount: {}. Account {} has balance (including prepaid amount): {}",
             incoming_amount, from_account_id, balance
         );
+        self.publish_balance_event(
+            from_account_id,
+            balance - incoming_amount as i64,
+            balance,
+            BalanceChangeReason::Reject,
+            packet_id,
+            None,
+        )
+        .await;
 
         Ok(())
     }
@@ -826,6 +1055,27 @@ impl ExchangeRateStore for RedisStore {
         (*self.exchange_rates.write()) = rates;
         Ok(())
     }
+
+    fn set_rate_provenance(
+        &self,
+        provenance: Option<RateProvenance>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        (*self.rate_provenance.write()) = provenance;
+        Ok(())
+    }
+
+    fn get_rate_provenance(&self) -> Result<Option<RateProvenance>, ExchangeRateStoreError> {
+        Ok(self.rate_provenance.read().clone())
+    }
+
+    fn set_rate_source(&self, source: Option<RateSource>) -> Result<(), ExchangeRateStoreError> {
+        (*self.rate_source.write()) = source;
+        Ok(())
+    }
+
+    fn get_rate_source(&self) -> Result<Option<RateSource>, ExchangeRateStoreError> {
+        Ok(self.rate_source.read().clone())
+    }
 }
 
 #[async_trait]
@@ -846,22 +1096,22 @@ impl BtpStore for RedisStore {
             .await?;
 
         if let Some(account) = account {
-            let account = account.decrypt_tokens(&self.decryption_key.expose_secret().0);
-            if let Some(ref t) = account.ilp_over_btp_incoming_token {
-                let t = t.expose_secret();
-                if t.as_ref() == token.as_bytes() {
-                    Ok(account)
-                } else {
-                    debug!(
-                        "Found account {} but BTP auth token was wrong",
-                        account.username
-                    );
-                    Err(BtpStoreError::Unauthorized(username.to_string()))
-                }
-            } else {
+            let decryption_key = &self.decryption_key.expose_secret().0;
+            if self.is_disabled(account.account.id) {
+                debug!("Account {} is disabled", account.account.username);
+                Err(BtpStoreError::Unauthorized(username.to_string()))
+            } else if account.account.ilp_over_btp_incoming_token.is_none() {
                 debug!(
                     "Account {} does not have an incoming btp token configured",
-                    account.username
+                    account.account.username
+                );
+                Err(BtpStoreError::Unauthorized(username.to_string()))
+            } else if account.verify_btp_incoming_token(decryption_key, token.as_bytes()) {
+                Ok(account.decrypt_tokens(decryption_key))
+            } else {
+                debug!(
+                    "Found account {} but BTP auth token was wrong",
+                    account.account.username
                 );
                 Err(BtpStoreError::Unauthorized(username.to_string()))
             }
@@ -896,6 +1146,19 @@ impl HttpStore for RedisStore {
         username: &Username,
         token: &str,
     ) -> Result<Self::Account, HttpStoreError> {
+        if let Some(ttl) = self.http_auth_cache_ttl {
+            if let Some((account, loaded_at)) = self.http_auth_cache.read().get(username.as_ref()) {
+                let token_matches = account
+                    .ilp_over_http_incoming_token
+                    .as_ref()
+                    .map(|t| t.expose_secret().as_ref() == token.as_bytes())
+                    .unwrap_or(false);
+                if token_matches && loaded_at.elapsed() < ttl && !self.is_disabled(account.id) {
+                    return Ok(account.clone());
+                }
+            }
+        }
+
         // TODO make sure it can't do script injection!
         let account: Option<AccountWithEncryptedTokens> = ACCOUNT_FROM_USERNAME
             .arg(username.as_ref())
@@ -904,9 +1167,18 @@ impl HttpStore for RedisStore {
 
         if let Some(account) = account {
             let account = account.decrypt_tokens(&self.decryption_key.expose_secret().0);
+            if self.is_disabled(account.id) {
+                debug!("Account {} is disabled", account.username);
+                return Err(HttpStoreError::Unauthorized(username.to_string()));
+            }
             if let Some(ref t) = account.ilp_over_http_incoming_token {
                 let t = t.expose_secret();
                 if t.as_ref() == token.as_bytes() {
+                    if self.http_auth_cache_ttl.is_some() {
+                        self.http_auth_cache
+                            .write()
+                            .insert(username.to_string(), (account.clone(), Instant::now()));
+                    }
                     Ok(account)
                 } else {
                     Err(HttpStoreError::Unauthorized(username.to_string()))
@@ -919,12 +1191,111 @@ impl HttpStore for RedisStore {
             Err(HttpStoreError::AccountNotFound(username.to_string()))
         }
     }
+
+    async fn get_account_from_client_certificate(
+        &self,
+        username: &Username,
+        fingerprint: &str,
+    ) -> Result<Option<Self::Account>, HttpStoreError> {
+        let account: Option<AccountWithEncryptedTokens> = ACCOUNT_FROM_USERNAME
+            .arg(username.as_ref())
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+
+        if let Some(account) = account {
+            let account = account.decrypt_tokens(&self.decryption_key.expose_secret().0);
+            if self.is_disabled(account.id) {
+                debug!("Account {} is disabled", account.username);
+                Err(HttpStoreError::Unauthorized(username.to_string()))
+            } else if account.ilp_over_http_client_certificates.is_empty() {
+                Ok(None)
+            } else if account
+                .ilp_over_http_client_certificates
+                .iter()
+                .any(|accepted| accepted == fingerprint)
+            {
+                Ok(Some(account))
+            } else {
+                Err(HttpStoreError::Unauthorized(username.to_string()))
+            }
+        } else {
+            warn!("No account found with given client certificate");
+            Err(HttpStoreError::AccountNotFound(username.to_string()))
+        }
+    }
 }
 
 impl RouterStore for RedisStore {
     fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
         self.routes.read().clone()
     }
+
+    fn multipath_routing_table(&self) -> Arc<HashMap<String, Vec<Uuid>>> {
+        self.multipath_routes.read().clone()
+    }
+
+    fn draining_accounts(&self) -> Arc<HashSet<Uuid>> {
+        self.draining_accounts.read().clone()
+    }
+
+    fn disabled_accounts(&self) -> Arc<HashSet<Uuid>> {
+        self.disabled_accounts.read().clone()
+    }
+}
+
+/// Adds the commands inserting the account to the pipeline, without checking whether
+/// an account with the same id, username or parent relation already exists
+fn add_insert_account_commands(
+    pipe: &mut redis_crate::Pipeline,
+    encrypted: &AccountWithEncryptedTokens,
+) {
+    let account = &encrypted.account;
+    // Add the account key to the list of accounts
+    pipe.sadd("accounts", RedisAccountId(account.id)).ignore();
+
+    // Save map for Username -> Account ID
+    pipe.hset(
+        "usernames",
+        account.username().as_ref(),
+        RedisAccountId(account.id),
+    )
+    .ignore();
+
+    // Set balance-related details
+    pipe.hset_multiple(
+        accounts_key(account.id),
+        &[("balance", 0), ("prepaid_amount", 0)],
+    )
+    .ignore();
+
+    if account.should_send_routes() {
+        pipe.sadd("send_routes_to", RedisAccountId(account.id))
+            .ignore();
+    }
+
+    if account.should_receive_routes() {
+        pipe.sadd("receive_routes_from", RedisAccountId(account.id))
+            .ignore();
+    }
+
+    if account.ilp_over_btp_url.is_some() {
+        pipe.sadd("btp_outgoing", RedisAccountId(account.id))
+            .ignore();
+    }
+
+    // Add route to routing table
+    pipe.hset(
+        ROUTES_KEY,
+        account.ilp_address.as_bytes(),
+        RedisAccountId(account.id),
+    )
+    .ignore();
+
+    // Set account details
+    pipe.cmd("HMSET")
+        .arg(accounts_key(account.id))
+        .arg(encrypted)
+        .ignore();
 }
 
 #[async_trait]
@@ -950,8 +1321,87 @@ impl NodeStore for RedisStore {
         Ok(account)
     }
 
+    async fn insert_accounts(
+        &self,
+        accounts: Vec<AccountDetails>,
+    ) -> Result<Vec<Result<Self::Account, NodeStoreError>>, NodeStoreError> {
+        let mut connection = self.connection.clone();
+        let node_ilp_address = self.get_ilp_address();
+
+        // Accounts with the same username (or parent relation) as an earlier one in the
+        // batch are rejected like the ones conflicting with existing accounts
+        let mut usernames = HashSet::new();
+        let mut has_parent = false;
+        let mut results: Vec<Result<Account, NodeStoreError>> = accounts
+            .into_iter()
+            .map(|details| {
+                let account = Account::try_from(Uuid::new_v4(), details, node_ilp_address.clone())
+                    .map_err(NodeStoreError::InvalidAccount)?;
+                let is_parent = account.routing_relation == RoutingRelation::Parent;
+                if !usernames.insert(account.username.to_string()) || (is_parent && has_parent) {
+                    return Err(NodeStoreError::AccountExists(account.username.to_string()));
+                }
+                has_parent |= is_parent;
+                Ok(account)
+            })
+            .collect();
+
+        // Check all accounts for conflicts with the existing ones in a single round trip
+        let mut pipe = redis_crate::pipe();
+        for account in results.iter().filter_map(|result| result.as_ref().ok()) {
+            pipe.hexists("usernames", account.username().as_ref());
+            pipe.exists(PARENT_ILP_KEY);
+        }
+        let exists: Vec<bool> = if results.iter().any(Result::is_ok) {
+            pipe.query_async(&mut connection).await?
+        } else {
+            Vec::new()
+        };
+        let mut exists = exists.chunks(2);
+        for result in results.iter_mut() {
+            let conflicts = match result {
+                Ok(account) => match exists.next() {
+                    Some(&[username_exists, parent_exists]) => {
+                        username_exists
+                            || (parent_exists
+                                && account.routing_relation == RoutingRelation::Parent)
+                    }
+                    _ => false,
+                },
+                Err(_) => false,
+            };
+            if conflicts {
+                let username = result.as_ref().unwrap().username.to_string();
+                warn!(
+                    "An account already exists with the same username or relation. Cannot insert account {}",
+                    username
+                );
+                *result = Err(NodeStoreError::AccountExists(username));
+            }
+        }
+
+        // Insert the remaining accounts atomically in a single round trip
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        let mut inserted = 0;
+        for account in results.iter().filter_map(|result| result.as_ref().ok()) {
+            let encrypted = account
+                .clone()
+                .encrypt_tokens(&self.encryption_key.expose_secret().0);
+            add_insert_account_commands(&mut pipe, &encrypted);
+            inserted += 1;
+        }
+        if inserted > 0 {
+            pipe.query_async(&mut connection).await?;
+            update_routes(connection, self.routes.clone()).await?;
+        }
+        debug!("Inserted {} of {} accounts", inserted, results.len());
+        Ok(results)
+    }
+
     async fn delete_account(&self, id: Uuid) -> Result<Account, NodeStoreError> {
         let account = self.redis_delete_account(id).await?;
+        self.invalidate_http_auth(id).await;
         Ok(account.decrypt_tokens(&self.decryption_key.expose_secret().0))
     }
 
@@ -972,6 +1422,7 @@ impl NodeStore for RedisStore {
             .encrypt_tokens(&self.encryption_key.expose_secret().0);
 
         self.redis_update_account(&encrypted).await?;
+        self.invalidate_http_auth(id).await;
         Ok(account)
     }
 
@@ -985,13 +1436,9 @@ impl NodeStore for RedisStore {
             settle_threshold: settings.settle_threshold,
             ilp_over_btp_url: settings.ilp_over_btp_url,
             ilp_over_http_url: settings.ilp_over_http_url,
-            ilp_over_btp_incoming_token: settings.ilp_over_btp_incoming_token.map(|token| {
-                encrypt_token(
-                    &self.encryption_key.expose_secret().0,
-                    token.expose_secret().as_bytes(),
-                )
-                .freeze()
-            }),
+            ilp_over_btp_incoming_token: settings
+                .ilp_over_btp_incoming_token
+                .map(|token| hash_token(token.expose_secret().as_bytes()).freeze()),
             ilp_over_http_incoming_token: settings.ilp_over_http_incoming_token.map(|token| {
                 encrypt_token(
                     &self.encryption_key.expose_secret().0,
@@ -1016,6 +1463,7 @@ impl NodeStore for RedisStore {
         };
 
         let account = self.redis_modify_account(id, settings).await?;
+        self.invalidate_http_auth(id).await;
         Ok(account.decrypt_tokens(&self.decryption_key.expose_secret().0))
     }
 
@@ -1107,6 +1555,15 @@ impl NodeStore for RedisStore {
         Ok(())
     }
 
+    async fn get_static_routes(&self) -> Result<HashMap<String, Uuid>, NodeStoreError> {
+        let static_routes: Vec<(String, RedisAccountId)> =
+            self.connection.clone().hgetall(STATIC_ROUTES_KEY).await?;
+        Ok(static_routes
+            .into_iter()
+            .map(|(prefix, id)| (prefix, id.0))
+            .collect())
+    }
+
     async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError> {
         let routing_table = self.routes.clone();
         // TODO replace this with a lua script to do both calls at once
@@ -1128,6 +1585,68 @@ impl NodeStore for RedisStore {
         Ok(())
     }
 
+    async fn set_account_draining(&self, id: Uuid, draining: bool) -> Result<(), NodeStoreError> {
+        let mut connection = self.connection.clone();
+        let exists: bool = connection.exists(accounts_key(id)).await?;
+        if !exists {
+            error!(
+                "Cannot change draining of account {} because it does not exist",
+                id
+            );
+            return Err(NodeStoreError::AccountNotFound(id.to_string()));
+        }
+
+        if draining {
+            connection
+                .sadd(DRAINING_ACCOUNTS_KEY, RedisAccountId(id))
+                .await?;
+            debug!("Draining account {}", id);
+        } else {
+            connection
+                .srem(DRAINING_ACCOUNTS_KEY, RedisAccountId(id))
+                .await?;
+            debug!("Stopped draining account {}", id);
+        }
+        update_account_set(
+            connection,
+            DRAINING_ACCOUNTS_KEY,
+            self.draining_accounts.clone(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn set_account_enabled(&self, id: Uuid, enabled: bool) -> Result<(), NodeStoreError> {
+        let mut connection = self.connection.clone();
+        let exists: bool = connection.exists(accounts_key(id)).await?;
+        if !exists {
+            error!(
+                "Cannot enable or disable account {} because it does not exist",
+                id
+            );
+            return Err(NodeStoreError::AccountNotFound(id.to_string()));
+        }
+
+        if enabled {
+            connection
+                .srem(DISABLED_ACCOUNTS_KEY, RedisAccountId(id))
+                .await?;
+            debug!("Enabled account {}", id);
+        } else {
+            connection
+                .sadd(DISABLED_ACCOUNTS_KEY, RedisAccountId(id))
+                .await?;
+            debug!("Disabled account {}", id);
+        }
+        update_account_set(
+            connection,
+            DISABLED_ACCOUNTS_KEY,
+            self.disabled_accounts.clone(),
+        )
+        .await?;
+        Ok(())
+    }
+
     async fn set_settlement_engines(
         &self,
         asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
@@ -1168,6 +1687,148 @@ impl NodeStore for RedisStore {
             Ok(None)
         }
     }
+
+    async fn check_health(&self) -> Result<(), NodeStoreError> {
+        cmd("PING")
+            .query_async::<_, String>(&mut self.connection.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn export_node(&self) -> Result<NodeExport, NodeStoreError> {
+        let mut connection = self.connection.clone();
+        let account_ids = self.get_all_accounts_ids().await?;
+
+        let mut accounts = Vec::with_capacity(account_ids.len());
+        if !account_ids.is_empty() {
+            let mut pipe = redis_crate::pipe();
+            pipe.atomic();
+            for id in account_ids.iter() {
+                pipe.hgetall(accounts_key(*id));
+            }
+            let hashes: Vec<Value> = pipe.query_async(&mut connection).await?;
+            for hash in hashes.iter() {
+                // The tokens are exported encrypted, as they are stored
+                let account = AccountWithEncryptedTokens::from_redis_value(hash)?.account;
+                let hash: HashMap<String, Value> = HashMap::from_redis_value(hash)?;
+                let balance: Option<i64> = get_value_option("balance", &hash)?;
+                let prepaid_amount: Option<i64> = get_value_option("prepaid_amount", &hash)?;
+                accounts.push(account.export(
+                    balance.unwrap_or_default(),
+                    prepaid_amount.unwrap_or_default(),
+                ));
+            }
+        }
+
+        let static_routes = self.get_static_routes().await?;
+        let default_route: Option<RedisAccountId> = connection.get(DEFAULT_ROUTE_KEY).await?;
+        Ok(NodeExport {
+            accounts,
+            static_routes,
+            default_route: default_route.map(|id| id.0),
+        })
+    }
+
+    async fn import_node(&self, export: NodeExport) -> Result<Vec<Self::Account>, NodeStoreError> {
+        let mut connection = self.connection.clone();
+        let node_ilp_address = self.get_ilp_address();
+        let decryption_key = &self.decryption_key.expose_secret().0;
+
+        let mut accounts = Vec::with_capacity(export.accounts.len());
+        let mut ids = HashSet::new();
+        let mut usernames = HashSet::new();
+        let mut has_parent = false;
+        for exported in export.accounts {
+            let balances = (exported.balance, exported.prepaid_amount);
+            let account = Account::from_export(exported, node_ilp_address.clone())
+                .map_err(NodeStoreError::InvalidAccount)?;
+            let encrypted = AccountWithEncryptedTokens { account };
+            if let Some(secret) = encrypted.undecryptable_secret(decryption_key) {
+                return Err(NodeStoreError::InvalidAccount(
+                    CreateAccountError::InvalidExportedSecret(secret.to_owned()),
+                ));
+            }
+
+            let account = &encrypted.account;
+            let is_parent = account.routing_relation == RoutingRelation::Parent;
+            if !ids.insert(account.id)
+                || !usernames.insert(account.username.to_string())
+                || (is_parent && has_parent)
+            {
+                warn!(
+                    "The export contains multiple accounts with the same {}. Cannot import account: {:?}",
+                    account.id, account
+                );
+                return Err(NodeStoreError::AccountExists(account.username.to_string()));
+            }
+            has_parent |= is_parent;
+            accounts.push((encrypted, balances));
+        }
+        if !export
+            .static_routes
+            .values()
+            .chain(export.default_route.iter())
+            .all(|id| ids.contains(id))
+        {
+            error!("Error importing routes because not all of their accounts are imported");
+            return Err(NodeStoreError::MissingAccounts);
+        }
+
+        // Check all accounts before inserting any of them, so that nothing is imported
+        // if one of them already exists
+        for (encrypted, _) in accounts.iter() {
+            let account = &encrypted.account;
+            let mut pipe = redis_crate::pipe();
+            pipe.exists(accounts_key(account.id));
+            pipe.hexists("usernames", account.username().as_ref());
+            if account.routing_relation == RoutingRelation::Parent {
+                pipe.exists(PARENT_ILP_KEY);
+            }
+            let results: Vec<bool> = pipe.query_async(&mut connection).await?;
+            if results.iter().any(|val| *val) {
+                warn!(
+                    "An account already exists with the same {}. Cannot import account: {:?}",
+                    account.id, account
+                );
+                return Err(NodeStoreError::AccountExists(account.username.to_string()));
+            }
+        }
+
+        for (encrypted, _) in accounts.iter() {
+            self.redis_insert_account(encrypted).await?;
+        }
+
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        for (encrypted, (balance, prepaid_amount)) in accounts.iter() {
+            pipe.hset_multiple(
+                accounts_key(encrypted.account.id),
+                &[("balance", *balance), ("prepaid_amount", *prepaid_amount)],
+            )
+            .ignore();
+        }
+        let static_routes: Vec<(String, RedisAccountId)> = export
+            .static_routes
+            .into_iter()
+            .map(|(prefix, id)| (prefix, RedisAccountId(id)))
+            .collect();
+        if !static_routes.is_empty() {
+            pipe.hset_multiple(STATIC_ROUTES_KEY, &static_routes)
+                .ignore();
+        }
+        if let Some(default_route) = export.default_route {
+            pipe.set(DEFAULT_ROUTE_KEY, RedisAccountId(default_route))
+                .ignore();
+        }
+        pipe.query_async(&mut connection).await?;
+
+        update_routes(connection, self.routes.clone()).await?;
+        debug!("Imported {} accounts", accounts.len());
+        Ok(accounts
+            .into_iter()
+            .map(|(encrypted, _)| encrypted.decrypt_tokens(decryption_key))
+            .collect())
+    }
 }
 
 #[async_trait]
@@ -1261,6 +1922,7 @@ impl AddressStore for RedisStore {
 }
 
 type RoutingTable<A> = HashMap<String, A>;
+type MultipathRoutingTable = HashMap<String, Vec<Uuid>>;
 
 #[async_trait]
 impl CcpRoutingStore for RedisStore {
@@ -1363,6 +2025,14 @@ impl CcpRoutingStore for RedisStore {
         update_routes(connection, self.routes.clone()).await?;
         Ok(())
     }
+
+    async fn set_multipath_routes(
+        &mut self,
+        routes: HashMap<String, Vec<Uuid>>,
+    ) -> Result<(), CcpRoutingStoreError> {
+        *self.multipath_routes.write() = Arc::new(routes);
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -1457,6 +2127,135 @@ impl RateLimitStore for RedisStore {
     }
 }
 
+/// Returns the key of the counter of the volume sent to the account in the current window
+fn volume_key(account_id: Uuid, window: Duration) -> String {
+    let window = window.as_secs().max(1);
+    format!(
+        "volume:{}:{}",
+        account_id,
+        Utc::now().timestamp() as u64 / window
+    )
+}
+
+#[async_trait]
+impl VolumeStore for RedisStore {
+    async fn get_outgoing_volume(&self, account_id: Uuid, window: Duration) -> Result<u64, ()> {
+        let volume: Option<u64> = self
+            .connection
+            .clone()
+            .get(volume_key(account_id, window))
+            .map_err(|err| error!("Error loading outgoing volume: {:?}", err))
+            .await?;
+        Ok(volume.unwrap_or(0))
+    }
+
+    /// Adds to the counter of the current window, which expires once the window rolled
+    async fn add_outgoing_volume(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        window: Duration,
+    ) -> Result<u64, ()> {
+        let key = volume_key(account_id, window);
+        let (volume,): (u64,) = redis_crate::pipe()
+            .atomic()
+            .cmd("INCRBY")
+            .arg(&key)
+            .arg(amount)
+            .cmd("EXPIRE")
+            .arg(&key)
+            .arg(window.as_secs().max(1))
+            .ignore()
+            .query_async(&mut self.connection.clone())
+            .map_err(|err| error!("Error adding to outgoing volume: {:?}", err))
+            .await?;
+        Ok(volume)
+    }
+}
+
+/// Returns the key of the hash with the traffic counters of the account
+fn traffic_key(account_id: Uuid) -> String {
+    format!("traffic:{}", account_id)
+}
+
+#[async_trait]
+impl TrafficStore for RedisStore {
+    async fn get_traffic(&self, account_id: Uuid) -> Result<TrafficCounters, ()> {
+        let counters: HashMap<String, u64> = self
+            .connection
+            .clone()
+            .hgetall(traffic_key(account_id))
+            .map_err(|err| error!("Error loading traffic counters: {:?}", err))
+            .await?;
+        let counter = |name: &str| counters.get(name).cloned().unwrap_or(0);
+        Ok(TrafficCounters {
+            packets_in: counter("packets_in"),
+            packets_out: counter("packets_out"),
+            bytes_in: counter("bytes_in"),
+            bytes_out: counter("bytes_out"),
+            amount_in: counter("amount_in"),
+            amount_out: counter("amount_out"),
+        })
+    }
+
+    /// Increments the counters of the account's hash, which is kept when the account is
+    /// deleted so that its traffic can still be billed
+    async fn add_traffic(&self, account_id: Uuid, traffic: TrafficCounters) -> Result<(), ()> {
+        let key = traffic_key(account_id);
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        for (field, value) in &[
+            ("packets_in", traffic.packets_in),
+            ("packets_out", traffic.packets_out),
+            ("bytes_in", traffic.bytes_in),
+            ("bytes_out", traffic.bytes_out),
+            ("amount_in", traffic.amount_in),
+            ("amount_out", traffic.amount_out),
+        ] {
+            if *value > 0 {
+                pipe.hincr(&key, *field, *value).ignore();
+            }
+        }
+        pipe.query_async(&mut self.connection.clone())
+            .map_err(|err| error!("Error updating traffic counters: {:?}", err))
+            .await
+    }
+}
+
+/// Returns the key which marks the execution condition as fulfilled
+fn fulfilled_condition_key(condition: [u8; 32]) -> Vec<u8> {
+    let mut key = b"fulfilled:".to_vec();
+    key.extend_from_slice(&condition);
+    key
+}
+
+#[async_trait]
+impl FulfilledConditionStore for RedisStore {
+    async fn was_condition_fulfilled(&self, condition: [u8; 32]) -> Result<bool, ()> {
+        self.connection
+            .clone()
+            .exists(fulfilled_condition_key(condition))
+            .map_err(|err| error!("Error checking fulfilled execution condition: {:?}", err))
+            .await
+    }
+
+    /// Sets a key which expires once the window passed
+    async fn save_fulfilled_condition(
+        &self,
+        condition: [u8; 32],
+        window: Duration,
+    ) -> Result<(), ()> {
+        redis_crate::cmd("SET")
+            .arg(fulfilled_condition_key(condition))
+            .arg(1)
+            .arg("PX")
+            .arg((window.as_millis() as u64).max(1))
+            .query_async(&mut self.connection.clone())
+            .map_err(|err| error!("Error saving fulfilled execution condition: {:?}", err))
+            .await
+    }
+}
+
 #[async_trait]
 impl IdempotentStore for RedisStore {
     async fn load_idempotent_data(
@@ -1530,10 +2329,11 @@ impl SettlementStore for RedisStore {
         idempotency_key: Option<String>,
     ) -> Result<(), SettlementStoreError> {
         let idempotency_key = idempotency_key.unwrap();
-        let balance: i64 = PROCESS_INCOMING_SETTLEMENT
+        let (balance, credited): (i64, bool) = PROCESS_INCOMING_SETTLEMENT
             .arg(RedisAccountId(account_id))
             .arg(amount)
-            .arg(idempotency_key)
+            .arg(&idempotency_key)
+            .arg(Utc::now().to_rfc3339())
             .invoke_async(&mut self.connection.clone())
             .await?;
         trace!(
@@ -1542,6 +2342,17 @@ impl SettlementStore for RedisStore {
             amount,
             balance
         );
+        if credited {
+            self.publish_balance_event(
+                account_id,
+                balance - amount as i64,
+                balance,
+                BalanceChangeReason::IncomingSettlement,
+                None,
+                Some(idempotency_key),
+            )
+            .await;
+        }
         Ok(())
     }
 
@@ -1567,6 +2378,15 @@ impl SettlementStore for RedisStore {
             settle_amount,
             balance
         );
+        self.publish_balance_event(
+            account_id,
+            balance - settle_amount as i64,
+            balance,
+            BalanceChangeReason::SettlementRefund,
+            None,
+            None,
+        )
+        .await;
         Ok(())
     }
 }
@@ -1795,6 +2615,18 @@ async fn update_routes(
     Ok(())
 }
 
+/// Replaces the accounts in memory with the ones in the given Redis set
+async fn update_account_set(
+    mut connection: RedisReconnect,
+    key: &str,
+    accounts: Arc<RwLock<Arc<HashSet<Uuid>>>>,
+) -> Result<(), RedisError> {
+    let ids: Vec<RedisAccountId> = connection.smembers(key).await?;
+    trace!("Loaded {} from redis: {:?}", key, ids);
+    *accounts.write() = Arc::new(ids.into_iter().map(|id| id.0).collect());
+    Ok(())
+}
+
 // Uuid does not implement ToRedisArgs and FromRedisValue.
 // Rust does not allow implementing foreign traits on foreign data types.
 // As a result, we wrap Uuid in a local data type, and implement the necessary
@@ -1857,6 +2689,10 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
         account.asset_scale.write_redis_args(&mut rv);
         "max_packet_amount".write_redis_args(&mut rv);
         account.max_packet_amount.write_redis_args(&mut rv);
+        if let Some(min_packet_amount) = account.min_packet_amount {
+            "min_packet_amount".write_redis_args(&mut rv);
+            min_packet_amount.write_redis_args(&mut rv);
+        }
         "routing_relation".write_redis_args(&mut rv);
         account
             .routing_relation
@@ -1884,6 +2720,22 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
                 .as_ref()
                 .write_redis_args(&mut rv);
         }
+        if !account.ilp_over_http_client_certificates.is_empty() {
+            // Fingerprints are hex-encoded so they never contain a comma
+            "ilp_over_http_client_certificates".write_redis_args(&mut rv);
+            account
+                .ilp_over_http_client_certificates
+                .join(",")
+                .write_redis_args(&mut rv);
+        }
+        if let Some(header) = account.ilp_over_http_signature_header.as_ref() {
+            "ilp_over_http_signature_header".write_redis_args(&mut rv);
+            header.write_redis_args(&mut rv);
+        }
+        if let Some(secret) = account.ilp_over_http_signature_secret.as_ref() {
+            "ilp_over_http_signature_secret".write_redis_args(&mut rv);
+            secret.expose_secret().as_ref().write_redis_args(&mut rv);
+        }
         if let Some(ilp_over_btp_url) = account.ilp_over_btp_url.as_ref() {
             "ilp_over_btp_url".write_redis_args(&mut rv);
             ilp_over_btp_url.as_str().write_redis_args(&mut rv);
@@ -1918,6 +2770,57 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
             "amount_per_minute_limit".write_redis_args(&mut rv);
             limit.write_redis_args(&mut rv);
         }
+        if let Some(spread) = account.spread {
+            "spread".write_redis_args(&mut rv);
+            spread.write_redis_args(&mut rv);
+        }
+        if let Some(rounding) = account.rounding {
+            "rounding".write_redis_args(&mut rv);
+            rounding.to_string().write_redis_args(&mut rv);
+        }
+        if let Some(routing_weight) = account.routing_weight {
+            "routing_weight".write_redis_args(&mut rv);
+            routing_weight.write_redis_args(&mut rv);
+        }
+        if let Some(route_priority) = account.route_priority {
+            "route_priority".write_redis_args(&mut rv);
+            route_priority.write_redis_args(&mut rv);
+        }
+        if let Some(volume_alert_threshold) = account.volume_alert_threshold {
+            "volume_alert_threshold".write_redis_args(&mut rv);
+            volume_alert_threshold.write_redis_args(&mut rv);
+        }
+        if let Some(spsp_display_symbol) = &account.spsp_display_symbol {
+            "spsp_display_symbol".write_redis_args(&mut rv);
+            spsp_display_symbol.write_redis_args(&mut rv);
+        }
+        if let Some(spsp_display_decimals) = account.spsp_display_decimals {
+            "spsp_display_decimals".write_redis_args(&mut rv);
+            spsp_display_decimals.write_redis_args(&mut rv);
+        }
+        if let Some(allowed_source_prefix) = &account.allowed_source_prefix {
+            "allowed_source_prefix".write_redis_args(&mut rv);
+            rv.push(allowed_source_prefix.to_bytes().to_vec());
+        }
+        if !account.allowed_destination_prefixes.is_empty() {
+            // ILP addresses never contain a comma
+            "allowed_destination_prefixes".write_redis_args(&mut rv);
+            account
+                .allowed_destination_prefixes
+                .iter()
+                .map(|prefix| prefix.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+                .write_redis_args(&mut rv);
+        }
+        if let Some(max_concurrent_outgoing_requests) = account.max_concurrent_outgoing_requests {
+            "max_concurrent_outgoing_requests".write_redis_args(&mut rv);
+            max_concurrent_outgoing_requests.write_redis_args(&mut rv);
+        }
+        if let Some(shadow_account) = &account.shadow_account {
+            "shadow_account".write_redis_args(&mut rv);
+            shadow_account.as_ref().write_redis_args(&mut rv);
+        }
         if let Some(min_balance) = account.min_balance {
             "min_balance".write_redis_args(&mut rv);
             min_balance.write_redis_args(&mut rv);
@@ -1926,6 +2829,14 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
             "settlement_engine_url".write_redis_args(&mut rv);
             settlement_engine_url.as_str().write_redis_args(&mut rv);
         }
+        if let Some(settlement_asset_code) = &account.settlement_asset_code {
+            "settlement_asset_code".write_redis_args(&mut rv);
+            settlement_asset_code.write_redis_args(&mut rv);
+        }
+        if let Some(settlement_asset_scale) = account.settlement_asset_scale {
+            "settlement_asset_scale".write_redis_args(&mut rv);
+            settlement_asset_scale.write_redis_args(&mut rv);
+        }
 
         debug_assert!(rv.len() <= ACCOUNT_DETAILS_FIELDS * 2);
         debug_assert!((rv.len() % 2) == 0);
@@ -1950,6 +2861,23 @@ impl FromRedisValue for AccountWithEncryptedTokens {
         } else {
             RoutingRelation::NonRoutingAccount
         };
+        let rounding: Option<String> = get_value_option("rounding", &hash)?;
+        let rounding =
+            if let Some(rounding) = rounding {
+                Some(RoundingPolicy::from_str(rounding.as_str()).map_err(|_| {
+                    RedisError::from((ErrorKind::TypeError, "Invalid rounding policy"))
+                })?)
+            } else {
+                None
+            };
+        let shadow_account: Option<String> = get_value_option("shadow_account", &hash)?;
+        let shadow_account = if let Some(shadow_account) = shadow_account {
+            Some(Username::from_str(&shadow_account).map_err(|_| {
+                RedisError::from((ErrorKind::TypeError, "Invalid shadow account username"))
+            })?)
+        } else {
+            None
+        };
         let round_trip_time: Option<u32> = get_value_option("round_trip_time", &hash)?;
         let round_trip_time: u32 = round_trip_time.unwrap_or(DEFAULT_ROUND_TRIP_TIME);
 
@@ -1973,6 +2901,21 @@ impl FromRedisValue for AccountWithEncryptedTokens {
                     &hash,
                 )?
                 .map(SecretBytesMut::from),
+                ilp_over_http_client_certificates: get_value_option::<String>(
+                    "ilp_over_http_client_certificates",
+                    &hash,
+                )?
+                .map(|fingerprints| fingerprints.split(',').map(String::from).collect())
+                .unwrap_or_default(),
+                ilp_over_http_signature_header: get_value_option(
+                    "ilp_over_http_signature_header",
+                    &hash,
+                )?,
+                ilp_over_http_signature_secret: get_bytes_option(
+                    "ilp_over_http_signature_secret",
+                    &hash,
+                )?
+                .map(SecretBytesMut::from),
                 ilp_over_btp_url: get_url_option("ilp_over_btp_url", &hash)?,
                 ilp_over_btp_incoming_token: get_bytes_option(
                     "ilp_over_btp_incoming_token",
@@ -1984,7 +2927,10 @@ impl FromRedisValue for AccountWithEncryptedTokens {
                     &hash,
                 )?
                 .map(SecretBytesMut::from),
-                max_packet_amount: get_value("max_packet_amount", &hash)?,
+                // Accounts without a configured limit forward packets of any size
+                max_packet_amount: get_value_option("max_packet_amount", &hash)?
+                    .unwrap_or_else(u64::max_value),
+                min_packet_amount: get_value_option("min_packet_amount", &hash)?,
                 min_balance: get_value_option("min_balance", &hash)?,
                 settle_threshold: get_value_option("settle_threshold", &hash)?,
                 settle_to: get_value_option("settle_to", &hash)?,
@@ -1992,7 +2938,26 @@ impl FromRedisValue for AccountWithEncryptedTokens {
                 round_trip_time,
                 packets_per_minute_limit: get_value_option("packets_per_minute_limit", &hash)?,
                 amount_per_minute_limit: get_value_option("amount_per_minute_limit", &hash)?,
+                spread: get_value_option("spread", &hash)?,
+                rounding,
+                routing_weight: get_value_option("routing_weight", &hash)?,
+                route_priority: get_value_option("route_priority", &hash)?,
+                volume_alert_threshold: get_value_option("volume_alert_threshold", &hash)?,
+                spsp_display_symbol: get_value_option("spsp_display_symbol", &hash)?,
+                spsp_display_decimals: get_value_option("spsp_display_decimals", &hash)?,
+                allowed_source_prefix: get_address_option("allowed_source_prefix", &hash)?,
+                allowed_destination_prefixes: get_address_list(
+                    "allowed_destination_prefixes",
+                    &hash,
+                )?,
+                max_concurrent_outgoing_requests: get_value_option(
+                    "max_concurrent_outgoing_requests",
+                    &hash,
+                )?,
+                shadow_account,
                 settlement_engine_url: get_url_option("settlement_engine_url", &hash)?,
+                settlement_asset_code: get_value_option("settlement_asset_code", &hash)?,
+                settlement_asset_scale: get_value_option("settlement_asset_scale", &hash)?,
             },
         })
     }
@@ -2049,6 +3014,38 @@ fn get_url_option(key: &str, map: &HashMap<String, Value>) -> Result<Option<Url>
     }
 }
 
+fn get_address_option(
+    key: &str,
+    map: &HashMap<String, Value>,
+) -> Result<Option<Address>, RedisError> {
+    if let Some(ref value) = map.get(key) {
+        let value: String = from_redis_value(value)?;
+        if let Ok(address) = Address::from_str(&value) {
+            Ok(Some(address))
+        } else {
+            Err(RedisError::from((
+                ErrorKind::TypeError,
+                "Invalid ILP address",
+            )))
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+fn get_address_list(key: &str, map: &HashMap<String, Value>) -> Result<Vec<Address>, RedisError> {
+    match get_value_option::<String>(key, map)? {
+        Some(value) => value
+            .split(',')
+            .map(|address| {
+                Address::from_str(address)
+                    .map_err(|_| RedisError::from((ErrorKind::TypeError, "Invalid ILP address")))
+            })
+            .collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,20 +1,132 @@
-use futures::future::{FutureExt, TryFutureExt};
-use log::{debug, error};
+use futures::future::{Future, FutureExt, TryFutureExt};
+use log::{debug, error, warn};
 use parking_lot::RwLock;
 use redis_crate::{
     aio::{ConnectionLike, MultiplexedConnection},
-    Client, Cmd, ConnectionInfo, Pipeline, RedisError, RedisFuture, Value,
+    Arg, Client, Cmd, ConnectionInfo, ErrorKind, Pipeline, RedisError, RedisFuture, Value,
 };
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Weak,
+};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 
 type Result<T> = std::result::Result<T, RedisError>;
 
+/// How long commands are retried for while the connection is re-established,
+/// which is enough to ride out a typical failover
+pub const DEFAULT_RETRY_TIMEOUT: Duration = Duration::from_millis(1000);
+/// Delay before the first reconnection attempt, which is doubled on every further attempt
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(10);
+/// Shortest time a connection attempt is given, so that the store still reconnects
+/// when the retries are disabled or the retry timeout is about to pass
+const MIN_CONNECT_TIMEOUT: Duration = Duration::from_millis(100);
+/// The commands which only read data, so they can safely be sent again when it is
+/// unknown whether Redis executed them before the connection dropped
+const READ_ONLY_COMMANDS: &[&[u8]] = &[
+    b"DBSIZE",
+    b"ECHO",
+    b"EXISTS",
+    b"GET",
+    b"GETRANGE",
+    b"HEXISTS",
+    b"HGET",
+    b"HGETALL",
+    b"HKEYS",
+    b"HLEN",
+    b"HMGET",
+    b"HSCAN",
+    b"HSTRLEN",
+    b"HVALS",
+    b"KEYS",
+    b"LINDEX",
+    b"LLEN",
+    b"LRANGE",
+    b"MGET",
+    b"PING",
+    b"PTTL",
+    b"SCAN",
+    b"SCARD",
+    b"SISMEMBER",
+    b"SMEMBERS",
+    b"SSCAN",
+    b"STRLEN",
+    b"TTL",
+    b"TYPE",
+    b"ZCARD",
+    b"ZCOUNT",
+    b"ZRANGE",
+    b"ZRANGEBYSCORE",
+    b"ZRANK",
+    b"ZREVRANGE",
+    b"ZREVRANGEBYSCORE",
+    b"ZREVRANK",
+    b"ZSCAN",
+    b"ZSCORE",
+];
+
+/// Whether the connection to Redis is currently up. Cloning it is cheap and every
+/// clone reflects the status of the same connection.
+#[derive(Clone, Debug)]
+pub struct ConnectionStatus(Arc<AtomicBool>);
+
+impl ConnectionStatus {
+    /// Returns false from when a command failed because the connection dropped
+    /// until the store reconnected
+    pub fn is_connected(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set_connected(&self, connected: bool) {
+        self.0.store(connected, Ordering::Relaxed);
+    }
+}
+
+/// Shared between all clones of a connection, so that the commands which fail
+/// at the same time only reconnect once
+struct ReconnectState {
+    status: ConnectionStatus,
+    /// Incremented on every reconnection
+    generation: AtomicUsize,
+    reconnecting: Mutex<()>,
+}
+
 /// Wrapper around a Redis MultiplexedConnection that automatically
-/// attempts to reconnect to the DB if the connection is dropped
+/// attempts to reconnect to the DB if the connection is dropped.
+///
+/// Once a command failed because the connection dropped (e.g. while Redis fails over
+/// to a replica), the commands sent over any clone wait in memory while it reconnects
+/// with exponential backoff, until the retry timeout passed. As those were not sent yet,
+/// they are executed once after reconnecting. The commands which were sent when the
+/// connection dropped are only repeated if they only read data, because the others
+/// (such as the scripts updating balances) may have been executed already.
 #[derive(Clone)]
 pub struct RedisReconnect {
     pub(crate) redis_info: Arc<ConnectionInfo>,
     pub(crate) conn: Arc<RwLock<MultiplexedConnection>>,
+    state: Arc<ReconnectState>,
+    retry_timeout: Duration,
+}
+
+/// A [`RedisReconnect`] which does not keep the connection open
+pub(crate) struct WeakRedisReconnect {
+    redis_info: Arc<ConnectionInfo>,
+    conn: Weak<RwLock<MultiplexedConnection>>,
+    state: Arc<ReconnectState>,
+    retry_timeout: Duration,
+}
+
+impl WeakRedisReconnect {
+    /// Returns the connection, unless all of its strong references were dropped
+    pub(crate) fn upgrade(&self) -> Option<RedisReconnect> {
+        Some(RedisReconnect {
+            redis_info: self.redis_info.clone(),
+            conn: self.conn.upgrade()?,
+            state: self.state.clone(),
+            retry_timeout: self.retry_timeout,
+        })
+    }
 }
 
 async fn get_shared_connection(redis_info: Arc<ConnectionInfo>) -> Result<MultiplexedConnection> {
@@ -28,6 +140,26 @@ async fn get_shared_connection(redis_info: Arc<ConnectionInfo>) -> Result<Multip
         .await
 }
 
+/// The error of the commands which could not be sent because the store is not connected
+fn not_connected() -> RedisError {
+    RedisError::from((ErrorKind::IoError, "Not connected to Redis"))
+}
+
+/// Returns whether the command failed because of the connection rather than the command itself
+fn is_connection_error(error: &RedisError) -> bool {
+    error.is_io_error() || error.is_connection_dropped() || error.is_connection_refusal()
+}
+
+/// Returns whether sending the command again has no other effect than sending it once
+fn is_read_only(cmd: &Cmd) -> bool {
+    match cmd.args_iter().next() {
+        Some(Arg::Simple(name)) => READ_ONLY_COMMANDS
+            .iter()
+            .any(|command| command.eq_ignore_ascii_case(name)),
+        _ => false,
+    }
+}
+
 impl RedisReconnect {
     /// Connects to redis with the provided [`ConnectionInfo`](redis_crate::ConnectionInfo)
     pub async fn connect(redis_info: ConnectionInfo) -> Result<RedisReconnect> {
@@ -36,19 +168,126 @@ impl RedisReconnect {
         Ok(RedisReconnect {
             conn: Arc::new(RwLock::new(conn)),
             redis_info,
+            state: Arc::new(ReconnectState {
+                status: ConnectionStatus(Arc::new(AtomicBool::new(true))),
+                generation: AtomicUsize::new(0),
+                reconnecting: Mutex::new(()),
+            }),
+            retry_timeout: DEFAULT_RETRY_TIMEOUT,
         })
     }
 
-    /// Reconnects to redis
-    pub async fn reconnect(self) -> Result<Self> {
-        let shared_connection = get_shared_connection(self.redis_info.clone()).await?;
-        (*self.conn.write()) = shared_connection;
-        debug!("Reconnected to Redis");
-        Ok(self)
+    /// Sets how long commands are retried for while reconnecting. Zero disables the retries.
+    /// This only applies to the clones made after it was set
+    pub fn retry_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.retry_timeout = timeout;
+        self
+    }
+
+    /// Returns the status of the connection
+    pub fn status(&self) -> ConnectionStatus {
+        self.state.status.clone()
+    }
+
+    /// Reconnects to redis, unless another clone already did so since the given generation.
+    /// Gives up once the timeout passed, including the time spent waiting for other clones
+    /// which are reconnecting
+    async fn reconnect(&self, generation: usize, timeout: Duration) -> Result<()> {
+        let reconnect = async {
+            let _reconnecting = self.state.reconnecting.lock().await;
+            if self.state.generation.load(Ordering::SeqCst) != generation {
+                return Ok(());
+            }
+            let shared_connection = get_shared_connection(self.redis_info.clone()).await?;
+            (*self.conn.write()) = shared_connection;
+            self.state.generation.fetch_add(1, Ordering::SeqCst);
+            self.state.status.set_connected(true);
+            debug!("Reconnected to Redis");
+            Ok(())
+        };
+        match tokio::time::timeout(timeout.max(MIN_CONNECT_TIMEOUT), reconnect).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("Timed out reconnecting to Redis");
+                Err(not_connected())
+            }
+        }
     }
 
-    fn get_shared_connection(&self) -> MultiplexedConnection {
-        self.conn.read().clone()
+    pub(crate) fn downgrade(&self) -> WeakRedisReconnect {
+        WeakRedisReconnect {
+            redis_info: self.redis_info.clone(),
+            conn: Arc::downgrade(&self.conn),
+            state: self.state.clone(),
+            retry_timeout: self.retry_timeout,
+        }
+    }
+
+    fn get_shared_connection(&self) -> (MultiplexedConnection, usize) {
+        let conn = self.conn.read();
+        (conn.clone(), self.state.generation.load(Ordering::SeqCst))
+    }
+
+    /// Sends the request over the current connection, after waiting for it to be
+    /// re-established if it is known to be down. If the request fails because of the
+    /// connection, the connection is re-established and the request is only sent again
+    /// if it is `read_only`. Reconnecting is retried with exponential backoff until the
+    /// retry timeout passed, and each attempt is bounded by the time which is left
+    async fn request_with_retries<T, F, Fut>(&self, read_only: bool, request: F) -> Result<T>
+    where
+        F: Fn(MultiplexedConnection) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let started_at = Instant::now();
+        let mut delay = INITIAL_RETRY_DELAY;
+        let mut error = None;
+        loop {
+            let (connection, generation) = self.get_shared_connection();
+            if self.state.status.is_connected() {
+                match request(connection).await {
+                    Ok(res) => return Ok(res),
+                    Err(err) if is_connection_error(&err) => {
+                        self.state.status.set_connected(false);
+                        if !read_only {
+                            warn!(
+                                "Redis connection error, reconnecting without sending the command again because it may have been executed: {:?}",
+                                err
+                            );
+                            // The next command is sent over a new connection
+                            let _ = self
+                                .reconnect(
+                                    generation,
+                                    self.retry_timeout.saturating_sub(started_at.elapsed()),
+                                )
+                                .await;
+                            return Err(err);
+                        }
+                        error = Some(err);
+                    }
+                    Err(err) => return Err(err),
+                }
+            } else if self
+                .reconnect(
+                    generation,
+                    self.retry_timeout.saturating_sub(started_at.elapsed()),
+                )
+                .await
+                .is_ok()
+            {
+                // The request was not sent yet, so it is safe to send it now
+                continue;
+            }
+
+            if started_at.elapsed() + delay > self.retry_timeout {
+                return Err(error.unwrap_or_else(not_connected));
+            }
+            warn!(
+                "Not connected to Redis, reconnecting and sending the command in {}ms",
+                delay.as_millis()
+            );
+            tokio::time::delay_for(delay).await;
+            delay *= 2;
+        }
     }
 }
 
@@ -58,20 +297,11 @@ impl ConnectionLike for RedisReconnect {
     }
 
     fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
-        // This is how it is implemented in the redis-rs repository
         (async move {
-            let mut connection = self.get_shared_connection();
-            match connection.req_packed_command(cmd).await {
-                Ok(res) => Ok(res),
-                Err(error) => {
-                    if error.is_connection_dropped() {
-                        debug!("Redis connection was dropped, attempting to reconnect");
-                        // TODO: Is this correct syntax? Otherwise we get an unused result warning
-                        let _ = self.clone().reconnect().await;
-                    }
-                    Err(error)
-                }
-            }
+            self.request_with_retries(is_read_only(cmd), move |mut connection| async move {
+                connection.req_packed_command(cmd).await
+            })
+            .await
         })
         .boxed()
     }
@@ -82,21 +312,48 @@ impl ConnectionLike for RedisReconnect {
         offset: usize,
         count: usize,
     ) -> RedisFuture<'a, Vec<Value>> {
-        // This is how it is implemented in the redis-rs repository
         (async move {
-            let mut connection = self.get_shared_connection();
-            match connection.req_packed_commands(cmd, offset, count).await {
-                Ok(res) => Ok(res),
-                Err(error) => {
-                    if error.is_connection_dropped() {
-                        debug!("Redis connection was dropped, attempting to reconnect");
-                        // TODO: Is this correct syntax? Otherwise we get an unused result warning
-                        let _ = self.clone().reconnect().await;
-                    }
-                    Err(error)
-                }
-            }
+            let read_only = cmd.cmd_iter().all(is_read_only);
+            self.request_with_retries(read_only, move |mut connection| async move {
+                connection.req_packed_commands(cmd, offset, count).await
+            })
+            .await
         })
         .boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis_crate::{cmd, pipe, Script};
+
+    #[test]
+    fn only_repeats_read_only_commands() {
+        assert!(is_read_only(cmd("HGET").arg("accounts:1").arg("balance")));
+        assert!(is_read_only(&cmd("hmget")));
+        assert!(is_read_only(&cmd("PING")));
+        assert!(!is_read_only(cmd("HINCRBY").arg("accounts:1").arg(100)));
+        assert!(!is_read_only(&cmd("PUBLISH")));
+        assert!(!is_read_only(&Cmd::new()));
+
+        // Scripts may change data, e.g. the balances
+        let script = Script::new("return 1");
+        assert!(!is_read_only(cmd("EVALSHA").arg(script.get_hash()).arg(0)));
+        assert!(!is_read_only(cmd("EVAL").arg("return 1").arg(0)));
+    }
+
+    #[test]
+    fn only_repeats_pipelines_of_read_only_commands() {
+        let mut reads = pipe();
+        reads.atomic().hget("accounts:1", "balance").get("rates");
+        assert!(reads.cmd_iter().all(is_read_only));
+
+        let mut writes = pipe();
+        writes
+            .atomic()
+            .hget("accounts:1", "balance")
+            .set("rates", 1);
+        assert!(!writes.cmd_iter().all(is_read_only));
+    }
+}
@@ -0,0 +1,335 @@
+use super::{fixtures::*, store_helpers::*};
+use interledger_api::{AccountSettings, NodeStore};
+use interledger_btp::{BtpAccount, BtpStore};
+use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
+use interledger_http::{HttpAccount, HttpStore};
+use interledger_packet::Address;
+use interledger_router::RouterStore;
+use interledger_service::Account as AccountTrait;
+use interledger_service::{AccountStore, AddressStore, Username};
+use interledger_service_util::BalanceStore;
+use secrecy::{ExposeSecret, SecretString};
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn insert_accounts() {
+    let (store, _) = test_store().await.unwrap();
+    let account = store
+        .insert_account(ACCOUNT_DETAILS_2.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        *account.ilp_address(),
+        Address::from_str("example.alice.user1.charlie").unwrap()
+    );
+
+    // cannot insert duplicate accounts
+    let err = store
+        .insert_account(ACCOUNT_DETAILS_2.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "account `charlie` already exists");
+}
+
+#[tokio::test]
+async fn update_ilp_and_children_addresses() {
+    let (store, accs) = test_store().await.unwrap();
+    // Add a NonRoutingAccount to make sure its address
+    // gets updated as well
+    let acc2 = store
+        .insert_account(ACCOUNT_DETAILS_2.clone())
+        .await
+        .unwrap();
+    let mut accs = accs.clone();
+    accs.push(acc2);
+    accs.sort_by_key(|a| a.username().clone());
+    let ilp_address = Address::from_str("test.parent.our_address").unwrap();
+
+    store.set_ilp_address(ilp_address.clone()).await.unwrap();
+    assert_eq!(store.get_ilp_address(), ilp_address);
+
+    let mut accounts = store.get_all_accounts().await.unwrap();
+    accounts.sort_by_key(|a| a.username().clone());
+    for (a, b) in accounts.into_iter().zip(&accs) {
+        if a.routing_relation() == RoutingRelation::Child
+            || a.routing_relation() == RoutingRelation::NonRoutingAccount
+        {
+            assert_eq!(
+                *a.ilp_address(),
+                ilp_address.with_suffix(a.username().as_bytes()).unwrap()
+            );
+        } else {
+            assert_eq!(a.ilp_address(), b.ilp_address());
+        }
+    }
+}
+
+#[tokio::test]
+async fn only_one_parent_allowed() {
+    let mut acc = ACCOUNT_DETAILS_2.clone();
+    acc.routing_relation = Some("Parent".to_owned());
+    acc.username = Username::from_str("another_name").unwrap();
+    acc.ilp_address = Some(Address::from_str("example.another_name").unwrap());
+    let (store, accs) = test_store().await.unwrap();
+    let res = store.insert_account(acc.clone()).await;
+    // This should fail
+    assert!(res.is_err());
+    store.delete_account(accs[0].id()).await.unwrap();
+    // must also clear the ILP Address to indicate that we no longer
+    // have a parent account configured
+    store.clear_ilp_address().await.unwrap();
+    let res = store.insert_account(acc).await;
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn delete_accounts() {
+    let (store, _) = test_store().await.unwrap();
+    let accounts = store.get_all_accounts().await.unwrap();
+    let id = accounts[0].id();
+    store.delete_account(id).await.unwrap();
+    let accounts = store.get_all_accounts().await.unwrap();
+    for a in &accounts {
+        assert_ne!(id, a.id());
+    }
+
+    // clear all accounts and try again
+    store.delete_account(accounts[0].id()).await.unwrap();
+    let accounts = store.get_all_accounts().await.unwrap();
+    assert_eq!(accounts.len(), 0);
+
+    // try deleting an account which does not exist
+    let err = store.delete_account(id).await.unwrap_err();
+    assert_eq!(err.to_string(), format!("account `{}` was not found", id));
+}
+
+#[tokio::test]
+async fn update_accounts() {
+    let (store, accounts) = test_store().await.unwrap();
+    let id = accounts[0].id();
+    store
+        .update_balances_for_prepare(id, 100, None)
+        .await
+        .unwrap();
+    let mut new = ACCOUNT_DETAILS_0.clone();
+    new.asset_code = String::from("TUV");
+    let account = store.update_account(id, new.clone()).await.unwrap();
+    assert_eq!(account.asset_code(), "TUV");
+    // the balance is kept
+    assert_eq!(store.get_balance(id).await.unwrap(), -100);
+
+    let id = Uuid::new_v4();
+    let err = store.update_account(id, new).await.unwrap_err();
+    assert_eq!(err.to_string(), format!("account `{}` was not found", id));
+}
+
+#[tokio::test]
+async fn modify_account_settings_settle_to_overflow() {
+    let (store, accounts) = test_store().await.unwrap();
+    let mut settings = AccountSettings::default();
+    settings.settle_to = Some(std::i64::MAX as u64 + 1);
+    let err = store
+        .modify_account_settings(accounts[0].id(), settings)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid account: the provided value for parameter `settle_to` was too large"
+    );
+}
+
+#[tokio::test]
+async fn modify_account_settings() {
+    let (store, accounts) = test_store().await.unwrap();
+    let settings = AccountSettings {
+        ilp_over_http_outgoing_token: Some(SecretString::new("test_token".to_owned())),
+        ilp_over_http_incoming_token: Some(SecretString::new("http_in_new".to_owned())),
+        ilp_over_btp_outgoing_token: Some(SecretString::new("dylan:test".to_owned())),
+        ilp_over_btp_incoming_token: Some(SecretString::new("btp_in_new".to_owned())),
+        ilp_over_http_url: Some("http://example.com/accounts/dylan/ilp".to_owned()),
+        ilp_over_btp_url: Some("http://example.com/accounts/dylan/ilp/btp".to_owned()),
+        settle_threshold: Some(-50),
+        settle_to: Some(100),
+    };
+    let id = accounts[0].id();
+    let ret = store
+        .modify_account_settings(id, settings.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        ret.get_http_auth_token().unwrap().expose_secret(),
+        "test_token",
+    );
+    assert_eq!(
+        ret.get_ilp_over_btp_outgoing_token().unwrap(),
+        &b"dylan:test"[..],
+    );
+
+    // the new incoming tokens are used for authentication
+    let alice = Username::from_str("alice").unwrap();
+    assert!(store
+        .get_account_from_http_auth(&alice, "http_in_new")
+        .await
+        .is_ok());
+    assert!(store
+        .get_account_from_btp_auth(&alice, "btp_in_new")
+        .await
+        .is_ok());
+    assert!(store
+        .get_account_from_btp_auth(&alice, "btp_token")
+        .await
+        .is_err());
+
+    let id = Uuid::new_v4();
+    let err = store
+        .modify_account_settings(id, settings)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), format!("account `{}` was not found", id));
+}
+
+#[tokio::test]
+async fn fetches_account_from_username() {
+    let (store, accs) = test_store().await.unwrap();
+    let account_id = store
+        .get_account_id_from_username(&Username::from_str("alice").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(account_id, accs[0].id());
+
+    let err = store
+        .get_account_id_from_username(&Username::from_str("random").unwrap())
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "account `random` was not found");
+}
+
+#[tokio::test]
+async fn gets_multiple() {
+    let (store, accs) = test_store().await.unwrap();
+    // set account ids in reverse order
+    let account_ids: Vec<Uuid> = accs.iter().rev().map(|a| a.id()).collect::<_>();
+    let accounts = store.get_accounts(account_ids).await.unwrap();
+    // note reverse order is intentional
+    assert_eq!(accounts[0].ilp_address(), accs[1].ilp_address());
+    assert_eq!(accounts[1].ilp_address(), accs[0].ilp_address());
+}
+
+#[tokio::test]
+async fn errors_for_unknown_accounts() {
+    let (store, _) = test_store().await.unwrap();
+    let err = store
+        .get_accounts(vec![Uuid::new_v4(), Uuid::new_v4()])
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "wrong account length (expected 2, got 0)");
+}
+
+#[tokio::test]
+async fn authenticates_btp_and_http_accounts() {
+    let (store, accs) = test_store().await.unwrap();
+    let alice = Username::from_str("alice").unwrap();
+    let account = store
+        .get_account_from_btp_auth(&alice, "btp_token")
+        .await
+        .unwrap();
+    assert_eq!(account.id(), accs[0].id());
+    let err = store
+        .get_account_from_btp_auth(&alice, "other_btp_token")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "account `alice` is not authorized for this action"
+    );
+
+    let account = store
+        .get_account_from_http_auth(&alice, "incoming_auth_token")
+        .await
+        .unwrap();
+    assert_eq!(account.id(), accs[0].id());
+    assert!(store
+        .get_account_from_http_auth(&alice, "wrong_token")
+        .await
+        .is_err());
+
+    let accounts = store.get_btp_outgoing_accounts().await.unwrap();
+    assert_eq!(accounts.len(), 2);
+}
+
+#[tokio::test]
+async fn gets_account_from_client_certificate() {
+    let fingerprint = "ab".repeat(32);
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.ilp_over_http_client_certificates = vec![fingerprint.to_uppercase()];
+    let (store, _) = test_store().await.unwrap();
+    let charlie = store.insert_account(details).await.unwrap();
+    let charlie_username = Username::from_str("charlie").unwrap();
+
+    let account = store
+        .get_account_from_client_certificate(&charlie_username, &fingerprint)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.id(), charlie.id());
+    assert_eq!(account.get_http_client_certificates(), &[fingerprint][..]);
+
+    let err = store
+        .get_account_from_client_certificate(&charlie_username, &"cd".repeat(32))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "account `charlie` is not authorized for this action"
+    );
+
+    // accounts without certificates are authenticated with their token instead
+    assert!(store
+        .get_account_from_client_certificate(
+            &Username::from_str("alice").unwrap(),
+            &"ab".repeat(32)
+        )
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn disabled_accounts_cannot_authenticate() {
+    let (store, accs) = test_store().await.unwrap();
+    let alice = Username::from_str("alice").unwrap();
+    store
+        .set_account_enabled(accs[0].id(), false)
+        .await
+        .unwrap();
+    let err = store
+        .get_account_from_btp_auth(&alice, "btp_token")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "account `alice` is not authorized for this action"
+    );
+    assert!(store
+        .get_account_from_http_auth(&alice, "incoming_auth_token")
+        .await
+        .is_err());
+    assert!(store.disabled_accounts().contains(&accs[0].id()));
+
+    store.set_account_enabled(accs[0].id(), true).await.unwrap();
+    assert!(store
+        .get_account_from_btp_auth(&alice, "btp_token")
+        .await
+        .is_ok());
+    assert!(store
+        .get_account_from_http_auth(&alice, "incoming_auth_token")
+        .await
+        .is_ok());
+    assert!(store.disabled_accounts().is_empty());
+
+    assert!(store
+        .set_account_enabled(Uuid::new_v4(), false)
+        .await
+        .is_err());
+}
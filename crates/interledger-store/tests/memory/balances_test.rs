@@ -0,0 +1,257 @@
+use super::{fixtures::*, store_helpers::*};
+
+use futures::future::join_all;
+use interledger_api::NodeStore;
+use interledger_packet::Address;
+use interledger_service::Account as AccountTrait;
+use interledger_service::Username;
+use interledger_service_util::BalanceStore;
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn starts_with_zero_balance() {
+    let (store, accs) = test_store().await.unwrap();
+    let balance = store.get_balance(accs[0].id()).await.unwrap();
+    assert_eq!(balance, 0);
+}
+
+#[tokio::test]
+async fn errors_for_unknown_accounts() {
+    let (store, _) = test_store().await.unwrap();
+    let id = Uuid::new_v4();
+    let err = store.get_balance(id).await.unwrap_err();
+    assert_eq!(err.to_string(), format!("account `{}` was not found", id));
+    assert!(store
+        .update_balances_for_prepare(id, 1, None)
+        .await
+        .is_err());
+    assert!(store
+        .update_balances_for_fulfill(id, 1, None)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn gets_balances_of_several_accounts() {
+    let (store, accs) = test_store().await.unwrap();
+    store
+        .update_balances_for_prepare(accs[0].id(), 100, None)
+        .await
+        .unwrap();
+    let balances = store
+        .get_balances(vec![accs[1].id(), accs[0].id()])
+        .await
+        .unwrap();
+    assert_eq!(balances, vec![0, -100]);
+    assert!(store
+        .get_balances(vec![accs[0].id(), Uuid::new_v4()])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn prepare_then_fulfill_with_settlement() {
+    let (store, accs) = test_store().await.unwrap();
+    let account0_id = accs[0].id();
+    let account1_id = accs[1].id();
+    // reduce account 0's balance by 100
+    store
+        .update_balances_for_prepare(account0_id, 100, None)
+        .await
+        .unwrap();
+    let balance0 = store.get_balance(account0_id).await.unwrap();
+    let balance1 = store.get_balance(account1_id).await.unwrap();
+    assert_eq!(balance0, -100);
+    assert_eq!(balance1, 0);
+
+    // account 1 settles down to its settle_to
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(account1_id, 100, None)
+        .await
+        .unwrap();
+    assert_eq!(balance, -1000);
+    assert_eq!(amount_to_settle, 1100);
+    let balance0 = store.get_balance(account0_id).await.unwrap();
+    let balance1 = store.get_balance(account1_id).await.unwrap();
+    assert_eq!(balance0, -100);
+    assert_eq!(balance1, -1000);
+}
+
+#[tokio::test]
+async fn process_fulfill_no_settle_to() {
+    // account without a settle_to
+    let acc = {
+        let mut acc = ACCOUNT_DETAILS_1.clone();
+        acc.username = Username::from_str("charlie").unwrap();
+        acc.ilp_address = Some(Address::from_str("example.charlie").unwrap());
+        acc.settle_to = None;
+        acc
+    };
+    let (store, _) = test_store().await.unwrap();
+    let account = store.insert_account(acc).await.unwrap();
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(account.id(), 100, None)
+        .await
+        .unwrap();
+    assert_eq!(balance, 100);
+    assert_eq!(amount_to_settle, 0);
+}
+
+#[tokio::test]
+async fn process_fulfill_settle_to_over_threshold() {
+    // account misconfigured with settle_to >= settle_threshold does not get settlements
+    let acc = {
+        let mut acc = ACCOUNT_DETAILS_1.clone();
+        acc.username = Username::from_str("charlie").unwrap();
+        acc.ilp_address = Some(Address::from_str("example.b").unwrap());
+        acc.settle_to = Some(101);
+        acc.settle_threshold = Some(100);
+        acc
+    };
+    let (store, _) = test_store().await.unwrap();
+    let account = store.insert_account(acc).await.unwrap();
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(account.id(), 1000, None)
+        .await
+        .unwrap();
+    assert_eq!(balance, 1000);
+    assert_eq!(amount_to_settle, 0);
+}
+
+#[tokio::test]
+async fn process_fulfill_ok() {
+    // account with settle to = 0 (not falsy) with settle_threshold > 0, gets settlements
+    let acc = {
+        let mut acc = ACCOUNT_DETAILS_1.clone();
+        acc.username = Username::from_str("charlie").unwrap();
+        acc.ilp_address = Some(Address::from_str("example.c").unwrap());
+        acc.settle_to = Some(0);
+        acc.settle_threshold = Some(100);
+        acc
+    };
+    let (store, _) = test_store().await.unwrap();
+    let account = store.insert_account(acc).await.unwrap();
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(account.id(), 101, None)
+        .await
+        .unwrap();
+    assert_eq!(balance, 0);
+    assert_eq!(amount_to_settle, 101);
+}
+
+#[tokio::test]
+async fn prepare_then_reject() {
+    let (store, accs) = test_store().await.unwrap();
+    let acc0 = accs[0].id();
+    let acc1 = accs[1].id();
+    store
+        .update_balances_for_prepare(acc0, 100, None)
+        .await
+        .unwrap();
+    let balance0 = store.get_balance(acc0).await.unwrap();
+    let balance1 = store.get_balance(acc1).await.unwrap();
+    assert_eq!(balance0, -100);
+    assert_eq!(balance1, 0);
+    store
+        .update_balances_for_reject(acc0, 100, None)
+        .await
+        .unwrap();
+    let balance0 = store.get_balance(acc0).await.unwrap();
+    let balance1 = store.get_balance(acc1).await.unwrap();
+    assert_eq!(balance0, 0);
+    assert_eq!(balance1, 0);
+}
+
+#[tokio::test]
+async fn enforces_minimum_balance() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    let err = store
+        .update_balances_for_prepare(id, 10000, None)
+        .await
+        .unwrap_err();
+    let expected = format!("Incoming prepare of 10000 would bring account {} under its minimum balance. Current balance: 0, min balance: -1000", id);
+    assert!(err.to_string().contains(&expected));
+}
+
+#[tokio::test]
+async fn allows_prepares_up_to_the_credit_line() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    // fits within the credit line of 1000
+    store
+        .update_balances_for_prepare(id, 600, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), -600);
+    // exactly hits the credit line
+    store
+        .update_balances_for_prepare(id, 400, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), -1000);
+    // exceeds the credit line, which must leave the balance untouched
+    store
+        .update_balances_for_prepare(id, 1, None)
+        .await
+        .unwrap_err();
+    assert_eq!(store.get_balance(id).await.unwrap(), -1000);
+}
+
+#[tokio::test]
+async fn concurrent_prepares_cannot_exceed_the_credit_line() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    let results = join_all((0..15).map(|_| {
+        let store = store.clone();
+        tokio::spawn(async move { store.update_balances_for_prepare(id, 100, None).await })
+    }))
+    .await;
+    let accepted = results
+        .into_iter()
+        .filter(|result| result.as_ref().unwrap().is_ok())
+        .count();
+    assert_eq!(accepted, 10);
+    assert_eq!(store.get_balance(id).await.unwrap(), -1000);
+}
+
+#[tokio::test]
+// Prepare and Fulfill a packet for 100 units from Account 0 to Account 1
+// Then, Prepare and Fulfill a packet for 80 units from Account 1 to Account 0
+async fn netting_fulfilled_balances() {
+    let (store, accs) = test_store().await.unwrap();
+    let acc = store
+        .insert_account(ACCOUNT_DETAILS_2.clone())
+        .await
+        .unwrap();
+    let account0 = accs[0].id();
+    let account1 = acc.id();
+
+    // decrement account 0 by 100
+    store
+        .update_balances_for_prepare(account0, 100, None)
+        .await
+        .unwrap();
+    // increment account 1 by 100
+    store
+        .update_balances_for_fulfill(account1, 100, None)
+        .await
+        .unwrap();
+
+    // decrement account 1 by 80
+    store
+        .update_balances_for_prepare(account1, 80, None)
+        .await
+        .unwrap();
+    // increment account 0 by 80
+    store
+        .update_balances_for_fulfill(account0, 80, None)
+        .await
+        .unwrap();
+
+    let balance0 = store.get_balance(account0).await.unwrap();
+    let balance1 = store.get_balance(account1).await.unwrap();
+    assert_eq!(balance0, -20);
+    assert_eq!(balance1, 20);
+}
@@ -0,0 +1,85 @@
+use super::store_helpers::*;
+
+use interledger_service::Account as AccountTrait;
+use interledger_service_util::BalanceStore;
+use interledger_settlement::core::types::SettlementStore;
+use interledger_store::events::{BalanceChangeReason, BalanceEventSink, OverflowPolicy};
+
+#[tokio::test]
+async fn fulfilled_packet_produces_balance_events() {
+    let (sink, mut events) = BalanceEventSink::new(10, OverflowPolicy::Drop);
+    let (store, accs) = test_store_with_balance_events(Some(sink)).await.unwrap();
+    store
+        .update_balances_for_prepare(accs[0].id(), 100, Some([1; 32]))
+        .await
+        .unwrap();
+    store
+        .update_balances_for_fulfill(accs[1].id(), 100, Some([1; 32]))
+        .await
+        .unwrap();
+
+    let event = events.recv().await.unwrap();
+    assert_eq!(event.account_id, accs[0].id());
+    assert_eq!(event.reason, BalanceChangeReason::Prepare);
+    assert_eq!((event.old_balance, event.new_balance), (0, -100));
+    assert_eq!(event.packet_id, Some(hex::encode([1; 32])));
+    assert_eq!(event.settlement_id, None);
+    assert!(!event.timestamp.is_empty());
+
+    let event = events.recv().await.unwrap();
+    assert_eq!(event.account_id, accs[1].id());
+    assert_eq!(event.reason, BalanceChangeReason::Fulfill);
+    assert_eq!((event.old_balance, event.new_balance), (0, 100));
+    assert_eq!(event.packet_id, Some(hex::encode([1; 32])));
+
+    // account 1 settles down to its settle_to
+    let event = events.recv().await.unwrap();
+    assert_eq!(event.account_id, accs[1].id());
+    assert_eq!(event.reason, BalanceChangeReason::OutgoingSettlement);
+    assert_eq!((event.old_balance, event.new_balance), (100, -1000));
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn incoming_settlement_produces_balance_event() {
+    let (sink, mut events) = BalanceEventSink::new(10, OverflowPolicy::Drop);
+    let (store, accs) = test_store_with_balance_events(Some(sink)).await.unwrap();
+    store
+        .update_balance_for_incoming_settlement(accs[0].id(), 100, Some("settlement1".into()))
+        .await
+        .unwrap();
+
+    let event = events.recv().await.unwrap();
+    assert_eq!(event.account_id, accs[0].id());
+    assert_eq!(event.reason, BalanceChangeReason::IncomingSettlement);
+    assert_eq!((event.old_balance, event.new_balance), (0, 100));
+    assert_eq!(event.packet_id, None);
+    assert_eq!(event.settlement_id, Some("settlement1".to_string()));
+
+    // a retried settlement does not change the balance
+    store
+        .update_balance_for_incoming_settlement(accs[0].id(), 100, Some("settlement1".into()))
+        .await
+        .unwrap();
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn drops_events_when_buffer_is_full() {
+    let (sink, mut events) = BalanceEventSink::new(1, OverflowPolicy::Drop);
+    let (store, accs) = test_store_with_balance_events(Some(sink)).await.unwrap();
+    let id = accs[0].id();
+    store
+        .update_balances_for_prepare(id, 100, None)
+        .await
+        .unwrap();
+    store
+        .update_balances_for_prepare(id, 100, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), -200);
+
+    let event = events.recv().await.unwrap();
+    assert_eq!((event.old_balance, event.new_balance), (0, -100));
+    assert!(events.try_recv().is_err());
+}
@@ -0,0 +1,68 @@
+use super::store_helpers::*;
+use interledger_api::{NodeExport, NodeStore};
+use interledger_btp::BtpStore;
+use interledger_http::{HttpAccount, HttpStore};
+use interledger_packet::Address;
+use interledger_router::RouterStore;
+use interledger_service::{Account as AccountTrait, AccountStore, Username};
+use interledger_service_util::BalanceStore;
+use interledger_store::memory::MemoryStore;
+use secrecy::ExposeSecret;
+use std::str::FromStr;
+
+#[tokio::test]
+async fn exports_and_imports_accounts_balances_and_routes() {
+    let (store, accs) = test_store().await.unwrap();
+    store
+        .update_balances_for_prepare(accs[0].id(), 100, None)
+        .await
+        .unwrap();
+    store
+        .set_static_route("example.other".to_string(), accs[1].id())
+        .await
+        .unwrap();
+    store.set_default_route(accs[0].id()).await.unwrap();
+
+    // The export is transferred as JSON
+    let export = serde_json::to_string(&store.export_node().await.unwrap()).unwrap();
+    let export: NodeExport = serde_json::from_str(&export).unwrap();
+    assert_eq!(export.accounts.len(), 2);
+
+    let new_store = MemoryStore::new(Address::from_str("example.node").unwrap());
+    let imported = new_store.import_node(export.clone()).await.unwrap();
+    assert_eq!(imported.len(), 2);
+
+    let accounts = new_store
+        .get_accounts(vec![accs[0].id(), accs[1].id()])
+        .await
+        .unwrap();
+    for (account, original) in accounts.iter().zip(accs.iter()) {
+        assert_eq!(account.username(), original.username());
+        assert_eq!(account.ilp_address(), original.ilp_address());
+        assert_eq!(
+            account.get_http_auth_token().unwrap().expose_secret(),
+            original.get_http_auth_token().unwrap().expose_secret(),
+        );
+    }
+    assert_eq!(new_store.get_balance(accs[0].id()).await.unwrap(), -100);
+
+    // The incoming tokens are still accepted
+    new_store
+        .get_account_from_http_auth(&Username::from_str("alice").unwrap(), "incoming_auth_token")
+        .await
+        .unwrap();
+    new_store
+        .get_account_from_btp_auth(&Username::from_str("bob").unwrap(), "other_btp_token")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        new_store.routing_table().get("example.other").unwrap(),
+        &accs[1].id()
+    );
+    assert_eq!(new_store.routing_table().get("").unwrap(), &accs[0].id());
+
+    // The accounts exist now, so they cannot be imported again
+    let err = new_store.import_node(export).await.unwrap_err();
+    assert!(err.to_string().ends_with("already exists"));
+}
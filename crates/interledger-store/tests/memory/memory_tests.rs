@@ -0,0 +1,51 @@
+mod accounts_test;
+mod balances_test;
+mod events_test;
+mod export_test;
+mod routing_test;
+mod settlement_test;
+
+#[path = "../common/fixtures.rs"]
+mod fixtures;
+
+mod store_helpers {
+    use super::fixtures::*;
+
+    use interledger_api::NodeStore;
+    use interledger_packet::Address;
+    use interledger_service::{Account as AccountTrait, AddressStore};
+    use interledger_store::{account::Account, events::BalanceEventSink, memory::MemoryStore};
+    use std::str::FromStr;
+
+    pub async fn test_store() -> Result<(MemoryStore, Vec<Account>), ()> {
+        test_store_with_balance_events(None).await
+    }
+
+    pub async fn test_store_with_balance_events(
+        sink: Option<BalanceEventSink>,
+    ) -> Result<(MemoryStore, Vec<Account>), ()> {
+        let mut store = MemoryStore::new(Address::from_str("example.node").unwrap());
+        if let Some(sink) = sink {
+            store.balance_events(sink);
+        }
+        let mut accs = Vec::new();
+        let acc = store
+            .insert_account(ACCOUNT_DETAILS_0.clone())
+            .await
+            .unwrap();
+        accs.push(acc.clone());
+        // alice is a Parent, so the store's ilp address is updated to
+        // the value that would be received by the ILDCP request
+        store
+            .set_ilp_address(acc.ilp_address().with_suffix(b"user1").unwrap())
+            .await
+            .unwrap();
+
+        let acc = store
+            .insert_account(ACCOUNT_DETAILS_1.clone())
+            .await
+            .unwrap();
+        accs.push(acc);
+        Ok((store, accs))
+    }
+}
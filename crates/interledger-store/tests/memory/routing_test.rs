@@ -0,0 +1,217 @@
+use super::{fixtures::*, store_helpers::*};
+
+use interledger_api::NodeStore;
+use interledger_ccp::CcpRoutingStore;
+use interledger_packet::Address;
+use interledger_router::RouterStore;
+use interledger_service::{Account as AccountTrait, AddressStore};
+use interledger_store::account::Account;
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn inserted_accounts_are_routed() {
+    let (store, accs) = test_store().await.unwrap();
+    let routing_table = store.routing_table();
+    assert_eq!(routing_table.len(), 2);
+    assert_eq!(routing_table["example.alice"], accs[0].id());
+    assert_eq!(routing_table["example.alice.user1.bob"], accs[1].id());
+
+    store.delete_account(accs[1].id()).await.unwrap();
+    let routing_table = store.routing_table();
+    assert_eq!(routing_table.len(), 1);
+    assert!(routing_table.get("example.alice.user1.bob").is_none());
+}
+
+#[tokio::test]
+async fn gets_accounts_to_send_routes_to() {
+    let (store, _) = test_store().await.unwrap();
+    let accounts = store
+        .get_accounts_to_send_routes_to(Vec::new())
+        .await
+        .unwrap();
+    // We send to child accounts but not parents
+    assert_eq!(accounts[0].username().as_ref(), "bob");
+    assert_eq!(accounts.len(), 1);
+}
+
+#[tokio::test]
+async fn gets_accounts_to_send_routes_to_and_skips_ignored() {
+    let (store, accs) = test_store().await.unwrap();
+    let accounts = store
+        .get_accounts_to_send_routes_to(vec![accs[1].id()])
+        .await
+        .unwrap();
+    assert!(accounts.is_empty());
+}
+
+#[tokio::test]
+async fn gets_accounts_to_receive_routes_from() {
+    let (store, _) = test_store().await.unwrap();
+    let accounts = store.get_accounts_to_receive_routes_from().await.unwrap();
+    assert_eq!(
+        *accounts[0].ilp_address(),
+        Address::from_str("example.alice").unwrap()
+    );
+}
+
+#[tokio::test]
+async fn gets_local_and_configured_routes() {
+    let (store, _) = test_store().await.unwrap();
+    let (local, configured) = store.get_local_and_configured_routes().await.unwrap();
+    assert_eq!(local.len(), 2);
+    assert!(configured.is_empty());
+}
+
+#[tokio::test]
+async fn saves_routes() {
+    let (store, _) = test_store().await.unwrap();
+    let account0_id = Uuid::new_v4();
+    let account1_id = Uuid::new_v4();
+    let account0 = Account::try_from(
+        account0_id,
+        ACCOUNT_DETAILS_0.clone(),
+        store.get_ilp_address(),
+    )
+    .unwrap();
+    let account1 = Account::try_from(
+        account1_id,
+        ACCOUNT_DETAILS_1.clone(),
+        store.get_ilp_address(),
+    )
+    .unwrap();
+
+    store
+        .clone()
+        .set_routes(vec![
+            ("example.a".to_string(), account0.clone()),
+            ("example.b".to_string(), account0.clone()),
+            ("example.c".to_string(), account1.clone()),
+        ])
+        .await
+        .unwrap();
+
+    // the new routes replace the previous ones
+    let routes = store.routing_table();
+    assert_eq!(routes["example.a"], account0_id);
+    assert_eq!(routes["example.b"], account0_id);
+    assert_eq!(routes["example.c"], account1_id);
+    assert_eq!(routes.len(), 3);
+}
+
+#[tokio::test]
+async fn static_routes_override_others() {
+    let (store, accs) = test_store().await.unwrap();
+    store
+        .set_static_routes(vec![
+            ("example.a".to_string(), accs[0].id()),
+            ("example.b".to_string(), accs[0].id()),
+        ])
+        .await
+        .unwrap();
+
+    let account1_id = Uuid::new_v4();
+    let account1 = Account::try_from(
+        account1_id,
+        ACCOUNT_DETAILS_1.clone(),
+        store.get_ilp_address(),
+    )
+    .unwrap();
+    store
+        .clone()
+        .set_routes(vec![
+            ("example.a".to_string(), account1.clone()),
+            ("example.b".to_string(), account1.clone()),
+            ("example.c".to_string(), account1),
+        ])
+        .await
+        .unwrap();
+
+    let routes = store.routing_table();
+    assert_eq!(routes["example.a"], accs[0].id());
+    assert_eq!(routes["example.b"], accs[0].id());
+    assert_eq!(routes["example.c"], account1_id);
+    assert_eq!(routes.len(), 3);
+}
+
+#[tokio::test]
+async fn static_routes_must_point_to_existing_accounts() {
+    let (store, accs) = test_store().await.unwrap();
+    let err = store
+        .set_static_routes(vec![
+            ("example.a".to_string(), accs[0].id()),
+            ("example.b".to_string(), Uuid::new_v4()),
+        ])
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "not all of the given accounts exist");
+    assert!(store.routing_table().get("example.a").is_none());
+}
+
+#[tokio::test]
+async fn default_route() {
+    let (store, accs) = test_store().await.unwrap();
+    store.set_default_route(accs[0].id()).await.unwrap();
+    let account1_id = Uuid::new_v4();
+    let account1 = Account::try_from(
+        account1_id,
+        ACCOUNT_DETAILS_1.clone(),
+        store.get_ilp_address(),
+    )
+    .unwrap();
+    store
+        .clone()
+        .set_routes(vec![
+            ("example.a".to_string(), account1.clone()),
+            ("example.b".to_string(), account1.clone()),
+        ])
+        .await
+        .unwrap();
+
+    let routes = store.routing_table();
+    assert_eq!(routes[""], accs[0].id());
+    assert_eq!(routes["example.a"], account1_id);
+    assert_eq!(routes["example.b"], account1_id);
+    assert_eq!(routes.len(), 3);
+}
+
+#[tokio::test]
+async fn returns_configured_routes_for_route_manager() {
+    let (store, accs) = test_store().await.unwrap();
+    store
+        .set_static_routes(vec![
+            ("example.a".to_string(), accs[0].id()),
+            ("example.b".to_string(), accs[1].id()),
+        ])
+        .await
+        .unwrap();
+    let (_, configured) = store.get_local_and_configured_routes().await.unwrap();
+    assert_eq!(configured.len(), 2);
+    assert_eq!(configured["example.a"].id(), accs[0].id());
+    assert_eq!(configured["example.b"].id(), accs[1].id());
+}
+
+#[tokio::test]
+async fn drains_accounts() {
+    let (store, accs) = test_store().await.unwrap();
+    assert!(store.draining_accounts().is_empty());
+
+    store
+        .set_account_draining(accs[0].id(), true)
+        .await
+        .unwrap();
+    assert!(store.draining_accounts().contains(&accs[0].id()));
+    // draining accounts are kept in the routing table
+    assert_eq!(store.routing_table()["example.alice"], accs[0].id());
+
+    store
+        .set_account_draining(accs[0].id(), false)
+        .await
+        .unwrap();
+    assert!(store.draining_accounts().is_empty());
+
+    assert!(store
+        .set_account_draining(Uuid::new_v4(), true)
+        .await
+        .is_err());
+}
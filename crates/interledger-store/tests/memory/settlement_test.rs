@@ -0,0 +1,306 @@
+use super::{fixtures::*, store_helpers::*};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+
+use http::StatusCode;
+use interledger_api::NodeStore;
+use interledger_rates::ExchangeRateStore;
+use interledger_service::{Account, AccountStore};
+use interledger_service_util::{
+    BalanceStore, IncomingSettlementConverter, SettlementConversion, SettlementDirection,
+};
+use interledger_settlement::core::{
+    idempotency::{IdempotentData, IdempotentStore},
+    types::{LeftoversStore, SettlementAccount, SettlementAsset, SettlementStore},
+};
+use num_bigint::BigUint;
+use once_cell::sync::Lazy;
+use url::Url;
+use uuid::Uuid;
+
+static IDEMPOTENCY_KEY: Lazy<String> = Lazy::new(|| String::from("AJKJNUjM0oyiAN46"));
+
+#[tokio::test]
+async fn saves_gets_clears_uncredited_settlement_amount_properly() {
+    let (store, _accs) = test_store().await.unwrap();
+    let amounts: Vec<(BigUint, u8)> = vec![
+        (BigUint::from(5u32), 11),   // 5
+        (BigUint::from(855u32), 12), // 905
+        (BigUint::from(1u32), 10),   // 1005 total
+    ];
+    let acc = Uuid::new_v4();
+    for a in amounts {
+        store
+            .save_uncredited_settlement_amount(acc, a)
+            .await
+            .unwrap();
+    }
+    let ret = store
+        .load_uncredited_settlement_amount(acc, 9u8)
+        .await
+        .unwrap();
+    // 1 uncredited unit for scale 9
+    assert_eq!(ret, BigUint::from(1u32));
+    // rest should be in the leftovers store
+    let ret = store.get_uncredited_settlement_amount(acc).await.unwrap();
+    assert_eq!(ret, (BigUint::from(5u32), 12));
+
+    // clears uncredited amount
+    store.clear_uncredited_settlement_amount(acc).await.unwrap();
+    let ret = store.get_uncredited_settlement_amount(acc).await.unwrap();
+    assert_eq!(ret, (BigUint::from(0u32), 0));
+}
+
+#[tokio::test]
+async fn saves_and_loads_idempotency_key_data_properly() {
+    let (store, _) = test_store().await.unwrap();
+    let input_hash: [u8; 32] = Default::default();
+    store
+        .save_idempotent_data(
+            IDEMPOTENCY_KEY.clone(),
+            input_hash,
+            StatusCode::OK,
+            Bytes::from("TEST"),
+        )
+        .await
+        .unwrap();
+    let data1 = store
+        .load_idempotent_data(IDEMPOTENCY_KEY.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        data1.unwrap(),
+        IdempotentData::new(StatusCode::OK, Bytes::from("TEST"), input_hash)
+    );
+
+    let data2 = store
+        .load_idempotent_data("asdf".to_string())
+        .await
+        .unwrap();
+    assert!(data2.is_none());
+}
+
+#[tokio::test]
+async fn idempotent_settlement_calls() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    store
+        .update_balance_for_incoming_settlement(id, 100, Some(IDEMPOTENCY_KEY.clone()))
+        .await
+        .unwrap();
+    let balance = store.get_balance(id).await.unwrap();
+    assert_eq!(balance, 100);
+
+    store
+        .update_balance_for_incoming_settlement(
+            id,
+            100,
+            Some(IDEMPOTENCY_KEY.clone()), // Reuse key to make idempotent request.
+        )
+        .await
+        .unwrap();
+    let balance = store.get_balance(id).await.unwrap();
+    // Since it's idempotent there will be no state update.
+    // Otherwise it'd be 200 (100 + 100)
+    assert_eq!(balance, 100);
+}
+
+#[tokio::test]
+async fn settlements_clear_balance_owed_before_prepaying() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    store
+        .update_balances_for_prepare(id, 200, None)
+        .await
+        .unwrap();
+
+    // part of the balance owed is cleared
+    store
+        .update_balance_for_incoming_settlement(id, 100, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), -100);
+
+    // the rest of the settlement is credited as prepaid amount, which is
+    // used before the balance on the next prepare
+    store
+        .update_balance_for_incoming_settlement(id, 160, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), 60);
+    store
+        .update_balances_for_prepare(id, 100, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), -40);
+}
+
+#[tokio::test]
+async fn refunds_settlements() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[1].id();
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(id, 0, None)
+        .await
+        .unwrap();
+    assert_eq!(balance, -1000);
+    assert_eq!(amount_to_settle, 1000);
+
+    store.refund_settlement(id, amount_to_settle).await.unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn records_last_settlement() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[1].id();
+    assert_eq!(store.get_last_settlement(id).await.unwrap(), None);
+
+    store
+        .update_balance_for_incoming_settlement(id, 100, None)
+        .await
+        .unwrap();
+    let last_settlement = store.get_last_settlement(id).await.unwrap().unwrap();
+    assert_eq!(last_settlement.amount, 100);
+    let settled_at = DateTime::parse_from_rfc3339(&last_settlement.timestamp).unwrap();
+    assert!(Utc::now().signed_duration_since(settled_at) < chrono::Duration::seconds(5));
+
+    // outgoing settlements reduce the balance, so their amount is negative
+    let (_, amount_to_settle) = store
+        .update_balances_for_fulfill(id, 0, None)
+        .await
+        .unwrap();
+    assert_eq!(amount_to_settle, 1000);
+    let last_settlement = store.get_last_settlement(id).await.unwrap().unwrap();
+    assert_eq!(last_settlement.amount, -1000);
+
+    // refunded settlements are removed
+    store.refund_settlement(id, amount_to_settle).await.unwrap();
+    assert_eq!(store.get_last_settlement(id).await.unwrap(), None);
+}
+
+#[allow(clippy::float_cmp)]
+#[tokio::test]
+async fn saves_settlement_asset_and_conversions() {
+    let (store, _accs) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.settlement_asset_code = Some("usdc".to_string());
+    let account = store.insert_account(details).await.unwrap();
+    let id = account.id();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    // The settlement asset defaults to the account's asset scale
+    assert_eq!(
+        account.settlement_asset(),
+        Some(SettlementAsset {
+            asset_code: "USDC".to_string(),
+            asset_scale: 9,
+        })
+    );
+
+    assert_eq!(
+        store.get_last_settlement_conversion(id).await.unwrap(),
+        None
+    );
+    let conversion = SettlementConversion {
+        direction: SettlementDirection::Incoming,
+        amount: 1_500_000_000,
+        settlement_amount: 3_000_000_000,
+        settlement_asset_code: "USDC".to_string(),
+        settlement_asset_scale: 9,
+        rate: 2.0,
+        rate_provenance: None,
+        timestamp: Utc::now().to_rfc3339(),
+    };
+    store
+        .save_settlement_conversion(id, conversion.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_last_settlement_conversion(id).await.unwrap(),
+        Some(conversion)
+    );
+
+    store.delete_account(id).await.unwrap();
+    assert_eq!(
+        store.get_last_settlement_conversion(id).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn converts_incoming_settlements_from_settlement_asset() {
+    let (store, _accs) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.settlement_asset_code = Some("USDC".to_string());
+    let id = store.insert_account(details).await.unwrap().id();
+    let mut rates = std::collections::HashMap::new();
+    rates.insert("XRP".to_string(), 2.0);
+    rates.insert("USDC".to_string(), 1.0);
+    store.set_exchange_rates(rates).unwrap();
+
+    // 3 USDC are credited as 1.5 XRP
+    let converter = IncomingSettlementConverter::new(store.clone());
+    converter
+        .update_balance_for_incoming_settlement(id, 3_000_000_000, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), 1_500_000_000);
+    let conversion = store
+        .get_last_settlement_conversion(id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(conversion.direction, SettlementDirection::Incoming);
+    assert_eq!(conversion.amount, 1_500_000_000);
+    assert_eq!(conversion.settlement_amount, 3_000_000_000);
+
+    // without a rate nothing is credited
+    store.set_exchange_rates(Default::default()).unwrap();
+    assert!(converter
+        .update_balance_for_incoming_settlement(id, 3_000_000_000, None)
+        .await
+        .is_err());
+    assert_eq!(store.get_balance(id).await.unwrap(), 1_500_000_000);
+}
+
+#[tokio::test]
+async fn loads_globally_configured_settlement_engine_url() {
+    let (store, accs) = test_store().await.unwrap();
+    assert!(accs[0].settlement_engine_details().is_some());
+    assert!(accs[1].settlement_engine_details().is_none());
+    let account_ids = vec![accs[0].id(), accs[1].id()];
+
+    store
+        .set_settlement_engines(vec![
+            (
+                "ABC".to_string(),
+                Url::parse("http://settle-abc.example").unwrap(),
+            ),
+            (
+                "XYZ".to_string(),
+                Url::parse("http://settle-xyz.example").unwrap(),
+            ),
+        ])
+        .await
+        .unwrap();
+    let accounts = store.get_accounts(account_ids).await.unwrap();
+    // It should not overwrite the one that was individually configured
+    assert_eq!(
+        accounts[0]
+            .settlement_engine_details()
+            .unwrap()
+            .url
+            .as_str(),
+        "http://settlement.example/"
+    );
+
+    // It should set the URL for the account that did not have one configured
+    assert_eq!(
+        accounts[1]
+            .settlement_engine_details()
+            .unwrap()
+            .url
+            .as_str(),
+        "http://settle-abc.example/"
+    );
+}
@@ -6,7 +6,7 @@ use interledger_http::HttpAccount;
 use interledger_packet::Address;
 use interledger_service::Account as AccountTrait;
 use interledger_service::{AccountStore, AddressStore, Username};
-use interledger_service_util::BalanceStore;
+use interledger_service_util::{BalanceStore, DestinationAllowlistAccount, MaxPacketAmountAccount};
 use interledger_store::redis::RedisStoreBuilder;
 use redis_crate::Client;
 use secrecy::ExposeSecret;
@@ -64,6 +64,77 @@ async fn insert_accounts() {
     assert_eq!(err.to_string(), "account `charlie` already exists");
 }
 
+#[tokio::test]
+async fn inserts_batches_of_accounts() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let mut invalid = ACCOUNT_DETAILS_2.clone();
+    invalid.username = Username::from_str("dave").unwrap();
+    invalid.ilp_over_http_url = Some("asdf".to_owned());
+    let results = store
+        .insert_accounts(vec![
+            ACCOUNT_DETAILS_2.clone(),
+            ACCOUNT_DETAILS_0.clone(),
+            invalid,
+            ACCOUNT_DETAILS_2.clone(),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 4);
+
+    let charlie = results[0].as_ref().unwrap();
+    let id = store
+        .get_account_id_from_username(&Username::from_str("charlie").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(charlie.id(), id);
+    assert_eq!(
+        results[1].as_ref().unwrap_err().to_string(),
+        "account `alice` already exists"
+    );
+    assert_eq!(
+        results[2].as_ref().unwrap_err().to_string(),
+        "invalid account: the provided http url is not valid: relative URL without a base"
+    );
+    // Accounts may not conflict with the earlier ones in the same batch either
+    assert_eq!(
+        results[3].as_ref().unwrap_err().to_string(),
+        "account `charlie` already exists"
+    );
+    assert!(store
+        .get_account_id_from_username(&Username::from_str("dave").unwrap())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn saves_allowed_destination_prefixes() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    let prefixes = vec![
+        Address::from_str("example.merchant").unwrap(),
+        Address::from_str("example.other").unwrap(),
+    ];
+    details.allowed_destination_prefixes = prefixes.clone();
+    let account = store.insert_account(details).await.unwrap();
+    let accounts = store.get_accounts(vec![account.id()]).await.unwrap();
+    assert_eq!(accounts[0].allowed_destination_prefixes(), &prefixes[..]);
+}
+
+#[tokio::test]
+async fn saves_min_packet_amount() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.min_packet_amount = Some(10);
+    let account = store.insert_account(details).await.unwrap();
+    let accounts = store
+        .get_accounts(vec![account.id(), accs[0].id()])
+        .await
+        .unwrap();
+    assert_eq!(accounts[0].min_packet_amount(), 10);
+    // Accounts without a minimum accept packets of any amount
+    assert_eq!(accounts[1].min_packet_amount(), 0);
+}
+
 #[tokio::test]
 async fn cannot_insert_invalid_accounts() {
     let (store, _context, _) = test_store().await.unwrap();
@@ -344,3 +415,12 @@ async fn errors_for_unknown_accounts() {
         .unwrap_err();
     assert_eq!(err.to_string(), "wrong account length (expected 2, got 0)");
 }
+
+#[tokio::test]
+async fn checks_health() {
+    let (store, mut context, _accs) = test_store().await.unwrap();
+    assert!(store.check_health().await.is_ok());
+
+    context.stop_server();
+    assert!(store.check_health().await.is_err());
+}
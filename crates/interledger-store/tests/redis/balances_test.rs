@@ -26,6 +26,20 @@ async fn get_balance() {
     assert_eq!(balance, 1000);
 }
 
+#[tokio::test]
+async fn get_balances() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    store
+        .update_balances_for_prepare(accs[0].id(), 100, None)
+        .await
+        .unwrap();
+    let balances = store
+        .get_balances(vec![accs[1].id(), accs[0].id()])
+        .await
+        .unwrap();
+    assert_eq!(balances, vec![0, -100]);
+}
+
 #[tokio::test]
 async fn update_balances_for_fulfill_tests() {
     let (store, context, _accs) = test_store().await.unwrap();
@@ -144,7 +158,7 @@ async fn update_balances_for_fulfill_tests() {
             .unwrap();
 
         let (balance_after, settle_amount) = store
-            .update_balances_for_fulfill(id, t.amount)
+            .update_balances_for_fulfill(id, t.amount, None)
             .await
             .unwrap();
 
@@ -172,7 +186,7 @@ async fn prepare_then_fulfill_with_settlement() {
     let account1_id = accounts[1].id();
     // reduce account 0's balance by 100
     store
-        .update_balances_for_prepare(account0_id, 100)
+        .update_balances_for_prepare(account0_id, 100, None)
         .await
         .unwrap();
     let balance0 = store.get_balance(account0_id).await.unwrap();
@@ -181,7 +195,7 @@ async fn prepare_then_fulfill_with_settlement() {
     assert_eq!(balance1, 0);
 
     store
-        .update_balances_for_fulfill(account1_id, 100)
+        .update_balances_for_fulfill(account1_id, 100, None)
         .await
         .unwrap();
     let balance0 = store.get_balance(account0_id).await.unwrap();
@@ -191,12 +205,12 @@ async fn prepare_then_fulfill_with_settlement() {
 
     drop(_context);
     let err = store
-        .update_balances_for_prepare(account1_id, 1)
+        .update_balances_for_prepare(account1_id, 1, None)
         .await
         .unwrap_err();
     assert_eq!(err.to_string(), "Broken pipe (os error 32)");
     let err = store
-        .update_balances_for_fulfill(account1_id, 1)
+        .update_balances_for_fulfill(account1_id, 1, None)
         .await
         .unwrap_err();
     // os error 32 only appears the first time
@@ -219,7 +233,10 @@ async fn process_fulfill_no_settle_to() {
     let (store, _context, _accs) = test_store().await.unwrap();
     let account = store.insert_account(acc).await.unwrap();
     let id = account.id();
-    let (balance, amount_to_settle) = store.update_balances_for_fulfill(id, 100).await.unwrap();
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(id, 100, None)
+        .await
+        .unwrap();
     assert_eq!(balance, 100);
     assert_eq!(amount_to_settle, 0);
 }
@@ -241,7 +258,10 @@ async fn process_fulfill_settle_to_over_threshold() {
     let (store, _context, _accs) = test_store().await.unwrap();
     let acc = store.insert_account(acc).await.unwrap();
     let id = acc.id();
-    let (balance, amount_to_settle) = store.update_balances_for_fulfill(id, 1000).await.unwrap();
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(id, 1000, None)
+        .await
+        .unwrap();
     assert_eq!(balance, 1000);
     assert_eq!(amount_to_settle, 0);
 }
@@ -263,7 +283,10 @@ async fn process_fulfill_ok() {
     let (store, _context, _accs) = test_store().await.unwrap();
     let account = store.insert_account(acc).await.unwrap();
     let id = account.id();
-    let (balance, amount_to_settle) = store.update_balances_for_fulfill(id, 101).await.unwrap();
+    let (balance, amount_to_settle) = store
+        .update_balances_for_fulfill(id, 101, None)
+        .await
+        .unwrap();
     assert_eq!(balance, 0);
     assert_eq!(amount_to_settle, 101);
 }
@@ -273,12 +296,18 @@ async fn prepare_then_reject() {
     let (store, _context, accs) = test_store().await.unwrap();
     let acc0 = accs[0].id();
     let acc1 = accs[1].id();
-    store.update_balances_for_prepare(acc0, 100).await.unwrap();
+    store
+        .update_balances_for_prepare(acc0, 100, None)
+        .await
+        .unwrap();
     let balance0 = store.get_balance(acc0).await.unwrap();
     let balance1 = store.get_balance(acc1).await.unwrap();
     assert_eq!(balance0, -100);
     assert_eq!(balance1, 0);
-    store.update_balances_for_reject(acc0, 100).await.unwrap();
+    store
+        .update_balances_for_reject(acc0, 100, None)
+        .await
+        .unwrap();
     let balance0 = store.get_balance(acc0).await.unwrap();
     let balance1 = store.get_balance(acc1).await.unwrap();
     assert_eq!(balance0, 0);
@@ -290,13 +319,37 @@ async fn enforces_minimum_balance() {
     let (store, _context, accs) = test_store().await.unwrap();
     let id = accs[0].id();
     let err = store
-        .update_balances_for_prepare(id, 10000)
+        .update_balances_for_prepare(id, 10000, None)
         .await
         .unwrap_err();
     let expected = format!("Incoming prepare of 10000 would bring account {} under its minimum balance. Current balance: 0, min balance: -1000", id);
     assert!(err.to_string().contains(&expected));
 }
 
+#[tokio::test]
+async fn allows_prepares_up_to_the_credit_line() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    // fits within the credit line of 1000
+    store
+        .update_balances_for_prepare(id, 600, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), -600);
+    // exactly hits the credit line
+    store
+        .update_balances_for_prepare(id, 400, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), -1000);
+    // exceeds the credit line, which must leave the balance untouched
+    store
+        .update_balances_for_prepare(id, 1, None)
+        .await
+        .unwrap_err();
+    assert_eq!(store.get_balance(id).await.unwrap(), -1000);
+}
+
 #[tokio::test]
 // Prepare and Fulfill a packet for 100 units from Account 0 to Account 1
 // Then, Prepare and Fulfill a packet for 80 units from Account 1 to Account 0
@@ -311,23 +364,23 @@ async fn netting_fulfilled_balances() {
 
     // decrement account 0 by 100
     store
-        .update_balances_for_prepare(account0, 100)
+        .update_balances_for_prepare(account0, 100, None)
         .await
         .unwrap();
     // increment account 1 by 100
     store
-        .update_balances_for_fulfill(account1, 100)
+        .update_balances_for_fulfill(account1, 100, None)
         .await
         .unwrap();
 
     // decrement account 1 by 80
     store
-        .update_balances_for_prepare(account1, 80)
+        .update_balances_for_prepare(account1, 80, None)
         .await
         .unwrap();
     // increment account 0 by 80
     store
-        .update_balances_for_fulfill(account0, 80)
+        .update_balances_for_fulfill(account0, 80, None)
         .await
         .unwrap();
 
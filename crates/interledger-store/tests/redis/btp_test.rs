@@ -2,7 +2,7 @@ use super::fixtures::*;
 
 use super::store_helpers::*;
 
-use interledger_api::NodeStore;
+use interledger_api::{AccountSettings, NodeStore};
 use interledger_btp::{BtpAccount, BtpStore};
 use interledger_http::HttpAccount;
 use interledger_packet::Address;
@@ -76,3 +76,54 @@ async fn duplicate_btp_incoming_auth_works() {
     assert_eq!(accs[0].id(), alice_id);
     assert_eq!(accs[1].id(), charlie_id);
 }
+
+#[tokio::test]
+async fn rotated_btp_token_replaces_old_one() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let bob = Username::from_str("bob").unwrap();
+    store
+        .modify_account_settings(
+            accs[1].id(),
+            AccountSettings {
+                ilp_over_btp_incoming_token: Some(SecretString::new("new_btp_token".to_string())),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    let account = store
+        .get_account_from_btp_auth(&bob, "new_btp_token")
+        .await
+        .unwrap();
+    assert_eq!(account.id(), accs[1].id());
+
+    let err = store
+        .get_account_from_btp_auth(&bob, "other_btp_token")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "account `bob` is not authorized for this action"
+    );
+}
+
+#[tokio::test]
+async fn stores_btp_incoming_token_hashed() {
+    let (store, context, accs) = test_store().await.unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+    let stored: Vec<u8> = redis_crate::cmd("HGET")
+        .arg(format!("accounts:{}", accs[1].id()))
+        .arg("ilp_over_btp_incoming_token")
+        .query_async(&mut connection)
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 32);
+    assert_ne!(&stored[..], b"other_btp_token");
+
+    // the hash is still what peers authenticate against
+    assert!(store
+        .get_account_from_btp_auth(&Username::from_str("bob").unwrap(), "other_btp_token")
+        .await
+        .is_ok());
+}
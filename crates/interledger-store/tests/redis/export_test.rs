@@ -0,0 +1,98 @@
+use super::{redis_helpers::*, store_helpers::*};
+use interledger_api::{NodeExport, NodeStore};
+use interledger_btp::{BtpAccount, BtpStore};
+use interledger_http::{HttpAccount, HttpStore};
+use interledger_packet::Address;
+use interledger_router::RouterStore;
+use interledger_service::{Account as AccountTrait, AccountStore, Username};
+use interledger_service_util::BalanceStore;
+use interledger_store::redis::{RedisStore, RedisStoreBuilder};
+use secrecy::ExposeSecret;
+use std::str::FromStr;
+
+async fn fresh_store(context: &TestContext, secret: [u8; 32]) -> RedisStore {
+    RedisStoreBuilder::new(context.get_client_connection_info(), secret)
+        .node_ilp_address(Address::from_str("example.node").unwrap())
+        .connect()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn exports_and_imports_accounts_balances_and_routes() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    store
+        .update_balances_for_prepare(accs[0].id(), 100, None)
+        .await
+        .unwrap();
+    store
+        .set_static_route("example.other".to_string(), accs[1].id())
+        .await
+        .unwrap();
+    store.set_default_route(accs[0].id()).await.unwrap();
+
+    // The export is transferred as JSON
+    let export = serde_json::to_string(&store.export_node().await.unwrap()).unwrap();
+    let export: NodeExport = serde_json::from_str(&export).unwrap();
+    assert_eq!(export.accounts.len(), 2);
+
+    let new_context = TestContext::new();
+    let new_store = fresh_store(&new_context, [0; 32]).await;
+    let imported = new_store.import_node(export.clone()).await.unwrap();
+    assert_eq!(imported.len(), 2);
+
+    let accounts = new_store
+        .get_accounts(vec![accs[0].id(), accs[1].id()])
+        .await
+        .unwrap();
+    for (account, original) in accounts.iter().zip(accs.iter()) {
+        assert_eq!(account.username(), original.username());
+        assert_eq!(account.ilp_address(), original.ilp_address());
+        assert_eq!(account.asset_code(), original.asset_code());
+        assert_eq!(account.asset_scale(), original.asset_scale());
+        assert_eq!(
+            account.get_http_auth_token().unwrap().expose_secret(),
+            original.get_http_auth_token().unwrap().expose_secret(),
+        );
+        assert_eq!(
+            account.get_ilp_over_btp_outgoing_token().unwrap(),
+            original.get_ilp_over_btp_outgoing_token().unwrap()
+        );
+    }
+    assert_eq!(new_store.get_balance(accs[0].id()).await.unwrap(), -100);
+    assert_eq!(new_store.get_balance(accs[1].id()).await.unwrap(), 0);
+
+    // The incoming tokens are still accepted
+    new_store
+        .get_account_from_http_auth(&Username::from_str("alice").unwrap(), "incoming_auth_token")
+        .await
+        .unwrap();
+    new_store
+        .get_account_from_btp_auth(&Username::from_str("bob").unwrap(), "other_btp_token")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        new_store.get_static_routes().await.unwrap(),
+        store.get_static_routes().await.unwrap()
+    );
+    assert_eq!(
+        new_store.routing_table().get("example.other").unwrap(),
+        &accs[1].id()
+    );
+
+    // The accounts exist now, so they cannot be imported again
+    let err = new_store.import_node(export).await.unwrap_err();
+    assert!(err.to_string().ends_with("already exists"));
+}
+
+#[tokio::test]
+async fn cannot_import_secrets_encrypted_with_another_secret() {
+    let (store, _context, _accs) = test_store().await.unwrap();
+    let export = store.export_node().await.unwrap();
+
+    let new_context = TestContext::new();
+    let new_store = fresh_store(&new_context, [1; 32]).await;
+    assert!(new_store.import_node(export).await.is_err());
+    assert!(new_store.get_all_accounts().await.unwrap().is_empty());
+}
@@ -0,0 +1,18 @@
+use super::store_helpers::*;
+use interledger_service_util::FulfilledConditionStore;
+use std::time::Duration;
+
+#[tokio::test]
+async fn remembers_fulfilled_conditions_within_window() {
+    let (store, _context, _accs) = test_store().await.unwrap();
+    assert!(!store.was_condition_fulfilled([1; 32]).await.unwrap());
+    store
+        .save_fulfilled_condition([1; 32], Duration::from_millis(100))
+        .await
+        .unwrap();
+    assert!(store.was_condition_fulfilled([1; 32]).await.unwrap());
+    assert!(!store.was_condition_fulfilled([2; 32]).await.unwrap());
+
+    tokio::time::delay_for(Duration::from_millis(150)).await;
+    assert!(!store.was_condition_fulfilled([1; 32]).await.unwrap());
+}
@@ -1,13 +1,15 @@
 use super::fixtures::*;
 use super::store_helpers::*;
 
-use interledger_api::NodeStore;
-use interledger_btp::BtpAccount;
+use interledger_api::{AccountSettings, NodeStore};
+use interledger_btp::{BtpAccount, BtpStore};
 use interledger_http::{HttpAccount, HttpStore};
 use interledger_packet::Address;
 use interledger_service::{Account, Username};
+use interledger_store::redis::RedisStoreBuilder;
 use secrecy::{ExposeSecret, SecretString};
 use std::str::FromStr;
+use std::time::Duration;
 
 #[tokio::test]
 async fn gets_account_from_http_bearer_token() {
@@ -84,3 +86,169 @@ async fn duplicate_http_incoming_auth_works() {
     assert_eq!(accs[0].id(), original_id);
     assert_eq!(accs[1].id(), duplicate_id);
 }
+
+#[tokio::test]
+async fn gets_account_from_client_certificate() {
+    let fingerprint = "ab".repeat(32);
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.ilp_over_http_client_certificates = vec![fingerprint.to_uppercase()];
+    let (store, _context, _) = test_store().await.unwrap();
+    let charlie = store.insert_account(details).await.unwrap();
+
+    let account = store
+        .get_account_from_client_certificate(&Username::from_str("charlie").unwrap(), &fingerprint)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.id(), charlie.id());
+    assert_eq!(account.get_http_client_certificates(), &[fingerprint][..]);
+
+    let err = store
+        .get_account_from_client_certificate(
+            &Username::from_str("charlie").unwrap(),
+            &"cd".repeat(32),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "account `charlie` is not authorized for this action"
+    );
+
+    // accounts without certificates are authenticated with their token instead
+    assert!(store
+        .get_account_from_client_certificate(
+            &Username::from_str("alice").unwrap(),
+            &"ab".repeat(32)
+        )
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn reuses_cached_http_auth_within_ttl() {
+    let (store, context, _) = test_store_with_http_auth_cache(Duration::from_millis(500))
+        .await
+        .unwrap();
+    let alice = Username::from_str("alice").unwrap();
+    store
+        .get_account_from_http_auth(&alice, "incoming_auth_token")
+        .await
+        .unwrap();
+
+    // the cached account is used without looking up alice in Redis
+    let mut connection = context.async_connection().await.unwrap();
+    let _: () = redis_crate::cmd("HDEL")
+        .arg("usernames")
+        .arg("alice")
+        .query_async(&mut connection)
+        .await
+        .unwrap();
+    let account = store
+        .get_account_from_http_auth(&alice, "incoming_auth_token")
+        .await
+        .unwrap();
+    assert_eq!(account.username(), &alice);
+
+    // other tokens are still checked against Redis
+    assert!(store
+        .get_account_from_http_auth(&alice, "unknown_token")
+        .await
+        .is_err());
+
+    tokio::time::delay_for(Duration::from_millis(600)).await;
+    let err = store
+        .get_account_from_http_auth(&alice, "incoming_auth_token")
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "account `alice` was not found");
+}
+
+#[tokio::test]
+async fn rotated_http_token_invalidates_cached_auth() {
+    let (store, context, accs) = test_store_with_http_auth_cache(Duration::from_secs(60))
+        .await
+        .unwrap();
+    // another store using the same database, e.g. another ILP over HTTP frontend
+    let mut builder = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32]);
+    builder.http_auth_cache_ttl(Duration::from_secs(60));
+    let other_store = builder.connect().await.unwrap();
+
+    let alice = Username::from_str("alice").unwrap();
+    for store in &[&store, &other_store] {
+        store
+            .get_account_from_http_auth(&alice, "incoming_auth_token")
+            .await
+            .unwrap();
+    }
+
+    store
+        .modify_account_settings(
+            accs[0].id(),
+            AccountSettings {
+                ilp_over_http_incoming_token: Some(SecretString::new("new_http_token".to_string())),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    // give the other store time to receive the invalidation
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+
+    for store in &[&store, &other_store] {
+        let err = store
+            .get_account_from_http_auth(&alice, "incoming_auth_token")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "account `alice` is not authorized for this action"
+        );
+        let account = store
+            .get_account_from_http_auth(&alice, "new_http_token")
+            .await
+            .unwrap();
+        assert_eq!(account.id(), accs[0].id());
+    }
+}
+
+#[tokio::test]
+async fn disabled_account_cannot_authenticate() {
+    let (store, _context, accs) = test_store_with_http_auth_cache(Duration::from_secs(60))
+        .await
+        .unwrap();
+    let alice = Username::from_str("alice").unwrap();
+    store
+        .get_account_from_http_auth(&alice, "incoming_auth_token")
+        .await
+        .unwrap();
+
+    // the cached auth is not used for disabled accounts
+    store
+        .set_account_enabled(accs[0].id(), false)
+        .await
+        .unwrap();
+    let err = store
+        .get_account_from_http_auth(&alice, "incoming_auth_token")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "account `alice` is not authorized for this action"
+    );
+    assert!(store
+        .get_account_from_btp_auth(&alice, "btp_token")
+        .await
+        .is_err());
+
+    store.set_account_enabled(accs[0].id(), true).await.unwrap();
+    assert!(store
+        .get_account_from_http_auth(&alice, "incoming_auth_token")
+        .await
+        .is_ok());
+    assert!(store
+        .get_account_from_btp_auth(&alice, "btp_token")
+        .await
+        .is_ok());
+}
@@ -0,0 +1,77 @@
+use super::{redis_helpers::*, store_helpers::*};
+use interledger_service::Account as AccountTrait;
+use interledger_service_util::BalanceStore;
+use interledger_store::redis::RedisStoreBuilder;
+use std::time::Duration;
+
+#[tokio::test]
+async fn reconnects_and_retries_when_connection_is_dropped() {
+    let (store, context, accs) = test_store().await.unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+    // Drops the store's connection, like Redis does when it fails over
+    let killed: u64 = redis_crate::cmd("CLIENT")
+        .arg("KILL")
+        .arg("TYPE")
+        .arg("normal")
+        .query_async(&mut connection)
+        .await
+        .unwrap();
+    assert!(killed > 0);
+
+    // The request is retried over a new connection instead of failing
+    let balance = store.get_balance(accs[0].id()).await.unwrap();
+    assert_eq!(balance, 0);
+    assert!(store.connection_status().is_connected());
+    store
+        .update_balances_for_prepare(accs[0].id(), 100, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(accs[0].id()).await.unwrap(), -100);
+}
+
+#[tokio::test]
+async fn does_not_repeat_balance_updates_when_connection_is_dropped() {
+    let (store, context, accs) = test_store().await.unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+    let _: u64 = redis_crate::cmd("CLIENT")
+        .arg("KILL")
+        .arg("TYPE")
+        .arg("normal")
+        .query_async(&mut connection)
+        .await
+        .unwrap();
+
+    // An update sent when the connection dropped may have been executed already, so it
+    // fails instead of being sent again (unless the store already reconnected before)
+    let first_update = store
+        .update_balances_for_prepare(accs[0].id(), 100, None)
+        .await;
+    let balance = if first_update.is_ok() { -100 } else { 0 };
+    assert_eq!(store.get_balance(accs[0].id()).await.unwrap(), balance);
+
+    // The following updates are sent once over the new connection
+    store
+        .update_balances_for_prepare(accs[0].id(), 100, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_balance(accs[0].id()).await.unwrap(),
+        balance - 100
+    );
+    assert!(store.connection_status().is_connected());
+}
+
+#[tokio::test]
+async fn gives_up_after_retry_timeout() {
+    let mut context = TestContext::new();
+    let store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .retry_timeout(Duration::from_millis(100))
+        .connect()
+        .await
+        .unwrap();
+    assert!(store.connection_status().is_connected());
+
+    context.stop_server();
+    assert!(store.get_balance(uuid::Uuid::new_v4()).await.is_err());
+    assert!(!store.connection_status().is_connected());
+}
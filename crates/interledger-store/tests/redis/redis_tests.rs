@@ -1,87 +1,20 @@
 mod accounts_test;
 mod balances_test;
 mod btp_test;
+mod events_test;
+mod export_test;
+mod fulfilled_conditions_test;
 mod http_test;
 mod rate_limiting_test;
 mod rates_test;
+mod reconnect_test;
 mod routing_test;
 mod settlement_test;
+mod traffic_test;
+mod volume_test;
 
-mod fixtures {
-
-    use interledger_api::AccountDetails;
-    use interledger_packet::Address;
-    use interledger_service::Username;
-    use once_cell::sync::Lazy;
-    use secrecy::SecretString;
-    use std::str::FromStr;
-
-    // We are dylan starting a connection with all these accounts
-    pub static ACCOUNT_DETAILS_0: Lazy<AccountDetails> = Lazy::new(|| AccountDetails {
-        ilp_address: Some(Address::from_str("example.alice").unwrap()),
-        username: Username::from_str("alice").unwrap(),
-        asset_scale: 6,
-        asset_code: "XYZ".to_string(),
-        max_packet_amount: 1000,
-        min_balance: Some(-1000),
-        ilp_over_http_url: Some("http://example.com/accounts/dylan/ilp".to_string()),
-        ilp_over_http_incoming_token: Some(SecretString::new("incoming_auth_token".to_string())),
-        ilp_over_http_outgoing_token: Some(SecretString::new("outgoing_auth_token".to_string())),
-        ilp_over_btp_url: Some("btp+ws://example.com/accounts/dylan/ilp/btp".to_string()),
-        ilp_over_btp_incoming_token: Some(SecretString::new("btp_token".to_string())),
-        ilp_over_btp_outgoing_token: Some(SecretString::new("btp_token".to_string())),
-        settle_threshold: Some(0),
-        settle_to: Some(-1000),
-        routing_relation: Some("Parent".to_owned()),
-        round_trip_time: None,
-        amount_per_minute_limit: Some(1000),
-        packets_per_minute_limit: Some(2),
-        settlement_engine_url: Some("http://settlement.example".to_string()),
-    });
-    pub static ACCOUNT_DETAILS_1: Lazy<AccountDetails> = Lazy::new(|| AccountDetails {
-        ilp_address: None,
-        username: Username::from_str("bob").unwrap(),
-        asset_scale: 9,
-        asset_code: "ABC".to_string(),
-        max_packet_amount: 1_000_000,
-        min_balance: Some(0),
-        ilp_over_http_url: Some("http://example.com/accounts/dylan/ilp".to_string()),
-        // incoming token has is the account's username concatenated wiht the password
-        ilp_over_http_incoming_token: Some(SecretString::new("incoming_auth_token".to_string())),
-        ilp_over_http_outgoing_token: Some(SecretString::new("outgoing_auth_token".to_string())),
-        ilp_over_btp_url: Some("btp+ws://example.com/accounts/dylan/ilp/btp".to_string()),
-        ilp_over_btp_incoming_token: Some(SecretString::new("other_btp_token".to_string())),
-        ilp_over_btp_outgoing_token: Some(SecretString::new("btp_token".to_string())),
-        settle_threshold: Some(0),
-        settle_to: Some(-1000),
-        routing_relation: Some("Child".to_owned()),
-        round_trip_time: None,
-        amount_per_minute_limit: Some(1000),
-        packets_per_minute_limit: Some(20),
-        settlement_engine_url: None,
-    });
-    pub static ACCOUNT_DETAILS_2: Lazy<AccountDetails> = Lazy::new(|| AccountDetails {
-        ilp_address: None,
-        username: Username::from_str("charlie").unwrap(),
-        asset_scale: 9,
-        asset_code: "XRP".to_string(),
-        max_packet_amount: 1000,
-        min_balance: Some(0),
-        ilp_over_http_url: None,
-        ilp_over_http_incoming_token: None,
-        ilp_over_http_outgoing_token: None,
-        ilp_over_btp_url: None,
-        ilp_over_btp_incoming_token: None,
-        ilp_over_btp_outgoing_token: None,
-        settle_threshold: Some(0),
-        settle_to: None,
-        routing_relation: None,
-        round_trip_time: None,
-        amount_per_minute_limit: None,
-        packets_per_minute_limit: None,
-        settlement_engine_url: None,
-    });
-}
+#[path = "../common/fixtures.rs"]
+mod fixtures;
 
 mod redis_helpers {
     // Copied from https://github.com/mitsuhiko/redis-rs/blob/9a1777e8a90c82c315a481cdf66beb7d69e681a2/tests/support/mod.rs
@@ -275,17 +208,44 @@ mod store_helpers {
     use interledger_service::{Account as AccountTrait, AddressStore};
     use interledger_store::{
         account::Account,
+        events::BalanceEventSink,
         redis::{RedisStore, RedisStoreBuilder},
     };
     use std::str::FromStr;
+    use std::time::Duration;
 
     pub async fn test_store() -> Result<(RedisStore, TestContext, Vec<Account>), ()> {
+        test_store_with_balance_events(None).await
+    }
+
+    pub async fn test_store_with_balance_events(
+        sink: Option<BalanceEventSink>,
+    ) -> Result<(RedisStore, TestContext, Vec<Account>), ()> {
         let context = TestContext::new();
-        let store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        let mut builder = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32]);
+        builder.node_ilp_address(Address::from_str("example.node").unwrap());
+        if let Some(sink) = sink {
+            builder.balance_events(sink);
+        }
+        let store = builder.connect().await.unwrap();
+        let accs = insert_test_accounts(&store).await;
+        Ok((store, context, accs))
+    }
+
+    pub async fn test_store_with_http_auth_cache(
+        ttl: Duration,
+    ) -> Result<(RedisStore, TestContext, Vec<Account>), ()> {
+        let context = TestContext::new();
+        let mut builder = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32]);
+        builder
             .node_ilp_address(Address::from_str("example.node").unwrap())
-            .connect()
-            .await
-            .unwrap();
+            .http_auth_cache_ttl(ttl);
+        let store = builder.connect().await.unwrap();
+        let accs = insert_test_accounts(&store).await;
+        Ok((store, context, accs))
+    }
+
+    async fn insert_test_accounts(store: &RedisStore) -> Vec<Account> {
         let mut accs = Vec::new();
         let acc = store
             .insert_account(ACCOUNT_DETAILS_0.clone())
@@ -305,6 +265,6 @@ mod store_helpers {
             .await
             .unwrap();
         accs.push(acc);
-        Ok((store, context, accs))
+        accs
     }
 }
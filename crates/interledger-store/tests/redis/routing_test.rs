@@ -38,10 +38,14 @@ async fn polls_for_route_updates() {
             asset_scale: 6,
             asset_code: "XYZ".to_string(),
             max_packet_amount: 1000,
+            min_packet_amount: None,
             min_balance: Some(-1000),
             ilp_over_http_url: None,
             ilp_over_http_incoming_token: None,
             ilp_over_http_outgoing_token: None,
+            ilp_over_http_client_certificates: Vec::new(),
+            ilp_over_http_signature_header: None,
+            ilp_over_http_signature_secret: None,
             ilp_over_btp_url: None,
             ilp_over_btp_outgoing_token: None,
             ilp_over_btp_incoming_token: None,
@@ -50,8 +54,21 @@ async fn polls_for_route_updates() {
             routing_relation: Some("Peer".to_owned()),
             round_trip_time: None,
             amount_per_minute_limit: None,
+            spread: None,
+            rounding: None,
+            routing_weight: None,
+            route_priority: None,
+            volume_alert_threshold: None,
+            spsp_display_symbol: None,
+            spsp_display_decimals: None,
+            allowed_source_prefix: None,
+            allowed_destination_prefixes: Vec::new(),
+            max_concurrent_outgoing_requests: None,
+            shadow_account: None,
             packets_per_minute_limit: None,
             settlement_engine_url: None,
+            settlement_asset_code: None,
+            settlement_asset_scale: None,
         })
         .await
         .unwrap();
@@ -192,6 +209,11 @@ async fn adds_static_routes_to_redis() {
     assert_eq!(routes["example.b"], accs[0].id().to_string());
     assert_eq!(routes["example.c"], accs[1].id().to_string());
     assert_eq!(routes.len(), 3);
+
+    let static_routes = store.get_static_routes().await.unwrap();
+    assert_eq!(static_routes["example.a"], accs[0].id());
+    assert_eq!(static_routes["example.c"], accs[1].id());
+    assert_eq!(static_routes.len(), 3);
 }
 
 #[tokio::test]
@@ -271,3 +293,80 @@ async fn returns_configured_routes_for_route_manager() {
     assert_eq!(configured["example.a"].id(), accs[0].id());
     assert_eq!(configured["example.b"].id(), accs[1].id());
 }
+
+#[tokio::test]
+async fn drains_accounts_of_all_nodes() {
+    let (store, context, accs) = test_store().await.unwrap();
+    let other_node = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .poll_interval(1)
+        .connect()
+        .await
+        .unwrap();
+    assert!(store.draining_accounts().is_empty());
+
+    store
+        .set_account_draining(accs[0].id(), true)
+        .await
+        .unwrap();
+    assert!(store.draining_accounts().contains(&accs[0].id()));
+    tokio::time::delay_for(Duration::from_millis(10)).await;
+    assert!(other_node.draining_accounts().contains(&accs[0].id()));
+
+    store
+        .set_account_draining(accs[0].id(), false)
+        .await
+        .unwrap();
+    assert!(store.draining_accounts().is_empty());
+    tokio::time::delay_for(Duration::from_millis(10)).await;
+    assert!(other_node.draining_accounts().is_empty());
+
+    // deleted accounts are not drained anymore
+    store
+        .set_account_draining(accs[1].id(), true)
+        .await
+        .unwrap();
+    store.delete_account(accs[1].id()).await.unwrap();
+    assert!(store.draining_accounts().is_empty());
+
+    assert!(store
+        .set_account_draining(Uuid::new_v4(), true)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn disables_accounts_of_all_nodes() {
+    let (store, context, accs) = test_store().await.unwrap();
+    let other_node = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .poll_interval(1)
+        .connect()
+        .await
+        .unwrap();
+    assert!(store.disabled_accounts().is_empty());
+
+    store
+        .set_account_enabled(accs[0].id(), false)
+        .await
+        .unwrap();
+    assert!(store.disabled_accounts().contains(&accs[0].id()));
+    tokio::time::delay_for(Duration::from_millis(10)).await;
+    assert!(other_node.disabled_accounts().contains(&accs[0].id()));
+
+    store.set_account_enabled(accs[0].id(), true).await.unwrap();
+    assert!(store.disabled_accounts().is_empty());
+    tokio::time::delay_for(Duration::from_millis(10)).await;
+    assert!(other_node.disabled_accounts().is_empty());
+
+    // deleted accounts are not disabled anymore
+    store
+        .set_account_enabled(accs[1].id(), false)
+        .await
+        .unwrap();
+    store.delete_account(accs[1].id()).await.unwrap();
+    assert!(store.disabled_accounts().is_empty());
+
+    assert!(store
+        .set_account_enabled(Uuid::new_v4(), false)
+        .await
+        .is_err());
+}
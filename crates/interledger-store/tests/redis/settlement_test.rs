@@ -1,13 +1,14 @@
-use super::store_helpers::*;
+use super::{fixtures::*, store_helpers::*};
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 
 use http::StatusCode;
 use interledger_api::NodeStore;
 use interledger_service::{Account, AccountStore};
-use interledger_service_util::BalanceStore;
+use interledger_service_util::{BalanceStore, SettlementConversion, SettlementDirection};
 use interledger_settlement::core::{
     idempotency::{IdempotentData, IdempotentStore},
-    types::{LeftoversStore, SettlementAccount, SettlementStore},
+    types::{LeftoversStore, SettlementAccount, SettlementAsset, SettlementStore},
 };
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
@@ -191,6 +192,83 @@ async fn clears_balance_owed_and_puts_remainder_as_prepaid() {
     assert_eq!(prepaid_amount, 60);
 }
 
+#[tokio::test]
+async fn records_last_settlement() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let id = accs[1].id();
+    assert_eq!(store.get_last_settlement(id).await.unwrap(), None);
+
+    store
+        .update_balance_for_incoming_settlement(id, 100, Some(IDEMPOTENCY_KEY.clone()))
+        .await
+        .unwrap();
+    let last_settlement = store.get_last_settlement(id).await.unwrap().unwrap();
+    assert_eq!(last_settlement.amount, 100);
+    let settled_at = DateTime::parse_from_rfc3339(&last_settlement.timestamp).unwrap();
+    assert!(Utc::now().signed_duration_since(settled_at) < chrono::Duration::seconds(5));
+
+    // outgoing settlements reduce the balance, so their amount is negative
+    let (_, amount_to_settle) = store
+        .update_balances_for_fulfill(id, 0, None)
+        .await
+        .unwrap();
+    assert_eq!(amount_to_settle, 1000);
+    let last_settlement = store.get_last_settlement(id).await.unwrap().unwrap();
+    assert_eq!(last_settlement.amount, -1000);
+
+    // refunded settlements are removed
+    store.refund_settlement(id, amount_to_settle).await.unwrap();
+    assert_eq!(store.get_last_settlement(id).await.unwrap(), None);
+}
+
+#[allow(clippy::float_cmp)]
+#[tokio::test]
+async fn saves_settlement_asset_and_conversions() {
+    let (store, _context, _accs) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.settlement_asset_code = Some("usdc".to_string());
+    let account = store.insert_account(details).await.unwrap();
+    let id = account.id();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    // The settlement asset defaults to the account's asset scale
+    assert_eq!(
+        account.settlement_asset(),
+        Some(SettlementAsset {
+            asset_code: "USDC".to_string(),
+            asset_scale: 9,
+        })
+    );
+
+    assert_eq!(
+        store.get_last_settlement_conversion(id).await.unwrap(),
+        None
+    );
+    let conversion = SettlementConversion {
+        direction: SettlementDirection::Incoming,
+        amount: 1_500_000_000,
+        settlement_amount: 3_000_000_000,
+        settlement_asset_code: "USDC".to_string(),
+        settlement_asset_scale: 9,
+        rate: 2.0,
+        rate_provenance: None,
+        timestamp: Utc::now().to_rfc3339(),
+    };
+    store
+        .save_settlement_conversion(id, conversion.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_last_settlement_conversion(id).await.unwrap(),
+        Some(conversion)
+    );
+
+    store.delete_account(id).await.unwrap();
+    assert_eq!(
+        store.get_last_settlement_conversion(id).await.unwrap(),
+        None
+    );
+}
+
 #[tokio::test]
 async fn loads_globally_configured_settlement_engine_url() {
     let (store, _context, accs) = test_store().await.unwrap();
@@ -0,0 +1,56 @@
+use super::store_helpers::*;
+use interledger_service::Account as AccountTrait;
+use interledger_service_util::{TrafficCounters, TrafficStore};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn adds_to_traffic_counters() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    assert_eq!(
+        store.get_traffic(id).await.unwrap(),
+        TrafficCounters::default()
+    );
+    store
+        .add_traffic(
+            id,
+            TrafficCounters {
+                packets_in: 1,
+                bytes_in: 120,
+                bytes_out: 45,
+                amount_in: 100,
+                ..TrafficCounters::default()
+            },
+        )
+        .await
+        .unwrap();
+    store
+        .add_traffic(
+            id,
+            TrafficCounters {
+                packets_out: 1,
+                bytes_in: 45,
+                bytes_out: 120,
+                amount_out: 50,
+                ..TrafficCounters::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_traffic(id).await.unwrap(),
+        TrafficCounters {
+            packets_in: 1,
+            packets_out: 1,
+            bytes_in: 165,
+            bytes_out: 165,
+            amount_in: 100,
+            amount_out: 50,
+        }
+    );
+    // Traffic is counted separately for every account
+    assert_eq!(
+        store.get_traffic(Uuid::new_v4()).await.unwrap(),
+        TrafficCounters::default()
+    );
+}
@@ -0,0 +1,30 @@
+use super::store_helpers::*;
+use interledger_service::Account as AccountTrait;
+use interledger_service_util::VolumeStore;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn counts_outgoing_volume_per_window() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let window = Duration::from_secs(3600);
+    let id = accs[0].id();
+    assert_eq!(store.get_outgoing_volume(id, window).await.unwrap(), 0);
+    assert_eq!(
+        store.add_outgoing_volume(id, 100, window).await.unwrap(),
+        100
+    );
+    assert_eq!(
+        store.add_outgoing_volume(id, 50, window).await.unwrap(),
+        150
+    );
+    assert_eq!(store.get_outgoing_volume(id, window).await.unwrap(), 150);
+    // Volumes are counted separately for every account
+    assert_eq!(
+        store
+            .get_outgoing_volume(Uuid::new_v4(), window)
+            .await
+            .unwrap(),
+        0
+    );
+}
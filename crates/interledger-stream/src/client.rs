@@ -6,8 +6,8 @@ use bytes::Bytes;
 use bytes::BytesMut;
 use futures::stream::{FuturesUnordered, StreamExt};
 use interledger_packet::{
-    Address, ErrorClass, ErrorCode as IlpErrorCode, PacketType as IlpPacketType, PrepareBuilder,
-    Reject,
+    Address, ErrorClass, ErrorCode as IlpErrorCode, MaxPacketAmountDetails,
+    PacketType as IlpPacketType, PrepareBuilder, Reject,
 };
 use interledger_rates::ExchangeRateStore;
 use interledger_service::*;
@@ -18,7 +18,7 @@ use num::traits::identities::One;
 use num::traits::pow::pow;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
-use tokio::time::timeout_at;
+use tokio::time::{delay_until, timeout_at};
 use tokio::time::{Duration, Instant};
 
 use std::cmp::{max, min};
@@ -31,12 +31,54 @@ use std::time::SystemTime;
 /// getting into an infinite loop of sending packets and effectively DoSing ourselves
 const MAX_TIME_SINCE_LAST_FULFILL: Duration = Duration::from_secs(30);
 
+/// Default time before the Prepares the sender sends expire
+pub const DEFAULT_PACKET_EXPIRY: Duration = Duration::from_secs(30);
+
 /// Minimum number of packet attempts before defaulting to failure rate
 const FAIL_FAST_MINIMUM_PACKET_ATTEMPTS: u64 = 200;
 
 /// Minimum rate of rejected packets in order to terminate the payment
 const FAIL_FAST_MINIMUM_FAILURE_RATE: f64 = 0.99;
 
+/// Longest time we wait before sending more packets after a temporary reject
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// How a STREAM payment backs off when its packets are rejected with temporary (`T`) errors,
+/// such as `T04` (Insufficient Liquidity).
+///
+/// After the `n`th packet in a row is rejected with a temporary error, no more packets are
+/// sent for `base_delay * 2^(n - 1)` (at most 5 seconds), and the payment fails once more than
+/// `max_retries` packets in a row were rejected. A fulfilled packet resets the count.
+/// Final (`F`) errors still stop the payment right away, except for `F08` (Amount Too Large)
+/// and `F99` (Application Error).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// How many packets in a row may be rejected with temporary errors
+    pub max_retries: u32,
+    /// The time to wait after the first temporary reject, which doubles with every other one
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Up to 10 retries, waiting 50 milliseconds after the first temporary reject
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(50),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The time to wait after the given number of temporary rejects in a row
+    fn delay(&self, temporary_rejects: u32) -> Duration {
+        let factor = 2u32.saturating_pow(temporary_rejects.saturating_sub(1));
+        self.base_delay
+            .checked_mul(factor)
+            .map_or(MAX_RETRY_DELAY, |delay| min(delay, MAX_RETRY_DELAY))
+    }
+}
+
 /// Receipt for STREAM payment to account for how much and what assets were sent & delivered
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct StreamDelivery {
@@ -95,11 +137,34 @@ struct StreamPayment {
     fulfilled_packets: u64,
     /// Number of rejected packets throughout the STREAM payment
     rejected_packets: u64,
+    /// Number of packets rejected with temporary errors since the last fulfill
+    temporary_rejects: u32,
+    /// After a temporary reject, no more packets are sent until this time
+    retry_at: Option<Instant>,
     /// Timestamp when a packet was last fulfilled for this payment
     last_fulfill_time: Instant,
 }
 
 impl StreamPayment {
+    fn new<A: Account>(from_account: &A, destination: Address, source_amount: u64) -> Self {
+        StreamPayment {
+            // TODO Make configurable to get money flowing ASAP vs as much as possible per-packet
+            congestion_controller: CongestionController::new(
+                source_amount,
+                source_amount / 10,
+                2.0,
+            ),
+            receipt: StreamDelivery::new(from_account, destination, source_amount),
+            should_send_source_account: true,
+            sequence: 1,
+            fulfilled_packets: 0,
+            rejected_packets: 0,
+            temporary_rejects: 0,
+            retry_at: None,
+            last_fulfill_time: Instant::now(),
+        }
+    }
+
     /// Account for and return amount to send in the next Prepare
     #[inline]
     fn apply_prepare(&mut self) -> u64 {
@@ -128,6 +193,8 @@ impl StreamPayment {
 
         self.last_fulfill_time = Instant::now();
         self.fulfilled_packets += 1;
+        self.temporary_rejects = 0;
+        self.retry_at = None;
     }
 
     /// Account for a rejected packet and update flow control
@@ -202,10 +269,81 @@ impl StreamPayment {
         num_packets >= FAIL_FAST_MINIMUM_PACKET_ATTEMPTS
             && (self.rejected_packets as f64 / num_packets as f64) > FAIL_FAST_MINIMUM_FAILURE_RATE
     }
+
+    /// Extrapolate the amount the receiver got for a probe packet to the whole source amount
+    fn to_quote<S: ExchangeRateStore>(
+        &self,
+        store: &S,
+        probe_amount: u64,
+        received_amount: u64,
+        slippage: f64,
+    ) -> Result<StreamQuote, Error> {
+        let receipt = &self.receipt;
+        let (destination_asset_code, destination_asset_scale) = match (
+            receipt.destination_asset_code.as_ref(),
+            receipt.destination_asset_scale,
+        ) {
+            (Some(code), Some(scale)) => (code.clone(), scale),
+            _ => {
+                return Err(Error::SendMoneyError(
+                    "Unable to quote payment: receiver did not send its asset details".to_string(),
+                ))
+            }
+        };
+
+        let rate = received_amount as f64 / probe_amount as f64;
+        let estimated_delivered_amount = (receipt.source_amount as f64 * rate).floor() as u64;
+        let effective_rate = rate
+            * 10f64
+                .powi(i32::from(receipt.source_asset_scale) - i32::from(destination_asset_scale));
+        let min_delivered_amount = get_min_destination_amount(
+            store,
+            receipt.source_amount,
+            receipt.source_asset_scale,
+            &receipt.source_asset_code,
+            Some(destination_asset_scale),
+            Some(&destination_asset_code),
+            slippage,
+        )
+        .unwrap_or(0);
+
+        Ok(StreamQuote {
+            from: receipt.from.clone(),
+            to: receipt.to.clone(),
+            source_asset_scale: receipt.source_asset_scale,
+            source_asset_code: receipt.source_asset_code.clone(),
+            source_amount: receipt.source_amount,
+            destination_asset_scale,
+            destination_asset_code,
+            estimated_delivered_amount,
+            min_delivered_amount,
+            effective_rate,
+        })
+    }
+}
+
+/// Returns the earlier of the two instants (tokio's `Instant` is not `Ord`)
+fn earliest(a: Instant, b: Instant) -> Instant {
+    if b < a {
+        b
+    } else {
+        a
+    }
 }
 
 /// Send the given source amount with packetized Interledger payments using the STREAM transport protocol
 /// Returns the receipt with sent & delivered amounts, asset & account details
+///
+/// Every Prepare expires after the given `packet_expiry` (usually
+/// [`DEFAULT_PACKET_EXPIRY`](./constant.DEFAULT_PACKET_EXPIRY.html)), which may have to be
+/// raised for paths with a high latency.
+///
+/// If a `payment_timeout` is given, no more packets are sent once it elapsed. The packets
+/// in flight are still waited for and the receipt of the partial delivery is returned,
+/// whose `sent_amount` is then less than the `source_amount`.
+///
+/// Temporary rejects are retried according to the [`RetryPolicy`](./struct.RetryPolicy.html).
+#[allow(clippy::too_many_arguments)]
 pub async fn send_money<I, A, S>(
     service: I,
     from_account: &A,
@@ -214,6 +352,9 @@ pub async fn send_money<I, A, S>(
     shared_secret: &[u8],
     source_amount: u64,
     slippage: f64,
+    packet_expiry: Duration,
+    payment_timeout: Option<Duration>,
+    retry_policy: RetryPolicy,
 ) -> Result<StreamDelivery, Error>
 where
     I: IncomingService<A> + Clone + Send + Sync + 'static,
@@ -238,22 +379,16 @@ where
         shared_secret,
         store,
         slippage,
-        payment: Arc::new(Mutex::new(StreamPayment {
-            // TODO Make configurable to get money flowing ASAP vs as much as possible per-packet
-            congestion_controller: CongestionController::new(
-                source_amount,
-                source_amount / 10,
-                2.0,
-            ),
-            receipt: StreamDelivery::new(from_account, destination_account, source_amount),
-            should_send_source_account: true,
-            sequence: 1,
-            fulfilled_packets: 0,
-            rejected_packets: 0,
-            last_fulfill_time: Instant::now(),
-        })),
+        packet_expiry,
+        retry_policy,
+        payment: Arc::new(Mutex::new(StreamPayment::new(
+            from_account,
+            destination_account,
+            source_amount,
+        ))),
     };
 
+    let payment_deadline = payment_timeout.map(|timeout| Instant::now() + timeout);
     let mut pending_requests = FuturesUnordered::new();
 
     /// Actions corresponding to the state of the payment
@@ -262,8 +397,12 @@ where
         SendMoney(u64),
         /// Congestion controller limited in-flight amount: wait for pending requests until given deadline
         MaxInFlight(Instant),
+        /// A packet was rejected with a temporary error: wait for pending requests until given deadline
+        Backoff(Instant),
         /// Send full source amount: close the connection and return success
         CloseConnection,
+        /// The payment timeout has elapsed: close the connection and return the partial delivery
+        PaymentTimeout,
         /// Maximum timeout since last fulfill has elapsed: terminate the payment
         Timeout,
         /// Too many packets are rejected, such as if the exchange rate is too low: terminate the payment
@@ -280,11 +419,25 @@ where
                 PaymentEvent::FailFast
             } else if payment.is_complete() {
                 PaymentEvent::CloseConnection
+            } else if payment_deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                PaymentEvent::PaymentTimeout
+            } else if let Some(retry_at) = payment
+                .retry_at
+                .filter(|retry_at| *retry_at > Instant::now())
+            {
+                let deadline = payment_deadline.map_or(retry_at, |payment_deadline| {
+                    earliest(retry_at, payment_deadline)
+                });
+                PaymentEvent::Backoff(deadline)
             } else if payment.is_max_in_flight() {
                 let deadline = payment
                     .last_fulfill_time
                     .checked_add(MAX_TIME_SINCE_LAST_FULFILL)
                     .unwrap();
+                // Wake up at the payment timeout as well to stop sending
+                let deadline = payment_deadline.map_or(deadline, |payment_deadline| {
+                    earliest(deadline, payment_deadline)
+                });
                 PaymentEvent::MaxInFlight(deadline)
             } else {
                 PaymentEvent::SendMoney(payment.apply_prepare())
@@ -308,6 +461,16 @@ where
                     return Err(error);
                 }
             }
+            PaymentEvent::Backoff(deadline) => {
+                if pending_requests.is_empty() {
+                    delay_until(deadline).await;
+                } else if let Ok(Ok(Err(error))) =
+                    timeout_at(deadline, pending_requests.select_next_some()).await
+                {
+                    error!("Send money stopped because of error: {:?}", error);
+                    return Err(error);
+                }
+            }
             PaymentEvent::CloseConnection => {
                 // Wait for all pending requests to complete before closing the connection
                 pending_requests.map(|_| ()).collect::<()>().await;
@@ -325,6 +488,21 @@ where
                 );
                 return Ok(payment.receipt.clone());
             }
+            PaymentEvent::PaymentTimeout => {
+                // The packets in flight are still accounted for in the receipt
+                pending_requests.map(|_| ()).collect::<()>().await;
+                sender.try_send_connection_close().await;
+
+                let payment = sender.payment.lock().await;
+                debug!(
+                    "Send money stopped after the payment timeout. Delivered: {} of the source amount {} ({} packets fulfilled, {} packets rejected)",
+                    payment.receipt.delivered_amount,
+                    payment.receipt.source_amount,
+                    payment.fulfilled_packets,
+                    payment.rejected_packets,
+                );
+                return Ok(payment.receipt.clone());
+            }
             PaymentEvent::Timeout => {
                 // Error if we haven't received a fulfill over a timeout period
                 return Err(Error::TimeoutError(
@@ -343,6 +521,102 @@ where
     }
 }
 
+/// Estimate of what a STREAM payment would deliver, obtained by sending unfulfillable probe packets
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StreamQuote {
+    /// Sender's ILP Address
+    pub from: Address,
+    /// Receiver's ILP Address
+    pub to: Address,
+    /// Asset scale of sender
+    pub source_asset_scale: u8,
+    /// Asset code of sender
+    pub source_asset_code: String,
+    /// Amount the quote was requested for, in source units
+    pub source_amount: u64,
+    /// Receiver's asset scale
+    pub destination_asset_scale: u8,
+    /// Receiver's asset code
+    pub destination_asset_code: String,
+    /// Amount the receiver would get if the whole source amount was sent at the
+    /// exchange rate observed on the path, in destination units
+    pub estimated_delivered_amount: u64,
+    /// Minimum amount the sender would accept for the source amount, based on its exchange rates
+    /// and the slippage, in destination units. This is 0 if the sender has no rate for the assets
+    pub min_delivered_amount: u64,
+    /// Exchange rate observed on the path, from one unit of the source asset to
+    /// one unit of the destination asset (i.e. independent of the assets' scales)
+    pub effective_rate: f64,
+}
+
+/// Outcome of a single probe packet
+enum Probe {
+    /// The receiver got the packet and told us how much arrived, in destination units
+    Received(u64),
+    /// A connector rejected the packet, so it should be retried with the given amount
+    Retry(u64),
+}
+
+/// Maximum number of probe packets sent to get a quote
+const MAX_PROBE_ATTEMPTS: usize = 10;
+
+/// Probe the path to the receiver with unfulfillable packets to estimate how much a
+/// STREAM payment of the given source amount would deliver, without moving any money
+#[allow(clippy::too_many_arguments)]
+pub async fn quote<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: &[u8],
+    source_amount: u64,
+    slippage: f64,
+    packet_expiry: Duration,
+) -> Result<StreamQuote, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    if source_amount == 0 {
+        return Err(Error::SendMoneyError(
+            "Cannot quote a payment of 0".to_string(),
+        ));
+    }
+
+    let mut sender = StreamSender {
+        next: service,
+        from_account: from_account.clone(),
+        shared_secret: Bytes::from(shared_secret),
+        store,
+        slippage,
+        packet_expiry,
+        retry_policy: RetryPolicy::default(),
+        payment: Arc::new(Mutex::new(StreamPayment::new(
+            from_account,
+            destination_account,
+            source_amount,
+        ))),
+    };
+
+    let mut probe_amount = source_amount;
+    for _ in 0..MAX_PROBE_ATTEMPTS {
+        match sender.send_probe_packet(probe_amount).await? {
+            Probe::Received(received_amount) => {
+                sender.try_send_connection_close().await;
+                let payment = sender.payment.lock().await;
+                return payment.to_quote(&sender.store, probe_amount, received_amount, slippage);
+            }
+            Probe::Retry(amount) if amount > 0 => probe_amount = amount,
+            Probe::Retry(_) => break,
+        }
+    }
+
+    Err(Error::SendMoneyError(
+        "Unable to quote payment: no probe packet reached the receiver".to_string(),
+    ))
+}
+
 /// Sends and handles all ILP & STREAM packets, encapsulating all payment state
 #[derive(Clone)]
 struct StreamSender<I, A, S> {
@@ -356,6 +630,10 @@ struct StreamSender<I, A, S> {
     store: S,
     /// Maximum acceptable slippage percentage below calculated minimum exchange rate
     slippage: f64,
+    /// Time before each Prepare expires
+    packet_expiry: Duration,
+    /// How temporary rejects are retried
+    retry_policy: RetryPolicy,
     /// Mutable payment state
     payment: Arc<Mutex<StreamPayment>>,
 }
@@ -430,7 +708,7 @@ where
                 destination: payment.receipt.to.clone(),
                 amount: source_amount,
                 execution_condition: &execution_condition,
-                expires_at: SystemTime::now() + Duration::from_secs(30),
+                expires_at: SystemTime::now() + self.packet_expiry,
                 // TODO Don't copy the data
                 data: &prepare_data[..],
             }
@@ -457,6 +735,7 @@ where
             StreamPacket::from_encrypted(&self.shared_secret, BytesMut::from(reply_data));
 
         let mut payment = self.payment.lock().await;
+        let mut application_error = None;
 
         // Parse the stream packet and determine the amount the recipient claims they received
         let claimed_amount: u64 = match stream_reply_packet {
@@ -478,6 +757,7 @@ where
                 } else {
                     // Since we decrypted the response, the recipient read the request packet and knows our account
                     payment.should_send_source_account = false;
+                    application_error = get_application_error(&stream_reply_packet);
 
                     // Update the destination asset scale & code
                     // https://github.com/interledger/rfcs/pull/551 ensures that this won't change
@@ -537,8 +817,29 @@ where
                     payment.get_remaining_amount()
                 );
 
+                if let Some(error) = application_error {
+                    return Err(Error::ApplicationError(error));
+                }
+
                 match (reject.code().class(), reject.code()) {
-                    (ErrorClass::Temporary, _) => Ok(()),
+                    (ErrorClass::Temporary, _) => {
+                        payment.temporary_rejects += 1;
+                        if payment.temporary_rejects > self.retry_policy.max_retries {
+                            return Err(Error::SendMoneyError(format!(
+                                "Packet was rejected with error: {} {} (after {} retries)",
+                                reject.code(),
+                                str::from_utf8(reject.message()).unwrap_or_default(),
+                                self.retry_policy.max_retries,
+                            )));
+                        }
+                        let delay = self.retry_policy.delay(payment.temporary_rejects);
+                        debug!(
+                            "Waiting {:?} before sending more packets after temporary reject {}",
+                            delay, payment.temporary_rejects
+                        );
+                        payment.retry_at = Some(Instant::now() + delay);
+                        Ok(())
+                    }
                     (_, IlpErrorCode::F08_AMOUNT_TOO_LARGE) => Ok(()), // Handled by the congestion controller
                     (_, IlpErrorCode::F99_APPLICATION_ERROR) => Ok(()),
                     // Any other error will stop the rest of the payment
@@ -552,6 +853,109 @@ where
         }
     }
 
+    /// Send an unfulfillable Prepare for the given source amount to find out how much
+    /// of it arrives at the receiver
+    async fn send_probe_packet(&mut self, source_amount: u64) -> Result<Probe, Error> {
+        let (prepare, sequence) = {
+            let mut payment = self.payment.lock().await;
+            let sequence = payment.next_sequence();
+
+            let mut frames = vec![Frame::StreamMoney(StreamMoneyFrame {
+                stream_id: 1,
+                shares: 1,
+            })];
+            if payment.should_send_source_account {
+                frames.push(Frame::ConnectionNewAddress(ConnectionNewAddressFrame {
+                    source_account: payment.receipt.from.clone(),
+                }));
+            }
+
+            let stream_packet = StreamPacketBuilder {
+                ilp_packet_type: IlpPacketType::Prepare,
+                prepare_amount: 0,
+                sequence,
+                frames: &frames,
+            }
+            .build();
+            let data = stream_packet.into_encrypted(&self.shared_secret);
+
+            // The condition is random so the packet can never be fulfilled
+            let prepare = PrepareBuilder {
+                destination: payment.receipt.to.clone(),
+                amount: source_amount,
+                execution_condition: &random_condition(),
+                expires_at: SystemTime::now() + self.packet_expiry,
+                data: &data[..],
+            }
+            .build();
+
+            (prepare, sequence)
+        };
+
+        debug!(
+            "Sending probe packet {} with amount: {}",
+            sequence, source_amount
+        );
+        let reject = match self
+            .next
+            .handle_request(IncomingRequest {
+                from: self.from_account.clone(),
+                prepare,
+            })
+            .await
+        {
+            Ok(_) => {
+                error!("Probe packet {} was fulfilled", sequence);
+                return Err(Error::SendMoneyError(
+                    "Probe packet was unexpectedly fulfilled".to_string(),
+                ));
+            }
+            Err(reject) => reject,
+        };
+
+        if let Ok(stream_packet) =
+            StreamPacket::from_encrypted(&self.shared_secret, BytesMut::from(reject.data()))
+        {
+            if stream_packet.sequence() == sequence {
+                if let Some(error) = get_application_error(&stream_packet) {
+                    return Err(Error::ApplicationError(error));
+                }
+                let mut payment = self.payment.lock().await;
+                payment.should_send_source_account = false;
+                for frame in stream_packet.frames() {
+                    if let Frame::ConnectionAssetDetails(frame) = frame {
+                        payment.set_destination_asset_details(
+                            frame.source_asset_code.to_string(),
+                            frame.source_asset_scale,
+                        );
+                    }
+                }
+                return Ok(Probe::Received(stream_packet.prepare_amount()));
+            }
+        }
+
+        debug!(
+            "Probe packet {} was rejected by a connector with code: {}",
+            sequence,
+            reject.code()
+        );
+        match (reject.code().class(), reject.code()) {
+            (ErrorClass::Temporary, _) => Ok(Probe::Retry(source_amount)),
+            (_, IlpErrorCode::F08_AMOUNT_TOO_LARGE) => {
+                let amount = match MaxPacketAmountDetails::from_bytes(reject.data()) {
+                    Ok(details) => reduce_to_max_packet_amount(source_amount, &details),
+                    _ => source_amount / 2,
+                };
+                Ok(Probe::Retry(amount))
+            }
+            _ => Err(Error::SendMoneyError(format!(
+                "Probe packet was rejected with error: {} {}",
+                reject.code(),
+                str::from_utf8(reject.message()).unwrap_or_default(),
+            ))),
+        }
+    }
+
     /// Send an unfulfillable Prepare with a ConnectionClose frame to the peer
     /// There's no ACK from the recipient, so we can't confirm it closed
     #[inline]
@@ -577,7 +981,7 @@ where
                 destination: payment.receipt.to.clone(),
                 amount: 0,
                 execution_condition: &random_condition(),
-                expires_at: SystemTime::now() + Duration::from_secs(30),
+                expires_at: SystemTime::now() + self.packet_expiry,
                 data: &data[..],
             }
             .build()
@@ -596,6 +1000,28 @@ where
     }
 }
 
+/// Returns the data of the application error the receiver closed the connection with, if it did
+fn get_application_error(packet: &StreamPacket) -> Option<String> {
+    packet.frames().find_map(|frame| match frame {
+        Frame::ConnectionClose(frame) if frame.code == ErrorCode::ApplicationError => {
+            Some(frame.message.to_string())
+        }
+        _ => None,
+    })
+}
+
+/// Scales the source amount of a packet rejected with F08 down to what the connector accepts.
+/// The product is computed in u128 so it can't overflow, and the result never exceeds the
+/// source amount of the rejected packet
+fn reduce_to_max_packet_amount(source_amount: u64, details: &MaxPacketAmountDetails) -> u64 {
+    if details.amount_received() == 0 {
+        return source_amount / 2;
+    }
+    let amount = u128::from(source_amount) * u128::from(details.max_amount())
+        / u128::from(details.amount_received());
+    amount.min(u128::from(source_amount)) as u64
+}
+
 // TODO Abstract duplicated conversion logic from interledger-settlement &
 //      exchange rate service into interledger-rates
 
@@ -667,6 +1093,7 @@ mod send_money_tests {
             asset_scale: 9,
             ilp_address: Address::from_str("example.destination").unwrap(),
             max_packet_amount: None,
+            allowed_source_prefix: None,
         };
         let requests = Arc::new(Mutex::new(Vec::new()));
         let requests_clone = requests.clone();
@@ -691,12 +1118,189 @@ mod send_money_tests {
             &[0; 32][..],
             100,
             0.0,
+            DEFAULT_PACKET_EXPIRY,
+            None,
+            RetryPolicy::default(),
         )
         .await;
         assert!(result.is_err());
         assert_eq!(requests.lock().len(), 1);
     }
 
+    #[tokio::test]
+    async fn aborts_at_unreachable_without_retrying() {
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            ilp_address: Address::from_str("example.destination").unwrap(),
+            max_packet_amount: None,
+            allowed_source_prefix: None,
+        };
+        let requests = Arc::new(AtomicUsize::new(0));
+        let requests_clone = requests.clone();
+        let start = Instant::now();
+        let result = send_money(
+            incoming_service_fn(move |_| {
+                requests_clone.fetch_add(1, Ordering::SeqCst);
+                Err(RejectBuilder {
+                    code: IlpErrorCode::F02_UNREACHABLE,
+                    message: b"no route found",
+                    triggered_by: Some(&EXAMPLE_CONNECTOR),
+                    data: &[],
+                }
+                .build())
+            }),
+            &account,
+            TestStore {
+                route: None,
+                price_1: None,
+                price_2: None,
+            },
+            Address::from_str("example.destination").unwrap(),
+            &[0; 32][..],
+            100,
+            0.0,
+            DEFAULT_PACKET_EXPIRY,
+            None,
+            RetryPolicy {
+                max_retries: 5,
+                base_delay: Duration::from_secs(1),
+            },
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn fails_after_too_many_temporary_rejects() {
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            ilp_address: Address::from_str("example.destination").unwrap(),
+            max_packet_amount: None,
+            allowed_source_prefix: None,
+        };
+        let requests = Arc::new(AtomicUsize::new(0));
+        let requests_clone = requests.clone();
+        let result = send_money(
+            incoming_service_fn(move |_| {
+                requests_clone.fetch_add(1, Ordering::SeqCst);
+                Err(RejectBuilder {
+                    code: IlpErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                    message: b"not enough liquidity",
+                    triggered_by: Some(&EXAMPLE_CONNECTOR),
+                    data: &[],
+                }
+                .build())
+            }),
+            &account,
+            TestStore {
+                route: None,
+                price_1: None,
+                price_2: None,
+            },
+            Address::from_str("example.destination").unwrap(),
+            &[0; 32][..],
+            100,
+            0.0,
+            DEFAULT_PACKET_EXPIRY,
+            None,
+            RetryPolicy {
+                max_retries: 2,
+                base_delay: Duration::from_millis(1),
+            },
+        )
+        .await;
+        match result {
+            Err(Error::SendMoneyError(message)) => assert!(message.contains("after 2 retries")),
+            other => panic!("Payment should fail after the retries: {:?}", other),
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            max_retries: 100,
+            base_delay: Duration::from_millis(100),
+        };
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(4), Duration::from_millis(800));
+        assert_eq!(policy.delay(7), MAX_RETRY_DELAY);
+        assert_eq!(policy.delay(100), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn reduces_large_amounts_to_max_packet_amount_without_overflow() {
+        let details = MaxPacketAmountDetails::new(u64::MAX, u64::MAX / 2);
+        assert_eq!(
+            reduce_to_max_packet_amount(u64::MAX, &details),
+            u64::MAX / 2
+        );
+
+        let details = MaxPacketAmountDetails::new(10, 1000);
+        assert_eq!(reduce_to_max_packet_amount(100, &details), 100);
+
+        let details = MaxPacketAmountDetails::new(0, 1000);
+        assert_eq!(reduce_to_max_packet_amount(100, &details), 50);
+    }
+
+    #[tokio::test]
+    async fn uses_configured_packet_expiry() {
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            ilp_address: Address::from_str("example.destination").unwrap(),
+            max_packet_amount: None,
+            allowed_source_prefix: None,
+        };
+        let packet_expiry = Duration::from_secs(120);
+        let expiries = Arc::new(Mutex::new(Vec::new()));
+        let expiries_clone = expiries.clone();
+        let before = SystemTime::now();
+        let result = send_money(
+            incoming_service_fn(move |request| {
+                expiries_clone.lock().push(request.prepare.expires_at());
+                Err(RejectBuilder {
+                    code: IlpErrorCode::F00_BAD_REQUEST,
+                    message: b"just some final error",
+                    triggered_by: Some(&EXAMPLE_CONNECTOR),
+                    data: &[],
+                }
+                .build())
+            }),
+            &account,
+            TestStore {
+                route: None,
+                price_1: None,
+                price_2: None,
+            },
+            Address::from_str("example.destination").unwrap(),
+            &[0; 32][..],
+            100,
+            0.0,
+            packet_expiry,
+            None,
+            RetryPolicy::default(),
+        )
+        .await;
+        let after = SystemTime::now();
+        assert!(result.is_err());
+
+        let expiries = expiries.lock();
+        assert_eq!(expiries.len(), 1);
+        // Prepares expire the configured time after they were sent
+        // (the packet's timestamp only has millisecond precision)
+        assert!(expiries[0] + Duration::from_millis(1) >= before + packet_expiry);
+        assert!(expiries[0] <= after + packet_expiry);
+    }
+
     #[tokio::test]
     async fn sends_concurrent_packets() {
         let destination_address = Address::from_str("example.receiver").unwrap();
@@ -706,6 +1310,7 @@ mod send_money_tests {
             asset_scale: 9,
             ilp_address: destination_address.clone(),
             max_packet_amount: Some(10),
+            allowed_source_prefix: None,
         };
         let store = TestStore {
             route: Some((destination_address.to_string(), account)),
@@ -763,6 +1368,7 @@ mod send_money_tests {
                 asset_scale: 9,
                 ilp_address: destination_address.clone(),
                 max_packet_amount: Some(10), // Requires at least 5 packets
+                allowed_source_prefix: None,
             },
             TestStore {
                 route: None,
@@ -773,6 +1379,9 @@ mod send_money_tests {
             &[0; 32][..],
             50,
             0.0,
+            DEFAULT_PACKET_EXPIRY,
+            None,
+            RetryPolicy::default(),
         )
         .await;
 
@@ -1,9 +1,10 @@
 use bytes::BytesMut;
+use interledger_packet::Prepare;
 use log::error;
 #[cfg(test)]
 use once_cell::sync::Lazy;
 use ring::rand::{SecureRandom, SystemRandom};
-use ring::{aead, digest, hmac};
+use ring::{aead, constant_time, digest, hmac};
 
 const NONCE_LENGTH: usize = 12;
 const AUTH_TAG_LENGTH: usize = 16;
@@ -48,6 +49,22 @@ pub fn generate_condition(shared_secret: &[u8], data: &[u8]) -> [u8; 32] {
     hash_sha256(&fulfillment)
 }
 
+/// Returns the fulfillment of a STREAM Prepare packet, which is generated from its
+/// data via the [generate_fulfillment](./fn.generate_fulfillment.html) function.
+///
+/// Note that the fulfillment is only valid if the sender derived the Prepare's
+/// condition from the same shared secret, which should be checked with
+/// [verify_condition](./fn.verify_condition.html) before fulfilling the packet.
+pub fn fulfillment_for_prepare(shared_secret: &[u8], prepare: &Prepare) -> [u8; 32] {
+    generate_fulfillment(shared_secret, prepare.data())
+}
+
+/// Returns whether the condition is the sha256 of the fulfillment.
+/// The digests are compared in constant time.
+pub fn verify_condition(fulfillment: &[u8], condition: &[u8]) -> bool {
+    constant_time::verify_slices_are_equal(&hash_sha256(fulfillment), condition).is_ok()
+}
+
 /// Returns a random 32 byte number using [SystemRandom::new()](../../ring/rand/struct.SystemRandom.html#method.new)
 pub fn random_condition() -> [u8; 32] {
     let mut condition_slice: [u8; 32] = [0; 32];
@@ -170,6 +187,9 @@ pub fn decrypt(shared_secret: &[u8], mut ciphertext: BytesMut) -> Result<BytesMu
 mod fulfillment_and_condition {
     use super::*;
     use bytes::Bytes;
+    use interledger_packet::{Address, PrepareBuilder};
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
 
     static SHARED_SECRET: Lazy<Vec<u8>> = Lazy::new(|| {
         vec![
@@ -189,6 +209,12 @@ mod fulfillment_and_condition {
             124, 119, 65, 13, 68, 54, 108, 120, 193, 59, 226, 107, 39,
         ]
     });
+    static CONDITION: Lazy<Vec<u8>> = Lazy::new(|| {
+        vec![
+            140, 30, 89, 153, 165, 15, 190, 26, 251, 146, 240, 163, 148, 133, 202, 200, 118, 244,
+            91, 84, 153, 200, 251, 130, 104, 170, 20, 229, 181, 11, 144, 187,
+        ]
+    });
 
     #[test]
     fn it_generates_the_same_fulfillment_as_javascript() {
@@ -196,6 +222,43 @@ mod fulfillment_and_condition {
             generate_fulfillment(&Bytes::from(&SHARED_SECRET[..]), &Bytes::from(&DATA[..]));
         assert_eq!(fulfillment.to_vec(), *FULFILLMENT);
     }
+
+    #[test]
+    fn it_generates_the_same_condition_as_javascript() {
+        let condition = generate_condition(&SHARED_SECRET[..], &DATA[..]);
+        assert_eq!(condition.to_vec(), *CONDITION);
+    }
+
+    #[test]
+    fn it_generates_the_fulfillment_of_a_prepare() {
+        let mut condition = [0; 32];
+        condition.copy_from_slice(&CONDITION[..]);
+        let prepare = PrepareBuilder {
+            destination: Address::from_str("example.receiver").unwrap(),
+            amount: 100,
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            execution_condition: &condition,
+            data: &DATA[..],
+        }
+        .build();
+        let fulfillment = fulfillment_for_prepare(&SHARED_SECRET[..], &prepare);
+        assert_eq!(fulfillment.to_vec(), *FULFILLMENT);
+        assert!(verify_condition(
+            &fulfillment[..],
+            prepare.execution_condition()
+        ));
+    }
+
+    #[test]
+    fn it_verifies_conditions() {
+        assert!(verify_condition(&FULFILLMENT[..], &CONDITION[..]));
+
+        let mut wrong_condition = CONDITION.clone();
+        wrong_condition[31] ^= 1;
+        assert!(!verify_condition(&FULFILLMENT[..], &wrong_condition[..]));
+        assert!(!verify_condition(&FULFILLMENT[..], &CONDITION[..31]));
+        assert!(!verify_condition(&DATA[..], &CONDITION[..]));
+    }
 }
 
 #[cfg(test)]
@@ -9,4 +9,6 @@ pub enum Error {
     SendMoneyError(String),
     #[error("Error maximum time exceeded: {0}")]
     TimeoutError(String),
+    #[error("Receiver closed the connection with an application error: {0}")]
+    ApplicationError(String),
 }
@@ -17,10 +17,16 @@ mod packet;
 /// A stream server implementing an [Outgoing Service](../interledger_service/trait.OutgoingService.html) for receiving STREAM payments from peers
 mod server;
 
-pub use client::{send_money, StreamDelivery};
+pub use client::{
+    quote, send_money, RetryPolicy, StreamDelivery, StreamQuote, DEFAULT_PACKET_EXPIRY,
+};
+pub use crypto::{
+    fulfillment_for_prepare, generate_condition, generate_fulfillment, verify_condition,
+};
 pub use error::Error;
 pub use server::{
-    ConnectionGenerator, PaymentNotification, StreamNotificationsStore, StreamReceiverService,
+    ApplicationError, ConnectionGenerator, PaymentNotification, SourcePrefixAccount,
+    StreamNotificationsStore, StreamReceiverService,
 };
 
 #[cfg(test)]
@@ -31,10 +37,11 @@ pub mod test_helpers {
     use interledger_errors::{AccountStoreError, AddressStoreError, ExchangeRateStoreError};
     use interledger_packet::Address;
     use interledger_rates::ExchangeRateStore;
-    use interledger_router::RouterStore;
+    use interledger_router::{RouterAccount, RouterStore};
     use interledger_service::{Account, AccountStore, AddressStore, Username};
-    use interledger_service_util::MaxPacketAmountAccount;
+    use interledger_service_util::{MaxPacketAmountAccount, SpreadAccount};
     use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
     use std::collections::HashMap;
     use std::iter::FromIterator;
     use std::str::FromStr;
@@ -54,6 +61,7 @@ pub mod test_helpers {
         pub asset_scale: u8,
         pub asset_code: String,
         pub max_packet_amount: Option<u64>,
+        pub allowed_source_prefix: Option<Address>,
     }
 
     impl Account for TestAccount {
@@ -84,6 +92,16 @@ pub mod test_helpers {
         }
     }
 
+    impl SpreadAccount for TestAccount {}
+
+    impl RouterAccount for TestAccount {}
+
+    impl SourcePrefixAccount for TestAccount {
+        fn allowed_source_prefix(&self) -> Option<&Address> {
+            self.allowed_source_prefix.as_ref()
+        }
+    }
+
     #[derive(Clone)]
     pub struct DummyStore;
 
@@ -100,6 +118,27 @@ pub mod test_helpers {
         fn publish_payment_notification(&self, _payment: PaymentNotification) {}
     }
 
+    /// Counts the payments the receiver fulfilled
+    #[derive(Clone, Default)]
+    pub struct CountingStore {
+        pub payments: Arc<Mutex<usize>>,
+    }
+
+    impl super::StreamNotificationsStore for CountingStore {
+        type Account = TestAccount;
+
+        fn add_payment_notification_subscription(
+            &self,
+            _account_id: Uuid,
+            _sender: UnboundedSender<PaymentNotification>,
+        ) {
+        }
+
+        fn publish_payment_notification(&self, _payment: PaymentNotification) {
+            *self.payments.lock() += 1;
+        }
+    }
+
     #[derive(Clone)]
     pub struct TestStore {
         pub route: Option<(String, TestAccount)>,
@@ -185,13 +224,19 @@ pub mod test_helpers {
 mod send_money_to_receiver {
     use super::test_helpers::*;
     use super::*;
+    use async_trait::async_trait;
     use bytes::Bytes;
     use interledger_packet::Address;
     use interledger_packet::{ErrorCode, RejectBuilder};
     use interledger_router::Router;
-    use interledger_service::outgoing_service_fn;
-    use interledger_service_util::ExchangeRateService;
+    use interledger_service::{
+        outgoing_service_fn, Account, IlpResult, IncomingRequest, IncomingService,
+    };
+    use interledger_service_util::{ExchangeRateService, MaxPacketAmountService};
     use std::str::FromStr;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::time::{delay_for, Duration, Instant};
     use uuid::Uuid;
 
     #[tokio::test]
@@ -204,6 +249,7 @@ mod send_money_to_receiver {
             asset_code: "XYZ".to_string(),
             asset_scale: 9,
             max_packet_amount: None,
+            allowed_source_prefix: None,
         };
         let store = TestStore {
             route: Some((destination_address.to_string(), account)),
@@ -238,6 +284,7 @@ mod send_money_to_receiver {
                 asset_scale: 9,
                 ilp_address: destination_address,
                 max_packet_amount: None,
+                allowed_source_prefix: None,
             },
             TestStore {
                 route: None,
@@ -248,6 +295,9 @@ mod send_money_to_receiver {
             &shared_secret[..],
             100,
             0.0,
+            DEFAULT_PACKET_EXPIRY,
+            None,
+            RetryPolicy::default(),
         )
         .await
         .unwrap();
@@ -255,6 +305,97 @@ mod send_money_to_receiver {
         assert_eq!(receipt.delivered_amount, 100);
     }
 
+    #[tokio::test]
+    async fn delivers_application_errors_to_sender() {
+        let server_secret = Bytes::from(&[0; 32][..]);
+        let destination_address = Address::from_str("example.receiver").unwrap();
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: destination_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+            allowed_source_prefix: None,
+        };
+        let store = TestStore {
+            route: Some((destination_address.to_string(), account)),
+            price_1: None,
+            price_2: None,
+        };
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let mut server = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&EXAMPLE_RECEIVER),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        server.on_receive(|_, _, _| {
+            Err(ApplicationError::new(
+                r#"{"reason":"account_frozen","retry":false}"#,
+            ))
+        });
+        let server = Router::new(store.clone(), server);
+
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&destination_address);
+        let sender_account = TestAccount {
+            id: Uuid::new_v4(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            ilp_address: Address::from_str("example.sender").unwrap(),
+            max_packet_amount: None,
+            allowed_source_prefix: None,
+        };
+
+        let result = send_money(
+            server.clone(),
+            &sender_account,
+            store.clone(),
+            destination_account.clone(),
+            &shared_secret[..],
+            100,
+            0.0,
+            DEFAULT_PACKET_EXPIRY,
+            None,
+            RetryPolicy::default(),
+        )
+        .await;
+        match result {
+            Err(Error::ApplicationError(data)) => {
+                assert_eq!(data, r#"{"reason":"account_frozen","retry":false}"#)
+            }
+            other => panic!(
+                "Payment should stop with the application error: {:?}",
+                other
+            ),
+        }
+
+        let result = quote(
+            server,
+            &sender_account,
+            store,
+            destination_account,
+            &shared_secret[..],
+            100,
+            0.0,
+            DEFAULT_PACKET_EXPIRY,
+        )
+        .await;
+        match result {
+            Err(Error::ApplicationError(data)) => {
+                assert_eq!(data, r#"{"reason":"account_frozen","retry":false}"#)
+            }
+            other => panic!("Quote should fail with the application error: {:?}", other),
+        }
+    }
+
     #[tokio::test]
     async fn payment_fails_if_large_spread() {
         let server_secret = Bytes::from(&[0; 32][..]);
@@ -267,6 +408,7 @@ mod send_money_to_receiver {
             asset_code: "XYZ".to_string(),
             asset_scale: 6,
             max_packet_amount: None,
+            allowed_source_prefix: None,
         };
 
         let recipient_account = TestAccount {
@@ -275,6 +417,7 @@ mod send_money_to_receiver {
             asset_code: "ABC".to_string(),
             asset_scale: 9,
             max_packet_amount: None,
+            allowed_source_prefix: None,
         };
 
         let store = TestStore {
@@ -312,6 +455,9 @@ mod send_money_to_receiver {
             &shared_secret[..],
             1000,
             0.014,
+            DEFAULT_PACKET_EXPIRY,
+            None,
+            RetryPolicy::default(),
         )
         .await;
 
@@ -321,4 +467,315 @@ mod send_money_to_receiver {
             _ => panic!("Payment should fail fast due to poor exchange rates"),
         }
     }
+
+    #[tokio::test]
+    async fn quote_matches_payment() {
+        let server_secret = Bytes::from(&[0; 32][..]);
+        let source_address = Address::from_str("example.sender").unwrap();
+        let destination_address = Address::from_str("example.receiver").unwrap();
+
+        let sender_account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: source_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 6,
+            max_packet_amount: None,
+            allowed_source_prefix: None,
+        };
+
+        let recipient_account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: destination_address.clone(),
+            asset_code: "ABC".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+            allowed_source_prefix: None,
+        };
+
+        let store = TestStore {
+            route: Some((destination_address.to_string(), recipient_account)),
+            price_1: Some(1.0),
+            price_2: Some(2.0),
+        };
+
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let notifications = CountingStore::default();
+        let server = StreamReceiverService::new(
+            server_secret,
+            notifications.clone(),
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&EXAMPLE_RECEIVER),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        let server = ExchangeRateService::new(0.01, store.clone(), server);
+        let server = Router::new(store.clone(), server);
+
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&destination_address);
+
+        let quote = quote(
+            server.clone(),
+            &sender_account,
+            store.clone(),
+            destination_account.clone(),
+            &shared_secret[..],
+            1_000_000,
+            0.02,
+            DEFAULT_PACKET_EXPIRY,
+        )
+        .await
+        .unwrap();
+
+        // Probing does not deliver any money
+        assert_eq!(*notifications.payments.lock(), 0);
+        assert_eq!(quote.destination_asset_code, "ABC");
+        assert_eq!(quote.destination_asset_scale, 9);
+        // 1 XYZ is worth 0.5 ABC and the connector takes 1%
+        assert!((quote.effective_rate - 0.495).abs() < 1e-9);
+        assert_eq!(quote.estimated_delivered_amount, 495_000_000);
+        assert_eq!(quote.min_delivered_amount, 490_000_000);
+
+        let receipt = send_money(
+            server,
+            &sender_account,
+            store,
+            destination_account,
+            &shared_secret[..],
+            1_000_000,
+            0.02,
+            DEFAULT_PACKET_EXPIRY,
+            None,
+            RetryPolicy::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(*notifications.payments.lock() > 0);
+        let difference =
+            (receipt.delivered_amount as f64 - quote.estimated_delivered_amount as f64).abs();
+        assert!(difference / (quote.estimated_delivered_amount as f64) < 0.001);
+    }
+
+    #[tokio::test]
+    async fn stops_after_payment_timeout_with_partial_delivery() {
+        let server_secret = Bytes::from(&[0; 32][..]);
+        let destination_address = Address::from_str("example.receiver").unwrap();
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: destination_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+            allowed_source_prefix: None,
+        };
+        let store = TestStore {
+            route: Some((destination_address.to_string(), account)),
+            price_1: None,
+            price_2: None,
+        };
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let server = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&EXAMPLE_RECEIVER),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        let server = Router::new(store.clone(), server);
+
+        /// Forwards the first few packets with money and then keeps rejecting them with a
+        /// temporary error, so the payment cannot complete before its timeout. The first
+        /// packet is unfulfillable because the sender does not know the receiver's asset yet
+        #[derive(Clone)]
+        struct StallingService<I> {
+            next: I,
+            forwarded_packets: Arc<AtomicUsize>,
+            fulfilled_amount: Arc<AtomicU64>,
+        }
+
+        #[async_trait]
+        impl<I, A> IncomingService<A> for StallingService<I>
+        where
+            I: IncomingService<A> + Send + Sync + 'static,
+            A: Account + 'static,
+        {
+            async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+                let amount = request.prepare.amount();
+                if amount > 0 && self.forwarded_packets.fetch_add(1, Ordering::SeqCst) >= 4 {
+                    delay_for(Duration::from_millis(5)).await;
+                    return Err(RejectBuilder {
+                        code: ErrorCode::T00_INTERNAL_ERROR,
+                        message: b"try again later",
+                        triggered_by: Some(&EXAMPLE_CONNECTOR),
+                        data: &[],
+                    }
+                    .build());
+                }
+                let result = self.next.handle_request(request).await;
+                if result.is_ok() {
+                    self.fulfilled_amount.fetch_add(amount, Ordering::SeqCst);
+                }
+                result
+            }
+        }
+
+        let fulfilled_amount = Arc::new(AtomicU64::new(0));
+        let server = StallingService {
+            next: server,
+            forwarded_packets: Arc::new(AtomicUsize::new(0)),
+            fulfilled_amount: fulfilled_amount.clone(),
+        };
+        let sender_account = TestAccount {
+            id: Uuid::new_v4(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            ilp_address: Address::from_str("example.sender").unwrap(),
+            max_packet_amount: Some(10),
+            allowed_source_prefix: None,
+        };
+
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&destination_address);
+        let payment_timeout = Duration::from_millis(100);
+        let start = Instant::now();
+        let receipt = send_money(
+            MaxPacketAmountService::new(store.clone(), server),
+            &sender_account,
+            store,
+            destination_account,
+            &shared_secret[..],
+            100,
+            0.0,
+            DEFAULT_PACKET_EXPIRY,
+            Some(payment_timeout),
+            RetryPolicy::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(start.elapsed() >= payment_timeout);
+        let fulfilled_amount = fulfilled_amount.load(Ordering::SeqCst);
+        assert_eq!(fulfilled_amount, 30);
+        assert_eq!(receipt.source_amount, 100);
+        assert_eq!(receipt.sent_amount, fulfilled_amount);
+        assert_eq!(receipt.delivered_amount, fulfilled_amount);
+        assert_eq!(receipt.in_flight_amount, 0);
+    }
+
+    #[tokio::test]
+    async fn retries_temporary_rejects_with_backoff() {
+        let server_secret = Bytes::from(&[0; 32][..]);
+        let destination_address = Address::from_str("example.receiver").unwrap();
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: destination_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+            allowed_source_prefix: None,
+        };
+        let store = TestStore {
+            route: Some((destination_address.to_string(), account)),
+            price_1: None,
+            price_2: None,
+        };
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let server = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&EXAMPLE_RECEIVER),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        let server = Router::new(store.clone(), server);
+
+        /// Rejects the first few packets with money with T04 and forwards the rest
+        #[derive(Clone)]
+        struct CongestedService<I> {
+            next: I,
+            rejected_packets: Arc<AtomicUsize>,
+        }
+
+        #[async_trait]
+        impl<I, A> IncomingService<A> for CongestedService<I>
+        where
+            I: IncomingService<A> + Send + Sync + 'static,
+            A: Account + 'static,
+        {
+            async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+                if request.prepare.amount() > 0
+                    && self.rejected_packets.fetch_add(1, Ordering::SeqCst) < 3
+                {
+                    return Err(RejectBuilder {
+                        code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                        message: b"not enough liquidity",
+                        triggered_by: Some(&EXAMPLE_CONNECTOR),
+                        data: &[],
+                    }
+                    .build());
+                }
+                self.next.handle_request(request).await
+            }
+        }
+
+        let rejected_packets = Arc::new(AtomicUsize::new(0));
+        let server = CongestedService {
+            next: server,
+            rejected_packets: rejected_packets.clone(),
+        };
+        let sender_account = TestAccount {
+            id: Uuid::new_v4(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            ilp_address: Address::from_str("example.sender").unwrap(),
+            max_packet_amount: None,
+            allowed_source_prefix: None,
+        };
+
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&destination_address);
+        let retry_policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(10),
+        };
+        let start = Instant::now();
+        let receipt = send_money(
+            server,
+            &sender_account,
+            store,
+            destination_account,
+            &shared_secret[..],
+            100,
+            0.0,
+            DEFAULT_PACKET_EXPIRY,
+            None,
+            retry_policy,
+        )
+        .await
+        .unwrap();
+
+        // Waited 10, 20 and 40 milliseconds after the rejects
+        assert!(start.elapsed() >= Duration::from_millis(70));
+        assert!(rejected_packets.load(Ordering::SeqCst) > 3);
+        assert_eq!(receipt.delivered_amount, 100);
+        assert_eq!(receipt.sent_amount, 100);
+    }
 }
@@ -1,5 +1,5 @@
 use super::crypto::*;
-use super::packet::*;
+use super::packet::{ErrorCode as StreamErrorCode, *};
 use async_trait::async_trait;
 use base64;
 use bytes::{Bytes, BytesMut};
@@ -12,9 +12,12 @@ use interledger_packet::{
 };
 use interledger_service::{Account, IlpResult, OutgoingRequest, OutgoingService, Username};
 use log::debug;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
-use std::time::SystemTime;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use uuid::Uuid;
 
 // Note we are using the same magic bytes as the Javascript
@@ -26,6 +29,10 @@ use uuid::Uuid;
 // this string is.
 const STREAM_SERVER_SECRET_GENERATOR: &[u8] = b"ilp_stream_shared_secret";
 
+/// How long a connection counts towards the connection limit after its last packet,
+/// unless the sender closes it before
+const IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
+
 /// A STREAM connection generator that creates `destination_account` and `shared_secret` values
 /// based on a single root secret.
 ///
@@ -116,11 +123,46 @@ pub trait StreamNotificationsStore {
     fn publish_payment_notification(&self, _payment: PaymentNotification);
 }
 
+/// Error the receiver rejects a payment with, which is conveyed to the sender in a
+/// `ConnectionClose` frame so the sender stops the payment and reports it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplicationError {
+    /// Application-specific data (e.g. JSON) which reaches the sender intact
+    pub message: String,
+}
+
+impl ApplicationError {
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        ApplicationError {
+            message: message.into(),
+        }
+    }
+}
+
+/// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the
+/// source addresses STREAM senders may claim in the packets received from the account
+pub trait SourcePrefixAccount: Account {
+    /// The prefix (e.g. the peer's subtree) which the source addresses in the STREAM
+    /// packets received from this account must be within. Defaults to allowing any address.
+    fn allowed_source_prefix(&self) -> Option<&Address> {
+        None
+    }
+}
+
+/// Decides whether the receiving account, the connection's destination address and the
+/// packet amount are refused with an application error
+type ApplicationErrorCallback<A> =
+    Arc<dyn Fn(&A, &Address, u64) -> Result<(), ApplicationError> + Send + Sync>;
+
 /// An OutgoingService that fulfills incoming STREAM packets.
 ///
 /// Note this does **not** maintain STREAM state, but instead fulfills
 /// all incoming packets to collect the money.
 ///
+/// The only state it keeps are the open connections and streams, if their number is limited
+/// with [`max_connections_per_account`](#method.max_connections_per_account) or
+/// [`max_streams_per_connection`](#method.max_streams_per_connection).
+///
 /// This does not currently support handling data sent via STREAM.
 #[derive(Clone)]
 pub struct StreamReceiverService<S, O: OutgoingService<A>, A: Account> {
@@ -128,6 +170,99 @@ pub struct StreamReceiverService<S, O: OutgoingService<A>, A: Account> {
     next: O,
     account_type: PhantomData<A>,
     store: S,
+    limits: ReceiveLimits,
+    application_error_callback: Option<ApplicationErrorCallback<A>>,
+}
+
+/// The connections and streams the receiver is tracking to enforce its limits
+#[derive(Clone, Default)]
+struct ReceiveLimits {
+    max_connections_per_account: Option<usize>,
+    max_streams_per_connection: Option<usize>,
+    /// The open connections of each receiving account, by their destination address
+    connections: Arc<Mutex<HashMap<Uuid, HashMap<Address, OpenConnection>>>>,
+}
+
+struct OpenConnection {
+    last_packet: Instant,
+    streams: HashSet<u64>,
+}
+
+impl ReceiveLimits {
+    fn is_enabled(&self) -> bool {
+        self.max_connections_per_account.is_some() || self.max_streams_per_connection.is_some()
+    }
+
+    /// Tracks the connection and the streams the packet is sent on, and returns the frames
+    /// refusing them if there are too many (or no frames if the packet can be accepted)
+    fn check<'a>(
+        &self,
+        account_id: Uuid,
+        destination: &Address,
+        packet: &StreamPacket,
+    ) -> Vec<Frame<'a>> {
+        let now = Instant::now();
+        let mut connections = self.connections.lock();
+        let account_connections = connections.entry(account_id).or_default();
+        account_connections.retain(|_, connection| {
+            now.duration_since(connection.last_packet) < IDLE_CONNECTION_TIMEOUT
+        });
+
+        if let Some(max) = self.max_connections_per_account {
+            if !account_connections.contains_key(destination) && account_connections.len() >= max {
+                debug!(
+                    "Refusing STREAM connection because account {} already has {} open connections",
+                    account_id, max
+                );
+                return vec![Frame::ConnectionClose(ConnectionCloseFrame {
+                    code: StreamErrorCode::EndpointBusy,
+                    message: "Too many open connections",
+                })];
+            }
+        }
+
+        let connection = account_connections
+            .entry(destination.clone())
+            .or_insert_with(|| OpenConnection {
+                last_packet: now,
+                streams: HashSet::new(),
+            });
+        connection.last_packet = now;
+
+        let mut refused = Vec::new();
+        let mut closed = false;
+        for frame in packet.frames() {
+            match frame {
+                Frame::StreamMoney(frame) if !connection.streams.contains(&frame.stream_id) => {
+                    match self.max_streams_per_connection {
+                        Some(max) if connection.streams.len() >= max => {
+                            debug!(
+                                "Refusing STREAM stream {} because its connection already has {} open streams",
+                                frame.stream_id, max
+                            );
+                            refused.push(Frame::StreamClose(StreamCloseFrame {
+                                stream_id: frame.stream_id,
+                                code: StreamErrorCode::StreamIdError,
+                                message: "Too many open streams",
+                            }));
+                        }
+                        _ => {
+                            connection.streams.insert(frame.stream_id);
+                        }
+                    }
+                }
+                Frame::StreamClose(frame) => {
+                    connection.streams.remove(&frame.stream_id);
+                }
+                Frame::ConnectionClose(_) => closed = true,
+                _ => {}
+            }
+        }
+        if closed {
+            account_connections.remove(destination);
+        }
+        refused
+    }
 }
 
 impl<S, O, A> StreamReceiverService<S, O, A>
@@ -143,8 +278,37 @@ where
             next,
             account_type: PhantomData,
             store,
+            limits: ReceiveLimits::default(),
+            application_error_callback: None,
         }
     }
+
+    /// Sets the maximum number of STREAM connections each account receives at the same time.
+    /// Packets of further connections are rejected with a `ConnectionClose` frame until one
+    /// of the connections is closed or has not been used for a minute.
+    pub fn max_connections_per_account(&mut self, limit: usize) -> &mut Self {
+        self.limits.max_connections_per_account = Some(limit);
+        self
+    }
+
+    /// Sets the maximum number of streams each incoming STREAM connection has open at the
+    /// same time. Packets sending money on further streams are rejected with a `StreamClose`
+    /// frame for those streams until the sender closes one of the others.
+    pub fn max_streams_per_connection(&mut self, limit: usize) -> &mut Self {
+        self.limits.max_streams_per_connection = Some(limit);
+        self
+    }
+
+    /// Sets the function which is called with every packet for this receiver. Packets it returns
+    /// an error for are rejected with a `ConnectionClose` frame carrying the error's message,
+    /// which ends the sender's payment with that message.
+    pub fn on_receive<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(&A, &Address, u64) -> Result<(), ApplicationError> + Send + Sync + 'static,
+    {
+        self.application_error_callback = Some(Arc::new(callback));
+        self
+    }
 }
 
 #[async_trait]
@@ -152,10 +316,11 @@ impl<S, O, A> OutgoingService<A> for StreamReceiverService<S, O, A>
 where
     S: StreamNotificationsStore + Send + Sync + 'static + Clone,
     O: OutgoingService<A> + Send + Sync + Clone,
-    A: Account + Send + Sync + Clone,
+    A: SourcePrefixAccount + Send + Sync + Clone,
 {
     /// Try fulfilling the request if it is for this STREAM server or pass it to the next
-    /// outgoing handler if not.
+    /// outgoing handler if not. Packets claiming a source address outside the allowed
+    /// prefix of the account they were received from are rejected.
     async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
         let to_username = request.to.username().clone();
         let from_username = request.from.username().clone();
@@ -169,12 +334,42 @@ where
         // The case where the request is bound for this server
         if dest.starts_with(to_address.as_ref()) {
             if let Ok(shared_secret) = self.connection_generator.rederive_secret(&destination) {
+                if let Some(prefix) = request.from.allowed_source_prefix() {
+                    if let Some(source) =
+                        source_outside_prefix(&shared_secret, &request.prepare, prefix)
+                    {
+                        debug!(
+                            "Rejecting packet from account {} claiming source address {} outside of {}",
+                            request.from.id(),
+                            source,
+                            prefix
+                        );
+                        return Err(RejectBuilder {
+                            code: ErrorCode::F00_BAD_REQUEST,
+                            message: b"Source address is outside the allowed prefix",
+                            triggered_by: Some(&to_address),
+                            data: &[],
+                        }
+                        .build());
+                    }
+                }
+                let limits = if self.limits.is_enabled() {
+                    Some((&self.limits, request.to.id()))
+                } else {
+                    None
+                };
+                let application_error = self
+                    .application_error_callback
+                    .as_ref()
+                    .and_then(|callback| callback(&request.to, &destination, amount).err());
                 let response = receive_money(
                     &shared_secret,
                     &to_address,
                     request.to.asset_code(),
                     request.to.asset_scale(),
                     &request.prepare,
+                    limits,
+                    application_error.as_ref(),
                 );
                 match response {
                     Ok(ref _fulfill) => store.publish_payment_notification(PaymentNotification {
@@ -203,6 +398,32 @@ where
 }
 
 // TODO send asset code and scale back to sender also
+/// Returns the source address claimed in the packet's `ConnectionNewAddress` frame if it is
+/// not within the prefix. Packets which can not be decrypted are left to `receive_money`
+fn source_outside_prefix(
+    shared_secret: &[u8; 32],
+    prepare: &Prepare,
+    prefix: &Address,
+) -> Option<Address> {
+    let stream_packet =
+        StreamPacket::from_encrypted(shared_secret, BytesMut::from(prepare.data())).ok()?;
+    let prefix: &[u8] = prefix.as_ref();
+    stream_packet.frames().find_map(|frame| match frame {
+        Frame::ConnectionNewAddress(frame) => {
+            let source: &[u8] = frame.source_account.as_ref();
+            // The prefix must be followed by a separator so e.g. `g.alice` does not allow `g.alicia`
+            let is_within = source.starts_with(prefix)
+                && (source.len() == prefix.len() || source[prefix.len()] == b'.');
+            if is_within {
+                None
+            } else {
+                Some(frame.source_account)
+            }
+        }
+        _ => None,
+    })
+}
+
 fn receive_money(
     shared_secret: &[u8; 32],
     // Our node's ILP Address ( we are the receiver, so we should return that
@@ -211,11 +432,14 @@ fn receive_money(
     asset_code: &str,
     asset_scale: u8,
     prepare: &Prepare,
+    // The limits of the receiving account's connections and streams, if there are any
+    limits: Option<(&ReceiveLimits, Uuid)>,
+    // The error the receiving application refused the packet with, if it did
+    application_error: Option<&ApplicationError>,
 ) -> Result<Fulfill, Reject> {
     // Generate fulfillment
-    let fulfillment = generate_fulfillment(&shared_secret[..], prepare.data());
-    let condition = hash_sha256(&fulfillment);
-    let is_fulfillable = condition == prepare.execution_condition();
+    let fulfillment = fulfillment_for_prepare(&shared_secret[..], prepare);
+    let is_fulfillable = verify_condition(&fulfillment, prepare.execution_condition());
 
     // Parse STREAM packet
     // TODO avoid copying data
@@ -269,8 +493,23 @@ fn receive_money(
         }
     }
 
+    let refused = match limits {
+        Some((limits, account_id)) => {
+            limits.check(account_id, &prepare.destination(), &stream_packet)
+        }
+        None => Vec::new(),
+    };
+    let is_refused = !refused.is_empty() || application_error.is_some();
+    response_frames.extend(refused);
+    if let Some(error) = application_error {
+        response_frames.push(Frame::ConnectionClose(ConnectionCloseFrame {
+            code: StreamErrorCode::ApplicationError,
+            message: &error.message,
+        }));
+    }
+
     // Return Fulfill or Reject Packet
-    if is_fulfillable && prepare_amount >= stream_packet.prepare_amount() {
+    if is_fulfillable && !is_refused && prepare_amount >= stream_packet.prepare_amount() {
         let response_packet = StreamPacketBuilder {
             sequence: stream_packet.sequence(),
             ilp_packet_type: IlpPacketType::Fulfill,
@@ -301,6 +540,13 @@ fn receive_money(
         .build();
         if !is_fulfillable {
             debug!("Packet is unfulfillable");
+        } else if let Some(error) = application_error {
+            debug!(
+                "Packet was refused with application error: {}",
+                error.message
+            );
+        } else if is_refused {
+            debug!("Packet is for a connection or stream over the limits");
         } else if prepare_amount < stream_packet.prepare_amount() {
             debug!(
                 "Received only: {} when we should have received at least: {}",
@@ -396,7 +642,7 @@ mod receiving_money {
         let shared_secret = connection_generator
             .rederive_secret(&prepare.destination())
             .unwrap();
-        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare);
+        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare, None, None);
         assert!(result.is_ok());
     }
 
@@ -424,7 +670,7 @@ mod receiving_money {
         let shared_secret = connection_generator
             .rederive_secret(&prepare.destination())
             .unwrap();
-        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare);
+        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare, None, None);
         assert!(result.is_ok());
     }
 
@@ -453,7 +699,7 @@ mod receiving_money {
         let shared_secret = connection_generator
             .rederive_secret(&prepare.destination())
             .unwrap();
-        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare);
+        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare, None, None);
         assert!(result.is_err());
     }
 
@@ -492,7 +738,7 @@ mod receiving_money {
         let shared_secret = connection_generator
             .rederive_secret(&prepare.destination())
             .unwrap();
-        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare);
+        let result = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare, None, None);
         assert!(result.is_err());
     }
 
@@ -514,7 +760,7 @@ mod receiving_money {
                 .as_ref() as &[u8],
             "did not regenerate the same shared secret",
         );
-        let fulfill = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare)
+        let fulfill = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare, None, None)
             .expect("Receiver should be able to generate the fulfillment");
         assert_eq!(
             &hash_sha256(fulfill.fulfillment())[..],
@@ -572,6 +818,7 @@ mod stream_receiver_service {
                     asset_code: "XYZ".to_string(),
                     asset_scale: 9,
                     max_packet_amount: None,
+                    allowed_source_prefix: None,
                 },
                 to: TestAccount {
                     id: Uuid::new_v4(),
@@ -579,6 +826,7 @@ mod stream_receiver_service {
                     asset_code: "XYZ".to_string(),
                     asset_scale: 9,
                     max_packet_amount: None,
+                    allowed_source_prefix: None,
                 },
                 original_amount: prepare.amount(),
                 prepare,
@@ -634,6 +882,7 @@ mod stream_receiver_service {
                     asset_code: "XYZ".to_string(),
                     asset_scale: 9,
                     max_packet_amount: None,
+                    allowed_source_prefix: None,
                 },
                 to: TestAccount {
                     id: Uuid::new_v4(),
@@ -641,6 +890,7 @@ mod stream_receiver_service {
                     asset_code: "XYZ".to_string(),
                     asset_scale: 9,
                     max_packet_amount: None,
+                    allowed_source_prefix: None,
                 },
                 original_amount: prepare.amount(),
                 prepare,
@@ -694,6 +944,7 @@ mod stream_receiver_service {
                     asset_code: "XYZ".to_string(),
                     asset_scale: 9,
                     max_packet_amount: None,
+                    allowed_source_prefix: None,
                 },
                 original_amount: prepare.amount(),
                 to: TestAccount {
@@ -702,6 +953,7 @@ mod stream_receiver_service {
                     asset_code: "XYZ".to_string(),
                     asset_scale: 9,
                     max_packet_amount: None,
+                    allowed_source_prefix: None,
                 },
                 prepare,
             })
@@ -712,4 +964,231 @@ mod stream_receiver_service {
             Address::from_str("example.other-receiver").unwrap(),
         );
     }
+
+    /// Returns a request sending a STREAM packet with the given frames to the account
+    fn request_with_frames(
+        account_id: Uuid,
+        destination: &Address,
+        shared_secret: &[u8],
+        frames: &[Frame],
+    ) -> OutgoingRequest<TestAccount> {
+        let data = StreamPacketBuilder {
+            ilp_packet_type: IlpPacketType::Prepare,
+            prepare_amount: 0,
+            sequence: 1,
+            frames,
+        }
+        .build()
+        .into_encrypted(shared_secret);
+        let execution_condition = generate_condition(shared_secret, &data);
+        let prepare = PrepareBuilder {
+            destination: destination.clone(),
+            amount: 100,
+            expires_at: UNIX_EPOCH,
+            data: &data[..],
+            execution_condition: &execution_condition,
+        }
+        .build();
+        OutgoingRequest {
+            from: TestAccount {
+                id: Uuid::new_v4(),
+                ilp_address: Address::from_str("example.sender").unwrap(),
+                asset_code: "XYZ".to_string(),
+                asset_scale: 9,
+                max_packet_amount: None,
+                allowed_source_prefix: None,
+            },
+            to: TestAccount {
+                id: account_id,
+                ilp_address: Address::from_str("example.destination").unwrap(),
+                asset_code: "XYZ".to_string(),
+                asset_scale: 9,
+                max_packet_amount: None,
+                allowed_source_prefix: None,
+            },
+            original_amount: prepare.amount(),
+            prepare,
+        }
+    }
+
+    fn money(stream_id: u64) -> Frame<'static> {
+        Frame::StreamMoney(StreamMoneyFrame {
+            stream_id,
+            shares: 1,
+        })
+    }
+
+    fn limited_service() -> StreamReceiverService<
+        DummyStore,
+        impl OutgoingService<TestAccount> + Send + Sync + Clone,
+        TestAccount,
+    > {
+        StreamReceiverService::new(
+            Bytes::from(&[1; 32][..]),
+            DummyStore,
+            outgoing_service_fn(|_: OutgoingRequest<TestAccount>| -> IlpResult {
+                panic!("shouldn't get here")
+            }),
+        )
+    }
+
+    /// Returns the STREAM packet in the reject
+    fn rejected_packet(shared_secret: &[u8], reject: &Reject) -> StreamPacket {
+        StreamPacket::from_encrypted(shared_secret, BytesMut::from(reject.data())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn refuses_connections_over_the_limit() {
+        let ilp_address = Address::from_str("example.destination").unwrap();
+        let connection_generator = ConnectionGenerator::new(Bytes::from(&[1; 32][..]));
+        let connections: Vec<(Address, [u8; 32])> = (0..3)
+            .map(|_| connection_generator.generate_address_and_secret(&ilp_address))
+            .collect();
+        let account_id = Uuid::new_v4();
+        let mut service = limited_service();
+        service.max_connections_per_account(2);
+
+        for (destination, shared_secret) in &connections[..2] {
+            let request = request_with_frames(account_id, destination, shared_secret, &[money(1)]);
+            assert!(service.send_request(request).await.is_ok());
+        }
+        let (destination, shared_secret) = &connections[2];
+        let request = request_with_frames(account_id, destination, shared_secret, &[money(1)]);
+        let reject = service.send_request(request).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F99_APPLICATION_ERROR);
+        let refusal = Frame::ConnectionClose(ConnectionCloseFrame {
+            code: StreamErrorCode::EndpointBusy,
+            message: "Too many open connections",
+        });
+        assert!(rejected_packet(shared_secret, &reject)
+            .frames()
+            .any(|frame| frame == refusal));
+
+        // Other accounts have their own connections
+        let request = request_with_frames(Uuid::new_v4(), destination, shared_secret, &[money(1)]);
+        assert!(service.send_request(request).await.is_ok());
+
+        // Once the sender closes one of the connections, the new one is accepted
+        let (closed_destination, closed_secret) = &connections[0];
+        let close = Frame::ConnectionClose(ConnectionCloseFrame {
+            code: StreamErrorCode::NoError,
+            message: "",
+        });
+        let request = request_with_frames(account_id, closed_destination, closed_secret, &[close]);
+        assert!(service.send_request(request).await.is_ok());
+        let request = request_with_frames(account_id, destination, shared_secret, &[money(1)]);
+        assert!(service.send_request(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn refuses_streams_over_the_limit() {
+        let ilp_address = Address::from_str("example.destination").unwrap();
+        let connection_generator = ConnectionGenerator::new(Bytes::from(&[1; 32][..]));
+        let (destination, shared_secret) =
+            connection_generator.generate_address_and_secret(&ilp_address);
+        let account_id = Uuid::new_v4();
+        let mut service = limited_service();
+        service.max_streams_per_connection(2);
+
+        let request = request_with_frames(
+            account_id,
+            &destination,
+            &shared_secret,
+            &[money(1), money(3)],
+        );
+        assert!(service.send_request(request).await.is_ok());
+        let request = request_with_frames(account_id, &destination, &shared_secret, &[money(1)]);
+        assert!(service.send_request(request).await.is_ok());
+
+        let request = request_with_frames(account_id, &destination, &shared_secret, &[money(5)]);
+        let reject = service.send_request(request).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F99_APPLICATION_ERROR);
+        let refusal = Frame::StreamClose(StreamCloseFrame {
+            stream_id: 5,
+            code: StreamErrorCode::StreamIdError,
+            message: "Too many open streams",
+        });
+        assert!(rejected_packet(&shared_secret, &reject)
+            .frames()
+            .any(|frame| frame == refusal));
+
+        // Once the sender closes one of the streams, the new one is accepted
+        let close = Frame::StreamClose(StreamCloseFrame {
+            stream_id: 1,
+            code: StreamErrorCode::NoError,
+            message: "",
+        });
+        let request = request_with_frames(account_id, &destination, &shared_secret, &[close]);
+        assert!(service.send_request(request).await.is_ok());
+        let request = request_with_frames(account_id, &destination, &shared_secret, &[money(5)]);
+        assert!(service.send_request(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_with_application_errors() {
+        let ilp_address = Address::from_str("example.destination").unwrap();
+        let connection_generator = ConnectionGenerator::new(Bytes::from(&[1; 32][..]));
+        let (destination, shared_secret) =
+            connection_generator.generate_address_and_secret(&ilp_address);
+        let account_id = Uuid::new_v4();
+        let mut service = limited_service();
+        service.on_receive(|_, _, amount| {
+            if amount > 50 {
+                Err(ApplicationError::new(r#"{"reason":"invoice_paid"}"#))
+            } else {
+                Ok(())
+            }
+        });
+
+        let request = request_with_frames(account_id, &destination, &shared_secret, &[money(1)]);
+        let reject = service.send_request(request).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F99_APPLICATION_ERROR);
+        let close = Frame::ConnectionClose(ConnectionCloseFrame {
+            code: StreamErrorCode::ApplicationError,
+            message: r#"{"reason":"invoice_paid"}"#,
+        });
+        assert!(rejected_packet(&shared_secret, &reject)
+            .frames()
+            .any(|frame| frame == close));
+
+        let mut request =
+            request_with_frames(account_id, &destination, &shared_secret, &[money(1)]);
+        request.prepare.set_amount(50);
+        assert!(service.send_request(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_source_addresses_outside_the_prefix() {
+        let ilp_address = Address::from_str("example.destination").unwrap();
+        let connection_generator = ConnectionGenerator::new(Bytes::from(&[1; 32][..]));
+        let (destination, shared_secret) =
+            connection_generator.generate_address_and_secret(&ilp_address);
+        let mut service = limited_service();
+        let prefix = Address::from_str("example.sender").unwrap();
+        let new_address = |source: &str| {
+            Frame::ConnectionNewAddress(ConnectionNewAddressFrame {
+                source_account: Address::from_str(source).unwrap(),
+            })
+        };
+
+        let mut request = request_with_frames(
+            Uuid::new_v4(),
+            &destination,
+            &shared_secret,
+            &[new_address("example.sender.alice"), money(1)],
+        );
+        request.from.allowed_source_prefix = Some(prefix.clone());
+        assert!(service.send_request(request).await.is_ok());
+
+        let mut request = request_with_frames(
+            Uuid::new_v4(),
+            &destination,
+            &shared_secret,
+            &[new_address("example.senders.mallory"), money(1)],
+        );
+        request.from.allowed_source_prefix = Some(prefix);
+        let reject = service.send_request(request).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F00_BAD_REQUEST);
+        assert_eq!(reject.triggered_by().unwrap(), ilp_address);
+    }
 }